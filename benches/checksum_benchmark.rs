@@ -0,0 +1,78 @@
+//! Compares the shard checksums (`create --checksum crc32|xxh3`).
+//!
+//! Hashes an in-memory buffer with both algorithms, then archives incompressible
+//! data once per checksum and times `verify_archive` (index, BLAKE3 footer and
+//! the shard checksums).
+//!
+//! Run with:
+//!     cargo bench --bench checksum_benchmark -- [--size-mb 512] [--threads 4]
+//!
+
+use std::error::Error;
+use std::fs;
+use std::time::Instant;
+
+use blitzarch::katana::{self, ShardChecksum};
+use blitzarch::katana_stream::{create_katana_archive_with_options, KatanaCreateOptions};
+use rand::RngCore;
+
+fn parse_args() -> (usize, usize) {
+    let mut size_mb = 256;
+    let mut threads = 4;
+    let mut args = std::env::args().skip(1);
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--size-mb" => size_mb = args.next().and_then(|v| v.parse().ok()).unwrap_or(size_mb),
+            "--threads" => threads = args.next().and_then(|v| v.parse().ok()).unwrap_or(threads),
+            _ => {} // cargo bench passes --bench
+        }
+    }
+    (size_mb, threads)
+}
+
+fn gib_per_sec(bytes: usize, secs: f64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0 * 1024.0) / secs
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let (size_mb, threads) = parse_args();
+    let mut data = vec![0u8; size_mb * 1024 * 1024];
+    rand::thread_rng().fill_bytes(&mut data);
+
+    println!("{:<8} {:>14}", "hash", "GiB/s");
+    let start = Instant::now();
+    let crc = crc32fast::hash(&data);
+    println!("{:<8} {:>14.2}", "crc32", gib_per_sec(data.len(), start.elapsed().as_secs_f64()));
+    let start = Instant::now();
+    let xxh3 = xxhash_rust::xxh3::xxh3_128(&data);
+    println!("{:<8} {:>14.2}", "xxh3", gib_per_sec(data.len(), start.elapsed().as_secs_f64()));
+    std::hint::black_box((crc, xxh3));
+
+    let dataset = tempfile::tempdir()?;
+    for (i, part) in data.chunks(64 * 1024 * 1024).enumerate() {
+        fs::write(dataset.path().join(format!("part_{i}.bin")), part)?;
+    }
+    let out_dir = tempfile::tempdir()?;
+    println!("{:<8} {:>14}", "checksum", "verify (ms)");
+    for (name, checksum) in [("crc32", ShardChecksum::Crc32), ("xxh3", ShardChecksum::Xxh3)] {
+        let archive = out_dir.path().join(format!("{name}.blz"));
+        let options = KatanaCreateOptions { shard_checksum: checksum, ..Default::default() };
+        create_katana_archive_with_options(
+            &[dataset.path().to_path_buf()],
+            &archive,
+            threads,
+            0,
+            None,
+            None,
+            Some(1),
+            &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )?;
+        // Warm the page cache so both runs read from memory
+        katana::verify_archive(&archive, None, false)?;
+        let start = Instant::now();
+        katana::verify_archive(&archive, None, false)?;
+        println!("{:<8} {:>14.1}", name, start.elapsed().as_secs_f64() * 1000.0);
+    }
+    Ok(())
+}
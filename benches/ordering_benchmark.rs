@@ -0,0 +1,100 @@
+//! Compares file ordering strategies inside Katana shards.
+//!
+//! Builds a synthetic, deliberately interleaved dataset (log rotations, JSON
+//! records, random binaries) – or uses `--dataset-path` – and archives it once
+//! per strategy, reporting archive size and wall time.
+//!
+//! Run with:
+//!     cargo bench --bench ordering_benchmark -- [--dataset-path <PATH>] [--threads 4]
+//!
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use blitzarch::katana_stream::{create_katana_archive_with_options, KatanaCreateOptions};
+use blitzarch::ordering::{FileOrder, TypeAwareOrder, WalkOrder};
+use rand::{thread_rng, Rng, RngCore};
+
+fn parse_args() -> (Option<PathBuf>, usize) {
+    let mut dataset = None;
+    let mut threads = 4;
+    let mut args = std::env::args().skip(1);
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--dataset-path" => dataset = args.next().map(PathBuf::from),
+            "--threads" => threads = args.next().and_then(|v| v.parse().ok()).unwrap_or(threads),
+            _ => {} // cargo bench passes --bench
+        }
+    }
+    (dataset, threads)
+}
+
+/// Writes files so that the walk order alternates between unrelated types.
+fn generate_dataset(root: &Path) -> Result<(), Box<dyn Error>> {
+    let mut rng = thread_rng();
+    for i in 0..200 {
+        let dir = root.join(format!("d{:03}", i % 20));
+        fs::create_dir_all(&dir)?;
+
+        let mut log = String::new();
+        for line in 0..200 {
+            log.push_str(&format!(
+                "2024-01-{:02} 12:00:{:02} INFO worker-{} request served path=/api/v1/items/{} status=200\n",
+                1 + i % 28,
+                line % 60,
+                line % 8,
+                rng.gen_range(0..10_000)
+            ));
+        }
+        fs::write(dir.join(format!("app.log.{}", i)), log)?;
+
+        let json: Vec<String> = (0..50)
+            .map(|k| format!(r#"{{"id":{},"name":"item-{}","price":{},"tags":["a","b"]}}"#, k, k, rng.gen_range(1..999)))
+            .collect();
+        fs::write(dir.join(format!("record_{}.json", i)), format!("[{}]", json.join(",")))?;
+
+        let mut bin = vec![0u8; 8 * 1024];
+        rng.fill_bytes(&mut bin);
+        fs::write(dir.join(format!("blob_{}.bin", i)), bin)?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let (dataset, threads) = parse_args();
+    let generated = tempfile::tempdir()?;
+    let dataset = match dataset {
+        Some(p) => p,
+        None => {
+            generate_dataset(generated.path())?;
+            generated.path().to_path_buf()
+        }
+    };
+    let out_dir = tempfile::tempdir()?;
+
+    let strategies: Vec<Arc<dyn FileOrder>> = vec![Arc::new(WalkOrder), Arc::new(TypeAwareOrder)];
+    println!("{:<8} {:>12} {:>10}", "order", "size (KiB)", "time (ms)");
+    for strategy in strategies {
+        let archive = out_dir.path().join(format!("{}.blz", strategy.name()));
+        let options = KatanaCreateOptions { ordering: Some(Arc::clone(&strategy)), ..Default::default() };
+        let start = Instant::now();
+        create_katana_archive_with_options(
+            std::slice::from_ref(&dataset),
+            &archive,
+            threads,
+            0,
+            None,
+            None,
+            Some(3),
+            &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        let size = fs::metadata(&archive)?.len();
+        println!("{:<8} {:>12.1} {:>10.1}", strategy.name(), size as f64 / 1024.0, elapsed);
+    }
+    Ok(())
+}
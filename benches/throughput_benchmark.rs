@@ -0,0 +1,101 @@
+//! Create/extract throughput with a JSON report and a regression gate, the
+//! same measurement as `blitzarch bench` (see `blitzarch::bench`).
+//!
+//! Benchmarks `--dataset DIR`, or a generated mix of text and random files.
+//! `--json FILE` saves the report; `--compare FILE` checks it against a saved
+//! baseline and exits with an error if create or extract slowed down by more
+//! than `--fail-threshold` (default 5%) or the baseline is not comparable.
+//!
+//! Run with:
+//!     cargo bench --bench throughput_benchmark -- [--dataset DIR] [--size-mb 256]
+//!         [--level 3] [--threads 0] [--runs 3] [--json FILE] [--compare FILE] [--fail-threshold 5%]
+//!
+
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use blitzarch::bench::{run_bench, BenchComparison, BenchOptions, BenchReport};
+use rand::RngCore;
+
+struct Args {
+    dataset: Option<PathBuf>,
+    size_mb: usize,
+    options: BenchOptions,
+    json: Option<PathBuf>,
+    compare: Option<PathBuf>,
+    fail_threshold: f64,
+}
+
+fn parse_args() -> Result<Args, Box<dyn Error>> {
+    let mut parsed = Args {
+        dataset: None,
+        size_mb: 256,
+        options: BenchOptions::default(),
+        json: None,
+        compare: None,
+        fail_threshold: 5.0,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--dataset" => parsed.dataset = args.next().map(PathBuf::from),
+            "--size-mb" => parsed.size_mb = args.next().and_then(|v| v.parse().ok()).unwrap_or(parsed.size_mb),
+            "--level" => parsed.options.level = args.next().and_then(|v| v.parse().ok()).unwrap_or(parsed.options.level),
+            "--threads" => parsed.options.threads = args.next().and_then(|v| v.parse().ok()).unwrap_or(parsed.options.threads),
+            "--runs" => parsed.options.runs = args.next().and_then(|v| v.parse().ok()).unwrap_or(parsed.options.runs),
+            "--json" => parsed.json = args.next().map(PathBuf::from),
+            "--compare" => parsed.compare = args.next().map(PathBuf::from),
+            "--fail-threshold" => {
+                parsed.fail_threshold = blitzarch::cli::parse_percent(&args.next().unwrap_or_default())?;
+            }
+            _ => {} // cargo bench passes --bench
+        }
+    }
+    Ok(parsed)
+}
+
+/// Half compressible text, half random bytes, in files of 1–8 MiB.
+fn generate_dataset(dir: &std::path::Path, size_mb: usize) -> Result<(), Box<dyn Error>> {
+    let mut rng = rand::thread_rng();
+    let mut left = size_mb * 1024 * 1024;
+    let mut i = 0;
+    while left > 0 {
+        let len = left.min((1 + i % 8) * 1024 * 1024);
+        let data = if i % 2 == 0 {
+            format!("line {i}: the quick brown fox jumps over the lazy dog\n").into_bytes().repeat(len / 48 + 1)[..len].to_vec()
+        } else {
+            let mut data = vec![0u8; len];
+            rng.fill_bytes(&mut data);
+            data
+        };
+        fs::write(dir.join(format!("file_{i:04}.bin")), data)?;
+        left -= len;
+        i += 1;
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+    let generated = tempfile::tempdir()?;
+    let dataset = match &args.dataset {
+        Some(dir) => dir.clone(),
+        None => {
+            generate_dataset(generated.path(), args.size_mb)?;
+            generated.path().to_path_buf()
+        }
+    };
+
+    let report = run_bench(&[dataset], &args.options)?;
+    report.print_summary();
+    if let Some(path) = &args.json {
+        report.save(path)?;
+    }
+    if let Some(baseline) = &args.compare {
+        let comparison = BenchComparison::new(&BenchReport::load(baseline)?, &report, args.fail_threshold);
+        comparison.print_summary();
+        comparison.check()?;
+    }
+    Ok(())
+}
@@ -58,9 +58,11 @@ use tauri::Emitter;
 
 
 // Import BlitzArch engine functions and types
-use blitzarch::katana_stream::create_katana_archive_with_progress;
-use blitzarch::katana::extract_katana_archive_with_progress;
+use blitzarch::katana_stream::{create_katana_archive_with_options, perform_paranoid_check, KatanaCreateOptions};
+use blitzarch::katana::extract_katana_archive_cancellable;
+use blitzarch::cancel::CancellationToken;
 use blitzarch::progress::ProgressState;
+use blitzarch::history::{HistoryStore, JobKind, JobParams, JobRecord, JobStats};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ArchiveStats {
@@ -84,6 +86,10 @@ pub struct ArchiveResult {
     pub integrity_ok: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub blake3_hex: Option<String>,
+    /// Non-fatal problems for the post-job report (skipped files, permission
+    /// failures, path rewrites, ...).
+    #[serde(default)]
+    pub warnings: Vec<blitzarch::warnings::Warning>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -262,10 +268,42 @@ pub struct ProgressEvent {
     pub compression_ratio: Option<f32>, // Compression ratio (for completed operations)
 }
 
+/// Token of the create / extract job currently running; the cancel button
+/// fires it through [`cancel_current_job`].
+#[derive(Default)]
+pub struct CurrentJob(std::sync::Mutex<Option<CancellationToken>>);
+
+impl CurrentJob {
+    /// Registers a new job and returns its token.
+    fn start(&self) -> CancellationToken {
+        let token = CancellationToken::new();
+        *self.0.lock().unwrap() = Some(token.clone());
+        token
+    }
+
+    fn finish(&self) {
+        self.0.lock().unwrap().take();
+    }
+}
+
+/// Cancels the running create / extract job. The job removes its partial
+/// output and reports a cancelled result; `false` when nothing was running.
+#[tauri::command]
+pub fn cancel_current_job(job: tauri::State<'_, CurrentJob>) -> bool {
+    match job.0.lock().unwrap().as_ref() {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
 // Async version of create_archive with progress events (RECREATED)
 #[tauri::command(async)]
 pub async fn create_archive_async(
     app: AppHandle,
+    job: tauri::State<'_, CurrentJob>,
     inputs: Vec<String>,
     output_path: String,
     compression_level: i32,
@@ -279,22 +317,81 @@ pub async fn create_archive_async(
     let app_clone = app.clone();
     let inputs_clone = inputs.clone();
     let output_path_clone = output_path.clone();
+    let params = JobParams {
+        level: Some(compression_level),
+        threads,
+        codec_threads,
+        memory_budget_mb: memory_budget,
+        encrypted: password.as_deref().is_some_and(|p| !p.is_empty()),
+        ..Default::default()
+    };
     
     // Use engine directly instead of CLI spawning for real progress
-    tauri::async_runtime::spawn_blocking(move || {
-        create_archive_with_real_progress(
-            app_clone,
-            inputs_clone,
-            output_path_clone,
-            compression_level,
-            skip_check,
+    let cancel = job.start();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        with_job_warnings(|| {
+            create_archive_with_real_progress(
+                app_clone,
+                inputs_clone,
+                output_path_clone,
+                compression_level,
+                skip_check,
+
+                password,
+                threads,
+                codec_threads,
+                memory_budget,
+                cancel,
+            )
+        })
+    }).await.map_err(|e| format!("Task execution failed: {}", e));
+    job.finish();
+    let result = result?;
+
+    if let Ok(res) = &result {
+        let mut record = job_record(JobKind::Create, res, params);
+        record.inputs = inputs;
+        // Paranoid check runs inside the engine unless the user skipped it
+        record.integrity_ok = (res.success && !skip_check).then_some(true);
+        save_job_record(&record);
+    }
+    result
+}
 
-            password,
-            threads,
-            codec_threads,
-            memory_budget,
-        )
-    }).await.map_err(|e| format!("Task execution failed: {}", e))?
+/// Runs an engine job and attaches the warnings it reported to its result.
+fn with_job_warnings(job: impl FnOnce() -> Result<ArchiveResult, String>) -> Result<ArchiveResult, String> {
+    let (result, warnings) = blitzarch::warnings::capture(job);
+    result.map(|mut res| {
+        res.warnings = warnings;
+        res
+    })
+}
+
+/// Builds a history record from the result returned to the frontend.
+fn job_record(kind: JobKind, res: &ArchiveResult, params: JobParams) -> JobRecord {
+    let mut record = JobRecord::new(kind);
+    record.success = res.success;
+    record.archive_path = res.archive_path.clone();
+    record.error = res.error.clone();
+    record.integrity_ok = res.integrity_ok;
+    record.params = params;
+    if let Some(stats) = &res.stats {
+        record.stats = JobStats {
+            files: stats.files.unwrap_or(0),
+            total_bytes: stats.total_bytes.unwrap_or(0),
+            archive_bytes: stats.archive_bytes,
+            duration_sec: stats.time_sec.unwrap_or(0.0),
+        };
+    }
+    record
+}
+
+/// History is best effort: a read-only home directory must not fail the job.
+fn save_job_record(record: &JobRecord) {
+    match HistoryStore::open_default().and_then(|store| store.record(record)) {
+        Ok(()) => {}
+        Err(e) => eprintln!("⚠️ Failed to save job history: {}", e),
+    }
 }
 
 fn create_archive_with_real_progress(
@@ -308,6 +405,7 @@ fn create_archive_with_real_progress(
     threads: Option<usize>,
     codec_threads: Option<u32>,
     memory_budget: Option<u64>,
+    cancel: CancellationToken,
 ) -> Result<ArchiveResult, String> {
     println!("🚀 Creating archive async: {}", output_path);
     
@@ -316,12 +414,8 @@ fn create_archive_with_real_progress(
     }
 
 
-    // Apply memory budget to engine via environment variable only if > 0
-    if let Some(mb) = memory_budget {
-        if mb > 0 {
-            std::env::set_var("BLITZ_MEM_BUDGET_MB", mb.to_string());
-        }
-    }
+    // 0 from the settings screen means "no budget"
+    let memory_budget = memory_budget.filter(|&mb| mb > 0);
     
     let password = normalize_password(password);
     
@@ -408,7 +502,8 @@ fn create_archive_with_real_progress(
     println!("🛠️ generate_unique_path => final_path: {:?}", output_pathbuf);
     let output_path = output_pathbuf.to_string_lossy().to_string();
 
-let result = create_katana_archive_with_progress(
+    let options = KatanaCreateOptions { cancel: Some(cancel), ..Default::default() };
+    let result = create_katana_archive_with_options(
         &input_paths,
         &output_pathbuf,
         threads.unwrap_or(0),
@@ -416,9 +511,10 @@ let result = create_katana_archive_with_progress(
         memory_budget,
         password,
         Some(compression_level),
-        skip_check,
+        &options,
         Some(progress_callback),
-    );
+    )
+    .and_then(|()| if skip_check { Ok(()) } else { perform_paranoid_check(&output_pathbuf) });
     
     // Handle result and emit final progress
     match result {
@@ -428,54 +524,13 @@ let result = create_katana_archive_with_progress(
             // Calculate final metrics with recursive directory traversal
             let archive_size = std::fs::metadata(&output_path).map(|m| m.len()).unwrap_or(0);
             
-            // Recursively calculate total size and file count
-            let (total_input_size, actual_file_count) = calculate_recursive_stats(&input_paths);
-            
-            fn calculate_recursive_stats(paths: &[std::path::PathBuf]) -> (u64, u64) {
-                let mut total_size = 0u64;
-                let mut file_count = 0u64;
-                
-                for path in paths {
-                    if let Ok(metadata) = std::fs::metadata(path) {
-                        if metadata.is_file() {
-                            total_size += metadata.len();
-                            file_count += 1;
-                        } else if metadata.is_dir() {
-                            if let Some(path_str) = path.to_str() {
-                                let (dir_size, dir_files) = calculate_dir_stats(path_str);
-                                total_size += dir_size;
-                                file_count += dir_files;
-                            }
-                        }
-                    }
-                }
-                
-                (total_size, file_count)
-            }
-            
-            fn calculate_dir_stats(dir_path: &str) -> (u64, u64) {
-                let mut total_size = 0u64;
-                let mut file_count = 0u64;
-                
-                if let Ok(entries) = std::fs::read_dir(dir_path) {
-                    for entry in entries.flatten() {
-                        if let Ok(metadata) = entry.metadata() {
-                            if metadata.is_file() {
-                                total_size += metadata.len();
-                                file_count += 1;
-                            } else if metadata.is_dir() {
-                                if let Some(path_str) = entry.path().to_str() {
-                                    let (sub_size, sub_files) = calculate_dir_stats(path_str);
-                                    total_size += sub_size;
-                                    file_count += sub_files;
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                (total_size, file_count)
-            }
+            // Same listing rules as the engine (links skipped, archive itself excluded)
+            let input_stats = blitzarch::fsx::tree_stats(
+                &input_paths,
+                blitzarch::vfs::SymlinkMode::Skip,
+                std::slice::from_ref(&output_pathbuf),
+            );
+            let (total_input_size, actual_file_count) = (input_stats.bytes, input_stats.files);
             
             let compression_ratio = if archive_size > 0 {
                 Some(total_input_size as f32 / archive_size as f32)
@@ -532,12 +587,13 @@ let result = create_katana_archive_with_progress(
             })
         }
         Err(e) => {
-            let error_msg = format!("Archive creation failed: {}", e);
+            let cancelled = blitzarch::cancel::is_cancelled(&*e);
+            let error_msg = if cancelled { "Archive creation cancelled".to_string() } else { format!("Archive creation failed: {}", e) };
             let final_progress = ProgressEvent {
                 operation: "create".to_string(),
                 progress: 0.0,
                 speed: 0.0,
-                message: "Archive creation failed!".to_string(),
+                message: if cancelled { "Archive creation cancelled" } else { "Archive creation failed!" }.to_string(),
                 completed: true,
                 error: Some(error_msg.clone()),
                 
@@ -577,7 +633,9 @@ pub fn create_archive(
     bundle_size: Option<u32>,
 ) -> Result<ArchiveResult, String> {
     println!("🚀 Creating archive: {} in {}", archive_name, output_dir);
-    println!("📋 Files: {:?}", files);
+    if !blitzarch::private_logs::enabled() {
+        println!("📋 Files: {:?}", files);
+    }
     
     let password = normalize_password(password);
     
@@ -614,7 +672,9 @@ let archive_path = archive_pathbuf.to_string_lossy().to_string();
         cmd.arg(file);
     }
     
-    println!("🔧 Command: {:?}", cmd);
+    if !blitzarch::private_logs::enabled() {
+        println!("🔧 Command: {:?}", cmd);
+    }
     
     // Execute command
     match cmd.output() {
@@ -683,6 +743,7 @@ pub fn get_parent_directory(file_path: String) -> Result<String, String> {
 #[tauri::command(async)]
 pub async fn extract_archive_async(
     app: AppHandle,
+    job: tauri::State<'_, CurrentJob>,
     archive_path: String,
     output_dir: String,
     password: Option<String>,
@@ -692,18 +753,38 @@ pub async fn extract_archive_async(
     let app_clone = app.clone();
     let archive_path_clone = archive_path.clone();
     let output_dir_clone = output_dir.clone();
+    let params = JobParams {
+        encrypted: password.as_deref().is_some_and(|p| !p.is_empty()),
+        strip_components,
+        selected_files: specific_files.as_ref().map_or(0, |f| f.len()),
+        ..Default::default()
+    };
     
     // Use engine directly instead of CLI spawning for real progress
-    tauri::async_runtime::spawn_blocking(move || {
-        extract_archive_with_real_progress(
-            app_clone,
-            archive_path_clone,
-            output_dir_clone,
-            password,
-            strip_components,
-            specific_files,
-        )
-    }).await.map_err(|e| format!("Task execution failed: {}", e))?
+    let cancel = job.start();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        with_job_warnings(|| {
+            extract_archive_with_real_progress(
+                app_clone,
+                archive_path_clone,
+                output_dir_clone,
+                password,
+                strip_components,
+                specific_files,
+                cancel,
+            )
+        })
+    }).await.map_err(|e| format!("Task execution failed: {}", e));
+    job.finish();
+    let result = result?;
+
+    if let Ok(res) = &result {
+        let mut record = job_record(JobKind::Extract, res, params);
+        record.archive_path = Some(archive_path);
+        record.output_dir = Some(output_dir);
+        save_job_record(&record);
+    }
+    result
 }
 
 fn extract_archive_with_real_progress(
@@ -713,10 +794,13 @@ fn extract_archive_with_real_progress(
     password: Option<String>,
     strip_components: Option<u32>,
     specific_files: Option<Vec<String>>,
+    cancel: CancellationToken,
 ) -> Result<ArchiveResult, String> {
     println!("🔄 Extracting archive async: {} to {}", archive_path, output_dir);
     // NOTE: do not normalize path here; list_archive_async uses the raw path and succeeds
-    println!("🔧 Debug params: strip_components={:?}, specific_files={:?}", strip_components, specific_files);
+    if !blitzarch::private_logs::enabled() {
+        println!("🔧 Debug params: strip_components={:?}, specific_files={:?}", strip_components, specific_files);
+    }
     
     let password = normalize_password(password);
     
@@ -835,14 +919,15 @@ fn extract_archive_with_real_progress(
     println!("✅ Output directory verified: {}", output_pathbuf.display());
     
     // Call engine directly with progress
-    println!("🚀 Calling extract_katana_archive_with_progress...");
-    let result = extract_katana_archive_with_progress(
+    println!("🚀 Calling extract_katana_archive_cancellable...");
+    let result = extract_katana_archive_cancellable(
         &archive_pathbuf,
         &output_pathbuf,
         &selected, // empty = all files
         password.clone(),
         strip_components,
         Some(progress_callback),
+        &cancel,
     );
     
     // Handle result and emit final progress
@@ -924,12 +1009,13 @@ fn extract_archive_with_real_progress(
             })
         }
         Err(e) => {
-            let error_msg = format!("Archive extraction failed: {}", e);
+            let cancelled = blitzarch::cancel::is_cancelled(&*e);
+            let error_msg = if cancelled { "Archive extraction cancelled".to_string() } else { format!("Archive extraction failed: {}", e) };
             let final_progress = ProgressEvent {
                 operation: "extract".to_string(),
                 progress: 0.0,
                 speed: 0.0,
-                message: "Archive extraction failed!".to_string(),
+                message: if cancelled { "Archive extraction cancelled" } else { "Archive extraction failed!" }.to_string(),
                 completed: true,
                 error: Some(error_msg.clone()),
                 
@@ -990,7 +1076,9 @@ pub fn extract_archive(
         cmd.args(["--password", &pwd]);
     }
     
-    println!("🔧 Extract command: {:?}", cmd);
+    if !blitzarch::private_logs::enabled() {
+        println!("🔧 Extract command: {:?}", cmd);
+    }
     
     // Execute command
     match cmd.output() {
@@ -1384,83 +1472,68 @@ pub async fn list_archive_async(
     .map_err(|e| e.to_string())?
 }
 
-/// Internal helper that returns archive entries by reading Katana index
-fn read_archive_index(archive_path: &str, _password: Option<String>) -> Result<Vec<ArchiveEntry>, Box<dyn std::error::Error>> {
-    use std::io::{Read, Seek, SeekFrom};
-    use zstd::decode_all;
-    use serde::Deserialize;
-
-
-    let mut f = std::fs::File::open(archive_path)?;
-    let file_len = f.metadata()?.len();
-    // Support optional BLAKE3 footer written by katana_stream (MAGIC + data_len + hash)
-    const FOOTER_MAGIC: &[u8; 16] = b"KATANA_HASH_FOOT";
-    const FOOTER_SIZE: u64 = 16 + 8 + 32; // magic + data_len + blake3
-    let data_len = {
-        if file_len >= FOOTER_SIZE {
-            // Peek possible footer
-            f.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
-            let mut magic_buf = [0u8; 16];
-            f.read_exact(&mut magic_buf)?;
-            if &magic_buf == FOOTER_MAGIC {
-                // Next 8 bytes -> original data length (without footer)
-                let mut len_bytes = [0u8; 8];
-                f.read_exact(&mut len_bytes)?;
-                u64::from_le_bytes(len_bytes)
-            } else {
-                file_len
-            }
-        } else {
-            file_len
-        }
-    };
-    println!("🔍 file_len={}, data_len={}", file_len, data_len);
-    if data_len < 24 {
-        return Err("File too small or not a Katana archive".into());
-    }
+/// Aggregate archive summary (entry count, sizes, top-level directories) for the
+/// browser view. Computed from the index only, so it returns instantly even for
+/// huge archives while the full tree is loaded lazily via `list_archive_async`.
+#[tauri::command(async)]
+pub async fn archive_index_stats(archive_path: String) -> Result<blitzarch::katana::IndexStats, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        blitzarch::katana::index_stats(Path::new(&archive_path)).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
 
-    // Read footer (index sizes + magic) located 24 bytes before end of data section (excluding optional BLAKE3 footer)
-    f.seek(SeekFrom::Start(data_len - 24))?;
-    let mut buf_footer = [0u8; 24];
-    f.read_exact(&mut buf_footer)?;
-    let (idx_comp_size_bytes, rest) = buf_footer.split_at(8);
-    let (idx_json_size_bytes, magic_bytes) = rest.split_at(8);
-    println!("🔍 Footer raw bytes: {:02X?}", buf_footer);
-    println!("🔍 idx_comp_size_le={}", u64::from_le_bytes(idx_comp_size_bytes.try_into().unwrap()));
-    println!("🔍 idx_json_size_le={}", u64::from_le_bytes(idx_json_size_bytes.try_into().unwrap()));
-    println!("🔍 magic_bytes={:?}", std::str::from_utf8(magic_bytes).unwrap_or("<nonutf8>"));
-    const LOCAL_KATANA_MAGIC: &[u8; 8] = b"KATIDX01";
-    if magic_bytes != LOCAL_KATANA_MAGIC {
-        return Err("Not a Katana archive".into());
-    }
-    let idx_comp_size = u64::from_le_bytes(idx_comp_size_bytes.try_into().unwrap());
-    let _idx_json_size = u64::from_le_bytes(idx_json_size_bytes.try_into().unwrap());
-
-    // Read compressed index
-    let idx_comp_offset = data_len - 24 - idx_comp_size;
-    f.seek(SeekFrom::Start(idx_comp_offset))?;
-    let mut idx_comp = vec![0u8; idx_comp_size as usize];
-    f.read_exact(&mut idx_comp)?;
-    let idx_json = decode_all(&*idx_comp)?;
-    #[derive(Deserialize)]
-    struct IndexFile { path: String, size: u64 }
-    #[derive(Deserialize)]
-    struct RootIndex { files: Vec<IndexFile> }
-    let index: RootIndex = serde_json::from_slice(&idx_json)?;
-
-    // Map FileEntry -> ArchiveEntry
-    let mut entries = Vec::with_capacity(index.files.len());
-    for file in index.files {
-        entries.push(ArchiveEntry {
-            path: file.path,
-            size: file.size,
-            is_dir: false,
-        });
-    }
+/// Most recent completed jobs (newest first) for the "recent operations" view.
+#[tauri::command]
+pub fn get_job_history(limit: Option<usize>) -> Result<Vec<JobRecord>, String> {
+    HistoryStore::open_default()
+        .and_then(|store| store.recent(limit.unwrap_or(50)))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn clear_job_history() -> Result<(), String> {
+    HistoryStore::open_default()
+        .and_then(|store| store.clear())
+        .map_err(|e| e.to_string())
+}
+
+/// Maximum size of a single entry returned by `preview_archive_entry`.
+const PREVIEW_BUDGET_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Returns the content of a single archive entry for the preview pane without
+/// extracting anything to disk. Entries above 16 MiB are refused.
+#[tauri::command(async)]
+pub async fn preview_archive_entry(archive_path: String, entry_path: String, password: Option<String>) -> Result<Vec<u8>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut tree = blitzarch::katana::extract_katana_to_memory(
+            Path::new(&archive_path),
+            &[PathBuf::from(&entry_path)],
+            password,
+            PREVIEW_BUDGET_BYTES,
+        )
+        .map_err(|e| e.to_string())?;
+        tree.pop_first()
+            .map(|(_, data)| data)
+            .ok_or_else(|| format!("Entry not found in archive: {}", entry_path))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Internal helper that returns archive entries by reading the archive index (Katana or classic)
+fn read_archive_index(archive_path: &str, _password: Option<String>) -> Result<Vec<ArchiveEntry>, Box<dyn std::error::Error>> {
+    // Footer + index only (one or two reads); shards are left untouched so
+    // opening archives on slow/network drives stays instant.
+    let mut entries: Vec<ArchiveEntry> = blitzarch::extract::list_entries(std::path::Path::new(archive_path), None)?
+        .into_iter()
+        .map(|file| ArchiveEntry { path: file.path, size: file.size, is_dir: file.is_dir })
+        .collect();
 
     // Also push directories for completeness (deduplicated)
     use std::collections::HashSet;
-    let mut seen_dirs = HashSet::new();
+    let mut seen_dirs: HashSet<String> = entries.iter().filter(|e| e.is_dir).map(|e| e.path.clone()).collect();
     let mut extra_dirs = Vec::new();
     for e in &entries {
         if let Some(parent) = std::path::Path::new(&e.path).parent() {
@@ -14,15 +14,21 @@ pub fn run() {
 
   builder
     .plugin(tauri_plugin_dialog::init())
+    .manage(CurrentJob::default())
     .invoke_handler(tauri::generate_handler![
         create_archive,
         create_archive_async,
+        cancel_current_job,
         get_parent_directory,
         get_downloads_path,
         extract_archive,
         extract_archive_async,
         list_archive,
         list_archive_async,
+        archive_index_stats,
+        preview_archive_entry,
+        get_job_history,
+        clear_job_history,
         drag_out_extract,
         cleanup_drag_out_temp,
         create_link_file,
@@ -31,6 +37,10 @@ pub fn run() {
         native_drag_out_global
     ])
     .setup(|app| {
+      // Re-opening the same encrypted archive (list → preview → extract) should not pay Argon2 every time
+      blitzarch::crypto::enable_key_cache(16, std::time::Duration::from_secs(15 * 60));
+      // Previewing several files of one shard decodes it once
+      blitzarch::shard_cache::enable_shard_cache(256 * 1024 * 1024);
       if cfg!(debug_assertions) {
         app.handle().plugin(
           tauri_plugin_log::Builder::default()
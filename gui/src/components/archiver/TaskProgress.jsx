@@ -2,8 +2,9 @@ import React from 'react';
 import { Card, CardContent, CardHeader, CardTitle } from '@/components/ui/card';
 import { Progress } from '@/components/ui/progress';
 import { Badge } from '@/components/ui/badge';
+import { Button } from '@/components/ui/button';
 import { motion } from 'framer-motion';
-import { Zap, Download, Upload, Clock, HardDrive } from 'lucide-react';
+import { Zap, Download, Upload, Clock, HardDrive, XCircle } from 'lucide-react';
 
 export default function TaskProgress({ 
   progress, 
@@ -18,7 +19,9 @@ export default function TaskProgress({
   totalShards = 0,
   elapsedTime = 0,
   etaSeconds = 0,
-  compressionRatio = null
+  compressionRatio = null,
+  isProcessing = false,
+  onCancel = null
 }) {
   const taskType = isCreating ? 'create' : 'extract';
   const taskIcon = isCreating ? Upload : Download;
@@ -47,6 +50,17 @@ export default function TaskProgress({
             {React.createElement(taskIcon, { className: "w-5 h-5" })}
           </motion.div>
           {taskName}
+          {isProcessing && onCancel && (
+            <Button
+              variant="outline"
+              size="sm"
+              onClick={onCancel}
+              className="ml-auto border-slate-600 text-slate-300 hover:text-red-400"
+            >
+              <XCircle className="w-4 h-4 mr-1" />
+              Cancel
+            </Button>
+          )}
         </CardTitle>
       </CardHeader>
       
@@ -68,7 +68,8 @@ class TauriBlitzArchEngine {
           archivePath: result.archive_path,
           stats: result.stats || null,
           integrityOk: result.integrity_ok ?? null,
-          blake3: result.blake3_hex ?? null
+          blake3: result.blake3_hex ?? null,
+          warnings: result.warnings || []
         };
       } else {
         return {
@@ -246,7 +247,8 @@ class TauriBlitzArchEngine {
         return {
           success: true,
           output: result.output,
-          stats: result.stats || null
+          stats: result.stats || null,
+          warnings: result.warnings || []
         };
       } else {
         console.error('❌ Archive extraction failed:', result.error);
@@ -440,6 +442,19 @@ class TauriBlitzArchEngine {
     }
   }
 
+  /**
+   * Cancel the running create / extract job.
+   * Resolves to true if a job was running; it then finishes with a cancelled result.
+   */
+  async cancelOperation() {
+    try {
+      return await invoke('cancel_current_job');
+    } catch (error) {
+      console.error('❌ Failed to cancel operation:', error);
+      return false;
+    }
+  }
+
   /**
    * Delete file using Tauri
    */
@@ -540,6 +540,13 @@ export default function BlitzArch() {
     }
   }, [settings]);
 
+  // The job stops at its next check, removes its partial output and reports a cancelled result
+  const handleCancel = async () => {
+    if (await tauriBlitzArchEngine.cancelOperation()) {
+      addLog('Cancelling operation...', 'warning');
+    }
+  };
+
   const handleLoadArchive = async (archivePath) => {
     if (!archivePath) {
       addLog('No archive selected', 'warning');
@@ -1052,6 +1059,8 @@ export default function BlitzArch() {
                 elapsedTime={elapsedTime}
                 etaSeconds={etaSeconds}
                 compressionRatio={compressionRatio}
+                isProcessing={isProcessing}
+                onCancel={handleCancel}
               />
 
               {/* Control Dashboard */}
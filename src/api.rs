@@ -0,0 +1,460 @@
+//! High-level library API.
+//!
+//! ```no_run
+//! use blitzarch::Archive;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! Archive::create(["photos", "notes.txt"])
+//!     .level(7)
+//!     .password("correct horse")
+//!     .threads(8)
+//!     .write_to("backup.blz")?;
+//!
+//! let archive = Archive::open("backup.blz")?.password("correct horse");
+//! for entry in archive.entries()? {
+//!     println!("{} ({} bytes)", entry.path, entry.size);
+//! }
+//! archive.extract_to("restore")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The builder drives the streaming Katana writer ([`crate::katana_stream`]);
+//! reading goes through [`crate::katana`]. Both remain available for callers
+//! that need lower-level control.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::extract::ExtractOptions;
+use crate::katana::{self, EntryInfo, EntryReader};
+use crate::katana_stream::{self, KatanaCreateOptions};
+use crate::ordering::FileOrder;
+use crate::progress::ProgressState;
+
+type ProgressFn = Box<dyn Fn(ProgressState) + Send + Sync>;
+
+/// Entry point of the high-level API.
+pub struct Archive;
+
+impl Archive {
+    /// Starts building a new archive from files and/or directories.
+    pub fn create<I, P>(inputs: I) -> ArchiveBuilder
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        ArchiveBuilder {
+            inputs: inputs.into_iter().map(Into::into).collect(),
+            level: None,
+            password: None,
+            threads: 0,
+            codec_threads: 0,
+            memory_budget_mb: None,
+            options: KatanaCreateOptions::default(),
+            verify: true,
+            max_duration: None,
+            progress: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            respect_gitignore: false,
+        }
+    }
+
+    /// Opens an existing archive for listing and extraction.
+    pub fn open(path: impl AsRef<Path>) -> Result<OpenArchive, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        if !katana::is_katana_archive(&path)? {
+            return Err(format!("Not a Katana archive: {}", path.display()).into());
+        }
+        Ok(OpenArchive { path, password: None, cancel: None, options: ExtractOptions::default() })
+    }
+}
+
+/// Builder returned by [`Archive::create`].
+pub struct ArchiveBuilder {
+    inputs: Vec<PathBuf>,
+    level: Option<i32>,
+    password: Option<String>,
+    threads: usize,
+    codec_threads: u32,
+    memory_budget_mb: Option<u64>,
+    options: KatanaCreateOptions,
+    verify: bool,
+    max_duration: Option<std::time::Duration>,
+    progress: Option<ProgressFn>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    respect_gitignore: bool,
+}
+
+impl ArchiveBuilder {
+    /// Zstandard level (1-22). Defaults to the AutoTune recommendation (see
+    /// [`Self::retune`]).
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Encrypts the archive (AES-256-GCM, Argon2id key derivation).
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Number of shards / worker threads. `0` = number of CPU cores.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// zstd threads per shard. `0` = AutoTune.
+    pub fn codec_threads(mut self, codec_threads: u32) -> Self {
+        self.codec_threads = codec_threads;
+        self
+    }
+
+    /// Upper bound for memory used by the writer, in MiB.
+    pub fn memory_budget_mb(mut self, mb: u64) -> Self {
+        self.memory_budget_mb = Some(mb);
+        self
+    }
+
+    /// Expert zstd parameter applied to every shard encoder.
+    pub fn zstd_param(mut self, param: zstd::stream::raw::CParameter) -> Self {
+        self.options.zstd_params.push(param);
+        self
+    }
+
+    /// Stores files under 4 KiB in the index (unencrypted archives only).
+    pub fn inline_small_files(mut self, enabled: bool) -> Self {
+        self.options.inline_small_files = enabled;
+        self
+    }
+
+    /// Order of files inside each shard (see [`crate::ordering`]).
+    pub fn ordering(mut self, ordering: Arc<dyn FileOrder>) -> Self {
+        self.options.ordering = Some(ordering);
+        self
+    }
+
+    /// Codec of the archive index (default: zstd level 3).
+    pub fn index_compression(mut self, compression: crate::katana::IndexCompression) -> Self {
+        self.options.index_compression = compression;
+        self
+    }
+
+    /// Layout of the archive index (default: version 2, see [`crate::katana::IndexFormat`]).
+    pub fn index_format(mut self, format: crate::katana::IndexFormat) -> Self {
+        self.options.index_format = format;
+        self
+    }
+
+    /// Incremental archive: files unchanged since `base` are stored as references.
+    pub fn incremental_from(mut self, base: impl Into<PathBuf>) -> Self {
+        self.options.base = Some(base.into());
+        self
+    }
+
+    /// Reads inputs through `vfs` instead of the local filesystem (see [`crate::vfs`]).
+    pub fn vfs(mut self, vfs: Arc<dyn crate::vfs::Vfs>) -> Self {
+        self.options.vfs = Some(vfs);
+        self
+    }
+
+    /// Stores byte-identical files once (see [`KatanaCreateOptions::dedup`]).
+    pub fn dedup(mut self, enabled: bool) -> Self {
+        self.options.dedup = enabled;
+        self
+    }
+
+    /// Stores distinct content-defined chunks once (see [`KatanaCreateOptions::chunk_dedup`]).
+    pub fn chunk_dedup(mut self, enabled: bool) -> Self {
+        self.options.chunk_dedup = enabled;
+        self
+    }
+
+    /// Checksum of the stored shard bytes (see [`KatanaCreateOptions::shard_checksum`]).
+    pub fn shard_checksum(mut self, checksum: crate::katana::ShardChecksum) -> Self {
+        self.options.shard_checksum = checksum;
+        self
+    }
+
+    /// Grouping of files into shards (see [`KatanaCreateOptions::shard_strategy`]).
+    pub fn shard_strategy(mut self, strategy: crate::katana_stream::ShardStrategy) -> Self {
+        self.options.shard_strategy = strategy;
+        self
+    }
+
+    /// Independent zstd frames of `bytes` per shard with a seek table (see
+    /// [`KatanaCreateOptions::seekable_frames`]).
+    pub fn seekable_frames(mut self, bytes: u64) -> Self {
+        self.options.seekable_frames = Some(bytes);
+        self
+    }
+
+    /// Adapts the level of every shard to how fast its output drains (see
+    /// [`KatanaCreateOptions::adapt`]); [`Self::level`] is the starting point.
+    pub fn adaptive_level(mut self, range: crate::katana_stream::AdaptiveLevel) -> Self {
+        self.options.adapt = Some(range);
+        self
+    }
+
+    /// Retunes the AutoTune level while compressing when no [`Self::level`]
+    /// is given (see [`KatanaCreateOptions::retune`]).
+    pub fn retune(mut self, enabled: bool) -> Self {
+        self.options.retune = enabled;
+        self
+    }
+
+    /// Records extended attributes and ACLs (see [`KatanaCreateOptions::xattrs`]).
+    pub fn xattrs(mut self, enabled: bool) -> Self {
+        self.options.xattrs = enabled;
+        self
+    }
+
+    /// Caps on concurrent reads, shard compressions and writes; unset caps are autotuned.
+    pub fn io_limits(mut self, limits: crate::autotune::IoLimits) -> Self {
+        self.options.io_limits = limits;
+        self
+    }
+
+    /// How the local filesystem source treats symbolic links (default: skip).
+    pub fn symlinks(mut self, mode: crate::vfs::SymlinkMode) -> Self {
+        self.options.symlinks = mode;
+        self
+    }
+
+    /// Cuts shards into frames at content-defined boundaries, so updated
+    /// archives sync cheaply (see [`KatanaCreateOptions::rsync_friendly`]).
+    pub fn rsync_friendly(mut self, enabled: bool) -> Self {
+        self.options.rsync_friendly = enabled;
+        self
+    }
+
+    /// Encrypts the index too, hiding entry names and sizes from anyone
+    /// without the password (needs [`Self::password`]).
+    pub fn hide_names(mut self, enabled: bool) -> Self {
+        self.options.hide_names = enabled;
+        self
+    }
+
+    /// Compresses every shard with a pre-trained zstd dictionary, referenced
+    /// by id only; readers have to be given the same dictionary (see
+    /// [`OpenArchive::dictionary`]).
+    pub fn dictionary(mut self, dictionary: crate::dictionary::ZstdDictionary) -> Self {
+        self.options.dictionary = Some(Arc::new(dictionary));
+        self
+    }
+
+    /// Attaches a key-value pair to the archive metadata (see
+    /// [`crate::katana::read_metadata`]); a later value for the same key wins.
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Keeps the shard split and file order recorded by an earlier
+    /// [`Self::export_ordering`] (see [`crate::ordering::OrderingManifest`]).
+    pub fn ordering_manifest(mut self, manifest: crate::ordering::OrderingManifest) -> Self {
+        self.options.ordering_manifest = Some(Arc::new(manifest));
+        self
+    }
+
+    /// Writes the file-to-shard assignment to `path` once the archive is complete.
+    pub fn export_ordering(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.export_ordering = Some(path.into());
+        self
+    }
+
+    /// Leaves out paths matching a glob pattern (see [`crate::fsx::PathFilter`]).
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Archives only paths matching a glob pattern; excludes still win.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Also skips what `.gitignore`/`.blitzignore` files inside the inputs exclude.
+    pub fn respect_gitignore(mut self, enabled: bool) -> Self {
+        self.respect_gitignore = enabled;
+        self
+    }
+
+    /// Leaves out regular files larger than `bytes`.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.options.size_filter.max = Some(bytes);
+        self
+    }
+
+    /// Leaves out regular files smaller than `bytes`.
+    pub fn min_file_size(mut self, bytes: u64) -> Self {
+        self.options.size_filter.min = Some(bytes);
+        self
+    }
+
+    /// Stores BlitzArch archives among the inputs without recompression, one
+    /// shard each, marked for [`crate::katana::extract_katana_archive_recursive`].
+    pub fn store_nested(mut self, enabled: bool) -> Self {
+        self.options.store_nested_archives = enabled;
+        self
+    }
+
+    /// Breaks a stale output lock left by a crashed run and overwrites a
+    /// partial output instead of failing.
+    pub fn force(mut self, enabled: bool) -> Self {
+        self.options.force = enabled;
+        self
+    }
+
+    /// Leaves out empty regular files.
+    pub fn skip_empty(mut self, enabled: bool) -> Self {
+        self.options.size_filter.skip_empty = enabled;
+        self
+    }
+
+    /// Stops adding files `duration` after [`Self::write_to`] starts; leftovers
+    /// go to a checkpoint for [`crate::timebox::resume_create`].
+    pub fn max_duration(mut self, duration: std::time::Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// Shares `budget` with the caller, who can [pause](crate::timebox::TimeBudget::pause)
+    /// the job at any time; takes precedence over [`Self::max_duration`].
+    pub fn time_budget(mut self, budget: Arc<crate::timebox::TimeBudget>) -> Self {
+        self.options.time_budget = Some(budget);
+        self
+    }
+
+    /// Re-reads the written archive and checks its BLAKE3 footer (default: on).
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Stops [`Self::write_to`] once `token` is cancelled; the partial archive
+    /// is removed and the call fails with [`crate::ArchiverError::Cancelled`].
+    pub fn cancel_token(mut self, token: crate::cancel::CancellationToken) -> Self {
+        self.options.cancel = Some(token);
+        self
+    }
+
+    /// Receives progress updates while the archive is written.
+    pub fn on_progress(mut self, callback: impl Fn(ProgressState) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Writes the archive to `path`.
+    pub fn write_to(mut self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        if let (Some(duration), None) = (self.max_duration, self.options.time_budget.as_ref()) {
+            self.options.time_budget = Some(crate::timebox::TimeBudget::new(duration));
+        }
+        if self.inputs.is_empty() {
+            return Err("No input files".into());
+        }
+        self.options.filter = crate::fsx::PathFilter::new(&self.include, &self.exclude)?.respect_ignore_files(self.respect_gitignore);
+        katana_stream::create_katana_archive_with_options(
+            &self.inputs,
+            path,
+            self.threads,
+            self.codec_threads,
+            self.memory_budget_mb,
+            self.password,
+            self.level,
+            &self.options,
+            self.progress,
+        )?;
+        if self.verify {
+            katana_stream::perform_paranoid_check(path)?;
+        }
+        crate::timebox::finish_create(path, &self.inputs, self.options.time_budget.as_deref())?;
+        Ok(())
+    }
+}
+
+/// An archive opened with [`Archive::open`].
+#[derive(Debug, Clone)]
+pub struct OpenArchive {
+    path: PathBuf,
+    password: Option<String>,
+    cancel: Option<crate::cancel::CancellationToken>,
+    options: ExtractOptions,
+}
+
+impl OpenArchive {
+    /// Password for encrypted archives.
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Stops extraction once `token` is cancelled, removing the files it created.
+    pub fn cancel_token(mut self, token: crate::cancel::CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Settings of this archive's extractions (permissions, conflicts, …).
+    pub fn extract_options(mut self, options: ExtractOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// zstd dictionary the archive was created with; may be given several
+    /// times for archives appended with different dictionaries.
+    pub fn dictionary(mut self, dictionary: crate::dictionary::ZstdDictionary) -> Self {
+        self.options.dictionaries.insert(dictionary);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// All entries in archive order.
+    pub fn entries(&self) -> Result<Vec<EntryInfo>, Box<dyn Error>> {
+        katana::list_entries(&self.path, self.password.as_deref())
+    }
+
+    /// Extracts everything into `dir`.
+    pub fn extract_to(&self, dir: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        self.extract_files_to(&[] as &[&str], dir)
+    }
+
+    /// Extracts only the given entries into `dir`.
+    pub fn extract_files_to<P: AsRef<Path>>(&self, files: &[P], dir: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let selected: Vec<PathBuf> = files.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        std::fs::create_dir_all(dir.as_ref())?;
+        katana::extract_katana_archive_with_options(
+            &self.path,
+            dir.as_ref(),
+            &selected,
+            self.password.clone(),
+            None,
+            None::<fn(ProgressState)>,
+            self.cancel.as_ref(),
+            &self.options,
+        )
+    }
+
+    /// Streams a single entry without extracting it to disk.
+    pub fn open_entry(&self, entry: &str) -> Result<EntryReader, Box<dyn Error>> {
+        katana::open_entry_with(&self.path, entry, self.password.as_deref(), &self.options.dictionaries)
+    }
+
+    /// Reads a single entry fully into memory.
+    pub fn read(&self, entry: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut reader = self.open_entry(entry)?;
+        let mut data = Vec::with_capacity(reader.size() as usize);
+        std::io::Read::read_to_end(&mut reader, &mut data)?;
+        Ok(data)
+    }
+}
@@ -50,6 +50,9 @@ pub struct FileIndexEntry {
     pub uncompressed_size: u64,
     /// The Unix-style permissions of the file or directory, if available.
     pub permissions: Option<u32>,
+    /// Creation (birth) time, seconds since the Unix epoch, if the source recorded one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_time: Option<i64>,
 }
 
 /// Represents the central directory of the archive.
@@ -68,6 +71,9 @@ pub struct ArchiveIndex {
     /// in the index is key to improving the compression ratio for datasets with many
     /// similar, small files.
     pub dictionary: Option<Vec<u8>>,
+    /// Free-form key-value metadata attached at create time (`--meta`, `--comment`).
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub metadata: std::collections::BTreeMap<String, String>,
 }
 
 /// Represents the footer of the archive, located at the end of the file.
@@ -112,6 +118,11 @@ pub struct ArchiveWriter {
 fn default_algo() -> String { "zstd".into() }
 
 impl ArchiveWriter {
+    /// Metadata recorded in the index on [`finalize`](Self::finalize).
+    pub fn set_metadata(&mut self, metadata: std::collections::BTreeMap<String, String>) {
+        self.index.metadata = metadata;
+    }
+
     /// Set the algorithm tag for the *next* bundle to be written.
     /// This does **not** modify the global `index.compression_algo`,
     /// only the field used for BundleInfo generation.
@@ -129,11 +140,7 @@ impl ArchiveWriter {
     /// * `password` - An optional password to encrypt the archive.
     /// * `algo` - The default compression algorithm to use for bundles.
     pub fn new(output_file: File, password: Option<String>, algo: CompressionAlgo) -> Result<Self, ArchiverError> {
-        let algo_str: String = match algo {
-            CompressionAlgo::Zstd => "zstd".into(),
-            CompressionAlgo::Lzma2 { .. } => "lzma2".into(),
-            CompressionAlgo::Store => "store".into(),
-        };
+        let algo_str: String = algo.tag().into();
         let salt = if password.is_some() { Some(generate_salt()) } else { None };
     // Pre-derive encryption key once if password provided
     let key_bytes_opt: Option<[u8; 32]> = if let (Some(ref pass), Some(ref salt_bytes)) = (password.as_ref(), salt.as_ref()) {
@@ -163,6 +170,7 @@ impl ArchiveWriter {
                 entries: Vec::new(),
                 bundles: Vec::new(),
                 dictionary: None,
+                metadata: Default::default(),
             },
             header_bytes,
             current_offset: HEADER_SIZE,
@@ -186,7 +194,8 @@ impl ArchiveWriter {
     }
 
     /// Adds a file or directory entry to the central index.
-    pub fn add_file_entry(&mut self, path: PathBuf, is_dir: bool, bundle_id: u32, offset_in_bundle: u64, stored_size: u64, uncompressed_size: u64, permissions: Option<u32>) {
+    /// Returns the new entry for fields not covered by the arguments (`created_time`).
+    pub fn add_file_entry(&mut self, path: PathBuf, is_dir: bool, bundle_id: u32, offset_in_bundle: u64, stored_size: u64, uncompressed_size: u64, permissions: Option<u32>) -> &mut FileIndexEntry {
         self.index.entries.push(FileIndexEntry {
             path,
             is_dir,
@@ -195,8 +204,10 @@ impl ArchiveWriter {
             stored_size,
             uncompressed_size,
             permissions,
+            created_time: None,
         });
         self.index.header.file_count += 1;
+        self.index.entries.last_mut().expect("entry just added")
     }
 
     /// Adds a shared compression dictionary to the archive index.
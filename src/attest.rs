@@ -0,0 +1,123 @@
+//! Detached integrity attestations (`blitzarch attest`).
+//!
+//! An attestation is a small JSON file next to the archive (`archive.blz.sig` by
+//! default) holding the BLAKE3 hash and size of the whole archive file. Existing
+//! archives gain an integrity record without being rewritten, and the file can be
+//! shipped or stored separately from the archive itself.
+//!
+//! With a key file the hash is keyed (`blake3-keyed`), turning the attestation
+//! into a MAC: only holders of the same key can produce or check it.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Format version written into new attestations.
+pub const ATTESTATION_VERSION: u32 = 1;
+
+/// Context string for deriving the MAC key from key-file contents.
+const KEY_CONTEXT: &str = "BlitzArch attest v1 key";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub version: u32,
+    /// `blake3` or `blake3-keyed`.
+    pub algorithm: String,
+    /// File name of the attested archive (informational).
+    pub archive: String,
+    pub size: u64,
+    /// Hex-encoded 32-byte hash.
+    pub hash: String,
+    /// Unix time the attestation was created.
+    pub created: i64,
+}
+
+/// `<archive>.sig`
+pub fn default_attestation_path(archive_path: &Path) -> PathBuf {
+    let mut name = archive_path.as_os_str().to_owned();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+/// Derives the MAC key from the contents of a key file.
+pub fn key_from_file(key_file: &Path) -> Result<[u8; 32], Box<dyn Error>> {
+    let material = std::fs::read(key_file)?;
+    if material.is_empty() {
+        return Err(format!("Key file {} is empty", key_file.display()).into());
+    }
+    Ok(blake3::derive_key(KEY_CONTEXT, &material))
+}
+
+/// Hashes `archive_path` and returns its attestation (not written anywhere).
+pub fn attest_archive(archive_path: &Path, key: Option<&[u8; 32]>) -> Result<Attestation, Box<dyn Error>> {
+    let (hash, size) = hash_file(archive_path, key)?;
+    Ok(Attestation {
+        version: ATTESTATION_VERSION,
+        algorithm: algorithm_name(key).to_string(),
+        archive: archive_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        size,
+        hash: hash.to_hex().to_string(),
+        created: crate::fsx::now_secs(),
+    })
+}
+
+/// Hashes `archive_path` and writes the attestation to `out`.
+pub fn write_attestation(archive_path: &Path, out: &Path, key: Option<&[u8; 32]>) -> Result<Attestation, Box<dyn Error>> {
+    let attestation = attest_archive(archive_path, key)?;
+    let mut json = serde_json::to_vec_pretty(&attestation)?;
+    json.push(b'\n');
+    std::fs::write(out, json)?;
+    Ok(attestation)
+}
+
+/// Checks `archive_path` against the attestation stored in `sig_path`.
+/// Returns the attestation on success; any mismatch is an error.
+pub fn verify_attestation(archive_path: &Path, sig_path: &Path, key: Option<&[u8; 32]>) -> Result<Attestation, Box<dyn Error>> {
+    let attestation: Attestation = serde_json::from_slice(&std::fs::read(sig_path)?)
+        .map_err(|e| format!("Invalid attestation file {}: {}", sig_path.display(), e))?;
+    if attestation.version > ATTESTATION_VERSION {
+        return Err(format!("Unsupported attestation version {}", attestation.version).into());
+    }
+    if attestation.algorithm != algorithm_name(key) {
+        return Err(match key {
+            Some(_) => "Attestation is not keyed; verify without --key-file".into(),
+            None => format!("Attestation uses {}; a --key-file is required", attestation.algorithm).into(),
+        });
+    }
+    let expected = blake3::Hash::from_hex(&attestation.hash).map_err(|_| "Invalid hash in attestation file")?;
+
+    let size = std::fs::metadata(archive_path)?.len();
+    if size != attestation.size {
+        return Err(format!("Attestation mismatch: size is {} bytes, attested {}", size, attestation.size).into());
+    }
+    let (hash, _) = hash_file(archive_path, key)?;
+    // blake3::Hash compares in constant time
+    if hash != expected {
+        return Err("Attestation mismatch: archive contents changed".into());
+    }
+    Ok(attestation)
+}
+
+fn algorithm_name(key: Option<&[u8; 32]>) -> &'static str {
+    if key.is_some() { "blake3-keyed" } else { "blake3" }
+}
+
+fn hash_file(path: &Path, key: Option<&[u8; 32]>) -> Result<(blake3::Hash, u64), Box<dyn Error>> {
+    let mut f = File::open(path)?;
+    let mut hasher = match key {
+        Some(k) => blake3::Hasher::new_keyed(k),
+        None => blake3::Hasher::new(),
+    };
+    let mut buf = vec![0u8; 1 << 20];
+    let mut size = 0u64;
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 { break; }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((hasher.finalize(), size))
+}
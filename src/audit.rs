@@ -0,0 +1,77 @@
+//! Static checks of archive contents (`blitzarch audit`).
+//!
+//! Rules only look at the index, so an audit is cheap even for huge archives and
+//! never decrypts shard data. Each finding names the rule that produced it:
+//!
+//! * `timestamp-skew` – modification time before 1970 or further in the future
+//!   than [`crate::katana::MTIME_FUTURE_TOLERANCE_SECS`]; such mtimes are
+//!   clamped on extract.
+
+use std::error::Error;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::katana::{self, EntryInfo};
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct AuditFinding {
+    pub rule: &'static str,
+    pub path: String,
+    pub message: String,
+}
+
+/// Runs all rules against the index of a Katana archive.
+pub fn audit_archive(archive_path: &Path, password: Option<&str>) -> Result<Vec<AuditFinding>, Box<dyn Error>> {
+    let entries = katana::list_entries(archive_path, password)?;
+    let mut findings = Vec::new();
+    check_timestamps(&entries, crate::fsx::now_secs(), &mut findings);
+    Ok(findings)
+}
+
+fn check_timestamps(entries: &[EntryInfo], now: i64, findings: &mut Vec<AuditFinding>) {
+    for entry in entries {
+        let Some(mtime) = entry.mtime else { continue };
+        if let Some((clamped, reason)) = katana::check_mtime(mtime, now) {
+            findings.push(AuditFinding {
+                rule: "timestamp-skew",
+                path: entry.path.clone(),
+                message: format!("mtime {} is {}; extract restores {}", mtime, reason, clamped),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_timestamps;
+    use crate::katana::{EntryInfo, MTIME_FUTURE_TOLERANCE_SECS};
+
+    #[test]
+    fn test_timestamp_rule() {
+        let now = 1_700_000_000;
+        let entry = |path: &str, mtime| EntryInfo {
+            path: path.into(),
+            size: 1,
+            permissions: None,
+            mtime,
+            is_dir: false,
+            shard_id: Some(0),
+            compressed_size: None,
+            symlink: None,
+            duplicate_of: None,
+        };
+        let entries = [
+            entry("ok", Some(now - 10)),
+            entry("slightly-ahead", Some(now + 60)),
+            entry("future", Some(now + MTIME_FUTURE_TOLERANCE_SECS + 1)),
+            entry("ancient", Some(-5)),
+            entry("legacy", None),
+        ];
+        let mut findings = Vec::new();
+        check_timestamps(&entries, now, &mut findings);
+        let paths: Vec<_> = findings.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["future", "ancient"]);
+        assert!(findings.iter().all(|f| f.rule == "timestamp-skew"));
+    }
+}
@@ -5,10 +5,25 @@
 
 use std::time::{Duration, Instant};
 use sysinfo::{System, Pid, Process};
+use crate::console::println;
 
 /// Memory budget in bytes
 pub type MemoryBudget = usize;
 
+/// Budget used when none is given: 70% of the system memory.
+pub fn default_memory_budget() -> MemoryBudget {
+    let mut sys = System::new();
+    sys.refresh_memory();
+    (sys.total_memory() as f64 * 0.7) as usize
+}
+
+/// Budget in bytes for an optional `--memory-budget` value in MiB.
+pub fn memory_budget_from_mb(mem_budget_mb: Option<u64>) -> MemoryBudget {
+    mem_budget_mb
+        .map(|mb| (mb as usize).saturating_mul(1024 * 1024))
+        .unwrap_or_else(default_memory_budget)
+}
+
 /// The main bottleneck types that can limit performance
 #[derive(Debug, Clone, PartialEq)]
 pub enum BottleneckType {
@@ -417,6 +432,11 @@ impl AutoTuner {
         }
     }
 
+    /// Tuner for an optional budget in MiB, see [`memory_budget_from_mb`].
+    pub fn with_budget_mb(mem_budget_mb: Option<u64>) -> Self {
+        Self::new(memory_budget_from_mb(mem_budget_mb))
+    }
+
     /// Main tuning method: analyze current state and return optimal configuration
     pub fn tune(&mut self, compression_stats: Option<&CompressionStats>) -> OptimalConfig {
         self.adaptation_counter += 1;
@@ -464,6 +484,52 @@ impl AutoTuner {
     }
 }
 
+/// Caps on concurrency inside the create pipeline (`--max-reads`,
+/// `--max-compressions`, `--max-writes`); `None` leaves a cap to autotuning.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoLimits {
+    /// Concurrent reads of input files.
+    pub reads: Option<usize>,
+    /// Shards compressed at the same time.
+    pub compressions: Option<usize>,
+    /// Concurrent writes of compressed shard data.
+    pub writes: Option<usize>,
+}
+
+/// Resolved [`IoLimits`], each between 1 and the number of shard workers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoConcurrency {
+    pub reads: usize,
+    pub compressions: usize,
+    pub writes: usize,
+}
+
+impl IoLimits {
+    /// Fills in unset caps for `workers` shard workers. Network sources get at
+    /// most 4 parallel reads (massive parallel reads thrash NFS/SMB servers),
+    /// I/O-bound disks half of the workers for reads and writes, and memory
+    /// pressure halves the number of shards compressed at once.
+    pub fn resolve(&self, workers: usize, bottleneck: &BottleneckType, network_input: bool) -> IoConcurrency {
+        let workers = workers.max(1);
+        let half = (workers / 2).max(1);
+        let io_bound = matches!(bottleneck, BottleneckType::IOBound | BottleneckType::FragmentedIO);
+        let auto_reads = if network_input {
+            workers.min(4)
+        } else if io_bound {
+            half
+        } else {
+            workers
+        };
+        let auto_compressions = if *bottleneck == BottleneckType::MemoryBound { half } else { workers };
+        let auto_writes = if io_bound { half } else { workers };
+        IoConcurrency {
+            reads: self.reads.unwrap_or(auto_reads).clamp(1, workers),
+            compressions: self.compressions.unwrap_or(auto_compressions).clamp(1, workers),
+            writes: self.writes.unwrap_or(auto_writes).clamp(1, workers),
+        }
+    }
+}
+
 /// Compression-specific statistics provided by the compression engine
 #[derive(Debug, Clone)]
 pub struct CompressionStats {
@@ -477,4 +543,159 @@ pub struct CompressionStats {
     pub files_processed: usize,
     /// Total bytes processed so far
     pub bytes_processed: u64,
+    /// Share of the workers' time spent waiting for input and output (0.0-1.0)
+    pub io_wait: f64,
+}
+
+/// Uncompressed bytes [`AutoTuner::retune_level`] wants to see at one level
+/// before it moves to another.
+pub const LEVEL_SAMPLE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Compression ratio below which data counts as incompressible.
+pub const INCOMPRESSIBLE_RATIO: f64 = 1.05;
+
+/// Highest level mid-run tuning raises to on its own; zstd slows down steeply above it.
+pub const MAX_AUTO_LEVEL: i32 = 9;
+
+impl AutoTuner {
+    /// Retunes the zstd level mid-run from what the shards achieved at `level`:
+    /// incompressible data drops to level 1, workers mostly waiting for I/O
+    /// get a higher level (the CPU would idle anyway) and busy workers that
+    /// compress slowly a lower one.
+    pub fn retune_level(&mut self, stats: &CompressionStats, level: i32) -> i32 {
+        if stats.bytes_processed < LEVEL_SAMPLE_BYTES {
+            return level;
+        }
+        let (bottleneck, next) = if stats.compression_ratio < INCOMPRESSIBLE_RATIO {
+            (BottleneckType::CompressionLimited, 1)
+        } else if stats.io_wait > 0.5 {
+            (BottleneckType::IOBound, (level + 1).min(MAX_AUTO_LEVEL.max(level)))
+        } else if stats.io_wait < 0.1 && stats.mbps_per_thread < 25.0 {
+            (BottleneckType::CompressionLimited, level - 1)
+        } else {
+            (BottleneckType::Balanced, level)
+        };
+        let next = next.max(1);
+        if next != level {
+            println!(
+                "[AutoTune] ratio {:.2}, {:.1} MB/s per thread, {:.0}% waiting on I/O → level {} → {}",
+                stats.compression_ratio,
+                stats.mbps_per_thread,
+                stats.io_wait * 100.0,
+                level,
+                next
+            );
+            self.current_bottleneck = bottleneck;
+        }
+        next
+    }
+}
+
+/// Compression level shared by the shard encoders of one create job while an
+/// [`AutoTuner`] retunes it from the windows they report (`create --retune`).
+/// Alongside the level it tunes the zstd threads of new frames and, once data
+/// stays incompressible at level 1, switches shards started later to Store.
+pub struct LevelTuning {
+    level: std::sync::atomic::AtomicI32,
+    codec_threads: std::sync::atomic::AtomicU32,
+    max_codec_threads: u32,
+    store: std::sync::atomic::AtomicBool,
+    state: std::sync::Mutex<(AutoTuner, LevelSample)>,
+}
+
+/// What the encoders reported since the last change.
+#[derive(Default)]
+struct LevelSample {
+    bytes_in: u64,
+    bytes_out: u64,
+    busy: Duration,
+    elapsed: Duration,
+}
+
+impl LevelTuning {
+    pub fn new(tuner: AutoTuner, level: i32) -> Self {
+        LevelTuning {
+            level: std::sync::atomic::AtomicI32::new(level),
+            codec_threads: std::sync::atomic::AtomicU32::new(0),
+            max_codec_threads: 0,
+            store: std::sync::atomic::AtomicBool::new(false),
+            state: std::sync::Mutex::new((tuner, LevelSample::default())),
+        }
+    }
+
+    /// Also tunes the zstd threads per encoder, between 1 and `threads`
+    /// (0 or 1 keeps single-threaded encoders).
+    pub fn with_codec_threads(mut self, threads: u32) -> Self {
+        self.codec_threads = std::sync::atomic::AtomicU32::new(threads);
+        self.max_codec_threads = threads;
+        self
+    }
+
+    /// Level new frames should use.
+    pub fn level(&self) -> i32 {
+        self.level.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// zstd threads new frames should use.
+    pub fn codec_threads(&self) -> u32 {
+        self.codec_threads.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Whether shards started from now on are stored uncompressed.
+    pub fn store(&self) -> bool {
+        self.store.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Reports a window one encoder compressed at `level`: `bytes_in` became
+    /// `bytes_out` in `busy` of `elapsed` (the rest went to reading input and
+    /// waiting for the output). Returns the level to go on with.
+    pub fn record(&self, level: i32, bytes_in: u64, bytes_out: u64, busy: Duration, elapsed: Duration) -> i32 {
+        use std::sync::atomic::Ordering;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let current = self.level();
+        // Окно ещё на прежнем уровне – новую выборку не портит
+        if level != current {
+            return current;
+        }
+        let (tuner, sample) = &mut *state;
+        sample.bytes_in += bytes_in;
+        sample.bytes_out += bytes_out;
+        sample.busy += busy;
+        sample.elapsed += elapsed.max(busy);
+        let busy_secs = sample.busy.as_secs_f64().max(1e-9);
+        let stats = CompressionStats {
+            avg_file_size: 0,
+            mbps_per_thread: sample.bytes_in as f64 / (1024.0 * 1024.0) / busy_secs,
+            compression_ratio: sample.bytes_in as f64 / sample.bytes_out.max(1) as f64,
+            files_processed: 0,
+            bytes_processed: sample.bytes_in,
+            io_wait: 1.0 - busy_secs / sample.elapsed.as_secs_f64().max(busy_secs),
+        };
+        if stats.bytes_processed < LEVEL_SAMPLE_BYTES {
+            return current;
+        }
+        let next = tuner.retune_level(&stats, current);
+        if next == 1 && current == 1 && stats.compression_ratio < INCOMPRESSIBLE_RATIO && !self.store() {
+            println!("[AutoTune] ratio {:.2} at level 1 → storing the remaining shards", stats.compression_ratio);
+            self.store.store(true, Ordering::Relaxed);
+        }
+        // Кодер ждёт ввода-вывода – лишние потоки zstd простаивают; занят и медленный – добавляем
+        let threads = self.codec_threads();
+        let next_threads = if self.max_codec_threads <= 1 {
+            threads
+        } else if stats.io_wait > 0.5 {
+            threads.saturating_sub(1).max(1)
+        } else if stats.io_wait < 0.1 && stats.mbps_per_thread < 25.0 {
+            (threads + 1).min(self.max_codec_threads)
+        } else {
+            threads
+        };
+        if next_threads != threads {
+            println!("[AutoTune] codec threads {threads} → {next_threads}");
+            self.codec_threads.store(next_threads, Ordering::Relaxed);
+        }
+        self.level.store(next, Ordering::Relaxed);
+        *sample = LevelSample::default();
+        next
+    }
 }
@@ -0,0 +1,240 @@
+//! Throughput benchmark with a regression gate (`blitzarch bench`).
+//!
+//! Archives the inputs into a private temp dir and extracts them again, a few
+//! times, and reports the median create and extract throughput. The report can
+//! be saved as JSON (`--json`) and later used as the baseline of another run
+//! (`--compare baseline.json --fail-threshold 5%`), which fails if a throughput
+//! dropped by more than the threshold – e.g. in CI or before upgrading. A
+//! baseline measured on other inputs, at another level or thread count, or in
+//! another report format is refused rather than compared.
+//!
+//! `cargo bench --bench throughput_benchmark` runs the same measurement.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use crate::console::println;
+
+/// Format version written into new reports.
+pub const BENCH_REPORT_VERSION: u32 = 1;
+
+/// What to measure.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    pub level: i32,
+    /// Worker threads, 0 = auto.
+    pub threads: usize,
+    /// Create + extract rounds; the median of each is reported.
+    pub runs: usize,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        BenchOptions { level: 3, threads: 0, runs: 3 }
+    }
+}
+
+/// Result of [`run_bench`], stable JSON for `--compare`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub version: u32,
+    /// BlitzArch version that produced the report.
+    pub blitzarch: String,
+    pub files: usize,
+    pub input_bytes: u64,
+    pub archive_bytes: u64,
+    pub level: i32,
+    /// Worker threads the runs used (auto resolved to the core count).
+    pub threads: usize,
+    pub runs: usize,
+    /// Median wall time of one create / extract, in seconds.
+    pub create_secs: f64,
+    pub extract_secs: f64,
+    /// Input bytes per second of the median run, in MB/s.
+    pub create_mb_s: f64,
+    pub extract_mb_s: f64,
+    /// Unix time the benchmark finished.
+    pub created: i64,
+}
+
+impl BenchReport {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let report: BenchReport = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| format!("Invalid benchmark report {}: {}", path.display(), e))?;
+        if report.version > BENCH_REPORT_VERSION {
+            return Err(format!("Unsupported benchmark report version {}", report.version).into());
+        }
+        Ok(report)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn print_summary(&self) {
+        println!(
+            "[bench] {} files, {:.2} MiB → {:.2} MiB (level {}, {} runs)",
+            self.files,
+            self.input_bytes as f64 / (1024.0 * 1024.0),
+            self.archive_bytes as f64 / (1024.0 * 1024.0),
+            self.level,
+            self.runs
+        );
+        println!("[bench] create:  {:>9.1} MB/s ({:.3}s)", self.create_mb_s, self.create_secs);
+        println!("[bench] extract: {:>9.1} MB/s ({:.3}s)", self.extract_mb_s, self.extract_secs);
+    }
+}
+
+/// One throughput of a [`BenchComparison`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDelta {
+    pub name: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+    /// Relative change in percent (negative = slower).
+    pub change_pct: f64,
+}
+
+/// A report checked against a baseline.
+#[derive(Debug, Clone)]
+pub struct BenchComparison {
+    pub metrics: Vec<MetricDelta>,
+    /// Allowed slowdown in percent.
+    pub threshold_pct: f64,
+    /// Settings in which the two runs differ (`"level 3 → 5"`); the
+    /// throughputs are not comparable then.
+    pub mismatches: Vec<String>,
+    /// BlitzArch versions of the baseline and the current run, if they differ.
+    pub versions: Option<(String, String)>,
+}
+
+impl BenchComparison {
+    pub fn new(baseline: &BenchReport, current: &BenchReport, threshold_pct: f64) -> Self {
+        let delta = |name, baseline: f64, current: f64| MetricDelta {
+            name,
+            baseline,
+            current,
+            change_pct: if baseline > 0.0 { (current - baseline) / baseline * 100.0 } else { 0.0 },
+        };
+        let mut mismatches = Vec::new();
+        let mut differ = |what: &str, baseline: String, current: String| {
+            if baseline != current {
+                mismatches.push(format!("{what} {baseline} → {current}"));
+            }
+        };
+        differ("report version", baseline.version.to_string(), current.version.to_string());
+        differ("files", baseline.files.to_string(), current.files.to_string());
+        differ("input bytes", baseline.input_bytes.to_string(), current.input_bytes.to_string());
+        differ("level", baseline.level.to_string(), current.level.to_string());
+        differ("threads", baseline.threads.to_string(), current.threads.to_string());
+        BenchComparison {
+            metrics: vec![
+                delta("create", baseline.create_mb_s, current.create_mb_s),
+                delta("extract", baseline.extract_mb_s, current.extract_mb_s),
+            ],
+            threshold_pct,
+            mismatches,
+            versions: (baseline.blitzarch != current.blitzarch).then(|| (baseline.blitzarch.clone(), current.blitzarch.clone())),
+        }
+    }
+
+    /// The `--compare` gate: an error for a baseline that cannot be compared
+    /// or a throughput that dropped by more than the threshold.
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        if !self.mismatches.is_empty() {
+            return Err(format!("Baseline is not comparable: {}", self.mismatches.join(", ")).into());
+        }
+        if !self.regressions().is_empty() {
+            return Err(format!("Performance regression beyond {}%", self.threshold_pct).into());
+        }
+        Ok(())
+    }
+
+    /// Metrics that slowed down by more than the threshold.
+    pub fn regressions(&self) -> Vec<&MetricDelta> {
+        self.metrics.iter().filter(|m| m.change_pct < -self.threshold_pct).collect()
+    }
+
+    pub fn print_summary(&self) {
+        for mismatch in &self.mismatches {
+            println!("[bench] ⚠️  Baseline differs: {mismatch}");
+        }
+        if let Some((baseline, current)) = &self.versions {
+            println!("[bench] BlitzArch {baseline} → {current}");
+        }
+        for m in &self.metrics {
+            let verdict = if m.change_pct < -self.threshold_pct { "❌ regression" } else { "ok" };
+            println!(
+                "[bench] {:<8} {:>9.1} → {:>9.1} MB/s ({:+.1}%) {}",
+                m.name, m.baseline, m.current, m.change_pct, verdict
+            );
+        }
+    }
+}
+
+/// Runs `options.runs` create + extract rounds of `inputs` in a temp dir.
+pub fn run_bench(inputs: &[PathBuf], options: &BenchOptions) -> Result<BenchReport, Box<dyn Error>> {
+    if inputs.is_empty() {
+        return Err("No inputs to benchmark".into());
+    }
+    let runs = options.runs.max(1);
+    let work = tempfile::Builder::new().prefix(&format!("{}bench-", crate::temp_manager::TEMP_PREFIX)).tempdir()?;
+    let archive_path = work.path().join("bench.blz");
+
+    let mut create_times = Vec::with_capacity(runs);
+    let mut extract_times = Vec::with_capacity(runs);
+    for run in 0..runs {
+        let _ = std::fs::remove_file(&archive_path);
+        let started = Instant::now();
+        crate::katana_stream::create_katana_archive(
+            inputs,
+            &archive_path,
+            options.threads,
+            0,
+            None,
+            None,
+            Some(options.level),
+            None::<fn(crate::progress::ProgressState)>,
+        )?;
+        create_times.push(started.elapsed().as_secs_f64());
+
+        let out_dir = work.path().join(format!("extract-{}", run));
+        std::fs::create_dir_all(&out_dir)?;
+        let started = Instant::now();
+        crate::katana::extract_katana_archive_internal(&archive_path, &out_dir, &[], None, None)?;
+        extract_times.push(started.elapsed().as_secs_f64());
+        std::fs::remove_dir_all(&out_dir)?;
+    }
+
+    let (files, input_bytes) = crate::katana::list_entries(&archive_path, None)?
+        .iter()
+        .fold((0usize, 0u64), |(n, bytes), e| (n + 1, bytes + e.size));
+    let create_secs = median(&mut create_times);
+    let extract_secs = median(&mut extract_times);
+    let mb_s = |secs: f64| if secs > 0.0 { input_bytes as f64 / 1_000_000.0 / secs } else { 0.0 };
+    Ok(BenchReport {
+        version: BENCH_REPORT_VERSION,
+        blitzarch: env!("CARGO_PKG_VERSION").to_string(),
+        files,
+        input_bytes,
+        archive_bytes: std::fs::metadata(&archive_path)?.len(),
+        level: options.level,
+        threads: if options.threads == 0 { num_cpus::get() } else { options.threads },
+        runs,
+        create_secs,
+        extract_secs,
+        create_mb_s: mb_s(create_secs),
+        extract_mb_s: mb_s(extract_secs),
+        created: crate::fsx::now_secs(),
+    })
+}
+
+fn median(samples: &mut [f64]) -> f64 {
+    samples.sort_by(|a, b| a.total_cmp(b));
+    samples[samples.len() / 2]
+}
@@ -6,9 +6,10 @@
 //! приложения из терминала с аргументами.
 
 fn main() -> std::process::ExitCode {
+    let error_format = blitzarch::cli::error_format_from_args(std::env::args());
     if let Err(e) = blitzarch::cli_runner::run_cli_app() {
         if e.downcast_ref::<clap::Error>().is_none() {
-            eprintln!("Error: {}", e);
+            blitzarch::cli::print_error(e.as_ref(), error_format);
         }
         return std::process::ExitCode::FAILURE;
     }
@@ -6,6 +6,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let archive_path = Path::new("test.blz");
     println!("Listing files in {:?}:", archive_path);
     let file = File::open(archive_path)?;
-    list_files(file)?;
+    list_files(file, None)?;
     Ok(())
 }
@@ -0,0 +1,69 @@
+//! Cooperative cancellation of create / extract jobs.
+//!
+//! A [`CancellationToken`] is shared between a running job and whoever may
+//! stop it (the GUI's cancel button, a signal handler). Workers poll it between
+//! files and read buffers; a cancelled job stops its workers, removes the
+//! partial output and fails with [`ArchiverError::Cancelled`].
+
+use std::error::Error;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::ArchiverError;
+
+/// Cheaply clonable flag; all clones observe the same cancellation.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation. The job notices it at its next check.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// `Err(ArchiverError::Cancelled)` once cancellation was requested.
+    pub fn check(&self) -> Result<(), ArchiverError> {
+        if self.is_cancelled() {
+            Err(ArchiverError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// True if `err` (or anything in its source chain) is [`ArchiverError::Cancelled`].
+pub fn is_cancelled(err: &(dyn Error + 'static)) -> bool {
+    let mut cur = Some(err);
+    while let Some(e) = cur {
+        if matches!(e.downcast_ref::<ArchiverError>(), Some(ArchiverError::Cancelled)) {
+            return true;
+        }
+        cur = e.source();
+    }
+    false
+}
+
+/// Reader that fails with [`ArchiverError::Cancelled`] once the token is set,
+/// so decoders deep inside the extractors stop at the next buffer.
+pub(crate) struct CancellableReader<'t, R> {
+    pub inner: R,
+    pub token: Option<&'t CancellationToken>,
+}
+
+impl<R: Read> Read for CancellableReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.token.is_some_and(|t| t.is_cancelled()) {
+            return Err(io::Error::other(ArchiverError::Cancelled));
+        }
+        self.inner.read(buf)
+    }
+}
@@ -0,0 +1,216 @@
+//! Content-defined chunking for `--dedup=chunks` and `--rsync-friendly` frames.
+//!
+//! A FastCDC-style gear hash picks cut points from the data itself, so an
+//! insertion or deletion only changes the chunks around it; the rest of a
+//! modified VM image or rebuilt object file still matches chunks stored for
+//! other files. Cut points are decided at create time only – extraction just
+//! concatenates the chunks an entry lists, so these parameters can change
+//! without affecting existing archives.
+
+use std::io::{self, Read};
+
+/// No cut before this many bytes (except at the end of the input).
+pub const MIN_CHUNK: usize = 16 * 1024;
+/// Target average chunk size.
+pub const AVG_CHUNK: usize = 64 * 1024;
+/// Chunks are cut here at the latest.
+pub const MAX_CHUNK: usize = 256 * 1024;
+
+// Normalized chunking: a stricter mask before the average size, a looser one
+// after it, which keeps chunk sizes close to AVG_CHUNK. The gear hash shifts
+// left, so its top bits depend on the most recent bytes.
+const MASK_STRICT: u64 = !0u64 << (64 - (AVG_CHUNK.trailing_zeros() + 2));
+const MASK_LOOSE: u64 = !0u64 << (64 - (AVG_CHUNK.trailing_zeros() - 2));
+
+/// Random table of the gear hash (splitmix64, fixed seed).
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Length of the first chunk of `data`. Inputs shorter than [`MAX_CHUNK`] are
+/// taken as the end of the stream: without a cut point they form one chunk.
+pub fn cut_point(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK);
+    let normal = end.min(AVG_CHUNK);
+    let mut hash = 0u64;
+    for (i, &b) in data.iter().enumerate().take(end).skip(MIN_CHUNK) {
+        hash = (hash << 1).wrapping_add(GEAR[b as usize]);
+        let mask = if i < normal { MASK_STRICT } else { MASK_LOOSE };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Splits a stream into content-defined chunks.
+pub struct Chunker<R> {
+    inner: R,
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(inner: R) -> Self {
+        Chunker { inner, buf: vec![0u8; 2 * MAX_CHUNK], start: 0, end: 0, eof: false }
+    }
+
+    /// The next chunk, `None` at the end of the stream.
+    pub fn next_chunk(&mut self) -> io::Result<Option<&[u8]>> {
+        if self.end - self.start < MAX_CHUNK && !self.eof {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+            while self.end < self.buf.len() {
+                match self.inner.read(&mut self.buf[self.end..]) {
+                    Ok(0) => {
+                        self.eof = true;
+                        break;
+                    }
+                    Ok(n) => self.end += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        if self.start == self.end {
+            return Ok(None);
+        }
+        let len = cut_point(&self.buf[self.start..self.end]);
+        let chunk = &self.buf[self.start..self.start + len];
+        self.start += len;
+        Ok(Some(chunk))
+    }
+}
+
+/// `--rsync-friendly`: shard frames are cut where the gear hash of the data
+/// allows it – on average every [`FRAME_AVG`] bytes, never before [`FRAME_MIN`]
+/// and at the latest after [`FRAME_MAX`] – so an edit only re-encodes the
+/// frames around it and the rest of the shard stays byte-identical.
+pub const FRAME_MIN: usize = 256 * 1024;
+pub const FRAME_AVG: usize = 1024 * 1024;
+pub const FRAME_MAX: usize = 4 * 1024 * 1024;
+const FRAME_MASK: u64 = !0u64 << (64 - FRAME_AVG.trailing_zeros());
+
+/// Streaming cut points of `--rsync-friendly` frames, fed with the data as the
+/// shard encoder receives it (in buffers of any size).
+#[derive(Debug, Default, Clone)]
+pub struct FrameCutter {
+    hash: u64,
+    /// Bytes since the last cut.
+    len: usize,
+}
+
+impl FrameCutter {
+    /// Length of the prefix of `data` that ends the current frame, or `None`
+    /// if the frame goes on past `data`. The bytes are taken as consumed.
+    pub fn find_cut(&mut self, data: &[u8]) -> Option<usize> {
+        for (i, &b) in data.iter().enumerate() {
+            // Хэш зависит только от последних 64 байт – после правки границы снова совпадают
+            self.hash = (self.hash << 1).wrapping_add(GEAR[b as usize]);
+            self.len += 1;
+            if self.len >= FRAME_MAX || (self.len >= FRAME_MIN && self.hash & FRAME_MASK == 0) {
+                self.len = 0;
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+
+    /// Starts counting a new frame (cut for another reason).
+    pub fn restart(&mut self) {
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chunker, FrameCutter, FRAME_MAX, FRAME_MIN, MAX_CHUNK, MIN_CHUNK};
+    use std::collections::HashSet;
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut chunker = Chunker::new(data);
+        let mut out = Vec::new();
+        while let Some(chunk) = chunker.next_chunk().unwrap() {
+            out.push(chunk.to_vec());
+        }
+        out
+    }
+
+    #[test]
+    fn test_chunks_survive_insertions() {
+        // Deterministic pseudo-random data (xorshift)
+        let mut x = 0x2545_F491_4F6C_DD1Du64;
+        let data: Vec<u8> = (0..4 * 1024 * 1024)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect();
+        let original = chunks(&data);
+        assert_eq!(original.concat(), data);
+        let (last, rest) = original.split_last().unwrap();
+        assert!(rest.iter().all(|c| c.len() >= MIN_CHUNK && c.len() <= MAX_CHUNK));
+        assert!(last.len() <= MAX_CHUNK);
+
+        // A few bytes inserted in the middle only disturb the chunks around them
+        let mut edited = data.clone();
+        edited.splice(2_000_000..2_000_000, *b"inserted");
+        let edited = chunks(&edited);
+        let known: HashSet<&Vec<u8>> = original.iter().collect();
+        let shared = edited.iter().filter(|c| known.contains(c)).count();
+        assert!(shared + 3 >= edited.len(), "{} of {} chunks shared", shared, edited.len());
+
+        assert!(chunks(&[]).is_empty());
+        assert_eq!(chunks(b"tiny"), [b"tiny".to_vec()]);
+    }
+
+    #[test]
+    fn test_frame_cuts_do_not_depend_on_buffer_sizes() {
+        let mut x = 0x2545_F491_4F6C_DD1Du64;
+        let data: Vec<u8> = (0..8 * 1024 * 1024)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect();
+        let cuts = |step: usize| {
+            let (mut cutter, mut cuts, mut pos) = (FrameCutter::default(), Vec::new(), 0);
+            while pos < data.len() {
+                let end = (pos + step).min(data.len());
+                match cutter.find_cut(&data[pos..end]) {
+                    Some(len) => {
+                        pos += len;
+                        cuts.push(pos);
+                    }
+                    None => pos = end,
+                }
+            }
+            cuts
+        };
+        let whole = cuts(data.len());
+        assert!(whole.len() > 2);
+        assert!(whole.windows(2).all(|w| (FRAME_MIN..=FRAME_MAX).contains(&(w[1] - w[0]))));
+        assert_eq!(cuts(64 * 1024 + 7), whole);
+    }
+}
@@ -0,0 +1,134 @@
+//! Machine-readable results of `--json` runs.
+//!
+//! The engine reports progress through [`crate::console`]; while a `--json`
+//! command runs, that output is captured into stderr, so the only thing left
+//! on stdout is the one JSON object printed at the end – the result, or an
+//! [`error_report`] if the command failed. Every object carries a `command`
+//! field.
+
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_json::{json, Value};
+
+use super::Commands;
+use crate::katana::VerifyReport;
+use crate::progress::ProgressState;
+use crate::warnings::Warning;
+
+/// Runs `f` with the engine's console output sent to stderr; returns its
+/// result, the warnings it reported and how long it took.
+pub fn run_quiet<R>(f: impl FnOnce() -> R) -> (R, Vec<Warning>, Duration) {
+    let started = Instant::now();
+    let _console = crate::console::capture(io::stderr());
+    let (result, warnings) = crate::warnings::capture(f);
+    (result, warnings, started.elapsed())
+}
+
+/// Name of `command` if it was asked for a `--json` result.
+pub fn json_command(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Create { json: true, .. } => Some("create"),
+        Commands::Extract { json: true, .. } => Some("extract"),
+        Commands::List { json: true, .. } => Some("list"),
+        Commands::Stats { json: true, .. } => Some("stats"),
+        Commands::Verify { json: true, .. } => Some("verify"),
+        _ => None,
+    }
+}
+
+/// Result of a `--json` command that failed: `ok` is false and `error` holds
+/// the same fields as `--error-format json`.
+pub fn error_report(command: &str, err: &(dyn Error + 'static)) -> Value {
+    json!({
+        "command": command,
+        "ok": false,
+        "error": super::redacted_error_report(err),
+    })
+}
+
+/// Whether [`print`] has written the command's JSON object already.
+static PRINTED: AtomicBool = AtomicBool::new(false);
+
+pub fn printed() -> bool {
+    PRINTED.load(Ordering::Relaxed)
+}
+
+/// Prints `value` on stdout (pretty, one object).
+pub fn print(value: &Value) -> Result<(), Box<dyn Error>> {
+    PRINTED.store(true, Ordering::Relaxed);
+    let mut out = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, value)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Result of `create --json`: entry and byte counts read back from the
+/// archive's index. `written` is false when no archive was produced
+/// (`--dry-run`, `--skip-if-unchanged`).
+pub fn create_report(
+    archive: &Path,
+    password: Option<&str>,
+    started: SystemTime,
+    elapsed: Duration,
+    warnings: &[Warning],
+) -> Result<Value, Box<dyn Error>> {
+    let written = std::fs::metadata(archive)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| modified >= started - Duration::from_secs(2));
+    let mut report = json!({
+        "command": "create",
+        "archive": archive,
+        "written": written,
+        "elapsed_secs": elapsed.as_secs_f64(),
+        "warnings": warnings,
+    });
+    if written {
+        let entries = crate::extract::list_entries(archive, password)?;
+        let files = entries.iter().filter(|e| !e.is_dir).count();
+        let input_bytes: u64 = entries.iter().map(|e| e.size).sum();
+        let archive_bytes = std::fs::metadata(archive)?.len();
+        report["files"] = json!(files);
+        report["input_bytes"] = json!(input_bytes);
+        report["archive_bytes"] = json!(archive_bytes);
+        report["ratio"] = json!(if input_bytes > 0 { archive_bytes as f64 / input_bytes as f64 } else { 0.0 });
+    }
+    Ok(report)
+}
+
+/// Result of `extract --json`, with the figures of the last progress report.
+pub fn extract_report(
+    archive: &Path,
+    output_dir: &Path,
+    last: Option<&ProgressState>,
+    elapsed: Duration,
+    warnings: &[Warning],
+) -> Value {
+    json!({
+        "command": "extract",
+        "archive": archive,
+        "output_dir": output_dir,
+        "files": last.map_or(0, |s| s.processed_files),
+        "bytes": last.map_or(0, |s| s.processed_bytes),
+        "elapsed_secs": elapsed.as_secs_f64(),
+        "warnings": warnings,
+    })
+}
+
+/// Result of `verify --json`; `ok` is false if any damage was found.
+pub fn verify_report(archive: &Path, report: &VerifyReport, elapsed: Duration) -> Result<Value, Box<dyn Error>> {
+    let mut value = json!({
+        "command": "verify",
+        "archive": archive,
+        "ok": report.corrupt.is_empty(),
+        "complete": report.resume_at.is_none(),
+        "elapsed_secs": elapsed.as_secs_f64(),
+    });
+    if let (Value::Object(fields), Value::Object(details)) = (&mut value, serde_json::to_value(report)?) {
+        fields.extend(details);
+    }
+    Ok(value)
+}
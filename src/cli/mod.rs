@@ -1,14 +1,75 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+
+pub mod json;
 use std::path::PathBuf;
+use crate::console::eprintln;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Format of the error printed on failure (`json` emits one object on stderr).
+    #[arg(long, value_enum, global = true, default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
+    /// Replace entry names in logs, warnings and error messages with short hash tags
+    /// (for encrypted archives whose names must not leak into terminal or CI logs).
+    #[arg(long, global = true)]
+    pub private_logs: bool,
+}
+
+/// Output format for top-level errors.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Human-readable `Error: ...` line.
+    Text,
+    /// Single-line JSON object with code, category, path, shard and message.
+    Json,
+}
+
+/// Determines the requested `--error-format` straight from raw arguments.
+///
+/// Errors can happen before (or without) a successful `clap` parse, so the
+/// top-level error printer cannot rely on [`Args`].
+pub fn error_format_from_args<I: IntoIterator<Item = String>>(args: I) -> ErrorFormat {
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        let value = match arg.strip_prefix("--error-format") {
+            Some("") => iter.next(),
+            Some(rest) => rest.strip_prefix('=').map(str::to_string),
+            None => None,
+        };
+        if let Some(v) = value {
+            return if v.eq_ignore_ascii_case("json") { ErrorFormat::Json } else { ErrorFormat::Text };
+        }
+    }
+    ErrorFormat::Text
+}
+
+/// Prints a top-level error to stderr in the requested format.
+pub fn print_error(err: &(dyn std::error::Error + 'static), format: ErrorFormat) {
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {}", crate::private_logs::message(&err.to_string(), "")),
+        ErrorFormat::Json => eprintln!("{}", serde_json::to_string(&redacted_error_report(err)).unwrap_or_default()),
+    }
+}
+
+/// [`crate::error::ErrorReport`] of `err` with names redacted under `--private-logs`.
+pub fn redacted_error_report(err: &(dyn std::error::Error + 'static)) -> crate::error::ErrorReport {
+    let mut report = crate::error::ErrorReport::from_error(err);
+    if let Some(path) = report.path.take() {
+        report.message = crate::private_logs::message(&report.message, &path).into_owned();
+        report.path = Some(crate::private_logs::path(&path).into_owned());
+    } else {
+        report.message = crate::private_logs::message(&report.message, "").into_owned();
+    }
+    report
 }
 
 #[derive(Subcommand, Clone, Debug)]
+#[allow(clippy::large_enum_variant)] // parsed once per run; `create` simply has many flags
 pub enum Commands {
     /// Create a new archive from specified files and directories.
     #[command(alias = "c")]
@@ -17,14 +78,30 @@ pub enum Commands {
         #[arg(required = true)]
         inputs: Vec<PathBuf>,
 
-        /// The path for the output archive file (e.g., my_archive.blz).
+        /// The path for the output archive file (e.g., my_archive.blz). Builds with the
+        /// `remote` feature also take `s3://bucket/key`, streamed to S3 as shards complete.
         #[arg(short, long)]
         output: PathBuf,
 
+        /// On-disk format to write. Options the format cannot honour are rejected up front.
+        #[arg(long, value_enum, default_value_t = ArchiveFormat::Katana)]
+        format: ArchiveFormat,
+
         /// Set a password to encrypt the archive. If not provided, the archive will be unencrypted.
         #[arg(long)]
         password: Option<String>,
 
+        /// Refuse to write an unencrypted archive: fail unless a password comes from
+        /// `--password` or `BLITZARCH_PASSWORD` (mostly for `encrypt = true` in profiles).
+        #[arg(long)]
+        encrypt: bool,
+
+        /// Take defaults for this create from `[profile.NAME]` of the config file
+        /// (`~/.config/blitzarch/config.toml`). Options given on the command line win, also
+        /// over profile options they conflict with; `--no-FLAG` turns off a flag the profile sets.
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+
         /// Zstandard compression level (0-22). Higher levels offer better compression at the cost of speed.
         #[arg(long, default_value_t = 3)]
         level: i32,
@@ -50,7 +127,8 @@ pub enum Commands {
         #[arg(long = "no-adaptive", action = clap::ArgAction::SetFalse, default_value_t = true)]
         adaptive: bool,
 
-        /// Use the LZMA2 compression algorithm instead of Zstandard.
+        /// Use the LZMA2 compression algorithm instead of Zstandard (requires `--format classic`).
+        /// Same as `--codec lzma2`.
         #[arg(long)]
         use_lzma2: bool,
 
@@ -58,6 +136,14 @@ pub enum Commands {
         #[arg(long, value_parser = clap::value_parser!(u32).range(0..=9))]
         lz_level: Option<u32>,
 
+        /// Bundle codec (requires `--format classic` for `lzma2` and `brotli`). [default: zstd]
+        #[arg(long, value_enum, conflicts_with = "use_lzma2")]
+        codec: Option<Codec>,
+
+        /// Brotli quality (0-11). Used only with `--codec brotli`. [default: 9]
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..=11))]
+        brotli_quality: Option<u32>,
+
         // --- Deprecated / Advanced --- //
         
         /// `[DEPRECATED]` Use sharded parallel compression mode. The Katana format is recommended instead.
@@ -87,16 +173,244 @@ pub enum Commands {
         /// Skip final integrity verification (UNSAFE; for benchmarks only).
         #[arg(long = "skip-check", default_value_t = false)]
         skip_check: bool,
+
+        /// `[EXPERT]` Advanced zstd parameter for every shard encoder, e.g. `chainLog=24`. Repeatable.
+        /// Keys: windowLog, hashLog, chainLog, searchLog, minMatch, targetLength, strategy,
+        /// enableLongDistanceMatching, ldmHashLog, enableDedicatedDictSearch.
+        #[arg(long = "zstd-param", value_name = "KEY=VALUE", value_parser = parse_zstd_param)]
+        zstd_param: Vec<zstd::stream::raw::CParameter>,
+
+        /// Store files under 4 KiB directly in the index instead of shards (unencrypted archives only).
+        #[arg(long)]
+        inline_small: bool,
+
+        /// Order of files inside each shard. `type` groups by extension, name cluster and size.
+        #[arg(long, value_enum, default_value_t = OrderMode::Walk)]
+        order: OrderMode,
+
+        /// Pin shard workers to NUMA nodes on multi-socket machines.
+        #[arg(long, value_enum, default_value_t = NumaMode::Off)]
+        numa: NumaMode,
+
+        /// Compression of the archive index: `zstd` (level 3), `zstd:LEVEL` (1-22) or `store`.
+        #[arg(long, value_name = "CODEC", value_parser = parse_index_compression)]
+        index_compression: Option<crate::katana::IndexCompression>,
+
+        /// Index layout: `v2` (versioned binary index, default) or `v1` (JSON index, for
+        /// BlitzArch releases that predate `v2`).
+        #[arg(long, value_enum, value_name = "VERSION")]
+        index_format: Option<IndexFormat>,
+
+        /// Store only new or changed files (size, mtime, permissions); unchanged files
+        /// reference the `--base` archive, which must stay available for extraction.
+        #[arg(long, requires = "base")]
+        incremental: bool,
+
+        /// Previous archive (full or incremental) for `--incremental`.
+        #[arg(long, value_name = "ARCHIVE", requires = "incremental")]
+        base: Option<PathBuf>,
+
+        /// Exit successfully without writing an archive if the inputs still match the
+        /// source set ARCHIVE was created from (same paths, sizes and mtimes).
+        #[arg(long, value_name = "ARCHIVE")]
+        skip_if_unchanged: Option<PathBuf>,
+
+        /// Leave out paths matching PATTERN (globset syntax: `*` stays within a directory,
+        /// `**` crosses them), e.g. `--exclude 'target/**' --exclude '*.tmp'`. Patterns are
+        /// relative to each input and match at any depth unless they start with `/`. Repeatable.
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+
+        /// Archive only paths matching PATTERN (a matching directory brings everything below
+        /// it); `--exclude` still wins. Repeatable.
+        #[arg(long, value_name = "PATTERN")]
+        include: Vec<String>,
+
+        /// Also skip what `.gitignore` and `.blitzignore` files inside the inputs exclude
+        /// (git semantics: deeper files win, `!pattern` re-includes), e.g. `node_modules/`
+        /// or `target/` of a source tree.
+        #[arg(long)]
+        respect_gitignore: bool,
+
+        /// Leave out regular files larger than SIZE (bytes, or with a K/M/G/T suffix,
+        /// e.g. `4G`), such as VM images in a document backup.
+        #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+        max_file_size: Option<u64>,
+
+        /// Leave out regular files smaller than SIZE (bytes, or with a K/M/G/T suffix).
+        #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+        min_file_size: Option<u64>,
+
+        /// Leave out empty regular files.
+        #[arg(long)]
+        skip_empty: bool,
+
+        /// Store BlitzArch archives among the inputs (`.blz` files or anything with a Katana
+        /// trailer) without recompressing them, one shard each, and mark them for
+        /// `extract --recursive-extract`.
+        #[arg(long)]
+        store_nested: bool,
+
+        /// Recover from a crashed run: break an output lock whose owner is no longer running
+        /// and overwrite an output that is not a complete archive (by default both are refused).
+        #[arg(long)]
+        force: bool,
+
+        /// Write one archive per input, `<input name>.blz` in the `--output` directory. Either
+        /// every archive is published or, if any of them fails, none (existing ones are kept).
+        #[arg(long, conflicts_with_all = ["resume", "max_duration", "pausable", "skip_if_unchanged", "dry_run", "export_ordering", "json"])]
+        per_input: bool,
+
+        /// Plan the archive without writing it: file count, total bytes, estimated compressed
+        /// size (from sampled compression) and shard layout.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Stop adding files after this long (e.g. `2h`, `30m`, `1h30m`); the archive is
+        /// finalized and the rest is recorded in `<archive>.checkpoint` for `--resume`.
+        #[arg(long, value_name = "DURATION", value_parser = parse_max_duration)]
+        max_duration: Option<std::time::Duration>,
+
+        /// Continue an interrupted `--max-duration` or `--pausable` run: append the files its
+        /// checkpoint lists.
+        #[arg(long)]
+        resume: bool,
+
+        /// Ctrl-C (or SIGTERM) pauses instead of aborting: the files being compressed are
+        /// finished, the archive is finalized and the rest is left for `--resume`.
+        /// A second Ctrl-C aborts immediately.
+        #[arg(long)]
+        pausable: bool,
+
+        /// Symbolic links: `skip` them, `follow` them (archive their targets) or `keep` them as links.
+        #[arg(long, value_enum, default_value_t = SymlinksMode::Skip)]
+        symlinks: SymlinksMode,
+
+        /// Store byte-identical files once (`--dedup`, `--dedup=files`), or split files into
+        /// content-defined chunks and store every distinct chunk once (`--dedup=chunks`).
+        #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, require_equals = true, default_missing_value = "files")]
+        dedup: Option<DedupMode>,
+
+        /// Record extended attributes and POSIX ACLs (user.*, security.* where readable).
+        #[arg(long)]
+        xattrs: bool,
+
+        /// Shard checksum: `crc32` (default, readable by every version) or `xxh3`
+        /// (XXH3-128, much faster to verify on large shards; older versions cannot read it).
+        #[arg(long, value_name = "ALGO", value_parser = parse_shard_checksum)]
+        checksum: Option<crate::katana::ShardChecksum>,
+
+        /// Shard composition: `solid` (files balanced by size across the workers), `per-file`
+        /// (the same shards with a zstd frame per file, for fast single-file extraction) or `size-capped:MIB` (shards of at most MIB MiB).
+        #[arg(long, value_name = "STRATEGY", value_parser = parse_shard_strategy)]
+        shard_strategy: Option<crate::katana_stream::ShardStrategy>,
+
+        /// Write shards as independent zstd frames of MIB MiB with a seek table, so single
+        /// files and byte ranges are read without decoding the shard from its start
+        /// (default: 8 MiB frames).
+        #[arg(long, value_name = "MIB", value_parser = clap::value_parser!(u64).range(1..))]
+        seekable_frames: Option<u64>,
+
+        /// Cut shards into zstd frames at content-defined boundaries (~1 MiB apart), so small
+        /// changes to the inputs only alter a few frames and rsync/rclone transfer little of the
+        /// updated archive. Pair with `--ordering-manifest` to keep files in the same shards.
+        /// Has no effect on the shards of encrypted archives.
+        #[arg(long)]
+        rsync_friendly: bool,
+
+        /// Encrypt the index as well, so file names and sizes cannot be listed without the
+        /// password (only with --password).
+        #[arg(long)]
+        hide_names: bool,
+
+        /// Compress with a pre-trained zstd dictionary (see `train-dictionary`). Only its hash
+        /// is stored in the archive: extract with the same `--dictionary FILE`.
+        #[arg(long, value_name = "FILE")]
+        dictionary: Option<PathBuf>,
+
+        /// Attach a KEY=VALUE pair to the archive (creator, host, backup job id, …); repeatable.
+        /// Shown by `list --json`.
+        #[arg(long = "meta", value_name = "KEY=VALUE")]
+        meta: Vec<String>,
+
+        /// Free-form archive comment, stored as the `comment` metadata key.
+        #[arg(long, value_name = "TEXT")]
+        comment: Option<String>,
+
+        /// Print the result (files, sizes, timing, warnings) as JSON on stdout; progress
+        /// output goes to stderr.
+        #[arg(long)]
+        json: bool,
+
+        /// Keep the file-to-shard assignment recorded by an earlier `--export-ordering`:
+        /// known files stay in their shards in the same order, new files get shards after
+        /// them, so unchanged shards compress to the same bytes (rsync-friendly deltas).
+        #[arg(long, value_name = "FILE")]
+        ordering_manifest: Option<PathBuf>,
+
+        /// Write the file-to-shard assignment of this archive to FILE (JSON), to be fed
+        /// back with `--ordering-manifest` on the next run.
+        #[arg(long, value_name = "FILE")]
+        export_ordering: Option<PathBuf>,
+
+        /// Adapt the zstd level of every shard to how fast the archive output drains, like `zstd --adapt`:
+        /// `--adapt` (levels 1-19) or `--adapt=min=3,max=15`. The level is the starting point.
+        #[arg(long, value_name = "RANGE", num_args = 0..=1, require_equals = true, default_missing_value = "", value_parser = parse_adaptive_level)]
+        adapt: Option<crate::katana_stream::AdaptiveLevel>,
+
+        /// Retune the AutoTune level and zstd threads while compressing, from the ratio and
+        /// throughput the shards achieve; data that stays incompressible is stored. Only without
+        /// `--level`. The archive then depends on timing and is not reproducible.
+        #[arg(long, conflicts_with_all = ["adapt", "rsync_friendly"])]
+        retune: bool,
+
+        /// Cap on concurrent input file reads (default: autotuned, e.g. 4 on network filesystems).
+        #[arg(long, value_name = "N")]
+        max_reads: Option<usize>,
+
+        /// Cap on shards compressed at the same time (default: autotuned).
+        #[arg(long, value_name = "N")]
+        max_compressions: Option<usize>,
+
+        /// Cap on concurrent writes of compressed shard data (default: autotuned).
+        #[arg(long, value_name = "N")]
+        max_writes: Option<usize>,
+
+        /// How input files of 8 MiB and more are read and shards written: `buffered` (page
+        /// cache), `readahead` (explicit readahead, pages dropped once read) or `direct`
+        /// (around the page cache: O_DIRECT on Linux, F_NOCACHE on macOS).
+        #[arg(long, value_enum, value_name = "MODE", default_value_t = IoMode::Buffered)]
+        io_mode: IoMode,
+
+        /// Readahead window of `--io-mode readahead`, in MiB.
+        #[arg(long, value_name = "MiB", default_value_t = DEFAULT_READAHEAD_MIB)]
+        readahead: u64,
+
+        /// Extra extensions of already-compressed files, stored as-is by adaptive mode
+        /// (e.g. `parquet,onnx`; classic format). A leading `-` removes a built-in one
+        /// (`-pdf`). Applied after `[extensions] dense` of the config file.
+        #[arg(long, value_name = "EXT,...", value_delimiter = ',', allow_hyphen_values = true)]
+        dense_ext: Vec<String>,
+
+        /// Extra extensions of text files, bundled and preprocessed like source code
+        /// (e.g. `proto,sql`). A leading `-` removes a built-in one.
+        /// Applied after `[extensions] text` of the config file.
+        #[arg(long, value_name = "EXT,...", value_delimiter = ',', allow_hyphen_values = true)]
+        text_ext: Vec<String>,
     },
 
     /// Extract files from an archive.
     #[command(alias = "x")]
     Extract {
-        /// The archive file to extract.
+        /// The archive file to extract (BlitzArch, or ZIP detected by its signature), or
+        /// `s3://bucket/key` or `https://…` of a Katana archive in builds with the `remote`
+        /// feature (only the shards holding the selected files are downloaded).
         #[arg(required = true)]
         archive: PathBuf,
 
-        /// Specific files or directories to extract. If empty, all files will be extracted.
+        /// Files, directories (with everything below them) or glob patterns to extract, e.g.
+        /// `'photos/2023/**'` or `'*.jpg'` (quote them so the shell does not expand them).
+        /// If empty, all files will be extracted.
         files: Vec<PathBuf>,
 
         /// The directory where files will be extracted. Defaults to the current directory.
@@ -110,98 +424,1479 @@ pub enum Commands {
         /// Strip NUMBER leading components from file names on extraction (like tar --strip-components).
         #[arg(long, value_name = "NUMBER")]
         strip_components: Option<u32>,
+
+        /// Extract only entries below PREFIX, recreated relative to it
+        /// (`--relative-to photos` restores `photos/2023/a.jpg` as `2023/a.jpg`).
+        /// Applied before --strip-components.
+        #[arg(long, value_name = "PREFIX")]
+        relative_to: Option<String>,
         
         /// Show real-time progress during archive extraction.
         #[arg(long)]
         progress: bool,
 
+        /// Do not restore archived permission bits; files get the default mode (0666 minus umask).
+        #[arg(long)]
+        no_preserve_permissions: bool,
+
+        /// What to do with entries differing only in case (Readme.md / README.md) on a case-insensitive filesystem.
+        #[arg(long, value_enum, default_value_t = CaseCollisionMode::Rename)]
+        case_collisions: CaseCollisionMode,
+
+        /// Extract into a hidden sibling of the output directory and move the result into place
+        /// only once everything is extracted; a failed run leaves the output directory as it was.
+        #[arg(long)]
+        atomic: bool,
+
+        /// Replace files that already exist in the output directory (the default).
+        #[arg(long, conflicts_with_all = ["skip_existing", "rename_existing", "keep_newer"])]
+        overwrite: bool,
+
+        /// Keep files that already exist in the output directory; their entries are not extracted.
+        #[arg(long, conflicts_with_all = ["rename_existing", "keep_newer"])]
+        skip_existing: bool,
+
+        /// Move files that already exist aside as `name copy.ext` (`name copy 2.ext`, …) before
+        /// extracting the entry under its own name.
+        #[arg(long, conflicts_with = "keep_newer")]
+        rename_existing: bool,
+
+        /// Replace existing files only with entries whose modification time is later.
+        #[arg(long)]
+        keep_newer: bool,
+
+        /// Restore files deduplicated with `create --dedup` as hard links instead of copies.
+        #[arg(long)]
+        hard_links: bool,
+
+        /// Restore extended attributes and ACLs recorded with `create --xattrs`.
+        #[arg(long)]
+        xattrs: bool,
+
+        /// With --xattrs, also restore `security.*` and `trusted.*` attributes
+        /// (SELinux labels, file capabilities), which are skipped otherwise.
+        #[arg(long, requires = "xattrs")]
+        privileged_xattrs: bool,
+
+        /// Only restore files with an execute bit set (scripts, binaries).
+        #[arg(long)]
+        only_executable: bool,
+
+        /// Only restore files owned by this numeric user id (recorded on Unix sources).
+        #[arg(long, value_name = "UID")]
+        uid: Option<u32>,
+
+        /// zstd dictionary the archive was created with (`create --dictionary`); repeatable.
+        #[arg(long, value_name = "FILE")]
+        dictionary: Vec<PathBuf>,
+
+        /// Also unpack BlitzArch archives found among the extracted files (`logs.blz` →
+        /// `logs/`), recursively, and remove them; nested archives use the same password.
+        #[arg(long, alias = "recursive")]
+        recursive_extract: bool,
+
+        /// Print the result (files, bytes, timing, warnings) as JSON on stdout; progress
+        /// output goes to stderr.
+        #[arg(long)]
+        json: bool,
     },
 
     /// List the contents of an archive without extracting it.
     #[command(alias = "l")]
     List {
-        /// The archive file to list contents of.
+        /// The archive file to list contents of (BlitzArch or ZIP), or `s3://bucket/key` or
+        /// `https://…` of a Katana archive in builds with the `remote` feature (only the
+        /// index is downloaded).
         #[arg(required = true)]
         archive: PathBuf,
+
+        /// Print the entries and the archive metadata as JSON.
+        #[arg(long)]
+        json: bool,
+
+        /// Password of an encrypted archive (verifies the index, needed with --hide-names).
+        #[arg(long)]
+        password: Option<String>,
     },
-}
 
-/// Defines the strategy for bundling text files to improve compression ratios.
-#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
-pub enum TextBundleMode {
-    /// Bundle small text files.
-    Small,
-    /// Automatically determine the best bundling strategy.
-    Auto,
-    /// Use a sliding window approach for bundling.
-    Window,
-}
+    /// Browse an archive in an interactive terminal UI and extract marked entries (builds with
+    /// the `tui` feature).
+    Tui {
+        /// The archive file to browse.
+        #[arg(required = true)]
+        archive: PathBuf,
 
-/// Defines the mode for multi-threaded workers.
-#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
-pub enum WorkerMode {
-    /// Automatically select the number of workers.
-    Auto,
-    /// Use 2 worker threads.
-    W2,
-    /// Use 4 worker threads.
-    W4,
-}
+        /// Directory marked entries are extracted into.
+        #[arg(short, long, value_name = "DIR", default_value = ".")]
+        output: PathBuf,
 
-/// Gets the password from the command-line option, the `BLITZARCH_PASSWORD` environment variable, or prompts the user if necessary.
-/// 
-/// This function centralizes password retrieval logic.
-/// Priority:
-/// 1. `--password` command-line argument.
-/// 2. `BLITZARCH_PASSWORD` environment variable.
-/// 3. Returns `Ok(None)` if neither is present, allowing the caller to prompt interactively.
-/// Parse memory budget string into MiB.
-/// Accepts:
-///   • Integer MiB (e.g. "512") → 512
-///   • Percentage (e.g. "50%") → calculates % of total RAM, rounds down to MiB
-/// Returns Ok(None) if input is None or "0".
-pub fn parse_memory_budget_mb(budget_opt: &Option<String>) -> Result<Option<u64>, String> {
-    let Some(raw) = budget_opt else { return Ok(None); };
-    let trimmed = raw.trim();
-    if trimmed.is_empty() || trimmed == "0" {
-        return Ok(None);
-    }
-    if let Some(percent_idx) = trimmed.strip_suffix('%') {
-        // Percentage mode
-        let pct: u64 = percent_idx.parse().map_err(|_| "invalid percentage")?;
-        if pct == 0 || pct > 100 {
-            return Err("percentage must be 1-100".into());
-        }
-        let mut sys = sysinfo::System::new();
-        sys.refresh_memory();
-        let total_bytes = sys.total_memory(); // bytes
-        let total_mib = total_bytes / (1024 * 1024);
-        let budget_mib = total_mib * pct / 100;
-        return Ok(Some(budget_mib));
-    }
-    // numeric MiB
-    let mb: u64 = trimmed.parse().map_err(|_| "invalid memory value")?;
-    if mb == 0 { return Ok(None); }
-    Ok(Some(mb))
-}
+        /// The password of an encrypted archive.
+        #[arg(long)]
+        password: Option<String>,
+    },
 
-pub fn get_password_from_opt_or_env(password_opt: Option<String>) -> Result<Option<String>, std::io::Error> {
-    if let Some(pass) = password_opt {
-        return Ok(Some(pass));
-    }
-    if let Ok(pass) = std::env::var("BLITZARCH_PASSWORD") {
-        return Ok(Some(pass));
-    }
-    Ok(None)
-}
+    /// Mount a Katana archive as a read-only filesystem (builds with the `fuse` feature);
+    /// files are decompressed on first read. Runs until unmounted.
+    Mount {
+        /// The archive file to mount.
+        #[arg(required = true)]
+        archive: PathBuf,
 
-/// Parses command-line arguments using `clap` and returns the command to execute.
-///
-/// This is the main entry point for the CLI logic.
-/// It handles parsing and returns a `Commands` enum variant, or an error if parsing fails.
-pub fn run() -> Result<Commands, Box<dyn std::error::Error>> {
-    let args = Args::parse();
-    Ok(args.command)
+        /// Existing empty directory to mount the archive on.
+        #[arg(required = true)]
+        mountpoint: PathBuf,
+
+        /// The password of an encrypted archive.
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Memory for decompressed file blocks (bytes, or with a K/M/G suffix).
+        #[arg(long, value_name = "SIZE", default_value = "256M", value_parser = parse_byte_size)]
+        cache_size: u64,
+    },
+
+    /// Serve create/extract/list/verify jobs to local clients such as the GUI over a Unix
+    /// socket (line-delimited JSON-RPC 2.0, see `blitzarch::daemon`). Runs until stopped.
+    Daemon {
+        /// Socket to listen on. Defaults to `$XDG_RUNTIME_DIR/blitzarch.sock`, or a per-user
+        /// socket in the temp directory.
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Jobs running at the same time; further jobs wait in a queue.
+        #[arg(long, default_value_t = crate::daemon::DEFAULT_MAX_JOBS)]
+        max_jobs: usize,
+
+        /// Memory shared by the running jobs:
+        ///   • Number in MiB (e.g., `4096`)
+        ///   • Percentage of total RAM (e.g., `50%`)
+        #[arg(long, value_name = "MiB|%", default_value = "50%")]
+        memory_budget: String,
+    },
+
+    /// Keep an archive of a directory up to date while it changes. New files are appended;
+    /// modifications and deletions roll a new incremental generation next to the archive
+    /// (`backup.g0001.blz`, …) that restores the current state. Runs until stopped.
+    Watch {
+        /// Directory to watch.
+        #[arg(required = true)]
+        dir: PathBuf,
+
+        /// The archive; created with a full copy of the directory if it does not exist yet.
+        #[arg(short, long, required = true)]
+        output: PathBuf,
+
+        /// Quiet period after the last change before it is archived (e.g. `2s`, `1m`).
+        #[arg(long, value_name = "DURATION", value_parser = parse_max_duration, default_value = "2s")]
+        debounce: std::time::Duration,
+
+        /// Roll a new generation for every batch of changes, never modifying a written archive.
+        #[arg(long)]
+        generations: bool,
+
+        /// Encrypt the archive with this password.
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Zstandard compression level (0-22).
+        #[arg(long, default_value_t = 3)]
+        level: i32,
+
+        /// Number of parallel threads to use. [0 = auto-detect based on CPU cores]
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+
+        /// Compact the archive after a batch once dead bytes exceed this share of it
+        /// (e.g. 25%). Defaults to `[compact] auto_above` of the config file.
+        #[arg(long, value_name = "PERCENT", value_parser = parse_percent, conflicts_with = "generations")]
+        auto_compact: Option<f64>,
+    },
+
+    /// Archive a directory to a temp file, extract it again and compare every file.
+    Selftest {
+        /// Directory to roundtrip.
+        #[arg(required = true)]
+        dir: PathBuf,
+
+        /// Number of parallel threads to use. [0 = auto-detect based on CPU cores]
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+
+        /// Also exercise encryption with this password.
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Measure create/extract throughput, optionally against a saved baseline.
+    Bench {
+        /// Files or directories to benchmark with (only read).
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Compression level.
+        #[arg(long, default_value_t = 3)]
+        level: i32,
+
+        /// Number of parallel threads to use. [0 = auto-detect based on CPU cores]
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+
+        /// Create/extract rounds; the median is reported.
+        #[arg(long, default_value_t = 3)]
+        runs: usize,
+
+        /// Write the report as JSON (usable as a later --compare baseline).
+        #[arg(long, value_name = "FILE")]
+        json: Option<PathBuf>,
+
+        /// Compare against a report saved with --json.
+        #[arg(long, value_name = "BASELINE")]
+        compare: Option<PathBuf>,
+
+        /// Fail if a throughput dropped by more than this (e.g. 5%).
+        #[arg(long, value_name = "PERCENT", default_value = "5%", value_parser = parse_percent, requires = "compare")]
+        fail_threshold: f64,
+    },
+
+    /// Add files and directories to an existing Katana archive without re-creating it.
+    Append {
+        /// The archive file to extend.
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// Files or directories to add. Paths already in the archive are rejected.
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// The password of an encrypted archive (new entries are encrypted with it).
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Zstandard compression level (0-22) for the new shards.
+        #[arg(long, default_value_t = 3)]
+        level: i32,
+
+        /// Number of parallel threads to use. [0 = auto-detect based on CPU cores]
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+
+        /// Compact the archive afterwards if dead bytes exceed this share of it
+        /// (e.g. 25%). Defaults to `[compact] auto_above` of the config file.
+        #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+        auto_compact: Option<f64>,
+    },
+
+    /// Check an archive's index for suspicious metadata (e.g. timestamps far in the future).
+    Audit {
+        /// The archive file to audit.
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// The password of an encrypted archive (enables index HMAC verification).
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Show per-shard compression statistics of a Katana archive, read from the index only.
+    Stats {
+        /// The archive file to inspect.
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// Print the shard details as JSON.
+        #[arg(long)]
+        json: bool,
+
+        /// The password of an encrypted archive (enables index HMAC verification).
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Print a manifest of the archive contents (modes, sizes, SHA-256) for OS-native tooling.
+    Manifest {
+        /// The archive file to describe.
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// Manifest format.
+        #[arg(long, value_enum, default_value_t = ManifestFormat::Mtree)]
+        format: ManifestFormat,
+
+        /// Skip the content digests; only the index is read (no password or decompression needed).
+        #[arg(long)]
+        no_hashes: bool,
+
+        /// Write the manifest to FILE instead of stdout.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// The password of an encrypted archive (needed for digests and hidden names).
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Train a zstd dictionary on sample files for `create --dictionary`, so a series of
+    /// archives of similar data (e.g. nightly backups) can share it.
+    TrainDictionary {
+        /// Directory of representative files to sample.
+        #[arg(required = true)]
+        dir: PathBuf,
+
+        /// Where to write the dictionary.
+        #[arg(short, long, value_name = "FILE", default_value = "dictionary.zdict")]
+        output: PathBuf,
+
+        /// Maximum dictionary size in bytes.
+        #[arg(long, value_name = "BYTES", default_value_t = crate::dictionary::DEFAULT_DICTIONARY_SIZE)]
+        max_size: usize,
+    },
+
+    /// Check archive integrity (index, BLAKE3 footer, shard CRC32s) without extracting.
+    Verify {
+        /// The archive file to verify.
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// Also decompress every shard in memory and check the entry sizes.
+        #[arg(long)]
+        deep: bool,
+
+        /// The password of an encrypted archive (enables index HMAC and deep checks).
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Stop after this long (e.g. `2h`); the next unchecked shard is recorded for `--resume`.
+        #[arg(long, value_name = "DURATION", value_parser = parse_max_duration)]
+        max_duration: Option<std::time::Duration>,
+
+        /// Continue an interrupted `--max-duration` verification.
+        #[arg(long)]
+        resume: bool,
+
+        /// zstd dictionary the archive was created with, needed for `--deep`; repeatable.
+        #[arg(long, value_name = "FILE")]
+        dictionary: Vec<PathBuf>,
+
+        /// Print the verification report as JSON on stdout.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Write a detached BLAKE3 attestation of an archive, or check one with `--verify`.
+    Attest {
+        /// The archive file to attest.
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// Attestation file to write or check. Defaults to `<archive>.sig`.
+        #[arg(long)]
+        out: Option<PathBuf>,
+
+        /// Check the archive against an existing attestation instead of writing one.
+        #[arg(long)]
+        verify: bool,
+
+        /// Secret key file; produces/checks a keyed hash that only key holders can forge.
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+
+    /// Rewrite an archive without dead shard bytes and rebuild a minimal index.
+    Compact {
+        /// The archive file to compact (replaced atomically).
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// The password of an encrypted archive (required to re-sign its index).
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Recover what is left of a truncated or corrupted archive into a new archive: the index is
+    /// searched for if the footer is damaged, intact shards are kept, damaged ones dropped.
+    Repair {
+        /// The damaged archive (left untouched).
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// Where to write the repaired archive. Defaults to `<archive>.repaired.blz`.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Also extract the recovered files into DIR.
+        #[arg(long, value_name = "DIR")]
+        extract: Option<PathBuf>,
+
+        /// The password of an encrypted archive (required to re-sign its index).
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Convert a classic (MFUS) archive to the Katana format; the result is verified against the
+    /// original before it is written.
+    Upgrade {
+        /// The classic archive to convert.
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// Replace the original archive (atomically, after verification).
+        #[arg(long, conflicts_with = "output", required_unless_present = "output")]
+        in_place: bool,
+
+        /// Write the Katana archive here and keep the original.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// The password of an encrypted archive; the upgraded archive is encrypted with it again.
+        /// If not provided, will try to read from BLITZARCH_PASSWORD.
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Number of worker threads for the conversion. [0 = auto]
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+
+        /// Overwrite an existing `--output` file (by default it is refused).
+        #[arg(long, requires = "output")]
+        force: bool,
+    },
+
+    /// Encrypt an unencrypted archive in place (shards are not recompressed).
+    Encrypt {
+        /// The archive file to encrypt (replaced atomically).
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// The new password. If not provided, will try to read from BLITZARCH_PASSWORD.
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Remove the encryption of an archive in place (shards are not recompressed).
+    Decrypt {
+        /// The archive file to decrypt (replaced atomically).
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// The current password. If not provided, will try to read from BLITZARCH_PASSWORD.
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Update metadata of archived entries in place (only the index is rewritten).
+    Touch {
+        /// The archive file to modify.
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// Glob patterns selecting entries (e.g. 'docs/**'). If empty, all entries are updated.
+        patterns: Vec<String>,
+
+        /// New Unix permission bits in octal notation (e.g. 644).
+        #[arg(long, value_name = "MODE", value_parser = parse_octal_mode, required_unless_present = "mtime")]
+        chmod: Option<u32>,
+
+        /// New modification time: Unix seconds or RFC 3339 (e.g. 2024-05-01T12:00:00Z).
+        #[arg(long, value_name = "TIME", value_parser = parse_mtime)]
+        mtime: Option<i64>,
+
+        /// The password of an encrypted archive (required to re-sign its index).
+        #[arg(long)]
+        password: Option<String>,
+
+        /// Do not recompute the BLAKE3 footer. Rehashing reads the whole archive, which
+        /// dominates on multi-terabyte archives; the archive is then left without the
+        /// optional footer (readers do not need it, full-file checks are skipped).
+        #[arg(long)]
+        no_footer: bool,
+    },
+
+    /// Manage the passwords of an encrypted archive (only the index is rewritten).
+    Key {
+        #[command(subcommand)]
+        action: KeyCommand,
+    },
+}
+
+/// `key` actions. Each password opens its own key slot wrapping the archive
+/// key, so adding or changing one never re-encrypts the data.
+#[derive(Subcommand, Clone, Debug)]
+pub enum KeyCommand {
+    /// Add a further password that opens the archive.
+    Add {
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// A current password. If not provided, will try to read from BLITZARCH_PASSWORD.
+        #[arg(long)]
+        password: Option<String>,
+
+        /// The password to add.
+        #[arg(long)]
+        new_password: String,
+    },
+
+    /// Remove a password (the last one cannot be removed).
+    Remove {
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// The password to remove. If not provided, will try to read from BLITZARCH_PASSWORD.
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Replace a password by a new one.
+    Rotate {
+        #[arg(required = true)]
+        archive: PathBuf,
+
+        /// The password to replace. If not provided, will try to read from BLITZARCH_PASSWORD.
+        #[arg(long)]
+        password: Option<String>,
+
+        /// The replacement password.
+        #[arg(long)]
+        new_password: String,
+    },
+}
+
+/// Output format of `blitzarch manifest`.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// BSD mtree specification (`bsdtar --format mtree` style, SHA-256 digests).
+    Mtree,
+}
+
+/// Defines the strategy for bundling text files to improve compression ratios.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextBundleMode {
+    /// Bundle small text files.
+    Small,
+    /// Automatically determine the best bundling strategy.
+    Auto,
+    /// Use a sliding window approach for bundling.
+    Window,
+}
+
+/// Defines the mode for multi-threaded workers.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WorkerMode {
+    /// Automatically select the number of workers.
+    Auto,
+    /// Use 2 worker threads.
+    W2,
+    /// Use 4 worker threads.
+    W4,
+}
+
+/// On-disk archive format written by `create`.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// Sharded, parallel Katana format (default).
+    Katana,
+    /// Legacy bundle format with a header and trailing index.
+    Classic,
+}
+
+/// Optional features supported by an [`ArchiveFormat`] writer.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatCapabilities {
+    pub encryption: bool,
+    pub dictionary: bool,
+    pub append: bool,
+    pub lzma2: bool,
+    pub brotli: bool,
+    pub zstd_params: bool,
+    pub progress: bool,
+    pub numa: bool,
+    pub inline_small: bool,
+    pub ordering: bool,
+    pub index_compression: bool,
+    pub index_format: bool,
+    pub incremental: bool,
+    pub time_budget: bool,
+    pub symlinks: bool,
+    pub dedup: bool,
+    pub xattrs: bool,
+    pub checksum: bool,
+    pub shard_strategy: bool,
+    pub seekable_frames: bool,
+    pub skip_if_unchanged: bool,
+    pub dry_run: bool,
+    pub io_limits: bool,
+    pub adapt: bool,
+    pub external_dictionary: bool,
+    pub size_filter: bool,
+    pub force: bool,
+    pub per_input: bool,
+    pub remote_output: bool,
+    pub dense_extensions: bool,
+    pub text_extensions: bool,
+    pub path_filter: bool,
+    pub metadata: bool,
+}
+
+impl ArchiveFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            ArchiveFormat::Katana => "katana",
+            ArchiveFormat::Classic => "classic",
+        }
+    }
+
+    pub fn capabilities(self) -> FormatCapabilities {
+        match self {
+            ArchiveFormat::Katana => FormatCapabilities {
+                encryption: true,
+                dictionary: false,
+                append: false,
+                lzma2: false,
+                brotli: false,
+                zstd_params: true,
+                progress: true,
+                numa: true,
+                inline_small: true,
+                ordering: true,
+                index_compression: true,
+                index_format: true,
+                incremental: true,
+                time_budget: true,
+                symlinks: true,
+                dedup: true,
+                xattrs: true,
+                checksum: true,
+                shard_strategy: true,
+                seekable_frames: true,
+                skip_if_unchanged: true,
+                dry_run: true,
+                io_limits: true,
+                adapt: true,
+                external_dictionary: true,
+                size_filter: true,
+                force: true,
+                per_input: true,
+                remote_output: true,
+                dense_extensions: false,
+                text_extensions: false,
+                path_filter: true,
+                metadata: true,
+            },
+            ArchiveFormat::Classic => FormatCapabilities {
+                encryption: true,
+                dictionary: true,
+                append: false,
+                lzma2: true,
+                brotli: true,
+                zstd_params: false,
+                progress: true,
+                numa: false,
+                inline_small: false,
+                ordering: false,
+                index_compression: false,
+                index_format: false,
+                incremental: false,
+                time_budget: false,
+                symlinks: false,
+                dedup: false,
+                xattrs: false,
+                checksum: false,
+                shard_strategy: false,
+                seekable_frames: false,
+                skip_if_unchanged: false,
+                dry_run: false,
+                io_limits: false,
+                adapt: false,
+                external_dictionary: false,
+                size_filter: false,
+                force: false,
+                per_input: false,
+                remote_output: false,
+                dense_extensions: true,
+                text_extensions: true,
+                path_filter: true,
+                metadata: true,
+            },
+        }
+    }
+}
+
+/// What the parallel Katana writer (`workers::create_archive_parallel`)
+/// supports; every other option needs the streaming writer.
+pub fn parallel_writer_capabilities() -> FormatCapabilities {
+    FormatCapabilities {
+        encryption: true,
+        progress: true,
+        numa: true,
+        skip_if_unchanged: true,
+        dry_run: true,
+        ..Default::default()
+    }
+}
+
+type Supported = fn(&FormatCapabilities) -> bool;
+
+/// `create --readahead` default, in MiB.
+const DEFAULT_READAHEAD_MIB: u64 = 16;
+
+/// Optional options of a `create` command: flag name, whether it is used and
+/// the writer capability it needs. Every new create option belongs here, so
+/// format checks and writer selection cannot miss it.
+fn requested_create_options(command: &Commands) -> Vec<(&'static str, bool, Supported)> {
+    let Commands::Create { password, encrypt, use_lzma2, codec, zstd_param, progress, numa, inline_small, order, index_compression, index_format, incremental, max_duration, resume, pausable, symlinks, dedup, xattrs, checksum, shard_strategy, seekable_frames, skip_if_unchanged, dry_run, max_reads, max_compressions, max_writes, io_mode, readahead, adapt, retune, ordering_manifest, export_ordering, rsync_friendly, hide_names, dictionary, max_file_size, min_file_size, skip_empty, store_nested, force, per_input, output, dense_ext, text_ext, include, exclude, respect_gitignore, meta, comment, .. } = command else {
+        return Vec::new();
+    };
+    vec![
+        ("--password", password.is_some(), |c| c.encryption),
+        ("--encrypt", *encrypt, |c| c.encryption),
+        ("--use-lzma2", *use_lzma2, |c| c.lzma2),
+        ("--codec lzma2", *codec == Some(Codec::Lzma2), |c| c.lzma2),
+        ("--codec brotli", *codec == Some(Codec::Brotli), |c| c.brotli),
+        ("--zstd-param", !zstd_param.is_empty(), |c| c.zstd_params),
+        ("--progress", *progress, |c| c.progress),
+        ("--numa auto", *numa == NumaMode::Auto, |c| c.numa),
+        ("--inline-small", *inline_small, |c| c.inline_small),
+        ("--order", *order != OrderMode::Walk, |c| c.ordering),
+        ("--index-compression", index_compression.is_some(), |c| c.index_compression),
+        ("--index-format", index_format.is_some(), |c| c.index_format),
+        ("--incremental", *incremental, |c| c.incremental),
+        ("--max-duration", max_duration.is_some(), |c| c.time_budget),
+        ("--resume", *resume, |c| c.time_budget),
+        ("--pausable", *pausable, |c| c.time_budget),
+        ("--symlinks", *symlinks != SymlinksMode::Skip, |c| c.symlinks),
+        ("--dedup", dedup.is_some(), |c| c.dedup),
+        ("--xattrs", *xattrs, |c| c.xattrs),
+        ("--checksum", checksum.is_some(), |c| c.checksum),
+        ("--shard-strategy", shard_strategy.is_some(), |c| c.shard_strategy),
+        ("--ordering-manifest", ordering_manifest.is_some(), |c| c.shard_strategy),
+        ("--export-ordering", export_ordering.is_some(), |c| c.shard_strategy),
+        ("--seekable-frames", seekable_frames.is_some(), |c| c.seekable_frames),
+        ("--rsync-friendly", *rsync_friendly, |c| c.seekable_frames),
+        ("--hide-names", *hide_names, |c| c.index_compression),
+        ("--dictionary", dictionary.is_some(), |c| c.external_dictionary),
+        ("--max-file-size", max_file_size.is_some(), |c| c.size_filter),
+        ("--min-file-size", min_file_size.is_some(), |c| c.size_filter),
+        ("--skip-empty", *skip_empty, |c| c.size_filter),
+        ("--store-nested", *store_nested, |c| c.shard_strategy),
+        ("--force", *force, |c| c.force),
+        ("--per-input", *per_input, |c| c.per_input),
+        ("--output s3://", is_remote_url(output), |c| c.remote_output),
+        ("--skip-if-unchanged", skip_if_unchanged.is_some(), |c| c.skip_if_unchanged),
+        ("--dry-run", *dry_run, |c| c.dry_run),
+        ("--max-reads", max_reads.is_some(), |c| c.io_limits),
+        ("--max-compressions", max_compressions.is_some(), |c| c.io_limits),
+        ("--max-writes", max_writes.is_some(), |c| c.io_limits),
+        ("--io-mode", *io_mode != IoMode::Buffered, |c| c.io_limits),
+        ("--readahead", *readahead != DEFAULT_READAHEAD_MIB, |c| c.io_limits),
+        ("--adapt", adapt.is_some(), |c| c.adapt),
+        ("--retune", *retune, |c| c.adapt),
+        ("--dense-ext", !dense_ext.is_empty(), |c| c.dense_extensions),
+        ("--text-ext", !text_ext.is_empty(), |c| c.text_extensions),
+        ("--include/--exclude", !include.is_empty() || !exclude.is_empty() || *respect_gitignore, |c| c.path_filter),
+        ("--meta/--comment", !meta.is_empty() || comment.is_some(), |c| c.metadata),
+    ]
+}
+
+/// Checks the options of a `create` command against the capabilities of the
+/// selected `--format` and returns that format.
+///
+/// Fails with a message naming the offending flag (and the formats that do
+/// support it) instead of silently ignoring it.
+pub fn resolve_create_format(command: &Commands) -> Result<ArchiveFormat, String> {
+    let Commands::Create { format, password, encrypt, .. } = command else {
+        return Err("not a create command".into());
+    };
+    if *encrypt && get_password_from_opt_or_env(password.clone()).ok().flatten().is_none() {
+        return Err("--encrypt needs a password: pass --password or set BLITZARCH_PASSWORD".into());
+    }
+    let caps = format.capabilities();
+    for (flag, used, supported) in requested_create_options(command) {
+        if used && !supported(&caps) {
+            let alternatives: Vec<&str> = ArchiveFormat::value_variants()
+                .iter()
+                .filter(|f| supported(&f.capabilities()))
+                .map(|f| f.name())
+                .collect();
+            return Err(format!(
+                "--format {} does not support {} (supported by: {})",
+                format.name(),
+                flag,
+                alternatives.join(", ")
+            ));
+        }
+    }
+    Ok(*format)
+}
+
+/// Options of a Katana `create` the parallel writer cannot honour (see
+/// [`parallel_writer_capabilities`]); empty if it can write the archive.
+pub fn streaming_writer_flags(command: &Commands) -> Vec<&'static str> {
+    let caps = parallel_writer_capabilities();
+    requested_create_options(command)
+        .into_iter()
+        .filter(|(_, used, supported)| *used && !supported(&caps))
+        .map(|(flag, ..)| flag)
+        .collect()
+}
+
+/// File ordering inside shards (see [`crate::ordering`]).
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OrderMode {
+    /// Directory walk order.
+    Walk,
+    /// Group by extension, near-duplicate names and size.
+    Type,
+}
+
+impl OrderMode {
+    /// Strategy to plug into the writer; `None` keeps the walk order.
+    pub fn strategy(self) -> Option<std::sync::Arc<dyn crate::ordering::FileOrder>> {
+        match self {
+            OrderMode::Walk => None,
+            OrderMode::Type => Some(std::sync::Arc::new(crate::ordering::TypeAwareOrder)),
+        }
+    }
+}
+
+/// IO concurrency caps of a `create` command (unset ones are autotuned).
+pub fn io_limits(command: &Commands) -> crate::autotune::IoLimits {
+    match command {
+        Commands::Create { max_reads, max_compressions, max_writes, .. } => crate::autotune::IoLimits {
+            reads: *max_reads,
+            compressions: *max_compressions,
+            writes: *max_writes,
+        },
+        _ => Default::default(),
+    }
+}
+
+/// `--io-mode` and `--readahead` of a `create` command.
+pub fn io_config(command: &Commands) -> crate::fsx::IoConfig {
+    match command {
+        Commands::Create { io_mode, readahead, .. } => crate::fsx::IoConfig {
+            mode: (*io_mode).into(),
+            readahead: readahead.saturating_mul(1024 * 1024),
+        },
+        _ => Default::default(),
+    }
+}
+
+/// `--include/--exclude/--respect-gitignore` filter of a `create` command.
+pub fn path_filter(command: &Commands) -> Result<crate::fsx::PathFilter, Box<dyn std::error::Error>> {
+    match command {
+        Commands::Create { include, exclude, respect_gitignore, .. } => {
+            Ok(crate::fsx::PathFilter::new(include, exclude)?.respect_ignore_files(*respect_gitignore))
+        }
+        _ => Ok(Default::default()),
+    }
+}
+
+/// `--min-file-size/--max-file-size/--skip-empty` of a `create` command.
+pub fn size_filter(command: &Commands) -> crate::fsx::SizeFilter {
+    match command {
+        Commands::Create { max_file_size, min_file_size, skip_empty, .. } => crate::fsx::SizeFilter {
+            min: *min_file_size,
+            max: *max_file_size,
+            skip_empty: *skip_empty,
+        },
+        _ => Default::default(),
+    }
+}
+
+/// `--meta KEY=VALUE` pairs and `--comment` of a `create` command.
+pub fn archive_metadata(command: &Commands) -> Result<std::collections::BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    let mut metadata = std::collections::BTreeMap::new();
+    if let Commands::Create { meta, comment, .. } = command {
+        for pair in meta {
+            match pair.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => {
+                    metadata.insert(key.trim().to_string(), value.to_string());
+                }
+                _ => return Err(format!("Invalid --meta '{}': expected KEY=VALUE", pair).into()),
+            }
+        }
+        if let Some(text) = comment {
+            metadata.insert("comment".to_string(), text.clone());
+        }
+    }
+    Ok(metadata)
+}
+
+/// `--dictionary` of a `create` command, loaded.
+pub fn create_dictionary(command: &Commands) -> Result<Option<std::sync::Arc<crate::dictionary::ZstdDictionary>>, Box<dyn std::error::Error>> {
+    match command {
+        Commands::Create { dictionary: Some(path), .. } => {
+            Ok(Some(std::sync::Arc::new(crate::dictionary::ZstdDictionary::load(path)?)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Whether `path` names an S3 object (`s3://bucket/key`) or a web URL rather
+/// than a local file.
+pub fn is_remote_url(path: &std::path::Path) -> bool {
+    path.to_str().is_some_and(|s| ["s3://", "http://", "https://"].iter().any(|scheme| s.starts_with(scheme)))
+}
+
+#[cfg(not(feature = "remote"))]
+fn no_remote_support(path: &std::path::Path) -> Box<dyn std::error::Error> {
+    format!("{} is a remote URL, but this build has no remote archive support (rebuild with `--features remote`)", path.display()).into()
+}
+
+/// Upload a `create --output s3://…` streams the archive into; `None` for a
+/// local output. Options that need the archive on local disk are refused.
+pub fn remote_create_sink(command: &Commands) -> Result<Option<std::sync::Arc<dyn crate::katana_stream::ArchiveSink>>, Box<dyn std::error::Error>> {
+    let Commands::Create { output, resume, max_duration, pausable, per_input, json, .. } = command else {
+        return Ok(None);
+    };
+    if !is_remote_url(output) {
+        return Ok(None);
+    }
+    if !output.to_string_lossy().starts_with("s3://") {
+        return Err(format!("cannot write to {}: remote outputs must be s3:// URLs", output.display()).into());
+    }
+    let local_only = [
+        ("--resume", *resume),
+        ("--max-duration", max_duration.is_some()),
+        ("--pausable", *pausable),
+        ("--per-input", *per_input),
+        ("--json", *json),
+    ];
+    if let Some((flag, _)) = local_only.iter().find(|(_, used)| *used) {
+        return Err(format!("{flag} needs a local output archive, not {}", output.display()).into());
+    }
+    #[cfg(feature = "remote")]
+    {
+        let upload = crate::remote::s3::MultipartUpload::start(&output.to_string_lossy())?;
+        Ok(Some(std::sync::Arc::new(upload)))
+    }
+    #[cfg(not(feature = "remote"))]
+    Err(no_remote_support(output))
+}
+
+/// `list s3://…` / `list https://…`: fetches the footer and index of the
+/// archive into a sparse local copy; `None` for a local archive.
+pub fn fetch_remote_index(archive: &std::path::Path, password: Option<&str>) -> Result<Option<tempfile::TempPath>, Box<dyn std::error::Error>> {
+    if !is_remote_url(archive) {
+        return Ok(None);
+    }
+    #[cfg(feature = "remote")]
+    {
+        Ok(Some(crate::remote::RemoteArchive::open_with_password(&archive.to_string_lossy(), None, password)?.into_temp_path()))
+    }
+    #[cfg(not(feature = "remote"))]
+    {
+        let _ = password;
+        Err(no_remote_support(archive))
+    }
+}
+
+/// `extract s3://…`: fetches what extracting `selected` reads into a sparse
+/// local copy in `scratch_dir` (see `remote::fetch_for_extract`); `None` for a
+/// local archive.
+pub fn fetch_remote_archive(
+    archive: &std::path::Path,
+    selected: &[PathBuf],
+    password: Option<&str>,
+    scratch_dir: &std::path::Path,
+) -> Result<Option<tempfile::TempPath>, Box<dyn std::error::Error>> {
+    if !is_remote_url(archive) {
+        return Ok(None);
+    }
+    #[cfg(feature = "remote")]
+    {
+        crate::remote::fetch_for_extract(&archive.to_string_lossy(), selected, password, scratch_dir).map(Some)
+    }
+    #[cfg(not(feature = "remote"))]
+    {
+        let _ = (selected, password, scratch_dir);
+        Err(no_remote_support(archive))
+    }
+}
+
+/// `--ordering-manifest` of a `create` command, loaded.
+pub fn ordering_manifest(command: &Commands) -> Result<Option<std::sync::Arc<crate::ordering::OrderingManifest>>, Box<dyn std::error::Error>> {
+    match command {
+        Commands::Create { ordering_manifest: Some(path), .. } => {
+            Ok(Some(std::sync::Arc::new(crate::ordering::OrderingManifest::load(path)?)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Handling of symbolic links found while walking the inputs (see [`crate::vfs::SymlinkMode`]).
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SymlinksMode {
+    /// Leave links out of the archive.
+    Skip,
+    /// Archive the files and directories links point to.
+    Follow,
+    /// Store links as links and recreate them on extract.
+    Keep,
+}
+
+impl From<SymlinksMode> for crate::vfs::SymlinkMode {
+    fn from(mode: SymlinksMode) -> Self {
+        match mode {
+            SymlinksMode::Skip => crate::vfs::SymlinkMode::Skip,
+            SymlinksMode::Follow => crate::vfs::SymlinkMode::Follow,
+            SymlinksMode::Keep => crate::vfs::SymlinkMode::Keep,
+        }
+    }
+}
+
+/// Bundle codec of `create --codec`.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Zstd,
+    Lzma2,
+    Brotli,
+}
+
+/// Bundle algorithm of a classic-format create (`--codec`, `--use-lzma2`).
+pub fn bundle_algo(command: &Commands) -> crate::compress::CompressionAlgo {
+    use crate::compress::CompressionAlgo;
+    let Commands::Create { use_lzma2, lz_level, codec, brotli_quality, .. } = command else {
+        return CompressionAlgo::Zstd;
+    };
+    match codec.unwrap_or(if *use_lzma2 { Codec::Lzma2 } else { Codec::Zstd }) {
+        Codec::Zstd => CompressionAlgo::Zstd,
+        Codec::Lzma2 => CompressionAlgo::Lzma2 { preset: lz_level.unwrap_or(6) },
+        Codec::Brotli => CompressionAlgo::Brotli { quality: brotli_quality.unwrap_or(9) },
+    }
+}
+
+/// Dense / text extension lists of a create: the config file's
+/// `[extensions]`, then `--dense-ext` / `--text-ext`.
+pub fn extension_rules(command: &Commands) -> Result<crate::compress::ExtensionRules, Box<dyn std::error::Error>> {
+    let Commands::Create { dense_ext, text_ext, .. } = command else {
+        return Ok(Default::default());
+    };
+    let config = crate::config::UserConfig::load_default()?;
+    Ok(crate::compress::ExtensionRules::default()
+        .dense(&config.extensions.dense)
+        .dense(dense_ext)
+        .text(&config.extensions.text)
+        .text(text_ext))
+}
+
+/// Dead-space threshold for automatic compaction: `--auto-compact`, else the
+/// config file's `[compact] auto_above`.
+pub fn auto_compact_threshold(flag: Option<f64>) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+    match flag {
+        Some(percent) => Ok(Some(percent)),
+        None => Ok(crate::config::UserConfig::load_default()?.compact.auto_above),
+    }
+}
+
+/// How large inputs are read and shards written (see [`crate::fsx::IoMode`]).
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IoMode {
+    /// Through the page cache.
+    Buffered,
+    /// Through the page cache with explicit readahead; pages are dropped once read.
+    Readahead,
+    /// Around the page cache (O_DIRECT / F_NOCACHE).
+    Direct,
+}
+
+impl From<IoMode> for crate::fsx::IoMode {
+    fn from(mode: IoMode) -> Self {
+        match mode {
+            IoMode::Buffered => crate::fsx::IoMode::Buffered,
+            IoMode::Readahead => crate::fsx::IoMode::Readahead,
+            IoMode::Direct => crate::fsx::IoMode::Direct,
+        }
+    }
+}
+
+/// Index layout of a Katana create (see [`crate::katana::IndexFormat`]).
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IndexFormat {
+    /// JSON index readable by every release.
+    V1,
+    /// Versioned binary index.
+    V2,
+}
+
+impl From<IndexFormat> for crate::katana::IndexFormat {
+    fn from(format: IndexFormat) -> Self {
+        match format {
+            IndexFormat::V1 => crate::katana::IndexFormat::V1,
+            IndexFormat::V2 => crate::katana::IndexFormat::V2,
+        }
+    }
+}
+
+/// What `create --dedup` deduplicates.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DedupMode {
+    /// Whole byte-identical files.
+    Files,
+    /// Content-defined chunks of files (see [`crate::cdc`]).
+    Chunks,
+}
+
+/// Extraction policy for files already present at the target
+/// (`--skip-existing`, `--rename-existing`, `--keep-newer`; otherwise overwrite).
+pub fn conflict_policy(skip_existing: bool, rename_existing: bool, keep_newer: bool) -> crate::fsx::ConflictPolicy {
+    match (skip_existing, rename_existing, keep_newer) {
+        (true, _, _) => crate::fsx::ConflictPolicy::Skip,
+        (_, true, _) => crate::fsx::ConflictPolicy::RenameExisting,
+        (_, _, true) => crate::fsx::ConflictPolicy::KeepNewer,
+        _ => crate::fsx::ConflictPolicy::Overwrite,
+    }
+}
+
+/// Handling of case-only name collisions on extract (see [`crate::katana::CaseCollisionPolicy`]).
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CaseCollisionMode {
+    /// Extract later entries as `name (2).ext`.
+    Rename,
+    /// Keep the first entry, skip the others.
+    Skip,
+    /// Let later entries overwrite the first.
+    Overwrite,
+}
+
+impl From<CaseCollisionMode> for crate::katana::CaseCollisionPolicy {
+    fn from(mode: CaseCollisionMode) -> Self {
+        match mode {
+            CaseCollisionMode::Rename => crate::katana::CaseCollisionPolicy::Rename,
+            CaseCollisionMode::Skip => crate::katana::CaseCollisionPolicy::Skip,
+            CaseCollisionMode::Overwrite => crate::katana::CaseCollisionPolicy::Overwrite,
+        }
+    }
+}
+
+/// Worker placement policy for multi-socket (NUMA) machines.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NumaMode {
+    /// Pin each shard worker and its codec threads to one NUMA node.
+    Auto,
+    /// Let the OS scheduler place threads freely.
+    Off,
+}
+
+impl From<NumaMode> for crate::numa::NumaPolicy {
+    fn from(mode: NumaMode) -> Self {
+        match mode {
+            NumaMode::Auto => crate::numa::NumaPolicy::Auto,
+            NumaMode::Off => crate::numa::NumaPolicy::Off,
+        }
+    }
+}
+
+/// Gets the password from the command-line option, the `BLITZARCH_PASSWORD` environment variable, or prompts the user if necessary.
+/// 
+/// This function centralizes password retrieval logic.
+/// Priority:
+/// 1. `--password` command-line argument.
+/// 2. `BLITZARCH_PASSWORD` environment variable.
+/// 3. Returns `Ok(None)` if neither is present, allowing the caller to prompt interactively.
+/// Parse memory budget string into MiB.
+/// Accepts:
+///   • Integer MiB (e.g. "512") → 512
+///   • Percentage (e.g. "50%") → calculates % of total RAM, rounds down to MiB
+/// Returns Ok(None) if input is None or "0".
+pub fn parse_memory_budget_mb(budget_opt: &Option<String>) -> Result<Option<u64>, String> {
+    let Some(raw) = budget_opt else { return Ok(None); };
+    let trimmed = raw.trim();
+    if trimmed.is_empty() || trimmed == "0" {
+        return Ok(None);
+    }
+    if let Some(percent_idx) = trimmed.strip_suffix('%') {
+        // Percentage mode
+        let pct: u64 = percent_idx.parse().map_err(|_| "invalid percentage")?;
+        if pct == 0 || pct > 100 {
+            return Err("percentage must be 1-100".into());
+        }
+        let mut sys = sysinfo::System::new();
+        sys.refresh_memory();
+        let total_bytes = sys.total_memory(); // bytes
+        let total_mib = total_bytes / (1024 * 1024);
+        let budget_mib = total_mib * pct / 100;
+        return Ok(Some(budget_mib));
+    }
+    // numeric MiB
+    let mb: u64 = trimmed.parse().map_err(|_| "invalid memory value")?;
+    if mb == 0 { return Ok(None); }
+    Ok(Some(mb))
+}
+
+/// Parses a `--zstd-param KEY=VALUE` pair into a zstd compression parameter.
+/// Key names follow the zstd CLI (`--zstd=`) spelling; short aliases are accepted.
+pub fn parse_zstd_param(raw: &str) -> Result<zstd::stream::raw::CParameter, String> {
+    use zstd::stream::raw::CParameter;
+    use zstd::zstd_safe::Strategy;
+
+    let (key, value) = raw.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got '{raw}'"))?;
+    let key = key.trim();
+    let value = value.trim();
+    let num = || value.parse::<u32>().map_err(|_| format!("invalid value '{value}' for {key}"));
+    let flag = || match value {
+        "1" | "true" | "on" => Ok(true),
+        "0" | "false" | "off" => Ok(false),
+        _ => Err(format!("invalid boolean '{value}' for {key}")),
+    };
+    let param = match key {
+        "windowLog" | "wlog" => CParameter::WindowLog(num()?),
+        "hashLog" | "hlog" => CParameter::HashLog(num()?),
+        "chainLog" | "clog" => CParameter::ChainLog(num()?),
+        "searchLog" | "slog" => CParameter::SearchLog(num()?),
+        "minMatch" | "mml" => CParameter::MinMatch(num()?),
+        "targetLength" | "tlen" => CParameter::TargetLength(num()?),
+        "strategy" | "strat" => CParameter::Strategy(match value {
+            "1" | "fast" => Strategy::ZSTD_fast,
+            "2" | "dfast" => Strategy::ZSTD_dfast,
+            "3" | "greedy" => Strategy::ZSTD_greedy,
+            "4" | "lazy" => Strategy::ZSTD_lazy,
+            "5" | "lazy2" => Strategy::ZSTD_lazy2,
+            "6" | "btlazy2" => Strategy::ZSTD_btlazy2,
+            "7" | "btopt" => Strategy::ZSTD_btopt,
+            "8" | "btultra" => Strategy::ZSTD_btultra,
+            "9" | "btultra2" => Strategy::ZSTD_btultra2,
+            _ => return Err(format!("unknown strategy '{value}'")),
+        }),
+        "enableLongDistanceMatching" | "long" => CParameter::EnableLongDistanceMatching(flag()?),
+        "ldmHashLog" | "lhlog" => CParameter::LdmHashLog(num()?),
+        "enableDedicatedDictSearch" => CParameter::EnableDedicatedDictSearch(flag()?),
+        _ => return Err(format!("unknown zstd parameter '{key}'")),
+    };
+    Ok(param)
+}
+
+/// Parses an `--index-compression` value (`store`, `zstd`, `zstd:LEVEL`).
+pub fn parse_index_compression(raw: &str) -> Result<crate::katana::IndexCompression, String> {
+    raw.parse()
+}
+
+/// Parses a `--checksum` value (`crc32`, `xxh3`).
+pub fn parse_shard_checksum(raw: &str) -> Result<crate::katana::ShardChecksum, String> {
+    raw.parse()
+}
+
+/// Parses a `--shard-strategy` value (`solid`, `per-file`, `size-capped:MIB`).
+pub fn parse_shard_strategy(raw: &str) -> Result<crate::katana_stream::ShardStrategy, String> {
+    raw.parse()
+}
+
+/// Parses the `--adapt` range (`min=N,max=N`).
+pub fn parse_adaptive_level(raw: &str) -> Result<crate::katana_stream::AdaptiveLevel, String> {
+    raw.parse()
+}
+
+/// Parses a `--max-duration` value (`90`, `45s`, `30m`, `2h`, `1h30m`).
+pub fn parse_max_duration(raw: &str) -> Result<std::time::Duration, String> {
+    crate::timebox::parse_duration(raw)
+}
+
+/// Parses a byte size such as `512`, `64K`, `1.5G` or `2GiB` (binary units).
+pub fn parse_byte_size(raw: &str) -> Result<u64, String> {
+    let upper = raw.trim().to_ascii_uppercase();
+    let number = upper.strip_suffix('B').unwrap_or(&upper);
+    let number = number.strip_suffix('I').filter(|n| n.ends_with(['K', 'M', 'G', 'T'])).unwrap_or(number);
+    let (digits, shift) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 10),
+        Some('M') => (&number[..number.len() - 1], 20),
+        Some('G') => (&number[..number.len() - 1], 30),
+        Some('T') => (&number[..number.len() - 1], 40),
+        _ => (number, 0),
+    };
+    let value: f64 = digits.trim().parse().map_err(|_| format!("invalid size '{raw}'"))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("size '{raw}' out of range"));
+    }
+    Ok((value * (1u64 << shift) as f64) as u64)
+}
+
+/// Parses an octal permission string such as `644` or `0755`.
+pub fn parse_octal_mode(raw: &str) -> Result<u32, String> {
+    let digits = raw.trim().trim_start_matches("0o");
+    let mode = u32::from_str_radix(digits, 8).map_err(|_| format!("invalid octal mode '{raw}'"))?;
+    if mode > 0o7777 {
+        return Err(format!("mode '{raw}' out of range"));
+    }
+    Ok(mode)
+}
+
+/// Parses a point in time given as Unix seconds or RFC 3339.
+pub fn parse_mtime(raw: &str) -> Result<i64, String> {
+    let raw = raw.trim();
+    if let Ok(secs) = raw.parse::<i64>() {
+        return Ok(secs);
+    }
+    chrono::DateTime::parse_from_rfc3339(raw)
+        .map(|t| t.timestamp())
+        .map_err(|_| format!("invalid time '{raw}' (Unix seconds or RFC 3339)"))
+}
+
+/// Parses a percentage like `5%` or `2.5`.
+pub fn parse_percent(raw: &str) -> Result<f64, String> {
+    let value: f64 = raw.trim().trim_end_matches('%').trim().parse().map_err(|_| format!("invalid percentage '{raw}'"))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("percentage '{raw}' out of range"));
+    }
+    Ok(value)
+}
+
+pub fn get_password_from_opt_or_env(password_opt: Option<String>) -> Result<Option<String>, std::io::Error> {
+    if let Some(pass) = password_opt {
+        return Ok(Some(pass));
+    }
+    if let Ok(pass) = std::env::var("BLITZARCH_PASSWORD") {
+        return Ok(Some(pass));
+    }
+    Ok(None)
+}
+
+/// Parses command-line arguments using `clap` and returns the command to execute.
+///
+/// This is the main entry point for the CLI logic.
+/// It handles parsing and returns a `Commands` enum variant, or an error if parsing fails.
+pub fn run() -> Result<Commands, Box<dyn std::error::Error>> {
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let args = match profile_argv(&argv)? {
+        Some(argv) => {
+            // Профиль идёт перед аргументами командной строки: последнее значение побеждает
+            let mut cmd = Args::command().mut_subcommand("create", |c| c.args_override_self(true));
+            let matches = cmd.try_get_matches_from_mut(argv).unwrap_or_else(|e| e.exit());
+            Args::from_arg_matches(&matches).unwrap_or_else(|e| e.format(&mut cmd).exit())
+        }
+        None => Args::parse(),
+    };
+    crate::private_logs::set_enabled(args.private_logs);
+    Ok(args.command)
+}
+
+/// Expands `create --profile NAME` into the profile's options placed right after
+/// the subcommand, ahead of the command line. Profile keys the command line
+/// sets itself, or that conflict with an option it sets, are left out, and
+/// `--no-FLAG` drops a flag the profile turns on. `None` when there is nothing
+/// to expand.
+fn profile_argv(argv: &[std::ffi::OsString]) -> Result<Option<Vec<std::ffi::OsString>>, Box<dyn std::error::Error>> {
+    let mut cmd = Args::command();
+    // Глобальные флаги (--private-logs, --error-format) тоже видны в create
+    cmd.build();
+    let create = cmd.find_subcommand("create").expect("create subcommand");
+    let names: Vec<&str> = std::iter::once(create.get_name()).chain(create.get_all_aliases()).collect();
+    let Some(at) = argv.iter().skip(1).position(|a| a.to_str().is_some_and(|a| names.contains(&a))).map(|i| i + 1) else {
+        return Ok(None);
+    };
+    let is_flag = |a: &clap::Arg| matches!(a.get_action(), clap::ArgAction::SetTrue);
+    let mut name = None;
+    let mut negated: Vec<String> = Vec::new();
+    let mut command_line = argv[..=at].to_vec();
+    let mut rest = argv[at + 1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.to_str() {
+            Some("--") => {
+                command_line.push(arg.clone());
+                command_line.extend(rest.by_ref().cloned());
+                break;
+            }
+            Some("--profile") => {
+                command_line.push(arg.clone());
+                if let Some(value) = rest.next() {
+                    name = value.to_str().map(str::to_string);
+                    command_line.push(value.clone());
+                }
+                continue;
+            }
+            Some(a) => {
+                if let Some(v) = a.strip_prefix("--profile=") {
+                    name = Some(v.to_string());
+                }
+                let flag = a.strip_prefix("--no-").and_then(|long| create.get_arguments().find(|f| is_flag(f) && f.get_long() == Some(long)));
+                if let Some(flag) = flag {
+                    negated.push(flag.get_id().to_string());
+                    continue;
+                }
+            }
+            None => {}
+        }
+        command_line.push(arg.clone());
+    }
+    let Some(name) = name else {
+        // --no-FLAG без профиля ничего не отменяет
+        return Ok((!negated.is_empty()).then_some(command_line));
+    };
+
+    let config = crate::config::UserConfig::load_default()?;
+    let profile = config.profiles.get(&name).ok_or_else(|| {
+        let path = crate::config::default_config_path().map_or_else(|| "none".to_string(), |p| p.display().to_string());
+        format!("no [profile.{name}] in the config file ({path})")
+    })?;
+    // Что задано в командной строке; ошибки разбора сообщит основной разбор
+    let given: Vec<&clap::Arg> = match Args::command().try_get_matches_from(&command_line) {
+        Ok(matches) => {
+            let sub = matches.subcommand_matches(create.get_name()).cloned().unwrap_or_default();
+            create
+                .get_arguments()
+                .filter(|a| sub.try_contains_id(a.get_id().as_str()).unwrap_or(false))
+                .filter(|a| sub.value_source(a.get_id().as_str()) == Some(clap::parser::ValueSource::CommandLine))
+                .collect()
+        }
+        Err(_) => Vec::new(),
+    };
+    let skip = |arg: &clap::Arg| {
+        negated.iter().any(|id| id == arg.get_id().as_str())
+            || given.iter().any(|g| {
+                g.get_id() == arg.get_id()
+                    || create.get_arg_conflicts_with(arg).iter().any(|c| c.get_id() == g.get_id())
+                    || create.get_arg_conflicts_with(g).iter().any(|c| c.get_id() == arg.get_id())
+            })
+    };
+    let mut expanded = argv[..=at].to_vec();
+    expanded.extend(profile_args_except(create, &name, profile, &skip)?.into_iter().map(Into::into));
+    expanded.extend_from_slice(&command_line[at + 1..]);
+    Ok(Some(expanded))
+}
+
+/// Turns a `[profile.NAME]` table into `create` options. Keys are the long option
+/// names, with `_` or `-`; lists repeat the option; booleans set or skip a flag.
+pub fn profile_args(create: &clap::Command, name: &str, profile: &toml::Table) -> Result<Vec<String>, String> {
+    profile_args_except(create, name, profile, &|_| false)
+}
+
+/// [`profile_args`] leaving out the options `skip` selects (set on the command line).
+fn profile_args_except(create: &clap::Command, name: &str, profile: &toml::Table, skip: &dyn Fn(&clap::Arg) -> bool) -> Result<Vec<String>, String> {
+    use clap::ArgAction;
+    let mut out = Vec::new();
+    for (key, value) in profile {
+        let id = key.replace('-', "_");
+        let arg = create
+            .get_arguments()
+            .filter(|a| !matches!(a.get_action(), ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version))
+            .find(|a| a.get_id().as_str() == id && a.get_long().is_some() && id != "profile")
+            .ok_or_else(|| format!("[profile.{name}]: unknown create option `{key}`"))?;
+        let long = arg.get_long().unwrap_or_default();
+        let values = match value {
+            toml::Value::Array(items) => items.iter().collect(),
+            single => vec![single],
+        };
+        let skipped = skip(arg);
+        for value in values {
+            match (arg.get_action(), value) {
+                // `--no-adaptive` style flags store `false` when given
+                (ArgAction::SetTrue | ArgAction::SetFalse, toml::Value::Boolean(on)) => {
+                    if *on == matches!(arg.get_action(), ArgAction::SetTrue) && !skipped {
+                        out.push(format!("--{long}"));
+                    }
+                }
+                (ArgAction::SetTrue | ArgAction::SetFalse, _) => {
+                    return Err(format!("[profile.{name}]: `{key}` must be true or false"));
+                }
+                (_, toml::Value::Array(_) | toml::Value::Table(_)) => {
+                    return Err(format!("[profile.{name}]: `{key}` must be a string, number, boolean or a list of them"));
+                }
+                _ if skipped => {}
+                (_, toml::Value::String(s)) => out.push(format!("--{long}={s}")),
+                (_, scalar) => out.push(format!("--{long}={scalar}")),
+            }
+        }
+    }
+    Ok(out)
 }
 
 // -----------------------------------------------------------------------------
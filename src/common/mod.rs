@@ -11,8 +11,13 @@ pub struct FileMetadata {
     pub size: u64,
     pub permissions: u32,
     pub modified_time: u64, // Unix timestamp
+    /// Creation (birth) time, seconds since the Unix epoch, where the
+    /// filesystem records one.
+    #[serde(default)]
+    pub created_time: Option<i64>,
     pub is_dir: bool,
     #[serde(skip)]
     pub dense_hint: Option<bool>,
-    // TODO: Add UID/GID, xattr, ACLs, etc.
+    // TODO: Add UID/GID. Extended attributes and ACLs are only stored by the
+    // Katana format (`create --xattrs`, see `fsx::read_xattrs`).
 }
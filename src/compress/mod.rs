@@ -11,6 +11,7 @@
 
 use crate::archive::ArchiveWriter;
 use crate::common::FileMetadata;
+use crate::progress::{ProgressState, ProgressTracker};
 use crate::ArchiverError;
 
 use jwalk;
@@ -22,6 +23,7 @@ use std::os::unix::fs::PermissionsExt; // mode() helper
 use std::path::{Path, PathBuf};
 use std::time::UNIX_EPOCH;
 use tempfile::NamedTempFile;
+use crate::console::println;
 
 /// Defines the available compression algorithms.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,6 +34,84 @@ pub enum CompressionAlgo {
     Store,
     /// Use the LZMA2 algorithm with a given preset (0-9).
     Lzma2 { preset: u32 },
+    /// Use Brotli with a given quality (0-11). Strong on web text (HTML, CSS, JS, JSON).
+    Brotli { quality: u32 },
+}
+
+impl CompressionAlgo {
+    /// Tag stored in the bundle's `algo` field of the archive index.
+    pub fn tag(self) -> &'static str {
+        match self {
+            CompressionAlgo::Zstd => "zstd",
+            CompressionAlgo::Lzma2 { .. } => "lzma2",
+            CompressionAlgo::Brotli { .. } => "brotli",
+            CompressionAlgo::Store => "store",
+        }
+    }
+}
+
+/// Brotli window (log2): 4 MiB.
+const BROTLI_LGWIN: u32 = 22;
+
+/// Reader over a bundle of Brotli-compressed files, positioned at the start
+/// of the bundle. Unlike zstd and xz, Brotli streams cannot be decoded back to
+/// back, so each file's stream is decoded on its own. `streams` are the
+/// `(offset_in_bundle, stored_size)` of the wanted files from the index, in
+/// bundle order; the streams between them are skipped.
+pub struct BrotliBundleReader<R: Read> {
+    state: BrotliBundleState<R>,
+    streams: std::vec::IntoIter<(u64, u64)>,
+    pos: u64,
+}
+
+enum BrotliBundleState<R: Read> {
+    Between(R),
+    Decoding(brotli::Decompressor<io::Take<R>>),
+    Done,
+}
+
+impl<R: Read> BrotliBundleReader<R> {
+    pub fn new(reader: R, streams: Vec<(u64, u64)>) -> Self {
+        BrotliBundleReader { state: BrotliBundleState::Between(reader), streams: streams.into_iter(), pos: 0 }
+    }
+
+    /// Reader over the streams of `files` (index entries of one bundle).
+    pub fn for_entries(reader: R, files: &[crate::archive::FileIndexEntry]) -> Self {
+        Self::new(reader, files.iter().map(|f| (f.offset_in_bundle, f.stored_size)).collect())
+    }
+}
+
+impl<R: Read> Read for BrotliBundleReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let BrotliBundleState::Decoding(decoder) = &mut self.state {
+                let n = decoder.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    return Ok(n);
+                }
+            }
+            self.state = match std::mem::replace(&mut self.state, BrotliBundleState::Done) {
+                BrotliBundleState::Decoding(decoder) => {
+                    // Поток файла кончился – остаток его доли пропускаем
+                    let mut rest = decoder.into_inner();
+                    io::copy(&mut rest, &mut io::sink())?;
+                    BrotliBundleState::Between(rest.into_inner())
+                }
+                BrotliBundleState::Between(mut reader) => match self.streams.next() {
+                    Some((offset, size)) => {
+                        let gap = offset.saturating_sub(self.pos);
+                        if io::copy(&mut (&mut reader).take(gap), &mut io::sink())? != gap {
+                            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Brotli bundle ended early"));
+                        }
+                        self.pos = offset.max(self.pos) + size;
+                        BrotliBundleState::Decoding(brotli::Decompressor::new(reader.take(size), 64 * 1024))
+                    }
+                    None => return Ok(0),
+                },
+                BrotliBundleState::Done => return Ok(0),
+            };
+        }
+    }
 }
 
 /// Holds all configuration options for a compression operation.
@@ -49,6 +129,64 @@ pub struct CompressOptions {
     pub adaptive_threshold: f64,
     /// The primary compression algorithm to use.
     pub algo: CompressionAlgo,
+    /// Memory budget in MiB for the codec threads; `None` leaves `threads` as given.
+    pub mem_budget_mb: Option<u64>,
+    /// Changes to the built-in dense / text extension lists.
+    pub extension_rules: ExtensionRules,
+}
+
+/// Rough working set of one codec thread: zstd keeps a few windows per job,
+/// LZMA2 about fifteen times its dictionary, Brotli a few windows (many at
+/// qualities 10-11).
+fn codec_thread_memory(algo: CompressionAlgo, level: i32) -> usize {
+    const MIB: usize = 1024 * 1024;
+    match algo {
+        CompressionAlgo::Store => MIB,
+        CompressionAlgo::Zstd => {
+            let window = match level {
+                ..=2 => MIB,
+                3..=5 => 2 * MIB,
+                6..=12 => 4 * MIB,
+                13..=19 => 8 * MIB,
+                _ => 128 * MIB,
+            };
+            6 * window
+        }
+        // Окно 4 MiB; качества 10-11 строят деревья на всё окно
+        CompressionAlgo::Brotli { quality } => {
+            if quality >= 10 { 20 * 4 * MIB } else { 3 * 4 * MIB }
+        }
+        CompressionAlgo::Lzma2 { preset } => {
+            const DICT_KIB: [usize; 10] = [256, 1024, 2048, 4096, 4096, 8192, 8192, 16384, 32768, 65536];
+            15 * DICT_KIB[(preset & 0x1f).min(9) as usize] * 1024
+        }
+    }
+}
+
+/// Shrinks `workers` parallel bundles of `codec_threads` threads each until
+/// their estimated working set fits `budget_mb`: first fewer workers, then
+/// fewer codec threads, never below one of each. `codec_threads == 0` (codec
+/// default) counts as every core for LZMA2 and as one thread for zstd and
+/// Brotli.
+pub fn fit_memory_budget(workers: usize, codec_threads: u32, algo: CompressionAlgo, level: i32, budget_mb: Option<u64>) -> (usize, u32) {
+    let Some(budget_mb) = budget_mb else { return (workers, codec_threads) };
+    let budget = (budget_mb as usize).saturating_mul(1024 * 1024);
+    let per_thread = codec_thread_memory(algo, level);
+    let threads = match (codec_threads, algo) {
+        (0, CompressionAlgo::Lzma2 { .. }) => num_cpus::get().max(1),
+        (0, _) => 1,
+        (n, _) => n as usize,
+    };
+    let workers = workers.min(budget / (per_thread * threads)).max(1);
+    if workers > 1 || per_thread * threads <= budget {
+        return (workers, codec_threads);
+    }
+    let fitting = (budget / per_thread).max(1);
+    if fitting >= threads {
+        (1, codec_threads)
+    } else {
+        (1, fitting as u32)
+    }
 }
 
 // A simple bin-packing strategy: group files until a certain size is reached.
@@ -59,11 +197,58 @@ const BUNDLE_SIZE_SMALL: u64 = 16 * 1024 * 1024; // 16 MiB
 const BUNDLE_SIZE_AUTO:  u64 = 64 * 1024 * 1024; // 64 MiB
 const BUNDLE_SIZE_WINDOW: u64 = 128 * 1024 * 1024; // 128 MiB
 
-/// Returns true if the file extension is typically already compressed / dense.
-pub(crate) fn is_dense_ext(ext: &str) -> bool {
-    matches!(ext.to_ascii_lowercase().as_str(),
-        "png" | "jpg" | "jpeg" | "gif" | "mp4" | "mkv" | "mp3" | "ogg" | "flac" |
-        "zip" | "rar" | "7z" | "gz" | "bz2" | "xz" | "pdf" | "docx" | "pptx" | "xlsx")
+/// User changes to the built-in dense / text extension lists (config file and
+/// `--dense-ext` / `--text-ext`). `true` adds an extension, `false` removes it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtensionRules {
+    dense: std::collections::HashMap<String, bool>,
+    text: std::collections::HashMap<String, bool>,
+}
+
+impl ExtensionRules {
+    /// Applies `ext` / `-ext` entries to the dense list; later entries win.
+    pub fn dense<S: AsRef<str>>(mut self, entries: impl IntoIterator<Item = S>) -> Self {
+        Self::apply(&mut self.dense, entries);
+        self
+    }
+
+    /// Applies `ext` / `-ext` entries to the text list; later entries win.
+    pub fn text<S: AsRef<str>>(mut self, entries: impl IntoIterator<Item = S>) -> Self {
+        Self::apply(&mut self.text, entries);
+        self
+    }
+
+    fn apply<S: AsRef<str>>(map: &mut std::collections::HashMap<String, bool>, entries: impl IntoIterator<Item = S>) {
+        for entry in entries {
+            let entry = entry.as_ref().trim();
+            let (ext, add) = match entry.strip_prefix('-') {
+                Some(ext) => (ext, false),
+                None => (entry, true),
+            };
+            let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+            if !ext.is_empty() {
+                map.insert(ext, add);
+            }
+        }
+    }
+
+    /// User decision for a lowercase extension on the dense list, `None` if the built-in list applies.
+    fn dense_rule(&self, ext: &str) -> Option<bool> {
+        self.dense.get(ext).copied()
+    }
+
+    /// User decision for a lowercase extension on the text list, `None` if the built-in list applies.
+    fn text_rule(&self, ext: &str) -> Option<bool> {
+        self.text.get(ext).copied()
+    }
+
+    /// Returns true if the file extension is typically already compressed / dense.
+    pub(crate) fn is_dense_ext(&self, ext: &str) -> bool {
+        let ext = ext.to_ascii_lowercase();
+        self.dense_rule(&ext).unwrap_or_else(|| matches!(ext.as_str(),
+            "png" | "jpg" | "jpeg" | "gif" | "mp4" | "mkv" | "mp3" | "ogg" | "flac" |
+            "zip" | "rar" | "7z" | "gz" | "bz2" | "xz" | "pdf" | "docx" | "pptx" | "xlsx" | "blz"))
+    }
 }
 
 /// Quick magic-bytes detection for already-compressed formats.
@@ -74,7 +259,11 @@ fn is_dense_magic(path: &std::path::Path) -> bool {
     let mut buf = [0u8; 8];
     let Ok(mut f) = File::open(path) else { return false };
     let Ok(n) = f.read(&mut buf) else { return false };
-    let slice = &buf[..n];
+    is_dense_header(&buf[..n])
+}
+
+/// Magic-bytes part of [`is_dense_magic`] for callers that already hold the file head.
+pub(crate) fn is_dense_header(slice: &[u8]) -> bool {
     match slice {
         b if b.starts_with(b"\x89PNG") => true,                     // PNG
         b if b.starts_with(b"\xFF\xD8") => true,                    // JPEG
@@ -91,18 +280,21 @@ fn is_dense_magic(path: &std::path::Path) -> bool {
 /// 1. Cheap extension check for common source/text formats.
 /// 2. If extension unknown, sample first bytes and count printable ASCII ratio.
 ///    If ≥85 % printable, treat as text.
-fn should_preprocess(path: &std::path::Path, sample: &[u8]) -> bool {
+fn should_preprocess(path: &std::path::Path, sample: &[u8], rules: &ExtensionRules) -> bool {
     // Fast path: extension whitelist
     if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-        match ext.to_ascii_lowercase().as_str() {
-            "rs" | "c" | "h" | "cpp" | "hpp" | "json" | "txt" | "md" | "toml" | "yaml" | "yml" | "html" | "htm" | "css" | "js" | "ts" | "py" | "csv" => {
-                return true;
-            }
-            // Obvious binary extensions – immediately skip
-            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "mp4" | "mkv" | "pdf" | "zip" | "gz" | "xz" | "tar" | "rar" | "7z" | "iso" => {
-                return false;
-            }
-            _ => {}
+        let ext = ext.to_ascii_lowercase();
+        let text = rules.text_rule(&ext);
+        if text.unwrap_or_else(|| matches!(ext.as_str(),
+            "rs" | "c" | "h" | "cpp" | "hpp" | "json" | "txt" | "md" | "toml" | "yaml" | "yml" | "html" | "htm" | "css" | "js" | "ts" | "py" | "csv"))
+        {
+            return true;
+        }
+        // Obvious binary extensions – immediately skip
+        let binary = rules.dense_rule(&ext).unwrap_or_else(|| matches!(ext.as_str(),
+            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "mp4" | "mkv" | "pdf" | "zip" | "gz" | "xz" | "tar" | "rar" | "7z" | "iso"));
+        if text.is_none() && binary {
+            return false;
         }
     }
 
@@ -133,7 +325,7 @@ fn should_preprocess(path: &std::path::Path, sample: &[u8]) -> bool {
 pub fn create_store_temp_bundle(
     files: &[FileMetadata],
 ) -> Result<(NamedTempFile, Vec<u64>), ArchiverError> {
-    let mut temp_file = NamedTempFile::new().map_err(|e| ArchiverError::Io { source: e, path: PathBuf::new() })?;
+    let mut temp_file = crate::temp_manager::temp_file("bundle").map_err(|e| ArchiverError::Io { source: e, path: PathBuf::new() })?;
     let mut stored_sizes = Vec::with_capacity(files.len());
 
     for file_meta in files {
@@ -162,7 +354,23 @@ pub fn run(
     options: CompressOptions,
     password: Option<String>,
 ) -> Result<(), ArchiverError> {
-    let mut metadata_list = collect_file_metadata(inputs)?;
+    run_with_progress::<fn(ProgressState)>(inputs, output, options, password, None)
+}
+
+/// [`run`] reporting progress after every bundle: files and bytes consumed,
+/// bundles written as shards.
+pub fn run_with_progress<F>(
+    inputs: &[PathBuf],
+    output: &PathBuf,
+    options: CompressOptions,
+    password: Option<String>,
+    progress_callback: Option<F>,
+) -> Result<(), ArchiverError>
+where
+    F: Fn(ProgressState) + Send + Sync + 'static,
+{
+    let _lock = crate::fsx::OutputLock::acquire(output).map_err(|e| ArchiverError::Io { source: e, path: output.clone() })?;
+    let mut metadata_list = collect_file_metadata_filtered(inputs, &crate::fsx::PathFilter::default(), &options.extension_rules)?;
 
 
     // --- Adaptive selection: if majority of files are "dense" (already compressed media/archives), switch to Store ---
@@ -172,7 +380,7 @@ pub fn run(
         for m in &metadata_list {
             if m.is_dir { continue; }
             total += 1;
-            if is_dense_ext(m.path.extension().and_then(|s| s.to_str()).unwrap_or("")) {
+            if options.extension_rules.is_dense_ext(m.path.extension().and_then(|s| s.to_str()).unwrap_or("")) {
                 dense += 1;
             }
         }
@@ -183,13 +391,21 @@ pub fn run(
         selected_algo = CompressionAlgo::Store;
         println!("[adaptive] Detected dense dataset ({} % dense) → using plain Store mode", (dense_ratio*100.0) as u32);
     }
+    let (_, codec_threads) = fit_memory_budget(1, options.threads, selected_algo, options.level, options.mem_budget_mb);
 
     let (directories, files): (Vec<_>, Vec<_>) = metadata_list.into_iter().partition(|m| m.is_dir);
 
     // Train dictionary on file samples
     let dictionary = train_dictionary(&files)?;
 
-    let bundles = group_files_into_bundles(&files, options.text_bundle);
+    let bundles = group_files_into_bundles(&files, options.text_bundle, &options.extension_rules);
+
+    let mut progress_tracker = ProgressTracker::new(1, std::time::Duration::from_millis(50));
+    if let Some(callback) = progress_callback {
+        progress_tracker.enable_with_callback(callback);
+        progress_tracker.set_totals(files.len() as u64, files.iter().map(|m| m.size).sum(), bundles.len());
+    }
+    let metrics = progress_tracker.get_thread_metrics(0).expect("one metrics slot");
 
     let output_file = File::create(output).map_err(|e| ArchiverError::Io { source: e, path: output.clone() })?;
     let mut archive_writer = ArchiveWriter::new(output_file, password, selected_algo)?;
@@ -211,7 +427,7 @@ pub fn run(
         let (dense_files, normal_files): (Vec<_>, Vec<_>) = bundle
             .iter()
             .cloned()
-            .partition(|m| is_dense_ext(m.path.extension().and_then(|s| s.to_str()).unwrap_or("")));
+            .partition(|m| options.extension_rules.is_dense_ext(m.path.extension().and_then(|s| s.to_str()).unwrap_or("")));
 
         let mut sub_bundles: Vec<(Vec<FileMetadata>, CompressionAlgo)> = Vec::new();
         if !normal_files.is_empty() {
@@ -230,7 +446,7 @@ pub fn run(
             let (tmp_file, sizes, used, _comp_size) = compress_bundle_streaming(
               &sb_files,
              options.level,
-             options.threads,
+             codec_threads,
              dictionary.as_deref(),
              /*preprocess already handled*/ false,
              /*adaptive already handled*/ false,
@@ -245,12 +461,7 @@ pub fn run(
             temp_file.read_to_end(&mut buffer)
                 .map_err(|e| ArchiverError::Io { source: e, path: PathBuf::new() })?;
             // tag bundle with its own algorithm
-            let algo_str = match used_algo {
-                CompressionAlgo::Zstd => "zstd",
-                CompressionAlgo::Lzma2 { .. } => "lzma2",
-                CompressionAlgo::Store => "store",
-            };
-            archive_writer.set_current_algo(algo_str);
+            archive_writer.set_current_algo(used_algo.tag());
             archive_writer.write_bundle(&buffer)?;
         }
 
@@ -271,13 +482,29 @@ pub fn run(
         }
         bundle_id_counter += 1;
     } // end sub_bundles loop
+        for file_meta in &bundle {
+            metrics.record_file_processed(file_meta.size);
+        }
+        progress_tracker.record_shard_completed();
     } // end for bundle
 
     archive_writer.finalize()?;
+    progress_tracker.force_completion();
     Ok(())
 }
 
 pub fn collect_file_metadata(paths: &[PathBuf]) -> Result<Vec<FileMetadata>, ArchiverError> {
+    collect_file_metadata_filtered(paths, &crate::fsx::PathFilter::default(), &ExtensionRules::default())
+}
+
+/// [`collect_file_metadata`] leaving out what `filter` (`--include/--exclude`)
+/// rejects; directories are only dropped by excludes. `dense_hint` follows
+/// the job's extension `rules`.
+pub fn collect_file_metadata_filtered(
+    paths: &[PathBuf],
+    filter: &crate::fsx::PathFilter,
+    rules: &ExtensionRules,
+) -> Result<Vec<FileMetadata>, ArchiverError> {
     let mut metadata_list = Vec::new();
 
     for path_arg in paths {
@@ -289,7 +516,16 @@ pub fn collect_file_metadata(paths: &[PathBuf]) -> Result<Vec<FileMetadata>, Arc
         let absolute_base_path = fs::canonicalize(&base_path)
             .map_err(|e| ArchiverError::Io { source: e, path: base_path.clone() })?;
 
-        for entry in jwalk::WalkDir::new(path_arg).sort(false) {
+        // Excluded and ignored directories are not read at all
+        let (prune_filter, root) = (filter.clone(), path_arg.clone());
+        let walker = jwalk::WalkDir::new(path_arg).sort(false).process_read_dir(move |_, _, _, children| {
+            for child in children.iter_mut().flatten() {
+                if child.file_type().is_dir() && prune_filter.prunes(&root, &child.path()) {
+                    child.read_children_path = None;
+                }
+            }
+        });
+        for entry in walker {
             let entry = entry.map_err(|e| ArchiverError::Io { source: e.into(), path: path_arg.clone() })?;
             let path = entry.path();
 
@@ -299,6 +535,7 @@ pub fn collect_file_metadata(paths: &[PathBuf]) -> Result<Vec<FileMetadata>, Arc
                 Err(e) => return Err(ArchiverError::Io { source: e, path: path.clone() }),
             };
 
+            // The classic format has no link entries; use a Katana archive with `--symlinks`
             if metadata.file_type().is_symlink() {
                 continue;
             }
@@ -313,6 +550,11 @@ pub fn collect_file_metadata(paths: &[PathBuf]) -> Result<Vec<FileMetadata>, Arc
                     path: absolute_path.clone(),
                 })?
                 .to_path_buf();
+            let kept = if metadata.is_dir() { !filter.excludes(&relative_path) } else { filter.allows(&relative_path) }
+                && !filter.ignored(path_arg, &path, metadata.is_dir());
+            if !kept {
+                continue;
+            }
 
             let permissions: u32 = {
                 #[cfg(unix)]
@@ -325,8 +567,7 @@ pub fn collect_file_metadata(paths: &[PathBuf]) -> Result<Vec<FileMetadata>, Arc
             let ext_dense = relative_path
                 .extension()
                 .and_then(|s| s.to_str())
-                .map(is_dense_ext)
-                .unwrap_or(false);
+                .is_some_and(|ext| rules.is_dense_ext(ext));
             let dense = if ext_dense {
                 true
             } else {
@@ -339,6 +580,7 @@ pub fn collect_file_metadata(paths: &[PathBuf]) -> Result<Vec<FileMetadata>, Arc
                 size: metadata.len(),
                 permissions,
                 modified_time,
+                created_time: crate::fsx::btime_secs(&metadata),
                 is_dir: metadata.is_dir(),
                 dense_hint: Some(dense),
             };
@@ -438,12 +680,12 @@ pub fn compress_bundle_streaming(
             let mut is_dense = if let Some(flag) = file_meta.dense_hint {
                  flag
              } else {
+                 // Без подсказки из collect_file_metadata – встроенный список
                  let ext_dense = file_meta
                      .path
                      .extension()
                      .and_then(|s| s.to_str())
-                     .map(is_dense_ext)
-                     .unwrap_or(false);
+                     .is_some_and(|ext| ExtensionRules::default().is_dense_ext(ext));
                  if ext_dense {
                      true
                  } else {
@@ -469,7 +711,7 @@ pub fn compress_bundle_streaming(
     }
 
 
-    let mut temp_file = NamedTempFile::new().map_err(|e| ArchiverError::Io { source: e, path: PathBuf::new() })?;
+    let mut temp_file = crate::temp_manager::temp_file("bundle").map_err(|e| ArchiverError::Io { source: e, path: PathBuf::new() })?;
     let mut stored_sizes: Vec<u64> = Vec::with_capacity(files.len());
 
     // Initialise global dictionary cache (decompression side)
@@ -556,6 +798,25 @@ pub fn compress_bundle_streaming(
                     stored_sizes.push(end_pos - start_pos);
                 }
             },
+            CompressionAlgo::Brotli { quality } => {
+                use std::io::{Read, Seek, SeekFrom};
+                // Brotli is single-threaded: `threads` does not apply
+                for file_meta in files {
+                    let start_pos = temp_file.as_file_mut().seek(SeekFrom::End(0))?;
+                    let file = File::open(&file_meta.absolute_path).map_err(|e| ArchiverError::Io {
+                        source: e,
+                        path: file_meta.absolute_path.clone(),
+                    })?;
+                    let params = brotli::enc::BrotliEncoderParams { quality: quality as i32, lgwin: BROTLI_LGWIN as i32, ..Default::default() };
+                    // Сжимаем целиком одним вызовом: ошибки завершения потока не теряются, как в into_inner
+                    let mut input = std::io::Cursor::new(u32::MAX.to_le_bytes()).chain(file);
+                    brotli::BrotliCompress(&mut input, temp_file.as_file_mut(), &params)
+                        .map_err(|e| ArchiverError::Io { source: e, path: file_meta.absolute_path.clone() })?;
+
+                    let end_pos = temp_file.as_file_mut().seek(SeekFrom::End(0))?;
+                    stored_sizes.push(end_pos - start_pos);
+                }
+            },
             CompressionAlgo::Store => {
                 // No additional compression; write files directly.
                 let mut writer = &mut temp_file;
@@ -606,7 +867,7 @@ fn write_files_to_encoder<W: Write>(
 }
 
 
-pub fn group_files_into_bundles(metadata_list: &[FileMetadata], mode: TextBundleMode) -> Vec<Vec<FileMetadata>> {
+pub fn group_files_into_bundles(metadata_list: &[FileMetadata], mode: TextBundleMode, rules: &ExtensionRules) -> Vec<Vec<FileMetadata>> {
     let mut bundles = Vec::new();
     if metadata_list.is_empty() {
         return bundles;
@@ -642,9 +903,10 @@ pub fn group_files_into_bundles(metadata_list: &[FileMetadata], mode: TextBundle
         if current_bundle.is_empty() {
             current_ext = metadata_ext.clone();
             // decide limit based on extension heuristic
-            let is_text = matches!(current_ext.as_str(),
+            let ext = current_ext.to_ascii_lowercase();
+            let is_text = rules.text_rule(&ext).unwrap_or_else(|| matches!(ext.as_str(),
                 "txt" | "csv" | "md" | "json" | "xml" | "html" | "htm" | "js" | "css" | "rs" | "py" | "java" | "go" | "kt" | "c" | "cpp" | "h" | "hpp" | "ts" | "tsx" | "yaml" | "yml"
-            );
+            ));
             current_limit = if is_text {
                 match mode {
                     TextBundleMode::Small => BUNDLE_SIZE_SMALL,
@@ -0,0 +1,108 @@
+//! User configuration file (`config.toml`).
+//!
+//! Looked up at:
+//! * `$BLITZARCH_CONFIG` when set;
+//! * Windows: `%APPDATA%\BlitzArch\config.toml`;
+//! * others: `$XDG_CONFIG_HOME/blitzarch/config.toml` or `~/.config/blitzarch/config.toml`.
+//!
+//! A missing file is the same as an empty one. Example:
+//!
+//! ```toml
+//! [extensions]
+//! # Stored as-is instead of being compressed
+//! dense = ["parquet", "onnx", "-pdf"]
+//! # Bundled and preprocessed like source code
+//! text = ["proto", "sql"]
+//! ```
+//!
+//! Entries with a leading `-` take an extension off the built-in list. The
+//! `--dense-ext` / `--text-ext` options of `create` are applied after the file.
+//!
+//! `[profile.NAME]` tables hold `create` options selected with `--profile NAME`;
+//! keys are the long option names and options given on the command line win:
+//!
+//! ```toml
+//! [profile.backup]
+//! level = 7
+//! threads = 0
+//! encrypt = true
+//! exclude = ["target/**", "*.tmp"]
+//! ```
+//!
+//! `[compact]` sets up automatic compaction after `append` and `watch` batches;
+//! `--auto-compact` on the command line wins:
+//!
+//! ```toml
+//! [compact]
+//! # Compact once dead bytes exceed this share of the archive, in percent
+//! auto_above = 25
+//! ```
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    pub extensions: ExtensionConfig,
+    /// `[profile.NAME]`: `create` options by profile name (see `cli::profile_args`).
+    #[serde(rename = "profile")]
+    pub profiles: BTreeMap<String, toml::Table>,
+    pub compact: CompactConfig,
+}
+
+/// `[compact]`: when archives are compacted without an explicit `compact`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompactConfig {
+    /// Dead bytes, in percent of the archive, above which `append` and `watch`
+    /// compact it. Unset = never.
+    pub auto_above: Option<f64>,
+}
+
+/// `[extensions]`: additions to (or, with `-`, removals from) the built-in lists.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtensionConfig {
+    /// Already-compressed formats, stored without compression by adaptive mode.
+    pub dense: Vec<String>,
+    /// Text formats, grouped into large bundles and preprocessed.
+    pub text: Vec<String>,
+}
+
+impl UserConfig {
+    /// Parses a config file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    /// Loads the config from [`default_config_path`]; defaults when there is none.
+    pub fn load_default() -> Result<Self, Box<dyn Error>> {
+        match default_config_path() {
+            Some(path) if path.is_file() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+/// Platform default path of the config file, if a home/config directory is known.
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Some(p) = std::env::var_os("BLITZARCH_CONFIG") {
+        return Some(PathBuf::from(p));
+    }
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|a| PathBuf::from(a).join("BlitzArch"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+            .map(|d| d.join("blitzarch"))
+    }?;
+    Some(dir.join(CONFIG_FILE_NAME))
+}
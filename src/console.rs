@@ -0,0 +1,124 @@
+//! Console output of the engine.
+//!
+//! Engine modules import `println!`/`eprintln!`/`print!`/`eprint!` from here
+//! instead of using the std macros. Without a [`capture`] they behave the same;
+//! while one is active, everything they write goes to the capture's writer
+//! instead, so front ends that own the terminal (the TUI) or stdout (`--json`)
+//! are not interrupted by progress lines.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+type Writer = Box<dyn Write + Send>;
+
+static CAPTURE: Mutex<Option<Writer>> = Mutex::new(None);
+
+#[doc(hidden)]
+pub fn write_out(args: fmt::Arguments) {
+    if !write_captured(args) {
+        let _ = io::stdout().lock().write_fmt(args);
+    }
+}
+
+#[doc(hidden)]
+pub fn write_err(args: fmt::Arguments) {
+    if !write_captured(args) {
+        let _ = io::stderr().lock().write_fmt(args);
+    }
+}
+
+fn write_captured(args: fmt::Arguments) -> bool {
+    let mut capture = CAPTURE.lock().unwrap_or_else(|e| e.into_inner());
+    match capture.as_mut() {
+        Some(writer) => {
+            let _ = writer.write_fmt(args);
+            let _ = writer.flush();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Sends engine output (both streams) to `writer` until the guard is dropped.
+/// Captures nest; dropping the guard restores the previous one.
+pub fn capture(writer: impl Write + Send + 'static) -> Capture {
+    let mut capture = CAPTURE.lock().unwrap_or_else(|e| e.into_inner());
+    Capture { previous: capture.replace(Box::new(writer)) }
+}
+
+#[must_use = "output is only captured while the guard is alive"]
+pub struct Capture {
+    previous: Option<Writer>,
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let mut capture = CAPTURE.lock().unwrap_or_else(|e| e.into_inner());
+        *capture = self.previous.take();
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __console_print {
+    ($($arg:tt)*) => { $crate::console::write_out(format_args!($($arg)*)) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __console_println {
+    () => { $crate::console::write_out(format_args!("\n")) };
+    ($($arg:tt)*) => { $crate::console::write_out(format_args!("{}\n", format_args!($($arg)*))) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __console_eprint {
+    ($($arg:tt)*) => { $crate::console::write_err(format_args!($($arg)*)) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __console_eprintln {
+    () => { $crate::console::write_err(format_args!("\n")) };
+    ($($arg:tt)*) => { $crate::console::write_err(format_args!("{}\n", format_args!($($arg)*))) };
+}
+
+pub use crate::__console_eprint as eprint;
+pub use crate::__console_eprintln as eprintln;
+pub use crate::__console_print as print;
+pub use crate::__console_println as println;
+
+#[cfg(test)]
+mod tests {
+    use super::{capture, eprint, eprintln, println, Mutex, Write};
+    use std::io;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_capture_takes_both_streams_until_dropped() {
+        let buf = Shared::default();
+        {
+            let _guard = capture(buf.clone());
+            println!("out {}", 1);
+            eprint!("err");
+            eprintln!();
+        }
+        println!("not captured");
+        assert_eq!(&*buf.0.lock().unwrap(), b"out 1\nerr\n");
+    }
+}
@@ -28,12 +28,23 @@ pub fn generate_salt() -> Vec<u8> {
     salt
 }
 
+/// Argon2id key of `password` for an archive with `salt`. Served from the
+/// session cache when [`enable_key_cache`] was called.
 pub fn derive_key_argon2(password: &str, salt: &[u8]) -> [u8; KEY_SIZE] {
     let mem_kib: u32 = std::env::var("BLITZ_ARGON2_MEM_KIB")
         .ok()
         .and_then(|v| v.parse().ok())
         .unwrap_or(ARGON2_DEFAULT_MEM_KIB);
 
+    if let Some(key) = cached_key(mem_kib, password, salt) {
+        return key;
+    }
+    let key = argon2_key(password, salt, mem_kib);
+    store_key(mem_kib, password, salt, &key);
+    key
+}
+
+fn argon2_key(password: &str, salt: &[u8], mem_kib: u32) -> [u8; KEY_SIZE] {
     let params = argon2::Params::new(mem_kib, ARGON2_ITER, ARGON2_PARALLELISM, None)
         .expect("argon2 params");
     let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
@@ -145,3 +156,208 @@ pub fn decrypt(
     let cipher_legacy = Aes256Gcm::new(key_legacy);
     cipher_legacy.decrypt(nonce, ciphertext)
 }
+
+// --- Session key cache -----------------------------------------------------------------------
+//
+// Long-lived sessions (daemon, GUI) list and extract the same encrypted archives over and over;
+// each open would pay the full Argon2 cost again. Once enabled, derived keys are kept in a small
+// LRU keyed by a keyed BLAKE3 hash of (Argon2 memory, salt, password) – a different password for
+// the same archive simply misses. Keys are zeroized when evicted, invalidated, idle for longer
+// than the timeout or when the session is locked.
+
+struct CachedKey {
+    salt: Vec<u8>,
+    key: [u8; KEY_SIZE],
+    last_used: std::time::Instant,
+}
+
+impl Drop for CachedKey {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+        self.key.zeroize();
+    }
+}
+
+struct KeyCache {
+    capacity: usize,
+    idle_timeout: std::time::Duration,
+    /// Per-session key of the lookup hash, so the map holds no password digests.
+    secret: [u8; 32],
+    entries: std::collections::HashMap<[u8; 32], CachedKey>,
+    /// Bumped on every (re)configuration; stops sweepers of earlier sessions.
+    generation: u64,
+}
+
+impl KeyCache {
+    fn id(&self, mem_kib: u32, password: &str, salt: &[u8]) -> [u8; 32] {
+        let mut h = blake3::Hasher::new_keyed(&self.secret);
+        h.update(&mem_kib.to_le_bytes());
+        h.update(&(salt.len() as u64).to_le_bytes());
+        h.update(salt);
+        h.update(password.as_bytes());
+        *h.finalize().as_bytes()
+    }
+
+    fn purge_expired(&mut self) {
+        let timeout = self.idle_timeout;
+        self.entries.retain(|_, e| e.last_used.elapsed() < timeout);
+    }
+}
+
+static KEY_CACHE: std::sync::Mutex<Option<KeyCache>> = std::sync::Mutex::new(None);
+static KEY_CACHE_GENERATION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+fn key_cache() -> std::sync::MutexGuard<'static, Option<KeyCache>> {
+    KEY_CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Starts caching derived keys for this process: at most `capacity` keys,
+/// each dropped after `idle_timeout` without use. Replaces (and clears) a
+/// previously enabled cache.
+pub fn enable_key_cache(capacity: usize, idle_timeout: std::time::Duration) {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let generation = KEY_CACHE_GENERATION.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+    *key_cache() = Some(KeyCache {
+        capacity: capacity.max(1),
+        idle_timeout,
+        secret,
+        entries: Default::default(),
+        generation,
+    });
+    // Ключи не должны дожидаться следующего обращения к кэшу, чтобы истечь
+    let tick = (idle_timeout / 2).clamp(std::time::Duration::from_millis(50), std::time::Duration::from_secs(60));
+    std::thread::Builder::new()
+        .name("blitz-key-cache".into())
+        .spawn(move || loop {
+            std::thread::sleep(tick);
+            match key_cache().as_mut() {
+                Some(cache) if cache.generation == generation => cache.purge_expired(),
+                _ => break,
+            }
+        })
+        .ok();
+}
+
+/// Stops caching and zeroizes every cached key.
+pub fn disable_key_cache() {
+    *key_cache() = None;
+}
+
+/// Zeroizes every cached key (e.g. when the session is locked); the cache
+/// stays enabled.
+pub fn lock_key_cache() {
+    if let Some(cache) = key_cache().as_mut() {
+        cache.entries.clear();
+    }
+}
+
+/// Drops the cached keys of the archive with `salt` (e.g. after its password
+/// was changed or it was re-encrypted).
+pub fn invalidate_cached_key(salt: &[u8]) {
+    if let Some(cache) = key_cache().as_mut() {
+        cache.entries.retain(|_, e| e.salt != salt);
+    }
+}
+
+/// Number of keys currently cached (0 while the cache is disabled).
+pub fn cached_key_count() -> usize {
+    key_cache().as_ref().map_or(0, |c| c.entries.len())
+}
+
+fn cached_key(mem_kib: u32, password: &str, salt: &[u8]) -> Option<[u8; KEY_SIZE]> {
+    let mut guard = key_cache();
+    let cache = guard.as_mut()?;
+    let id = cache.id(mem_kib, password, salt);
+    let timeout = cache.idle_timeout;
+    match cache.entries.get_mut(&id) {
+        Some(entry) if entry.last_used.elapsed() < timeout => {
+            entry.last_used = std::time::Instant::now();
+            Some(entry.key)
+        }
+        Some(_) => {
+            cache.entries.remove(&id);
+            None
+        }
+        None => None,
+    }
+}
+
+fn store_key(mem_kib: u32, password: &str, salt: &[u8], key: &[u8; KEY_SIZE]) {
+    let mut guard = key_cache();
+    let Some(cache) = guard.as_mut() else { return };
+    cache.purge_expired();
+    let id = cache.id(mem_kib, password, salt);
+    if !cache.entries.contains_key(&id) && cache.entries.len() >= cache.capacity {
+        let oldest = cache.entries.iter().min_by_key(|(_, e)| e.last_used).map(|(id, _)| *id);
+        if let Some(oldest) = oldest {
+            cache.entries.remove(&oldest);
+        }
+    }
+    cache.entries.insert(id, CachedKey { salt: salt.to_vec(), key: *key, last_used: std::time::Instant::now() });
+}
+
+// --- Per-shard subkeys -----------------------------------------------------------------------
+//
+// Katana archives whose index carries the `FEATURE_SHARD_SUBKEYS` bit encrypt every shard with
+// its own key instead of the Argon2 master key:
+//
+//     shard_key = HKDF-SHA256(ikm = master_key, salt = none, info = SHARD_KEY_INFO || key_id_le64)
+//
+// `key_id` is stored per shard in the index (`ShardInfo::key_id`). Since no two shards share a key,
+// the random 96-bit GCM nonces never have to be coordinated between workers or across appends,
+// a single shard can be re-encrypted under a fresh id, and new shards can be appended without
+// touching existing ones. The master key itself is only used for the index HMAC and to derive the
+// key of a hidden index (`derive_index_key`).
+//
+// Test vectors (master key = bytes 0x00..=0x1f):
+//
+//     key_id 0     → b0ce8f0953124a86aaf8c21679e0247b583823747b925e1b6bdc50e874d08517
+//     key_id 1     → 17b59caa33654ad093443de56ad452bef8d6091c06f83f220f86935f0ea0f230
+//     key_id 2^32  → 17dfdd30af8db392247938faba907dc8c6220271d679681438700bab06ad5a59
+
+/// HKDF `info` prefix for shard subkeys; the little-endian key id is appended.
+pub const SHARD_KEY_INFO: &[u8] = b"blitzarch/katana/shard-key/v1";
+
+/// Derives the AES-256 key of the shard with `key_id` from the archive master key.
+pub fn derive_shard_key(master_key: &[u8; KEY_SIZE], key_id: u64) -> [u8; KEY_SIZE] {
+    let hk = hkdf::Hkdf::<Sha256>::new(None, master_key);
+    let mut info = Vec::with_capacity(SHARD_KEY_INFO.len() + 8);
+    info.extend_from_slice(SHARD_KEY_INFO);
+    info.extend_from_slice(&key_id.to_le_bytes());
+    let mut key = [0u8; KEY_SIZE];
+    hk.expand(&info, &mut key).expect("32 bytes is a valid HKDF-SHA256 length");
+    key
+}
+
+/// HKDF `info` of the key that encrypts the index block of archives created
+/// with `--hide-names`.
+pub const INDEX_KEY_INFO: &[u8] = b"blitzarch/katana/index-key/v1";
+
+/// Derives the AES-256 key of a sealed index from the archive master key.
+pub fn derive_index_key(master_key: &[u8; KEY_SIZE]) -> [u8; KEY_SIZE] {
+    let hk = hkdf::Hkdf::<Sha256>::new(None, master_key);
+    let mut key = [0u8; KEY_SIZE];
+    hk.expand(INDEX_KEY_INFO, &mut key).expect("32 bytes is a valid HKDF-SHA256 length");
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::derive_shard_key;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_shard_key_vectors() {
+        let mut master = [0u8; 32];
+        for (i, b) in master.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        assert_eq!(hex(&derive_shard_key(&master, 0)), "b0ce8f0953124a86aaf8c21679e0247b583823747b925e1b6bdc50e874d08517");
+        assert_eq!(hex(&derive_shard_key(&master, 1)), "17b59caa33654ad093443de56ad452bef8d6091c06f83f220f86935f0ea0f230");
+        assert_eq!(hex(&derive_shard_key(&master, 1 << 32)), "17dfdd30af8db392247938faba907dc8c6220271d679681438700bab06ad5a59");
+    }
+}
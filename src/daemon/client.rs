@@ -0,0 +1,82 @@
+//! Client side of the daemon protocol, for the GUI and scripts.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+/// Error response of the daemon. `data` carries the
+/// [`ErrorReport`](crate::ErrorReport) of a failed job.
+#[derive(Debug, Clone)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for RpcError {}
+
+/// One connection to a running daemon. Calls on one client are sequential;
+/// open several clients to drive jobs in parallel.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+}
+
+impl Client {
+    /// Connects to the daemon at `socket`. A socket served by another user is
+    /// refused, since requests carry passwords.
+    pub fn connect(socket: &Path) -> Result<Self, Box<dyn Error>> {
+        let writer = UnixStream::connect(socket).map_err(|e| format!("cannot connect to the daemon at {}: {e}", socket.display()))?;
+        let peer = super::peer_uid(&writer)?;
+        if peer != unsafe { libc::geteuid() } {
+            return Err(format!("the daemon at {} runs as uid {peer}, not as the current user", socket.display()).into());
+        }
+        Ok(Client { reader: BufReader::new(writer.try_clone()?), writer, next_id: 1 })
+    }
+
+    /// Sends a request and waits for its response. Notifications arriving
+    /// meanwhile (`job`, `progress`) are passed to `on_event` with their
+    /// method and params.
+    pub fn call(&mut self, method: &str, params: Value, mut on_event: impl FnMut(&str, &Value)) -> Result<Value, Box<dyn Error>> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut line = serde_json::to_string(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err("the daemon closed the connection".into());
+            }
+            let mut message: Value = serde_json::from_str(&line)?;
+            if message.get("id").is_none_or(Value::is_null) {
+                if let Some(event) = message["method"].as_str() {
+                    on_event(event, &message["params"]);
+                }
+                continue;
+            }
+            if message["id"] != id {
+                continue;
+            }
+            if let Some(error) = message.get_mut("error") {
+                return Err(Box::new(RpcError {
+                    code: error["code"].as_i64().unwrap_or_default(),
+                    message: error["message"].as_str().unwrap_or("unknown daemon error").to_string(),
+                    data: error.get_mut("data").map(Value::take),
+                }));
+            }
+            return Ok(message["result"].take());
+        }
+    }
+}
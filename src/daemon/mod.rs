@@ -1,2 +1,564 @@
-//! gRPC/REST daemon module.
-// Implements the remote management API.
+//! Long-running job server (`blitzarch daemon`).
+//!
+//! The daemon listens on a Unix socket and speaks line-delimited JSON-RPC 2.0:
+//! one request object per line in, one response per request out, plus
+//! notifications (requests without `id`) while a job runs. The GUI uses it
+//! instead of in-process calls, so archive jobs survive a window reload and
+//! share one memory budget.
+//!
+//! Job methods – `create`, `extract`, `list`, `verify` – answer when the job
+//! is finished; until then the connection receives `job` notifications on
+//! state changes (`queued`, `running`) and `progress` notifications with the
+//! job's figures. Each job carries a daemon-wide `job` number. Several jobs can
+//! be in flight on one connection; the responses carry the request `id`.
+//!
+//! ```text
+//! → {"jsonrpc":"2.0","id":1,"method":"create","params":{"inputs":["docs"],"output":"docs.blz"}}
+//! ← {"jsonrpc":"2.0","method":"job","params":{"job":4,"state":"queued"}}
+//! ← {"jsonrpc":"2.0","method":"job","params":{"job":4,"state":"running"}}
+//! ← {"jsonrpc":"2.0","method":"progress","params":{"job":4,"processed_bytes":…}}
+//! ← {"jsonrpc":"2.0","id":1,"result":{"job":4,"command":"create","files":12,…}}
+//! ```
+//!
+//! `jobs` lists the known jobs, `cancel` (`{"job":4}`) stops a queued or
+//! running one and `status` reports the scheduler usage. Jobs start once one
+//! of `--max-jobs` slots is free and the memory they reserve (`memory_mb`, or a
+//! per-method default) fits into `--memory-budget` next to the running jobs;
+//! a create job is held to its reservation. The worker threads and memory of
+//! running jobs are shared through the [`crate::governor`], whose memory limit
+//! is `--memory-budget`. A job keeps running when its client disconnects.
+
+pub mod scheduler;
+
+#[cfg(unix)]
+pub mod client;
+
+pub use scheduler::{Permit, Scheduler};
+
+#[cfg(unix)]
+pub use client::{Client, RpcError};
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::cancel::CancellationToken;
+use crate::progress::ProgressState;
+#[cfg(unix)]
+use crate::console::{eprintln, println};
+
+/// Default `--max-jobs`.
+pub const DEFAULT_MAX_JOBS: usize = 2;
+
+/// Finished jobs kept for `jobs`; older ones are forgotten.
+const FINISHED_JOBS_KEPT: usize = 256;
+
+// JSON-RPC 2.0 error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// A job failed; `data` is its [`crate::ErrorReport`].
+pub const JOB_FAILED: i64 = -32000;
+/// A job was cancelled (see the `cancel` method).
+pub const JOB_CANCELLED: i64 = -32001;
+
+/// `$XDG_RUNTIME_DIR/blitzarch.sock`, or a socket in a private per-user
+/// directory of the temp dir (see [`serve`]).
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("blitzarch.sock"),
+        #[cfg(unix)]
+        _ => std::env::temp_dir().join(format!("blitzarch-{}", unsafe { libc::getuid() })).join("daemon.sock"),
+        #[cfg(not(unix))]
+        _ => std::env::temp_dir().join("blitzarch.sock"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn finished(self) -> bool {
+        matches!(self, JobState::Done | JobState::Failed | JobState::Cancelled)
+    }
+}
+
+struct JobEntry {
+    method: String,
+    state: JobState,
+    memory_mb: u64,
+    cancel: CancellationToken,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CreateParams {
+    inputs: Vec<PathBuf>,
+    output: PathBuf,
+    #[serde(default)]
+    level: Option<i32>,
+    #[serde(default)]
+    threads: usize,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    memory_mb: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExtractParams {
+    archive: PathBuf,
+    output_dir: PathBuf,
+    #[serde(default)]
+    files: Vec<PathBuf>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    memory_mb: Option<u64>,
+    /// Per-job extraction settings, e.g. `{"preserve_permissions": false}`
+    #[serde(default)]
+    options: crate::extract::ExtractOptions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListParams {
+    archive: PathBuf,
+    #[serde(default)]
+    password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VerifyParams {
+    archive: PathBuf,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    deep: bool,
+    #[serde(default)]
+    memory_mb: Option<u64>,
+}
+
+/// A job request with its parameters checked.
+#[derive(Debug)]
+enum Job {
+    Create(CreateParams),
+    Extract(ExtractParams),
+    List(ListParams),
+    Verify(VerifyParams),
+}
+
+impl Job {
+    /// `Ok(None)` for a method that is not a job.
+    fn parse(method: &str, params: Value) -> Result<Option<Job>, serde_json::Error> {
+        let params = if params.is_null() { json!({}) } else { params };
+        Ok(Some(match method {
+            "create" => Job::Create(serde_json::from_value(params)?),
+            "extract" => Job::Extract(serde_json::from_value(params)?),
+            "list" => Job::List(serde_json::from_value(params)?),
+            "verify" => Job::Verify(serde_json::from_value(params)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn method(&self) -> &'static str {
+        match self {
+            Job::Create(_) => "create",
+            Job::Extract(_) => "extract",
+            Job::List(_) => "list",
+            Job::Verify(_) => "verify",
+        }
+    }
+
+    /// Memory the job reserves in the scheduler, in MiB.
+    fn memory_mb(&self) -> u64 {
+        match self {
+            Job::Create(p) => p.memory_mb.unwrap_or(1024),
+            Job::Extract(p) => p.memory_mb.unwrap_or(512),
+            Job::List(_) => 16,
+            Job::Verify(p) => p.memory_mb.unwrap_or(if p.deep { 256 } else { 64 }),
+        }
+    }
+
+    fn run(self, memory_mb: u64, cancel: &CancellationToken, progress: impl Fn(ProgressState) + Send + Sync + 'static) -> Result<Value, Box<dyn Error>> {
+        let started = Instant::now();
+        match self {
+            Job::Create(p) => {
+                let started_at = SystemTime::now();
+                let mut builder = crate::Archive::create(&p.inputs)
+                    .threads(p.threads)
+                    .memory_budget_mb(memory_mb)
+                    .cancel_token(cancel.clone())
+                    .on_progress(progress);
+                if let Some(level) = p.level {
+                    builder = builder.level(level);
+                }
+                if let Some(password) = p.password.clone() {
+                    builder = builder.password(password);
+                }
+                for pattern in &p.exclude {
+                    builder = builder.exclude(pattern.as_str());
+                }
+                builder.write_to(&p.output)?;
+                crate::cli::json::create_report(&p.output, p.password.as_deref(), started_at, started.elapsed(), &[])
+            }
+            Job::Extract(p) => {
+                let last = Arc::new(Mutex::new(None));
+                let seen = Arc::clone(&last);
+                std::fs::create_dir_all(&p.output_dir)?;
+                crate::katana::extract_katana_archive_with_options(
+                    &p.archive,
+                    &p.output_dir,
+                    &p.files,
+                    p.password,
+                    None,
+                    Some(move |state: ProgressState| {
+                        *seen.lock().unwrap_or_else(|e| e.into_inner()) = Some(state.clone());
+                        progress(state);
+                    }),
+                    Some(cancel),
+                    &p.options,
+                )?;
+                let last = last.lock().unwrap_or_else(|e| e.into_inner()).take();
+                Ok(crate::cli::json::extract_report(&p.archive, &p.output_dir, last.as_ref(), started.elapsed(), &[]))
+            }
+            Job::List(p) => Ok(json!({
+                "command": "list",
+                "archive": p.archive,
+                "metadata": crate::extract::read_metadata(&p.archive, p.password.as_deref())?,
+                "entries": crate::extract::list_entries(&p.archive, p.password.as_deref())?,
+            })),
+            Job::Verify(p) => {
+                let report = crate::katana::verify_archive(&p.archive, p.password.as_deref(), p.deep)?;
+                crate::cli::json::verify_report(&p.archive, &report, started.elapsed())
+            }
+        }
+    }
+}
+
+fn progress_params(job: u64, state: &ProgressState) -> Value {
+    json!({
+        "job": job,
+        "processed_files": state.processed_files,
+        "total_files": state.total_files,
+        "processed_bytes": state.processed_bytes,
+        "total_bytes": state.total_bytes,
+        "completed_shards": state.completed_shards,
+        "total_shards": state.total_shards,
+        "percent": state.progress_percent,
+        "speed_mbps": state.speed_mbps,
+    })
+}
+
+fn error_object(code: i64, message: impl Into<String>, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message.into() });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    error
+}
+
+/// Jobs and scheduler shared by all connections.
+pub struct Server {
+    scheduler: Arc<Scheduler>,
+    jobs: Mutex<BTreeMap<u64, JobEntry>>,
+    next_job: AtomicU64,
+}
+
+impl Server {
+    pub fn new(max_jobs: usize, memory_budget_mb: u64) -> Arc<Self> {
+        Arc::new(Server { scheduler: Scheduler::new(max_jobs, memory_budget_mb), jobs: Mutex::new(BTreeMap::new()), next_job: AtomicU64::new(1) })
+    }
+
+    fn jobs(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, JobEntry>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_state(&self, job: u64, state: JobState) {
+        if let Some(entry) = self.jobs().get_mut(&job) {
+            entry.state = state;
+        }
+    }
+
+    /// Answers a request that is not a job; `None` for an unknown method.
+    fn call(&self, method: &str, params: &Value) -> Option<Result<Value, Value>> {
+        Some(match method {
+            "status" => {
+                let (running, memory_used_mb) = self.scheduler.running();
+                let queued = self.jobs().values().filter(|j| j.state == JobState::Queued).count();
+                let (_, leased) = crate::governor::ResourceGovernor::global().in_use();
+                Ok(json!({
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "running": running,
+                    "queued": queued,
+                    "max_jobs": self.scheduler.max_jobs(),
+                    "memory_used_mb": memory_used_mb,
+                    "memory_budget_mb": self.scheduler.memory_budget_mb(),
+                    "threads_used": leased.threads,
+                    "max_threads": crate::governor::ResourceGovernor::global().limits().threads,
+                }))
+            }
+            "jobs" => Ok(Value::Array(
+                self.jobs()
+                    .iter()
+                    .map(|(id, j)| json!({ "job": id, "method": j.method, "state": j.state, "memory_mb": j.memory_mb }))
+                    .collect(),
+            )),
+            "cancel" => match params["job"].as_u64() {
+                Some(job) => {
+                    let jobs = self.jobs();
+                    let cancelled = jobs.get(&job).filter(|j| !j.state.finished()).map(|j| j.cancel.cancel()).is_some();
+                    if jobs.contains_key(&job) {
+                        Ok(json!({ "job": job, "cancelled": cancelled }))
+                    } else {
+                        Err(error_object(INVALID_PARAMS, format!("no job {job}"), None))
+                    }
+                }
+                None => Err(error_object(INVALID_PARAMS, "`job` (a job number) is required", None)),
+            },
+            _ => return None,
+        })
+    }
+
+    /// Queues `job`, runs it once the scheduler admits it and returns its
+    /// result; notifications go to `notify`.
+    fn run_job(&self, job: Job, notify: &(impl Fn(&str, Value) + Send + Sync + Clone + 'static)) -> Result<Value, Value> {
+        let id = self.next_job.fetch_add(1, Ordering::SeqCst);
+        let memory_mb = job.memory_mb();
+        let cancel = CancellationToken::new();
+        {
+            let mut jobs = self.jobs();
+            while jobs.len() >= FINISHED_JOBS_KEPT {
+                let Some(oldest) = jobs.iter().find(|(_, j)| j.state.finished()).map(|(id, _)| *id) else { break };
+                jobs.remove(&oldest);
+            }
+            jobs.insert(id, JobEntry { method: job.method().to_string(), state: JobState::Queued, memory_mb, cancel: cancel.clone() });
+        }
+        notify("job", json!({ "job": id, "state": JobState::Queued }));
+
+        let cancelled = || error_object(JOB_CANCELLED, crate::ArchiverError::Cancelled.to_string(), Some(json!({ "job": id })));
+        let Some(permit) = self.scheduler.admit(memory_mb, &cancel) else {
+            self.set_state(id, JobState::Cancelled);
+            return Err(cancelled());
+        };
+        self.set_state(id, JobState::Running);
+        notify("job", json!({ "job": id, "state": JobState::Running }));
+
+        let started = Instant::now();
+        let progress = {
+            let notify = notify.clone();
+            move |state: ProgressState| notify("progress", progress_params(id, &state))
+        };
+        let (result, warnings) = crate::warnings::capture(|| job.run(permit.memory_mb(), &cancel, progress));
+        drop(permit);
+        match result {
+            Ok(mut value) => {
+                value["job"] = json!(id);
+                value["elapsed_secs"] = json!(started.elapsed().as_secs_f64());
+                value["warnings"] = json!(warnings);
+                self.set_state(id, JobState::Done);
+                Ok(value)
+            }
+            Err(e) if crate::cancel::is_cancelled(e.as_ref()) => {
+                self.set_state(id, JobState::Cancelled);
+                Err(cancelled())
+            }
+            Err(e) => {
+                self.set_state(id, JobState::Failed);
+                let mut report = serde_json::to_value(crate::ErrorReport::from_error(e.as_ref())).unwrap_or_default();
+                report["job"] = json!(id);
+                Err(error_object(JOB_FAILED, e.to_string(), Some(report)))
+            }
+        }
+    }
+
+    /// Serves one client until it disconnects. Job requests run on their own
+    /// threads, so a connection can have several jobs in flight.
+    #[cfg(unix)]
+    pub fn handle(self: &Arc<Self>, stream: std::os::unix::net::UnixStream) -> std::io::Result<()> {
+        use std::io::{BufRead, BufReader, Write};
+
+        let writer = Arc::new(Mutex::new(stream.try_clone()?));
+        let send = move |message: Value| {
+            let mut line = message.to_string();
+            line.push('\n');
+            // Клиент мог уйти – задания всё равно доводим до конца
+            let _ = writer.lock().unwrap_or_else(|e| e.into_inner()).write_all(line.as_bytes());
+        };
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request: Value = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(e) => {
+                    send(json!({ "jsonrpc": "2.0", "id": null, "error": error_object(PARSE_ERROR, e.to_string(), None) }));
+                    continue;
+                }
+            };
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            let method = request["method"].as_str().unwrap_or_default().to_string();
+            let params = request.get("params").cloned().unwrap_or(Value::Null);
+            let reply = {
+                let send = send.clone();
+                move |result: Result<Value, Value>| match result {
+                    Ok(result) => send(json!({ "jsonrpc": "2.0", "id": id, "result": result })),
+                    Err(error) => send(json!({ "jsonrpc": "2.0", "id": id, "error": error })),
+                }
+            };
+            match Job::parse(&method, params.clone()) {
+                Ok(Some(job)) => {
+                    let server = Arc::clone(self);
+                    let send = send.clone();
+                    let notify = move |method: &str, params: Value| send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+                    std::thread::Builder::new()
+                        .name(format!("blitz-daemon-{method}"))
+                        .spawn(move || reply(server.run_job(job, &notify)))?;
+                }
+                Ok(None) => match self.call(&method, &params) {
+                    Some(result) => reply(result),
+                    None => reply(Err(error_object(METHOD_NOT_FOUND, format!("unknown method `{method}`"), None))),
+                },
+                Err(e) => reply(Err(error_object(INVALID_PARAMS, format!("invalid params for `{method}`: {e}"), None))),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// User id of the process at the other end of `stream`.
+#[cfg(unix)]
+pub(crate) fn peer_uid(stream: &std::os::unix::net::UnixStream) -> std::io::Result<u32> {
+    use std::os::unix::io::AsRawFd;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED, &mut cred as *mut _ as *mut libc::c_void, &mut len)
+        };
+        if rc != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(cred.uid)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let (mut uid, mut gid) = (0, 0);
+        if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(uid)
+    }
+}
+
+/// Creates the socket's directory private (0700) or checks that an existing
+/// one is ours (or root's) and not writable by others, so nobody else can
+/// swap the socket for their own.
+#[cfg(unix)]
+fn prepare_socket_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    if !dir.exists() {
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    let meta = std::fs::symlink_metadata(dir)?;
+    let uid = unsafe { libc::geteuid() };
+    if !meta.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()).into());
+    }
+    if meta.uid() != uid && meta.uid() != 0 {
+        return Err(format!("{} belongs to another user; pick a --socket in a directory of your own", dir.display()).into());
+    }
+    // Общий каталог (/tmp) – только с sticky-битом
+    if meta.uid() == uid && meta.mode() & 0o022 != 0 && meta.mode() & 0o1000 == 0 {
+        return Err(format!("{} is writable by other users; pick a --socket in a private directory", dir.display()).into());
+    }
+    Ok(())
+}
+
+/// Runs the daemon on `socket` until the process is stopped. A stale socket
+/// left by a crashed daemon is replaced; a live one is an error. Only
+/// connections from processes of the daemon's own user are served.
+#[cfg(unix)]
+pub fn serve(socket: &Path, max_jobs: usize, memory_budget_mb: u64) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Some(dir) = socket.parent().filter(|d| !d.as_os_str().is_empty()) {
+        prepare_socket_dir(dir)?;
+    }
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(format!("a daemon is already listening on {}", socket.display()).into());
+        }
+        std::fs::remove_file(socket)?;
+    }
+    // Пароли идут через сокет – только владелец, и с первого мгновения
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket);
+    unsafe { libc::umask(umask) };
+    let listener = listener.map_err(|e| format!("cannot listen on {}: {e}", socket.display()))?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+    let uid = unsafe { libc::geteuid() };
+    let server = Server::new(max_jobs, memory_budget_mb);
+    // Jobs size their workers from the same budget the scheduler admits them by
+    crate::governor::ResourceGovernor::global().set_limits(crate::governor::Limits { threads: num_cpus::get(), memory_mb: memory_budget_mb });
+    println!(
+        "[daemon] Listening on {} (up to {} jobs, {} MiB memory budget)",
+        socket.display(),
+        server.scheduler.max_jobs(),
+        memory_budget_mb
+    );
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("[daemon] Accept failed: {e}");
+                continue;
+            }
+        };
+        match peer_uid(&stream) {
+            Ok(peer) if peer == uid => {}
+            Ok(peer) => {
+                eprintln!("[daemon] Refused a connection from uid {peer}");
+                continue;
+            }
+            Err(e) => {
+                eprintln!("[daemon] Cannot check the peer of a connection: {e}");
+                continue;
+            }
+        }
+        let server = Arc::clone(&server);
+        std::thread::Builder::new().name("blitz-daemon-conn".into()).spawn(move || {
+            if let Err(e) = server.handle(stream) {
+                eprintln!("[daemon] Connection error: {e}");
+            }
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(socket: &Path, max_jobs: usize, memory_budget_mb: u64) -> Result<(), Box<dyn Error>> {
+    Err("the daemon needs Unix domain sockets, which this platform does not have".into())
+}
@@ -0,0 +1,98 @@
+//! Admission control for daemon jobs: a job starts once a job slot is free
+//! and the memory it reserves fits into what the running jobs left of the
+//! budget.
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+
+/// How often a queued job checks whether it was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Usage {
+    jobs: usize,
+    memory_mb: u64,
+}
+
+/// Shared by all connections of a daemon.
+#[derive(Debug)]
+pub struct Scheduler {
+    max_jobs: usize,
+    memory_budget_mb: u64,
+    usage: Mutex<Usage>,
+    released: Condvar,
+}
+
+impl Scheduler {
+    /// `max_jobs` is at least 1.
+    pub fn new(max_jobs: usize, memory_budget_mb: u64) -> Arc<Self> {
+        Arc::new(Scheduler { max_jobs: max_jobs.max(1), memory_budget_mb, usage: Mutex::new(Usage::default()), released: Condvar::new() })
+    }
+
+    fn usage(&self) -> MutexGuard<'_, Usage> {
+        self.usage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn fits(&self, usage: &Usage, memory_mb: u64) -> bool {
+        // A job bigger than the whole budget runs alone instead of never
+        usage.jobs == 0 || (usage.jobs < self.max_jobs && usage.memory_mb + memory_mb <= self.memory_budget_mb)
+    }
+
+    /// Waits until a job reserving `memory_mb` may run; `None` if `cancel`
+    /// fired while it was queued. Waiting jobs are not served in order: a small
+    /// job may start while a big one still waits for memory.
+    pub fn admit(self: &Arc<Self>, memory_mb: u64, cancel: &CancellationToken) -> Option<Permit> {
+        let mut usage = self.usage();
+        while !self.fits(&usage, memory_mb) {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            usage = self.released.wait_timeout(usage, CANCEL_POLL).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        if cancel.is_cancelled() {
+            return None;
+        }
+        usage.jobs += 1;
+        usage.memory_mb += memory_mb;
+        Some(Permit { scheduler: Arc::clone(self), memory_mb })
+    }
+
+    /// Running jobs and the memory they reserved.
+    pub fn running(&self) -> (usize, u64) {
+        let usage = self.usage();
+        (usage.jobs, usage.memory_mb)
+    }
+
+    pub fn max_jobs(&self) -> usize {
+        self.max_jobs
+    }
+
+    pub fn memory_budget_mb(&self) -> u64 {
+        self.memory_budget_mb
+    }
+}
+
+/// A running job's reservation, returned to the scheduler when dropped.
+#[derive(Debug)]
+pub struct Permit {
+    scheduler: Arc<Scheduler>,
+    memory_mb: u64,
+}
+
+impl Permit {
+    pub fn memory_mb(&self) -> u64 {
+        self.memory_mb
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut usage = self.scheduler.usage();
+        usage.jobs -= 1;
+        usage.memory_mb -= self.memory_mb;
+        drop(usage);
+        self.scheduler.released.notify_all();
+    }
+}
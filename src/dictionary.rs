@@ -0,0 +1,137 @@
+//! External zstd dictionaries shared by a series of archives
+//! (`create --dictionary`, `blitzarch train-dictionary`).
+//!
+//! Nightly archives of similar data (logs, JSON exports, source trees) compress
+//! much better with a dictionary trained once on a representative sample. The
+//! dictionary is not stored in the archive: shards compressed with it record
+//! its BLAKE3 id, and readers have to be given the same file (`extract
+//! --dictionary`). The id guarantees a wrong or retrained dictionary is
+//! reported instead of producing garbage.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::console::println;
+
+/// Default size of a trained dictionary (`train-dictionary --max-size`).
+pub const DEFAULT_DICTIONARY_SIZE: usize = 112 * 1024;
+/// Bytes taken from the start of every sample file.
+const SAMPLE_SIZE: u64 = 128 * 1024;
+/// Total sample bytes; zstd recommends ~100× the dictionary size.
+const MAX_SAMPLE_BYTES: usize = 256 * 1024 * 1024;
+
+/// A zstd dictionary identified by the BLAKE3 of its bytes.
+#[derive(Debug, Clone)]
+pub struct ZstdDictionary {
+    id: String,
+    bytes: Arc<Vec<u8>>,
+}
+
+impl ZstdDictionary {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Box<dyn Error>> {
+        if bytes.is_empty() {
+            return Err("Empty zstd dictionary".into());
+        }
+        let id = blake3::hash(&bytes).to_hex().to_string();
+        Ok(ZstdDictionary { id, bytes: Arc::new(bytes) })
+    }
+
+    /// Reads a dictionary file written by `train-dictionary` (or `zstd --train`).
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Cannot read dictionary {}: {}", path.display(), e))?;
+        Self::from_bytes(bytes)
+    }
+
+    /// Hex BLAKE3 of the dictionary, recorded in the index of every shard using it.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Dictionaries a reader may need, by id (`extract`/`verify --dictionary`).
+///
+/// Passed along with the job (see [`crate::extract::ExtractOptions::dictionaries`]);
+/// shards needing one that is not in the set fail naming its id.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionaries(HashMap<String, ZstdDictionary>);
+
+impl Dictionaries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the dictionary files given with `--dictionary`.
+    pub fn load(paths: &[PathBuf]) -> Result<Self, Box<dyn Error>> {
+        let mut set = Self::new();
+        for path in paths {
+            set.insert(ZstdDictionary::load(path)?);
+        }
+        Ok(set)
+    }
+
+    pub fn insert(&mut self, dictionary: ZstdDictionary) {
+        self.0.insert(dictionary.id.clone(), dictionary);
+    }
+
+    /// Dictionary with this id.
+    pub fn get(&self, id: &str) -> Result<&ZstdDictionary, Box<dyn Error>> {
+        self.0
+            .get(id)
+            .ok_or_else(|| format!("Archive needs zstd dictionary {}; pass it with --dictionary", id).into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Sets holding the same dictionaries (by id) are equal.
+impl PartialEq for Dictionaries {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.keys().all(|id| other.0.contains_key(id))
+    }
+}
+
+impl Eq for Dictionaries {}
+
+/// Trains a dictionary of at most `max_size` bytes on the files below `dir`,
+/// sampling the start of every file.
+pub fn train_dictionary(dir: &Path, max_size: usize) -> Result<ZstdDictionary, Box<dyn Error>> {
+    let mut samples = Vec::new();
+    let mut total = 0;
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let mut sample = Vec::new();
+        std::fs::File::open(entry.path())?.take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+        if sample.is_empty() {
+            continue;
+        }
+        total += sample.len();
+        samples.push(sample);
+        if total >= MAX_SAMPLE_BYTES {
+            break;
+        }
+    }
+    // zstd needs a handful of samples to find anything shared
+    if samples.len() < 8 {
+        return Err(format!("Too few sample files in {} to train a dictionary (need at least 8)", dir.display()).into());
+    }
+    let bytes = zstd::dict::from_samples(&samples, max_size)
+        .map_err(|e| format!("Dictionary training failed: {}", e))?;
+    println!(
+        "[katana] Trained a {} KiB dictionary on {} samples ({:.2} MiB)",
+        bytes.len() / 1024,
+        samples.len(),
+        total as f64 / (1024.0 * 1024.0)
+    );
+    ZstdDictionary::from_bytes(bytes)
+}
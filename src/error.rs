@@ -24,6 +24,9 @@ pub enum ArchiverError {
     /// A system time error, which can occur when reading file metadata.
     SystemTime(SystemTimeError),
 
+    /// The job was stopped through its [`crate::cancel::CancellationToken`].
+    Cancelled,
+
     /// A wrapper for any other error that doesn't fit the specific variants.
     Other(Box<dyn std::error::Error + Send + Sync>),
 }
@@ -37,6 +40,7 @@ impl std::fmt::Display for ArchiverError {
             ArchiverError::AesGcm(e) => write!(f, "AEAD encryption error: {}", e),
             ArchiverError::SerdeJson(e) => write!(f, "Serialization error: {}", e),
             ArchiverError::SystemTime(e) => write!(f, "System time error: {}", e),
+            ArchiverError::Cancelled => write!(f, "Operation cancelled"),
             ArchiverError::Other(e) => write!(f, "An unexpected error occurred: {}", e),
         }
     }
@@ -78,3 +82,153 @@ impl From<std::io::Error> for ArchiverError {
         ArchiverError::Io { source: err, path: PathBuf::new() } // Generic path
     }
 }
+
+impl ArchiverError {
+    /// Stable, machine-readable identifier of the error variant.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ArchiverError::Io { .. } => "io",
+            ArchiverError::StripPrefix { .. } => "strip_prefix",
+            ArchiverError::Crypto(_) => "crypto",
+            ArchiverError::AesGcm(_) => "aead",
+            ArchiverError::SerdeJson(_) => "index_serialization",
+            ArchiverError::SystemTime(_) => "system_time",
+            ArchiverError::Cancelled => "cancelled",
+            ArchiverError::Other(_) => "other",
+        }
+    }
+
+    /// Coarse error category shared with [`ErrorReport::category`].
+    pub fn category(&self) -> &'static str {
+        match self {
+            ArchiverError::Io { .. } | ArchiverError::StripPrefix { .. } | ArchiverError::SystemTime(_) => "io",
+            ArchiverError::Crypto(_) | ArchiverError::AesGcm(_) => "crypto",
+            ArchiverError::SerdeJson(_) => "format",
+            ArchiverError::Cancelled => "cancelled",
+            ArchiverError::Other(_) => "internal",
+        }
+    }
+
+    /// Filesystem path the error refers to, if known.
+    pub fn path(&self) -> Option<&std::path::Path> {
+        match self {
+            ArchiverError::Io { path, .. } if !path.as_os_str().is_empty() => Some(path),
+            ArchiverError::StripPrefix { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Machine-readable description of an error, emitted by `--error-format json`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ErrorReport {
+    /// Stable identifier, e.g. `io`, `index_crc_mismatch`, `password_required`.
+    pub code: String,
+    /// Coarse class: `io`, `crypto`, `integrity`, `format`, `usage`, `cancelled` or `internal`.
+    pub category: &'static str,
+    /// Human-readable message (same text as the plain error output).
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Byte offset of the shard involved, when the error is shard-specific.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard: Option<u64>,
+}
+
+impl ErrorReport {
+    /// Builds a report from any error returned by the library.
+    ///
+    /// Typed errors (`ArchiverError`, `std::io::Error`) anywhere in the source chain
+    /// are classified by variant; plain string errors from the Katana engine are
+    /// mapped by their well-known messages.
+    pub fn from_error(err: &(dyn std::error::Error + 'static)) -> Self {
+        let message = err.to_string();
+        let shard = shard_offset_from_message(&message);
+
+        let mut cur: Option<&(dyn std::error::Error + 'static)> = Some(err);
+        while let Some(e) = cur {
+            if let Some(ae) = e.downcast_ref::<ArchiverError>() {
+                return ErrorReport {
+                    code: ae.code().to_string(),
+                    category: ae.category(),
+                    message,
+                    path: ae.path().map(|p| p.display().to_string()),
+                    shard,
+                };
+            }
+            if let Some(io) = e.downcast_ref::<std::io::Error>() {
+                return ErrorReport {
+                    code: format!("io_{:?}", io.kind()).to_lowercase(),
+                    category: "io",
+                    message,
+                    path: None,
+                    shard,
+                };
+            }
+            cur = e.source();
+        }
+
+        let (code, category) = classify_message(&message);
+        ErrorReport { code: code.to_string(), category, message, path: None, shard }
+    }
+}
+
+/// Maps the string errors produced by the Katana reader/writer onto stable codes.
+fn classify_message(msg: &str) -> (&'static str, &'static str) {
+    const TABLE: &[(&str, &str, &str)] = &[
+        ("CRC mismatch in shard", "shard_crc_mismatch", "integrity"),
+        ("Index CRC mismatch", "index_crc_mismatch", "integrity"),
+        ("HMAC verification failed", "index_hmac_mismatch", "crypto"),
+        ("password required", "password_required", "crypto"),
+        ("no password was provided", "password_required", "crypto"),
+        ("decrypt failed", "decrypt_failed", "crypto"),
+        ("integrity check failed", "integrity_check_failed", "integrity"),
+        ("Footer", "footer_invalid", "format"),
+        ("Not a Katana archive", "not_an_archive", "format"),
+        ("Not a valid MicroFusion archive", "not_an_archive", "format"),
+        ("is not an archive BlitzArch can read", "not_an_archive", "format"),
+        ("BlitzArch reads Katana, classic BlitzArch and ZIP archives only", "unsupported_format", "format"),
+        ("File too small", "truncated_archive", "format"),
+        ("Unexpected EOF", "truncated_shard", "integrity"),
+        ("No input files", "no_input_files", "usage"),
+        ("is also listed as an input", "output_is_input", "usage"),
+        ("shards failed", "shard_failed", "internal"),
+    ];
+    TABLE
+        .iter()
+        .find(|(needle, _, _)| msg.contains(needle))
+        .map(|(_, code, cat)| (*code, *cat))
+        .unwrap_or(("other", "internal"))
+}
+
+fn shard_offset_from_message(msg: &str) -> Option<u64> {
+    let rest = &msg[msg.find("shard at offset ")? + "shard at offset ".len()..];
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_classifies_engine_messages() {
+        let err: Box<dyn std::error::Error> =
+            "CRC mismatch in shard at offset 4096 (expected 00000001, got 00000002)".into();
+        let report = ErrorReport::from_error(err.as_ref());
+        assert_eq!(report.code, "shard_crc_mismatch");
+        assert_eq!(report.category, "integrity");
+        assert_eq!(report.shard, Some(4096));
+    }
+
+    #[test]
+    fn report_uses_archiver_error_path() {
+        let err = ArchiverError::Io {
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "gone"),
+            path: PathBuf::from("/tmp/missing.bin"),
+        };
+        let report = ErrorReport::from_error(&err);
+        assert_eq!(report.code, "io");
+        assert_eq!(report.path.as_deref(), Some("/tmp/missing.bin"));
+    }
+}
@@ -19,6 +19,55 @@ use std::fs::Permissions;
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::error::Error;
+use crate::console::println;
+
+/// Per-job extraction settings, shared by every reader (Katana, classic, ZIP
+/// and plugin backends). `Default` is `extract` without flags; concurrent jobs
+/// (daemon, GUI, API) each pass their own.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtractOptions {
+    /// Restore archived modes, filtered by the umask (`--no-preserve-permissions`
+    /// clears it: files keep the mode they were created with).
+    pub preserve_permissions: bool,
+    /// Entries whose names differ only in case, on a case-insensitive output
+    /// directory (`--case-collisions`; Katana archives).
+    pub case_collisions: crate::katana::CaseCollisionPolicy,
+    /// Restore `--dedup` duplicates as hard links to their original instead of
+    /// copies (`--hard-links`; Katana archives).
+    pub hard_links: bool,
+    /// Restore only entries matching the stored metadata (`--only-executable`,
+    /// `--uid`; Katana archives).
+    pub filter: crate::katana::ExtractFilter,
+    /// Files already present at the target (`--skip-existing`,
+    /// `--rename-existing`, `--keep-newer`).
+    pub conflict_policy: crate::fsx::ConflictPolicy,
+    /// External zstd dictionaries the archive was created with (`--dictionary`;
+    /// Katana archives).
+    #[serde(skip)]
+    pub dictionaries: crate::dictionary::Dictionaries,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            preserve_permissions: true,
+            case_collisions: Default::default(),
+            hard_links: false,
+            filter: Default::default(),
+            conflict_policy: Default::default(),
+            dictionaries: Default::default(),
+        }
+    }
+}
+
+impl ExtractOptions {
+    /// Archived mode to restore on an extracted entry, `None` if it keeps the
+    /// mode it was created with.
+    pub(crate) fn mode(&self, archived: Option<u32>) -> Option<u32> {
+        archived.filter(|_| self.preserve_permissions)
+    }
+}
 
 /// A reader for `.blz` archives, responsible for parsing the header, footer, and index.
 pub struct ArchiveReader {
@@ -105,11 +154,26 @@ pub(crate) fn strip_path_components(path: &Path, components: u32) -> PathBuf {
     }
 }
 
+/// Restores the archived creation time of a classic entry written to `path`
+/// where the platform can set one (Windows, macOS); elsewhere it is dropped.
+/// Runs before the permissions are restored, which may make the file read-only.
+pub(crate) fn restore_created_time(path: &Path, created_time: Option<i64>) {
+    let Some(secs) = created_time.filter(|_| crate::fsx::CAN_SET_BTIME) else { return };
+    let restored = fs::OpenOptions::new().write(true).open(path).and_then(|f| crate::fsx::set_btime(&f, secs));
+    if let Err(e) = restored {
+        crate::warnings::warn(
+            crate::warnings::WarningKind::Metadata,
+            path.to_string_lossy(),
+            format!("Cannot restore creation time of {:?}: {}", path, e),
+        );
+    }
+}
+
 /// Lists the contents of an archive to standard output.
 ///
 /// # Arguments
 /// * `file` - The archive file to read.
-pub fn list_files(file: File) -> Result<(), Box<dyn Error>> {
+pub fn list_files(file: File, password: Option<&str>) -> Result<(), Box<dyn Error>> {
     // Проверяем, является ли файл Katana-архивом, для этого нам нужно сохранить файл
     // во временное место, т.к. is_katana_archive требует Path
     let tempdir = tempfile::tempdir()?;
@@ -124,14 +188,15 @@ pub fn list_files(file: File) -> Result<(), Box<dyn Error>> {
         file_copy.flush()?;
     }
     
-    // Проверяем, является ли файл Katana-архивом
-    if crate::katana::is_katana_archive(&temp_path)? {
-        // Если да, используем функцию list_katana_files
-        return crate::katana::list_katana_files(&temp_path, None);
+    match crate::formats::backend_for(&temp_path)? {
+        Some(backend) => backend.print_listing(&temp_path),
+        None => crate::katana::list_katana_files(&temp_path, password.map(str::to_string)),
     }
-    
-    // Если нет, обрабатываем как обычный архив
-    let file = File::open(&temp_path)?;
+}
+
+/// `blitzarch list` output of a classic archive.
+fn print_classic_listing(archive_path: &Path) -> Result<(), Box<dyn Error>> {
+    let file = File::open(archive_path)?;
     let mut reader = ArchiveReader::new(file)?;
     let index = reader.read_footer_and_index()?;
 
@@ -147,6 +212,89 @@ pub fn list_files(file: File) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Structured listing of any archive [`crate::formats::detect`] can read,
+/// straight from the index (no copy of the archive, no text to parse).
+///
+/// For Katana archives a password additionally verifies the index HMAC; see
+/// [`crate::katana::list_entries`]. Classic indexes and ZIP central directories
+/// are not encrypted, so the password is ignored there.
+pub fn list_entries(archive_path: &Path, password: Option<&str>) -> Result<Vec<crate::katana::EntryInfo>, Box<dyn Error>> {
+    match crate::formats::backend_for(archive_path)? {
+        Some(backend) => backend.list_entries(archive_path, password),
+        None => crate::katana::list_entries(archive_path, password),
+    }
+}
+
+fn list_classic_entries(archive_path: &Path) -> Result<Vec<crate::katana::EntryInfo>, Box<dyn Error>> {
+    let mut reader = ArchiveReader::new(File::open(archive_path)?)?;
+    let index = reader.read_footer_and_index()?;
+    Ok(index
+        .entries
+        .into_iter()
+        .map(|e| crate::katana::EntryInfo {
+            path: e.path.to_string_lossy().replace('\\', "/"),
+            size: e.uncompressed_size,
+            permissions: e.permissions,
+            mtime: None,
+            is_dir: e.is_dir,
+            shard_id: (!e.is_dir).then_some(e.bundle_id),
+            compressed_size: (!e.is_dir).then_some(e.stored_size),
+            symlink: None,
+            duplicate_of: None,
+        })
+        .collect())
+}
+
+/// Archive metadata (`--meta`, `--comment`) of a Katana or classic archive,
+/// or the comment of a ZIP; see [`crate::katana::read_metadata`].
+pub fn read_metadata(archive_path: &Path, password: Option<&str>) -> Result<std::collections::BTreeMap<String, String>, Box<dyn Error>> {
+    match crate::formats::backend_for(archive_path)? {
+        Some(backend) => backend.read_metadata(archive_path, password),
+        None => crate::katana::read_metadata(archive_path, password),
+    }
+}
+
+/// Classic (MFUS) archives as an [`crate::formats::ArchiveBackend`].
+pub(crate) struct ClassicBackend;
+
+impl crate::formats::ArchiveBackend for ClassicBackend {
+    fn name(&self) -> &'static str {
+        "classic BlitzArch"
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<bool> {
+        Ok(crate::formats::detect(path)? == crate::formats::ArchiveKind::Classic)
+    }
+
+    /// Classic indexes are not encrypted, the password is not needed.
+    fn list_entries(&self, path: &Path, _password: Option<&str>) -> Result<Vec<crate::katana::EntryInfo>, Box<dyn Error>> {
+        list_classic_entries(path)
+    }
+
+    fn read_metadata(&self, path: &Path, _password: Option<&str>) -> Result<std::collections::BTreeMap<String, String>, Box<dyn Error>> {
+        let mut reader = ArchiveReader::new(File::open(path)?)?;
+        Ok(reader.read_footer_and_index()?.metadata)
+    }
+
+    fn print_listing(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        print_classic_listing(path)
+    }
+
+    /// Classic bundles are decoded without progress reports.
+    fn extract(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        selected: &[PathBuf],
+        password: Option<&str>,
+        strip_components: Option<u32>,
+        _progress: Option<&dyn Fn(ProgressState)>,
+        options: &ExtractOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        extract_classic_files(path, selected, password, output_dir, strip_components, options)
+    }
+}
+
 // -----------------------------------------------------------------------------
 // Compatibility wrapper for CLI-runner until it is fully migrated
 // -----------------------------------------------------------------------------
@@ -160,19 +308,48 @@ pub fn katana_extract(
     strip_components: Option<u32>,
     password: Option<&str>,
     progress_callback: Option<Box<dyn Fn(ProgressState) + Send + Sync>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    katana_extract_with_options(
+        archive_path,
+        selected_files,
+        output_dir,
+        strip_components,
+        password,
+        progress_callback,
+        &ExtractOptions::default(),
+    )
+}
+
+/// [`katana_extract`] with the job's [`ExtractOptions`].
+#[allow(clippy::too_many_arguments)]
+pub fn katana_extract_with_options(
+    archive_path: &Path,
+    selected_files: &[PathBuf],
+    output_dir: &Option<PathBuf>,
+    strip_components: Option<u32>,
+    password: Option<&str>,
+    progress_callback: Option<Box<dyn Fn(ProgressState) + Send + Sync>>,
+    options: &ExtractOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let out_dir: &Path = match output_dir {
         Some(p) => p.as_path(),
         None => std::path::Path::new("."),
     };
 
-    crate::katana::extract_katana_archive_with_progress(
+    if let Some(backend) = crate::formats::backend_for(archive_path)? {
+        crate::formats::check_extract_options(backend.as_ref(), options)?;
+        let progress = progress_callback.as_ref().map(|cb| cb as &dyn Fn(ProgressState));
+        return backend.extract(archive_path, out_dir, selected_files, password, strip_components, progress, options);
+    }
+    crate::katana::extract_katana_archive_with_options(
         archive_path,
         out_dir,
         selected_files,
         password.map(|s| s.to_string()),
         strip_components,
         progress_callback,
+        None,
+        options,
     )
 }
 
@@ -194,21 +371,40 @@ pub fn extract_files(
     output_dir: Option<&Path>,
     strip_components: Option<u32>,
 ) -> Result<(), Box<dyn Error>> {
-        // Detect and delegate to Katana extractor if needed
-    if crate::katana::is_katana_archive(archive_path)? {
-        let base_output_path = match output_dir {
-            Some(p) => p.to_path_buf(),
-            None => std::env::current_dir()?,
-        };
-        return crate::katana::extract_katana_archive_internal(
+    let base_output_path = match output_dir {
+        Some(path) => path.to_path_buf(),
+        None => std::env::current_dir()?,
+    };
+    // Katana – свой путь распаковки, остальные форматы – через backend
+    match crate::formats::backend_for(archive_path)? {
+        None => crate::katana::extract_katana_archive_internal(
             archive_path,
             &base_output_path,
             files_to_extract,
             password.map(|s| s.to_string()),
             strip_components,
-        );
+        ),
+        Some(backend) => backend.extract(
+            archive_path,
+            &base_output_path,
+            files_to_extract,
+            password,
+            strip_components,
+            None,
+            &ExtractOptions::default(),
+        ),
     }
+}
 
+/// Classic (MFUS) extraction behind [`extract_files`].
+fn extract_classic_files(
+    archive_path: &Path,
+    files_to_extract: &[PathBuf],
+    password: Option<&str>,
+    base_output_path: &Path,
+    strip_components: Option<u32>,
+    options: &ExtractOptions,
+) -> Result<(), Box<dyn Error>> {
     let file = File::open(archive_path)?;
     let mut reader = ArchiveReader::new(file)?;
     let index = reader.read_footer_and_index()?;
@@ -223,14 +419,11 @@ pub fn extract_files(
         _ => None,
     };
 
-    let base_output_path = match output_dir {
-        Some(path) => path.to_path_buf(),
-        None => std::env::current_dir()?,
-    };
+    let base_output_path = base_output_path.to_path_buf();
     fs::create_dir_all(&base_output_path)?;
 
-    let files_to_extract_set: HashSet<_> = files_to_extract.iter().collect();
-    let all_files = files_to_extract.is_empty();
+    let selector = crate::katana::EntrySelector::new(files_to_extract)?;
+    let all_files = selector.is_empty();
 
     for entry in &index.entries {
         if entry.is_dir {
@@ -243,8 +436,8 @@ pub fn extract_files(
             #[cfg(unix)]
             {
                 use std::os::unix::fs::PermissionsExt;
-                if let Some(mode) = entry.permissions {
-                    crate::fsx::set_unix_permissions(&target_path, mode)?;
+                if let Some(mode) = options.mode(entry.permissions) {
+                    crate::fsx::restore_permissions(&target_path, mode)?;
                 }
             }
         }
@@ -252,7 +445,7 @@ pub fn extract_files(
 
     let mut files_by_bundle: HashMap<u32, Vec<_>> = HashMap::new();
     for entry in &index.entries {
-        if !entry.is_dir && (all_files || files_to_extract_set.contains(&entry.path)) {
+        if !entry.is_dir && (all_files || selector.matches(&crate::katana::normalize_path(&entry.path.to_string_lossy()))) {
             files_by_bundle
                 .entry(entry.bundle_id)
                 .or_insert_with(Vec::new)
@@ -293,6 +486,7 @@ pub fn extract_files(
                     &index,
                     &base_output_path,
                     strip_components,
+                    options,
                 )?;
             }
             return Ok(());
@@ -313,6 +507,7 @@ pub fn extract_files(
                 &index_arc,
                 &base_out,
                 strip_components,
+                options,
             )
         })?;
 
@@ -327,6 +522,7 @@ pub fn extract_files(
         base_output_path: &Path,
         algo: &str,
         strip_components: Option<u32>,
+        options: &ExtractOptions,
     ) -> io::Result<()> {
         for file_entry in files {
             let stripped_path = strip_components.map_or_else(
@@ -338,7 +534,9 @@ pub fn extract_files(
                 fs::create_dir_all(parent)?;
             }
 
-            let mut output_file = File::create(&target_path)?;
+            // Skipped entries are still decoded (the stream is sequential) into a sink
+            let claimed = crate::fsx::claim_output(&target_path, None, options.conflict_policy)?;
+            let mut output_file: Box<dyn Write> = if claimed { Box::new(File::create(&target_path)?) } else { Box::new(io::sink()) };
 
             if algo == "store" {
                 // For 'store' mode, we read the size prefix for each file.
@@ -364,12 +562,15 @@ pub fn extract_files(
                 // Now copy exactly `uncompressed_size` bytes of real file data.
                 io::copy(&mut decoder.take(file_entry.uncompressed_size), &mut output_file)?;
             }
+            if claimed {
+                restore_created_time(&target_path, file_entry.created_time);
+            }
 
             #[cfg(unix)]
             {
                 use std::os::unix::fs::PermissionsExt;
-                if let Some(mode) = file_entry.permissions {
-                    crate::fsx::set_unix_permissions(&target_path, mode)?;
+                if let Some(mode) = options.mode(file_entry.permissions).filter(|_| claimed) {
+                    crate::fsx::restore_permissions(&target_path, mode)?;
                 }
             }
         }
@@ -392,6 +593,7 @@ pub fn extract_files(
             let mut decoder: Box<dyn Read> = match bundle_info.algo.as_str() {
                 "store" => Box::new(compressed_data_reader),
                 "lzma2" => Box::new(xz2::read::XzDecoder::new(compressed_data_reader)),
+                "brotli" => Box::new(crate::compress::BrotliBundleReader::for_entries(compressed_data_reader, files)),
                  _ => { // zstd
                      if let Some(dict) = &index.dictionary {
                          Box::new(zstd::stream::Decoder::with_dictionary(compressed_data_reader, dict)?)
@@ -400,7 +602,7 @@ pub fn extract_files(
                      }
                  }
              };
-            extract_from_decoder(&mut decoder, &files, &base_output_path, &bundle_info.algo, strip_components)?;
+            extract_from_decoder(&mut decoder, &files, &base_output_path, &bundle_info.algo, strip_components, options)?;
         } else if salt.is_some() {
             return Err("Inconsistent encryption metadata: archive is encrypted, but bundle is not.".into());
         } else {
@@ -410,6 +612,7 @@ pub fn extract_files(
             let mut decoder: Box<dyn Read> = match bundle_info.algo.as_str() {
                  "store" => Box::new(buffered_reader),
                 "lzma2" => Box::new(xz2::read::XzDecoder::new(buffered_reader)),
+                "brotli" => Box::new(crate::compress::BrotliBundleReader::for_entries(buffered_reader, files)),
                  _ => {
                      if let Some(dict) = &index.dictionary {
                          Box::new(zstd::stream::Decoder::with_dictionary(buffered_reader, dict)?)
@@ -418,7 +621,7 @@ pub fn extract_files(
                      }
                  }
              };
-            extract_from_decoder(&mut decoder, &files, &base_output_path, &bundle_info.algo, strip_components)?;
+            extract_from_decoder(&mut decoder, &files, &base_output_path, &bundle_info.algo, strip_components, options)?;
         }
     }
 
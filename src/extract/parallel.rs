@@ -31,13 +31,16 @@ use std::io::BufReader;
 use std::path::{Path};
 
 use crate::archive::{ArchiveIndex, FileIndexEntry};
+use super::ExtractOptions;
 
 /// Temporary re-export of helper until we refactor it out of `mod.rs`.
 fn extract_from_decoder(
     decoder: &mut dyn Read,
     files: &[FileIndexEntry],
     base_output_path: &Path,
+    algo: &str,
     strip_components: Option<u32>,
+    options: &ExtractOptions,
 ) -> io::Result<()> {
     // SAFETY: This duplicates the helper from `extract::mod` for now.
     let mut current_offset_in_bundle = 0;
@@ -51,8 +54,9 @@ fn extract_from_decoder(
             fs::create_dir_all(parent)?;
         }
 
+        // BrotliBundleReader already dropped the unselected streams between entries
         let bytes_to_skip = file_entry.offset_in_bundle - current_offset_in_bundle;
-        if bytes_to_skip > 0 {
+        if bytes_to_skip > 0 && algo != "brotli" {
             io::copy(&mut decoder.take(bytes_to_skip), &mut io::sink())?;
         }
 
@@ -62,6 +66,10 @@ fn extract_from_decoder(
             file_entry.stored_size
         };
 
+        if !crate::fsx::claim_output(&target_path, None, options.conflict_policy)? {
+            current_offset_in_bundle = file_entry.offset_in_bundle;
+            continue;
+        }
         let mut output_file = File::create(&target_path)?;
         // --- Read preprocessing sentinel + optional meta block ---
         let mut len_buf = [0u8; 4];
@@ -75,13 +83,14 @@ fn extract_from_decoder(
         }
         // Copy exact uncompressed file bytes
         io::copy(&mut decoder.take(file_entry.uncompressed_size), &mut output_file)?;
+        super::restore_created_time(&target_path, file_entry.created_time);
 
         #[cfg(unix)]
         {
             #[cfg(unix)]
             use std::os::unix::fs::PermissionsExt;
-            if let Some(mode) = file_entry.permissions {
-                crate::fsx::set_unix_permissions(&target_path, mode)?;
+            if let Some(mode) = options.mode(file_entry.permissions) {
+                crate::fsx::restore_permissions(&target_path, mode)?;
             }
         }
 
@@ -111,10 +120,11 @@ pub fn extract_bundle_sequential(
     index: &ArchiveIndex,
     base_output_path: &Path,
     strip_components: Option<u32>,
+    options: &ExtractOptions,
 ) -> io::Result<()> {
     // --- Zero-copy fast path for plain Store bundles ---
     if bundle_info.algo == "store" {
-        return extract_store_bundle_zero_copy(archive_path, bundle_info, files, base_output_path, strip_components);
+        return extract_store_bundle_zero_copy(archive_path, bundle_info, files, base_output_path, strip_components, options);
     }
     use std::io::BufReader;
 
@@ -127,7 +137,7 @@ pub fn extract_bundle_sequential(
     const PARALLEL_THRESHOLD: u64 = 32 * 1024 * 1024; // 32 MiB
     if bundle_info.algo == "zstd" && bundle_info.compressed_size > PARALLEL_THRESHOLD {
         if let Ok(decoded_vec) = block_pipeline::decode_bundle_parallel_blocks(&mut buffered_reader) {
-            writer_pool::flush_files(&decoded_vec, files, base_output_path)?;
+            writer_pool::flush_files(&decoded_vec, files, base_output_path, options)?;
             return Ok(());
         }
         // On any error fall back to sequential decoder below.
@@ -136,6 +146,7 @@ pub fn extract_bundle_sequential(
     let mut decoder: Box<dyn Read + Send> = match bundle_info.algo.as_str() {
         "store" => Box::new(buffered_reader),
         "lzma2" => Box::new(xz2::read::XzDecoder::new(buffered_reader)),
+        "brotli" => Box::new(crate::compress::BrotliBundleReader::for_entries(buffered_reader, files)),
         _ => {
             if let Some(dict) = &index.dictionary {
                 Box::new(zstd::stream::Decoder::with_dictionary(buffered_reader, dict)?)
@@ -145,7 +156,7 @@ pub fn extract_bundle_sequential(
         }
     };
 
-    extract_from_decoder(&mut decoder, files, base_output_path, strip_components)
+    extract_from_decoder(&mut decoder, files, base_output_path, &bundle_info.algo, strip_components, options)
 }
 
 use rayon::prelude::*;
@@ -160,6 +171,7 @@ fn extract_store_bundle_zero_copy(
     files: &[FileIndexEntry],
     base_output_path: &Path,
     strip_components: Option<u32>,
+    options: &ExtractOptions,
 ) -> io::Result<()> {
     use std::io::{Read, Seek};
     let mut archive = File::open(archive_path)?;
@@ -186,13 +198,17 @@ fn extract_store_bundle_zero_copy(
         if let Some(parent) = target_path.parent() {
             fs::create_dir_all(parent)?;
         }
+        if !crate::fsx::claim_output(&target_path, None, options.conflict_policy)? {
+            continue;
+        }
         let mut out = File::create(&target_path)?;
 
         let mut limited_reader = (&mut archive).take(bytes_to_copy);
         io::copy(&mut limited_reader, &mut out)?;
+        super::restore_created_time(&target_path, entry.created_time);
 
-        if let Some(mode) = entry.permissions {
-            crate::fsx::set_unix_permissions(&target_path, mode)?;
+        if let Some(mode) = options.mode(entry.permissions) {
+            crate::fsx::restore_permissions(&target_path, mode)?;
         }
     }
     Ok(())
@@ -208,17 +224,18 @@ pub fn extract_bundle_parallel(
     index: &ArchiveIndex,
     base_output_path: &Path,
     strip_components: Option<u32>,
+    options: &ExtractOptions,
 ) -> io::Result<()> {
     // Fast-path: если весь бандл записан в режиме `Store`, параллельная обработка даёт
     // лишь накладные расходы. Используем проверенный последовательный путь.
     if bundle_info.algo == "store" {
-        return extract_bundle_sequential(archive_path, bundle_info, files, index, base_output_path, strip_components);
+        return extract_bundle_sequential(archive_path, bundle_info, files, index, base_output_path, strip_components, options);
     }
 
     // Для очень больших бандлов (>2 ГБ) тоже лучше остаться на последовательном пути —
     // объём I/O сопоставим, но избегаем лишних seek'ов.
     if bundle_info.compressed_size > 2 * 1024 * 1024 * 1024 {
-        return extract_bundle_sequential(archive_path, bundle_info, files, index, base_output_path, strip_components);
+        return extract_bundle_sequential(archive_path, bundle_info, files, index, base_output_path, strip_components, options);
     }
 
     // 1. Read compressed bundle into memory.
@@ -245,6 +262,8 @@ pub fn extract_bundle_parallel(
         let mut decoder: Box<dyn Read> = match bundle_info.algo.as_str() {
             "store" => Box::new(buffered),
             "lzma2" => Box::new(xz2::read::XzDecoder::new(buffered)),
+            // Свой поток у каждого файла
+            "brotli" => Box::new(brotli::Decompressor::new(buffered, 64 * 1024)),
             _ => {
                 if let Some(dict) = &index.dictionary {
                     Box::new(zstd::stream::Decoder::with_dictionary(buffered, dict)?)
@@ -259,6 +278,9 @@ pub fn extract_bundle_parallel(
         if let Some(parent) = target_path.parent() {
             fs::create_dir_all(parent)?;
         }
+        if !crate::fsx::claim_output(&target_path, None, options.conflict_policy)? {
+            return Ok(());
+        }
         let mut out = File::create(&target_path)?;
 
         // Читаем префикс метаданных.
@@ -274,9 +296,10 @@ pub fn extract_bundle_parallel(
         }
             io::copy(&mut decoder, &mut out)?;
         }
+        super::restore_created_time(&target_path, entry.created_time);
 
-        if let Some(mode) = entry.permissions {
-            crate::fsx::set_unix_permissions(&target_path, mode)?;
+        if let Some(mode) = options.mode(entry.permissions) {
+            crate::fsx::restore_permissions(&target_path, mode)?;
         }
         Ok(())
     })?;
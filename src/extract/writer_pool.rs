@@ -18,6 +18,7 @@ use std::fs::Permissions;
 use std::path::{Path, PathBuf};
 
 use crate::archive::FileIndexEntry;
+use super::ExtractOptions;
 
 // Defaults; may be overridden per bundle using heuristics
 const DEFAULT_BATCH_FILE_LIMIT: usize = 8;
@@ -33,7 +34,7 @@ const DEFAULT_BATCH_SIZE_LIMIT: usize = 512 * 1024; // 512 KiB
 const MIN_FILE_THRESHOLD: usize = 1000;
 const MAX_AVG_SIZE: usize = 256 * 1024; // 256 KiB
 
-pub fn flush_files(decoded: &[u8], files: &[FileIndexEntry], base: &Path) -> io::Result<()> {
+pub fn flush_files(decoded: &[u8], files: &[FileIndexEntry], base: &Path, options: &ExtractOptions) -> io::Result<()> {
     // Decide early whether to use batching.
     let avg_size = if files.is_empty() { 0 } else { decoded.len() / files.len() };
     let use_batch = files.len() >= MIN_FILE_THRESHOLD && avg_size <= MAX_AVG_SIZE;
@@ -55,13 +56,14 @@ pub fn flush_files(decoded: &[u8], files: &[FileIndexEntry], base: &Path) -> io:
                 #[cfg(unix)]
                 {
                     use std::os::unix::fs::PermissionsExt;
-                    if let Some(mode) = entry.permissions {
-                        crate::fsx::set_unix_permissions(&target_path, mode)?;
+                    if let Some(mode) = options.mode(entry.permissions) {
+                        crate::fsx::restore_permissions(&target_path, mode)?;
                     }
                 }
-            } else {
+            } else if crate::fsx::claim_output(&target_path, None, options.conflict_policy)? {
                 let mut f = File::create(&target_path)?;
                 f.write_all(slice)?;
+                super::restore_created_time(&target_path, entry.created_time);
             }
         }
         return Ok(());
@@ -77,7 +79,7 @@ pub fn flush_files(decoded: &[u8], files: &[FileIndexEntry], base: &Path) -> io:
     let batch_file_limit = DEFAULT_BATCH_FILE_LIMIT;
 
     // Internal staging buffer.
-    let mut batch_items: Vec<(PathBuf, usize /*size*/)> = Vec::with_capacity(batch_file_limit);
+    let mut batch_items: Vec<(PathBuf, usize /*size*/, Option<i64> /*created_time*/)> = Vec::with_capacity(batch_file_limit);
     let mut batch_buf = Vec::<u8>::with_capacity(batch_size_limit);
 
     for entry in files {
@@ -96,13 +98,17 @@ pub fn flush_files(decoded: &[u8], files: &[FileIndexEntry], base: &Path) -> io:
             #[cfg(unix)]
             {
                 use std::os::unix::fs::PermissionsExt;
-                if let Some(mode) = entry.permissions {
-                    crate::fsx::set_unix_permissions(&target_path, mode)?;
+                if let Some(mode) = options.mode(entry.permissions) {
+                    crate::fsx::restore_permissions(&target_path, mode)?;
                 }
             }
             continue;
         }
 
+        if !crate::fsx::claim_output(&target_path, None, options.conflict_policy)? {
+            continue;
+        }
+
         // Flush if limits exceeded
         if batch_items.len() >= batch_file_limit || batch_buf.len() + size > batch_size_limit {
             flush_batch(&batch_items, &batch_buf)?;
@@ -110,7 +116,7 @@ pub fn flush_files(decoded: &[u8], files: &[FileIndexEntry], base: &Path) -> io:
             batch_buf.clear();
         }
 
-        batch_items.push((target_path, size));
+        batch_items.push((target_path, size, entry.created_time));
         batch_buf.extend_from_slice(slice);
     }
 
@@ -122,11 +128,12 @@ pub fn flush_files(decoded: &[u8], files: &[FileIndexEntry], base: &Path) -> io:
 }
 
 /// Helper: flush prepared batch buffer to individual files.
-fn flush_batch(items: &[(PathBuf, usize)], buf: &[u8]) -> io::Result<()> {
+fn flush_batch(items: &[(PathBuf, usize, Option<i64>)], buf: &[u8]) -> io::Result<()> {
     let mut offset = 0usize;
-    for (path, size) in items {
+    for (path, size, created_time) in items {
         let mut f = File::create(path)?;
         f.write_all(&buf[offset..offset + *size])?;
+        super::restore_created_time(path, *created_time);
         offset += *size;
     }
     Ok(())
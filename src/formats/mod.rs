@@ -0,0 +1,203 @@
+//! Archive format detection and readers for formats other than Katana.
+//!
+//! Detection is by signature, never by extension: [`detect`] tells what a file
+//! is, [`backend_for`] picks the reader `extract` and `list` use. Katana
+//! archives keep their native (parallel, resumable) path; classic BlitzArch
+//! and ZIP are built-in backends, and other crates can plug in more formats
+//! with [`register_backend`]. 7z and RAR are recognised so users get a clear
+//! "not supported" instead of "Not a Katana archive".
+
+// Read-only ZIP (`extract`, `list`)
+pub mod zip;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::extract::ExtractOptions;
+use crate::katana::EntryInfo;
+use crate::progress::ProgressState;
+use crate::console::println;
+
+/// What [`detect`] found in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    /// Katana archive (`.blz` written by `create`).
+    Katana,
+    /// Classic BlitzArch (MFUS) archive (`create --format classic`).
+    Classic,
+    Zip,
+    /// 7-Zip; recognised, not readable without a registered backend.
+    SevenZip,
+    /// RAR 4 or 5; recognised, not readable without a registered backend.
+    Rar,
+    /// Claimed by a backend registered with [`register_backend`].
+    Plugin(&'static str),
+    /// No known signature.
+    Unknown,
+}
+
+impl ArchiveKind {
+    /// Format name for messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveKind::Katana => "Katana",
+            ArchiveKind::Classic => "classic BlitzArch",
+            ArchiveKind::Zip => "ZIP",
+            ArchiveKind::SevenZip => "7z",
+            ArchiveKind::Rar => "RAR",
+            ArchiveKind::Plugin(name) => name,
+            ArchiveKind::Unknown => "unknown",
+        }
+    }
+}
+
+/// Optional [`ExtractOptions`] an [`ArchiveBackend`] honours; asking a backend
+/// for one it lacks fails in [`check_extract_options`] instead of being ignored.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BackendCapabilities {
+    /// Selection by stored metadata ([`ExtractOptions::filter`]).
+    pub filter: bool,
+}
+
+/// Reader for one archive format, used by `extract` and `list`.
+///
+/// Implementations must keep extracted entries inside `output_dir` (see how
+/// [`zip`] does it) and honour `selected` (entry paths or globs; all entries
+/// if empty), `strip_components` and the job's [`ExtractOptions`] like the
+/// Katana reader does.
+pub trait ArchiveBackend: Send + Sync {
+    /// Short format name for messages, e.g. `"7z"`.
+    fn name(&self) -> &'static str;
+
+    /// Whether `path` is in this format. Called for every file that is not a
+    /// BlitzArch archive, so it should only look at a signature.
+    fn probe(&self, path: &Path) -> io::Result<bool>;
+
+    /// Entries in archive order.
+    fn list_entries(&self, path: &Path, password: Option<&str>) -> Result<Vec<EntryInfo>, Box<dyn Error>>;
+
+    /// Key/value metadata of the archive (e.g. its comment).
+    fn read_metadata(&self, path: &Path, password: Option<&str>) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+        let _ = (path, password);
+        Ok(BTreeMap::new())
+    }
+
+    /// Optional extraction features this reader supports (none by default).
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::default()
+    }
+
+    /// Human-readable listing for `blitzarch list`.
+    fn print_listing(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let entries = self.list_entries(path, None)?;
+        println!("{} archive ({} entries):", self.name(), entries.len());
+        for entry in entries {
+            if entry.is_dir {
+                println!("- {}/", entry.path);
+            } else {
+                println!("- {} ({} bytes)", entry.path, entry.size);
+            }
+        }
+        Ok(())
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        selected: &[PathBuf],
+        password: Option<&str>,
+        strip_components: Option<u32>,
+        progress: Option<&dyn Fn(ProgressState)>,
+        options: &ExtractOptions,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// Checks the job's [`ExtractOptions`] against the capabilities of `backend`.
+///
+/// Fails with a message naming the offending flag instead of silently
+/// extracting everything.
+pub fn check_extract_options(backend: &dyn ArchiveBackend, options: &ExtractOptions) -> Result<(), Box<dyn Error>> {
+    let caps = backend.capabilities();
+    type Supported = fn(&BackendCapabilities) -> bool;
+    let requested: [(&str, bool, Supported); 2] = [
+        ("--only-executable", options.filter.only_executable, |c| c.filter),
+        ("--uid", options.filter.uid.is_some(), |c| c.filter),
+    ];
+    for (flag, used, supported) in requested {
+        if used && !supported(&caps) {
+            return Err(format!("{} archives do not support {} (supported by: Katana)", backend.name(), flag).into());
+        }
+    }
+    Ok(())
+}
+
+static BACKENDS: RwLock<Vec<Arc<dyn ArchiveBackend>>> = RwLock::new(Vec::new());
+
+/// Adds a reader for another format. Registered backends are probed in
+/// registration order after the built-in formats, but before the 7z / RAR
+/// signatures, so a plugin can take those over.
+pub fn register_backend(backend: impl ArchiveBackend + 'static) {
+    BACKENDS.write().unwrap_or_else(|e| e.into_inner()).push(Arc::new(backend));
+}
+
+const SEVEN_ZIP_MAGIC: &[u8] = b"7z\xBC\xAF\x27\x1C";
+const RAR_MAGIC: &[u8] = b"Rar!\x1A\x07"; // RAR 4 (…\x00) и RAR 5 (…\x01\x00)
+
+fn starts_with(path: &Path, magic: &[u8]) -> io::Result<bool> {
+    let mut head = Vec::with_capacity(magic.len());
+    File::open(path)?.take(magic.len() as u64).read_to_end(&mut head)?;
+    Ok(head == magic)
+}
+
+/// Identifies the format of `path` from its signature.
+pub fn detect(path: &Path) -> io::Result<ArchiveKind> {
+    if crate::katana::is_katana_archive(path)? {
+        return Ok(ArchiveKind::Katana);
+    }
+    if starts_with(path, crate::archive::MAGIC_BYTES)? {
+        return Ok(ArchiveKind::Classic);
+    }
+    if zip::is_zip_archive(path)? {
+        return Ok(ArchiveKind::Zip);
+    }
+    for backend in BACKENDS.read().unwrap_or_else(|e| e.into_inner()).iter() {
+        if backend.probe(path)? {
+            return Ok(ArchiveKind::Plugin(backend.name()));
+        }
+    }
+    if starts_with(path, SEVEN_ZIP_MAGIC)? {
+        return Ok(ArchiveKind::SevenZip);
+    }
+    if starts_with(path, RAR_MAGIC)? {
+        return Ok(ArchiveKind::Rar);
+    }
+    Ok(ArchiveKind::Unknown)
+}
+
+/// Reader for a non-Katana archive; `None` for Katana archives, which have
+/// their own extraction path. Formats without a reader fail with a message
+/// naming the detected format.
+pub fn backend_for(path: &Path) -> Result<Option<Arc<dyn ArchiveBackend>>, Box<dyn Error>> {
+    let kind = detect(path)?;
+    match kind {
+        ArchiveKind::Katana => Ok(None),
+        ArchiveKind::Classic => Ok(Some(Arc::new(crate::extract::ClassicBackend))),
+        ArchiveKind::Zip => Ok(Some(Arc::new(zip::ZipBackend))),
+        ArchiveKind::Plugin(name) => {
+            let backends = BACKENDS.read().unwrap_or_else(|e| e.into_inner());
+            Ok(backends.iter().find(|b| b.name() == name).cloned())
+        }
+        ArchiveKind::SevenZip | ArchiveKind::Rar => Err(format!(
+            "{:?} is a {} archive; BlitzArch reads Katana, classic BlitzArch and ZIP archives only",
+            path,
+            kind.name()
+        )
+        .into()),
+        ArchiveKind::Unknown => Err(format!("{:?} is not an archive BlitzArch can read (no known format signature)", path).into()),
+    }
+}
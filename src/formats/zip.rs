@@ -0,0 +1,269 @@
+//! Read-only ZIP support for `extract` and `list`.
+//!
+//! Entries go through the same path checks as Katana entries (`..`, absolute
+//! paths, symlinked directories, symlink targets outside the output). The
+//! case collision policy does not apply here, and `--only-executable` / `--uid`
+//! are rejected (see [`super::check_extract_options`]). Writing ZIP archives is
+//! not supported.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use ::zip::read::ZipFile;
+use ::zip::result::ZipError;
+use ::zip::ZipArchive;
+
+use super::ArchiveBackend;
+use crate::extract::ExtractOptions;
+use crate::katana::{EntryInfo, EntrySelector};
+use crate::progress::ProgressState;
+use crate::warnings::{warn, WarningKind};
+use crate::console::println;
+
+/// Local file header, end of central directory (empty archive) and the
+/// spanned-archive marker some tools put first.
+const ZIP_MAGICS: [[u8; 4]; 3] = [*b"PK\x03\x04", *b"PK\x05\x06", *b"PK\x07\x08"];
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Checks the first bytes of `path` for a ZIP signature.
+pub fn is_zip_archive(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; 4];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(ZIP_MAGICS.contains(&magic)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn open(path: &Path) -> Result<ZipArchive<io::BufReader<File>>, Box<dyn Error>> {
+    ZipArchive::new(io::BufReader::new(File::open(path)?)).map_err(|e| format!("Cannot read ZIP archive {:?}: {}", path, e).into())
+}
+
+/// Seconds since the Unix epoch of the DOS timestamp (ZIP stores local time
+/// without a zone; it is taken as UTC).
+fn entry_mtime(file: &ZipFile) -> Option<i64> {
+    let t = file.last_modified();
+    let date = chrono::NaiveDate::from_ymd_opt(t.year().into(), t.month().into(), t.day().into())?;
+    Some(date.and_hms_opt(t.hour().into(), t.minute().into(), t.second().into())?.and_utc().timestamp())
+}
+
+fn is_symlink(file: &ZipFile) -> bool {
+    file.unix_mode().is_some_and(|mode| mode & S_IFMT == S_IFLNK)
+}
+
+/// Entries of a ZIP archive in central directory order. Names and sizes are
+/// not encrypted in ZIP, so no password is needed.
+pub fn list_entries(archive_path: &Path) -> Result<Vec<EntryInfo>, Box<dyn Error>> {
+    let mut archive = open(archive_path)?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let mut entry = EntryInfo {
+            path: crate::katana::normalize_path(file.name()).trim_end_matches('/').to_string(),
+            size: file.size(),
+            permissions: file.unix_mode().map(|m| m & 0o7777),
+            mtime: entry_mtime(&file),
+            is_dir: file.is_dir(),
+            shard_id: None,
+            compressed_size: (!file.is_dir()).then_some(file.compressed_size()),
+            symlink: None,
+            duplicate_of: None,
+        };
+        if is_symlink(&file) {
+            drop(file);
+            // Цель ссылки – содержимое записи; у зашифрованной записи без пароля остаётся пустой
+            let mut target = String::new();
+            if let Ok(mut link) = archive.by_index(i) {
+                link.read_to_string(&mut target)?;
+            }
+            entry.symlink = Some(target);
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Archive comment as metadata (`comment`), like `create --comment` of Katana archives.
+pub fn read_metadata(archive_path: &Path) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let archive = open(archive_path)?;
+    let mut metadata = BTreeMap::new();
+    let comment = String::from_utf8_lossy(archive.comment()).trim().to_string();
+    if !comment.is_empty() {
+        metadata.insert("comment".to_string(), comment);
+    }
+    Ok(metadata)
+}
+
+/// Built-in [`ArchiveBackend`] over the functions of this module.
+pub struct ZipBackend;
+
+impl ArchiveBackend for ZipBackend {
+    fn name(&self) -> &'static str {
+        "ZIP"
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<bool> {
+        is_zip_archive(path)
+    }
+
+    fn list_entries(&self, path: &Path, _password: Option<&str>) -> Result<Vec<EntryInfo>, Box<dyn Error>> {
+        list_entries(path)
+    }
+
+    fn read_metadata(&self, path: &Path, _password: Option<&str>) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+        read_metadata(path)
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        selected: &[PathBuf],
+        password: Option<&str>,
+        strip_components: Option<u32>,
+        progress: Option<&dyn Fn(ProgressState)>,
+        options: &ExtractOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        extract_zip_archive_with_progress(path, output_dir, selected, password, strip_components, progress, options)
+    }
+}
+
+/// Extracts a ZIP archive; see [`extract_zip_archive_with_progress`].
+pub fn extract_zip_archive(
+    archive_path: &Path,
+    output_dir: &Path,
+    selected_files: &[PathBuf],
+    password: Option<&str>,
+    strip_components: Option<u32>,
+) -> Result<(), Box<dyn Error>> {
+    extract_zip_archive_with_progress(
+        archive_path,
+        output_dir,
+        selected_files,
+        password,
+        strip_components,
+        None::<fn(ProgressState)>,
+        &ExtractOptions::default(),
+    )
+}
+
+/// Extracts `selected_files` (all entries if empty; globs allowed as for
+/// Katana archives) below `output_dir`. Deflate, bzip2, zstd and stored
+/// entries are supported, as are ZipCrypto and AES encrypted ones.
+pub fn extract_zip_archive_with_progress<F>(
+    archive_path: &Path,
+    output_dir: &Path,
+    selected_files: &[PathBuf],
+    password: Option<&str>,
+    strip_components: Option<u32>,
+    progress_callback: Option<F>,
+    options: &ExtractOptions,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(ProgressState),
+{
+    let started = Instant::now();
+    let mut archive = open(archive_path)?;
+    let selector = EntrySelector::new(selected_files)?;
+    fs::create_dir_all(output_dir)?;
+
+    // Выбранные записи и их объём – для прогресса
+    let mut wanted = Vec::new();
+    let mut total_bytes = 0u64;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let path = crate::katana::normalize_path(file.name());
+        if selector.is_empty() || selector.matches(path.trim_end_matches('/')) {
+            total_bytes += file.size();
+            wanted.push(i);
+        }
+    }
+    println!("[zip] Extracting {} entries from {:?}", wanted.len(), archive_path);
+
+    let mut state = ProgressState {
+        total_files: wanted.len() as u64,
+        processed_files: 0,
+        total_bytes,
+        processed_bytes: 0,
+        completed_shards: 0,
+        total_shards: 1,
+        elapsed_time: started.elapsed(),
+        speed_mbps: 0.0,
+        progress_percent: 0.0,
+        compression_level: None,
+    };
+    let mut symlinks = crate::katana::PendingSymlinks::default();
+    for i in wanted {
+        let mut file = match password {
+            Some(pass) => archive.by_index_decrypt(i, pass.as_bytes())?.map_err(|_| "Wrong password for ZIP archive")?,
+            None => archive.by_index(i).map_err(|e| match e {
+                ZipError::UnsupportedArchive(msg) if msg == ZipError::PASSWORD_REQUIRED => "ZIP archive is encrypted, but no password was provided.".into(),
+                e => Box::<dyn Error>::from(e),
+            })?,
+        };
+        let name = file.name().to_string();
+        if file.enclosed_name().is_none() {
+            warn(WarningKind::SkippedFile, &name, format!("Skipping unsafe ZIP entry {:?}", name));
+            continue;
+        }
+        let rel = crate::katana::output_rel_path(crate::katana::normalize_path(&name).trim_end_matches('/'), strip_components);
+        if rel.is_empty() {
+            continue; // stripped away entirely
+        }
+        let Some(out_path) = crate::katana::checked_output_path(output_dir, &rel) else {
+            continue;
+        };
+
+        if file.is_dir() {
+            fs::create_dir_all(&out_path)?;
+        } else if is_symlink(&file) {
+            // Created after all files, see `PendingSymlinks`
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            symlinks.push(out_path, target, entry_mtime(&file));
+        } else if !crate::fsx::claim_output(&out_path, entry_mtime(&file), options.conflict_policy)? {
+            // --skip-existing / --keep-newer
+        } else if out_path.symlink_metadata().is_ok_and(|meta| meta.is_dir() || meta.file_type().is_symlink()) {
+            warn(WarningKind::SkippedFile, &name, format!("Skipping file that conflicts with existing directory: {:?}", out_path));
+        } else {
+            if let Some(parent) = out_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut out = File::create(&out_path)?;
+            // CRC записи проверяет сам zip-ридер в конце потока
+            io::copy(&mut file, &mut out).map_err(|e| format!("Cannot extract {}: {}", name, e))?;
+            if let Some(mtime) = entry_mtime(&file) {
+                crate::fsx::set_mtime(&out, mtime)?;
+            }
+            if let Some(mode) = options.mode(file.unix_mode()) {
+                if let Err(e) = crate::fsx::restore_permissions(&out_path, mode) {
+                    warn(WarningKind::PermissionFailure, &name, format!("Cannot restore permissions on {:?}: {}", out_path, e));
+                }
+            }
+        }
+
+        state.processed_files += 1;
+        state.processed_bytes += file.size();
+        if let Some(callback) = progress_callback.as_ref() {
+            state.elapsed_time = started.elapsed();
+            let secs = state.elapsed_time.as_secs_f64();
+            state.speed_mbps = if secs > 0.0 { (state.processed_bytes as f64 / (1024.0 * 1024.0) / secs) as f32 } else { 0.0 };
+            state.progress_percent = if total_bytes > 0 { state.processed_bytes as f32 * 100.0 / total_bytes as f32 } else { 100.0 };
+            callback(state.clone());
+        }
+    }
+    symlinks.restore(output_dir, options.conflict_policy)?;
+    if let Some(callback) = progress_callback.as_ref() {
+        state.completed_shards = 1;
+        state.progress_percent = 100.0;
+        state.elapsed_time = started.elapsed();
+        callback(state);
+    }
+    Ok(())
+}
@@ -8,7 +8,10 @@
 // * On Windows (and other non-Unix platforms), these helpers are no-ops.
 
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::vfs::SymlinkMode;
+use crate::console::{eprintln, println};
 
 // We DO NOT re-export std::fs directly to avoid conflicts and cross-platform issues
 // Instead, callers should explicitly import std::fs::File, etc.
@@ -34,6 +37,21 @@ pub fn maybe_unix_mode(meta: &std::fs::Metadata) -> Option<u32> {
     { None }
 }
 
+/// Owner (uid, gid) on Unix, None elsewhere.
+#[inline]
+pub fn owner_ids(meta: &std::fs::Metadata) -> Option<(u32, u32)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some((meta.uid(), meta.gid()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        None
+    }
+}
+
 // --------------------------------------------------------------------------
 // Unix-specific helper
 // --------------------------------------------------------------------------
@@ -53,4 +71,1799 @@ pub fn set_unix_permissions(_path: &Path, _mode: u32) -> io::Result<()> {
     Ok(())
 }
 
+// --------------------------------------------------------------------------
+// Modification times
+// --------------------------------------------------------------------------
+
+/// Modification time in whole seconds since the Unix epoch (negative before 1970).
+pub fn mtime_secs(meta: &std::fs::Metadata) -> Option<i64> {
+    meta.modified().ok().map(epoch_secs)
+}
+
+/// Creation (birth) time in whole seconds since the Unix epoch, if the
+/// platform and filesystem record one.
+pub fn btime_secs(meta: &std::fs::Metadata) -> Option<i64> {
+    meta.created().ok().map(epoch_secs)
+}
+
+fn epoch_secs(t: std::time::SystemTime) -> i64 {
+    match t.duration_since(std::time::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+fn from_epoch_secs(secs: i64) -> std::time::SystemTime {
+    let offset = std::time::Duration::from_secs(secs.unsigned_abs());
+    if secs >= 0 {
+        std::time::UNIX_EPOCH + offset
+    } else {
+        std::time::UNIX_EPOCH - offset
+    }
+}
+
+/// Sets the modification time of an open file.
+pub fn set_mtime(file: &std::fs::File, secs: i64) -> io::Result<()> {
+    file.set_modified(from_epoch_secs(secs))
+}
+
+/// Whether [`set_btime`] can work on this platform (Windows and macOS).
+pub const CAN_SET_BTIME: bool = cfg!(any(windows, target_os = "macos"));
+
+/// Sets the creation (birth) time of an open file; fails with
+/// `ErrorKind::Unsupported` where [`CAN_SET_BTIME`] is false.
+pub fn set_btime(file: &std::fs::File, secs: i64) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileTimesExt;
+        file.set_times(std::fs::FileTimes::new().set_created(from_epoch_secs(secs)))
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::macos::fs::FileTimesExt;
+        file.set_times(std::fs::FileTimes::new().set_created(from_epoch_secs(secs)))
+    }
+    #[cfg(not(any(windows, target_os = "macos")))]
+    {
+        let _ = (file, secs);
+        Err(io::Error::new(io::ErrorKind::Unsupported, "creation times cannot be set on this platform"))
+    }
+}
+
+/// Creates a symbolic link at `link` pointing to `target` (stored verbatim).
+pub fn create_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)
+    }
+    #[cfg(windows)]
+    {
+        let resolved = link.parent().map_or_else(|| target.to_path_buf(), |p| p.join(target));
+        if resolved.is_dir() {
+            std::os::windows::fs::symlink_dir(target, link)
+        } else {
+            std::os::windows::fs::symlink_file(target, link)
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "symbolic links are not supported on this platform"))
+    }
+}
+
+/// Extended attributes as archived: name → raw value.
+pub type Xattrs = std::collections::BTreeMap<String, Vec<u8>>;
+
+/// Whether an attribute is worth archiving: `user.*`, `security.*` and POSIX
+/// ACLs on Linux (`trusted.*` and other `system.*` names are host-specific),
+/// everything on other Unixes.
+#[cfg(unix)]
+fn is_archived_xattr(name: &str) -> bool {
+    if cfg!(target_os = "linux") {
+        name.starts_with("user.")
+            || name.starts_with("security.")
+            || name == "system.posix_acl_access"
+            || name == "system.posix_acl_default"
+    } else {
+        true
+    }
+}
+
+/// Extended attributes and ACLs of `path` (not following symlinks). Attributes
+/// that cannot be read are left out; a filesystem without xattr support yields
+/// an empty set.
+pub fn read_xattrs(path: &Path) -> io::Result<Xattrs> {
+    let mut attrs = Xattrs::new();
+    #[cfg(unix)]
+    {
+        let names = match xattr::list(path) {
+            Ok(names) => names,
+            Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(attrs),
+            Err(e) => return Err(e),
+        };
+        for name in names {
+            let Some(name) = name.to_str().filter(|n| is_archived_xattr(n)) else { continue };
+            if let Ok(Some(value)) = xattr::get(path, name) {
+                attrs.insert(name.to_string(), value);
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(attrs)
+}
+
+/// Sets archived attributes on `path`; returns the ones that could not be
+/// restored (e.g. `security.*` without privileges) with the reason.
+pub fn write_xattrs(path: &Path, attrs: &Xattrs) -> Vec<(String, io::Error)> {
+    let mut failed = Vec::new();
+    for (name, value) in attrs {
+        #[cfg(unix)]
+        let result = xattr::set(path, name, value);
+        #[cfg(not(unix))]
+        let result: io::Result<()> = {
+            let _ = (path, value);
+            Err(io::Error::new(io::ErrorKind::Unsupported, "extended attributes are not supported on this platform"))
+        };
+        if let Err(e) = result {
+            failed.push((name.clone(), e));
+        }
+    }
+    failed
+}
+
+static RESTORE_XATTRS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Process-wide switch used by the Katana extractor (`--xattrs`).
+pub fn set_restore_xattrs(restore: bool) {
+    RESTORE_XATTRS.store(restore, std::sync::atomic::Ordering::Relaxed);
+}
+
+pub fn restore_xattrs() -> bool {
+    RESTORE_XATTRS.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// `security.*` (SELinux labels, file capabilities) and `trusted.*` attributes
+/// grant privileges on the host, so they are only restored on explicit request.
+pub fn is_privileged_xattr(name: &str) -> bool {
+    name.starts_with("security.") || name.starts_with("trusted.")
+}
+
+static RESTORE_PRIVILEGED_XATTRS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Process-wide switch (`--privileged-xattrs`): also restore the attributes
+/// [`is_privileged_xattr`] holds back.
+pub fn set_restore_privileged_xattrs(restore: bool) {
+    RESTORE_PRIVILEGED_XATTRS.store(restore, std::sync::atomic::Ordering::Relaxed);
+}
+
+pub fn restore_privileged_xattrs() -> bool {
+    RESTORE_PRIVILEGED_XATTRS.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Whether `path` lives on a network filesystem (NFS, SMB/CIFS, AFS, 9P…), where
+/// many parallel reads hurt more than they help. Unknown ⇒ `false`.
+pub fn is_network_fs(path: &Path) -> bool {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else { return false };
+        let mut st: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut st) } != 0 {
+            return false;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            // NFS, SMB, CIFS, SMB2, AFS, Coda, NCP, 9P
+            const NETWORK_MAGICS: [i64; 8] =
+                [0x6969, 0x517B, 0xFF53_4D42, 0xFE53_4D42, 0x5346_414F, 0x7375_7245, 0x564C, 0x0102_1997];
+            NETWORK_MAGICS.contains(&(st.f_type as i64))
+        }
+        #[cfg(target_os = "macos")]
+        {
+            let name = unsafe { std::ffi::CStr::from_ptr(st.f_fstypename.as_ptr()) }.to_string_lossy();
+            matches!(name.as_ref(), "nfs" | "smbfs" | "afpfs" | "webdav" | "cifs")
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Current time in whole seconds since the Unix epoch.
+pub fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Makes the creation, rename or removal of `path` durable by syncing its
+/// directory. Best effort: not every platform lets a directory be synced.
+pub fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    {
+        let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if let Ok(d) = std::fs::File::open(dir) {
+            let _ = d.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// Whether `dir` lives on a case-insensitive filesystem (macOS/Windows defaults).
+///
+/// Probes by creating a mixed-case scratch file and looking it up in lower case;
+/// `dir` is created if missing. Any I/O failure counts as case-sensitive.
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    if std::fs::create_dir_all(dir).is_err() {
+        return false;
+    }
+    let Ok(probe) = tempfile::Builder::new().prefix(".BlitzArch-CaseProbe-").tempfile_in(dir) else {
+        return false;
+    };
+    let Some(name) = probe.path().file_name().map(|n| n.to_string_lossy().to_lowercase()) else {
+        return false;
+    };
+    dir.join(name).exists()
+}
+
+// --------------------------------------------------------------------------
+// Restoring archived modes on extraction
+// --------------------------------------------------------------------------
+
+/// Umask of the process, read once.
+///
+/// Linux exposes it in `/proc/self/status`; elsewhere it is read with the
+/// set-and-restore `umask(2)` dance, which is only done once to keep the window
+/// in which other threads would create files with mode 0o022 tiny.
+#[cfg(unix)]
+pub fn process_umask() -> u32 {
+    static UMASK: std::sync::OnceLock<u32> = std::sync::OnceLock::new();
+    *UMASK.get_or_init(|| {
+        let from_proc = std::fs::read_to_string("/proc/self/status").ok().and_then(|status| {
+            status
+                .lines()
+                .find_map(|l| l.strip_prefix("Umask:"))
+                .and_then(|v| u32::from_str_radix(v.trim(), 8).ok())
+        });
+        from_proc.unwrap_or_else(|| unsafe {
+            let old = libc::umask(0o022);
+            libc::umask(old);
+            old as u32
+        })
+    })
+}
+
+#[cfg(not(unix))]
+pub fn process_umask() -> u32 {
+    0
+}
+
+/// Applies the archived mode of an extracted entry, filtered by the umask.
+///
+/// Extractors skip this when the job's
+/// [`ExtractOptions::preserve_permissions`](crate::extract::ExtractOptions::preserve_permissions) is
+/// off, so the file keeps the default mode it was created with (0o666 minus
+/// umask). SUID/SGID/sticky bits are never restored.
+pub fn restore_permissions(path: &Path, archived_mode: u32) -> io::Result<()> {
+    set_unix_permissions(path, archived_mode & 0o777 & !process_umask())
+}
+
+// --------------------------------------------------------------------------
+// Sparse files
+// --------------------------------------------------------------------------
+
+/// Data regions `(offset, len)` of an open file of `len` bytes, found with
+/// `SEEK_DATA`/`SEEK_HOLE`. `None` when the file has no holes or the platform
+/// or filesystem cannot report them. Moves the file position.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd"))]
+pub fn data_extents(file: &std::fs::File, len: u64) -> io::Result<Option<Vec<(u64, u64)>>> {
+    use std::os::unix::io::AsRawFd;
+    let fd = file.as_raw_fd();
+    let mut extents = Vec::new();
+    let mut pos: u64 = 0;
+    while pos < len {
+        // SAFETY: plain lseek on a descriptor owned by `file`
+        let data = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                // Только дыра до конца файла
+                Some(libc::ENXIO) => break,
+                Some(libc::EINVAL) | Some(libc::ENOTSUP) => Ok(None),
+                _ => Err(err),
+            };
+        }
+        // SAFETY: as above
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let end = (hole as u64).min(len);
+        if end > data as u64 {
+            extents.push((data as u64, end - data as u64));
+        }
+        pos = end.max(data as u64 + 1);
+    }
+    let covered: u64 = extents.iter().map(|&(_, n)| n).sum();
+    Ok((covered < len).then_some(extents))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd")))]
+pub fn data_extents(_file: &std::fs::File, _len: u64) -> io::Result<Option<Vec<(u64, u64)>>> {
+    Ok(None)
+}
+
+/// Writer recreating a sparse file: of the full content written to it, the
+/// bytes inside the data regions reach the file (at their offsets), so holes
+/// stay unallocated. Non-zero bytes in a hole (the file was written to after
+/// its map was taken) are written as well. Without regions it writes straight
+/// through.
+#[derive(Debug)]
+pub struct SparseWriter<'a> {
+    file: std::fs::File,
+    extents: &'a [(u64, u64)],
+    pos: u64,
+}
+
+impl<'a> SparseWriter<'a> {
+    pub fn new(file: std::fs::File, extents: &'a [(u64, u64)]) -> Self {
+        SparseWriter { file, extents, pos: 0 }
+    }
+
+    /// Sets the final length (a trailing hole has no data to write) and
+    /// returns the file.
+    pub fn finish(self, len: u64) -> io::Result<std::fs::File> {
+        if !self.extents.is_empty() {
+            self.file.set_len(len)?;
+        }
+        Ok(self.file)
+    }
+}
+
+impl io::Write for SparseWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        use std::io::{Seek, SeekFrom, Write};
+        if self.extents.is_empty() {
+            let n = self.file.write(buf)?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        let end = self.pos + buf.len() as u64;
+        let first = self.extents.partition_point(|&(off, n)| off + n <= self.pos);
+        // Области карты и дыры между ними, по порядку
+        let mut spans = Vec::new();
+        let mut at = self.pos;
+        for &(off, n) in self.extents[first..].iter().take_while(|&&(off, _)| off < end) {
+            let from = off.max(at);
+            spans.push((at, from, false));
+            at = (off + n).min(end);
+            spans.push((from, at, true));
+        }
+        spans.push((at, end, false));
+        for (mut from, to, in_extent) in spans {
+            let mut chunk = &buf[(from - self.pos) as usize..(to - self.pos) as usize];
+            if !in_extent {
+                // В дыре по карте пишем только то, что не нули
+                let Some(lead) = chunk.iter().position(|&b| b != 0) else { continue };
+                let tail = chunk.iter().rposition(|&b| b != 0).unwrap_or(lead);
+                chunk = &chunk[lead..=tail];
+                from += lead as u64;
+            }
+            if chunk.is_empty() {
+                continue;
+            }
+            self.file.seek(SeekFrom::Start(from))?;
+            self.file.write_all(chunk)?;
+        }
+        self.pos = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut self.file)
+    }
+}
+
+// --------------------------------------------------------------------------
+// I/O mode for large inputs and shard files
+// --------------------------------------------------------------------------
+
+/// How large inputs are read and shard temp files written (`create --io-mode`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IoMode {
+    /// Through the page cache with the kernel's default readahead (the default).
+    #[default]
+    Buffered,
+    /// Through the page cache, announcing the next [`IoConfig::readahead`]
+    /// bytes ahead of the reader (`posix_fadvise`) and dropping the pages already read.
+    Readahead,
+    /// Around the page cache: `O_DIRECT` on Linux, `F_NOCACHE` on macOS. Where
+    /// the filesystem refuses it, reads fall back to [`IoMode::Readahead`] and
+    /// writes to buffered.
+    Direct,
+}
+
+/// Files below this size are always read through the page cache.
+pub const LARGE_FILE_MIN_SIZE: u64 = 8 << 20;
+
+/// Alignment of `O_DIRECT` buffers, offsets and lengths.
+const DIRECT_ALIGN: usize = 4096;
+
+/// Size of the aligned buffer of direct reads and writes.
+const DIRECT_BUF_SIZE: usize = 4 << 20;
+
+/// I/O settings of one Katana create job (`--io-mode`, `--readahead`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoConfig {
+    pub mode: IoMode,
+    /// Readahead window of [`IoMode::Readahead`] in bytes (default 16 MiB).
+    pub readahead: u64,
+}
+
+impl Default for IoConfig {
+    fn default() -> Self {
+        IoConfig { mode: IoMode::Buffered, readahead: 16 << 20 }
+    }
+}
+
+/// Opens `path` for reading it front to back as `io` says.
+pub fn open_sequential(path: &Path, io: IoConfig) -> io::Result<Box<dyn io::Read + Send>> {
+    let file = std::fs::File::open(path)?;
+    if io.mode == IoMode::Buffered || file.metadata()?.len() < LARGE_FILE_MIN_SIZE {
+        return Ok(Box::new(file));
+    }
+    if io.mode == IoMode::Direct {
+        if let Some(direct) = open_direct(path)? {
+            return Ok(direct);
+        }
+    }
+    Ok(Box::new(ReadaheadReader::new(file, io.readahead.max(DIRECT_ALIGN as u64))))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn open_direct(path: &Path) -> io::Result<Option<Box<dyn io::Read + Send>>> {
+    use std::os::unix::fs::OpenOptionsExt;
+    match std::fs::OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(path) {
+        Ok(file) => Ok(Some(Box::new(DirectReader {
+            file,
+            buf: AlignedBuf::new(DIRECT_BUF_SIZE),
+            start: 0,
+            end: 0,
+            eof: false,
+            direct: true,
+        }))),
+        // tmpfs и прочие без O_DIRECT
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn open_direct(path: &Path) -> io::Result<Option<Box<dyn io::Read + Send>>> {
+    use std::os::unix::io::AsRawFd;
+    let file = std::fs::File::open(path)?;
+    // SAFETY: fcntl on a descriptor owned by `file`
+    let ok = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } != -1;
+    Ok(ok.then(|| Box::new(file) as Box<dyn io::Read + Send>))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn open_direct(_path: &Path) -> io::Result<Option<Box<dyn io::Read + Send>>> {
+    Ok(None)
+}
+
+/// Byte buffer whose usable part starts at a [`DIRECT_ALIGN`] boundary.
+#[derive(Debug)]
+struct AlignedBuf {
+    raw: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    fn new(len: usize) -> Self {
+        let raw = vec![0u8; len + DIRECT_ALIGN];
+        let start = raw.as_ptr().align_offset(DIRECT_ALIGN);
+        AlignedBuf { raw, start, len }
+    }
+
+    fn get(&self) -> &[u8] {
+        &self.raw[self.start..self.start + self.len]
+    }
+
+    fn get_mut(&mut self) -> &mut [u8] {
+        &mut self.raw[self.start..self.start + self.len]
+    }
+}
+
+/// Reader of an `O_DIRECT` file: the kernel only accepts whole aligned blocks,
+/// so it refills an aligned buffer and hands out the bytes from there.
+#[derive(Debug)]
+struct DirectReader {
+    file: std::fs::File,
+    buf: AlignedBuf,
+    start: usize,
+    end: usize,
+    eof: bool,
+    /// Cleared once a read was refused with `EINVAL` and `O_DIRECT` turned off.
+    direct: bool,
+}
+
+impl io::Read for DirectReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.start == self.end {
+            if self.eof {
+                return Ok(0);
+            }
+            let mut filled = 0;
+            while filled < self.buf.len {
+                match io::Read::read(&mut self.file, &mut self.buf.get_mut()[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    // Открыть с O_DIRECT дали, а читать – нет (FUSE, часть сетевых ФС)
+                    Err(e) if self.direct && e.raw_os_error() == Some(EINVAL) => {
+                        disable_direct(&self.file)?;
+                        self.direct = false;
+                    }
+                    Err(e) => return Err(e),
+                }
+                // Неполный блок бывает только в конце файла
+                if self.direct && filled % DIRECT_ALIGN != 0 {
+                    break;
+                }
+            }
+            self.eof = filled < self.buf.len;
+            (self.start, self.end) = (0, filled);
+        }
+        let n = out.len().min(self.end - self.start);
+        out[..n].copy_from_slice(&self.buf.get()[self.start..self.start + n]);
+        self.start += n;
+        Ok(n)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Advice {
+    Sequential,
+    WillNeed,
+    DontNeed,
+}
+
+/// Access-pattern hint for a byte range of `file`; ignored where the platform
+/// has no such hint.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn advise(file: &std::fs::File, offset: u64, len: u64, advice: Advice) {
+    use std::os::unix::io::AsRawFd;
+    let advice = match advice {
+        Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+        Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+    };
+    // SAFETY: posix_fadvise on a descriptor owned by `file`; failures are harmless
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, len as libc::off_t, advice) };
+}
+
+#[cfg(target_os = "macos")]
+fn advise(file: &std::fs::File, offset: u64, len: u64, advice: Advice) {
+    use std::os::unix::io::AsRawFd;
+    if let Advice::WillNeed = advice {
+        let ra = libc::radvisory { ra_offset: offset as libc::off_t, ra_count: len.min(i32::MAX as u64) as libc::c_int };
+        // SAFETY: F_RDADVISE reads `ra`, which outlives the call
+        unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDADVISE, &ra) };
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "macos")))]
+fn advise(_file: &std::fs::File, _offset: u64, _len: u64, _advice: Advice) {}
+
+/// Reader keeping a `window` of the file requested ahead of its position and
+/// dropping what it has read from the page cache.
+#[derive(Debug)]
+struct ReadaheadReader {
+    file: std::fs::File,
+    window: u64,
+    pos: u64,
+    /// Everything below was requested with `WILLNEED`.
+    requested: u64,
+    /// Everything below was dropped with `DONTNEED`.
+    dropped: u64,
+}
+
+impl ReadaheadReader {
+    fn new(file: std::fs::File, window: u64) -> Self {
+        advise(&file, 0, 0, Advice::Sequential);
+        advise(&file, 0, window, Advice::WillNeed);
+        ReadaheadReader { file, window, pos: 0, requested: window, dropped: 0 }
+    }
+}
+
+impl io::Read for ReadaheadReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = io::Read::read(&mut self.file, out)?;
+        self.pos += n as u64;
+        // Следующее окно – когда текущее прочитано наполовину
+        if self.pos + self.window / 2 >= self.requested {
+            advise(&self.file, self.requested, self.window, Advice::WillNeed);
+            self.requested += self.window;
+        }
+        if self.pos - self.dropped >= self.window {
+            advise(&self.file, self.dropped, self.pos - self.dropped, Advice::DontNeed);
+            self.dropped = self.pos;
+        }
+        Ok(n)
+    }
+}
+
+/// Writer of a file written front to back from offset 0 (shard temp files)
+/// in the given [`IoMode`]. With [`IoMode::Direct`] on Linux whole aligned
+/// blocks go to disk with `O_DIRECT` and the unaligned tail is written through
+/// the page cache by [`IoWriter::finish`]; `flush` keeps the tail buffered.
+#[derive(Debug)]
+pub struct IoWriter<'a> {
+    file: &'a mut std::fs::File,
+    direct: Option<(AlignedBuf, usize)>,
+}
+
+impl<'a> IoWriter<'a> {
+    pub fn new(file: &'a mut std::fs::File, mode: IoMode) -> Self {
+        let direct = (mode == IoMode::Direct && enable_direct(file)).then(|| (AlignedBuf::new(DIRECT_BUF_SIZE), 0));
+        IoWriter { file, direct }
+    }
+
+    /// Writes the buffered tail; must be called once everything was written.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write_tail()
+    }
+
+    fn write_tail(&mut self) -> io::Result<()> {
+        let Some((buf, filled)) = self.direct.take() else { return Ok(()) };
+        let aligned = filled / DIRECT_ALIGN * DIRECT_ALIGN;
+        self.write_blocks(&buf.get()[..aligned])?;
+        disable_direct(self.file)?;
+        io::Write::write_all(self.file, &buf.get()[aligned..filled])
+    }
+
+    /// Writes whole blocks with `O_DIRECT`, or buffered if the filesystem
+    /// turns out not to accept it.
+    fn write_blocks(&mut self, blocks: &[u8]) -> io::Result<()> {
+        match io::Write::write_all(self.file, blocks) {
+            Err(e) if e.raw_os_error() == Some(EINVAL) => {
+                disable_direct(self.file)?;
+                io::Write::write_all(self.file, blocks)
+            }
+            res => res,
+        }
+    }
+}
+
+impl io::Write for IoWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let Some((mut buf, mut filled)) = self.direct.take() else { return io::Write::write(self.file, data) };
+        let n = data.len().min(buf.len - filled);
+        buf.get_mut()[filled..filled + n].copy_from_slice(&data[..n]);
+        filled += n;
+        let res = if filled == buf.len {
+            filled = 0;
+            self.write_blocks(buf.get())
+        } else {
+            Ok(())
+        };
+        self.direct = Some((buf, filled));
+        res.map(|()| n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(self.file)
+    }
+}
+
+impl Drop for IoWriter<'_> {
+    fn drop(&mut self) {
+        let _ = self.write_tail();
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const EINVAL: i32 = libc::EINVAL;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const EINVAL: i32 = -1;
+
+/// Turns on `O_DIRECT` (Linux) for a file positioned at an aligned offset, or
+/// `F_NOCACHE` (macOS, which needs no aligned writes). `true` only when writes
+/// must go through the aligned buffer.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn enable_direct(file: &mut std::fs::File) -> bool {
+    use std::io::Seek;
+    use std::os::unix::io::AsRawFd;
+    if file.stream_position().map_or(true, |pos| pos % DIRECT_ALIGN as u64 != 0) {
+        return false;
+    }
+    let fd = file.as_raw_fd();
+    // SAFETY: fcntl flag changes on a descriptor owned by `file`
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        flags != -1 && libc::fcntl(fd, libc::F_SETFL, flags | libc::O_DIRECT) != -1
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn enable_direct(file: &mut std::fs::File) -> bool {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: fcntl on a descriptor owned by `file`
+    unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) };
+    false
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn enable_direct(_file: &mut std::fs::File) -> bool {
+    false
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn disable_direct(file: &std::fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let fd = file.as_raw_fd();
+    // SAFETY: as in `enable_direct`
+    let ok = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        flags != -1 && libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_DIRECT) != -1
+    };
+    if ok { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn disable_direct(_file: &std::fs::File) -> io::Result<()> {
+    Ok(())
+}
+
+// --------------------------------------------------------------------------
+// Files already present at the extraction target
+// --------------------------------------------------------------------------
+
+/// What extraction does when a regular file already exists where an entry is
+/// to be written (`--overwrite`, `--skip-existing`, `--rename-existing`,
+/// `--keep-newer`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Replace the existing file (the default).
+    #[default]
+    Overwrite,
+    /// Keep the existing file; the entry is not extracted.
+    Skip,
+    /// Move the existing file aside as `name copy.ext` (`name copy 2.ext`, …,
+    /// like Finder and the GUI) and extract the entry under its own name.
+    RenameExisting,
+    /// Replace the existing file only if the entry's modification time is
+    /// later; entries without one never replace a file.
+    KeepNewer,
+}
+
+/// First free `name copy.ext`, `name copy 2.ext`, … next to `path`.
+pub fn copy_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (1u32..)
+        .map(|n| match n {
+            1 => path.with_file_name(format!("{stem} copy{ext}")),
+            n => path.with_file_name(format!("{stem} copy {n}{ext}")),
+        })
+        .find(|candidate| candidate.symlink_metadata().is_err())
+        .expect("unbounded candidate sequence")
+}
+
+/// Applies the job's conflict `policy` before an entry with modification time
+/// `archived_mtime` is written to `path`. Returns `false` when the entry must
+/// be skipped; with [`ConflictPolicy::RenameExisting`] the existing file has
+/// been moved aside when this returns. Anything but an existing regular file
+/// (or symlink) at `path` is left to the caller.
+pub fn claim_output(path: &Path, archived_mtime: Option<i64>, policy: ConflictPolicy) -> io::Result<bool> {
+    let Ok(meta) = path.symlink_metadata() else { return Ok(true) };
+    if meta.is_dir() {
+        return Ok(true);
+    }
+    let skip = match policy {
+        ConflictPolicy::Overwrite => return Ok(true),
+        ConflictPolicy::RenameExisting => {
+            let aside = copy_name(path);
+            std::fs::rename(path, &aside)?;
+            crate::warnings::warn(
+                crate::warnings::WarningKind::PathRewrite,
+                path.to_string_lossy(),
+                format!("Existing file {:?} renamed to {:?}", path, aside),
+            );
+            return Ok(true);
+        }
+        ConflictPolicy::Skip => "it already exists",
+        ConflictPolicy::KeepNewer => match (archived_mtime, mtime_secs(&meta)) {
+            (Some(archived), Some(existing)) if archived > existing => return Ok(true),
+            _ => "the existing file is not older",
+        },
+    };
+    crate::warnings::warn(
+        crate::warnings::WarningKind::SkippedFile,
+        path.to_string_lossy(),
+        format!("Skipping {:?}: {}", path, skip),
+    );
+    Ok(false)
+}
+
+// --------------------------------------------------------------------------
+// Path identity helpers
+// --------------------------------------------------------------------------
+
+/// Absolute, symlink-resolved form of `path` that also works for files that do
+/// not exist yet (the parent directory is canonicalized instead).
+pub fn absolute_path(path: &Path) -> PathBuf {
+    if let Ok(p) = std::fs::canonicalize(path) {
+        return p;
+    }
+    let abs = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().map(|d| d.join(path)).unwrap_or_else(|_| path.to_path_buf())
+    };
+    match (abs.parent(), abs.file_name()) {
+        (Some(parent), Some(name)) => std::fs::canonicalize(parent)
+            .map(|p| p.join(name))
+            .unwrap_or(abs),
+        _ => abs,
+    }
+}
+
+/// Returns true if both paths refer to the same filesystem location.
+pub fn is_same_path(a: &Path, b: &Path) -> bool {
+    absolute_path(a) == absolute_path(b)
+}
+
+// --------------------------------------------------------------------------
+// Include/exclude patterns
+// --------------------------------------------------------------------------
+
+/// `create --include/--exclude` patterns in globset syntax (`*` stays within
+/// a directory, `**` crosses them).
+///
+/// Patterns are matched against paths relative to the input they were found
+/// under. A pattern without a leading `/` matches at any depth (`*.tmp`,
+/// `target/**`), and a match on a directory covers everything below it.
+/// Excludes win over includes; without includes everything is included.
+///
+/// With [`Self::respect_ignore_files`] the `.gitignore`/`.blitzignore` files
+/// found inside the inputs are honoured as well.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Option<globset::GlobSet>,
+    exclude: Option<globset::GlobSet>,
+    ignore_files: Option<IgnoreCache>,
+}
+
+/// Ignore files `--respect-gitignore` reads in each directory; a later one
+/// overrides an earlier one.
+pub const IGNORE_FILES: [&str; 2] = [".gitignore", ".blitzignore"];
+
+/// Parsed ignore rules per directory (None: the directory has none).
+type IgnoreCache = std::sync::Arc<std::sync::Mutex<std::collections::HashMap<PathBuf, Option<std::sync::Arc<ignore::gitignore::Gitignore>>>>>;
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(PathFilter { include: Self::compile(include)?, exclude: Self::compile(exclude)?, ignore_files: None })
+    }
+
+    /// Also leave out what ignore files ([`IGNORE_FILES`]) in the walked
+    /// directories exclude, with git's semantics: patterns are relative to the
+    /// directory of the file, deeper files take precedence and `!pattern`
+    /// re-includes. Only ignore files inside an input are read.
+    pub fn respect_ignore_files(mut self, enabled: bool) -> Self {
+        self.ignore_files = enabled.then(Default::default);
+        self
+    }
+
+    fn compile(patterns: &[String]) -> Result<Option<globset::GlobSet>, Box<dyn std::error::Error>> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        let mut expanded = Vec::with_capacity(patterns.len() * 2);
+        for pattern in patterns {
+            let pattern = pattern.trim_end_matches('/');
+            match pattern.strip_prefix('/') {
+                Some(anchored) => expanded.push(anchored.to_string()),
+                None if pattern.starts_with("**/") => expanded.push(pattern.to_string()),
+                None => {
+                    expanded.push(pattern.to_string());
+                    expanded.push(format!("**/{}", pattern));
+                }
+            }
+        }
+        crate::katana::build_glob_set(&expanded).map(Some)
+    }
+
+    /// True if no patterns were given (everything is archived).
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_none() && self.ignore_files.is_none()
+    }
+
+    fn matches(set: &globset::GlobSet, rel: &Path) -> bool {
+        rel.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| set.is_match(crate::katana::normalize_path(&p.to_string_lossy())))
+    }
+
+    /// Whether the file `rel` (relative to its input) is archived.
+    pub fn allows(&self, rel: &Path) -> bool {
+        if self.excludes(rel) {
+            return false;
+        }
+        self.include.as_ref().is_none_or(|set| Self::matches(set, rel))
+    }
+
+    /// Whether `rel` or one of its parent directories is excluded.
+    pub fn excludes(&self, rel: &Path) -> bool {
+        self.exclude.as_ref().is_some_and(|set| Self::matches(set, rel))
+    }
+
+    /// [`Self::allows`] for `path` found while walking the input `root`
+    /// (an input file is matched by its name).
+    pub fn allows_under(&self, root: &Path, path: &Path) -> bool {
+        self.is_empty() || (self.allows(&relative_to_input(root, path)) && !self.ignored(root, path, false))
+    }
+
+    /// Whether a walk of the input `root` can skip the directory `dir` without
+    /// reading it: it is excluded or ignored, and so is everything below it.
+    pub fn prunes(&self, root: &Path, dir: &Path) -> bool {
+        dir != root && (self.excludes(&relative_to_input(root, dir)) || self.ignored(root, dir, true))
+    }
+
+    /// Whether ignore files between the input directory `root` and `path`
+    /// (found while walking it) exclude `path` or one of its parents.
+    pub fn ignored(&self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        use ignore::Match;
+        let Some(cache) = &self.ignore_files else { return false };
+        if path == root || !path.starts_with(root) {
+            return false;
+        }
+        // Ближайший к файлу ignore-файл с совпадением решает
+        for dir in path.ancestors().skip(1).take_while(|d| d.starts_with(root)) {
+            let Some(rules) = Self::ignore_rules(cache, dir) else { continue };
+            let rel = path.strip_prefix(dir).unwrap_or(path);
+            match rules.matched_path_or_any_parents(rel, is_dir) {
+                Match::None => continue,
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+        false
+    }
+
+    fn ignore_rules(cache: &IgnoreCache, dir: &Path) -> Option<std::sync::Arc<ignore::gitignore::Gitignore>> {
+        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let mut builder = ignore::gitignore::GitignoreBuilder::new(dir);
+                for name in IGNORE_FILES {
+                    let file = dir.join(name);
+                    if !file.is_file() {
+                        continue;
+                    }
+                    if let Some(e) = builder.add(&file) {
+                        crate::warnings::warn(
+                            crate::warnings::WarningKind::SkippedFile,
+                            file.to_string_lossy(),
+                            format!("Ignoring invalid patterns in {}: {}", file.display(), e),
+                        );
+                    }
+                }
+                builder.build().ok().filter(|rules| !rules.is_empty()).map(std::sync::Arc::new)
+            })
+            .clone()
+    }
+}
+
+/// `create --min-file-size/--max-file-size/--skip-empty`: regular files left
+/// out by their size, e.g. VM images in a document backup. Followed symbolic
+/// links are judged by the size of their target; links stored as links
+/// (`--symlinks keep`) are never filtered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeFilter {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub skip_empty: bool,
+}
+
+impl SizeFilter {
+    /// True if no limit was given (every size is archived).
+    pub fn is_empty(&self) -> bool {
+        *self == SizeFilter::default()
+    }
+
+    /// Whether a file of `size` bytes is archived.
+    pub fn allows(&self, size: u64) -> bool {
+        !(self.skip_empty && size == 0)
+            && self.min.is_none_or(|min| size >= min)
+            && self.max.is_none_or(|max| size <= max)
+    }
+}
+
+/// Files a [`SizeFilter`] left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct SizeSkipped {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// `path` relative to the input `root` it was found under.
+pub(crate) fn relative_to_input(root: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(rel) if !rel.as_os_str().is_empty() => rel.to_path_buf(),
+        _ => path.file_name().map(PathBuf::from).unwrap_or_else(|| path.to_path_buf()),
+    }
+}
+
+// --------------------------------------------------------------------------
+// Input tree statistics
+// --------------------------------------------------------------------------
+
+/// Totals of an input tree, counted the way `create` lists it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct TreeStats {
+    /// Regular files, plus symbolic links stored as links ([`SymlinkMode::Keep`]).
+    pub files: u64,
+    pub dirs: u64,
+    /// Symbolic links met on the way, whether stored, followed or skipped.
+    pub symlinks: u64,
+    /// Size of the files; stored links count 0.
+    pub bytes: u64,
+    /// Entries whose metadata or listing could not be read.
+    pub unreadable: u64,
+}
+
+impl TreeStats {
+    fn merge(self, other: TreeStats) -> TreeStats {
+        TreeStats {
+            files: self.files + other.files,
+            dirs: self.dirs + other.dirs,
+            symlinks: self.symlinks + other.symlinks,
+            bytes: self.bytes + other.bytes,
+            unreadable: self.unreadable + other.unreadable,
+        }
+    }
+}
+
+/// Sums files and bytes below `paths`, one rayon task per directory.
+///
+/// Links are treated like [`crate::vfs::OsFs`] with the same `symlinks` mode
+/// (links given directly as inputs are followed unless kept), and anything at
+/// or below an `exclude` path (e.g. the output archive) is left out.
+pub fn tree_stats(paths: &[PathBuf], symlinks: SymlinkMode, exclude: &[PathBuf]) -> TreeStats {
+    use rayon::prelude::*;
+    let exclude: Vec<PathBuf> = exclude.iter().map(|p| absolute_path(p)).collect();
+    paths
+        .par_iter()
+        .map(|p| entry_stats(p, &absolute_path(p), true, symlinks, &exclude, &[]))
+        .reduce(TreeStats::default, TreeStats::merge)
+}
+
+fn entry_stats(
+    path: &Path,
+    abs: &Path,
+    top: bool,
+    mode: SymlinkMode,
+    exclude: &[PathBuf],
+    ancestors: &[PathBuf],
+) -> TreeStats {
+    use rayon::prelude::*;
+    let mut stats = TreeStats::default();
+    if exclude.iter().any(|e| abs.starts_with(e)) {
+        return stats;
+    }
+    let Ok(link_meta) = path.symlink_metadata() else {
+        stats.unreadable = 1;
+        return stats;
+    };
+    let meta = if link_meta.file_type().is_symlink() {
+        stats.symlinks = 1;
+        let target = std::fs::metadata(path);
+        match mode {
+            SymlinkMode::Keep if !(top && target.as_ref().is_ok_and(|m| m.is_dir())) => {
+                stats.files = 1;
+                return stats;
+            }
+            SymlinkMode::Skip if !top => return stats,
+            _ => match target {
+                Ok(meta) => meta,
+                Err(_) => {
+                    stats.unreadable = 1;
+                    return stats;
+                }
+            },
+        }
+    } else {
+        link_meta
+    };
+    if meta.is_file() {
+        stats.files = 1;
+        stats.bytes = meta.len();
+        return stats;
+    }
+    if !meta.is_dir() {
+        return stats;
+    }
+    // Followed links may lead back up the tree
+    let mut chain = Vec::new();
+    if mode == SymlinkMode::Follow {
+        let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| abs.to_path_buf());
+        if ancestors.contains(&canonical) {
+            return stats;
+        }
+        chain = ancestors.to_vec();
+        chain.push(canonical);
+    }
+    stats.dirs = 1;
+    let children: Vec<std::fs::DirEntry> = match std::fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(_) => {
+            stats.unreadable += 1;
+            return stats;
+        }
+    };
+    children
+        .par_iter()
+        .map(|child| entry_stats(&child.path(), &abs.join(child.file_name()), false, mode, exclude, &chain))
+        .reduce(TreeStats::default, TreeStats::merge)
+        .merge(stats)
+}
+
+// --------------------------------------------------------------------------
+// Advisory locks on outputs
+// --------------------------------------------------------------------------
+
+/// Locks this process holds, so an in-place rewrite may call another locked
+/// operation on the same file. Nesting is per thread: a second job on the
+/// same target (another GUI or daemon job) gets `ResourceBusy` like another
+/// process would.
+static HELD_LOCKS: std::sync::Mutex<Vec<HeldLock>> = std::sync::Mutex::new(Vec::new());
+
+#[derive(Debug)]
+struct HeldLock {
+    target: PathBuf,
+    owner: std::thread::ThreadId,
+    depth: usize,
+    /// Handle holding the flock; closed when the outermost guard goes away.
+    file: std::fs::File,
+}
+
+/// How long a lock naming a dead owner must stay that way before
+/// [`OutputLock::acquire_breaking_stale`] breaks it.
+const STALE_LOCK_GRACE: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// `<target>.lock`
+pub fn lock_path(target: &Path) -> PathBuf {
+    let mut name = target.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Owner recorded in a `<target>.lock` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockOwner {
+    pub pid: u32,
+    /// When the lock was taken (seconds since the Unix epoch); missing in
+    /// lock files of older versions.
+    pub since: Option<i64>,
+}
+
+impl LockOwner {
+    /// Owner written to `lock_file`, if it names one.
+    pub fn read(lock_file: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(lock_file).ok()?;
+        let mut fields = content.split_whitespace();
+        let pid = fields.next()?.parse().ok()?;
+        Some(LockOwner { pid, since: fields.next().and_then(|s| s.parse().ok()) })
+    }
+
+    /// Whether the owner PID is a running process on this host.
+    pub fn is_running(&self) -> bool {
+        self.pid == std::process::id() || sysinfo::System::new().refresh_process(sysinfo::Pid::from_u32(self.pid))
+    }
+
+    fn age(&self) -> Option<String> {
+        let taken = self.since?;
+        Some(format_age((chrono::Utc::now().timestamp() - taken).max(0) as u64))
+    }
+}
+
+/// `90s`, `25m`, `5h`, `3d` – rough age for messages.
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..120 => format!("{}s", secs),
+        120..7200 => format!("{}m", secs / 60),
+        7200..172_800 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+/// Error for a lock held by someone else, with advice when the recorded
+/// owner is gone.
+fn lock_busy_error(target: &Path, lock_file: &Path, owner: Option<LockOwner>) -> io::Error {
+    let mut message = format!(
+        "Another BlitzArch process{} is writing {}",
+        owner.map(|o| format!(" (pid {})", o.pid)).unwrap_or_default(),
+        target.display()
+    );
+    if let Some(age) = owner.and_then(|o| o.age()) {
+        message.push_str(&format!(" (lock taken {} ago)", age));
+    }
+    if let Some(owner) = owner.filter(|o| !o.is_running()) {
+        message.push_str(&format!(
+            "; pid {} is not running here, so the lock looks stale (held by a leftover child of a crashed run or by another host). \
+             If no other run is active, rerun with --force to break it or delete {}",
+            owner.pid,
+            lock_file.display()
+        ));
+    }
+    io::Error::new(io::ErrorKind::ResourceBusy, message)
+}
+
+/// Advisory lock on an output file, held through `<target>.lock` (which records
+/// the owner's PID and when it was taken) until dropped.
+///
+/// Only BlitzArch processes honour it: two runs writing the same archive (or
+/// the job history) now fail fast or wait instead of interleaving their bytes.
+#[derive(Debug)]
+pub struct OutputLock {
+    target: PathBuf,
+    owner: std::thread::ThreadId,
+}
+
+impl OutputLock {
+    /// Locks `target`, failing with [`io::ErrorKind::ResourceBusy`] if another
+    /// process holds it.
+    pub fn acquire(target: &Path) -> io::Result<Self> {
+        Self::lock(target, false, false)
+    }
+
+    /// [`Self::acquire`] that also breaks a lock whose recorded owner is no
+    /// longer running (`create --force`) while its flock stays held (a leftover
+    /// child of a crashed run); a live owner still fails, as does a lock whose
+    /// owner changes while we look.
+    pub fn acquire_breaking_stale(target: &Path) -> io::Result<Self> {
+        Self::lock(target, false, true)
+    }
+
+    /// Locks `target`, waiting for another holder to finish (short critical
+    /// sections like the history file).
+    pub fn wait(target: &Path) -> io::Result<Self> {
+        Self::lock(target, true, false)
+    }
+
+    fn lock(target: &Path, block: bool, break_stale: bool) -> io::Result<Self> {
+        let target = absolute_path(target);
+        let thread = std::thread::current().id();
+        {
+            let mut held = HELD_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(lock) = held.iter_mut().find(|l| l.target == target && l.owner == thread) {
+                lock.depth += 1;
+                return Ok(OutputLock { target, owner: thread });
+            }
+        }
+        // Not under HELD_LOCKS: a waiting lock must not keep other guards from being dropped
+        let path = lock_path(&target);
+        loop {
+            let mut file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+            let locked = if block { file.lock().map_err(std::fs::TryLockError::Error) } else { file.try_lock() };
+            match locked {
+                Ok(()) => {}
+                Err(std::fs::TryLockError::WouldBlock) => {
+                    let owner = LockOwner::read(&path);
+                    let Some(stale) = owner.filter(|o| break_stale && !o.is_running()) else {
+                        return Err(lock_busy_error(&target, &path, owner));
+                    };
+                    // A new holder records its PID right after taking the flock, so
+                    // a dead PID only means a stale lock if it is still there later
+                    std::thread::sleep(STALE_LOCK_GRACE);
+                    match file.try_lock() {
+                        Ok(()) => {}
+                        Err(std::fs::TryLockError::WouldBlock) => {
+                            let owner = LockOwner::read(&path);
+                            if owner != Some(stale) {
+                                return Err(lock_busy_error(&target, &path, owner));
+                            }
+                            println!("[katana] Breaking stale lock {} (pid {} is not running)", path.display(), stale.pid);
+                            // Новый файл – новый inode со свободной блокировкой
+                            std::fs::remove_file(&path)?;
+                            continue;
+                        }
+                        Err(std::fs::TryLockError::Error(e)) => return Err(e),
+                    }
+                }
+                Err(std::fs::TryLockError::Error(e)) => return Err(e),
+            }
+            // The previous holder may have removed the lock file between our open and lock
+            if !is_same_file(&file, &path) {
+                continue;
+            }
+            if let Some(previous) = LockOwner::read(&path).filter(|o| !o.is_running()) {
+                println!("[katana] Taking over the lock of an interrupted run (pid {}) on {}", previous.pid, target.display());
+            }
+            use std::io::Write;
+            file.set_len(0)?;
+            writeln!(file, "{} {}", std::process::id(), chrono::Utc::now().timestamp())?;
+            let mut held = HELD_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+            held.push(HeldLock { target: target.clone(), owner: thread, depth: 1, file });
+            return Ok(OutputLock { target, owner: thread });
+        }
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        let mut held = HELD_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(pos) = held.iter().position(|l| l.target == self.target && l.owner == self.owner) else { return };
+        held[pos].depth -= 1;
+        if held[pos].depth == 0 {
+            let lock = held.remove(pos);
+            // Remove while still locked, and only our own lock file; closing the
+            // handle releases the lock
+            let path = lock_path(&self.target);
+            if is_same_file(&lock.file, &path) {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &std::fs::File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &std::fs::File, path: &Path) -> bool {
+    // Open files cannot be deleted out from under the holder on Windows
+    path.exists()
+}
+
+// --------------------------------------------------------------------------
+// All-or-nothing multi-output jobs
+// --------------------------------------------------------------------------
+
+/// Several outputs published together: each is written to a staging path
+/// and [`Self::commit`] renames them all into place, or none of them.
+///
+/// The staging directory (`.blitzarch-txn-*`) lives next to the outputs, so
+/// every rename stays on one filesystem. Dropping an uncommitted transaction
+/// removes everything staged and leaves existing files alone.
+///
+/// Each target is locked from [`Self::stage`] until the transaction ends. The
+/// commit keeps a journal of its renames; a commit cut short by a crash is
+/// rolled back by the next transaction in the same directory.
+#[derive(Debug)]
+pub struct OutputTransaction {
+    staging: tempfile::TempDir,
+    outputs: Vec<(PathBuf, PathBuf)>,
+    /// On the staging directory (so other runs know it is live) and on every target.
+    _locks: Vec<OutputLock>,
+}
+
+/// Journal of a commit in progress, inside the staging directory.
+const COMMIT_JOURNAL: &str = "commit.journal";
+
+impl OutputTransaction {
+    /// Starts a transaction for outputs in `dir` (created if missing).
+    /// Transactions left behind by an interrupted run are rolled back and removed first.
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        for leftover in std::fs::read_dir(dir)?.flatten() {
+            let path = leftover.path();
+            if !leftover.file_name().to_string_lossy().starts_with(".blitzarch-txn-") || !path.is_dir() {
+                continue;
+            }
+            // Занят – транзакция другого живого процесса
+            let Ok(_lock) = OutputLock::acquire(&path) else { continue };
+            let journal = path.join(COMMIT_JOURNAL);
+            if journal.exists() {
+                roll_back_journal(&journal)?;
+                println!("[katana] Rolled back an interrupted commit in {}", dir.display());
+            }
+            std::fs::remove_dir_all(&path)?;
+        }
+        let staging = tempfile::Builder::new().prefix(".blitzarch-txn-").tempdir_in(dir)?;
+        let lock = OutputLock::acquire(staging.path())?;
+        Ok(OutputTransaction { staging, outputs: Vec::new(), _locks: vec![lock] })
+    }
+
+    /// Path to write `target` to until the commit; locks `target`.
+    pub fn stage(&mut self, target: &Path) -> io::Result<PathBuf> {
+        self._locks.push(OutputLock::acquire(target)?);
+        let staged = self.staging.path().join(format!("out-{}", self.outputs.len()));
+        self.outputs.push((staged.clone(), target.to_path_buf()));
+        Ok(staged)
+    }
+
+    /// Renames every staged output to its target. Existing targets are
+    /// replaced; if any rename fails, the targets already published are
+    /// removed and the replaced files put back before the error is returned.
+    /// Each output is synced before its rename and the directories after them.
+    pub fn commit(self) -> io::Result<Vec<PathBuf>> {
+        let journal_path = self.staging.path().join(COMMIT_JOURNAL);
+        let mut journal = std::fs::File::create(&journal_path)?;
+        let mut backups: Vec<(PathBuf, Option<PathBuf>)> = Vec::with_capacity(self.outputs.len());
+        let mut result = Ok(());
+        for (i, (staged, target)) in self.outputs.iter().enumerate() {
+            let backup = self.staging.path().join(format!("backup-{i}"));
+            let had_target = target.symlink_metadata().is_ok();
+            let prepared = std::fs::File::open(staged)
+                .and_then(|f| f.sync_all())
+                .and_then(|_| write_journal_record(&mut journal, b"f", target, had_target.then_some(backup.as_path())));
+            if let Err(e) = prepared {
+                result = Err(e);
+                break;
+            }
+            if had_target {
+                if let Err(e) = std::fs::rename(target, &backup) {
+                    result = Err(e);
+                    break;
+                }
+            }
+            let published = std::fs::rename(staged, target);
+            backups.push((target.clone(), had_target.then_some(backup)));
+            if let Err(e) = published {
+                result = Err(e);
+                break;
+            }
+        }
+        if let Err(e) = result {
+            // Откат в обратном порядке: новые файлы долой, старые на место
+            for (target, backup) in backups.into_iter().rev() {
+                let _ = std::fs::remove_file(&target);
+                if let Some(backup) = backup {
+                    let _ = std::fs::rename(&backup, &target);
+                }
+            }
+            std::fs::remove_file(&journal_path)?;
+            return Err(e);
+        }
+        for (_, target) in &self.outputs {
+            sync_parent_dir(target);
+        }
+        // Published: a later run must not undo this commit
+        std::fs::remove_file(&journal_path)?;
+        Ok(self.outputs.iter().map(|(_, target)| target.clone()).collect())
+    }
+}
+
+/// Directory tree built next to its destination (`extract --atomic`): the
+/// extraction writes below [`Self::path`] and [`Self::publish`] moves the
+/// finished tree into place. Dropping the stage removes whatever is left of
+/// it, so a failed run leaves the destination untouched.
+///
+/// The target is locked while staged. A merge into an existing directory keeps
+/// a journal of its steps; if the process dies halfway, the next stage for the
+/// same target undoes the published files and puts the replaced ones back.
+#[derive(Debug)]
+pub struct StagedDir {
+    staging: tempfile::TempDir,
+    target: PathBuf,
+    _lock: OutputLock,
+}
+
+/// Journal of a merge in progress, inside the staging directory.
+const MERGE_JOURNAL: &str = "merge.journal";
+
+impl StagedDir {
+    /// Stages a tree for `target` in a hidden sibling directory (same filesystem).
+    /// Stages left behind by an interrupted run are rolled back and removed first.
+    pub fn new(target: &Path) -> io::Result<Self> {
+        let target = absolute_path(target);
+        let parent = target.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(parent)?;
+        let lock = OutputLock::acquire(&target)?;
+        let name = target.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let prefix = format!(".{name}.blitzarch-partial-");
+        // Под блокировкой цели чужих живых стейджей быть не может
+        for leftover in std::fs::read_dir(parent)?.flatten() {
+            if !leftover.file_name().to_string_lossy().starts_with(&prefix) || !leftover.path().is_dir() {
+                continue;
+            }
+            let journal = leftover.path().join(MERGE_JOURNAL);
+            if journal.exists() {
+                roll_back_journal(&journal)?;
+                println!("[katana] Rolled back an interrupted extraction into {}", target.display());
+            }
+            std::fs::remove_dir_all(leftover.path())?;
+        }
+        let staging = tempfile::Builder::new().prefix(&prefix).tempdir_in(parent)?;
+        std::fs::create_dir(staging.path().join("tree"))?;
+        std::fs::create_dir(staging.path().join("replaced"))?;
+        Ok(StagedDir { staging, target, _lock: lock })
+    }
+
+    /// Where the tree is written to until it is published.
+    pub fn path(&self) -> PathBuf {
+        self.staging.path().join("tree")
+    }
+
+    /// Moves the staged tree to the target. A missing or empty target is
+    /// replaced by one rename; into an existing directory every file is moved
+    /// separately, following the conflict `policy` for files already there,
+    /// and if a move fails all files moved so far are taken back out and the
+    /// replaced ones restored. Directories the merge creates get the modes of
+    /// their staged counterparts.
+    pub fn publish(&self, policy: ConflictPolicy) -> io::Result<()> {
+        let tree = self.path();
+        if self.target.symlink_metadata().is_err() || std::fs::remove_dir(&self.target).is_ok() {
+            // Каталог временного стейджа создан с 0700 – выставляем обычные права
+            set_unix_permissions(&tree, 0o777 & !process_umask())?;
+            return std::fs::rename(&tree, &self.target);
+        }
+        let replaced = self.staging.path().join("replaced");
+        let journal_path = self.staging.path().join(MERGE_JOURNAL);
+        let mut journal = std::fs::File::create(&journal_path)?;
+        // (published file, where the file it replaced was moved)
+        let mut published: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+        // (created directory, mode of the staged one)
+        let mut created_dirs = Vec::new();
+        let mut merge = || -> io::Result<()> {
+            let entries: Vec<walkdir::DirEntry> =
+                walkdir::WalkDir::new(&tree).min_depth(1).into_iter().collect::<Result<_, _>>().map_err(io::Error::other)?;
+            for entry in entries {
+                let Ok(rel) = entry.path().strip_prefix(&tree) else { continue };
+                let dest = self.target.join(rel);
+                if entry.file_type().is_dir() {
+                    if dest.symlink_metadata().is_err() {
+                        write_journal_record(&mut journal, b"d", &dest, None)?;
+                        std::fs::create_dir(&dest)?;
+                        created_dirs.push((dest, unix_mode(&entry.metadata().map_err(io::Error::other)?) & 0o7777));
+                    }
+                    continue;
+                }
+                let moved_aside = match dest.symlink_metadata() {
+                    Err(_) => None,
+                    Ok(meta) if meta.is_dir() => {
+                        crate::warnings::warn(
+                            crate::warnings::WarningKind::SkippedFile,
+                            dest.to_string_lossy(),
+                            format!("Skipping file that conflicts with existing directory: {:?}", dest),
+                        );
+                        continue;
+                    }
+                    Ok(_) => {
+                        let aside = match policy {
+                            ConflictPolicy::RenameExisting => {
+                                let aside = copy_name(&dest);
+                                crate::warnings::warn(
+                                    crate::warnings::WarningKind::PathRewrite,
+                                    dest.to_string_lossy(),
+                                    format!("Existing file {:?} renamed to {:?}", dest, aside),
+                                );
+                                aside
+                            }
+                            ConflictPolicy::Overwrite => replaced.join(published.len().to_string()),
+                            _ if claim_output(&dest, entry.path().symlink_metadata().ok().and_then(|m| mtime_secs(&m)), policy)? => {
+                                replaced.join(published.len().to_string())
+                            }
+                            _ => continue,
+                        };
+                        write_journal_record(&mut journal, b"f", &dest, Some(&aside))?;
+                        std::fs::rename(&dest, &aside)?;
+                        Some(aside)
+                    }
+                };
+                if moved_aside.is_none() {
+                    write_journal_record(&mut journal, b"f", &dest, None)?;
+                }
+                let moved = std::fs::rename(entry.path(), &dest);
+                published.push((dest, moved_aside));
+                moved?;
+            }
+            Ok(())
+        };
+        let result = merge();
+        if result.is_err() {
+            // Откат: опубликованное убираем, заменённое возвращаем на место
+            for (dest, moved_aside) in published.into_iter().rev() {
+                let _ = std::fs::remove_file(&dest);
+                if let Some(aside) = moved_aside {
+                    let _ = std::fs::rename(&aside, &dest);
+                }
+            }
+            for (dir, _) in created_dirs.into_iter().rev() {
+                let _ = std::fs::remove_dir(dir);
+            }
+        } else {
+            // Deepest first: a read-only parent must not block its children
+            for (dir, mode) in created_dirs.iter().rev() {
+                set_unix_permissions(dir, *mode)?;
+            }
+        }
+        // Done either way: a later run must not undo this merge
+        std::fs::remove_file(&journal_path)?;
+        result
+    }
+}
+
+/// Appends a step of a publish ([`StagedDir::publish`], [`OutputTransaction::commit`])
+/// to its journal: `kind` (`f` for a published file, `d` for a created
+/// directory), the destination and where the file it replaces is moved.
+/// Fields are NUL-terminated.
+fn write_journal_record(journal: &mut std::fs::File, kind: &[u8], dest: &Path, aside: Option<&Path>) -> io::Result<()> {
+    use std::io::Write;
+    let mut record = kind.to_vec();
+    record.push(0);
+    record.extend_from_slice(&path_bytes(dest));
+    record.push(0);
+    record.extend_from_slice(&aside.map(path_bytes).unwrap_or_default());
+    record.push(0);
+    journal.write_all(&record)
+}
+
+/// Undoes the steps recorded in a publish journal, newest first. Every step is
+/// checked against the disk, so a step that never happened (or a rollback
+/// that was itself interrupted) is harmless.
+fn roll_back_journal(journal: &Path) -> io::Result<()> {
+    let data = std::fs::read(journal)?;
+    let fields: Vec<&[u8]> = data.split(|&b| b == 0).collect();
+    // A torn last record is incomplete and was never acted on
+    let records: Vec<&[&[u8]]> = fields.chunks_exact(3).collect();
+    for record in records.into_iter().rev() {
+        let dest = path_from_bytes(record[1]);
+        match (record[0], record[2]) {
+            (b"d", _) => {
+                let _ = std::fs::remove_dir(&dest);
+            }
+            (_, b"") => {
+                let _ = std::fs::remove_file(&dest);
+            }
+            (_, aside) => {
+                // Not moved aside yet: the original is still in place
+                let aside = path_from_bytes(aside);
+                if aside.symlink_metadata().is_ok() {
+                    let _ = std::fs::remove_file(&dest);
+                    std::fs::rename(&aside, &dest)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
 
+// --------------------------------------------------------------------------
+// Transient write failures (SMB/NFS targets)
+// --------------------------------------------------------------------------
+
+/// How often a failed output write is retried before giving up.
+const WRITE_RETRY_LIMIT: u32 = 8;
+/// Backoff before the first retry; doubled on every further attempt.
+const WRITE_RETRY_BASE_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+const WRITE_RETRY_MAX_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Returns true for I/O errors that network filesystems report on hiccups
+/// (EIO, EAGAIN, ESTALE, timeouts) and that are worth a retry.
+pub fn is_transient_io_error(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => return true,
+        _ => {}
+    }
+    #[cfg(unix)]
+    {
+        matches!(err.raw_os_error(), Some(libc::EIO) | Some(libc::EAGAIN) | Some(libc::ESTALE))
+    }
+    #[cfg(windows)]
+    {
+        // ERROR_UNEXP_NET_ERR, ERROR_NETNAME_DELETED, ERROR_SEM_TIMEOUT
+        matches!(err.raw_os_error(), Some(59) | Some(64) | Some(121))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        false
+    }
+}
+
+/// Append-only output file that survives transient write failures.
+///
+/// Only bytes whose `write_all` succeeded are counted as committed. On a
+/// transient error the handle is dropped, the file re-opened, truncated back to
+/// the committed length and the write repeated with exponential backoff, so a
+/// short network outage no longer aborts a multi-hour create.
+pub struct RetryingAppender {
+    path: PathBuf,
+    file: std::fs::File,
+    committed: u64,
+    retries: u64,
+}
+
+impl RetryingAppender {
+    /// Opens (or creates) `path` for appending; existing content counts as committed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = Self::open_handle(path)?;
+        let committed = file.metadata()?.len();
+        Ok(Self { path: path.to_path_buf(), file, committed, retries: 0 })
+    }
+
+    fn open_handle(path: &Path) -> io::Result<std::fs::File> {
+        std::fs::OpenOptions::new().create(true).append(true).read(true).open(path)
+    }
+
+    /// Length of the file up to the last successful write.
+    pub fn committed_len(&self) -> u64 {
+        self.committed
+    }
+
+    /// Number of retries performed so far.
+    pub fn retries(&self) -> u64 {
+        self.retries
+    }
+
+    /// Appends `buf`, retrying transient failures.
+    pub fn append(&mut self, buf: &[u8]) -> io::Result<()> {
+        use std::io::Write;
+        let mut attempt = 0u32;
+        loop {
+            let err = match self.file.write_all(buf) {
+                Ok(()) => {
+                    self.committed += buf.len() as u64;
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+            if !is_transient_io_error(&err) || attempt >= WRITE_RETRY_LIMIT {
+                return Err(err);
+            }
+            let delay = (WRITE_RETRY_BASE_DELAY * 2u32.pow(attempt)).min(WRITE_RETRY_MAX_DELAY);
+            eprintln!(
+                "[fsx] transient write error on {} ({}), retry {}/{} in {:?}",
+                self.path.display(),
+                err,
+                attempt + 1,
+                WRITE_RETRY_LIMIT,
+                delay
+            );
+            std::thread::sleep(delay);
+            attempt += 1;
+            self.retries += 1;
+            // A partial write may have landed before the error – drop it
+            if let Ok(file) = Self::open_handle(&self.path) {
+                if file.set_len(self.committed).is_ok() {
+                    self.file = file;
+                }
+            }
+        }
+    }
+
+    /// Underlying handle, e.g. for reading back what was written.
+    pub fn file_mut(&mut self) -> &mut std::fs::File {
+        &mut self.file
+    }
+}
@@ -0,0 +1,167 @@
+//! Process-wide limits on the worker threads and memory of concurrent jobs.
+//!
+//! Several create / extract jobs in one process (GUI, daemon) would otherwise
+//! each size their worker pool to every core and their memory budget to most
+//! of the RAM. Instead every job registers with the [`ResourceGovernor`] before
+//! starting its workers and gets a [`Lease`]: the part of the limits that the
+//! running jobs left free, capped to what the job asked for. The job runs its
+//! workers in a pool of the leased size and tunes itself to the leased memory;
+//! dropping the lease gives both back.
+//!
+//! A job waits while the running ones hold every thread or all but
+//! [`MIN_LEASE_MB`] of the memory. A job registering with nothing else running
+//! always starts, even if it asked for more than the limits.
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+
+/// Smallest memory share a job starts with while others are running.
+pub const MIN_LEASE_MB: u64 = 64;
+
+/// How often a waiting job checks whether it was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(200);
+
+/// Worker threads and memory (MiB), as limits or as a share of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub threads: usize,
+    pub memory_mb: u64,
+}
+
+impl Limits {
+    /// Every core and 70% of the system memory.
+    pub fn system() -> Self {
+        Limits {
+            threads: num_cpus::get().max(1),
+            memory_mb: (crate::autotune::default_memory_budget() / (1024 * 1024)) as u64,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    limits: Limits,
+    jobs: usize,
+    threads: usize,
+    memory_mb: u64,
+}
+
+/// Shared by all jobs of a process, see [`ResourceGovernor::global`].
+#[derive(Debug)]
+pub struct ResourceGovernor {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+static GLOBAL: OnceLock<Arc<ResourceGovernor>> = OnceLock::new();
+
+impl ResourceGovernor {
+    pub fn new(limits: Limits) -> Arc<Self> {
+        let limits = Limits { threads: limits.threads.max(1), ..limits };
+        Arc::new(ResourceGovernor {
+            state: Mutex::new(State { limits, jobs: 0, threads: 0, memory_mb: 0 }),
+            released: Condvar::new(),
+        })
+    }
+
+    /// The governor the create and extract pipelines register with; starts
+    /// with [`Limits::system`].
+    pub fn global() -> &'static Arc<ResourceGovernor> {
+        GLOBAL.get_or_init(|| ResourceGovernor::new(Limits::system()))
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// New limits apply to leases granted from now on; running jobs keep theirs.
+    pub fn set_limits(&self, limits: Limits) {
+        self.state().limits = Limits { threads: limits.threads.max(1), ..limits };
+        self.released.notify_all();
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.state().limits
+    }
+
+    /// Running jobs and the threads and memory they hold.
+    pub fn in_use(&self) -> (usize, Limits) {
+        let state = self.state();
+        (state.jobs, Limits { threads: state.threads, memory_mb: state.memory_mb })
+    }
+
+    /// Waits for a share of up to `threads` workers and `memory_mb` MiB;
+    /// `None` if `cancel` fired while waiting.
+    pub fn register(self: &Arc<Self>, threads: usize, memory_mb: u64, cancel: Option<&CancellationToken>) -> Option<Lease> {
+        let threads = threads.max(1);
+        let mut state = self.state();
+        let share = loop {
+            if cancel.is_some_and(|c| c.is_cancelled()) {
+                return None;
+            }
+            let free = Limits {
+                threads: state.limits.threads.saturating_sub(state.threads),
+                memory_mb: state.limits.memory_mb.saturating_sub(state.memory_mb),
+            };
+            if state.jobs == 0 {
+                break Limits { threads: threads.min(free.threads), memory_mb: memory_mb.min(free.memory_mb.max(MIN_LEASE_MB)) };
+            }
+            if free.threads > 0 && free.memory_mb >= memory_mb.min(MIN_LEASE_MB) {
+                break Limits { threads: threads.min(free.threads), memory_mb: memory_mb.min(free.memory_mb) };
+            }
+            state = self.released.wait_timeout(state, CANCEL_POLL).unwrap_or_else(|e| e.into_inner()).0;
+        };
+        state.jobs += 1;
+        state.threads += share.threads;
+        state.memory_mb += share.memory_mb;
+        Some(Lease { governor: Arc::clone(self), share })
+    }
+}
+
+/// A job's share of the limits, returned to the governor when dropped.
+#[derive(Debug)]
+pub struct Lease {
+    governor: Arc<ResourceGovernor>,
+    share: Limits,
+}
+
+impl Lease {
+    pub fn threads(&self) -> usize {
+        self.share.threads
+    }
+
+    pub fn memory_mb(&self) -> u64 {
+        self.share.memory_mb
+    }
+
+    /// Runs `op` in a rayon pool of the leased size, so the `rayon::scope`s
+    /// inside it stay within the lease. The pool has one thread more for the
+    /// job's coordinator, which mostly waits for the workers. Its threads
+    /// report warnings to the caller's [`crate::warnings::capture`].
+    pub fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        let scope = crate::warnings::current_scope();
+        match rayon::ThreadPoolBuilder::new()
+            .num_threads(self.share.threads + 1)
+            .thread_name(|i| format!("blitz-job-{i}"))
+            .start_handler(move |_| crate::warnings::enter_scope(scope.clone()))
+            .build()
+        {
+            Ok(pool) => pool.install(op),
+            // Без собственного пула – в глобальном, как раньше
+            Err(_) => op(),
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut state = self.governor.state();
+        state.jobs -= 1;
+        state.threads -= self.share.threads;
+        state.memory_mb -= self.share.memory_mb;
+        drop(state);
+        self.governor.released.notify_all();
+    }
+}
@@ -0,0 +1,275 @@
+//! Local history of completed jobs (create / extract).
+//!
+//! Each finished operation is appended as one JSON line to a small file in the
+//! user's data directory, so the GUI can show "recent operations" without
+//! re-scanning archives or source trees. The file is trimmed to the newest
+//! [`HISTORY_MAX_ENTRIES`] records; unreadable lines are skipped, so a torn
+//! write never makes the whole history unusable.
+//!
+//! Location (first match wins):
+//! * `$BLITZARCH_HISTORY` – explicit file path;
+//! * macOS: `~/Library/Application Support/BlitzArch/history.jsonl`;
+//! * Windows: `%APPDATA%\BlitzArch\history.jsonl`;
+//! * others: `$XDG_DATA_HOME/blitzarch/history.jsonl` or `~/.local/share/blitzarch/history.jsonl`.
+//!
+//! Passwords are never stored, only whether the archive was encrypted.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Number of records kept on disk.
+pub const HISTORY_MAX_ENTRIES: usize = 200;
+
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    #[default]
+    Create,
+    Extract,
+}
+
+/// Parameters the job was started with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobParams {
+    pub level: Option<i32>,
+    pub threads: Option<usize>,
+    pub codec_threads: Option<u32>,
+    pub memory_budget_mb: Option<u64>,
+    pub encrypted: bool,
+    pub strip_components: Option<u32>,
+    /// Number of explicitly selected entries (extract); 0 = everything.
+    pub selected_files: usize,
+}
+
+/// Result figures of a finished job.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobStats {
+    pub files: u64,
+    pub total_bytes: u64,
+    pub archive_bytes: Option<u64>,
+    pub duration_sec: f64,
+}
+
+impl JobStats {
+    /// Uncompressed / archive size, if both are known.
+    pub fn ratio(&self) -> Option<f64> {
+        match self.archive_bytes {
+            Some(a) if a > 0 => Some(self.total_bytes as f64 / a as f64),
+            _ => None,
+        }
+    }
+}
+
+/// One completed operation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobRecord {
+    /// Unix time of completion in milliseconds; unique enough to key GUI rows.
+    pub id: u64,
+    pub kind: JobKind,
+    pub success: bool,
+    pub archive_path: Option<String>,
+    pub inputs: Vec<String>,
+    pub output_dir: Option<String>,
+    pub params: JobParams,
+    pub stats: JobStats,
+    /// `Some(true)` if the post-create / post-extract integrity check passed.
+    pub integrity_ok: Option<bool>,
+    pub error: Option<String>,
+}
+
+impl JobRecord {
+    /// New record stamped with the current time.
+    pub fn new(kind: JobKind) -> Self {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Self { id, kind, ..Default::default() }
+    }
+
+    /// Completion time in seconds since the Unix epoch.
+    pub fn finished_at(&self) -> u64 {
+        self.id / 1000
+    }
+}
+
+/// Append-only JSON-lines store of [`JobRecord`]s.
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+    path: PathBuf,
+    max_entries: usize,
+}
+
+impl HistoryStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), max_entries: HISTORY_MAX_ENTRIES }
+    }
+
+    /// Store at the platform default location (see module docs).
+    pub fn open_default() -> io::Result<Self> {
+        default_history_path()
+            .map(Self::new)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cannot determine data directory for job history"))
+    }
+
+    /// Overrides the number of records kept (minimum 1).
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.max_entries = max.max(1);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `record` and trims the file if it grew past the limit.
+    pub fn record(&self, record: &JobRecord) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        // Trimming rewrites the file: keep other processes out until done
+        let _lock = crate::fsx::OutputLock::wait(&self.path)?;
+        {
+            let mut f = OpenOptions::new().create(true).read(true).append(true).open(&self.path)?;
+            // Terminate a torn last line so it does not swallow this record
+            let len = f.metadata()?.len();
+            if len > 0 {
+                let mut last = [0u8; 1];
+                f.seek(SeekFrom::Start(len - 1))?;
+                f.read_exact(&mut last)?;
+                if last[0] != b'\n' {
+                    line.insert(0, '\n');
+                }
+            }
+            // One write call keeps concurrent appenders (GUI + CLI) from interleaving
+            f.write_all(line.as_bytes())?;
+        }
+        let records = self.read_all()?;
+        if records.len() > self.max_entries {
+            self.rewrite(&records[records.len() - self.max_entries..])?;
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` most recent records, newest first.
+    pub fn recent(&self, limit: usize) -> io::Result<Vec<JobRecord>> {
+        let mut records = self.read_all()?;
+        records.reverse();
+        records.truncate(limit);
+        Ok(records)
+    }
+
+    /// Removes all records.
+    pub fn clear(&self) -> io::Result<()> {
+        let _lock = crate::fsx::OutputLock::wait(&self.path)?;
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// All records in insertion order; corrupt lines are skipped.
+    fn read_all(&self) -> io::Result<Vec<JobRecord>> {
+        let f = match fs::File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut out = Vec::new();
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Ok(rec) = serde_json::from_str::<JobRecord>(&line) {
+                out.push(rec);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Atomically replaces the file with `records`.
+    fn rewrite(&self, records: &[JobRecord]) -> io::Result<()> {
+        let dir = self.path.parent().unwrap_or_else(|| Path::new("."));
+        let mut tmp = crate::temp_manager::temp_file_in(dir, "history")?;
+        for rec in records {
+            serde_json::to_writer(&mut tmp, rec)?;
+            tmp.write_all(b"\n")?;
+        }
+        tmp.as_file().sync_all()?;
+        tmp.persist(&self.path).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
+
+/// Platform default path of the history file, if a home/data directory is known.
+pub fn default_history_path() -> Option<PathBuf> {
+    if let Some(p) = std::env::var_os("BLITZARCH_HISTORY") {
+        return Some(PathBuf::from(p));
+    }
+    let dir = if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Application Support/BlitzArch"))
+    } else if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|a| PathBuf::from(a).join("BlitzArch"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+            .map(|d| d.join("blitzarch"))
+    }?;
+    Some(dir.join(HISTORY_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistoryStore, JobKind, JobRecord};
+
+    #[test]
+    fn test_history_record_recent_and_trim() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = HistoryStore::new(dir.path().join("sub/history.jsonl")).max_entries(3);
+        assert!(store.recent(10).unwrap().is_empty());
+
+        for i in 0..5u64 {
+            let mut rec = JobRecord::new(JobKind::Create);
+            rec.id = i;
+            rec.success = true;
+            rec.stats.total_bytes = 1000;
+            rec.stats.archive_bytes = Some(250);
+            store.record(&rec).unwrap();
+        }
+        // Torn line from an interrupted writer is ignored
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(store.path())
+            .and_then(|mut f| std::io::Write::write_all(&mut f, b"{\"id\":99,\"ki"))
+            .unwrap();
+
+        let recent = store.recent(10).unwrap();
+        assert_eq!(recent.iter().map(|r| r.id).collect::<Vec<_>>(), [4, 3, 2]);
+        let mut next = JobRecord::new(JobKind::Extract);
+        next.id = 5;
+        store.record(&next).unwrap();
+        let recent = store.recent(10).unwrap();
+        assert_eq!(recent.iter().map(|r| r.id).collect::<Vec<_>>(), [5, 4, 3]);
+        assert_eq!(recent[0].kind, JobKind::Extract);
+        assert_eq!(recent[1].stats.ratio(), Some(4.0));
+        assert_eq!(store.recent(1).unwrap().len(), 1);
+
+        store.clear().unwrap();
+        assert!(store.recent(10).unwrap().is_empty());
+        store.clear().unwrap();
+    }
+}
@@ -7,31 +7,59 @@
 //! The Katana format (`.blz` when used with the `--katana` flag) is designed for maximum creation and extraction speed on modern, multi-core systems. Its structure is as follows:
 //! 
 //! 1.  **Data Shards**: A sequence of independent, concatenated `zstd` compressed data streams. Each shard is created and can be extracted in parallel.
-//! 2.  **JSON Index**: A `zstd`-compressed JSON object containing metadata for all shards and files.
+//! 2.  **Index**: metadata for all shards and files, `zstd`-compressed by default or stored as is (see [`IndexCompression`]); `--hide-names` encrypts this block as well (see [`FEATURE_HIDDEN_NAMES`]). Two layouts exist (see [`IndexFormat`]):
+//!     - version 1: a JSON object;
+//!     - version 2 (written by default): a binary header followed by a MessagePack map
+//!       with the same fields as the JSON object:
+//!       `"KIX2" | u16 version | u32 features | u32 crc32 | u8 has_hmac | [32-byte hmac] | payload`
+//!       (little-endian). The CRC32 and HMAC cover the block with `crc32` zeroed,
+//!       `has_hmac = 0` and no HMAC, so the version and feature bits are signed too.
 //! 3.  **Footer**: A fixed-size block at the very end of the file containing:
-//!     - `index_compressed_size: u64`: The size of the compressed JSON index.
-//!     - `index_uncompressed_size: u64`: The original size of the JSON index.
-//!     - `magic_bytes: [u8; 8]`: The magic signature `b"KATIDX01"`.
+//!     - `index_compressed_size: u64`: The size of the stored index block.
+//!     - `index_uncompressed_size: u64`: The decoded size of the index.
+//!     - `magic_bytes: [u8; 8]`: `b"KATIDX01"` or `b"KATIDX02"`, the index version.
 //! 
 //! This design allows an extractor to read the footer, locate and decompress the index, and then dispatch multiple threads to decompress the data shards in parallel, achieving very high I/O throughput.
+//!
+//! ## Forward compatibility
+//!
+//! Older binaries should read indexes written by newer ones wherever that is safe:
+//!
+//! - Unknown JSON fields are ignored at every level (no `deny_unknown_fields`).
+//! - The index CRC32 and HMAC are checked against the stored JSON bytes, not a
+//!   re-serialization of what this version understood, so unknown fields do not
+//!   break integrity checks. `crc32` and `hmac` therefore stay the first fields.
+//! - Anything a reader must understand to restore data correctly sets a
+//!   `FEATURE_*` bit; unknown bits are rejected with an "upgrade" error.
+//! - New optional fields use `#[serde(default)]` and must be harmless to lose:
+//!   tools that rewrite the index (append, touch, compact, …) drop fields they
+//!   do not know.
+//! - Optional data that has to survive such rewrites goes into the versioned
+//!   `extensions` map ([`IndexExtension`]), which every version carries over.
+//! - The same rules hold for the MessagePack map of a version 2 index. Its header
+//!   repeats the feature bits, so unknown ones are refused before the payload is
+//!   decoded, and a header version above [`INDEX_FORMAT_VERSION`] asks for an
+//!   upgrade. Rewrites keep the version the archive was written with.
 
 //! Katana: ultra-fast multi-threaded archive writer.
 //!
 //! The format is deliberately simple:
 //! 1. A sequence of independent zstd streams ("shards"), concatenated back-to-back.
-//! 2. A zstd-compressed JSON index appended at the end, followed by:
-//!        [u64 index_comp_size] [u64 index_json_size] [8-byte magic "KATIDX01"]
+//! 2. A zstd-compressed index (JSON or version 2 binary) appended at the end, followed by:
+//!        [u64 index_comp_size] [u64 index_json_size] [8-byte magic "KATIDX01" / "KATIDX02"]
 //!
 //! Each shard knows the list of files it owns, so extraction can run one thread per shard.
-//! We do **NOT** use zstd-seekable; instead each shard is one normal zstd stream
-//! produced by `zstd::Encoder::new_mt(level 0, nb_threads)`.
+//! We do **NOT** use zstd-seekable; each shard is one normal zstd stream, optionally
+//! cut into independent frames with a seek table in the index ([`DEFAULT_FRAME_SIZE`]
+//! apart unless `--seekable-frames` says otherwise).
 
 use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::{Read, Seek, SeekFrom, Write, BufWriter};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write, BufWriter};
 use scopeguard;
+use crate::console::{eprintln, println};
 
 // ---------- Footer constants (added for compatibility with new BLAKE3 footer) --------
 /// 16-byte magic that marks optional integrity footer written by `katana_stream`.
@@ -85,7 +113,7 @@ pub fn read_katana_footer(f: &mut File) -> Result<(u64, u64, u64), Box<dyn Error
     // Helpful debug: print the raw magic that was read.
     eprintln!("[katana-debug] magic_bytes={:?}", magic_bytes);
 
-    if magic_bytes != KATANA_MAGIC {
+    if IndexFormat::from_footer_magic(magic_bytes).is_none() {
         return Err("Not a Katana archive".into());
     }
 
@@ -99,12 +127,13 @@ pub fn read_katana_footer(f: &mut File) -> Result<(u64, u64, u64), Box<dyn Error
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt; // mode()
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 use crate::crypto;
+use crate::dictionary::Dictionaries;
 use crate::progress::{ProgressTracker, ProgressState};
 
 /// Decrypts AES-GCM ciphertext provided as a reader (ciphertext body) and writes plaintext to writer.
@@ -142,6 +171,302 @@ fn decrypt_stream_prekey<R: Read, W: Write>(mut rdr: R, mut wtr: W, key: &[u8; 3
 
 /// Magic footer for Katana index (version 1)
 const KATANA_MAGIC: &[u8; 8] = b"KATIDX01";
+/// Magic footer of archives with a version 2 (binary) index.
+const KATANA_MAGIC_V2: &[u8; 8] = b"KATIDX02";
+
+/// First bytes of a decoded version 2 index.
+const INDEX_V2_MAGIC: &[u8; 4] = b"KIX2";
+/// Fixed part of the version 2 index header: magic, version, features, crc32, has_hmac.
+const INDEX_V2_HEADER_LEN: usize = 4 + 2 + 4 + 4 + 1;
+/// Highest binary index version this build reads (and the one it writes).
+pub const INDEX_FORMAT_VERSION: u16 = 2;
+
+/// Layout of the index written by create (see the format notes in the module docs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexFormat {
+    /// JSON object, footer `KATIDX01`; readable by every BlitzArch release.
+    V1,
+    /// Versioned binary header + MessagePack, footer `KATIDX02`.
+    #[default]
+    V2,
+}
+
+impl IndexFormat {
+    /// Footer magic announcing this format.
+    pub(crate) fn footer_magic(self) -> &'static [u8; 8] {
+        match self {
+            IndexFormat::V1 => KATANA_MAGIC,
+            IndexFormat::V2 => KATANA_MAGIC_V2,
+        }
+    }
+
+    /// Format announced by a footer magic; `None` if it is not a Katana footer.
+    fn from_footer_magic(magic: &[u8]) -> Option<Self> {
+        match magic {
+            m if m == KATANA_MAGIC => Some(IndexFormat::V1),
+            m if m == KATANA_MAGIC_V2 => Some(IndexFormat::V2),
+            _ => None,
+        }
+    }
+}
+
+/// Serializes an index in `format` and signs it: CRC32, plus the HMAC with
+/// `key` for encrypted archives. `unsigned` must have `crc32 = 0` and no
+/// `hmac`; V1 writes both at the head of the JSON (where
+/// [`unsigned_index_json`] strips them again), V2 in the block header.
+pub(crate) fn encode_signed_index<T: Serialize>(
+    unsigned: &T,
+    format: IndexFormat,
+    features: u32,
+    key: Option<&[u8; 32]>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if format == IndexFormat::V2 {
+        return encode_index_v2(unsigned, features, key);
+    }
+    let unsigned_json = serde_json::to_vec(unsigned)?;
+    let rest = unsigned_json.strip_prefix(b"{\"crc32\":0,").ok_or("unsigned index must start with crc32 = 0")?;
+    let mut json = format!("{{\"crc32\":{},", crc32fast::hash(&unsigned_json)).into_bytes();
+    if let Some(key) = key {
+        use hmac::{Hmac, Mac};
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key).expect("HMAC new");
+        mac.update(&unsigned_json);
+        let tag: [u8; 32] = mac.finalize().into_bytes().into();
+        json.extend_from_slice(b"\"hmac\":");
+        json.extend_from_slice(&serde_json::to_vec(&tag)?);
+        json.push(b',');
+    }
+    json.extend_from_slice(rest);
+    Ok(json)
+}
+
+/// Serializes an index as a version 2 block and signs it (CRC32, plus the HMAC
+/// with `key`). `unsigned` must have `crc32 = 0` and no `hmac`; both live in
+/// the header instead.
+pub(crate) fn encode_index_v2<T: Serialize>(unsigned: &T, features: u32, key: Option<&[u8; 32]>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let payload = rmp_serde::to_vec_named(unsigned)?;
+    // Signing input: the header with crc32 = 0 and no HMAC, then the payload
+    let mut header = [0u8; INDEX_V2_HEADER_LEN];
+    header[..4].copy_from_slice(INDEX_V2_MAGIC);
+    header[4..6].copy_from_slice(&INDEX_FORMAT_VERSION.to_le_bytes());
+    header[6..10].copy_from_slice(&features.to_le_bytes());
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&header);
+    crc.update(&payload);
+    let hmac = key.map(|key| {
+        use hmac::{Hmac, Mac};
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key).expect("HMAC new");
+        mac.update(&header);
+        mac.update(&payload);
+        mac.finalize().into_bytes()
+    });
+    header[10..14].copy_from_slice(&crc.finalize().to_le_bytes());
+    header[14] = hmac.is_some() as u8;
+
+    let mut block = Vec::with_capacity(INDEX_V2_HEADER_LEN + 32 + payload.len());
+    block.extend_from_slice(&header);
+    if let Some(tag) = &hmac {
+        block.extend_from_slice(tag);
+    }
+    block.extend_from_slice(&payload);
+    Ok(block)
+}
+
+/// Parses a version 2 index block, checks the header and the CRC32.
+fn parse_index_v2(block: &[u8]) -> Result<KatanaIndex, Box<dyn Error>> {
+    let corrupt = || -> Box<dyn Error> { "Corrupted index header".into() };
+    let header = block.get(..INDEX_V2_HEADER_LEN).ok_or_else(corrupt)?;
+    let version = u16::from_le_bytes(header[4..6].try_into()?);
+    if version > INDEX_FORMAT_VERSION {
+        return Err(format!("Archive index format version {version} is newer than this build reads ({INDEX_FORMAT_VERSION}); upgrade BlitzArch").into());
+    }
+    let features = u32::from_le_bytes(header[6..10].try_into()?);
+    let crc32 = u32::from_le_bytes(header[10..14].try_into()?);
+    let (hmac, payload) = match header[14] {
+        0 => (None, &block[INDEX_V2_HEADER_LEN..]),
+        1 => {
+            let tag = block.get(INDEX_V2_HEADER_LEN..INDEX_V2_HEADER_LEN + 32).ok_or_else(corrupt)?;
+            (Some(<[u8; 32]>::try_from(tag)?), &block[INDEX_V2_HEADER_LEN + 32..])
+        }
+        _ => return Err(corrupt()),
+    };
+    let mut signed = Vec::with_capacity(INDEX_V2_HEADER_LEN + payload.len());
+    signed.extend_from_slice(&header[..10]);
+    signed.extend_from_slice(&[0u8; 5]);
+    signed.extend_from_slice(payload);
+    if crc32fast::hash(&signed) != crc32 {
+        return Err("Index CRC mismatch".into());
+    }
+    // Must-understand features may change the payload itself
+    let unknown = features & !SUPPORTED_FEATURES;
+    if unknown != 0 {
+        return Err(format!("Archive uses unsupported format features (0x{:x}); upgrade BlitzArch", unknown).into());
+    }
+    let mut index: KatanaIndex = rmp_serde::from_slice(payload)?;
+    if index.features != features {
+        return Err("Index feature bits do not match the index header".into());
+    }
+    index.crc32 = crc32;
+    index.hmac = hmac;
+    validate_index_layout(&index)?;
+    index.signed_json = Some(Arc::new(signed));
+    index.format = IndexFormat::V2;
+    Ok(index)
+}
+
+/// First bytes of every zstd frame.
+const ZSTD_FRAME_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// zstd level of the index unless configured otherwise.
+pub const DEFAULT_INDEX_ZSTD_LEVEL: i32 = 3;
+
+/// How the index is stored in front of the footer.
+///
+/// The choice is recorded by the index bytes themselves: a compressed index is a
+/// zstd frame (magic `28 B5 2F FD`), a stored one is the plain JSON object or
+/// version 2 block (`{` or `KIX2`, and `index_comp_size == index_json_size` in the footer). Readers pick
+/// the decoder from that, so archives written with any setting open everywhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexCompression {
+    /// zstd at the given level (1..=22).
+    Zstd(i32),
+    /// No compression – cheapest for tiny indexes.
+    Store,
+}
+
+impl Default for IndexCompression {
+    fn default() -> Self {
+        IndexCompression::Zstd(DEFAULT_INDEX_ZSTD_LEVEL)
+    }
+}
+
+impl std::str::FromStr for IndexCompression {
+    type Err = String;
+
+    /// Parses `store`, `zstd` or `zstd:LEVEL`.
+    fn from_str(raw: &str) -> Result<Self, String> {
+        match raw.trim() {
+            "store" | "none" => Ok(IndexCompression::Store),
+            "zstd" => Ok(IndexCompression::default()),
+            other => {
+                let level = other
+                    .strip_prefix("zstd:")
+                    .ok_or_else(|| format!("unknown index compression '{raw}' (expected store, zstd or zstd:LEVEL)"))?;
+                let level: i32 = level.parse().map_err(|_| format!("invalid zstd level '{level}'"))?;
+                if !(1..=22).contains(&level) {
+                    return Err(format!("zstd level {level} out of range 1..=22"));
+                }
+                Ok(IndexCompression::Zstd(level))
+            }
+        }
+    }
+}
+
+impl IndexCompression {
+    /// Codec of an index given its first bytes. The level is not recoverable,
+    /// so zstd indexes report the default level.
+    pub fn detect(index_bytes: &[u8]) -> Option<Self> {
+        if index_bytes.starts_with(&ZSTD_FRAME_MAGIC) {
+            Some(IndexCompression::default())
+        } else if index_bytes.first() == Some(&b'{') || index_bytes.starts_with(INDEX_V2_MAGIC) {
+            Some(IndexCompression::Store)
+        } else {
+            None
+        }
+    }
+}
+
+/// Encodes the serialized index for writing.
+pub(crate) fn encode_index(index_json: &[u8], compression: IndexCompression) -> Result<Vec<u8>, Box<dyn Error>> {
+    match compression {
+        IndexCompression::Store => Ok(index_json.to_vec()),
+        IndexCompression::Zstd(level) => {
+            let mut enc = zstd::Encoder::new(Vec::new(), level)?;
+            enc.include_checksum(true)?;
+            enc.write_all(index_json)?;
+            Ok(enc.finish()?)
+        }
+    }
+}
+
+/// Decodes the index bytes found in front of the footer (see [`IndexCompression`]).
+pub(crate) fn decode_index(index_bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if index_bytes.starts_with(SEALED_INDEX_MAGIC) {
+        return Err("Encrypted archive: the index is hidden (--hide-names), password required".into());
+    }
+    match IndexCompression::detect(index_bytes) {
+        Some(IndexCompression::Store) => Ok(index_bytes.to_vec()),
+        Some(IndexCompression::Zstd(_)) => Ok(zstd::decode_all(index_bytes)?),
+        None => Err("Unknown index encoding".into()),
+    }
+}
+
+/// [`decode_index`] that also opens sealed indexes with `password`.
+fn decode_index_block(index_bytes: &[u8], password: Option<&str>) -> Result<Vec<u8>, Box<dyn Error>> {
+    match password {
+        Some(pass) if index_bytes.starts_with(SEALED_INDEX_MAGIC) => decode_index(&unseal_index(index_bytes, pass)?),
+        _ => decode_index(index_bytes),
+    }
+}
+
+/// Magic of an index block encrypted with `create --hide-names`.
+const SEALED_INDEX_MAGIC: &[u8; 8] = b"KATSEAL1";
+
+/// Clear-text part of a sealed index: what it takes to derive the archive key.
+#[derive(Serialize, Deserialize)]
+struct SealedIndexHeader {
+    salt: [u8; 16],
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    key_slots: Vec<KeySlot>,
+}
+
+/// Encrypts an encoded index block under the index key of the archive (see
+/// [`crypto::derive_index_key`]), so entry names and sizes cannot be read
+/// without the password:
+///
+/// `KATSEAL1 | u32 header length | header JSON | 12-byte nonce | ciphertext + 16-byte tag`
+pub(crate) fn seal_index(encoded: &[u8], salt: [u8; 16], key: &[u8; 32]) -> Result<Vec<u8>, Box<dyn Error>> {
+    seal_index_with_slots(encoded, SealedIndexHeader { salt, key_slots: Vec::new() }, key)
+}
+
+fn seal_index_with_slots(encoded: &[u8], header: SealedIndexHeader, key: &[u8; 32]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let header = serde_json::to_vec(&header)?;
+    let (ciphertext, nonce) =
+        crypto::encrypt_prekey(encoded, &crypto::derive_index_key(key)).map_err(|_| "Index encryption failed")?;
+    let mut block = Vec::with_capacity(12 + header.len() + nonce.len() + ciphertext.len());
+    block.extend_from_slice(SEALED_INDEX_MAGIC);
+    block.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    block.extend_from_slice(&header);
+    block.extend_from_slice(&nonce);
+    block.extend_from_slice(&ciphertext);
+    Ok(block)
+}
+
+/// Decrypts a sealed index block; returns the encoded (zstd or stored) index.
+fn unseal_index(block: &[u8], password: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let corrupt = || -> Box<dyn Error> { "Corrupted encrypted index".into() };
+    let rest = block.strip_prefix(SEALED_INDEX_MAGIC).ok_or_else(corrupt)?;
+    let header_len = u32::from_le_bytes(rest.get(..4).ok_or_else(corrupt)?.try_into()?) as usize;
+    let header: SealedIndexHeader = serde_json::from_slice(rest.get(4..4 + header_len).ok_or_else(corrupt)?)?;
+    let sealed = rest.get(4 + header_len..).filter(|s| s.len() >= 12 + 16).ok_or_else(corrupt)?;
+    let key = unlock_archive_key(Some(header.salt), &header.key_slots, password)?;
+    crypto::decrypt_prekey(&sealed[12..], &crypto::derive_index_key(&key), &sealed[..12])
+        .map_err(|_| "Wrong password: the hidden index cannot be decrypted".into())
+}
+
+/// Whether the index of the archive is encrypted (`create --hide-names`).
+fn has_sealed_index(f: &mut File) -> Result<bool, Box<dyn Error>> {
+    let (_, index_offset, _) = read_katana_footer(f)?;
+    let mut head = [0u8; 8];
+    f.seek(SeekFrom::Start(index_offset))?;
+    Ok(f.read_exact(&mut head).is_ok() && &head == SEALED_INDEX_MAGIC)
+}
+
+/// Codec of the index stored at `index_offset`; defaults to zstd if unreadable.
+fn index_compression_at(f: &mut File, index_offset: u64) -> IndexCompression {
+    let mut head = [0u8; 4];
+    let read = f.seek(SeekFrom::Start(index_offset)).and_then(|_| f.read_exact(&mut head));
+    read.ok().and_then(|_| IndexCompression::detect(&head)).unwrap_or_default()
+}
 
 /// Normalize path by replacing backslashes with forward slashes and maintaining directory structure.
 /// Remove unnecessary path components like './' while preserving all directories.
@@ -172,7 +497,7 @@ pub(crate) fn normalize_path(path: &str) -> String {
     let sanitized = collapsed;
 
     // --- Debug log --------------------------------------------------------
-    if std::env::var("BLITZ_DEBUG_PATHS").is_ok() {
+    if std::env::var("BLITZ_DEBUG_PATHS").is_ok() && !crate::private_logs::enabled() {
         eprintln!("[dbg] normalize_path: {} -> {}", path, sanitized);
     }
     // ---------------------------------------------------------------------
@@ -222,13 +547,115 @@ fn is_windows_reserved(name_upper: &str) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::normalize_path;
+    use super::{
+        normalize_path, parse_index_json, plan_case_collisions, sections_needed, split_by_size, unsigned_json_of,
+        verify_index_hmac, CaseCollisionPolicy,
+    };
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_sections_needed_distrusts_file_counts() {
+        let shard = |offset: u32, count: u64| format!(r#"{{"offset":{offset},"compressed_size":9,"uncompressed_size":4,"file_count":{count},"crc32":0}}"#);
+        let file = |path: &str| format!(r#"{{"path":"{path}","size":2,"permissions":null}}"#);
+        let index = |shards: [String; 2]| {
+            let json = format!(r#"{{"crc32":0,"shards":[{},{}],"files":[{},{},{}]}}"#, shards[0], shards[1], file("a"), file("b"), file("c"));
+            parse_index_json(json.as_bytes()).unwrap()
+        };
+        let good = index([shard(0, 2), shard(9, 1)]);
+        let wanted: HashSet<String> = ["c".to_string()].into();
+        let needed = sections_needed(&good.files, &good.shards, None, &wanted).unwrap();
+        assert_eq!(needed.iter().map(|s| s.offset).collect::<Vec<_>>(), [9]);
+        assert_eq!(sections_needed(&good.files, &good.shards, None, &HashSet::new()).unwrap().len(), 2);
+
+        for count in [5, u64::MAX] {
+            let bad = index([shard(0, 2), shard(9, count)]);
+            assert!(sections_needed(&bad.files, &bad.shards, None, &wanted).is_err());
+        }
+    }
 
     #[test]
     fn test_normalize_simple() {
         assert_eq!(normalize_path("./dir1/dir2/file.txt"), "dir1/dir2/file.txt");
     }
 
+    #[test]
+    fn test_case_collision_plan() {
+        let paths = ["Readme.md", "docs/a.txt", "README.md", "readme (2).md", "DOCS/A.txt", "Makefile", "makefile"];
+        let plan = plan_case_collisions(&paths, None, CaseCollisionPolicy::Rename);
+        assert_eq!(
+            plan,
+            [
+                ("README.md".to_string(), Some("README (3).md".to_string())),
+                ("DOCS/A.txt".to_string(), Some("DOCS/A (2).txt".to_string())),
+                ("makefile".to_string(), Some("makefile (2)".to_string())),
+            ]
+        );
+        let skipped = plan_case_collisions(&paths, None, CaseCollisionPolicy::Skip);
+        assert!(skipped.iter().all(|(_, to)| to.is_none()));
+        assert_eq!(skipped.len(), 3);
+        // Only the destination counts: stripped prefixes may differ
+        let stripped = plan_case_collisions(&["a/X.txt", "b/x.txt"], Some(1), CaseCollisionPolicy::Rename);
+        assert_eq!(stripped, [("b/x.txt".to_string(), Some("b/x (2).txt".to_string()))]);
+        assert!(plan_case_collisions(&["a.txt", "b.txt"], None, CaseCollisionPolicy::Rename).is_empty());
+    }
+
+    #[test]
+    fn test_split_by_size_balances_bytes() {
+        let files: Vec<PathBuf> = (0..6).map(|i| PathBuf::from(format!("f{i}"))).collect();
+        // By count the two 100-byte files would share the first shard
+        let shards = split_by_size(&files, &[100, 100, 10, 10, 10, 10], 2);
+        let names = |shard: &Vec<PathBuf>| shard.iter().map(|p| p.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        assert_eq!(names(&shards[0]), ["f0", "f2", "f4"]);
+        assert_eq!(names(&shards[1]), ["f1", "f3", "f5"]);
+        // Empty files spread by count; never more shards than files
+        assert_eq!(split_by_size(&files, &[0; 6], 3).iter().map(Vec::len).collect::<Vec<_>>(), [2, 2, 2]);
+        assert_eq!(split_by_size(&files[..2], &[5, 5], 8).len(), 2);
+        assert!(split_by_size(&[], &[], 4).is_empty());
+    }
+
+    #[test]
+    fn test_index_from_newer_writer() {
+        use hmac::{Hmac, Mac};
+        // Unknown fields at every level plus an extension this version does not know
+        let unsigned = r#"{"crc32":0,"salt":[1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16],"shards":[{"offset":0,"compressed_size":9,"uncompressed_size":0,"file_count":1,"crc32":0,"future_shard":true}],"files":[{"path":"a.txt","size":0,"permissions":null,"future_entry":{"x":1}}],"future_top":[1,2],"extensions":{"vendor.note":{"version":2,"data":"hi"}}}"#;
+        let crc = crc32fast::hash(unsigned.as_bytes());
+        let key = [7u8; 32];
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(&key).unwrap();
+        mac.update(unsigned.as_bytes());
+        let tag: Vec<String> = mac.finalize().into_bytes().iter().map(|b| b.to_string()).collect();
+        let signed = unsigned.replacen("\"crc32\":0,", &format!("\"crc32\":{crc},\"hmac\":[{}],", tag.join(",")), 1);
+
+        let index = parse_index_json(signed.as_bytes()).unwrap();
+        assert_eq!(index.files[0].path, "a.txt");
+        assert_eq!(index.extensions["vendor.note"].version, 2);
+        verify_index_hmac(&index, &key).unwrap();
+        assert!(verify_index_hmac(&index, &[8u8; 32]).is_err());
+        // A re-serialization loses the unknown fields, so it could not have been checked
+        assert_ne!(unsigned_json_of(&index).unwrap(), unsigned.as_bytes());
+
+        // Unknown fields are still covered by the CRC
+        let tampered = signed.replace("[1,2]", "[1,3]");
+        assert!(parse_index_json(tampered.as_bytes()).unwrap_err().to_string().contains("CRC mismatch"));
+        // Must-understand changes are refused via feature bits
+        let future = r#"{"crc32":0,"shards":[],"files":[],"features":1073741824}"#;
+        assert!(parse_index_json(future.as_bytes()).unwrap_err().to_string().contains("upgrade"));
+    }
+
+    #[test]
+    fn test_inline_bytes_encoding() {
+        use super::FileEntry;
+        let entry: FileEntry = serde_json::from_str(r#"{"path":"a","size":3,"permissions":null,"inline":[1,2,3]}"#).unwrap();
+        assert_eq!(entry.inline.as_deref(), Some(&[1u8, 2, 3][..]));
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains(r#""inline":"AQID""#), "{json}");
+        let back: FileEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.inline, entry.inline);
+        let packed = rmp_serde::to_vec_named(&entry).unwrap();
+        let back: FileEntry = rmp_serde::from_slice(&packed).unwrap();
+        assert_eq!(back.inline, entry.inline);
+    }
+
     #[cfg(windows)]
     #[test]
     fn test_windows_sanitization() {
@@ -249,6 +676,130 @@ struct FileEntry {
     offset: u64,
     /// The file's Unix permissions, if available.
     permissions: Option<u32>,
+    /// Content of a tiny file stored in the index itself instead of a shard
+    /// (only with [`FEATURE_INLINE_SMALL`]).
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "inline_bytes")]
+    inline: Option<Vec<u8>>,
+    /// Modification time, seconds since the Unix epoch. Missing in older archives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mtime: Option<i64>,
+    /// Creation (birth) time, seconds since the Unix epoch, where the source
+    /// filesystem records one; restored on Windows and macOS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    btime: Option<i64>,
+    /// Owner as (uid, gid) on Unix sources; used by the `extract --uid` filter,
+    /// not restored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<(u32, u32)>,
+    /// Unchanged file of an incremental archive: the data lives in the base
+    /// archive (`KatanaIndex::base`, only with [`FEATURE_BASE_REFS`]).
+    #[serde(default, skip_serializing_if = "is_false")]
+    base_ref: bool,
+    /// Target of a symbolic link stored as such (`--symlinks keep`, only with
+    /// [`FEATURE_SYMLINKS`]); the entry has no data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    symlink: Option<String>,
+    /// Byte-identical copy of the entry at this path (`--dedup`, only with
+    /// [`FEATURE_DUPLICATES`]); the entry has no data of its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<String>,
+    /// Extended attributes and POSIX ACLs (`--xattrs`, only with [`FEATURE_XATTRS`]).
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    xattrs: crate::fsx::Xattrs,
+    /// Content stored as these chunks of the chunk store, in order
+    /// (`--dedup=chunks`, only with [`FEATURE_CHUNKS`]); ids index
+    /// `KatanaIndex::chunk_sizes`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<u32>,
+    /// BlitzArch archive stored without recompression (`create --store-nested`);
+    /// `extract --recursive-extract` unpacks it in place.
+    #[serde(default, skip_serializing_if = "is_false")]
+    nested_archive: bool,
+    /// Data regions `(offset, len)` of a sparse source file; the rest reads as
+    /// zeros. The stored data still covers the whole file, so readers that
+    /// ignore the map extract the same bytes, only without the holes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sparse: Vec<(u64, u64)>,
+}
+
+/// Serde form of [`FileEntry::inline`]: a base64 string in JSON indexes, raw
+/// bytes in MessagePack ones. Number arrays written by older versions still read.
+mod inline_bytes {
+    use base64::Engine as _;
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::Serializer;
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(data: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match data {
+            Some(bytes) if serializer.is_human_readable() => {
+                serializer.serialize_some(&base64::engine::general_purpose::STANDARD.encode(bytes))
+            }
+            Some(bytes) => serializer.serialize_some(&Bytes(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Ok(Option::<Inline>::deserialize(deserializer)?.map(|inline| inline.0))
+    }
+
+    struct Bytes<'a>(&'a [u8]);
+
+    impl serde::Serialize for Bytes<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    struct Inline(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for Inline {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(InlineVisitor).map(Inline)
+        }
+    }
+
+    struct InlineVisitor;
+
+    impl<'de> Visitor<'de> for InlineVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("base64 string, bytes or byte array")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+            base64::engine::general_purpose::STANDARD.decode(v).map_err(E::custom)
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut out = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element::<u8>()? {
+                out.push(b);
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// Link from an incremental archive to the archive holding its unchanged files.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BaseLink {
+    /// Path of the base archive; relative paths are resolved against the
+    /// directory of the incremental archive.
+    pub(crate) path: String,
+    /// Hex BLAKE3 footer hash of the base at the time the increment was made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) blake3: Option<String>,
 }
 
 /// Represents a single data shard's metadata within the Katana index.
@@ -262,10 +813,111 @@ struct ShardInfo {
     uncompressed_size: u64,
     /// The number of files contained within this shard.
     file_count: usize,
+    /// CRC32 of the stored bytes; 0 when [`ShardInfo::xxh3`] is set.
     crc32: u32,
+    /// XXH3-128 of the stored bytes (big-endian), checked instead of the CRC32
+    /// (`create --checksum xxh3`, only with [`FEATURE_XXH3`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    xxh3: Option<[u8; 16]>,
     /// 12-byte AES-GCM nonce; `None` ⇒ shard not encrypted.
     #[serde(skip_serializing_if = "Option::is_none")]
     nonce: Option<[u8; 12]>,
+    /// Subkey id ([`crypto::derive_shard_key`]); `None` ⇒ encrypted with the master key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<u64>,
+    /// Seek table of a shard written as independent zstd frames
+    /// ([`DEFAULT_FRAME_SIZE`] or `create --seekable-frames`): (compressed, uncompressed) size of every
+    /// frame in order. Empty ⇒ decoded from the start of the shard. Readers that
+    /// ignore it still see one valid zstd stream.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    frames: Vec<(u64, u64)>,
+    /// BLAKE3 id of the external zstd dictionary the shard was compressed with
+    /// (`create --dictionary`, only with [`FEATURE_DICTIONARY`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dictionary: Option<String>,
+    /// Compression statistics recorded at create time; missing in older
+    /// archives and in shards written by append, compact or repair.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<ShardStats>,
+}
+
+/// Per-shard compression statistics stored in the index by `create`.
+///
+/// They are hints only: the shard decodes the same without them. Extraction
+/// schedules shards by [`ShardStats::decode_cost`] and `blitzarch stats` shows
+/// them without touching shard data.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ShardStats {
+    /// zstd level the shard ended with (`--adapt` moves it while compressing).
+    pub level: i32,
+    /// Relative cost of decoding the shard, see [`ShardStats::estimate_decode_cost`].
+    pub decode_cost: u64,
+    /// Data kept in raw zstd blocks (`create --store-nested`).
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub stored: bool,
+}
+
+impl ShardStats {
+    /// Decode cost in byte-sized units: zstd entropy-decodes every stored byte
+    /// and writes every uncompressed one, raw blocks are a plain copy, and
+    /// AES-GCM adds a pass over the stored bytes.
+    pub fn estimate_decode_cost(compressed: u64, uncompressed: u64, stored: bool, encrypted: bool) -> u64 {
+        let codec = if stored { uncompressed / 8 } else { uncompressed + compressed };
+        codec + if encrypted { compressed } else { 0 }
+    }
+}
+
+impl ShardInfo {
+    /// Recorded decode cost, estimated from the sizes for shards without stats.
+    fn decode_cost(&self) -> u64 {
+        self.stats.map_or_else(
+            || ShardStats::estimate_decode_cost(self.compressed_size, self.uncompressed_size, false, self.nonce.is_some()),
+            |s| s.decode_cost,
+        )
+    }
+
+    /// Stats of the same data stored as `compressed` bytes, encrypted or not
+    /// (`blitzarch encrypt/decrypt`).
+    fn stats_for(&self, compressed: u64, encrypted: bool) -> Option<ShardStats> {
+        self.stats.map(|s| ShardStats {
+            decode_cost: ShardStats::estimate_decode_cost(compressed, self.uncompressed_size, s.stored, encrypted),
+            ..s
+        })
+    }
+
+    /// Checksum recorded for the stored bytes of this shard.
+    fn checksum(&self) -> ShardChecksum {
+        if self.xxh3.is_some() { ShardChecksum::Xxh3 } else { ShardChecksum::Crc32 }
+    }
+
+    /// Compressed and uncompressed start of the frame holding uncompressed
+    /// byte `pos`; (0, 0) without a seek table.
+    fn frame_start(&self, pos: u64) -> (u64, u64) {
+        let (mut comp, mut plain) = (0, 0);
+        for &(c, u) in &self.frames {
+            if plain + u > pos {
+                break;
+            }
+            comp += c;
+            plain += u;
+        }
+        (comp, plain)
+    }
+
+    /// zstd decoder of the shard's stream, primed with its dictionary from `dictionaries`.
+    fn decoder<R: Read>(&self, reader: R, dictionaries: &Dictionaries) -> Result<zstd::stream::read::Decoder<'static, BufReader<R>>, Box<dyn Error>> {
+        let reader = BufReader::with_capacity(zstd::zstd_safe::DCtx::in_size(), reader);
+        Ok(match &self.dictionary {
+            Some(id) => zstd::stream::read::Decoder::with_dictionary(reader, dictionaries.get(id)?.bytes())?,
+            None => zstd::stream::read::Decoder::with_buffer(reader)?,
+        })
+    }
+}
+
+/// Checksum for shards added to an existing archive: XXH3 once the archive
+/// uses it, CRC32 otherwise (so older readers keep opening it).
+fn new_shard_checksum(index: &KatanaIndex) -> ShardChecksum {
+    if index.features & FEATURE_XXH3 != 0 { ShardChecksum::Xxh3 } else { ShardChecksum::Crc32 }
 }
 
 /// The main index structure for a Katana archive.
@@ -281,113 +933,879 @@ struct KatanaIndex {
     /// Optional 16-byte salt used for key derivation when archive is encrypted.
     #[serde(skip_serializing_if = "Option::is_none")]
     salt: Option<[u8; 16]>,
+    /// Password slots wrapping the archive key (`blitzarch key add`, only with
+    /// [`FEATURE_KEY_SLOTS`]); without slots the key is derived from `salt`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    key_slots: Vec<KeySlot>,
     /// A list of all data shards in the archive.
     shards: Vec<ShardInfo>,
     /// A flat list of all files in the archive, sorted by shard and then by offset.
+    /// Inlined files (if any) follow the files of the last shard, base references
+    /// (if any) come last.
     files: Vec<FileEntry>,
+    /// Bit set of optional format features (`FEATURE_*`) used by this archive.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    features: u32,
+    /// Base archive of an incremental archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base: Option<BaseLink>,
+    /// Unique chunks of chunked entries, back to back in one zstd stream
+    /// (encrypted like a shard); `file_count` is the number of chunks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chunk_store: Option<ShardInfo>,
+    /// Uncompressed size of every chunk in the chunk store, in store order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chunk_sizes: Vec<u32>,
+    /// Fingerprint of the source set the archive was created from (see
+    /// [`crate::katana_stream::source_fingerprint`]); dropped by append.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_fingerprint: Option<String>,
+    /// Named optional extensions, carried over by every index rewrite (see the
+    /// forward compatibility notes in the module docs).
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    extensions: std::collections::BTreeMap<String, IndexExtension>,
+    /// The JSON (or version 2 block) the CRC32/HMAC were computed over, as read
+    /// from the archive; `None` for indexes built or changed in memory.
+    #[serde(skip)]
+    signed_json: Option<Arc<Vec<u8>>>,
+    /// Layout the index was read in; rewrites keep it.
+    #[serde(skip)]
+    format: IndexFormat,
 }
 
-/// Split a list into approx equal chunks
-fn split_even<T: Clone>(list: &[T], parts: usize) -> Vec<Vec<T>> {
-    let mut chunks = Vec::with_capacity(parts);
-    let chunk_size = (list.len() + parts - 1) / parts;
-    for c in list.chunks(chunk_size) {
-        chunks.push(c.to_vec());
-    }
-    chunks
+/// One password able to open an archive: the archive key encrypted with
+/// AES-256-GCM under `Argon2id(password, salt)`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct KeySlot {
+    salt: [u8; 16],
+    nonce: [u8; 12],
+    /// Encrypted 32-byte archive key followed by the 16-byte tag.
+    wrapped: Vec<u8>,
 }
 
-/// Returns the longest common ancestor directory shared by all provided paths.
-/// If the slice is empty, an empty `PathBuf` is returned.
-pub(crate) fn common_parent(paths: &[PathBuf]) -> PathBuf {
-    use std::path::Component;
+/// Versioned entry of the index `extensions` map. Versions that do not know an
+/// extension keep it untouched; the owner of a name bumps `version` when the
+/// layout of `data` changes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexExtension {
+    pub version: u32,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
 
-    if paths.is_empty() {
-        return PathBuf::new();
+/// Index extension holding the archive metadata (`--meta`, `--comment`): a JSON
+/// object of string values, see [`read_metadata`].
+pub const METADATA_EXTENSION: &str = "metadata";
+/// Current version of the [`METADATA_EXTENSION`] layout.
+pub const METADATA_EXTENSION_VERSION: u32 = 1;
+
+/// Index feature bit: files smaller than [`INLINE_MAX_SIZE`] may be stored in the
+/// index (`FileEntry::inline`) instead of a shard.
+pub const FEATURE_INLINE_SMALL: u32 = 1 << 0;
+/// Index feature bit: encrypted shards use per-shard HKDF subkeys (`ShardInfo::key_id`).
+pub const FEATURE_SHARD_SUBKEYS: u32 = 1 << 1;
+/// Index feature bit: entries may reference unchanged files in a base archive
+/// (`FileEntry::base_ref`, incremental archives).
+pub const FEATURE_BASE_REFS: u32 = 1 << 2;
+/// Index feature bit: entries may be symbolic links (`FileEntry::symlink`),
+/// stored without data after the inlined entries.
+pub const FEATURE_SYMLINKS: u32 = 1 << 3;
+/// Index feature bit: entries may be copies of other entries
+/// (`FileEntry::duplicate_of`), stored without data next to the symbolic links.
+pub const FEATURE_DUPLICATES: u32 = 1 << 4;
+/// Index feature bit: entries may carry extended attributes (`FileEntry::xattrs`).
+pub const FEATURE_XATTRS: u32 = 1 << 5;
+/// Index feature bit: entries may be stored as content-defined chunks of the
+/// chunk store (`FileEntry::chunks`, `KatanaIndex::chunk_store`).
+pub const FEATURE_CHUNKS: u32 = 1 << 6;
+/// Index feature bit: shards may be checked with XXH3-128 instead of CRC32
+/// (`ShardInfo::xxh3`).
+pub const FEATURE_XXH3: u32 = 1 << 7;
+/// Index feature bit: the archive key is wrapped by password slots
+/// (`KatanaIndex::key_slots`) instead of being derived from the password.
+pub const FEATURE_KEY_SLOTS: u32 = 1 << 8;
+/// Index feature bit: the index block is encrypted (`create --hide-names`), so
+/// index rewrites have to seal it again.
+pub const FEATURE_HIDDEN_NAMES: u32 = 1 << 9;
+/// Index feature bit: shards may be compressed with an external zstd
+/// dictionary (`ShardInfo::dictionary`, see [`crate::dictionary`]).
+pub const FEATURE_DICTIONARY: u32 = 1 << 10;
+/// All feature bits this reader understands; archives using others are rejected.
+pub(crate) const SUPPORTED_FEATURES: u32 = FEATURE_INLINE_SMALL
+    | FEATURE_SHARD_SUBKEYS
+    | FEATURE_BASE_REFS
+    | FEATURE_SYMLINKS
+    | FEATURE_DUPLICATES
+    | FEATURE_XATTRS
+    | FEATURE_CHUNKS
+    | FEATURE_XXH3
+    | FEATURE_KEY_SLOTS
+    | FEATURE_HIDDEN_NAMES
+    | FEATURE_DICTIONARY;
+/// Integrity checksum of the stored bytes of new shards.
+///
+/// CRC32 is readable by every version; XXH3-128 is much faster to verify on
+/// multi-GB shards and far less likely to miss corruption, but sets
+/// [`FEATURE_XXH3`], so older readers refuse the archive. Readers check every
+/// shard with the checksum it records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShardChecksum {
+    #[default]
+    Crc32,
+    Xxh3,
+}
+
+impl std::str::FromStr for ShardChecksum {
+    type Err = String;
+
+    /// Parses `crc32` or `xxh3`.
+    fn from_str(raw: &str) -> Result<Self, String> {
+        match raw.trim() {
+            "crc32" => Ok(ShardChecksum::Crc32),
+            "xxh3" | "xxh3-128" => Ok(ShardChecksum::Xxh3),
+            _ => Err(format!("unknown checksum '{raw}' (expected crc32 or xxh3)")),
+        }
     }
+}
 
-    // Start with components of the first path
-    let mut prefix: Vec<Component> = paths[0].components().collect();
-    for p in &paths[1..] {
-        let comps: Vec<Component> = p.components().collect();
-        let mut idx = 0usize;
-        while idx < prefix.len() && idx < comps.len() && prefix[idx] == comps[idx] {
-            idx += 1;
+/// Running checksum of a shard's stored bytes, of the kind [`ShardChecksum`]
+/// selects or an existing shard records.
+pub(crate) enum ShardDigest {
+    Crc32(crc32fast::Hasher),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl ShardDigest {
+    pub(crate) fn new(checksum: ShardChecksum) -> Self {
+        match checksum {
+            ShardChecksum::Crc32 => ShardDigest::Crc32(crc32fast::Hasher::new()),
+            ShardChecksum::Xxh3 => ShardDigest::Xxh3(Box::default()),
         }
-        prefix.truncate(idx);
-        if prefix.is_empty() {
-            break;
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            ShardDigest::Crc32(h) => h.update(data),
+            ShardDigest::Xxh3(h) => h.update(data),
         }
     }
 
-    let mut out = PathBuf::new();
-    for c in prefix {
-        out.push(c.as_os_str());
+    /// Values for `ShardInfo::crc32` and `ShardInfo::xxh3`.
+    pub(crate) fn finish(self) -> (u32, Option<[u8; 16]>) {
+        match self {
+            ShardDigest::Crc32(h) => (h.finalize(), None),
+            ShardDigest::Xxh3(h) => (0, Some(h.digest128().to_be_bytes())),
+        }
     }
 
-    // Edge-case: if result is empty and the first path is a file – use its parent.
-    if out.as_os_str().is_empty() {
-        if let Some(par) = paths[0].parent() {
-            return par.to_path_buf();
+    /// Compares the digest with the checksum recorded for `shard`.
+    fn check(self, shard: &ShardInfo) -> Result<(), Box<dyn Error>> {
+        match (self.finish(), shard.xxh3) {
+            ((calc, None), _) if calc != shard.crc32 => Err(format!(
+                "CRC mismatch in shard at offset {} (expected {:08x}, got {:08x})",
+                shard.offset, shard.crc32, calc
+            )
+            .into()),
+            ((_, Some(calc)), Some(expected)) if calc != expected => Err(format!(
+                "XXH3 mismatch in shard at offset {} (expected {:032x}, got {:032x})",
+                shard.offset,
+                u128::from_be_bytes(expected),
+                u128::from_be_bytes(calc)
+            )
+            .into()),
+            _ => Ok(()),
         }
     }
+}
 
-    out
+impl Write for ShardDigest {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
-/// Creates a new Katana archive from a set of input files and directories.
-///
-/// This function orchestrates the parallel compression of files into shards and writes the final archive.
-///
-/// # Arguments
-/// * `inputs` - A slice of paths to files or directories to be archived.
-/// * `output_path` - The path where the final `.blz` archive will be created.
-/// * `threads` - The number of parallel shards to create. If `0`, it will auto-detect based on the number of CPU cores.
-/// * `password` - Optional password for encryption.
-pub fn create_katana_archive(
-    inputs: &[PathBuf],
-    output_path: &Path,
-    threads: usize,
-    password: Option<String>,
-) -> Result<(), Box<dyn Error>> {
-    // Call new katana_stream implementation with default parameters
-    crate::katana_stream::create_katana_archive(
-        inputs, 
-        output_path, 
-        threads, 
-        0, // codec_threads - auto
-        None, // mem_budget_mb - auto
-        password, 
-        None, // compression_level - auto
-        None::<fn(crate::progress::ProgressState)>, // no progress callback
-    )
+/// Files below this size are candidates for index inlining.
+pub const INLINE_MAX_SIZE: u64 = 4 * 1024;
+
+/// Stored mtimes further ahead of the local clock than this are treated as clock skew.
+pub const MTIME_FUTURE_TOLERANCE_SECS: i64 = 24 * 3600;
+
+/// Uncompressed size of the zstd frames shards are cut into when no other
+/// frame size is asked for. The seek table lets extraction split a shard
+/// across cores and range reads start near their offset; frames this large
+/// cost next to nothing in ratio.
+pub const DEFAULT_FRAME_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Returns the value to restore instead of `mtime` and the reason, if `mtime`
+/// is absurd: before 1970 (clamped to the epoch) or beyond `now` plus
+/// [`MTIME_FUTURE_TOLERANCE_SECS`] (clamped to `now`).
+pub fn check_mtime(mtime: i64, now: i64) -> Option<(i64, &'static str)> {
+    if mtime < 0 {
+        Some((0, "before 1970"))
+    } else if mtime > now.saturating_add(MTIME_FUTURE_TOLERANCE_SECS) {
+        Some((now, "in the future"))
+    } else {
+        None
+    }
 }
 
-/// Creates a new Katana archive with optional progress tracking.
-///
-/// This is the internal implementation that supports progress callbacks.
-///
-/// # Arguments
-/// * `inputs` - A slice of paths to files or directories to be archived.
-/// * `output_path` - The path where the final `.blz` archive will be created.
-/// * `threads` - The number of parallel shards to create. If `0`, it will auto-detect based on the number of CPU cores.
-/// * `password` - Optional password for encryption.
-/// * `progress_callback` - Optional callback for progress updates.
-pub fn create_katana_archive_with_progress<F>(
-    inputs: &[PathBuf],
-    output_path: &Path,
-    threads: usize,
-    codec_threads: u32,
-    mem_budget_mb: Option<u64>,
-    password: Option<String>,
-    progress_callback: Option<F>,
-) -> Result<(), Box<dyn Error>>
-where
-    F: Fn(ProgressState) + Send + Sync + 'static,
-{
-    // 1. Enumerate all files
-    let mut files = Vec::new();
-    for path in inputs {
+pub(crate) fn is_zero(v: &u32) -> bool {
+    *v == 0
+}
+
+pub(crate) fn is_false(v: &bool) -> bool {
+    !*v
+}
+
+/// Rejects indexes using unknown feature bits and checks that inlined entries
+/// are well-formed and placed after all shard entries, and base references
+/// after those. Duplicates must point at an entry with data of the same size,
+/// chunked entries at existing chunks adding up to their size.
+fn validate_index_layout(index: &KatanaIndex) -> Result<(), Box<dyn Error>> {
+    let unknown = index.features & !SUPPORTED_FEATURES;
+    if unknown != 0 {
+        return Err(format!("Archive uses unsupported format features (0x{:x}); upgrade BlitzArch", unknown).into());
+    }
+    if index.features & FEATURE_SHARD_SUBKEYS == 0 && data_sections(index).any(|s| s.key_id.is_some()) {
+        return Err("Shard subkey ids without subkey feature bit".into());
+    }
+    if index.features & FEATURE_XXH3 == 0 && data_sections(index).any(|s| s.xxh3.is_some()) {
+        return Err("XXH3 shard checksums without XXH3 feature bit".into());
+    }
+    if index.features & FEATURE_DICTIONARY == 0 && data_sections(index).any(|s| s.dictionary.is_some()) {
+        return Err("Shard dictionary ids without dictionary feature bit".into());
+    }
+    for shard in data_sections(index).filter(|s| !s.frames.is_empty()) {
+        // Encrypted shards end with the 16-byte GCM tag
+        let body = shard.compressed_size.saturating_sub(if shard.nonce.is_some() { 16 } else { 0 });
+        let comp: u64 = shard.frames.iter().map(|f| f.0).sum();
+        let plain: u64 = shard.frames.iter().map(|f| f.1).sum();
+        if comp != body || plain != shard.uncompressed_size {
+            return Err("Shard seek table does not match the shard".into());
+        }
+    }
+    if let Some(store) = &index.chunk_store {
+        if index.features & FEATURE_CHUNKS == 0 {
+            return Err("Chunk store without chunk feature bit".into());
+        }
+        let total: u64 = index.chunk_sizes.iter().map(|&s| s as u64).sum();
+        if store.file_count != index.chunk_sizes.len() || store.uncompressed_size != total {
+            return Err("Chunk table does not match the chunk store".into());
+        }
+    } else if !index.chunk_sizes.is_empty() {
+        return Err("Chunk table without chunk store".into());
+    }
+    let sharded: usize = index.shards.iter().map(|s| s.file_count).sum();
+    if sharded > index.files.len() {
+        return Err("Index lists more shard files than entries".into());
+    }
+    let originals: std::collections::HashMap<&str, u64> = index
+        .files
+        .iter()
+        .filter(|e| !e.base_ref && e.symlink.is_none() && e.duplicate_of.is_none())
+        .map(|e| (e.path.as_str(), e.size))
+        .collect();
+    if index.features & FEATURE_XATTRS == 0 {
+        if let Some(entry) = index.files.iter().find(|e| !e.xattrs.is_empty()) {
+            return Err(format!("Entry {} has extended attributes without xattr feature bit", crate::private_logs::path(&entry.path)).into());
+        }
+    }
+    let mut seen_base_ref = false;
+    for (i, entry) in index.files.iter().enumerate() {
+        if entry.base_ref {
+            if index.features & FEATURE_BASE_REFS == 0 || index.base.is_none() {
+                return Err(format!("Base reference {} without base archive", crate::private_logs::path(&entry.path)).into());
+            }
+            if i < sharded || entry.inline.is_some() {
+                return Err(format!("Base reference {} inside shard or inline list", crate::private_logs::path(&entry.path)).into());
+            }
+            seen_base_ref = true;
+            continue;
+        }
+        if seen_base_ref {
+            return Err(format!("Entry {} follows base references", crate::private_logs::path(&entry.path)).into());
+        }
+        if entry.symlink.is_some() {
+            if index.features & FEATURE_SYMLINKS == 0 {
+                return Err(format!("Symbolic link {} without symlink feature bit", crate::private_logs::path(&entry.path)).into());
+            }
+            if entry.size != 0 || entry.inline.is_some() {
+                return Err(format!("Symbolic link {} carries data", crate::private_logs::path(&entry.path)).into());
+            }
+            continue;
+        }
+        if let Some(original) = &entry.duplicate_of {
+            if index.features & FEATURE_DUPLICATES == 0 {
+                return Err(format!("Duplicate {} without duplicates feature bit", crate::private_logs::path(&entry.path)).into());
+            }
+            if i < sharded || entry.inline.is_some() {
+                return Err(format!("Duplicate {} inside shard or inline list", crate::private_logs::path(&entry.path)).into());
+            }
+            if originals.get(original.as_str()) != Some(&entry.size) {
+                return Err(format!("Duplicate {} refers to missing or different entry {}", crate::private_logs::path(&entry.path), crate::private_logs::path(original)).into());
+            }
+            continue;
+        }
+        if !entry.chunks.is_empty() {
+            if index.features & FEATURE_CHUNKS == 0 {
+                return Err(format!("Chunked entry {} without chunk feature bit", crate::private_logs::path(&entry.path)).into());
+            }
+            if i < sharded || entry.inline.is_some() {
+                return Err(format!("Chunked entry {} inside shard or inline list", crate::private_logs::path(&entry.path)).into());
+            }
+            let size: Option<u64> = entry.chunks.iter().map(|&c| index.chunk_sizes.get(c as usize).map(|&s| s as u64)).sum();
+            if size != Some(entry.size) {
+                return Err(format!("Chunked entry {} refers to missing chunks or has the wrong size", crate::private_logs::path(&entry.path)).into());
+            }
+            continue;
+        }
+        match &entry.inline {
+            Some(_) if index.features & FEATURE_INLINE_SMALL == 0 => {
+                return Err(format!("Inlined entry {} without inline feature bit", crate::private_logs::path(&entry.path)).into());
+            }
+            Some(data) if data.len() as u64 != entry.size => {
+                return Err(format!("Inlined entry {} has wrong size", crate::private_logs::path(&entry.path)).into());
+            }
+            Some(_) if i < sharded => return Err(format!("Inlined entry {} inside shard list", crate::private_logs::path(&entry.path)).into()),
+            None if i >= sharded => return Err(format!("Entry {} belongs to no shard", crate::private_logs::path(&entry.path)).into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Shards followed by the chunk store, if any: every stretch of data the index points at.
+fn data_sections(index: &KatanaIndex) -> impl Iterator<Item = &ShardInfo> {
+    index.shards.iter().chain(index.chunk_store.iter())
+}
+
+/// Index of the first base reference (`files.len()` if there are none).
+fn base_refs_start(index: &KatanaIndex) -> usize {
+    index.files.iter().position(|e| e.base_ref).unwrap_or(index.files.len())
+}
+
+/// Hex BLAKE3 hash from the footer of `archive_path`, if it has one.
+fn footer_blake3_hex(archive_path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    if f.metadata()?.len() < FOOTER_SIZE {
+        return Ok(None);
+    }
+    let mut foot = [0u8; FOOTER_SIZE as usize];
+    f.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+    f.read_exact(&mut foot)?;
+    if &foot[..16] != FOOTER_MAGIC {
+        return Ok(None);
+    }
+    let hash: [u8; 32] = foot[24..].try_into()?;
+    Ok(Some(blake3::Hash::from(hash).to_hex().to_string()))
+}
+
+/// Locates the base archive of an incremental archive and checks that it is
+/// still the file the increment was made against.
+fn resolve_base(archive_path: &Path, link: &BaseLink) -> Result<PathBuf, Box<dyn Error>> {
+    let stored = Path::new(&link.path);
+    let base_path = if stored.is_absolute() {
+        stored.to_path_buf()
+    } else {
+        archive_path.parent().unwrap_or(Path::new(".")).join(stored)
+    };
+    if !base_path.exists() {
+        return Err(format!("Base archive {} not found (needed by {})", base_path.display(), archive_path.display()).into());
+    }
+    if let Some(expected) = &link.blake3 {
+        if footer_blake3_hex(&base_path)?.as_ref() != Some(expected) {
+            return Err(format!("Base archive {} changed since {} was created", base_path.display(), archive_path.display()).into());
+        }
+    }
+    Ok(base_path)
+}
+
+/// Files of a base archive as seen by an incremental create.
+pub(crate) struct BaseSnapshot {
+    pub(crate) link: BaseLink,
+    entries: std::collections::HashMap<String, (u64, Option<i64>, Option<u32>)>,
+}
+
+impl BaseSnapshot {
+    /// Reads the index of `base_path` (HMAC-verified when a password is given).
+    pub(crate) fn load(base_path: &Path, output_path: &Path, password: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let base_abs = crate::fsx::absolute_path(base_path);
+        let out_abs = crate::fsx::absolute_path(output_path);
+        if base_abs == out_abs {
+            return Err("Base archive cannot be the output archive".into());
+        }
+        let mut f = File::open(base_path)?;
+        let (index, _) = match password {
+            Some(_) => read_verified_index(&mut f, password)?,
+            None => read_index_crc_checked(&mut f)?,
+        };
+        // Next to the output the link survives moving both archives together
+        let path = match base_abs.file_name() {
+            Some(name) if base_abs.parent() == out_abs.parent() => name.to_string_lossy().into_owned(),
+            _ => base_abs.to_string_lossy().into_owned(),
+        };
+        Ok(BaseSnapshot {
+            link: BaseLink { path, blake3: footer_blake3_hex(base_path)? },
+            entries: index.files.into_iter().map(|e| (e.path, (e.size, e.mtime, e.permissions))).collect(),
+        })
+    }
+
+    /// True if the base holds `path` with the same size, mtime and permissions.
+    /// Entries without a recorded mtime never count as unchanged.
+    pub(crate) fn unchanged(&self, path: &str, size: u64, mtime: Option<i64>, permissions: Option<u32>) -> bool {
+        mtime.is_some() && self.entries.get(path) == Some(&(size, mtime, permissions))
+    }
+}
+
+/// Concatenated content of inlined entries, read lazily in index order.
+struct InlineReader<'a> {
+    entries: &'a [FileEntry],
+    pos: usize,
+}
+
+impl Read for InlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while let Some((first, rest)) = self.entries.split_first() {
+            let data = first.inline.as_deref().unwrap_or_default();
+            if self.pos < data.len() {
+                let n = buf.len().min(data.len() - self.pos);
+                buf[..n].copy_from_slice(&data[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            self.entries = rest;
+            self.pos = 0;
+        }
+        Ok(0)
+    }
+}
+
+/// Decoded chunk store of a `--dedup=chunks` archive: the unique chunks back to
+/// back in a file of the job's temp workspace.
+struct ChunkStore {
+    file: WorkspaceFile,
+    /// `(offset, size)` of every chunk in `file`.
+    spans: Vec<(u64, u64)>,
+}
+
+impl ChunkStore {
+    /// Checks the CRC32 of the chunk store `info` (`KatanaIndex::chunk_store`),
+    /// then decrypts and decompresses it into `workspace`.
+    fn open(
+        archive_path: &Path,
+        info: Option<&ShardInfo>,
+        chunk_sizes: &[u32],
+        key_bytes: Option<&[u8; 32]>,
+        workspace: &Arc<TempWorkspace>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let info = info.ok_or("Archive has no chunk store")?;
+        verify_shard_crcs(archive_path, std::slice::from_ref(info))?;
+        let (reader, _decrypted_tmp) = open_shard_stream(archive_path, info, key_bytes, workspace)?;
+        let mut file = workspace.create_file("katana_chunks")?;
+        file.reserve(info.uncompressed_size)?;
+        let decoded = {
+            let mut out = BufWriter::new(file.file_mut());
+            let n = std::io::copy(&mut zstd::stream::read::Decoder::new(reader)?, &mut out)
+                .map_err(|e| format!("Chunk store failed to decode: {}", e))?;
+            out.flush()?;
+            n
+        };
+        if decoded != info.uncompressed_size {
+            return Err(format!("Chunk store decodes to {} bytes, index says {}", decoded, info.uncompressed_size).into());
+        }
+        let mut spans = Vec::with_capacity(chunk_sizes.len());
+        let mut offset = 0u64;
+        for &size in chunk_sizes {
+            spans.push((offset, size as u64));
+            offset += size as u64;
+        }
+        Ok(ChunkStore { file, spans })
+    }
+
+    /// Content of `entries` (chunked entries), one after another.
+    fn reader<'a>(&self, entries: impl IntoIterator<Item = &'a FileEntry>) -> std::io::Result<ChunkedReader> {
+        let spans = entries
+            .into_iter()
+            .flat_map(|e| e.chunks.iter().map(|&c| self.spans.get(c as usize).copied()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "unknown chunk id"))?;
+        Ok(ChunkedReader { store: self.file.reopen()?, spans: spans.into_iter(), left: 0 })
+    }
+}
+
+/// Reads a list of chunk spans from the decoded chunk store.
+struct ChunkedReader {
+    store: File,
+    spans: std::vec::IntoIter<(u64, u64)>,
+    left: u64,
+}
+
+impl Read for ChunkedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.left == 0 {
+            let Some((offset, size)) = self.spans.next() else { return Ok(0) };
+            self.store.seek(SeekFrom::Start(offset))?;
+            self.left = size;
+        }
+        let max = buf.len().min(self.left as usize);
+        let n = self.store.read(&mut buf[..max])?;
+        if n == 0 && max > 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "chunk store ended early"));
+        }
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Split files into at most `parts` shards of roughly equal total size.
+///
+/// Longest-processing-time packing: files are taken largest first and each goes
+/// to the currently lightest shard (ties: fewer files, then lower shard id), so a
+/// few big files no longer end up in the same shard and stall the whole create.
+/// Every shard keeps its files in input order; empty shards are dropped.
+pub(crate) fn split_by_size(files: &[PathBuf], sizes: &[u64], parts: usize) -> Vec<Vec<PathBuf>> {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    let parts = parts.clamp(1, files.len().max(1));
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| Reverse(sizes[i])); // stable: equal sizes keep input order
+    let mut lightest: BinaryHeap<Reverse<(u64, usize, usize)>> = (0..parts).map(|b| Reverse((0, 0, b))).collect();
+    let mut bins: Vec<Vec<usize>> = vec![Vec::new(); parts];
+    for i in order {
+        let Reverse((load, count, b)) = lightest.pop().expect("parts >= 1");
+        bins[b].push(i);
+        lightest.push(Reverse((load + sizes[i], count + 1, b)));
+    }
+    bins.into_iter()
+        .filter(|bin| !bin.is_empty())
+        .map(|mut bin| {
+            bin.sort_unstable();
+            bin.into_iter().map(|i| files[i].clone()).collect()
+        })
+        .collect()
+}
+
+/// Returns the longest common ancestor directory shared by all provided paths.
+/// If the slice is empty, an empty `PathBuf` is returned.
+pub(crate) fn common_parent(paths: &[PathBuf]) -> PathBuf {
+    use std::path::Component;
+
+    if paths.is_empty() {
+        return PathBuf::new();
+    }
+
+    // Start with components of the first path
+    let mut prefix: Vec<Component> = paths[0].components().collect();
+    for p in &paths[1..] {
+        let comps: Vec<Component> = p.components().collect();
+        let mut idx = 0usize;
+        while idx < prefix.len() && idx < comps.len() && prefix[idx] == comps[idx] {
+            idx += 1;
+        }
+        prefix.truncate(idx);
+        if prefix.is_empty() {
+            break;
+        }
+    }
+
+    let mut out = PathBuf::new();
+    for c in prefix {
+        out.push(c.as_os_str());
+    }
+
+    // Edge-case: if result is empty and the first path is a file – use its parent.
+    if out.as_os_str().is_empty() {
+        if let Some(par) = paths[0].parent() {
+            return par.to_path_buf();
+        }
+    }
+
+    out
+}
+
+/// Creates a new Katana archive from a set of input files and directories.
+///
+/// This function orchestrates the parallel compression of files into shards and writes the final archive.
+///
+/// # Arguments
+/// * `inputs` - A slice of paths to files or directories to be archived.
+/// * `output_path` - The path where the final `.blz` archive will be created.
+/// * `threads` - The number of parallel shards to create. If `0`, it will auto-detect based on the number of CPU cores.
+/// * `password` - Optional password for encryption.
+pub fn create_katana_archive(
+    inputs: &[PathBuf],
+    output_path: &Path,
+    threads: usize,
+    password: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    // Call new katana_stream implementation with default parameters
+    crate::katana_stream::create_katana_archive(
+        inputs, 
+        output_path, 
+        threads, 
+        0, // codec_threads - auto
+        None, // mem_budget_mb - auto
+        password, 
+        None, // compression_level - auto
+        None::<fn(crate::progress::ProgressState)>, // no progress callback
+    )
+}
+
+/// Size of the blocks compressed shards travel in from the workers of
+/// [`create_katana_archive_with_progress`] to the output.
+const SHARD_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Message from a shard worker to the coordinator writing the archive.
+enum ShardBlock {
+    /// Next block of the shard's compressed (and encrypted) stream.
+    Data(usize, crate::memory_pool::PooledBuf),
+    /// The shard's stream is complete.
+    Done { shard_id: usize, uncompressed: u64, files: Vec<FileEntry>, nonce: Option<[u8; 12]>, frames: Vec<(u64, u64)> },
+}
+
+/// Output of a shard worker: cuts the stream into pool blocks (encrypting it
+/// on the way when `enc` is set) and hands full blocks to the coordinator.
+struct BlockWriter {
+    shard_id: usize,
+    pool: Arc<crate::memory_pool::BufferPool>,
+    /// Block being filled; taken from the pool on the first byte after a send.
+    block: Option<crate::memory_pool::PooledBuf>,
+    tx: crossbeam_channel::Sender<ShardBlock>,
+    enc: Option<aes_gcm_stream::Aes256GcmStreamEncryptor>,
+    /// Compressed bytes written so far (before encryption), for the seek table.
+    written: u64,
+}
+
+impl BlockWriter {
+    fn new(shard_id: usize, pool: Arc<crate::memory_pool::BufferPool>, tx: crossbeam_channel::Sender<ShardBlock>, enc: Option<aes_gcm_stream::Aes256GcmStreamEncryptor>) -> Self {
+        BlockWriter { shard_id, pool, block: None, tx, enc, written: 0 }
+    }
+
+    fn push(&mut self, mut data: &[u8]) -> std::io::Result<()> {
+        while !data.is_empty() {
+            let block = self.block.get_or_insert_with(|| self.pool.get());
+            let n = data.len().min(block.room());
+            block.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if block.room() == 0 {
+                self.send_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands the current block over; a worker waiting for the pool holds none.
+    fn send_block(&mut self) -> std::io::Result<()> {
+        let Some(full) = self.block.take() else { return Ok(()) };
+        self.tx
+            .send(ShardBlock::Data(self.shard_id, full))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "archive writer stopped"))
+    }
+
+    /// Sends the tail (and the GCM tag) of the stream.
+    fn finish(mut self) -> std::io::Result<()> {
+        if let Some(enc) = self.enc.take() {
+            let (ct_tail, tag) = enc.finalize();
+            self.push(&ct_tail)?;
+            self.push(&tag)?;
+        }
+        self.send_block()
+    }
+}
+
+impl Write for BlockWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.enc.as_mut().map(|enc| enc.update(buf)) {
+            Some(ct) => self.push(&ct)?,
+            None => self.push(buf)?,
+        }
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Blocks of a shard that cannot be written yet because another shard is
+/// being appended; spilled to a temp file when the pool runs dry.
+#[derive(Default)]
+struct ParkedShard {
+    blocks: Vec<crate::memory_pool::PooledBuf>,
+    spill: Option<tempfile::NamedTempFile>,
+    done: Option<(u64, Vec<FileEntry>, Option<[u8; 12]>, Vec<(u64, u64)>)>,
+}
+
+/// Shard currently appended to the output.
+struct ActiveShard {
+    id: usize,
+    offset: u64,
+    len: u64,
+    crc: crc32fast::Hasher,
+}
+
+/// Coordinator side of the block pipeline: appends one shard at a time to
+/// the archive (shards must be contiguous) while the others park their blocks.
+struct ShardAssembler<'a> {
+    out: &'a mut File,
+    pool: Arc<crate::memory_pool::BufferPool>,
+    active: Option<ActiveShard>,
+    parked: Vec<ParkedShard>,
+    finished: Vec<Option<(ShardInfo, Vec<FileEntry>)>>,
+}
+
+impl<'a> ShardAssembler<'a> {
+    fn new(out: &'a mut File, pool: Arc<crate::memory_pool::BufferPool>, num_shards: usize) -> Self {
+        ShardAssembler {
+            out,
+            pool,
+            active: None,
+            parked: (0..num_shards).map(|_| ParkedShard::default()).collect(),
+            finished: (0..num_shards).map(|_| None).collect(),
+        }
+    }
+
+    fn receive(&mut self, msg: ShardBlock) -> std::io::Result<()> {
+        match msg {
+            ShardBlock::Data(sid, block) => {
+                if self.active.is_none() {
+                    self.activate(sid)?;
+                }
+                if self.active.as_ref().is_some_and(|a| a.id == sid) {
+                    return self.append(&block);
+                }
+                self.parked[sid].blocks.push(block);
+                // Все блоки разобраны – иначе воркеры встанут, а активный шард не допишется
+                if self.pool.idle() == 0 {
+                    self.spill()?;
+                }
+                Ok(())
+            }
+            ShardBlock::Done { shard_id, uncompressed, files, nonce, frames } => {
+                self.parked[shard_id].done = Some((uncompressed, files, nonce, frames));
+                match &self.active {
+                    None => {
+                        self.activate(shard_id)?;
+                        self.complete()
+                    }
+                    Some(active) if active.id == shard_id => self.complete(),
+                    Some(_) => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(data)?;
+        let active = self.active.as_mut().expect("active shard");
+        active.crc.update(data);
+        active.len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Starts appending shard `sid`, beginning with what it has parked.
+    fn activate(&mut self, sid: usize) -> std::io::Result<()> {
+        let offset = self.out.seek(SeekFrom::End(0))?;
+        self.active = Some(ActiveShard { id: sid, offset, len: 0, crc: crc32fast::Hasher::new() });
+        if let Some(mut spill) = self.parked[sid].spill.take() {
+            let file = spill.as_file_mut();
+            file.seek(SeekFrom::Start(0))?;
+            let mut buf = vec![0u8; 1024 * 1024];
+            loop {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                self.append(&buf[..n])?;
+            }
+        }
+        for block in std::mem::take(&mut self.parked[sid].blocks) {
+            self.append(&block)?;
+        }
+        Ok(())
+    }
+
+    /// Records the finished active shard and moves on to the next one that
+    /// can be written: finished shards first, then any with parked data.
+    fn complete(&mut self) -> std::io::Result<()> {
+        loop {
+            let active = self.active.take().expect("active shard");
+            let (uncompressed, files, nonce, frames) = self.parked[active.id].done.take().expect("finished shard");
+            let info = ShardInfo {
+                offset: active.offset,
+                compressed_size: active.len,
+                uncompressed_size: uncompressed,
+                file_count: files.len(),
+                crc32: active.crc.finalize(),
+                xxh3: None,
+                key_id: nonce.map(|_| active.id as u64),
+                nonce,
+                frames,
+                dictionary: None,
+                stats: None,
+            };
+            self.finished[active.id] = Some((info, files));
+            let next = self
+                .parked
+                .iter()
+                .position(|p| p.done.is_some())
+                .or_else(|| self.parked.iter().position(|p| p.spill.is_some() || !p.blocks.is_empty()));
+            let Some(next) = next else { return Ok(()) };
+            self.activate(next)?;
+            if self.parked[next].done.is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Moves every parked block to its shard's temp file, freeing the blocks.
+    fn spill(&mut self) -> std::io::Result<()> {
+        for parked in self.parked.iter_mut().filter(|p| !p.blocks.is_empty()) {
+            if parked.spill.is_none() {
+                parked.spill = Some(crate::temp_manager::temp_file("shard")?);
+            }
+            let file = parked.spill.as_mut().expect("spill file").as_file_mut();
+            for block in parked.blocks.drain(..) {
+                file.write_all(&block)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creates a new Katana archive with optional progress tracking.
+///
+/// This is the internal implementation that supports progress callbacks.
+///
+/// # Arguments
+/// * `inputs` - A slice of paths to files or directories to be archived.
+/// * `output_path` - The path where the final `.blz` archive will be created.
+/// * `threads` - The number of parallel shards to create. If `0`, it will auto-detect based on the number of CPU cores.
+/// * `password` - Optional password for encryption.
+/// * `progress_callback` - Optional callback for progress updates.
+pub fn create_katana_archive_with_progress<F>(
+    inputs: &[PathBuf],
+    output_path: &Path,
+    threads: usize,
+    codec_threads: u32,
+    mem_budget_mb: Option<u64>,
+    password: Option<String>,
+    progress_callback: Option<F>,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(ProgressState) + Send + Sync + 'static,
+{
+    let _lock = crate::fsx::OutputLock::acquire(output_path)?;
+    // 1. Enumerate all files
+    let mut files = Vec::new();
+    for path in inputs {
         if path.is_file() {
             files.push(path.clone());
         } else if path.is_dir() {
@@ -399,9 +1817,12 @@ where
             }
         }
     }
+    crate::katana_stream::exclude_output_path(&mut files, inputs, output_path)?;
     if files.is_empty() {
         return Err("No input files".into());
     }
+    let source_fingerprint =
+        crate::katana_stream::fingerprint_files(&crate::vfs::OsFs::default(), &common_parent(inputs), &files)?;
     let num_shards = if threads == 0 { num_cpus::get() } else { threads };
     let num_shards = num_shards.max(1);
 
@@ -454,13 +1875,14 @@ where
         .open(output_path)?;
 
 
-    // 2. Assign files evenly to shards
-    let file_chunks = split_even(&files, num_shards);
+    // 2. Balance files across shards by size
+    let sizes: Vec<u64> = files.iter().map(|p| p.metadata().map(|m| m.len()).unwrap_or(0)).collect();
+    let file_chunks = split_by_size(&files, &sizes, num_shards);
 
-    // 3. Each shard compresses its chunk in parallel and writes directly via pwrite
-    use crossbeam_channel::bounded;
-    // Channel capacity 1 → workers block until coordinator writes, limiting peak RAM
-    let (meta_tx, meta_rx) = bounded::<(usize, Vec<u8>, u64, Vec<FileEntry>, Option<[u8; 12]>)>(1);
+    // 3. Each shard compresses its chunk in parallel and streams it to the coordinator
+    // in pool blocks; the pool (two blocks per shard), not the shard size, bounds peak RAM
+    let pool = crate::memory_pool::BufferPool::new(SHARD_BLOCK_SIZE, 2 * num_shards);
+    let (meta_tx, meta_rx) = crossbeam_channel::unbounded::<ShardBlock>();
     #[cfg(unix)]
     let _out_fd = out_file.as_raw_fd();
 
@@ -483,8 +1905,17 @@ where
         crc32: 0,
         hmac: None,
         salt: archive_salt,
+        key_slots: Vec::new(),
         shards: Vec::with_capacity(num_shards),
         files: Vec::new(),
+        features: if archive_salt.is_some() { FEATURE_SHARD_SUBKEYS } else { 0 },
+        base: None,
+        chunk_store: None,
+        chunk_sizes: Vec::new(),
+        source_fingerprint: Some(source_fingerprint),
+        extensions: Default::default(),
+        signed_json: None,
+        format: IndexFormat::default(),
     };
 
     rayon::scope(|s| {
@@ -502,20 +1933,36 @@ where
                 tracker.get_thread_metrics(shard_id)
             };
 
+            let pool = Arc::clone(&pool);
+
             s.spawn(move |_| {
-                // Calculate total uncompressed size to size zstd encoder buffer (optional)
-                let unc_sum: u64 = chunk
-                    .iter()
-                    .map(|p| p.metadata().map(|m| m.len()).unwrap_or(0))
-                    .sum();
+                // Pin to a NUMA node (no-op unless --numa auto); restored on drop
+                let _affinity = crate::numa::pin_worker(shard_id);
+
+                // Шифруем на лету: тот же AES-GCM (nonce, пустой AAD, тег в конце), что и разом,
+                // но подключом шарда (key_id = shard_id), как в katana_stream
+                let nonce_opt = key_arc_cl.as_ref().map(|_| {
+                    let mut nonce = [0u8; 12];
+                    rand::RngCore::fill_bytes(&mut rand::rngs::OsRng, &mut nonce);
+                    nonce
+                });
+                let enc = key_arc_cl
+                    .as_ref()
+                    .zip(nonce_opt.as_ref())
+                    .map(|(key, nonce)| aes_gcm_stream::Aes256GcmStreamEncryptor::new(crypto::derive_shard_key(key, shard_id as u64), nonce));
+                let blocks = BlockWriter::new(shard_id, pool, meta_tx.clone(), enc);
 
-                // Prepare zstd encoder
+                // Prepare zstd encoder; a new one starts every DEFAULT_FRAME_SIZE bytes
                 let zstd_threads = codec_thr_auto.max(1);
-                // Start with 4 MiB buffer regardless of shard size to avoid large allocations
-                let mut encoder = zstd::Encoder::new(Vec::with_capacity(4 * 1024 * 1024), 0)
-                    .expect("encoder");
-                encoder.include_checksum(true).expect("chk");
-                encoder.multithread(zstd_threads).expect("mt");
+                let new_encoder = |blocks: BlockWriter| {
+                    let mut encoder = zstd::Encoder::new(blocks, 0).expect("encoder");
+                    encoder.include_checksum(true).expect("chk");
+                    encoder.multithread(zstd_threads).expect("mt");
+                    encoder
+                };
+                let mut encoder = new_encoder(blocks);
+                let mut frames = Vec::new();
+                let (mut frame_start, mut in_frame) = (0u64, 0u64);
 
                 let mut local_index = Vec::new();
                 let mut uncompressed_written: u64 = 0;
@@ -535,6 +1982,17 @@ where
                         size: meta.len(),
                         offset: uncompressed_written, // record current offset
                         permissions: crate::fsx::maybe_unix_mode(&meta),
+                        inline: None,
+                        mtime: crate::fsx::mtime_secs(&meta),
+                        btime: crate::fsx::btime_secs(&meta),
+                        owner: crate::fsx::owner_ids(&meta),
+                        base_ref: false,
+                        symlink: None,
+                        duplicate_of: None,
+                        xattrs: Default::default(),
+                        chunks: Vec::new(),
+                        nested_archive: false,
+                        sparse: Vec::new(),
                     });
                     uncompressed_written += meta.len();
                     loop {
@@ -542,7 +2000,19 @@ where
                         if rd == 0 {
                             break;
                         }
-                        encoder.write_all(&in_buf[..rd]).expect("enc write");
+                        let mut data = &in_buf[..rd];
+                        while !data.is_empty() {
+                            let n = data.len().min((DEFAULT_FRAME_SIZE - in_frame) as usize);
+                            encoder.write_all(&data[..n]).expect("enc write");
+                            in_frame += n as u64;
+                            data = &data[n..];
+                            if in_frame == DEFAULT_FRAME_SIZE {
+                                let blocks = encoder.finish().expect("finish");
+                                frames.push((blocks.written - frame_start, in_frame));
+                                (frame_start, in_frame) = (blocks.written, 0);
+                                encoder = new_encoder(blocks);
+                            }
+                        }
                     }
                     
                     // Record file processed (zero-overhead when progress disabled)
@@ -550,108 +2020,58 @@ where
                         metrics.record_file_processed(meta.len());
                     }
                 }
-                let comp_buf = encoder.finish().expect("finish");
-
-                // Critical section: reserve offset and pwrite data
-
-
-                // Send to coordinator
-                let (final_buf, nonce_opt) = if let Some(ref key_bytes) = key_arc_cl {
-                        let mut comp_buf = comp_buf; // take ownership
-let nonce_vec = crypto::encrypt_prekey_in_place(&mut comp_buf, key_bytes).expect("encrypt");
-let enc = comp_buf;
-                        (enc, Some(nonce_vec))
-                    } else {
-                        (comp_buf, None)
-                    };
+                let blocks = encoder.finish().expect("finish");
+                frames.push((blocks.written - frame_start, in_frame));
+                blocks.finish().expect("send shard block");
 
-                    meta_tx
-                        .send((
-                            shard_id,
-                            final_buf,
-                            uncompressed_written,
-                            local_index,
-                            nonce_opt.map(|n| <[u8;12]>::try_from(n).unwrap()),
-                        ))
+                meta_tx
+                    .send(ShardBlock::Done {
+                        shard_id,
+                        uncompressed: uncompressed_written,
+                        files: local_index,
+                        nonce: nonce_opt,
+                        frames,
+                    })
                     .expect("send meta");
             });
         }
 
         // Coordinator loop runs inside the same scope, so we can write shards while workers continue
         drop(meta_tx);
-        // Temporary buffers to keep deterministic order
-        let mut shard_infos: Vec<Option<ShardInfo>> = vec![None; num_shards];
-        let mut files_by_shard: Vec<Option<Vec<FileEntry>>> = vec![None; num_shards];
-
-        for (sid, comp_data, unc_size, local_files, nonce_opt) in meta_rx.iter() {
-            let offset = out_file.seek(SeekFrom::End(0)).expect("seek");
-            out_file.write_all(&comp_data).expect("write shard");
-
-            let shard_crc = crc32fast::hash(&comp_data);
-            shard_infos[sid] = Some(ShardInfo {
-                offset: offset as u64,
-                compressed_size: comp_data.len() as u64,
-                uncompressed_size: unc_size,
-                file_count: local_files.len(),
-                crc32: shard_crc,
-                nonce: nonce_opt,
-            });
-            files_by_shard[sid] = Some(local_files);
-            
-            // Record shard completion and emit progress
-            {
+        let mut assembler = ShardAssembler::new(&mut out_file, Arc::clone(&pool), num_shards);
+        for msg in meta_rx.iter() {
+            let shard_done = matches!(msg, ShardBlock::Done { .. });
+            assembler.receive(msg).expect("write shard");
+            if shard_done {
+                // Record shard completion and emit progress
                 let tracker = progress_tracker.lock().unwrap();
                 tracker.record_shard_completed();
             }
         }
 
-        for sid in 0..num_shards {
-            if let Some(info) = shard_infos[sid].take() {
-                index.shards.push(info);
-                if let Some(files) = files_by_shard[sid].take() {
-                    index.files.extend(files);
-                }
-            }
+        // Index in shard order, whatever order the shards were written in
+        for (info, files) in assembler.finished.into_iter().flatten() {
+            index.shards.push(info);
+            index.files.extend(files);
         }
     }); // close rayon::scope
 
-    // 5. Write compressed JSON index + footer
+    // 5. Write compressed index + footer
     index.salt = archive_salt;
     // Optional debug print – show first 20 paths before we compress the index
-if std::env::var("BLITZ_DEBUG_PATHS").is_ok() {
+if std::env::var("BLITZ_DEBUG_PATHS").is_ok() && !crate::private_logs::enabled() {
     let sample: Vec<_> = index.files.iter().take(20).map(|f| f.path.clone()).collect();
     eprintln!("[dbg] index sample ({} paths): {:?}", sample.len(), sample);
 }
 
 // --- Integrity codes -------------------------------------------------------
-    use crc32fast::Hasher as Crc32Hasher;
-    let mut hasher = Crc32Hasher::new();
-    let tmp_json = serde_json::to_vec(&index)?;
-    hasher.update(&tmp_json);
-    let crc32_val = hasher.finalize();
-
-    let mut index_with_hash = index.clone();
-    index_with_hash.crc32 = crc32_val;
-
-    // If encrypted, compute HMAC-SHA256 with key derived from password+salt
-    if let (Some(pass), Some(salt)) = (password, archive_salt) {
-        use hmac::{Hmac, Mac};
-        use sha2::Sha256 as Sha256Mac;
-        type HmacSha256 = Hmac<Sha256Mac>;
-        let key = crypto::derive_key_argon2(&pass, &salt);
-        let mut mac = HmacSha256::new_from_slice(&key).expect("HMAC new");
-        mac.update(&tmp_json);
-        let result = mac.finalize();
-        let bytes = result.into_bytes();
-        let mut h = [0u8; 32];
-        h.copy_from_slice(&bytes);
-        index_with_hash.hmac = Some(h);
-    }
-
-    let index_json = serde_json::to_vec(&index_with_hash)?;
-    let mut encoder = zstd::Encoder::new(Vec::new(), 3)?;
-    encoder.write_all(&index_json)?;
-    let index_comp = encoder.finish()?;
+    // If encrypted, the HMAC-SHA256 key is derived from password+salt
+    let mac_key = match (password, archive_salt) {
+        (Some(pass), Some(salt)) => Some(crypto::derive_key_argon2(&pass, &salt)),
+        _ => None,
+    };
+    let index_json = encode_signed_index(&index, index.format, index.features, mac_key.as_ref())?;
+    let index_comp = encode_index(&index_json, IndexCompression::default())?;
 
     let index_comp_size = index_comp.len() as u64;
     let index_json_size = index_json.len() as u64;
@@ -660,7 +2080,7 @@ if std::env::var("BLITZ_DEBUG_PATHS").is_ok() {
 
     out_file.write_all(&index_comp_size.to_le_bytes())?;
     out_file.write_all(&index_json_size.to_le_bytes())?;
-    out_file.write_all(KATANA_MAGIC)?;
+    out_file.write_all(index.format.footer_magic())?;
 
     // Final progress update and statistics
     {
@@ -689,7 +2109,7 @@ if std::env::var("BLITZ_DEBUG_PATHS").is_ok() {
 ///
 /// This provides a quick and efficient way to identify Katana archives without parsing the full structure.
 pub fn is_katana_archive(path: &Path) -> std::io::Result<bool> {
-    let mut f = File::open(path)?;
+    let mut f = open_archive(path)?;
     let file_len = f.metadata()?.len();
     let data_len = data_len_without_footer(&mut f, file_len)?;
     if data_len < 8 {
@@ -698,7 +2118,7 @@ pub fn is_katana_archive(path: &Path) -> std::io::Result<bool> {
     f.seek(SeekFrom::Start(data_len - 8))?;
     let mut magic = [0u8; 8];
     f.read_exact(&mut magic)?;
-    Ok(&magic == KATANA_MAGIC)
+    Ok(IndexFormat::from_footer_magic(&magic).is_some())
 }
 
 /// Lists all files in a Katana archive without extracting them.
@@ -712,7 +2132,7 @@ pub fn list_katana_files(
     archive_path: &Path,
     password: Option<String>,
 ) -> Result<(), Box<dyn Error>> {
-    let mut f = File::open(archive_path)?;
+    let mut f = open_archive(archive_path)?;
     let file_len = f.metadata()?.len();
     let data_len = data_len_without_footer(&mut f, file_len)?;
     if data_len < 24 {
@@ -725,57 +2145,2347 @@ pub fn list_katana_files(
     f.seek(SeekFrom::Start(idx_comp_offset))?;
     let mut idx_comp = vec![0u8; idx_comp_size as usize];
     f.read_exact(&mut idx_comp)?;
-    let idx_json = zstd::decode_all(&*idx_comp)?;
-    let index: KatanaIndex = serde_json::from_slice(&idx_json)?;
-    // ---------------- Integrity verification ------------------
-    use crc32fast::Hasher as Crc32Hasher;
-    // При создании архива вычисляется CRC по JSON с нулевым полем crc32.
-    // Для корректной проверки воспроизводим тот же алгоритм.
-    let mut index_for_crc = index.clone();
-    index_for_crc.crc32 = 0;
-    index_for_crc.hmac = None; // CRC вычисляется по JSON без HMAC
-    let idx_json_zero = serde_json::to_vec(&index_for_crc)?;
-    let mut hasher = Crc32Hasher::new();
-    hasher.update(&idx_json_zero);
-    let crc_now = hasher.finalize();
-    if index.crc32 != 0 && index.crc32 != crc_now {
+    let idx_json = decode_index_block(&idx_comp, password.as_deref())?;
+    let index = parse_index_json(&idx_json)?;
+    if index.hmac.is_some() {
+        let (Some(pass), Some(_)) = (password.as_ref(), index.salt) else {
+            return Err("Encrypted archive: password required for HMAC verification".into());
+        };
+        verify_index_hmac(&index, &archive_key(&index, pass)?)?;
+    }
+    
+    // Print archive information
+    if index.salt.is_some() && password.is_none() {
+        println!("Archive is encrypted.");
+    }
+    
+    println!("Archive Index ({} files):", index.files.len());
+    
+    // Print the list of files
+    for file in &index.files {
+        println!("- {} ({} bytes)", file.path, file.size);
+    }
+    
+    Ok(())
+}
+
+/// Reads the compressed index of an opened Katana archive and verifies its CRC32
+/// (and HMAC when the archive is encrypted).
+///
+/// Returns the parsed index together with the byte offset at which the
+/// compressed index starts, i.e. the end of the shard data.
+fn read_verified_index(f: &mut File, password: Option<&str>) -> Result<(KatanaIndex, u64), Box<dyn Error>> {
+    let (index, idx_comp_offset) = read_index_with(f, password)?;
+    if index.hmac.is_some() {
+        let (Some(pass), Some(_)) = (password, index.salt) else {
+            return Err("Encrypted archive: password required for HMAC verification".into());
+        };
+        verify_index_hmac(&index, &archive_key(&index, pass)?)?;
+    }
+    Ok((index, idx_comp_offset))
+}
+
+/// The key shards and the index HMAC are protected with: unwrapped from the
+/// first key slot `password` opens or, for archives without slots, derived
+/// from the password and the archive salt.
+fn archive_key(index: &KatanaIndex, password: &str) -> Result<[u8; 32], Box<dyn Error>> {
+    unlock_archive_key(index.salt, &index.key_slots, password)
+}
+
+fn unlock_archive_key(salt: Option<[u8; 16]>, key_slots: &[KeySlot], password: &str) -> Result<[u8; 32], Box<dyn Error>> {
+    if key_slots.is_empty() {
+        let salt = salt.ok_or("Archive is not encrypted")?;
+        return Ok(crypto::derive_key_argon2(password, &salt));
+    }
+    match open_key_slot(key_slots, password) {
+        Some((_, key)) => Ok(key),
+        None => Err("Wrong password: no key slot of this archive matches".into()),
+    }
+}
+
+/// Checks the index HMAC (if any) against the archive key.
+fn verify_index_hmac(index: &KatanaIndex, key: &[u8; 32]) -> Result<(), Box<dyn Error>> {
+    let Some(expected_hmac) = &index.hmac else { return Ok(()) };
+    use hmac::{Hmac, Mac};
+    type HmacSha256 = Hmac<sha2::Sha256>;
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC new");
+    match &index.signed_json {
+        Some(json) => mac.update(json),
+        None => mac.update(&unsigned_json_of(index)?),
+    }
+    if mac.verify_slice(expected_hmac).is_err() {
+        return Err("Index HMAC verification failed".into());
+    }
+    Ok(())
+}
+
+/// Bytes read from the end of an archive in one go when locating the index.
+/// Covers both footers and, for all but huge archives, the whole compressed index.
+const INDEX_TAIL_PROBE: u64 = 256 * 1024;
+
+/// Reads the decompressed index JSON with as few reads as possible: one block
+/// from the end of the file (footers and usually the index) plus, for big
+/// indexes, one more read for the part in front of that block. Matters on slow
+/// or remote media where every seek is a round trip.
+///
+/// Returns the JSON, the offset of the compressed index and the number of reads.
+/// Sealed indexes (`--hide-names`) need `password`.
+fn read_index_json(f: &mut File, password: Option<&str>) -> Result<(Vec<u8>, u64, u32), Box<dyn Error>> {
+    let file_len = f.metadata()?.len();
+    let tail_start = file_len.saturating_sub(INDEX_TAIL_PROBE);
+    let mut tail = vec![0u8; (file_len - tail_start) as usize];
+    f.seek(SeekFrom::Start(tail_start))?;
+    f.read_exact(&mut tail)?;
+    let mut reads = 1;
+
+    // Optional BLAKE3 footer tells where the Katana data ends
+    let mut data_len = file_len;
+    if tail.len() as u64 >= FOOTER_SIZE {
+        let foot = &tail[tail.len() - FOOTER_SIZE as usize..];
+        if &foot[..16] == FOOTER_MAGIC {
+            data_len = u64::from_le_bytes(foot[16..24].try_into()?);
+        }
+    }
+    if data_len < 24 || data_len > file_len {
+        return Err("File too small or not a Katana archive".into());
+    }
+    if data_len - 24 < tail_start {
+        // Footer outside the probed block (trailing bytes after the data) – slow path
+        let (comp_size, comp_offset, _) = read_katana_footer(f)?;
+        let mut idx_comp = vec![0u8; comp_size as usize];
+        f.seek(SeekFrom::Start(comp_offset))?;
+        f.read_exact(&mut idx_comp)?;
+        return Ok((decode_index_block(&idx_comp, password)?, comp_offset, reads + 3));
+    }
+
+    let rel = |abs: u64| (abs - tail_start) as usize;
+    let footer = &tail[rel(data_len - 24)..rel(data_len)];
+    if IndexFormat::from_footer_magic(&footer[16..24]).is_none() {
+        return Err("Not a Katana archive".into());
+    }
+    let comp_size = u64::from_le_bytes(footer[..8].try_into()?);
+    let comp_offset = (data_len - 24).checked_sub(comp_size).ok_or("Index size exceeds archive")?;
+    let idx_json = if comp_offset >= tail_start {
+        decode_index_block(&tail[rel(comp_offset)..rel(data_len - 24)], password)?
+    } else {
+        let mut idx_comp = vec![0u8; (tail_start - comp_offset) as usize];
+        f.seek(SeekFrom::Start(comp_offset))?;
+        f.read_exact(&mut idx_comp)?;
+        reads += 1;
+        idx_comp.extend_from_slice(&tail[..rel(data_len - 24)]);
+        decode_index_block(&idx_comp, password)?
+    };
+    Ok((idx_json, comp_offset, reads))
+}
+
+/// Parses a decoded index (version 1 JSON or a version 2 block), checks its
+/// CRC32 and layout.
+fn parse_index_json(idx_json: &[u8]) -> Result<KatanaIndex, Box<dyn Error>> {
+    if idx_json.starts_with(INDEX_V2_MAGIC) {
+        return parse_index_v2(idx_json);
+    }
+    let mut index: KatanaIndex = serde_json::from_slice(idx_json)?;
+    index.format = IndexFormat::V1;
+
+    // CRC считается по JSON с crc32 = 0 и hmac = None
+    let idx_json_unsigned = match unsigned_index_json(idx_json) {
+        Some(json) => json,
+        None => unsigned_json_of(&index)?,
+    };
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&idx_json_unsigned);
+    if index.crc32 != 0 && index.crc32 != hasher.finalize() {
         return Err("Index CRC mismatch".into());
     }
-    if let Some(expected_hmac) = &index.hmac {
-        if let (Some(pass), Some(salt)) = (password.as_ref(), index.salt) {
-            use hmac::{Hmac, Mac};
-            use sha2::Sha256 as Sha256Mac;
-            type HmacSha256 = Hmac<Sha256Mac>;
-            let key = crypto::derive_key_argon2(&pass, &salt);
-            // Для проверки HMAC нужно сериализовать индекс с hmac = None,
-            // ровно так же, как при вычислении в create_katana_archive.
-            let mut idx_no_hmac = index.clone();
-            idx_no_hmac.crc32 = 0;
-            idx_no_hmac.hmac = None;
-            let idx_json_no_hmac = serde_json::to_vec(&idx_no_hmac)?;
-            let mut mac = HmacSha256::new_from_slice(&key).expect("HMAC new");
-            mac.update(&idx_json_no_hmac);
-            if mac.verify_slice(expected_hmac).is_err() {
-                return Err("Index HMAC verification failed".into());
-            }
+    validate_index_layout(&index)?;
+    index.signed_json = Some(Arc::new(idx_json_unsigned));
+    Ok(index)
+}
+
+/// The stored index JSON as it was signed: writers serialize `crc32` first
+/// (0 while signing) followed by the optional `hmac`, so only that prefix
+/// differs. Working on the stored bytes keeps fields unknown to this version
+/// covered by the CRC32/HMAC. `None` if the JSON does not start that way.
+fn unsigned_index_json(idx_json: &[u8]) -> Option<Vec<u8>> {
+    let rest = idx_json.strip_prefix(b"{\"crc32\":")?;
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    let mut rest = rest[digits..].strip_prefix(b",")?;
+    if let Some(mac) = rest.strip_prefix(b"\"hmac\":[") {
+        let end = mac.iter().position(|&b| b == b']')?;
+        rest = mac[end + 1..].strip_prefix(b",")?;
+    }
+    let mut json = b"{\"crc32\":0,".to_vec();
+    json.extend_from_slice(rest);
+    Some(json)
+}
+
+/// Signing input re-serialized from an in-memory index.
+fn unsigned_json_of(index: &KatanaIndex) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut index_unsigned = index.clone();
+    index_unsigned.crc32 = 0;
+    index_unsigned.hmac = None;
+    Ok(serde_json::to_vec(&index_unsigned)?)
+}
+
+/// Like [`read_verified_index`] but only checks the CRC32; the HMAC of encrypted
+/// archives is not verified. Suitable for read-only summaries that never touch shards.
+fn read_index_crc_checked(f: &mut File) -> Result<(KatanaIndex, u64), Box<dyn Error>> {
+    read_index_with(f, None)
+}
+
+/// [`read_index_crc_checked`] opening a sealed index with `password`.
+fn read_index_with(f: &mut File, password: Option<&str>) -> Result<(KatanaIndex, u64), Box<dyn Error>> {
+    let (idx_json, idx_comp_offset, _reads) = read_index_json(f, password)?;
+    Ok((parse_index_json(&idx_json)?, idx_comp_offset))
+}
+
+/// Replaces the index of an existing archive in place.
+///
+/// The file is truncated at `index_offset` (end of shard data), then the re-signed
+/// index, the 24-byte Katana footer and, with `hash_footer`, a fresh BLAKE3
+/// footer are appended. Shard data is never rewritten, but the BLAKE3 footer
+/// covers the whole file, so hashing it reads every byte of the archive again:
+/// O(archive size) even when only the index changed. Without `hash_footer` the
+/// archive is left without the optional footer (readers do not need it; only
+/// full-file checks and the base-archive check of increments use it).
+fn rewrite_index(
+    archive_path: &Path,
+    index_offset: u64,
+    index: &mut KatanaIndex,
+    key: Option<&[u8; 32]>,
+    compression: IndexCompression,
+    hash_footer: bool,
+) -> Result<(), Box<dyn Error>> {
+    index.crc32 = 0;
+    index.hmac = None;
+    let index_json = encode_signed_index(&*index, index.format, index.features, key)?;
+    let mut index_comp = encode_index(&index_json, compression)?;
+    if index.features & FEATURE_HIDDEN_NAMES != 0 {
+        let (Some(key), Some(salt)) = (key, index.salt) else {
+            return Err("Encrypted archive: password required to rewrite the hidden index".into());
+        };
+        let header = SealedIndexHeader { salt, key_slots: index.key_slots.clone() };
+        index_comp = seal_index_with_slots(&index_comp, header, key)?;
+    }
+
+    let mut f = OpenOptions::new().read(true).write(true).open(archive_path)?;
+    f.set_len(index_offset)?;
+    f.seek(SeekFrom::Start(index_offset))?;
+    f.write_all(&index_comp)?;
+    f.write_all(&(index_comp.len() as u64).to_le_bytes())?;
+    f.write_all(&(index_json.len() as u64).to_le_bytes())?;
+    f.write_all(index.format.footer_magic())?;
+    f.flush()?;
+    if !hash_footer {
+        f.sync_all()?;
+        return Ok(());
+    }
+
+    // BLAKE3 footer covers everything written so far
+    let data_len = f.seek(SeekFrom::End(0))?;
+    f.seek(SeekFrom::Start(0))?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 { break; }
+        hasher.update(&buf[..n]);
+    }
+    f.seek(SeekFrom::Start(data_len))?;
+    f.write_all(FOOTER_MAGIC)?;
+    f.write_all(&data_len.to_le_bytes())?;
+    f.write_all(hasher.finalize().as_bytes())?;
+    f.sync_all()?;
+    Ok(())
+}
+
+/// [`rewrite_index`] without touching the archive in place: the data before
+/// `index_offset` is copied next to it, the new index is written to the copy
+/// and the copy is renamed over the archive. A crash leaves the old archive.
+fn rewrite_index_atomic(
+    archive_path: &Path,
+    index_offset: u64,
+    index: &mut KatanaIndex,
+    key: Option<&[u8; 32]>,
+    compression: IndexCompression,
+) -> Result<(), Box<dyn Error>> {
+    let src = open_archive(archive_path)?;
+    let dir = archive_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut tmp = crate::temp_manager::temp_file_in(dir, "index")?;
+    let copied = std::io::copy(&mut (&src).take(index_offset), tmp.as_file_mut())?;
+    if copied != index_offset {
+        return Err("Archive is shorter than its index offset".into());
+    }
+    rewrite_index(tmp.path(), index_offset, index, key, compression, true)?;
+    replace_archive(tmp, src, archive_path)
+}
+
+/// [`rewrite_index`] on the archive itself, under an [`UndoJournal`]: a
+/// failure restores the old index, a crash is rolled back on the next open.
+fn rewrite_index_in_place(
+    archive_path: &Path,
+    index_offset: u64,
+    index: &mut KatanaIndex,
+    key: Option<&[u8; 32]>,
+    compression: IndexCompression,
+    hash_footer: bool,
+) -> Result<(), Box<dyn Error>> {
+    let journal = UndoJournal::begin(archive_path, index_offset)?;
+    match rewrite_index(archive_path, index_offset, index, key, compression, hash_footer) {
+        Ok(()) => Ok(journal.finish()?),
+        Err(e) => {
+            journal.roll_back(archive_path)?;
+            Err(e)
+        }
+    }
+}
+
+/// Magic of an [`UndoJournal`] record.
+const UNDO_MAGIC: &[u8; 8] = b"KATUNDO1";
+
+/// Old tail (index and footers from `index_offset` on) of an archive that is
+/// being changed in place, kept in `<archive>.undo` until the change is
+/// durable. Everything before `index_offset` is never overwritten, so the tail
+/// is all it takes to restore the old archive:
+///
+/// `KATUNDO1 | u64 index offset | u64 old length | old tail | BLAKE3 of the preceding bytes`
+///
+/// A record without a valid checksum was torn before the archive was touched.
+struct UndoJournal {
+    path: PathBuf,
+    index_offset: u64,
+    old_len: u64,
+    tail: Vec<u8>,
+}
+
+impl UndoJournal {
+    fn path_for(archive_path: &Path) -> PathBuf {
+        let mut name = archive_path.as_os_str().to_owned();
+        name.push(".undo");
+        PathBuf::from(name)
+    }
+
+    /// Records the tail of `archive_path` from `index_offset` on and makes the
+    /// record durable; the archive may be changed in place afterwards.
+    fn begin(archive_path: &Path, index_offset: u64) -> std::io::Result<Self> {
+        let mut f = File::open(archive_path)?;
+        let old_len = f.metadata()?.len();
+        if index_offset > old_len {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "index offset beyond the end of the archive"));
+        }
+        let mut tail = Vec::with_capacity((old_len - index_offset) as usize);
+        f.seek(SeekFrom::Start(index_offset))?;
+        f.take(old_len - index_offset).read_to_end(&mut tail)?;
+
+        let mut record = Vec::with_capacity(tail.len() + 56);
+        record.extend_from_slice(UNDO_MAGIC);
+        record.extend_from_slice(&index_offset.to_le_bytes());
+        record.extend_from_slice(&old_len.to_le_bytes());
+        record.extend_from_slice(&tail);
+        let digest = blake3::hash(&record);
+        record.extend_from_slice(digest.as_bytes());
+        let path = Self::path_for(archive_path);
+        let mut journal = File::create(&path)?;
+        journal.write_all(&record)?;
+        journal.sync_all()?;
+        crate::fsx::sync_parent_dir(&path);
+        Ok(UndoJournal { path, index_offset, old_len, tail })
+    }
+
+    /// Record left by an interrupted change of `archive_path`; `Ok(None)` if
+    /// there is none or it was torn.
+    fn load(archive_path: &Path) -> std::io::Result<Option<Self>> {
+        let path = Self::path_for(archive_path);
+        let record = match std::fs::read(&path) {
+            Ok(record) => record,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let valid = record.len() >= 24 + 32 && record.starts_with(UNDO_MAGIC) && {
+            let (body, digest) = record.split_at(record.len() - 32);
+            blake3::hash(body).as_bytes() == digest
+        };
+        if !valid {
+            std::fs::remove_file(&path)?;
+            return Ok(None);
+        }
+        let index_offset = u64::from_le_bytes(record[8..16].try_into().expect("8 bytes"));
+        let old_len = u64::from_le_bytes(record[16..24].try_into().expect("8 bytes"));
+        let tail = record[24..record.len() - 32].to_vec();
+        if index_offset.checked_add(tail.len() as u64) != Some(old_len) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("inconsistent undo record {}", path.display())));
+        }
+        Ok(Some(UndoJournal { path, index_offset, old_len, tail }))
+    }
+
+    /// The change is durable: drops the record.
+    fn finish(self) -> std::io::Result<()> {
+        std::fs::remove_file(&self.path)?;
+        crate::fsx::sync_parent_dir(&self.path);
+        Ok(())
+    }
+
+    /// Puts the old tail back and drops the record.
+    fn roll_back(self, archive_path: &Path) -> std::io::Result<()> {
+        let mut f = OpenOptions::new().write(true).open(archive_path)?;
+        f.seek(SeekFrom::Start(self.index_offset))?;
+        f.write_all(&self.tail)?;
+        f.set_len(self.old_len)?;
+        f.sync_all()?;
+        self.finish()
+    }
+}
+
+/// Rolls back an in-place change of `archive_path` that a crash cut short
+/// (see [`UndoJournal`]); true if there was one. Left alone while another
+/// run holds the archive's lock – its change is still in progress.
+pub(crate) fn recover_interrupted_update(archive_path: &Path) -> std::io::Result<bool> {
+    if !UndoJournal::path_for(archive_path).exists() {
+        return Ok(false);
+    }
+    let _lock = match crate::fsx::OutputLock::acquire(archive_path) {
+        Ok(lock) => lock,
+        Err(e) if e.kind() == std::io::ErrorKind::ResourceBusy => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let Some(journal) = UndoJournal::load(archive_path)? else { return Ok(false) };
+    journal.roll_back(archive_path)?;
+    println!("[katana] Rolled back an interrupted update of {}", archive_path.display());
+    Ok(true)
+}
+
+/// Opens an archive, first rolling back an interrupted in-place change.
+fn open_archive(archive_path: &Path) -> std::io::Result<File> {
+    recover_interrupted_update(archive_path)?;
+    File::open(archive_path)
+}
+
+/// Named extensions recorded in the index (see [`IndexExtension`]).
+pub fn index_extensions(archive_path: &Path) -> Result<std::collections::BTreeMap<String, IndexExtension>, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = read_index_crc_checked(&mut f)?;
+    Ok(index.extensions)
+}
+
+/// Adds or replaces (`Some`) or removes (`None`) a named index extension.
+/// Only the index is rewritten.
+pub fn set_index_extension(
+    archive_path: &Path,
+    name: &str,
+    extension: Option<IndexExtension>,
+    password: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let _lock = crate::fsx::OutputLock::acquire(archive_path)?;
+    let mut f = open_archive(archive_path)?;
+    let (mut index, index_offset) = read_verified_index(&mut f, password.as_deref())?;
+    let index_compression = index_compression_at(&mut f, index_offset);
+    drop(f);
+
+    let key = match (password.as_ref(), index.salt) {
+        (Some(pass), Some(_)) => Some(archive_key(&index, pass)?),
+        (None, Some(_)) => return Err("Encrypted archive: password required to update the index".into()),
+        _ => None,
+    };
+    match extension {
+        Some(ext) => index.extensions.insert(name.to_string(), ext),
+        None => index.extensions.remove(name),
+    };
+    rewrite_index_in_place(archive_path, index_offset, &mut index, key.as_ref(), index_compression, true)
+}
+
+/// Key-value metadata attached to the archive at create time; empty if none.
+/// As with [`list_entries`], a password also verifies the index HMAC and is
+/// needed for archives created with `--hide-names`.
+pub fn read_metadata(archive_path: &Path, password: Option<&str>) -> Result<std::collections::BTreeMap<String, String>, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = match password {
+        Some(_) => read_verified_index(&mut f, password)?,
+        None => read_index_crc_checked(&mut f)?,
+    };
+    metadata_of(&index.extensions)
+}
+
+pub(crate) fn metadata_of(
+    extensions: &std::collections::BTreeMap<String, IndexExtension>,
+) -> Result<std::collections::BTreeMap<String, String>, Box<dyn Error>> {
+    let Some(ext) = extensions.get(METADATA_EXTENSION) else {
+        return Ok(Default::default());
+    };
+    if ext.version > METADATA_EXTENSION_VERSION {
+        return Err(format!("Archive metadata version {} is newer than supported; please upgrade BlitzArch", ext.version).into());
+    }
+    Ok(serde_json::from_value(ext.data.clone()).map_err(|e| format!("Invalid archive metadata: {}", e))?)
+}
+
+/// The [`METADATA_EXTENSION`] entry for `metadata`, `None` if it is empty.
+pub(crate) fn metadata_extension(metadata: &std::collections::BTreeMap<String, String>) -> Option<IndexExtension> {
+    (!metadata.is_empty()).then(|| IndexExtension {
+        version: METADATA_EXTENSION_VERSION,
+        data: serde_json::to_value(metadata).expect("string map"),
+    })
+}
+
+/// Changes Unix permissions of archived entries without re-compressing anything.
+///
+/// Only the index is rewritten; shard data stays untouched, so this is cheap even
+/// for very large archives. `patterns` are glob patterns (`docs/**`, `*.sh`) matched
+/// against archive paths; an empty slice selects every entry. Returns the number
+/// of entries that were updated.
+pub fn touch_katana_archive(
+    archive_path: &Path,
+    patterns: &[String],
+    mode: u32,
+    password: Option<String>,
+) -> Result<usize, Box<dyn Error>> {
+    touch_entries(archive_path, patterns, TouchChanges { mode: Some(mode), ..Default::default() }, password)
+}
+
+/// Metadata [`touch_entries`] sets on the selected entries; `None` keeps it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TouchChanges {
+    /// Unix permission bits.
+    pub mode: Option<u32>,
+    /// Modification time, Unix seconds.
+    pub mtime: Option<i64>,
+    /// Leave the archive without its BLAKE3 footer instead of re-hashing the
+    /// whole file (see [`rewrite_index`]); for quick fixes on huge archives.
+    pub drop_footer: bool,
+}
+
+/// [`touch_katana_archive`] for permissions and/or modification times.
+pub fn touch_entries(
+    archive_path: &Path,
+    patterns: &[String],
+    changes: TouchChanges,
+    password: Option<String>,
+) -> Result<usize, Box<dyn Error>> {
+    if changes.mode.is_none() && changes.mtime.is_none() {
+        return Err("Nothing to change: give a mode and/or a modification time".into());
+    }
+    let matcher = build_glob_set(patterns)?;
+
+    let _lock = crate::fsx::OutputLock::acquire(archive_path)?;
+    let mut f = open_archive(archive_path)?;
+    let (mut index, index_offset) = read_verified_index(&mut f, password.as_deref())?;
+    let index_compression = index_compression_at(&mut f, index_offset);
+    drop(f);
+
+    let key = match (password.as_ref(), index.salt) {
+        (Some(pass), Some(_)) => Some(archive_key(&index, pass)?),
+        (None, Some(_)) => return Err("Encrypted archive: password required to update the index".into()),
+        _ => None,
+    };
+
+    let mut updated = 0usize;
+    for entry in index.files.iter_mut() {
+        if patterns.is_empty() || matcher.is_match(&entry.path) {
+            if let Some(mode) = changes.mode {
+                entry.permissions = Some(mode & 0o7777);
+            }
+            if let Some(mtime) = changes.mtime {
+                entry.mtime = Some(mtime);
+            }
+            updated += 1;
+        }
+    }
+    if updated == 0 {
+        return Ok(0);
+    }
+
+    rewrite_index_in_place(archive_path, index_offset, &mut index, key.as_ref(), index_compression, !changes.drop_footer)?;
+    if let Some(mode) = changes.mode {
+        println!("[katana] Updated permissions of {} entries (mode {:o})", updated, mode & 0o7777);
+    }
+    if let Some(mtime) = changes.mtime {
+        println!("[katana] Updated modification times of {} entries ({})", updated, mtime);
+    }
+    Ok(updated)
+}
+
+/// Outcome of [`compact_katana_archive`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CompactReport {
+    pub old_size: u64,
+    pub new_size: u64,
+    /// Shards dropped because no index entry references them.
+    pub shards_dropped: usize,
+}
+
+impl CompactReport {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.old_size.saturating_sub(self.new_size)
+    }
+}
+
+/// Rewrites an archive keeping only live data.
+///
+/// Shards without entries and any bytes not covered by a shard (leftovers of an
+/// interrupted write, stale prefixes) are dropped and a minimal index is rebuilt.
+/// Live shards are copied verbatim – nothing is re-compressed or re-encrypted, and
+/// their CRC32 is verified on the way. The new archive is written to a temp file
+/// next to the original and renamed over it only after it was synced, so a crash
+/// leaves either the old or the new archive in place.
+pub fn compact_katana_archive(archive_path: &Path, password: Option<String>) -> Result<CompactReport, Box<dyn Error>> {
+    let _lock = crate::fsx::OutputLock::acquire(archive_path)?;
+    let mut src = open_archive(archive_path)?;
+    let old_size = src.metadata()?.len();
+    let (index, index_offset) = read_verified_index(&mut src, password.as_deref())?;
+    let index_compression = index_compression_at(&mut src, index_offset);
+    let key = match (password.as_ref(), index.salt) {
+        (Some(pass), Some(_)) => Some(archive_key(&index, pass)?),
+        (None, Some(_)) => return Err("Encrypted archive: password required to rebuild the index".into()),
+        _ => None,
+    };
+
+    let dir = archive_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut tmp = crate::temp_manager::temp_file_in(dir, "compact")?;
+
+    let mut new_index = KatanaIndex { shards: Vec::with_capacity(index.shards.len()), ..index.clone() };
+    let mut shards_dropped = 0usize;
+    let mut offset = 0u64;
+    {
+        let mut out = BufWriter::new(tmp.as_file_mut());
+        for shard in &index.shards {
+            if shard.file_count == 0 {
+                shards_dropped += 1;
+                continue;
+            }
+            copy_shard_checked(&mut src, shard, &mut out)?;
+            new_index.shards.push(ShardInfo { offset, ..shard.clone() });
+            offset += shard.compressed_size;
+        }
+        if let Some(store) = &index.chunk_store {
+            copy_shard_checked(&mut src, store, &mut out)?;
+            new_index.chunk_store = Some(ShardInfo { offset, ..store.clone() });
+            offset += store.compressed_size;
+        }
+        out.flush()?;
+    }
+
+    rewrite_index(tmp.path(), offset, &mut new_index, key.as_ref(), index_compression, true)?;
+    let new_size = tmp.as_file().metadata()?.len();
+    replace_archive(tmp, src, archive_path)?;
+
+    let report = CompactReport { old_size, new_size, shards_dropped };
+    println!(
+        "[katana] Compacted {} | {:.2} → {:.2} MiB | reclaimed {:.2} MiB | empty shards dropped: {}",
+        archive_path.display(),
+        old_size as f64 / (1024.0 * 1024.0),
+        new_size as f64 / (1024.0 * 1024.0),
+        report.reclaimed_bytes() as f64 / (1024.0 * 1024.0),
+        shards_dropped
+    );
+    Ok(report)
+}
+
+/// Compacts `archive_path` when bytes no live shard covers make up more than
+/// `threshold_percent` of the file (`append`/`watch --auto-compact`, config
+/// `[compact] auto_above`). `None` when the archive was left alone.
+pub fn compact_if_wasteful(archive_path: &Path, password: Option<String>, threshold_percent: f64) -> Result<Option<CompactReport>, Box<dyn Error>> {
+    let mut src = open_archive(archive_path)?;
+    let size = src.metadata()?.len();
+    let (index, index_offset) = read_verified_index(&mut src, password.as_deref())?;
+    drop(src);
+    let live: u64 = index
+        .shards
+        .iter()
+        .filter(|s| s.file_count > 0)
+        .chain(index.chunk_store.as_ref())
+        .map(|s| s.compressed_size)
+        .sum();
+    let dead = index_offset.saturating_sub(live);
+    if size == 0 || dead as f64 * 100.0 / size as f64 <= threshold_percent {
+        return Ok(None);
+    }
+    compact_katana_archive(archive_path, password).map(Some)
+}
+
+/// Copies the stored bytes of `shard` from `src` to `out`, checking its checksum on the way.
+fn copy_shard_checked<W: Write>(src: &mut File, shard: &ShardInfo, out: &mut W) -> Result<(), Box<dyn Error>> {
+    src.seek(SeekFrom::Start(shard.offset))?;
+    let mut digest = ShardDigest::new(shard.checksum());
+    let mut reader = src.take(shard.compressed_size);
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 { break; }
+        digest.update(&buf[..n]);
+        out.write_all(&buf[..n])?;
+    }
+    digest.check(shard)
+}
+
+/// Renames a fully written (and synced) rewrite of `archive_path` over it,
+/// keeping the permissions of the original `src`.
+pub(crate) fn replace_archive(tmp: tempfile::NamedTempFile, src: File, archive_path: &Path) -> Result<(), Box<dyn Error>> {
+    #[cfg(unix)]
+    {
+        // Keep the permissions of the original archive
+        let mode = crate::fsx::unix_mode(&src.metadata()?);
+        crate::fsx::set_unix_permissions(tmp.path(), mode & 0o7777)?;
+    }
+    drop(src);
+    // The data must be on disk before the rename can expose it
+    tmp.as_file().sync_all()?;
+    tmp.persist(archive_path).map_err(|e| e.error)?;
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(dir) = archive_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if let Ok(d) = File::open(dir) {
+            let _ = d.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Where [`repair_katana_archive`] found the index of the damaged archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveredIndex {
+    /// Read as usual; only shards or the BLAKE3 footer were damaged.
+    Trailer,
+    /// An index trailer found further up (garbage or a torn footer at the end).
+    ScannedTrailer,
+    /// A complete index block whose trailer was cut off.
+    ScannedBlock,
+    /// No index survived: every intact zstd frame became an entry
+    /// `recovered/shard-NNNN.bin` with the concatenated files it held.
+    Rebuilt,
+}
+
+/// Outcome of [`repair_katana_archive`].
+#[derive(Debug, Clone, Serialize)]
+pub struct RepairReport {
+    pub index: RecoveredIndex,
+    pub shards_kept: usize,
+    pub shards_lost: usize,
+    pub files_kept: usize,
+    /// Entries whose data was in a damaged or missing shard.
+    pub files_lost: Vec<String>,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+/// Recovers what is left of a truncated or partially corrupted archive into a
+/// new archive at `output`.
+///
+/// The index is read as usual or, with a damaged end of file, searched for:
+/// first a Katana trailer further up, then a complete index block without its
+/// trailer. Shards whose stored bytes are missing or fail their checksum are
+/// dropped together with their entries (and duplicates of them); intact shards
+/// are copied verbatim. Without any usable index – e.g. an archive cut off in
+/// the middle of its shards – the file is scanned for intact zstd frames, each
+/// stored as one entry without names or metadata; encrypted shards cannot be
+/// recovered that way. The original archive is never modified.
+pub fn repair_katana_archive(archive_path: &Path, output: &Path, password: Option<&str>) -> Result<RepairReport, Box<dyn Error>> {
+    if output.exists() && fs::canonicalize(output)? == fs::canonicalize(archive_path)? {
+        return Err("Write the repaired archive to a new file".into());
+    }
+    let _lock = crate::fsx::OutputLock::acquire(output)?;
+    let mut src = open_archive(archive_path)?;
+    let old_size = src.metadata()?.len();
+    // SAFETY: read-only mapping; the damaged archive is not expected to change meanwhile
+    let data = unsafe { memmap2::Mmap::map(&src)? };
+
+    let dir = output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut tmp = crate::temp_manager::temp_file_in(dir, "repair")?;
+    let mut out = BufWriter::new(tmp.as_file_mut());
+    let (mut new_index, source, key, compression, files_lost, shards_lost) = match find_index(&mut src, &data, password)? {
+        Some((index, source, compression)) => {
+            let key = match (password, index.salt) {
+                (Some(pass), Some(_)) => {
+                    let key = archive_key(&index, pass)?;
+                    verify_index_hmac(&index, &key)?;
+                    Some(key)
+                }
+                (None, Some(_)) => return Err("Encrypted archive: password required to rebuild the index".into()),
+                _ => None,
+            };
+            let (new_index, files_lost, shards_lost) = salvage_shards(&data, &index, &mut out)?;
+            (new_index, source, key, compression, files_lost, shards_lost)
+        }
+        // A sealed index we could not open: rebuilding from frames would find
+        // nothing in the encrypted shards and drop the whole archive
+        None if data.windows(SEALED_INDEX_MAGIC.len()).any(|w| w == SEALED_INDEX_MAGIC) => {
+            return Err(match password {
+                None => "Encrypted archive: password required to rebuild the index".into(),
+                Some(_) => "Encrypted archive: the index cannot be opened with this password".into(),
+            });
+        }
+        None => {
+            let new_index = rebuild_from_frames(&data, &mut out)?;
+            (new_index, RecoveredIndex::Rebuilt, None, IndexCompression::default(), Vec::new(), 0)
+        }
+    };
+    out.flush()?;
+    drop(out);
+    let offset = tmp.as_file().metadata()?.len();
+    rewrite_index(tmp.path(), offset, &mut new_index, key.as_ref(), compression, true)?;
+    let new_size = tmp.as_file().metadata()?.len();
+    // The repaired bytes must be on disk before the rename can expose them
+    tmp.as_file().sync_all()?;
+    tmp.persist(output).map_err(|e| e.error)?;
+    #[cfg(unix)]
+    if let Some(dir) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if let Ok(d) = File::open(dir) {
+            let _ = d.sync_all();
+        }
+    }
+
+    let report = RepairReport {
+        index: source,
+        shards_kept: new_index.shards.len(),
+        shards_lost,
+        files_kept: new_index.files.len(),
+        files_lost,
+        old_size,
+        new_size,
+    };
+    println!(
+        "[katana] Repaired {} → {} | index: {:?} | shards kept: {}, lost: {} | files kept: {}, lost: {}",
+        archive_path.display(),
+        output.display(),
+        report.index,
+        report.shards_kept,
+        report.shards_lost,
+        report.files_kept,
+        report.files_lost.len()
+    );
+    Ok(report)
+}
+
+/// An index found by [`find_index`], where it was found and its codec.
+type FoundIndex = (KatanaIndex, RecoveredIndex, IndexCompression);
+
+/// The archive's index, looked up the usual way and then by scanning `data`
+/// backwards; `None` if nothing parses (CRC32 and layout checked).
+fn find_index(f: &mut File, data: &[u8], password: Option<&str>) -> Result<Option<FoundIndex>, Box<dyn Error>> {
+    if let Ok((index, offset)) = read_index_with(f, password) {
+        return Ok(Some((index, RecoveredIndex::Trailer, index_compression_at(f, offset))));
+    }
+    // Trailer: `u64 index size | u64 JSON size | KATIDX01/02` right after the index block
+    let mut end = data.len();
+    while let Some(pos) = data[..end].windows(KATANA_MAGIC.len()).rposition(|w| IndexFormat::from_footer_magic(w).is_some()) {
+        end = pos;
+        let Some(trailer) = pos.checked_sub(16) else { break };
+        let comp_size = u64::from_le_bytes(data[trailer..trailer + 8].try_into()?);
+        let Some(block) = (trailer as u64).checked_sub(comp_size).map(|start| &data[start as usize..trailer]) else { continue };
+        if let Some((index, compression)) = parse_index_block(block, password) {
+            return Ok(Some((index, RecoveredIndex::ScannedTrailer, compression)));
+        }
+    }
+    // Index block without trailer: at most the partial 24-byte trailer follows
+    let starts: [&[u8]; 4] = [&ZSTD_FRAME_MAGIC, SEALED_INDEX_MAGIC, INDEX_V2_MAGIC, b"{\"crc32\":"];
+    for pos in (0..data.len()).rev().filter(|&p| starts.iter().any(|s| data[p..].starts_with(s))) {
+        let tail = &data[pos..];
+        let found = if tail.starts_with(&ZSTD_FRAME_MAGIC) {
+            // zstd frames know their own length
+            zstd::zstd_safe::find_frame_compressed_size(tail)
+                .ok()
+                .filter(|&len| starts_with_index_json(&tail[..len]))
+                .and_then(|len| parse_index_block(&tail[..len], password))
+        } else {
+            (0..24.min(tail.len())).find_map(|cut| parse_index_block(&tail[..tail.len() - cut], password))
+        };
+        if let Some((index, compression)) = found {
+            return Ok(Some((index, RecoveredIndex::ScannedBlock, compression)));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether a zstd frame decodes to something that starts like an index, without
+/// decoding all of it (most frames are shards).
+fn starts_with_index_json(frame: &[u8]) -> bool {
+    let mut head = [0u8; 9];
+    zstd::stream::read::Decoder::with_buffer(frame)
+        .and_then(|d| d.single_frame().read_exact(&mut head))
+        .is_ok_and(|_| &head == b"{\"crc32\":" || head.starts_with(INDEX_V2_MAGIC))
+}
+
+/// Index stored in `block` (sealed, compressed or plain) and its codec.
+fn parse_index_block(block: &[u8], password: Option<&str>) -> Option<(KatanaIndex, IndexCompression)> {
+    let encoded = match block.starts_with(SEALED_INDEX_MAGIC) {
+        true => std::borrow::Cow::Owned(unseal_index(block, password?).ok()?),
+        false => std::borrow::Cow::Borrowed(block),
+    };
+    let index = parse_index_json(&decode_index(&encoded).ok()?).ok()?;
+    Some((index, IndexCompression::detect(&encoded).unwrap_or_default()))
+}
+
+/// Whether the stored bytes of `shard` are all there and match its checksum.
+fn shard_intact(data: &[u8], shard: &ShardInfo) -> bool {
+    let Some(end) = shard.offset.checked_add(shard.compressed_size).filter(|&end| end <= data.len() as u64) else {
+        return false;
+    };
+    let mut digest = ShardDigest::new(shard.checksum());
+    digest.update(&data[shard.offset as usize..end as usize]);
+    digest.check(shard).is_ok()
+}
+
+/// Copies the intact shards of `index` to `out`; returns the index of what was
+/// kept, the paths of the lost entries and the number of lost shards.
+fn salvage_shards<W: Write>(data: &[u8], index: &KatanaIndex, out: &mut W) -> Result<(KatanaIndex, Vec<String>, usize), Box<dyn Error>> {
+    let mut new_index = KatanaIndex { shards: Vec::new(), files: Vec::new(), ..index.clone() };
+    let mut lost: Vec<&FileEntry> = Vec::new();
+    let mut shards_lost = 0;
+    let mut offset = 0u64;
+    let mut first = 0usize;
+    for shard in &index.shards {
+        let entries = &index.files[first..first + shard.file_count];
+        first += shard.file_count;
+        if !shard_intact(data, shard) {
+            shards_lost += 1;
+            lost.extend(entries);
+            continue;
+        }
+        out.write_all(&data[shard.offset as usize..(shard.offset + shard.compressed_size) as usize])?;
+        new_index.shards.push(ShardInfo { offset, ..shard.clone() });
+        new_index.files.extend_from_slice(entries);
+        offset += shard.compressed_size;
+    }
+    let chunks_lost = match &index.chunk_store {
+        Some(store) if shard_intact(data, store) => {
+            out.write_all(&data[store.offset as usize..(store.offset + store.compressed_size) as usize])?;
+            new_index.chunk_store = Some(ShardInfo { offset, ..store.clone() });
+            false
+        }
+        Some(_) => {
+            shards_lost += 1;
+            new_index.chunk_store = None;
+            new_index.chunk_sizes.clear();
+            true
+        }
+        None => false,
+    };
+    // Inline entries, symlinks and base references live in the index; chunked
+    // entries need the chunk store, duplicates their original
+    let mut lost_paths: HashSet<&str> = lost.iter().map(|e| e.path.as_str()).collect();
+    for entry in &index.files[first..] {
+        let gone = (chunks_lost && !entry.chunks.is_empty())
+            || entry.duplicate_of.as_deref().is_some_and(|o| lost_paths.contains(o));
+        if gone {
+            lost_paths.insert(&entry.path);
+            lost.push(entry);
+        } else {
+            new_index.files.push(entry.clone());
+        }
+    }
+    Ok((new_index, lost.iter().map(|e| e.path.clone()).collect(), shards_lost))
+}
+
+/// Index for an archive whose own index is gone: every intact zstd frame found
+/// in `data` is copied to `out` as a shard with one entry.
+fn rebuild_from_frames<W: Write>(data: &[u8], out: &mut W) -> Result<KatanaIndex, Box<dyn Error>> {
+    let mut index = KatanaIndex {
+        crc32: 0,
+        hmac: None,
+        salt: None,
+        key_slots: Vec::new(),
+        shards: Vec::new(),
+        files: Vec::new(),
+        features: 0,
+        base: None,
+        chunk_store: None,
+        chunk_sizes: Vec::new(),
+        source_fingerprint: None,
+        extensions: Default::default(),
+        signed_json: None,
+        format: IndexFormat::default(),
+    };
+    let mut offset = 0u64;
+    let mut pos = 0usize;
+    while pos + ZSTD_FRAME_MAGIC.len() <= data.len() {
+        if let Some((len, plain)) = intact_frame(&data[pos..]) {
+            let frame = &data[pos..pos + len];
+            out.write_all(frame)?;
+            index.files.push(FileEntry {
+                path: format!("recovered/shard-{:04}.bin", index.shards.len()),
+                size: plain,
+                offset: 0,
+                permissions: None,
+                inline: None,
+                mtime: None,
+                btime: None,
+                owner: None,
+                base_ref: false,
+                symlink: None,
+                duplicate_of: None,
+                xattrs: Default::default(),
+                chunks: Vec::new(),
+                nested_archive: false,
+                sparse: Vec::new(),
+            });
+            index.shards.push(ShardInfo {
+                offset,
+                compressed_size: len as u64,
+                uncompressed_size: plain,
+                file_count: 1,
+                crc32: crc32fast::hash(frame),
+                xxh3: None,
+                nonce: None,
+                key_id: None,
+                frames: Vec::new(),
+                dictionary: None,
+                stats: None,
+            });
+            offset += len as u64;
+            pos += len;
+            continue;
+        }
+        // Resynchronise at the next frame start
+        pos = data[pos + 1..]
+            .windows(ZSTD_FRAME_MAGIC.len())
+            .position(|w| w == ZSTD_FRAME_MAGIC)
+            .map_or(data.len(), |p| pos + 1 + p);
+    }
+    if index.shards.is_empty() {
+        return Err("No index and no intact zstd frames found (encrypted shards cannot be recovered without the index)".into());
+    }
+    Ok(index)
+}
+
+/// Compressed and decoded size of the complete, checksum-verified zstd frame
+/// at the start of `data`.
+fn intact_frame(data: &[u8]) -> Option<(usize, u64)> {
+    if !data.starts_with(&ZSTD_FRAME_MAGIC) {
+        return None;
+    }
+    let len = zstd::zstd_safe::find_frame_compressed_size(data).ok()?;
+    let decoder = zstd::stream::read::Decoder::with_buffer(&data[..len]).ok()?;
+    let plain = std::io::copy(&mut decoder.single_frame(), &mut std::io::sink()).ok()?;
+    Some((len, plain))
+}
+
+/// Outcome of [`encrypt_katana_archive`] / [`decrypt_katana_archive`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConvertReport {
+    pub shards: usize,
+    /// Inlined small files moved into a new shard (encryption only; encrypted
+    /// archives cannot hold data in their index).
+    pub inlined_packed: usize,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+/// Encrypts an unencrypted archive with `password`.
+///
+/// Shards are streamed through AES-256-GCM as they are – the compressed bytes
+/// are not recompressed – each with its own subkey and nonce, and the index is
+/// signed. Inlined small files are compressed into one extra shard. Like
+/// [`compact_katana_archive`] the result is written next to the archive and
+/// renamed over it.
+pub fn encrypt_katana_archive(archive_path: &Path, password: &str) -> Result<ConvertReport, Box<dyn Error>> {
+    let _lock = crate::fsx::OutputLock::acquire(archive_path)?;
+    let mut src = open_archive(archive_path)?;
+    let old_size = src.metadata()?.len();
+    let (index, index_offset) = read_verified_index(&mut src, None)?;
+    let index_compression = index_compression_at(&mut src, index_offset);
+    if index.salt.is_some() {
+        return Err("Archive is already encrypted".into());
+    }
+    if index.base.is_some() {
+        return Err("Incremental archives cannot be converted: their base archive would keep the old encryption".into());
+    }
+    let salt: [u8; 16] = crypto::generate_salt().try_into().map_err(|_| "salt size")?;
+    let key = crypto::derive_key_argon2(password, &salt);
+
+    let dir = archive_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut tmp = crate::temp_manager::temp_file_in(dir, "encrypt")?;
+    let mut new_index = KatanaIndex { shards: Vec::with_capacity(index.shards.len() + 1), salt: Some(salt), ..index.clone() };
+    let mut offset = 0u64;
+    {
+        let mut out = BufWriter::new(tmp.as_file_mut());
+        for shard in &index.shards {
+            let encrypted = encrypt_stored_shard(&mut src, shard, &mut out, &key, new_index.shards.len() as u64, offset)?;
+            offset += encrypted.compressed_size;
+            new_index.shards.push(encrypted);
+        }
+
+        // Inlined entries become one more shard right behind the existing ones;
+        // entries without data (symbolic links, duplicates) stay behind it
+        let sharded: usize = index.shards.iter().map(|s| s.file_count).sum();
+        let refs = base_refs_start(&index);
+        new_index.files[sharded..refs].sort_by_key(|e| e.inline.is_none());
+        let packed = new_index.files[sharded..refs].iter().filter(|e| e.inline.is_some()).count();
+        let inlined = &mut new_index.files[sharded..sharded + packed];
+        if !inlined.is_empty() {
+            let mut plain = Vec::new();
+            for entry in inlined.iter_mut() {
+                entry.offset = plain.len() as u64;
+                plain.extend_from_slice(&entry.inline.take().unwrap_or_default());
+            }
+            let compressed = zstd::encode_all(&plain[..], zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            let key_id = new_index.shards.len() as u64;
+            let (nonce, written, (crc32, xxh3)) = encrypt_shard_stream(
+                &mut &compressed[..],
+                &mut out,
+                &crypto::derive_shard_key(&key, key_id),
+                new_shard_checksum(&index),
+            )?;
+            new_index.shards.push(ShardInfo {
+                offset,
+                compressed_size: written,
+                uncompressed_size: plain.len() as u64,
+                file_count: inlined.len(),
+                crc32,
+                xxh3,
+                nonce: Some(nonce),
+                key_id: Some(key_id),
+                frames: Vec::new(),
+                dictionary: None,
+                stats: None,
+            });
+            offset += written;
+        }
+        // The chunk store takes the next subkey
+        if let Some(store) = &index.chunk_store {
+            let encrypted = encrypt_stored_shard(&mut src, store, &mut out, &key, new_index.shards.len() as u64, offset)?;
+            offset += encrypted.compressed_size;
+            new_index.chunk_store = Some(encrypted);
+        }
+        out.flush()?;
+    }
+    let inlined_packed = index.files.iter().filter(|e| e.inline.is_some()).count();
+    new_index.features = (new_index.features & !FEATURE_INLINE_SMALL) | FEATURE_SHARD_SUBKEYS;
+
+    rewrite_index(tmp.path(), offset, &mut new_index, Some(&key), index_compression, true)?;
+    let new_size = tmp.as_file().metadata()?.len();
+    replace_archive(tmp, src, archive_path)?;
+    let report = ConvertReport { shards: new_index.shards.len(), inlined_packed, old_size, new_size };
+    println!(
+        "[katana] Encrypted {} | Shards: {} | Inlined files packed: {}",
+        archive_path.display(),
+        report.shards,
+        inlined_packed
+    );
+    Ok(report)
+}
+
+/// Encrypts the stored (compressed) bytes of `shard` into `out` at `offset`
+/// with subkey `key_id`, checking the checksum of the input. Returns the new shard info.
+fn encrypt_stored_shard<W: Write>(
+    src: &mut File,
+    shard: &ShardInfo,
+    out: &mut W,
+    key: &[u8; 32],
+    key_id: u64,
+    offset: u64,
+) -> Result<ShardInfo, Box<dyn Error>> {
+    src.seek(SeekFrom::Start(shard.offset))?;
+    let mut digest_in = ShardDigest::new(shard.checksum());
+    let mut reader = DigestReader { inner: src.take(shard.compressed_size), digest: &mut digest_in };
+    let (nonce, written, (crc32, xxh3)) =
+        encrypt_shard_stream(&mut reader, out, &crypto::derive_shard_key(key, key_id), shard.checksum())?;
+    digest_in.check(shard)?;
+    Ok(ShardInfo {
+        offset,
+        compressed_size: written,
+        crc32,
+        xxh3,
+        nonce: Some(nonce),
+        key_id: Some(key_id),
+        stats: shard.stats_for(written, true),
+        ..shard.clone()
+    })
+}
+
+/// Removes the encryption of an archive, the inverse of [`encrypt_katana_archive`].
+///
+/// Every shard is authenticated and decrypted (through the job's temp
+/// workspace, as on extract) and stored as plain zstd; nothing is recompressed.
+pub fn decrypt_katana_archive(archive_path: &Path, password: &str) -> Result<ConvertReport, Box<dyn Error>> {
+    let _lock = crate::fsx::OutputLock::acquire(archive_path)?;
+    let mut src = open_archive(archive_path)?;
+    let old_size = src.metadata()?.len();
+    let (index, index_offset) = read_verified_index(&mut src, Some(password))?;
+    let index_compression = index_compression_at(&mut src, index_offset);
+    if index.salt.is_none() {
+        return Err("Archive is not encrypted".into());
+    }
+    if index.base.is_some() {
+        return Err("Incremental archives cannot be converted: their base archive would keep the old encryption".into());
+    }
+    let key = archive_key(&index, password)?;
+
+    let dir = archive_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut tmp = crate::temp_manager::temp_file_in(dir, "decrypt")?;
+    let workspace = TempWorkspace::new("decrypt")?;
+    verify_shard_crcs(archive_path, data_sections(&index))?;
+    let mut new_index = KatanaIndex {
+        shards: Vec::with_capacity(index.shards.len()),
+        salt: None,
+        key_slots: Vec::new(),
+        ..index.clone()
+    };
+    let mut offset = 0u64;
+    {
+        let mut out = BufWriter::new(tmp.as_file_mut());
+        let mut decrypt = |shard: &ShardInfo, out: &mut BufWriter<&mut File>| -> Result<ShardInfo, Box<dyn Error>> {
+            let (reader, _decrypted_tmp) = open_shard_stream(archive_path, shard, Some(&key), &workspace)?;
+            let mut digest = ShardDigest::new(shard.checksum());
+            let written = std::io::copy(&mut DigestReader { inner: reader, digest: &mut digest }, out)?;
+            let (crc32, xxh3) = digest.finish();
+            let plain = ShardInfo {
+                offset,
+                compressed_size: written,
+                crc32,
+                xxh3,
+                nonce: None,
+                key_id: None,
+                stats: shard.stats_for(written, false),
+                ..shard.clone()
+            };
+            offset += written;
+            Ok(plain)
+        };
+        for shard in &index.shards {
+            new_index.shards.push(decrypt(shard, &mut out)?);
+        }
+        if let Some(store) = &index.chunk_store {
+            new_index.chunk_store = Some(decrypt(store, &mut out)?);
+        }
+        out.flush()?;
+    }
+    new_index.features &= !(FEATURE_SHARD_SUBKEYS | FEATURE_KEY_SLOTS | FEATURE_HIDDEN_NAMES);
+
+    rewrite_index(tmp.path(), offset, &mut new_index, None, index_compression, true)?;
+    let new_size = tmp.as_file().metadata()?.len();
+    replace_archive(tmp, src, archive_path)?;
+    let report = ConvertReport { shards: new_index.shards.len(), inlined_packed: 0, old_size, new_size };
+    println!("[katana] Decrypted {} | Shards: {}", archive_path.display(), report.shards);
+    Ok(report)
+}
+
+/// Adds `new_password` as a further password of the encrypted archive.
+///
+/// Archives without key slots are converted first: the archive key is wrapped
+/// for `password` in slot 0 and the salt it was derived from is replaced, so
+/// that key stops depending on the original password once its slot is removed.
+/// Only the index is rewritten – shards are never re-encrypted. Returns the
+/// number of slots.
+pub fn add_key_slot(archive_path: &Path, password: &str, new_password: &str) -> Result<usize, Box<dyn Error>> {
+    let count = update_key_slots(archive_path, password, |index, key, _| {
+        index.key_slots.push(new_key_slot(key, new_password)?);
+        Ok(())
+    })?;
+    println!("[katana] Added a password to {} ({} key slots)", archive_path.display(), count);
+    Ok(count)
+}
+
+/// Removes the key slot `password` opens; the last slot is never removed.
+/// Returns the number of slots left.
+pub fn remove_key_slot(archive_path: &Path, password: &str) -> Result<usize, Box<dyn Error>> {
+    let count = update_key_slots(archive_path, password, |index, _, slot| {
+        if index.key_slots.len() < 2 {
+            return Err("Cannot remove the only password of the archive".into());
+        }
+        index.key_slots.remove(slot);
+        Ok(())
+    })?;
+    println!("[katana] Removed a password from {} ({} key slots left)", archive_path.display(), count);
+    Ok(count)
+}
+
+/// Replaces `password` by `new_password` in the slot it opens.
+/// Returns the number of slots.
+pub fn rotate_key_slot(archive_path: &Path, password: &str, new_password: &str) -> Result<usize, Box<dyn Error>> {
+    let count = update_key_slots(archive_path, password, |index, key, slot| {
+        index.key_slots[slot] = new_key_slot(key, new_password)?;
+        Ok(())
+    })?;
+    println!("[katana] Changed a password of {}", archive_path.display());
+    Ok(count)
+}
+
+/// Number of key slots of the archive; 0 for archives whose key is derived
+/// from a single password (or that are not encrypted). Sealed indexes
+/// (`--hide-names`) need `password`.
+pub fn key_slot_count(archive_path: &Path, password: Option<&str>) -> Result<usize, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = read_index_with(&mut f, password)?;
+    Ok(index.key_slots.len())
+}
+
+/// Opens the archive with `password`, converts it to key slots if needed and
+/// lets `change` edit the slots given the archive key and the slot `password`
+/// opened, then rewrites the index.
+fn update_key_slots(
+    archive_path: &Path,
+    password: &str,
+    change: impl FnOnce(&mut KatanaIndex, &[u8; 32], usize) -> Result<(), Box<dyn Error>>,
+) -> Result<usize, Box<dyn Error>> {
+    let _lock = crate::fsx::OutputLock::acquire(archive_path)?;
+    let mut f = open_archive(archive_path)?;
+    let (mut index, index_offset) = read_index_with(&mut f, Some(password))?;
+    let index_compression = index_compression_at(&mut f, index_offset);
+    drop(f);
+    let Some(old_salt) = index.salt else {
+        return Err("Archive is not encrypted".into());
+    };
+    if index.base.is_some() {
+        return Err("Incremental archives cannot change passwords: their base archive would keep the old ones".into());
+    }
+    let (slot, key) = match open_key_slot(&index.key_slots, password) {
+        Some(opened) => opened,
+        None if index.key_slots.is_empty() => (0, crypto::derive_key_argon2(password, &old_salt)),
+        None => return Err("Wrong password: no key slot of this archive matches".into()),
+    };
+    verify_index_hmac(&index, &key)?;
+
+    let old_slots: Vec<[u8; 16]> = index.key_slots.iter().map(|s| s.salt).collect();
+    if index.key_slots.is_empty() {
+        index.key_slots.push(new_key_slot(&key, password)?);
+        index.salt = Some(crypto::generate_salt().try_into().map_err(|_| "salt size")?);
+        index.features |= FEATURE_KEY_SLOTS;
+    }
+    change(&mut index, &key, slot)?;
+    // Не на месте: обрыв посреди записи не должен оставить архив без паролей
+    rewrite_index_atomic(archive_path, index_offset, &mut index, Some(&key), index_compression)?;
+
+    // Кэш сессии не должен открывать архив паролями, которых в нём больше нет
+    crypto::invalidate_cached_key(&old_salt);
+    for salt in old_slots {
+        crypto::invalidate_cached_key(&salt);
+    }
+    Ok(index.key_slots.len())
+}
+
+/// The first key slot `password` opens and the archive key it wraps.
+fn open_key_slot(key_slots: &[KeySlot], password: &str) -> Option<(usize, [u8; 32])> {
+    key_slots.iter().enumerate().find_map(|(i, slot)| {
+        let kek = crypto::derive_key_argon2(password, &slot.salt);
+        let key = crypto::decrypt_prekey(&slot.wrapped, &kek, &slot.nonce).ok()?;
+        Some((i, key.try_into().ok()?))
+    })
+}
+
+fn new_key_slot(key: &[u8; 32], password: &str) -> Result<KeySlot, Box<dyn Error>> {
+    let salt: [u8; 16] = crypto::generate_salt().try_into().map_err(|_| "salt size")?;
+    let kek = crypto::derive_key_argon2(password, &salt);
+    let (wrapped, nonce) = crypto::encrypt_prekey(key, &kek).map_err(|_| "Key slot encryption failed")?;
+    Ok(KeySlot { salt, nonce: nonce.try_into().map_err(|_| "nonce size")?, wrapped })
+}
+
+/// Passes bytes through while updating a shard digest.
+struct DigestReader<'a, R> {
+    inner: R,
+    digest: &'a mut ShardDigest,
+}
+
+impl<R: Read> Read for DigestReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.digest.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Encrypts `rdr` into `wtr` with a fresh nonce, tag last (the layout the
+/// writer produces). Returns the nonce, the bytes written and their
+/// `checksum` (`ShardInfo::crc32`, `ShardInfo::xxh3`).
+#[allow(clippy::type_complexity)]
+fn encrypt_shard_stream<R: Read, W: Write>(
+    rdr: &mut R,
+    wtr: &mut W,
+    key: &[u8; 32],
+    checksum: ShardChecksum,
+) -> Result<([u8; 12], u64, (u32, Option<[u8; 16]>)), Box<dyn Error>> {
+    use rand::RngCore;
+    let mut nonce = [0u8; 12];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    let mut enc = aes_gcm_stream::Aes256GcmStreamEncryptor::new(*key, &nonce);
+    let mut digest = ShardDigest::new(checksum);
+    let mut written = 0u64;
+    let mut emit = |bytes: &[u8], wtr: &mut W| -> std::io::Result<()> {
+        digest.update(bytes);
+        written += bytes.len() as u64;
+        wtr.write_all(bytes)
+    };
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = rdr.read(&mut buf)?;
+        if n == 0 { break; }
+        emit(&enc.update(&buf[..n]), wtr)?;
+    }
+    let (tail, tag) = enc.finalize();
+    emit(&tail, wtr)?;
+    emit(&tag, wtr)?;
+    Ok((nonce, written, digest.finish()))
+}
+
+/// Outcome of [`append_to_archive`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AppendReport {
+    pub files_added: usize,
+    pub shards_added: usize,
+    /// Compressed shard bytes written into the archive.
+    pub bytes_added: u64,
+}
+
+/// Adds files and directories to an existing Katana archive.
+///
+/// The new files are compressed into fresh shards (in a temp file next to the
+/// archive) exactly like [`crate::katana_stream::create_katana_archive`] would;
+/// then the shards are written over the old index, and a merged index plus
+/// footers are appended. Existing shards are never touched. Encrypted archives
+/// keep their salt, so one password opens old and new entries.
+///
+/// Entry paths already present in the archive are rejected. If anything fails
+/// after the archive was modified, the old index and footers are restored; after
+/// a crash, the next open of the archive restores them from `<archive>.undo`.
+pub fn append_to_archive(
+    archive_path: &Path,
+    inputs: &[PathBuf],
+    threads: usize,
+    password: Option<String>,
+    compression_level: Option<i32>,
+) -> Result<AppendReport, Box<dyn Error>> {
+    append_with_source(archive_path, inputs, threads, password, compression_level, None, None)
+}
+
+/// [`append_to_archive`] reading the inputs through `vfs` and honouring a time
+/// budget (files left over are reported through the budget).
+pub(crate) fn append_with_source(
+    archive_path: &Path,
+    inputs: &[PathBuf],
+    threads: usize,
+    password: Option<String>,
+    compression_level: Option<i32>,
+    vfs: Option<Arc<dyn crate::vfs::Vfs>>,
+    time_budget: Option<Arc<crate::timebox::TimeBudget>>,
+) -> Result<AppendReport, Box<dyn Error>> {
+    let _lock = crate::fsx::OutputLock::acquire(archive_path)?;
+    let mut f = open_archive(archive_path)?;
+    let (index, index_offset) = read_verified_index(&mut f, password.as_deref())?;
+    drop(f);
+    let key = match (password.as_ref(), index.salt) {
+        (Some(pass), Some(_)) => Some(archive_key(&index, pass)?),
+        (None, Some(_)) => return Err("Encrypted archive: password required to append".into()),
+        (Some(_), None) => return Err("Archive is not encrypted; appending with a password is not supported".into()),
+        _ => None,
+    };
+
+    // New shards are built as a standalone archive first, so a failed
+    // compression never touches the original
+    let dir = archive_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let staging = crate::temp_manager::temp_file_in(dir, "append")?.into_temp_path();
+    let options = crate::katana_stream::KatanaCreateOptions {
+        inline_small_files: index.features & FEATURE_INLINE_SMALL != 0,
+        salt: index.salt,
+        archive_key: key,
+        key_id_base: data_sections(&index).filter_map(|s| s.key_id).max().map_or(0, |id| id + 1),
+        exclude_outputs: vec![archive_path.to_path_buf()],
+        vfs,
+        time_budget,
+        shard_checksum: new_shard_checksum(&index),
+        ..Default::default()
+    };
+    crate::katana_stream::create_katana_archive_with_options(
+        inputs,
+        &staging,
+        threads,
+        0,
+        None,
+        password.clone(),
+        compression_level,
+        &options,
+        None::<fn(ProgressState)>,
+    )?;
+    let mut staged = File::open(&staging)?;
+    let (new_index, new_data_len) = read_index_crc_checked(&mut staged)?;
+    if let Some(key) = &key {
+        verify_index_hmac(&new_index, key)?;
+    }
+
+    let existing: std::collections::HashSet<&str> = index.files.iter().map(|e| e.path.as_str()).collect();
+    if let Some(dup) = new_index.files.iter().find(|e| existing.contains(e.path.as_str())) {
+        return Err(format!("Entry already exists in archive: {}", crate::private_logs::path(&dup.path)).into());
+    }
+
+    // Merged index: old shard entries, new shard entries, all inlined entries, base references
+    let old_sharded: usize = index.shards.iter().map(|s| s.file_count).sum();
+    let old_refs = base_refs_start(&index);
+    let new_sharded: usize = new_index.shards.iter().map(|s| s.file_count).sum();
+    let mut merged = KatanaIndex {
+        shards: index.shards.clone(),
+        files: Vec::with_capacity(index.files.len() + new_index.files.len()),
+        features: index.features | new_index.features,
+        // The archive no longer mirrors the source set it was created from
+        source_fingerprint: None,
+        ..index.clone()
+    };
+    merged.shards.extend(new_index.shards.iter().map(|s| ShardInfo { offset: s.offset + index_offset, ..s.clone() }));
+    merged.files.extend_from_slice(&index.files[..old_sharded]);
+    merged.files.extend_from_slice(&new_index.files[..new_sharded]);
+    merged.files.extend_from_slice(&index.files[old_sharded..old_refs]);
+    merged.files.extend_from_slice(&new_index.files[new_sharded..]);
+    merged.files.extend_from_slice(&index.files[old_refs..]);
+    validate_index_layout(&merged)?;
+
+    // The old index + footers are kept on disk until the append is durable
+    let journal = UndoJournal::begin(archive_path, index_offset)?;
+    let index_compression = IndexCompression::detect(&journal.tail).unwrap_or_default();
+
+    let write_result = (|| -> Result<(), Box<dyn Error>> {
+        let mut out = OpenOptions::new().write(true).open(archive_path)?;
+        out.seek(SeekFrom::Start(index_offset))?;
+        staged.seek(SeekFrom::Start(0))?;
+        std::io::copy(&mut (&mut staged).take(new_data_len), &mut out)?;
+        out.flush()?;
+        rewrite_index(archive_path, index_offset + new_data_len, &mut merged, key.as_ref(), index_compression, true)
+    })();
+    match write_result {
+        Ok(()) => journal.finish()?,
+        Err(e) => {
+            journal.roll_back(archive_path)?;
+            return Err(format!("Append failed, archive restored: {}", e).into());
+        }
+    }
+
+    let report = AppendReport {
+        files_added: new_index.files.len(),
+        shards_added: new_index.shards.len(),
+        bytes_added: new_index.shards.iter().map(|s| s.compressed_size).sum(),
+    };
+    println!(
+        "[katana] Appended {} files in {} shards ({:.2} MiB) to {}",
+        report.files_added,
+        report.shards_added,
+        report.bytes_added as f64 / (1024.0 * 1024.0),
+        archive_path.display()
+    );
+    Ok(report)
+}
+
+/// Compiles user supplied glob patterns into a single matcher.
+/// `*` does not cross directory boundaries, `**` does.
+pub(crate) fn build_glob_set(patterns: &[String]) -> Result<globset::GlobSet, Box<dyn Error>> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for pat in patterns {
+        let glob = globset::GlobBuilder::new(&normalize_path(pat))
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("Invalid pattern '{}': {}", pat, e))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+/// The shards, then the chunk store, that reading the entries of `wanted`
+/// (all of them if it is empty) touches, originals of selected copies
+/// included; `files` is the index file list, shard after shard.
+fn sections_needed<'a>(
+    files: &[FileEntry],
+    shards: &'a [ShardInfo],
+    chunk_store: Option<&'a ShardInfo>,
+    wanted: &HashSet<String>,
+) -> Result<Vec<&'a ShardInfo>, Box<dyn Error>> {
+    let mut read: HashSet<&str> = wanted.iter().map(String::as_str).collect();
+    read.extend(files.iter().filter(|e| wanted.contains(&e.path)).filter_map(|e| e.duplicate_of.as_deref()));
+    let reads = |e: &FileEntry| wanted.is_empty() || read.contains(e.path.as_str());
+
+    let mut sections = Vec::new();
+    let mut cursor = 0usize;
+    for shard in shards {
+        // file_count comes from the index – possibly a remote one – and is not trusted
+        let shard_files = cursor
+            .checked_add(shard.file_count)
+            .and_then(|end| files.get(cursor..end))
+            .ok_or("Corrupt index: shard file counts exceed the file list")?;
+        cursor += shard.file_count;
+        if wanted.is_empty() || shard_files.iter().any(reads) {
+            sections.push(shard);
+        }
+    }
+    if let Some(store) = chunk_store {
+        if files.iter().any(|e| !e.chunks.is_empty() && reads(e)) {
+            sections.push(store);
+        }
+    }
+    Ok(sections)
+}
+
+/// Byte ranges (`start`, `len`) of the shards and chunk store that extracting
+/// `selected_files` reads, so an archive whose index and footer are already
+/// local can be fetched partially (`extract s3://…`). Ranges are in archive
+/// order.
+pub fn shard_ranges_for(archive_path: &Path, selected_files: &[PathBuf], password: Option<&str>) -> Result<Vec<(u64, u64)>, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = read_verified_index(&mut f, password)?;
+    let selector = EntrySelector::new(selected_files)?;
+    let wanted = selector.select(&index.files);
+    if !selector.is_empty() && wanted.is_empty() {
+        // Nothing matched: extract reports it, there is nothing to fetch
+        return Ok(Vec::new());
+    }
+    let mut ranges: Vec<(u64, u64)> = sections_needed(&index.files, &index.shards, index.chunk_store.as_ref(), &wanted)?
+        .into_iter()
+        .map(|shard| (shard.offset, shard.compressed_size))
+        .collect();
+    ranges.sort_unstable();
+    Ok(ranges)
+}
+
+/// Entries named on the `extract` command line.
+///
+/// Every selector matches the entry with exactly that path and, as a directory,
+/// everything below it (`photos/2023` ⇒ `photos/2023/…`). Selectors with glob
+/// characters (`*?[{`) are also matched as patterns – `*` stays within a
+/// directory, `**` crosses them – against each entry and its parent
+/// directories, so `photos/*` brings whole sub-folders along.
+#[derive(Debug, Clone, Default)]
+pub struct EntrySelector {
+    paths: Vec<String>,
+    globs: Option<globset::GlobSet>,
+}
+
+impl EntrySelector {
+    pub fn new(selected: &[PathBuf]) -> Result<Self, Box<dyn Error>> {
+        let paths: Vec<String> = selected
+            .iter()
+            .map(|p| normalize_path(&p.to_string_lossy()).trim_end_matches('/').to_string())
+            .collect();
+        let patterns: Vec<String> = paths.iter().filter(|p| p.contains(['*', '?', '[', '{'])).cloned().collect();
+        let globs = if patterns.is_empty() { None } else { Some(build_glob_set(&patterns)?) };
+        Ok(EntrySelector { paths, globs })
+    }
+
+    /// True if nothing was selected (everything is extracted).
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn matches(&self, entry_path: &str) -> bool {
+        let under = |dir: &str| {
+            entry_path.strip_prefix(dir).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')) || dir.is_empty()
+        };
+        self.paths.iter().any(|p| under(p))
+            || self.globs.as_ref().is_some_and(|set| {
+                Path::new(entry_path).ancestors().filter(|a| !a.as_os_str().is_empty()).any(|a| set.is_match(a))
+            })
+    }
+
+    /// Paths of the selected entries.
+    fn select(&self, entries: &[FileEntry]) -> HashSet<String> {
+        entries.iter().filter(|e| self.matches(&e.path)).map(|e| e.path.clone()).collect()
+    }
+}
+
+/// `extract --relative-to PREFIX`: turns the selection into entries below
+/// `prefix` (all of them if nothing else was selected) and adds the components
+/// of `prefix` to `strip_components`, so `photos/2023/a.jpg` relative to
+/// `photos` lands in `<out>/2023/a.jpg`. Selectors outside `prefix` are rejected.
+pub fn relative_to_prefix(
+    prefix: &str,
+    selected: &[PathBuf],
+    strip_components: Option<u32>,
+) -> Result<(Vec<PathBuf>, Option<u32>), Box<dyn Error>> {
+    let prefix = normalize_path(prefix).trim_matches('/').to_string();
+    if prefix.is_empty() || prefix.split('/').any(|c| c == "..") {
+        return Err(format!("Invalid --relative-to prefix '{}'", prefix).into());
+    }
+    let selection = if selected.is_empty() {
+        vec![PathBuf::from(&prefix)]
+    } else {
+        for path in selected {
+            let path = normalize_path(&path.to_string_lossy());
+            let path = path.trim_end_matches('/');
+            if !path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')) {
+                return Err(format!("'{}' is not below --relative-to '{}'", path, prefix).into());
+            }
+        }
+        selected.to_vec()
+    };
+    let depth = prefix.split('/').filter(|c| !c.is_empty() && *c != ".").count() as u32;
+    Ok((selection, Some(depth + strip_components.unwrap_or(0))))
+}
+
+/// Aggregate statistics of a Katana archive, computed from the index alone.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct IndexStats {
+    /// Number of file entries in the archive.
+    pub total_entries: u64,
+    /// Sum of the uncompressed file sizes.
+    pub total_size: u64,
+    /// Sum of the compressed (and possibly encrypted) shard sizes.
+    pub compressed_size: u64,
+    pub shard_count: usize,
+    pub encrypted: bool,
+    /// First-level entries sorted by name (directories first).
+    pub top_level: Vec<TopLevelStats>,
+}
+
+/// Summary of one first-level entry of the archive tree.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TopLevelStats {
+    pub name: String,
+    pub is_dir: bool,
+    /// Number of direct children (files and sub-directories); 0 for files.
+    pub children: u64,
+    /// Number of files anywhere below this entry (1 for a file).
+    pub files: u64,
+    /// Total uncompressed size below this entry.
+    pub size: u64,
+}
+
+/// Computes [`IndexStats`] without decoding any shard.
+///
+/// Only the index CRC is checked: the summary does not expose file contents, so
+/// encrypted archives can be summarised without the password – unless their
+/// index is sealed (`--hide-names`), see [`index_stats_with_password`].
+pub fn index_stats(archive_path: &Path) -> Result<IndexStats, Box<dyn Error>> {
+    index_stats_with_password(archive_path, None)
+}
+
+/// [`index_stats`] that also opens sealed indexes with `password`.
+pub fn index_stats_with_password(archive_path: &Path, password: Option<&str>) -> Result<IndexStats, Box<dyn Error>> {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = read_index_with(&mut f, password)?;
+
+    struct Acc {
+        is_dir: bool,
+        children: BTreeSet<String>,
+        files: u64,
+        size: u64,
+    }
+    let mut top: BTreeMap<String, Acc> = BTreeMap::new();
+    for entry in &index.files {
+        let mut parts = entry.path.trim_start_matches('/').splitn(3, '/');
+        let first = parts.next().unwrap_or_default().to_string();
+        let child = parts.next().map(str::to_string);
+        let acc = top.entry(first).or_insert(Acc { is_dir: false, children: BTreeSet::new(), files: 0, size: 0 });
+        if let Some(child) = child {
+            acc.is_dir = true;
+            acc.children.insert(child);
+        }
+        acc.files += 1;
+        acc.size += entry.size;
+    }
+
+    let mut top_level: Vec<TopLevelStats> = top
+        .into_iter()
+        .map(|(name, acc)| TopLevelStats {
+            name,
+            is_dir: acc.is_dir,
+            children: acc.children.len() as u64,
+            files: acc.files,
+            size: acc.size,
+        })
+        .collect();
+    top_level.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(IndexStats {
+        total_entries: index.files.len() as u64,
+        total_size: index.files.iter().map(|e| e.size).sum(),
+        compressed_size: data_sections(&index).map(|s| s.compressed_size).sum(),
+        shard_count: index.shards.len(),
+        encrypted: index.salt.is_some(),
+        top_level,
+    })
+}
+
+/// Public view of a single archive entry.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub path: String,
+    pub size: u64,
+    pub permissions: Option<u32>,
+    /// Modification time (seconds since the Unix epoch), if recorded.
+    pub mtime: Option<i64>,
+    /// Directory entry (only the classic format stores those).
+    pub is_dir: bool,
+    /// Shard (Katana) or bundle (classic) holding the data; `None` for entries
+    /// inlined in the Katana index.
+    pub shard_id: Option<u32>,
+    /// Stored size, where the format keeps it per entry (classic bundles).
+    /// Katana shards are single zstd streams, so their entries report `None`.
+    pub compressed_size: Option<u64>,
+    /// Target of a symbolic link stored with `--symlinks keep`.
+    pub symlink: Option<String>,
+    /// Entry this one is a byte-identical copy of (`--dedup`).
+    pub duplicate_of: Option<String>,
+}
+
+/// Entry infos in index order; entries map to shards by the shards' `file_count`.
+fn entry_infos(index: &KatanaIndex) -> Vec<EntryInfo> {
+    let shard_ids = index
+        .shards
+        .iter()
+        .enumerate()
+        .flat_map(|(id, s)| std::iter::repeat_n(Some(id as u32), s.file_count));
+    index
+        .files
+        .iter()
+        .zip(shard_ids.chain(std::iter::repeat(None)))
+        .map(|(e, shard_id)| EntryInfo {
+            path: e.path.clone(),
+            size: e.size,
+            permissions: e.permissions,
+            mtime: e.mtime,
+            is_dir: false,
+            shard_id,
+            compressed_size: None,
+            symlink: e.symlink.clone(),
+            duplicate_of: e.duplicate_of.clone(),
+        })
+        .collect()
+}
+
+/// Lists all entries of a Katana archive in index order.
+///
+/// With a password the index HMAC of encrypted archives is verified as well;
+/// without one only the CRC32 is checked (entry names are not encrypted).
+pub fn list_entries(archive_path: &Path, password: Option<&str>) -> Result<Vec<EntryInfo>, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = match password {
+        Some(_) => read_verified_index(&mut f, password)?,
+        None => read_index_crc_checked(&mut f)?,
+    };
+    Ok(entry_infos(&index))
+}
+
+/// Per-shard view of an archive for `blitzarch stats`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ShardDetails {
+    pub id: usize,
+    pub offset: u64,
+    pub files: usize,
+    pub uncompressed_size: u64,
+    pub compressed_size: u64,
+    /// `uncompressed / compressed`; 0 for an empty shard.
+    pub ratio: f64,
+    pub encrypted: bool,
+    /// Independently decodable frames (`--seekable-frames`), 0 for a single stream.
+    pub frames: usize,
+    /// BLAKE3 of the external zstd dictionary the shard needs.
+    pub dictionary: Option<String>,
+    /// Recorded at create time; `None` for archives written before stats existed.
+    pub stats: Option<ShardStats>,
+    /// Cost extraction schedules by: recorded, or estimated from the sizes.
+    pub decode_cost: u64,
+}
+
+/// Shard details straight from the index: no shard is read or decoded.
+pub fn shard_details(archive_path: &Path, password: Option<&str>) -> Result<Vec<ShardDetails>, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = match password {
+        Some(_) => read_verified_index(&mut f, password)?,
+        None => read_index_crc_checked(&mut f)?,
+    };
+    Ok(index
+        .shards
+        .iter()
+        .enumerate()
+        .map(|(id, shard)| ShardDetails {
+            id,
+            offset: shard.offset,
+            files: shard.file_count,
+            uncompressed_size: shard.uncompressed_size,
+            compressed_size: shard.compressed_size,
+            ratio: if shard.compressed_size > 0 { shard.uncompressed_size as f64 / shard.compressed_size as f64 } else { 0.0 },
+            encrypted: shard.nonce.is_some(),
+            frames: shard.frames.len(),
+            dictionary: shard.dictionary.clone(),
+            stats: shard.stats,
+            decode_cost: shard.decode_cost(),
+        })
+        .collect())
+}
+
+/// Table of [`shard_details`] for `blitzarch stats`.
+pub fn print_shard_details(shards: &[ShardDetails]) {
+    const MIB: f64 = 1024.0 * 1024.0;
+    println!(
+        "{:>5} {:>8} {:>12} {:>12} {:>7} {:>5} {:>11}  flags",
+        "shard", "files", "size MiB", "stored MiB", "ratio", "level", "decode cost"
+    );
+    for s in shards {
+        let level = s.stats.map_or_else(|| "-".to_string(), |st| st.level.to_string());
+        let mut flags = Vec::new();
+        if s.encrypted {
+            flags.push("encrypted".to_string());
+        }
+        if s.stats.is_some_and(|st| st.stored) {
+            flags.push("stored".to_string());
+        }
+        if s.frames > 0 {
+            flags.push(format!("{} frames", s.frames));
+        }
+        if s.dictionary.is_some() {
+            flags.push("dictionary".to_string());
+        }
+        println!(
+            "{:>5} {:>8} {:>12.2} {:>12.2} {:>7.2} {:>5} {:>11}  {}",
+            s.id,
+            s.files,
+            s.uncompressed_size as f64 / MIB,
+            s.compressed_size as f64 / MIB,
+            s.ratio,
+            level,
+            s.decode_cost,
+            flags.join(", ")
+        );
+    }
+    let uncompressed: u64 = shards.iter().map(|s| s.uncompressed_size).sum();
+    let compressed: u64 = shards.iter().map(|s| s.compressed_size).sum();
+    println!(
+        "[katana] {} shards, {:.2} MiB -> {:.2} MiB",
+        shards.len(),
+        uncompressed as f64 / MIB,
+        compressed as f64 / MIB
+    );
+}
+
+/// Lookup key of an entry path: `/` separators, no empty or `.` components,
+/// so `./dir//file`, `dir\\file` and `/dir/file/` all match `dir/file`.
+fn lookup_key(path: &str) -> String {
+    path.split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Entries of an archive indexed by path for fast membership checks (search
+/// boxes, "is this file in the backup?") without rescanning the entry list.
+#[derive(Debug, Clone, Default)]
+pub struct KatanaIndexView {
+    entries: Vec<EntryInfo>,
+    by_path: std::collections::HashMap<String, usize>,
+}
+
+impl KatanaIndexView {
+    /// Reads the index of `archive_path`; see [`list_entries`].
+    pub fn open(archive_path: &Path, password: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_entries(list_entries(archive_path, password)?))
+    }
+
+    /// Builds the lookup table; with duplicate paths the later entry wins, as on extract.
+    pub fn from_entries(entries: Vec<EntryInfo>) -> Self {
+        let by_path = entries.iter().enumerate().map(|(i, e)| (lookup_key(&e.path), i)).collect();
+        KatanaIndexView { entries, by_path }
+    }
+
+    /// The entry stored under `path`, if any. Separators, leading `./` or `/`
+    /// and repeated slashes are normalized before the lookup.
+    pub fn contains(&self, path: &str) -> Option<EntryInfo> {
+        self.get(path).cloned()
+    }
+
+    /// Like [`contains`](Self::contains) without cloning the entry.
+    pub fn get(&self, path: &str) -> Option<&EntryInfo> {
+        self.by_path.get(&lookup_key(path)).map(|&i| &self.entries[i])
+    }
+
+    /// All entries in index order.
+    pub fn entries(&self) -> &[EntryInfo] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// An archive whose index was loaded by [`open_lazy`]; shards are not touched
+/// until an entry is read or [`LazyArchive::verify_shards`] is called.
+#[derive(Debug)]
+pub struct LazyArchive {
+    path: PathBuf,
+    index: KatanaIndex,
+    index_reads: u32,
+    /// Archive key, derived and checked against the index HMAC on first use.
+    key: std::sync::OnceLock<[u8; 32]>,
+    /// zstd dictionaries for shards compressed with one.
+    dictionaries: Dictionaries,
+}
+
+/// Derives the key of the encrypted archive at `archive_path` on a background
+/// thread, so that a following list or extract with `password` finds it in the
+/// session key cache (see [`crypto::enable_key_cache`]). Only the index is
+/// read; returns `None` for unencrypted archives.
+pub fn prefetch_archive_key(archive_path: &Path, password: &str) -> Result<Option<std::thread::JoinHandle<()>>, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let password = password.to_string();
+    if has_sealed_index(&mut f)? {
+        // Спрятанный индекс расшифровывается тем же ключом – его и греем
+        let handle = std::thread::Builder::new().name("blitz-key-prefetch".into()).spawn(move || {
+            let _ = read_index_json(&mut f, Some(&password));
+        })?;
+        return Ok(Some(handle));
+    }
+    let (idx_json, _, _) = read_index_json(&mut f, None)?;
+    let index = parse_index_json(&idx_json)?;
+    if index.salt.is_none() {
+        return Ok(None);
+    }
+    let handle = std::thread::Builder::new().name("blitz-key-prefetch".into()).spawn(move || {
+        // Wrong passwords are reported by the real open
+        let _ = archive_key(&index, &password);
+    })?;
+    Ok(Some(handle))
+}
+
+/// Opens an archive for browsing with the minimum of I/O.
+///
+/// Only the footers and the index are read – normally in a single read from the
+/// end of the file, two for very large indexes – and only the index CRC is
+/// checked. The shard CRC pre-scan done by extraction and the HMAC check of
+/// encrypted archives are deferred until data is actually requested, so opening
+/// an archive on slow or network media is instant.
+pub fn open_lazy(archive_path: &Path) -> Result<LazyArchive, Box<dyn Error>> {
+    open_lazy_with_password(archive_path, None)
+}
+
+/// [`open_lazy`] that also opens sealed indexes (`--hide-names`) with `password`.
+pub fn open_lazy_with_password(archive_path: &Path, password: Option<&str>) -> Result<LazyArchive, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (idx_json, _, index_reads) = read_index_json(&mut f, password)?;
+    Ok(LazyArchive {
+        path: archive_path.to_path_buf(),
+        index: parse_index_json(&idx_json)?,
+        index_reads,
+        key: std::sync::OnceLock::new(),
+        dictionaries: Dictionaries::new(),
+    })
+}
+
+/// Opens a hosted archive (`https://…`, `http://…` or `s3://bucket/key`) with
+/// range requests: the footer and index are fetched now, shards only when
+/// entries are read or extracted. See [`crate::remote::RemoteArchive`].
+#[cfg(feature = "remote")]
+pub fn open_remote(url: &str) -> Result<crate::remote::RemoteArchive, Box<dyn Error>> {
+    crate::remote::RemoteArchive::open(url, None)
+}
+
+impl LazyArchive {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of reads it took to load the index (1 or 2 for well-formed archives).
+    pub fn index_reads(&self) -> u32 {
+        self.index_reads
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.index.salt.is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.files.is_empty()
+    }
+
+    /// All entries in index order.
+    pub fn entries(&self) -> Vec<EntryInfo> {
+        entry_infos(&self.index)
+    }
+
+    /// Path lookup table over the already loaded index.
+    pub fn index_view(&self) -> KatanaIndexView {
+        KatanaIndexView::from_entries(entry_infos(&self.index))
+    }
+
+    /// Dictionaries used to read shards compressed with one
+    /// (`create --dictionary`).
+    pub fn with_dictionaries(mut self, dictionaries: Dictionaries) -> Self {
+        self.dictionaries = dictionaries;
+        self
+    }
+
+    /// Streams one entry; see [`open_entry`]. The first call on an encrypted
+    /// archive derives the key and verifies the index HMAC.
+    pub fn open_entry(&self, entry_path: &str, password: Option<&str>) -> Result<EntryReader, Box<dyn Error>> {
+        if let Some(base_path) = base_of_entry(&self.path, &self.index, entry_path)? {
+            return open_entry_with(&base_path, entry_path, password, &self.dictionaries);
+        }
+        let key = self.key(password)?;
+        open_entry_in(&self.path, &self.index, entry_path, key, &self.dictionaries)
+    }
+
+    /// Reads part of one entry; see [`read_range`].
+    pub fn read_range(&self, entry_path: &str, offset: u64, len: u64, password: Option<&str>) -> Result<Vec<u8>, Box<dyn Error>> {
+        if let Some(base_path) = base_of_entry(&self.path, &self.index, entry_path)? {
+            return read_len(open_entry_from(&base_path, entry_path, offset, password, &self.dictionaries)?, len);
+        }
+        let key = self.key(password)?;
+        read_len(open_entry_at(&self.path, &self.index, entry_path, key, offset, &self.dictionaries)?, len)
+    }
+
+    /// The deferred shard pre-scan: checks the CRC32 of every shard.
+    pub fn verify_shards(&self) -> Result<(), Box<dyn Error>> {
+        verify_shard_crcs(&self.path, data_sections(&self.index)).map(|_| ())
+    }
+
+    /// Derives the key of an encrypted archive and checks it against the index
+    /// HMAC, so a wrong password fails before the first read.
+    pub fn check_password(&self, password: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.key(password).map(|_| ())
+    }
+
+    fn key(&self, password: Option<&str>) -> Result<Option<&[u8; 32]>, Box<dyn Error>> {
+        if self.index.salt.is_none() {
+            return Ok(None);
+        }
+        if let Some(key) = self.key.get() {
+            return Ok(Some(key));
+        }
+        let pass = password.ok_or("Password/key required for encrypted archive")?;
+        let key = archive_key(&self.index, pass)?;
+        verify_index_hmac(&self.index, &key)?;
+        Ok(Some(self.key.get_or_init(|| key)))
+    }
+}
+
+/// Checks the checksum (CRC32 or XXH3) of every shard; returns the number of bytes read.
+fn verify_shard_crcs<'a>(
+    archive_path: &Path,
+    shards: impl IntoIterator<Item = &'a ShardInfo>,
+) -> Result<u64, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let mut buf = vec![0u8; 1 << 20];
+    let mut total = 0u64;
+    for shard in shards {
+        f.seek(SeekFrom::Start(shard.offset))?;
+        let mut reader = (&mut f).take(shard.compressed_size);
+        let mut digest = ShardDigest::new(shard.checksum());
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 { break; }
+            digest.update(&buf[..n]);
+            total += n as u64;
+        }
+        digest.check(shard)?;
+    }
+    Ok(total)
+}
+
+/// Source fingerprint recorded when the archive was created (see
+/// [`crate::katana_stream::source_fingerprint`]); `None` for older archives
+/// and archives appended to since. Sealed indexes (`--hide-names`) need `password`.
+pub fn archive_source_fingerprint(archive_path: &Path, password: Option<&str>) -> Result<Option<String>, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = read_index_with(&mut f, password)?;
+    Ok(index.source_fingerprint)
+}
+
+/// Outcome of [`verify_archive`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    pub shards: usize,
+    pub files: usize,
+    /// Index HMAC was checked (encrypted archive opened with a password).
+    pub index_hmac: bool,
+    /// BLAKE3 footer was present and matched.
+    pub blake3: bool,
+    /// Every shard was decompressed and the entry sizes matched (`--deep`).
+    pub deep: bool,
+    /// The time budget ran out before this shard; shards from here on are unchecked.
+    pub resume_at: Option<usize>,
+    /// Stored shard bytes read and checked.
+    pub bytes: u64,
+    /// Damage found by [`crate::timebox::verify_timeboxed`], which checks every
+    /// shard instead of stopping at the first bad one.
+    pub corrupt: Vec<CorruptShard>,
+}
+
+/// A shard (or the BLAKE3 footer, or the chunk store) that failed verification.
+#[derive(Debug, Clone, Serialize)]
+pub struct CorruptShard {
+    /// `None` for damage not tied to one shard (BLAKE3 footer, chunk store).
+    pub shard: Option<usize>,
+    pub error: String,
+    /// Entries whose data lives in the damaged shard.
+    pub entries: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Report for a check that could not get past the index (unreadable,
+    /// tampered, wrong password): nothing verified, one problem.
+    pub fn failed(error: impl Into<String>) -> Self {
+        VerifyReport {
+            corrupt: vec![CorruptShard { shard: None, error: error.into(), entries: Vec::new() }],
+            ..Default::default()
+        }
+    }
+
+    /// One-line status for cron/Nagios wrappers, e.g.
+    /// `VERIFY OK shards=12/12 files=340 bytes=1048576 corrupt=0 duration=0.84s archive=/backups/a.blz`.
+    pub fn status_line(&self, archive_path: &Path, elapsed: std::time::Duration) -> String {
+        let status = if !self.corrupt.is_empty() {
+            "FAILED"
+        } else if self.resume_at.is_some() {
+            "INCOMPLETE"
+        } else {
+            "OK"
+        };
+        format!(
+            "VERIFY {} shards={}/{} files={} bytes={} corrupt={} duration={:.2}s archive={}",
+            status,
+            self.resume_at.unwrap_or(self.shards),
+            self.shards,
+            self.files,
+            self.bytes,
+            self.corrupt.iter().map(|c| c.entries.len().max(1)).sum::<usize>(),
+            elapsed.as_secs_f64(),
+            archive_path.display()
+        )
+    }
+}
+
+/// Checks an archive without extracting anything to disk: index CRC32 (and
+/// HMAC when a password is given), the optional BLAKE3 footer and every shard
+/// CRC32. With `deep` each shard is also decrypted and decompressed – which
+/// checks the zstd frame checksums – and the decoded length must match the
+/// sizes of the shard's entries. The chunk store of `--dedup=chunks` archives
+/// is checked the same way after the last shard. Files held in a base archive
+/// are not checked.
+///
+/// Shards compressed with an external dictionary need [`verify_archive_from`]
+/// for `deep`.
+pub fn verify_archive(archive_path: &Path, password: Option<&str>, deep: bool) -> Result<VerifyReport, Box<dyn Error>> {
+    verify_archive_from(archive_path, password, deep, 0, None, &Dictionaries::new())
+}
+
+/// [`verify_archive`] starting at shard `start_shard` and stopping before the
+/// next shard once `budget` is exhausted ([`VerifyReport::resume_at`]). The
+/// BLAKE3 footer covers the whole file and is only checked when starting at 0.
+pub fn verify_archive_from(
+    archive_path: &Path,
+    password: Option<&str>,
+    deep: bool,
+    start_shard: usize,
+    budget: Option<&crate::timebox::TimeBudget>,
+    dictionaries: &Dictionaries,
+) -> Result<VerifyReport, Box<dyn Error>> {
+    verify_archive_impl(archive_path, password, deep, start_shard, budget, false, dictionaries)
+}
+
+/// With `keep_going` damaged data is recorded in [`VerifyReport::corrupt`]
+/// instead of failing; unreadable or tampered indexes still fail.
+pub(crate) fn verify_archive_impl(
+    archive_path: &Path,
+    password: Option<&str>,
+    deep: bool,
+    start_shard: usize,
+    budget: Option<&crate::timebox::TimeBudget>,
+    keep_going: bool,
+    dictionaries: &Dictionaries,
+) -> Result<VerifyReport, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = match password {
+        Some(_) => read_verified_index(&mut f, password)?,
+        None => read_index_crc_checked(&mut f)?,
+    };
+    if start_shard > index.shards.len() {
+        return Err(format!("Archive has only {} shards, cannot resume at {}", index.shards.len(), start_shard).into());
+    }
+    let mut report = VerifyReport {
+        shards: index.shards.len(),
+        files: index.files.len(),
+        index_hmac: password.is_some() && index.hmac.is_some(),
+        ..Default::default()
+    };
+
+    // Optional BLAKE3 footer over everything before it
+    let file_len = f.metadata()?.len();
+    let data_len = data_len_without_footer(&mut f, file_len)?;
+    if data_len != file_len && start_shard == 0 {
+        let expected = footer_blake3_hex(archive_path)?.ok_or("Footer magic mismatch")?;
+        f.seek(SeekFrom::Start(0))?;
+        let mut hasher = blake3::Hasher::new();
+        std::io::copy(&mut (&mut f).take(data_len), &mut hasher)?;
+        if hasher.finalize().to_hex().as_str() == expected {
+            report.blake3 = true;
+        } else if keep_going {
+            // The shards below tell which entries are affected
+            report.corrupt.push(CorruptShard {
+                shard: None,
+                error: "BLAKE3 footer mismatch".into(),
+                entries: Vec::new(),
+            });
         } else {
-            return Err("Encrypted archive: password required for HMAC verification".into());
+            return Err("BLAKE3 footer mismatch: archive is corrupted".into());
         }
     }
-    
-    // Print archive information
-    if index.salt.is_some() && password.is_none() {
-        println!("Archive is encrypted.");
+
+    let key_bytes = match (deep, password, index.salt) {
+        (true, Some(pass), Some(_)) => Some(archive_key(&index, pass)?),
+        (true, None, Some(_)) => return Err("Encrypted archive: password required for deep verification".into()),
+        _ => None,
+    };
+    let workspace = if deep { Some(TempWorkspace::new("verify")?) } else { None };
+    let mut first: usize = index.shards[..start_shard].iter().map(|s| s.file_count).sum();
+    for (id, shard) in index.shards.iter().enumerate().skip(start_shard) {
+        if budget.is_some_and(|b| b.expired()) {
+            report.resume_at = Some(id);
+            return Ok(report);
+        }
+        let entries = &index.files[first..first + shard.file_count];
+        first += shard.file_count;
+        let checked = (|| -> Result<u64, Box<dyn Error>> {
+            let bytes = verify_shard_crcs(archive_path, std::slice::from_ref(shard))?;
+            let expected: u64 = entries.iter().map(|e| e.size).sum();
+            let Some(workspace) = &workspace else { return Ok(bytes) };
+            let (reader, _decrypted_tmp) = open_shard_stream(archive_path, shard, key_bytes.as_ref(), workspace)?;
+            let mut decoder = shard.decoder(reader, dictionaries)?;
+            let decoded = std::io::copy(&mut decoder, &mut std::io::sink())
+                .map_err(|e| format!("Shard {} failed to decode: {}", id, e))?;
+            if decoded != expected || decoded != shard.uncompressed_size {
+                return Err(format!(
+                    "Shard {} decodes to {} bytes, entries list {} (index says {})",
+                    id, decoded, expected, shard.uncompressed_size
+                )
+                .into());
+            }
+            Ok(bytes)
+        })();
+        match checked {
+            Ok(bytes) => report.bytes += bytes,
+            Err(e) if keep_going => report.corrupt.push(CorruptShard {
+                shard: Some(id),
+                error: e.to_string(),
+                entries: entries.iter().map(|e| e.path.clone()).collect(),
+            }),
+            Err(e) => return Err(e),
+        }
     }
-    
-    println!("Archive Index ({} files):", index.files.len());
-    
-    // Print the list of files
-    for file in &index.files {
-        println!("- {} ({} bytes)", file.path, file.size);
+    // The chunk store is checked after the last shard
+    if let Some(store) = &index.chunk_store {
+        if budget.is_some_and(|b| b.expired()) {
+            report.resume_at = Some(index.shards.len());
+            return Ok(report);
+        }
+        let checked = match &workspace {
+            Some(workspace) => ChunkStore::open(archive_path, Some(store), &index.chunk_sizes, key_bytes.as_ref(), workspace)
+                .map(|_| store.compressed_size),
+            None => verify_shard_crcs(archive_path, [store]),
+        };
+        match checked {
+            Ok(bytes) => report.bytes += bytes,
+            Err(e) if keep_going => report.corrupt.push(CorruptShard {
+                shard: None,
+                error: format!("Chunk store: {}", e),
+                entries: index.files.iter().filter(|e| !e.chunks.is_empty()).map(|e| e.path.clone()).collect(),
+            }),
+            Err(e) => return Err(e),
+        }
     }
-    
-    Ok(())
+    report.deep = deep && report.corrupt.is_empty();
+    Ok(report)
 }
 
 /// Internal helper that accepts a list of files to extract. Empty slice ⇒ extract all.
@@ -789,6 +4499,94 @@ pub fn extract_katana_archive_internal(
     extract_katana_archive_with_progress(archive_path, output_dir, selected_files, password, strip_components, None::<fn(ProgressState)>)
 }
 
+/// Nesting depth `extract --recursive-extract` follows (archives of archives).
+const MAX_NESTED_DEPTH: usize = 8;
+
+/// `extract --recursive-extract`: [`extract_katana_archive_with_progress`],
+/// then the nested BlitzArch archives it wrote (entries marked by
+/// `create --store-nested`) are unpacked into a directory next to them named
+/// after the archive (`logs.blz` → `logs/`) and the archive file is removed.
+/// Files that were already there, or skipped because of a conflict policy, are
+/// never touched.
+///
+/// Nested archives are opened with the same password; ones that cannot be
+/// extracted are kept with a warning. Returns the number of archives unpacked,
+/// at any depth.
+pub fn extract_katana_archive_recursive<F>(
+    archive_path: &Path,
+    output_dir: &Path,
+    selected_files: &[PathBuf],
+    password: Option<String>,
+    strip_components: Option<u32>,
+    progress_callback: Option<F>,
+    options: &ExtractOptions,
+) -> Result<usize, Box<dyn Error>>
+where
+    F: Fn(ProgressState) + Send + Sync + 'static,
+{
+    let written = Mutex::new(Vec::new());
+    extract_katana_archive_with_progress_impl(
+        archive_path,
+        output_dir,
+        selected_files,
+        password.clone(),
+        strip_components,
+        progress_callback,
+        None,
+        Some(&written),
+        options,
+    )?;
+    unpack_nested_archives(written.into_inner().unwrap(), &password, 0, options)
+}
+
+/// Unpacks the nested archives a run wrote (see [`extract_katana_archive_recursive`]).
+fn unpack_nested_archives(
+    mut written: Vec<PathBuf>,
+    password: &Option<String>,
+    depth: usize,
+    options: &ExtractOptions,
+) -> Result<usize, Box<dyn Error>> {
+    written.sort();
+    written.dedup();
+    let mut unpacked = 0;
+    for nested in written {
+        let shown = crate::private_logs::path(&nested.to_string_lossy());
+        if !is_katana_archive(&nested).unwrap_or(false) {
+            continue;
+        }
+        if depth >= MAX_NESTED_DEPTH {
+            warn(WarningKind::SkippedFile, nested.to_string_lossy(), format!("Not unpacking {}: archives nested deeper than {} levels", shown, MAX_NESTED_DEPTH));
+            continue;
+        }
+        let target = match nested.with_extension("") {
+            dir if dir != nested => dir,
+            _ => nested.with_extension("d"),
+        };
+        let inner = Mutex::new(Vec::new());
+        let result = extract_katana_archive_with_progress_impl(
+            &nested,
+            &target,
+            &[],
+            password.clone(),
+            None,
+            None::<fn(ProgressState)>,
+            None,
+            Some(&inner),
+            options,
+        )
+        .and_then(|()| unpack_nested_archives(inner.into_inner().unwrap(), password, depth + 1, options));
+        match result {
+            Ok(inner) => {
+                std::fs::remove_file(&nested)?;
+                println!("[katana] Unpacked nested archive {} → {}", shown, crate::private_logs::path(&target.to_string_lossy()));
+                unpacked += 1 + inner;
+            }
+            Err(e) => warn(WarningKind::SkippedFile, nested.to_string_lossy(), format!("Kept nested archive {}: {}", shown, e)),
+        }
+    }
+    Ok(unpacked)
+}
+
 /// Public wrapper for Katana extraction with optional real-time progress.
 ///
 /// This thin wrapper forwards to `extract_katana_archive_with_progress_impl` so that
@@ -812,10 +4610,483 @@ where
         password,
         strip_components,
         progress_callback,
+        None,
+        None,
+        &ExtractOptions::default(),
+    )
+}
+
+/// [`extract_katana_archive_with_progress`] with the job's [`ExtractOptions`],
+/// stopping once `cancel` (if any) is set like
+/// [`extract_katana_archive_cancellable`].
+#[allow(clippy::too_many_arguments)]
+pub fn extract_katana_archive_with_options<F>(
+    archive_path: &Path,
+    output_dir: &Path,
+    selected_files: &[PathBuf],
+    password: Option<String>,
+    strip_components: Option<u32>,
+    progress_callback: Option<F>,
+    cancel: Option<&CancellationToken>,
+    options: &ExtractOptions,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(ProgressState) + Send + Sync + 'static,
+{
+    extract_katana_archive_with_progress_impl(
+        archive_path,
+        output_dir,
+        selected_files,
+        password,
+        strip_components,
+        progress_callback,
+        cancel,
+        None,
+        options,
+    )
+}
+
+/// [`extract_katana_archive_with_progress`] that stops once `cancel` is set.
+///
+/// Shard workers quit at their next read buffer. Files and directories this
+/// call created are removed again (files it overwrote cannot be restored) and
+/// the call fails with [`crate::ArchiverError::Cancelled`].
+pub fn extract_katana_archive_cancellable<F>(
+    archive_path: &Path,
+    output_dir: &Path,
+    selected_files: &[PathBuf],
+    password: Option<String>,
+    strip_components: Option<u32>,
+    progress_callback: Option<F>,
+    cancel: &CancellationToken,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(ProgressState) + Send + Sync + 'static,
+{
+    extract_katana_archive_with_progress_impl(
+        archive_path,
+        output_dir,
+        selected_files,
+        password,
+        strip_components,
+        progress_callback,
+        Some(cancel),
+        None,
+        &ExtractOptions::default(),
     )
 }
 
+/// Streaming reader over the content of a single archive entry.
+///
+/// Created by [`open_entry`]. Data is decompressed lazily as the caller reads,
+/// so memory use stays constant regardless of the entry size.
+pub struct EntryReader {
+    // Field order = drop order: close the shard stream before removing its scratch file
+    inner: std::io::Take<Box<dyn Read + Send>>,
+    size: u64,
+    _decrypted_tmp: Option<WorkspaceFile>,
+}
+
+impl EntryReader {
+    /// Uncompressed size of the entry.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes left to read.
+    pub fn remaining(&self) -> u64 {
+        self.inner.limit()
+    }
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() && self.inner.limit() > 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "shard ended before entry"));
+        }
+        Ok(n)
+    }
+}
+
+/// Opens a single entry for streaming, e.g. to pipe a huge file into another
+/// sink without extracting it to disk first.
+///
+/// Only the part of the shard up to the end of the entry is ever decoded.
+/// Encrypted shards are authenticated and decrypted into a private temp file
+/// before the first byte is returned; plain shards are streamed straight from the
+/// archive (shard CRC is not checked up front, zstd checks frame integrity).
+pub fn open_entry(archive_path: &Path, entry_path: &str, password: Option<&str>) -> Result<EntryReader, Box<dyn Error>> {
+    open_entry_with(archive_path, entry_path, password, &Dictionaries::new())
+}
+
+/// [`open_entry`] for archives whose shards were compressed with a zstd
+/// dictionary.
+pub fn open_entry_with(
+    archive_path: &Path,
+    entry_path: &str,
+    password: Option<&str>,
+    dictionaries: &Dictionaries,
+) -> Result<EntryReader, Box<dyn Error>> {
+    open_entry_from(archive_path, entry_path, 0, password, dictionaries)
+}
+
+/// Reads `len` bytes of an entry starting at `offset` (fewer at the end of the
+/// entry).
+///
+/// Shards written with a seek table (`create --seekable-frames`) are decoded
+/// from the frame holding `offset`, so a small range of a file deep inside a
+/// large shard costs about one frame of decompression. Other shards are decoded
+/// from their start up to the end of the range.
+pub fn read_range(
+    archive_path: &Path,
+    entry_path: &str,
+    offset: u64,
+    len: u64,
+    password: Option<&str>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    read_len(open_entry_from(archive_path, entry_path, offset, password, &Dictionaries::new())?, len)
+}
+
+fn read_len(reader: EntryReader, len: u64) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::with_capacity(len.min(reader.remaining()) as usize);
+    reader.take(len).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn open_entry_from(
+    archive_path: &Path,
+    entry_path: &str,
+    start: u64,
+    password: Option<&str>,
+    dictionaries: &Dictionaries,
+) -> Result<EntryReader, Box<dyn Error>> {
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = read_verified_index(&mut f, password)?;
+    if let Some(base_path) = base_of_entry(archive_path, &index, entry_path)? {
+        return open_entry_from(&base_path, entry_path, start, password, dictionaries);
+    }
+    let key_bytes = match (password, index.salt) {
+        (Some(pass), Some(_)) => Some(archive_key(&index, pass)?),
+        (None, Some(_)) => return Err("Password/key required for encrypted archive".into()),
+        _ => None,
+    };
+    open_entry_at(archive_path, &index, entry_path, key_bytes.as_ref(), start, dictionaries)
+}
+
+/// Base archive holding `entry_path`, if it is a base reference.
+fn base_of_entry(archive_path: &Path, index: &KatanaIndex, entry_path: &str) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    let wanted = normalize_path(entry_path);
+    match &index.base {
+        Some(link) if index.files.iter().any(|e| e.base_ref && e.path == wanted) => Ok(Some(resolve_base(archive_path, link)?)),
+        _ => Ok(None),
+    }
+}
+
+fn open_entry_in(
+    archive_path: &Path,
+    index: &KatanaIndex,
+    entry_path: &str,
+    key_bytes: Option<&[u8; 32]>,
+    dictionaries: &Dictionaries,
+) -> Result<EntryReader, Box<dyn Error>> {
+    open_entry_at(archive_path, index, entry_path, key_bytes, 0, dictionaries)
+}
+
+/// Reader over an entry from uncompressed byte `start` on.
+fn open_entry_at(
+    archive_path: &Path,
+    index: &KatanaIndex,
+    entry_path: &str,
+    key_bytes: Option<&[u8; 32]>,
+    start: u64,
+    dictionaries: &Dictionaries,
+) -> Result<EntryReader, Box<dyn Error>> {
+    let wanted = normalize_path(entry_path);
+    let pos = index
+        .files
+        .iter()
+        .position(|e| e.path == wanted)
+        .ok_or_else(|| format!("Entry not found in archive: {}", entry_path))?;
+    let entry = &index.files[pos];
+    if entry.base_ref {
+        return Err(format!("Entry {} is stored in the base archive", crate::private_logs::path(&entry.path)).into());
+    }
+    if let Some(target) = &entry.symlink {
+        return Err(format!("Entry {} is a symbolic link to {}", crate::private_logs::path(&entry.path), crate::private_logs::path(target)).into());
+    }
+    if let Some(original) = &entry.duplicate_of {
+        return open_entry_at(archive_path, index, original, key_bytes, start, dictionaries);
+    }
+    let start = start.min(entry.size);
+    let left = entry.size - start;
+
+    if let Some(data) = &entry.inline {
+        let mut cursor = std::io::Cursor::new(data.clone());
+        cursor.set_position(start);
+        let reader: Box<dyn Read + Send> = Box::new(cursor);
+        return Ok(EntryReader { inner: reader.take(left), size: entry.size, _decrypted_tmp: None });
+    }
+    if !entry.chunks.is_empty() {
+        let workspace = TempWorkspace::new("entry")?;
+        let store = ChunkStore::open(archive_path, index.chunk_store.as_ref(), &index.chunk_sizes, key_bytes, &workspace)?;
+        let mut reader: Box<dyn Read + Send> = Box::new(store.reader([entry])?);
+        if std::io::copy(&mut (&mut reader).take(start), &mut std::io::sink())? != start {
+            return Err("Unexpected end of chunk store while seeking in entry".into());
+        }
+        return Ok(EntryReader { inner: reader.take(left), size: entry.size, _decrypted_tmp: Some(store.file) });
+    }
+
+    let mut first = 0usize;
+    let mut shard = None;
+    for s in &index.shards {
+        if pos < first + s.file_count {
+            shard = Some(s);
+            break;
+        }
+        first += s.file_count;
+    }
+    let shard_info = shard.ok_or("Entry belongs to no shard")?;
+    // Entries are stored back-to-back, so the entry starts after its predecessors
+    let pos_in_shard: u64 = index.files[first..pos].iter().map(|e| e.size).sum::<u64>() + start;
+    let workspace = TempWorkspace::new("entry")?;
+    if let Some(data) = cached_shard(archive_path, shard_info, key_bytes, &workspace, dictionaries)? {
+        let mut cursor = std::io::Cursor::new(crate::shard_cache::SharedBytes(data));
+        cursor.set_position(pos_in_shard);
+        let reader: Box<dyn Read + Send> = Box::new(cursor);
+        return Ok(EntryReader { inner: reader.take(left), size: entry.size, _decrypted_tmp: None });
+    }
+    // With a seek table decoding starts at the frame holding the first byte
+    let (frame_comp, frame_plain) = shard_info.frame_start(pos_in_shard);
+    let skip = pos_in_shard - frame_plain;
+    let (reader, decrypted_tmp) = open_shard_stream_at(archive_path, shard_info, key_bytes, &workspace, frame_comp)?;
+    let mut decoder: Box<dyn Read + Send> = Box::new(shard_info.decoder(reader, dictionaries)?);
+    // Skip the bytes stored before the start position
+    let skipped = std::io::copy(&mut (&mut decoder).take(skip), &mut std::io::sink())?;
+    if skipped != skip {
+        return Err("Unexpected end of shard while seeking to entry".into());
+    }
+    Ok(EntryReader { inner: decoder.take(left), size: entry.size, _decrypted_tmp: decrypted_tmp })
+}
+
+/// SHA-256 of the content of every entry, in index order (aligned with
+/// [`list_entries`]); `None` for symbolic links.
+///
+/// The CRC32 of every shard (and of the base archive's shards, if entries are
+/// stored there) is checked before anything is hashed, so a damaged archive
+/// fails instead of yielding digests of corrupt data. Each shard is then
+/// decoded once, front to back; entries stored elsewhere (inline, chunk store,
+/// base archive) are read through [`open_entry`]'s reader.
+pub fn entry_sha256(archive_path: &Path, password: Option<&str>) -> Result<Vec<Option<[u8; 32]>>, Box<dyn Error>> {
+    use sha2::{Digest, Sha256};
+
+    let dictionaries = Dictionaries::new();
+
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = read_verified_index(&mut f, password)?;
+    let key_bytes = match (password, index.salt) {
+        (Some(pass), Some(_)) => Some(archive_key(&index, pass)?),
+        (None, Some(_)) => return Err("Encrypted archive: password required to hash entries".into()),
+        _ => None,
+    };
+    verify_shard_crcs(archive_path, data_sections(&index))?;
+    let mut base_checked = false;
+    let hash_reader = |reader: &mut dyn Read, size: u64| -> Result<[u8; 32], Box<dyn Error>> {
+        let mut hasher = Sha256::new();
+        if std::io::copy(&mut reader.take(size), &mut hasher)? != size {
+            return Err("Unexpected end of data while hashing entry".into());
+        }
+        Ok(hasher.finalize().into())
+    };
+
+    let mut digests = vec![None; index.files.len()];
+    let workspace = TempWorkspace::new("hash")?;
+    let mut first = 0usize;
+    for shard in &index.shards {
+        let entries = first..first + shard.file_count;
+        first += shard.file_count;
+        let (reader, _decrypted_tmp) = open_shard_stream(archive_path, shard, key_bytes.as_ref(), &workspace)?;
+        let mut decoder = shard.decoder(reader, &dictionaries)?;
+        // Entries of a shard are stored back to back
+        for i in entries {
+            digests[i] = Some(hash_reader(&mut decoder, index.files[i].size)?);
+        }
+    }
+    let by_path: std::collections::HashMap<&str, usize> =
+        index.files.iter().enumerate().map(|(i, e)| (e.path.as_str(), i)).collect();
+    for i in first..index.files.len() {
+        let entry = &index.files[i];
+        if entry.symlink.is_some() {
+            continue;
+        }
+        // Copies share the digest of their original
+        let source = match entry.duplicate_of.as_deref().and_then(|original| by_path.get(original)) {
+            Some(&j) => j,
+            None => i,
+        };
+        let data = &index.files[source];
+        digests[i] = Some(match digests[source] {
+            Some(digest) => digest,
+            None if data.base_ref => {
+                let base_path = base_of_entry(archive_path, &index, &data.path)?.ok_or("Base reference without base archive")?;
+                if !base_checked {
+                    let (base_index, _) = read_verified_index(&mut open_archive(&base_path)?, password)?;
+                    verify_shard_crcs(&base_path, data_sections(&base_index))?;
+                    base_checked = true;
+                }
+                hash_reader(&mut open_entry_from(&base_path, &data.path, 0, password, &dictionaries)?, data.size)?
+            }
+            None => hash_reader(&mut open_entry_in(archive_path, &index, &data.path, key_bytes.as_ref(), &dictionaries)?, data.size)?,
+        });
+    }
+    Ok(digests)
+}
+
+/// Extracted files kept in memory, keyed by normalized archive path.
+pub type MemoryTree = std::collections::BTreeMap<String, Vec<u8>>;
+
+/// Extracts files into memory instead of onto disk.
+///
+/// Meant for previews (GUI), the self-test and unit tests – nothing is written
+/// outside the job's temp workspace (only used for encrypted shards). The total
+/// size of the selected files is checked against `budget_bytes` up front, so a
+/// large archive fails fast instead of exhausting RAM. Empty `selected_files` ⇒
+/// all files.
+pub fn extract_katana_to_memory(
+    archive_path: &Path,
+    selected_files: &[PathBuf],
+    password: Option<String>,
+    budget_bytes: u64,
+) -> Result<MemoryTree, Box<dyn Error>> {
+    extract_katana_to_memory_with(archive_path, selected_files, password, budget_bytes, &Dictionaries::new())
+}
+
+/// [`extract_katana_to_memory`] for archives whose shards were compressed with
+/// a zstd dictionary.
+pub fn extract_katana_to_memory_with(
+    archive_path: &Path,
+    selected_files: &[PathBuf],
+    password: Option<String>,
+    budget_bytes: u64,
+    dictionaries: &Dictionaries,
+) -> Result<MemoryTree, Box<dyn Error>> {
+    use std::collections::HashSet;
+
+    let mut f = open_archive(archive_path)?;
+    let (index, _) = read_verified_index(&mut f, password.as_deref())?;
+
+    let selector = EntrySelector::new(selected_files)?;
+    // Symbolic links have no place in a memory tree
+    let is_wanted = |e: &FileEntry| e.symlink.is_none() && (selector.is_empty() || selector.matches(&e.path));
+
+    let total: u64 = index.files.iter().filter(|e| is_wanted(e)).map(|e| e.size).sum();
+    if total > budget_bytes {
+        return Err(format!(
+            "In-memory extraction budget exceeded ({} > {} bytes)",
+            total, budget_bytes
+        )
+        .into());
+    }
+
+    let key_bytes = match (password.as_ref(), index.salt) {
+        (Some(pass), Some(_)) => Some(archive_key(&index, pass)?),
+        (None, Some(_)) => return Err("Password/key required for encrypted archive".into()),
+        _ => None,
+    };
+    let workspace = TempWorkspace::new("memextract")?;
+
+    let mut tree = MemoryTree::new();
+    let mut file_cursor = 0usize;
+    for shard_info in &index.shards {
+        let files = &index.files[file_cursor..file_cursor + shard_info.file_count];
+        file_cursor += shard_info.file_count;
+        let Some(decode_end) = files.iter().rposition(&is_wanted).map(|i| i + 1) else {
+            continue; // shard holds nothing we need
+        };
+        if let Some(data) = cached_shard(archive_path, shard_info, key_bytes.as_ref(), &workspace, dictionaries)? {
+            let mut start = 0usize;
+            for entry in &files[..decode_end] {
+                let end = start + entry.size as usize;
+                if is_wanted(entry) {
+                    let bytes = data.get(start..end).ok_or_else(|| format!("Unexpected end of shard while reading {}", crate::private_logs::path(&entry.path)))?;
+                    tree.insert(entry.path.clone(), bytes.to_vec());
+                }
+                start = end;
+            }
+            continue;
+        }
+
+        let mut crc_reader = File::open(archive_path)?;
+        crc_reader.seek(SeekFrom::Start(shard_info.offset))?;
+        let mut digest = ShardDigest::new(shard_info.checksum());
+        std::io::copy(&mut crc_reader.take(shard_info.compressed_size), &mut digest)?;
+        digest.check(shard_info)?;
+
+        let (reader, _decrypted_tmp) = open_shard_stream(archive_path, shard_info, key_bytes.as_ref(), &workspace)?;
+        let mut decoder = shard_info.decoder(reader, dictionaries)?;
+        for entry in &files[..decode_end] {
+            if is_wanted(entry) {
+                let mut data = Vec::with_capacity(entry.size as usize);
+                (&mut decoder).take(entry.size).read_to_end(&mut data)?;
+                if data.len() as u64 != entry.size {
+                    return Err(format!("Unexpected end of shard while reading {}", crate::private_logs::path(&entry.path)).into());
+                }
+                tree.insert(entry.path.clone(), data);
+            } else {
+                std::io::copy(&mut (&mut decoder).take(entry.size), &mut std::io::sink())?;
+            }
+        }
+    }
+    // Inlined small files follow the shard entries, base references come last
+    let refs_start = base_refs_start(&index);
+    for entry in index.files[file_cursor..refs_start]
+        .iter()
+        .filter(|e| is_wanted(e) && e.duplicate_of.is_none() && e.chunks.is_empty())
+    {
+        tree.insert(entry.path.clone(), entry.inline.clone().unwrap_or_default());
+    }
+    let chunked: Vec<&FileEntry> =
+        index.files[file_cursor..refs_start].iter().filter(|e| is_wanted(e) && !e.chunks.is_empty()).collect();
+    if !chunked.is_empty() {
+        let store = ChunkStore::open(archive_path, index.chunk_store.as_ref(), &index.chunk_sizes, key_bytes.as_ref(), &workspace)?;
+        let mut reader = store.reader(chunked.iter().copied())?;
+        for entry in chunked {
+            let mut data = Vec::with_capacity(entry.size as usize);
+            (&mut reader).take(entry.size).read_to_end(&mut data)?;
+            tree.insert(entry.path.clone(), data);
+        }
+    }
+    // Duplicates share the data of their original, which may not be selected itself
+    for entry in index.files[file_cursor..refs_start].iter().filter(|e| is_wanted(e)) {
+        let Some(original) = &entry.duplicate_of else { continue };
+        let data = match tree.get(original) {
+            Some(data) => data.clone(),
+            None => {
+                let mut data = Vec::with_capacity(entry.size as usize);
+                open_entry_in(archive_path, &index, original, key_bytes.as_ref(), dictionaries)?.read_to_end(&mut data)?;
+                data
+            }
+        };
+        tree.insert(entry.path.clone(), data);
+    }
+    let from_base: Vec<PathBuf> = index.files[refs_start..]
+        .iter()
+        .filter(|e| is_wanted(e))
+        .map(|e| PathBuf::from(&e.path))
+        .collect();
+    if let (false, Some(link)) = (from_base.is_empty(), index.base.as_ref()) {
+        let base_path = resolve_base(archive_path, link)?;
+        tree.append(&mut extract_katana_to_memory_with(&base_path, &from_base, password, budget_bytes, dictionaries)?);
+    }
+    Ok(tree)
+}
+
+/// Memory an extraction reserves in the [`crate::governor`] while its shard
+/// workers run.
+const EXTRACT_LEASE_MB: u64 = 512;
+
 /// Internal implementation of Katana extraction with progress support.
+#[allow(clippy::too_many_arguments)]
 fn extract_katana_archive_with_progress_impl<F>(
     archive_path: &Path,
     output_dir: &Path,
@@ -823,11 +5094,14 @@ fn extract_katana_archive_with_progress_impl<F>(
     password: Option<String>,
     strip_components: Option<u32>,
     progress_callback: Option<F>,
+    cancel: Option<&CancellationToken>,
+    nested_written: Option<&Mutex<Vec<PathBuf>>>,
+    options: &ExtractOptions,
 ) -> Result<(), Box<dyn Error>>
 where
     F: Fn(ProgressState) + Send + Sync + 'static,
 {
-    let mut f = File::open(archive_path)?;
+    let mut f = open_archive(archive_path)?;
     let file_len = f.metadata()?.len();
     let data_len = data_len_without_footer(&mut f, file_len)?;
     if data_len < 24 {
@@ -840,42 +5114,13 @@ where
     f.seek(SeekFrom::Start(idx_comp_offset))?;
     let mut idx_comp = vec![0u8; idx_comp_size as usize];
     f.read_exact(&mut idx_comp)?;
-    let idx_json = zstd::decode_all(&*idx_comp)?;
-    let index: KatanaIndex = serde_json::from_slice(&idx_json)?;
-    // ---------------- Integrity verification ------------------
-    use crc32fast::Hasher as Crc32Hasher;
-    // При создании архива вычисляется CRC по JSON с нулевым полем crc32.
-    // Для корректной проверки воспроизводим тот же алгоритм.
-    let mut index_for_crc = index.clone();
-    index_for_crc.crc32 = 0;
-    index_for_crc.hmac = None; // CRC вычисляется по JSON без HMAC
-    let idx_json_zero = serde_json::to_vec(&index_for_crc)?;
-    let mut hasher = Crc32Hasher::new();
-    hasher.update(&idx_json_zero);
-    let crc_now = hasher.finalize();
-    if index.crc32 != 0 && index.crc32 != crc_now {
-        return Err("Index CRC mismatch".into());
-    }
-    if let Some(expected_hmac) = &index.hmac {
-        if let (Some(pass), Some(salt)) = (password.as_ref(), index.salt) {
-            use hmac::{Hmac, Mac};
-            use sha2::Sha256 as Sha256Mac;
-            type HmacSha256 = Hmac<Sha256Mac>;
-            let key = crypto::derive_key_argon2(&pass, &salt);
-            // Для проверки HMAC нужно сериализовать индекс с hmac = None,
-            // ровно так же, как при вычислении в create_katana_archive.
-            let mut idx_no_hmac = index.clone();
-            idx_no_hmac.crc32 = 0;
-            idx_no_hmac.hmac = None;
-            let idx_json_no_hmac = serde_json::to_vec(&idx_no_hmac)?;
-            let mut mac = HmacSha256::new_from_slice(&key).expect("HMAC new");
-            mac.update(&idx_json_no_hmac);
-            if mac.verify_slice(expected_hmac).is_err() {
-                return Err("Index HMAC verification failed".into());
-            }
-        } else {
+    let idx_json = decode_index_block(&idx_comp, password.as_deref())?;
+    let index = parse_index_json(&idx_json)?;
+    if index.hmac.is_some() {
+        let (Some(pass), Some(_)) = (password.as_ref(), index.salt) else {
             return Err("Encrypted archive: password required for HMAC verification".into());
-        }
+        };
+        verify_index_hmac(&index, &archive_key(&index, pass)?)?;
     }
 
     // Prepare shard file slices
@@ -888,24 +5133,62 @@ where
     let ratio = if total_comp > 0 {
         total_uncomp as f64 / total_comp as f64
     } else { 0.0 };
-    let files_all = index.files;
+    // Ключ разворачиваем до разбора индекса – слоты живут в нём
+    let archive_key_arc: Option<std::sync::Arc<[u8; 32]>> = match (password.as_ref(), index.salt) {
+        (Some(pass), Some(_)) => Some(std::sync::Arc::new(archive_key(&index, pass)?)),
+        _ => None,
+    };
+    let mut files_all = index.files;
     use std::collections::{HashSet};
-    use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
-    let wanted: HashSet<String> = selected_files
-        .iter()
-        .map(|p| {
-            normalize_path(&p.to_string_lossy())
-        })
-        .collect();
+    use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}};
+    // Точные пути, каталоги и glob-шаблоны → явный список записей
+    let selector = EntrySelector::new(selected_files)?;
+    let mut wanted = selector.select(&files_all);
+    if !selector.is_empty() && wanted.is_empty() {
+        println!("[katana] No entries match the selected paths – nothing to extract");
+        return Ok(());
+    }
+    let filter = options.filter;
+    if filter.is_active() {
+        // Фильтр сужает выбор до явного списка путей
+        let matching: HashSet<String> = files_all
+            .iter()
+            .filter(|e| (wanted.is_empty() || wanted.contains(&e.path)) && filter.matches(e))
+            .map(|e| e.path.clone())
+            .collect();
+        if matching.is_empty() {
+            println!("[katana] No entries match the extraction filter – nothing to extract");
+            return Ok(());
+        }
+        wanted = matching;
+    }
+    // Пути записей проверяются относительно реального каталога вывода
+    fs::create_dir_all(output_dir)?;
+    resolve_case_collisions(&mut files_all, &mut wanted, output_dir, strip_components, options.case_collisions);
+    // Что появится заново – то и убираем при отмене
+    let created = match cancel {
+        Some(_) => new_output_paths(&files_all, &wanted, output_dir, strip_components),
+        None => Vec::new(),
+    };
+    let check_cancelled = || -> Result<(), Box<dyn Error>> {
+        match cancel.map(|c| c.check()) {
+            Some(Err(cancelled)) => {
+                remove_new_outputs(&created);
+                println!("[katana] Extract cancelled – partial output removed");
+                Err(cancelled.into())
+            }
+            _ => Ok(()),
+        }
+    };
 
     let had_error = Arc::new(AtomicBool::new(false));
+    // Private scratch space for this extraction job (decrypted shards)
+    let workspace = TempWorkspace::new("extract")?;
 
     let salt_opt = index.salt;
     
     // Pre-derive encryption key once for extraction (if encrypted)
-    let key_bytes_arc: Option<Arc<[u8; 32]>> = if let (Some(ref pass), Some(ref salt)) = (password.as_ref(), salt_opt.as_ref()) {
-        Some(Arc::new(crypto::derive_key_argon2(pass, &salt[..])))
-    } else { None };
+    let key_bytes_arc = archive_key_arc;
 
     // Initialize progress tracker for extraction
     let mut progress_tracker = ProgressTracker::new(shard_count, std::time::Duration::from_millis(50));
@@ -915,25 +5198,22 @@ where
     }
     let progress_tracker = std::sync::Arc::new(std::sync::Mutex::new(progress_tracker));
     
-    // --- Verify shard CRC32 before extraction ---
-    // use crc32fast::Hasher as Crc32Hasher; // already imported earlier in function
-    for shard in &shards {
+    // --- Verify shard checksums (CRC32 / XXH3) before extraction ---
+    // Только шарды, которые будут прочитаны: у частично скачанного архива остальных нет
+    for shard in sections_needed(&files_all, &shards, index.chunk_store.as_ref(), &wanted)? {
         let mut file_crc = File::open(archive_path)?;
         file_crc.seek(SeekFrom::Start(shard.offset))?;
-        let mut hasher = Crc32Hasher::new();
+        let mut digest = ShardDigest::new(shard.checksum());
         let mut remaining = shard.compressed_size;
         let mut buf = vec![0u8; 8 * 1024 * 1024];
         while remaining > 0 {
             let read_sz = std::cmp::min(remaining, buf.len() as u64) as usize;
             let n = file_crc.read(&mut buf[..read_sz])?;
             if n == 0 { break; }
-            hasher.update(&buf[..n]);
+            digest.update(&buf[..n]);
             remaining -= n as u64;
         }
-        let calc = hasher.finalize();
-        if calc != shard.crc32 {
-            return Err(format!("CRC mismatch in shard at offset {} (expected {:08x}, got {:08x})", shard.offset, shard.crc32, calc).into());
-        }
+        digest.check(shard)?;
     }
 
     println!(
@@ -941,16 +5221,39 @@ where
         shards.len(),
         wanted.len()
     );
-    rayon::scope(|s| {
-        for shard_info in shards.iter().cloned() {
+    // Шардов меньше, чем ядер: шарды с таблицей фреймов режутся на сегменты по ядру
+    let shards_read = needed.iter().filter(|need| **need).count();
+    let parts = if shards_read == 0 { 1 } else { num_cpus::get().div_ceil(shards_read) };
+    // Самые дорогие шарды – первыми (FIFO): короткие добивают хвост, а не наоборот
+    let mut schedule: Vec<(usize, usize, &ShardInfo, Arc<SplitShard>)> = Vec::with_capacity(shards.len());
+    for (shard_info, need) in shards.iter().zip(&needed) {
+        if *need {
+            let shard_files = &files_all[file_cursor..file_cursor + shard_info.file_count];
+            let segments = shard_segments(shard_info, shard_files, parts);
+            let split = Arc::new(SplitShard { parts_left: AtomicUsize::new(segments.len()), decrypted: std::sync::OnceLock::new() });
+            for segment in segments {
+                schedule.push((file_cursor + segment.start, segment.len(), shard_info, Arc::clone(&split)));
+            }
+        }
+        file_cursor += shard_info.file_count;
+    }
+    schedule.sort_by_key(|(first, count, shard, _)| {
+        std::cmp::Reverse(segment_decode_cost(shard, &files_all[*first..*first + *count]))
+    });
+    // Шарды декодируются в доле общих потоков процесса, параллельно с другими заданиями
+    let wanted_threads = schedule.len().min(num_cpus::get());
+    let Some(lease) = crate::governor::ResourceGovernor::global().register(wanted_threads, EXTRACT_LEASE_MB, cancel) else {
+        check_cancelled()?;
+        return Err(crate::ArchiverError::Cancelled.into());
+    };
+    lease.install(|| rayon::scope_fifo(|s| {
+        for (first_file, file_count, shard_info, split) in schedule {
+            let shard_info = shard_info.clone();
             let archive_path = archive_path.to_path_buf();
             let out_root = output_dir.to_path_buf();
-            let shard_files_slice = &files_all[file_cursor..file_cursor + shard_info.file_count];
-            file_cursor += shard_info.file_count;
-
-            let need_shard = wanted.is_empty() || shard_files_slice.iter().any(|f| wanted.contains(&f.path));
-            if !need_shard {
-                continue; // skip shard entirely
+            let shard_files_slice = &files_all[first_file..first_file + file_count];
+            if cancel.is_some_and(|c| c.is_cancelled()) {
+                continue;
             }
 
             let key_arc_cl = key_bytes_arc.clone();
@@ -958,6 +5261,7 @@ where
             let wanted_cl = wanted.clone();
             let strip_components_cl = strip_components;
             let progress_tracker_cl = Arc::clone(&progress_tracker);
+            let workspace_cl = Arc::clone(&workspace);
             
             // Get thread-specific metrics handle for this shard
             let thread_metrics = {
@@ -967,7 +5271,7 @@ where
             
             // Pass full slice to maintain correct byte positions
             let shard_vec: Vec<FileEntry> = shard_files_slice.to_vec();
-            s.spawn(move |_| {
+            s.spawn_fifo(move |_| {
                 if let Err(e) = extract_katana_shard_with_progress(
                     &archive_path,
                     &out_root,
@@ -976,23 +5280,138 @@ where
                     &wanted_cl,
                     key_arc_cl.as_deref(),
                     strip_components_cl,
+                    &workspace_cl,
                     thread_metrics,
+                    cancel,
+                    nested_written,
+                    Some(&*split),
+                    options,
                 ) {
-                    eprintln!("[katana] shard extract error: {}", e);
+                    if cancel.is_some_and(|c| c.is_cancelled()) {
+                        return; // reported once below
+                    }
+                    eprintln!("[katana] shard extract error: {}", crate::private_logs::message(&e.to_string(), ""));
                     error_flag.store(true, Ordering::SeqCst);
                 }
                 
-                // Record shard completion
-                {
+                // Record shard completion once its last segment is done
+                if split.parts_left.fetch_sub(1, Ordering::SeqCst) == 1 {
                     let tracker = progress_tracker_cl.lock().unwrap();
                     tracker.record_shard_completed();
                 }
             });
         }
-    });
-    if had_error.load(Ordering::SeqCst) {
-        return Err("One or more shards failed".into());
+    }));
+    drop(lease);
+
+    check_cancelled()?;
+
+    // Inlined small files live in the index itself, right after the shard entries
+    // (duplicates have no data there, they are restored from their originals below;
+    // chunked entries are assembled from the chunk store)
+    let refs_start = files_all.iter().position(|e| e.base_ref).unwrap_or(files_all.len());
+    let unsharded = &files_all[file_cursor..refs_start];
+    let without_references: Vec<FileEntry>;
+    let inline_files = if unsharded.iter().any(|e| e.duplicate_of.is_some() || !e.chunks.is_empty()) {
+        without_references =
+            unsharded.iter().filter(|e| e.duplicate_of.is_none() && e.chunks.is_empty()).cloned().collect();
+        &without_references[..]
+    } else {
+        unsharded
+    };
+    if !inline_files.is_empty() && (wanted.is_empty() || inline_files.iter().any(|f| wanted.contains(&f.path))) {
+        let thread_metrics = progress_tracker.lock().unwrap().get_thread_metrics(0);
+        let mut reader = InlineReader { entries: inline_files, pos: 0 };
+        if let Err(e) = extract_entries(&mut reader, output_dir, inline_files, &wanted, strip_components, thread_metrics, nested_written, options) {
+            eprintln!("[katana] inline extract error: {}", e);
+            had_error.store(true, Ordering::SeqCst);
+        }
+    }
+    let chunked: Vec<FileEntry> = unsharded
+        .iter()
+        .filter(|e| !e.chunks.is_empty() && (wanted.is_empty() || wanted.contains(&e.path)))
+        .cloned()
+        .collect();
+    if !chunked.is_empty() {
+        let thread_metrics = progress_tracker.lock().unwrap().get_thread_metrics(0);
+        let store = index.chunk_store.as_ref();
+        let result = ChunkStore::open(archive_path, store, &index.chunk_sizes, key_bytes_arc.as_deref(), &workspace)
+            .and_then(|store| Ok(store.reader(&chunked)?))
+            .and_then(|mut reader| {
+                extract_entries(&mut reader, output_dir, &chunked, &wanted, strip_components, thread_metrics, nested_written, options)
+            });
+        if let Err(e) = result {
+            eprintln!("[katana] chunk store extract error: {}", e);
+            had_error.store(true, Ordering::SeqCst);
+        }
+    }
+    check_cancelled()?;
+    if had_error.load(Ordering::SeqCst) {
+        return Err("One or more shards failed".into());
+    }
+    let duplicates: Vec<&FileEntry> = unsharded
+        .iter()
+        .filter(|e| e.duplicate_of.is_some() && (wanted.is_empty() || wanted.contains(&e.path)))
+        .collect();
+    if !duplicates.is_empty() {
+        let lookup = KatanaIndex {
+            crc32: 0,
+            hmac: None,
+            salt: salt_opt,
+            key_slots: Vec::new(),
+            shards: shards.clone(),
+            files: files_all.clone(),
+            features: index.features,
+            base: None,
+            chunk_store: index.chunk_store.clone(),
+            chunk_sizes: index.chunk_sizes.clone(),
+            source_fingerprint: None,
+            extensions: Default::default(),
+            signed_json: None,
+            format: IndexFormat::default(),
+        };
+        restore_duplicates(
+            archive_path,
+            &lookup,
+            key_bytes_arc.as_deref(),
+            output_dir,
+            &duplicates,
+            &wanted,
+            strip_components,
+            nested_written,
+            options,
+        )?;
+    }
+    // Unchanged files of an incremental archive come from its base (recursively along the chain)
+    let from_base: Vec<PathBuf> = files_all[refs_start..]
+        .iter()
+        .filter(|f| wanted.is_empty() || wanted.contains(&f.path))
+        .map(|f| PathBuf::from(&f.path))
+        .collect();
+    if let (false, Some(link)) = (from_base.is_empty(), index.base.as_ref()) {
+        let base_path = resolve_base(archive_path, link)?;
+        println!("[katana] Restoring {} unchanged files from base {}", from_base.len(), base_path.display());
+        extract_katana_archive_with_progress_impl(
+            &base_path,
+            output_dir,
+            &from_base,
+            password.clone(),
+            strip_components,
+            None::<fn(ProgressState)>,
+            cancel,
+            nested_written,
+            options,
+        )?;
+    }
+    check_cancelled()?;
+    let mut symlinks = PendingSymlinks::default();
+    for entry in files_all.iter().filter(|e| wanted.is_empty() || wanted.contains(&e.path)) {
+        let Some(target) = &entry.symlink else { continue };
+        if let Some(out_path) = checked_output_path(output_dir, &output_rel_path(&entry.path, strip_components)) {
+            symlinks.push(out_path, target.clone(), entry.mtime);
+        }
     }
+    symlinks.restore(output_dir, options.conflict_policy)?;
     println!(
         "[katana] ✅ Extract complete | Files: {} | Shards: {} | Size: {:.2} → {:.2} MiB (ratio {:.2}x) | CRC: all ok",
         files_all.len(),
@@ -1011,8 +5430,116 @@ where
     Ok(())
 }
 
+/// Output files (and their missing parent directories) of the selected entries
+/// that do not exist yet, children before parents.
+fn new_output_paths(files: &[FileEntry], wanted: &HashSet<String>, output_dir: &Path, strip_components: Option<u32>) -> Vec<PathBuf> {
+    let mut files_new = Vec::new();
+    let mut dirs_new = std::collections::BTreeSet::new();
+    for entry in files.iter().filter(|e| wanted.is_empty() || wanted.contains(&e.path)) {
+        let Some(out_path) = checked_output_path(output_dir, &output_rel_path(&entry.path, strip_components)) else {
+            continue;
+        };
+        if out_path.symlink_metadata().is_ok() {
+            continue;
+        }
+        for dir in out_path.ancestors().skip(1).take_while(|d| d.starts_with(output_dir) && *d != output_dir) {
+            if dir.exists() {
+                break;
+            }
+            dirs_new.insert(dir.to_path_buf());
+        }
+        files_new.push(out_path);
+    }
+    // Deeper directories sort after their parents
+    files_new.extend(dirs_new.into_iter().rev());
+    files_new
+}
+
+/// Removes what [`new_output_paths`] listed; directories only while empty.
+fn remove_new_outputs(paths: &[PathBuf]) {
+    for path in paths {
+        match path.symlink_metadata() {
+            Ok(meta) if meta.is_dir() => {
+                let _ = std::fs::remove_dir(path);
+            }
+            Ok(_) => {
+                let _ = std::fs::remove_file(path);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Applies the job's [`CaseCollisionPolicy`] to the selected entries when
+/// `output_dir` is case-insensitive, renaming entries in `files` (and `wanted`)
+/// or dropping them from the selection. Entries restored from a base archive
+/// are left alone.
+fn resolve_case_collisions(
+    files: &mut [FileEntry],
+    wanted: &mut HashSet<String>,
+    output_dir: &Path,
+    strip_components: Option<u32>,
+    policy: CaseCollisionPolicy,
+) {
+    let selected: Vec<&str> = files
+        .iter()
+        .filter(|e| !e.base_ref && (wanted.is_empty() || wanted.contains(&e.path)))
+        .map(|e| e.path.as_str())
+        .collect();
+    // Cheap check first: the filesystem probe touches the output directory
+    let mut seen = HashSet::new();
+    if selected.iter().all(|p| seen.insert(output_rel_path(p, strip_components).to_lowercase())) {
+        return;
+    }
+    if !crate::fsx::is_case_insensitive(output_dir) {
+        return;
+    }
+    let plan = plan_case_collisions(&selected, strip_components, policy);
+    if plan.is_empty() {
+        return;
+    }
+    if wanted.is_empty() && policy == CaseCollisionPolicy::Skip {
+        wanted.extend(files.iter().map(|e| e.path.clone()));
+    }
+    let plan: std::collections::HashMap<String, Option<String>> = plan.into_iter().collect();
+    for entry in files.iter_mut() {
+        if let Some(Some(new_path)) = entry.duplicate_of.as_ref().and_then(|o| plan.get(o)) {
+            if policy == CaseCollisionPolicy::Rename {
+                entry.duplicate_of = Some(new_path.clone());
+            }
+        }
+    }
+    for entry in files.iter_mut().filter(|e| !e.base_ref) {
+        let Some(target) = plan.get(&entry.path) else { continue };
+        match (policy, target) {
+            (CaseCollisionPolicy::Rename, Some(new_path)) => {
+                warn(WarningKind::PathRewrite, &entry.path, format!("Case collision: {} extracted as {}", entry.path, crate::private_logs::path(new_path)));
+                if wanted.remove(&entry.path) {
+                    wanted.insert(new_path.clone());
+                }
+                entry.path = new_path.clone();
+            }
+            (CaseCollisionPolicy::Overwrite, _) => {
+                warn(
+                    WarningKind::PathRewrite,
+                    &entry.path,
+                    format!("Case collision: {} overwrites an entry with the same name", entry.path),
+                );
+            }
+            _ => {
+                warn(WarningKind::SkippedFile, &entry.path, format!("Case collision: skipping {}", entry.path));
+                wanted.remove(&entry.path);
+            }
+        }
+    }
+}
+
 use std::collections::HashSet;
 use crate::progress::ThreadMetrics;
+use crate::warnings::{warn, WarningKind};
+use crate::temp_manager::{TempWorkspace, WorkspaceFile};
+use crate::cancel::{CancellableReader, CancellationToken};
+use crate::extract::ExtractOptions;
 
 fn extract_katana_shard(
     archive_path: &Path,
@@ -1023,6 +5550,7 @@ fn extract_katana_shard(
     key_bytes: Option<&[u8; 32]>,
     strip_components: Option<u32>,
 ) -> Result<(), Box<dyn Error>> {
+    let workspace = TempWorkspace::new("extract")?;
     extract_katana_shard_with_progress(
         archive_path, 
         out_root, 
@@ -1031,10 +5559,173 @@ fn extract_katana_shard(
         wanted, 
         key_bytes, 
         strip_components,
-        None
+        &workspace,
+        None,
+        None,
+        None,
+        None,
+        &ExtractOptions::default(),
     )
 }
 
+/// Reader over a shard's zstd stream, plus the decrypted scratch copy it
+/// reads from (encrypted shards only).
+type ShardStream = (Box<dyn Read + Send>, Option<WorkspaceFile>);
+
+/// Opens the plaintext zstd stream of a shard.
+///
+/// Encrypted shards are authenticated and decrypted into a file of the job's
+/// temp workspace first (low RAM); the returned guard owns that file and must
+/// outlive the reader.
+/// Whole decompressed shard from [`crate::shard_cache`] (decoded and checked
+/// on a miss); `None` while the cache is off or the shard does not fit.
+fn cached_shard(
+    archive_path: &Path,
+    shard_info: &ShardInfo,
+    key_bytes: Option<&[u8; 32]>,
+    workspace: &Arc<TempWorkspace>,
+    dictionaries: &Dictionaries,
+) -> Result<Option<Arc<Vec<u8>>>, Box<dyn Error>> {
+    crate::shard_cache::get_or_decode(archive_path, shard_info.offset, shard_info.uncompressed_size, key_bytes, || {
+        let mut raw = File::open(archive_path)?;
+        raw.seek(SeekFrom::Start(shard_info.offset))?;
+        let mut digest = ShardDigest::new(shard_info.checksum());
+        std::io::copy(&mut raw.take(shard_info.compressed_size), &mut digest)?;
+        digest.check(shard_info)?;
+        let (reader, _decrypted_tmp) = open_shard_stream(archive_path, shard_info, key_bytes, workspace)?;
+        let mut data = Vec::with_capacity(shard_info.uncompressed_size as usize);
+        shard_info.decoder(reader, dictionaries)?.read_to_end(&mut data)?;
+        Ok(data)
+    })
+}
+
+fn open_shard_stream(
+    archive_path: &Path,
+    shard_info: &ShardInfo,
+    key_bytes: Option<&[u8; 32]>,
+    workspace: &Arc<TempWorkspace>,
+) -> Result<ShardStream, Box<dyn Error>> {
+    open_shard_stream_at(archive_path, shard_info, key_bytes, workspace, 0)
+}
+
+/// Like [`open_shard_stream`], starting `comp_offset` bytes into the
+/// compressed stream (a frame boundary from the seek table).
+fn open_shard_stream_at(
+    archive_path: &Path,
+    shard_info: &ShardInfo,
+    key_bytes: Option<&[u8; 32]>,
+    workspace: &Arc<TempWorkspace>,
+    comp_offset: u64,
+) -> Result<ShardStream, Box<dyn Error>> {
+    // Build a reader depending on encryption
+    if shard_info.nonce.is_some() {
+        // --- Encrypted shard: stream decrypt to temp file (low RAM) ---
+        let tmp = decrypt_shard(archive_path, shard_info, key_bytes, workspace)?;
+        let mut opened = tmp.reopen()?;
+        opened.seek(SeekFrom::Start(comp_offset))?;
+        // The scratch copy is removed when the returned guard drops
+        Ok((Box::new(opened), Some(tmp)))
+    } else {
+        // --- Not encrypted: stream directly from file, no large allocation ---
+        let mut shard_file = File::open(archive_path)?;
+        let comp_offset = comp_offset.min(shard_info.compressed_size);
+        shard_file.seek(SeekFrom::Start(shard_info.offset + comp_offset))?;
+        Ok((Box::new(shard_file.take(shard_info.compressed_size - comp_offset)), None))
+    }
+}
+
+/// Decrypts an encrypted shard into a scratch file of `workspace`, which holds
+/// its plaintext zstd stream.
+fn decrypt_shard(
+    archive_path: &Path,
+    shard_info: &ShardInfo,
+    key_bytes: Option<&[u8; 32]>,
+    workspace: &Arc<TempWorkspace>,
+) -> Result<WorkspaceFile, Box<dyn Error>> {
+    use std::io::BufWriter;
+    let nc = shard_info.nonce.ok_or("shard is not encrypted")?;
+    let body_size = shard_info
+        .compressed_size
+        .checked_sub(16)
+        .ok_or("shard size too small for tag")?;
+    let key = key_bytes.ok_or("Password/key required for encrypted archive")?;
+    let shard_key = match shard_info.key_id {
+        Some(id) => crypto::derive_shard_key(key, id),
+        None => *key,
+    };
+
+    // Read tag located at end of shard first
+    let mut shard_file = File::open(archive_path)?;
+    shard_file.seek(SeekFrom::Start(shard_info.offset + body_size))?;
+    let mut tag = [0u8; 16];
+    shard_file.read_exact(&mut tag)?;
+
+    // Seek back to start of ciphertext body
+    shard_file.seek(SeekFrom::Start(shard_info.offset))?;
+    // Ciphertext body reader (excluding tag)
+    let mut body_reader = (&mut shard_file).take(body_size);
+
+    // Temp file to hold decrypted stream (avoids holding whole Vec).
+    // Lives in the job's private workspace, so concurrent jobs never collide.
+    let mut tmp = workspace.create_file(&format!("katana_dec_{}", shard_info.offset))?;
+    tmp.reserve(body_size)?;
+    {
+        let mut tmp_f = BufWriter::new(tmp.file_mut());
+        decrypt_stream_prekey(&mut body_reader, &mut tmp_f, &shard_key, &nc, &tag)
+            .map_err(|e| format!("decrypt failed: {:?}", e))?;
+        tmp_f.flush()?;
+    }
+    Ok(tmp)
+}
+
+/// A shard extracted in several segments ([`shard_segments`]).
+struct SplitShard {
+    /// Segments still running; the last one reports the shard as done.
+    parts_left: std::sync::atomic::AtomicUsize,
+    /// Decrypted copy of an encrypted shard: the first segment to start makes
+    /// it, the others wait for it and read their frames from it.
+    decrypted: std::sync::OnceLock<Result<WorkspaceFile, String>>,
+}
+
+/// Splits the entries of a shard into up to `parts` runs of about equal size
+/// that are extracted in parallel (as ranges into `files`).
+///
+/// Only shards with a seek table are split: each run is decoded from the frame
+/// holding its first entry, so runs share at most the one frame around their
+/// boundary. An encrypted shard is decrypted once for all of its runs (see
+/// [`SplitShard`]). Shards without frames are one stream and stay in one piece.
+fn shard_segments(shard_info: &ShardInfo, files: &[FileEntry], parts: usize) -> Vec<std::ops::Range<usize>> {
+    if parts <= 1 || shard_info.frames.len() < 2 || files.is_empty() {
+        return vec![0..files.len()];
+    }
+    let parts = parts.min(shard_info.frames.len()) as u64;
+    let target = shard_info.uncompressed_size.div_ceil(parts).max(1);
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for (i, entry) in files.iter().enumerate().skip(1) {
+        // Новый сегмент – с первой записи за очередной долей шарда
+        if entry.offset >= target * (segments.len() as u64 + 1) {
+            segments.push(start..i);
+            start = i;
+        }
+    }
+    segments.push(start..files.len());
+    segments
+}
+
+/// Decode cost of a run of a shard's entries, for scheduling.
+fn segment_decode_cost(shard_info: &ShardInfo, files: &[FileEntry]) -> u64 {
+    let size: u64 = files.iter().map(|e| e.size).sum();
+    match shard_info.uncompressed_size {
+        0 => shard_info.decode_cost(),
+        total => (shard_info.decode_cost() as u128 * size.min(total) as u128 / total as u128) as u64,
+    }
+}
+
+/// Extracts `files`, all of the shard's entries or a run of them from
+/// [`shard_segments`]; a run is decoded from the frame holding its first entry,
+/// out of the decrypted copy in `split` for encrypted shards.
+#[allow(clippy::too_many_arguments)]
 fn extract_katana_shard_with_progress(
     archive_path: &Path,
     out_root: &Path,
@@ -1043,137 +5734,517 @@ fn extract_katana_shard_with_progress(
     wanted: &HashSet<String>,
     key_bytes: Option<&[u8; 32]>,
     strip_components: Option<u32>,
+    workspace: &Arc<TempWorkspace>,
     thread_metrics: Option<Arc<ThreadMetrics>>,
+    cancel: Option<&CancellationToken>,
+    nested_written: Option<&Mutex<Vec<PathBuf>>>,
+    split: Option<&SplitShard>,
+    options: &ExtractOptions,
 ) -> Result<(), Box<dyn Error>> {
-    use std::io::{BufWriter, Cursor, Read};
-    let mut shard_file = File::open(archive_path)?;
-    shard_file.seek(SeekFrom::Start(shard_info.offset))?;
-
-    // Build a reader depending on encryption
-    let reader: Box<dyn Read> = if let Some(nc) = shard_info.nonce {
-        // --- Encrypted shard: stream decrypt to temp file (low RAM) ---
-        let body_size = shard_info
-            .compressed_size
-            .checked_sub(16)
-            .ok_or("shard size too small for tag")?;
-        let key = key_bytes.ok_or("Password/key required for encrypted archive")?;
-
-        // Read tag located at end of shard first
-        shard_file.seek(SeekFrom::Start(shard_info.offset + body_size))?;
-        let mut tag = [0u8; 16];
-        shard_file.read_exact(&mut tag)?;
-
-        // Seek back to start of ciphertext body
-        shard_file.seek(SeekFrom::Start(shard_info.offset))?;
-        // Ciphertext body reader (excluding tag)
-        let mut body_reader = (&mut shard_file).take(body_size);
-
-        // Temp file to hold decrypted stream (avoids holding whole Vec).
-        // Include a high-resolution timestamp to ensure uniqueness across concurrent extractions.
-        let unique = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let tmp_path = std::env::temp_dir()
-            .join(format!("katana_dec_{}_{}.tmp", shard_info.offset, unique));
-        {
-            let mut tmp_f = BufWriter::new(File::create(&tmp_path)?);
-            decrypt_stream_prekey(&mut body_reader, &mut tmp_f, key, &nc, &tag)
-                .map_err(|e| format!("decrypt failed: {:?}", e))?;
-            tmp_f.flush()?;
-        }
-        // Ensure cleanup afterwards
-        let cleanup = tmp_path.clone();
-        scopeguard::defer! { fs::remove_file(&cleanup).ok(); }
-        let opened = File::open(&tmp_path)?;
-        Box::new(opened)
-    } else {
-        // --- Not encrypted: stream directly from file, no large allocation ---
-        shard_file.seek(SeekFrom::Start(shard_info.offset))?;
-        Box::new(shard_file.take(shard_info.compressed_size))
+    let start = match (&shard_info.frames[..], files.first()) {
+        ([_, _, ..], Some(first)) => first.offset,
+        _ => 0,
+    };
+    let (comp_start, plain_start) = shard_info.frame_start(start);
+    // `_decrypted_tmp` keeps the decrypted scratch copy alive until decoding is done
+    let (reader, _decrypted_tmp): ShardStream = match split {
+        Some(split) if shard_info.nonce.is_some() => {
+            let decrypted = split
+                .decrypted
+                .get_or_init(|| decrypt_shard(archive_path, shard_info, key_bytes, workspace).map_err(|e| e.to_string()))
+                .as_ref()
+                .map_err(|e| e.clone())?;
+            let mut opened = decrypted.reopen()?;
+            opened.seek(SeekFrom::Start(comp_start))?;
+            (Box::new(opened), None)
+        }
+        _ => open_shard_stream_at(archive_path, shard_info, key_bytes, workspace, comp_start)?,
     };
 
-    let mut decoder = zstd::stream::read::Decoder::new(reader)?;
+    let reader = CancellableReader { inner: reader, token: cancel };
+    let mut decoder = shard_info.decoder(reader, &options.dictionaries)?;
+    let skip = start - plain_start;
+    if std::io::copy(&mut (&mut decoder).take(skip), &mut std::io::sink())? != skip {
+        return Err("Unexpected end of shard while seeking to segment".into());
+    }
+    extract_entries(&mut decoder, out_root, files, wanted, strip_components, thread_metrics, nested_written, options)
+}
 
-    let mut in_buf = [0u8; 1 << 16];
-    for entry in files {
-        let mut remaining = entry.size;
-        if wanted.is_empty() || wanted.contains(&entry.path) {
-            // Determine if original path was absolute (Unix /... or Windows C:\...)
-            let original_absolute = entry.path.starts_with('/') || (entry.path.len() >= 2 && entry.path.chars().nth(1) == Some(':'));
-            // Write this file to disk
-            // Ensure path is relative, remove leading slash or drive letter if present
-            let mut normalized_path = entry.path.clone();
-            
-            // Обработка Unix-style путей с /
-            if normalized_path.starts_with('/') {
-                normalized_path = normalized_path.trim_start_matches('/').to_string();
-            }
-            
-            // Обработка Windows-style путей с C:\, D:\ и т.д.
-            #[cfg(windows)]
-            {
-                // Проверяем на Windows-путь с буквой диска (C:\path\file)
-                if normalized_path.len() >= 2 && normalized_path.chars().nth(1) == Some(':') {
-                    // Удаляем имя диска и первый разделитель
-                    if normalized_path.len() >= 3 && normalized_path.chars().nth(2) == Some('\\') {
-                        normalized_path = normalized_path.chars().skip(3).collect::<String>();
-                    } else {
-                        normalized_path = normalized_path.chars().skip(2).collect::<String>();
-                    }
-                    
-                    // Заменяем обратные слеши на прямые для совместимости
-                    normalized_path = normalized_path.replace('\\', "/");
-                }
-                
-                // Если путь начинается с \\
-                if normalized_path.starts_with('\\') {
-                    normalized_path = normalized_path.trim_start_matches('\\').to_string();
-                    normalized_path = normalized_path.replace('\\', "/");
+/// What extraction does with entries whose destinations differ only in letter
+/// case (`Readme.md` / `README.md`) when the output directory is on a
+/// case-insensitive filesystem. The first entry in index order always keeps
+/// its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseCollisionPolicy {
+    /// Later entries are written as `name (2).ext`, `name (3).ext`, …
+    #[default]
+    Rename,
+    /// Later entries are not extracted.
+    Skip,
+    /// Later entries overwrite the first (pre-detection behaviour).
+    Overwrite,
+}
+
+/// Finds entries of `paths` that would land on the same file of a
+/// case-insensitive filesystem. Returns `(entry path, new entry path)` for every
+/// colliding entry except the first of each group; the new path is `None` when
+/// the entry is to be skipped. With [`CaseCollisionPolicy::Overwrite`] the new
+/// path is the unchanged entry path.
+pub(crate) fn plan_case_collisions(
+    paths: &[&str],
+    strip_components: Option<u32>,
+    policy: CaseCollisionPolicy,
+) -> Vec<(String, Option<String>)> {
+    let mut taken: HashSet<String> = HashSet::new();
+    let mut colliding = Vec::new();
+    for path in paths {
+        if !taken.insert(output_rel_path(path, strip_components).to_lowercase()) {
+            colliding.push(*path);
+        }
+    }
+    colliding
+        .into_iter()
+        .map(|path| {
+            let target = match policy {
+                CaseCollisionPolicy::Skip => None,
+                CaseCollisionPolicy::Overwrite => Some(path.to_string()),
+                CaseCollisionPolicy::Rename => {
+                    // Rename the last component; directories stay where they are
+                    let (dir, name) = match path.rfind('/') {
+                        Some(i) => path.split_at(i + 1),
+                        None => ("", path),
+                    };
+                    let (stem, ext) = match name.rfind('.') {
+                        Some(i) if i > 0 => name.split_at(i),
+                        _ => (name, ""),
+                    };
+                    (2u32..)
+                        .map(|n| format!("{}{} ({}){}", dir, stem, n, ext))
+                        .find(|cand| taken.insert(output_rel_path(cand, strip_components).to_lowercase()))
                 }
+            };
+            (path.to_string(), target)
+        })
+        .collect()
+}
+
+/// Path (relative to the output root) an entry is extracted to: leading `/` or
+/// drive letters dropped, absolute entries reduced to their file name, then
+/// `strip_components` applied. `..` components are rejected by the caller.
+pub(crate) fn output_rel_path(entry_path: &str, strip_components: Option<u32>) -> String {
+    // Determine if original path was absolute (Unix /... or Windows C:\...)
+    let original_absolute = entry_path.starts_with('/') || (entry_path.len() >= 2 && entry_path.chars().nth(1) == Some(':'));
+    // Write this file to disk
+    // Ensure path is relative, remove leading slash or drive letter if present
+    let mut normalized_path = entry_path.to_string();
+    
+    // Обработка Unix-style путей с /
+    if normalized_path.starts_with('/') {
+        normalized_path = normalized_path.trim_start_matches('/').to_string();
+    }
+    
+    // Обработка Windows-style путей с C:\, D:\ и т.д.
+    #[cfg(windows)]
+    {
+        // Проверяем на Windows-путь с буквой диска (C:\path\file)
+        if normalized_path.len() >= 2 && normalized_path.chars().nth(1) == Some(':') {
+            // Удаляем имя диска и первый разделитель
+            if normalized_path.len() >= 3 && normalized_path.chars().nth(2) == Some('\\') {
+                normalized_path = normalized_path.chars().skip(3).collect::<String>();
+            } else {
+                normalized_path = normalized_path.chars().skip(2).collect::<String>();
             }
             
-            // Если исходный путь был абсолютным – отбросим все промежуточные директории, оставим только имя файла
-            if original_absolute {
-                if let Some(fname) = std::path::Path::new(&normalized_path).file_name() {
-                    normalized_path = fname.to_string_lossy().into_owned();
+            // Заменяем обратные слеши на прямые для совместимости
+            normalized_path = normalized_path.replace('\\', "/");
+        }
+        
+        // Если путь начинается с \\
+        if normalized_path.starts_with('\\') {
+            normalized_path = normalized_path.trim_start_matches('\\').to_string();
+            normalized_path = normalized_path.replace('\\', "/");
+        }
+    }
+    
+    // Если исходный путь был абсолютным – отбросим все промежуточные директории, оставим только имя файла
+    if original_absolute {
+        if let Some(fname) = std::path::Path::new(&normalized_path).file_name() {
+            normalized_path = fname.to_string_lossy().into_owned();
+        }
+    }
+
+    // Если путь стал пустым после нормализации (был только /), используем имя файла
+    if normalized_path.is_empty() || normalized_path == "/" {
+        // Извлечь имя файла из абсолютного пути
+        let path = std::path::Path::new(entry_path);
+        if let Some(filename) = path.file_name() {
+            normalized_path = filename.to_string_lossy().into_owned();
+        } else {
+            // Если не удалось получить имя файла, смотрим на последний компонент пути
+            let components: Vec<_> = path.components().collect();
+            if let Some(last) = components.last() {
+                normalized_path = last.as_os_str().to_string_lossy().into_owned();
+            } else {
+                // Запасной вариант если ничего не помогло
+                normalized_path = "secret.txt".to_string();
+            }
+        }
+    }
+    
+    // Apply strip_components if specified
+    if let Some(n) = strip_components {
+        let path_buf = std::path::Path::new(&normalized_path).to_path_buf();
+        let stripped = crate::extract::strip_path_components(&path_buf, n);
+        normalized_path = stripped.to_string_lossy().into_owned();
+    }
+    normalized_path
+}
+
+/// Output path of an entry below `out_root`, or `None` (with a warning) if the
+/// entry would end up outside of it – through `..` components or a directory
+/// that is a symlink pointing elsewhere. Fails closed: the deepest part of the
+/// path that already exists must resolve below the real `out_root`.
+pub(crate) fn checked_output_path(out_root: &Path, normalized_path: &str) -> Option<PathBuf> {
+    // Reject any remaining parent directory components
+    if std::path::Path::new(normalized_path)
+        .components()
+        .any(|c| matches!(c, std::path::Component::ParentDir))
+    {
+        warn(WarningKind::SkippedFile, normalized_path, format!("Skipping suspicious entry with '..': {}", normalized_path));
+        return None;
+    }
+    let out_path = out_root.join(normalized_path);
+    let parent = out_path.parent().unwrap_or(out_root);
+    let inside = match (out_root.canonicalize(), real_existing_ancestor(parent)) {
+        (Ok(root_real), Some(dir_real)) => dir_real.starts_with(&root_real),
+        _ => false,
+    };
+    if !inside {
+        warn(WarningKind::SkippedFile, normalized_path, format!("Detected path escaping output dir: {:?}", out_path));
+        return None;
+    }
+    Some(out_path)
+}
+
+/// Real location of the deepest existing ancestor of `path` (`path` itself
+/// included); `None` if that ancestor is a dangling or looping symlink.
+fn real_existing_ancestor(path: &Path) -> Option<PathBuf> {
+    let existing = path.ancestors().find(|a| a.symlink_metadata().is_ok())?;
+    existing.canonicalize().ok()
+}
+
+/// Selective restore by stored metadata (`extract --only-executable`, `--uid`).
+/// Applied on top of the explicitly selected files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtractFilter {
+    /// Only regular files with an execute bit set (entries without stored
+    /// permissions never match).
+    pub only_executable: bool,
+    /// Only files owned by this uid (entries without a stored owner never match).
+    pub uid: Option<u32>,
+}
+
+impl ExtractFilter {
+    fn is_active(&self) -> bool {
+        self.only_executable || self.uid.is_some()
+    }
+
+    fn matches(&self, entry: &FileEntry) -> bool {
+        let executable = entry.symlink.is_none() && entry.permissions.is_some_and(|m| m & 0o111 != 0);
+        (!self.only_executable || executable) && self.uid.is_none_or(|uid| entry.owner.is_some_and(|(u, _)| u == uid))
+    }
+}
+
+/// Restores duplicate entries once the rest of the archive is extracted: a
+/// copy of (or, with [`ExtractOptions::hard_links`], a hard link to) the
+/// extracted original. Originals that were not extracted themselves are read
+/// from the archive instead.
+#[allow(clippy::too_many_arguments)]
+fn restore_duplicates(
+    archive_path: &Path,
+    index: &KatanaIndex,
+    key_bytes: Option<&[u8; 32]>,
+    out_root: &Path,
+    duplicates: &[&FileEntry],
+    wanted: &HashSet<String>,
+    strip_components: Option<u32>,
+    nested_written: Option<&Mutex<Vec<PathBuf>>>,
+    options: &ExtractOptions,
+) -> Result<(), Box<dyn Error>> {
+    let hard_links = options.hard_links;
+    let now = crate::fsx::now_secs();
+    for entry in duplicates {
+        let Some(original) = entry.duplicate_of.as_deref() else { continue };
+        let Some(out_path) = checked_output_path(out_root, &output_rel_path(&entry.path, strip_components)) else {
+            continue;
+        };
+        if !crate::fsx::claim_output(&out_path, entry.mtime, options.conflict_policy)? {
+            continue;
+        }
+        if let Ok(meta) = out_path.symlink_metadata() {
+            if !meta.is_file() {
+                warn(
+                    WarningKind::SkippedFile,
+                    &entry.path,
+                    format!("Skipping file that conflicts with existing directory: {:?}", out_path),
+                );
+                continue;
+            }
+            fs::remove_file(&out_path)?;
+        }
+        if let Some(dir) = out_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // The original on disk, if this run extracted it
+        let extracted = (wanted.is_empty() || wanted.contains(original))
+            .then(|| out_root.join(output_rel_path(original, strip_components)))
+            .filter(|p| p.symlink_metadata().is_ok_and(|m| m.is_file() && m.len() == entry.size));
+        if let (true, Some(src)) = (hard_links, extracted.as_ref()) {
+            match fs::hard_link(src, &out_path) {
+                Ok(()) => {
+                    record_nested(nested_written, entry, &out_path);
+                    continue;
                 }
+                Err(e) => warn(
+                    WarningKind::Metadata,
+                    &entry.path,
+                    format!("Cannot hard link {:?}: {}; copying instead", out_path, e),
+                ),
             }
+        }
+        let mut reader: Box<dyn Read> = match extracted {
+            Some(src) => Box::new(File::open(src)?),
+            None => Box::new(open_entry_in(archive_path, index, original, key_bytes, &options.dictionaries)?),
+        };
+        let out_f = File::create(&out_path)?;
+        std::io::copy(&mut reader, &mut std::io::BufWriter::new(&out_f))?;
+        if let Some(mtime) = entry.mtime {
+            crate::fsx::set_mtime(&out_f, check_mtime(mtime, now).map_or(mtime, |(clamped, _)| clamped))?;
+        }
+        restore_entry_btime(&out_f, entry);
+        drop(out_f);
+        restore_entry_permissions(&out_path, entry, options);
+        restore_entry_xattrs(&out_path, entry);
+        record_nested(nested_written, entry, &out_path);
+    }
+    Ok(())
+}
 
-            // Если путь стал пустым после нормализации (был только /), используем имя файла
-            if normalized_path.is_empty() || normalized_path == "/" {
-                // Извлечь имя файла из абсолютного пути
-                let path = std::path::Path::new(&entry.path);
-                if let Some(filename) = path.file_name() {
-                    normalized_path = filename.to_string_lossy().into_owned();
-                } else {
-                    // Если не удалось получить имя файла, смотрим на последний компонент пути
-                    let components: Vec<_> = path.components().collect();
-                    if let Some(last) = components.last() {
-                        normalized_path = last.as_os_str().to_string_lossy().into_owned();
-                    } else {
-                        // Запасной вариант если ничего не помогло
-                        normalized_path = "secret.txt".to_string();
-                    }
+/// Notes `out_path` for `--recursive-extract` when `entry` is a nested archive
+/// (`create --store-nested`) this run has just written.
+fn record_nested(nested_written: Option<&Mutex<Vec<PathBuf>>>, entry: &FileEntry, out_path: &Path) {
+    if let (Some(written), true) = (nested_written, entry.nested_archive) {
+        written.lock().unwrap().push(out_path.to_path_buf());
+    }
+}
+
+/// Restores the archived permission bits of `entry` (SUID/SGID stripped, umask
+/// applied) unless the job does not preserve permissions; a failure leaves the
+/// file as created and is reported.
+fn restore_entry_permissions(out_path: &Path, entry: &FileEntry, options: &ExtractOptions) {
+    let Some(perm) = options.mode(entry.permissions) else { return };
+    if let Err(e) = crate::fsx::restore_permissions(out_path, perm) {
+        warn(WarningKind::PermissionFailure, &entry.path, format!("Cannot restore permissions of {:?}: {}", out_path, e));
+    }
+}
+
+/// Restores the archived creation time of `entry` where the platform can set
+/// one (Windows, macOS); elsewhere it is silently dropped.
+fn restore_entry_btime(out_f: &File, entry: &FileEntry) {
+    let Some(btime) = entry.btime.filter(|_| crate::fsx::CAN_SET_BTIME) else {
+        return;
+    };
+    if let Err(e) = crate::fsx::set_btime(out_f, btime) {
+        warn(WarningKind::Metadata, &entry.path, format!("Cannot restore creation time of {}: {}", entry.path, e));
+    }
+}
+
+/// Applies the archived extended attributes of `entry` when `--xattrs` is on;
+/// `security.*` and `trusted.*` ones only with `--privileged-xattrs`. Attributes
+/// the target filesystem or our privileges do not allow are reported and skipped.
+fn restore_entry_xattrs(out_path: &Path, entry: &FileEntry) {
+    if entry.xattrs.is_empty() || !crate::fsx::restore_xattrs() {
+        return;
+    }
+    let mut attrs = entry.xattrs.clone();
+    if !crate::fsx::restore_privileged_xattrs() {
+        attrs.retain(|name, _| !crate::fsx::is_privileged_xattr(name));
+        if attrs.len() < entry.xattrs.len() {
+            warn(
+                WarningKind::Metadata,
+                &entry.path,
+                format!("Not restoring security.*/trusted.* xattrs on {:?} without --privileged-xattrs", out_path),
+            );
+        }
+    }
+    for (name, e) in crate::fsx::write_xattrs(out_path, &attrs) {
+        warn(WarningKind::Metadata, &entry.path, format!("Cannot restore xattr {} on {:?}: {}", name, out_path, e));
+    }
+}
+
+/// Symbolic links of one extraction. They are only created once every regular
+/// file is written, so no file is ever written through a link from the archive.
+#[derive(Default)]
+pub(crate) struct PendingSymlinks {
+    links: Vec<(PathBuf, String, Option<i64>)>,
+}
+
+impl PendingSymlinks {
+    pub(crate) fn push(&mut self, out_path: PathBuf, target: String, mtime: Option<i64>) {
+        self.links.push((out_path, target, mtime));
+    }
+
+    /// Creates the links below `out_root`. A link created later can change what
+    /// an earlier one resolves to (`b -> a/..` followed by `a -> .`), so every
+    /// link is checked again at the end and removed if it now leads outside.
+    pub(crate) fn restore(self, out_root: &Path, policy: crate::fsx::ConflictPolicy) -> Result<(), Box<dyn Error>> {
+        let mut created = Vec::new();
+        for (out_path, target, mtime) in self.links {
+            if crate::fsx::claim_output(&out_path, mtime, policy)? && restore_symlink(out_root, &out_path, &target)? {
+                created.push((out_path, target));
+            }
+        }
+        let root = out_root.canonicalize()?;
+        for (link, target) in created {
+            let inside = link
+                .parent()
+                .and_then(|dir| dir.canonicalize().ok())
+                .is_some_and(|dir| dir.starts_with(&root) && symlink_target_inside(&root, &dir, &target));
+            if !inside {
+                warn(
+                    WarningKind::SkippedFile,
+                    link.to_string_lossy(),
+                    format!("Removing symlink {:?}: it leads outside the output directory through other links", link),
+                );
+                fs::remove_file(&link)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recreates an archived symbolic link at `out_path`; `Ok(false)` if it was
+/// skipped. Links whose target is absolute or leaves `out_root` are skipped
+/// with a warning, as are links that would replace a directory. The target is
+/// resolved against the real filesystem: links met on the way are followed,
+/// and a `..` after a component that does not exist yet is refused, since a
+/// link created there later could make it climb anywhere.
+pub(crate) fn restore_symlink(out_root: &Path, out_path: &Path, target: &str) -> Result<bool, Box<dyn Error>> {
+    let parent = out_path.parent().unwrap_or(out_root);
+    let root = out_root.canonicalize()?;
+    let mut inside = real_existing_ancestor(parent).is_some_and(|dir| dir.starts_with(&root));
+    if inside {
+        // Missing parents become real directories, the target is judged from there
+        fs::create_dir_all(parent)?;
+        inside = parent.canonicalize().is_ok_and(|dir| dir.starts_with(&root) && symlink_target_inside(&root, &dir, target));
+    }
+    if !inside {
+        warn(
+            WarningKind::SkippedFile,
+            out_path.to_string_lossy(),
+            format!("Skipping symlink {:?} -> {:?}: target outside the output directory", out_path, target),
+        );
+        return Ok(false);
+    }
+    if let Ok(meta) = out_path.symlink_metadata() {
+        if meta.is_dir() {
+            warn(
+                WarningKind::SkippedFile,
+                out_path.to_string_lossy(),
+                format!("Skipping symlink that conflicts with existing directory: {:?}", out_path),
+            );
+            return Ok(false);
+        }
+        fs::remove_file(out_path)?;
+    }
+    if let Err(e) = crate::fsx::create_symlink(std::path::Path::new(target), out_path) {
+        warn(
+            WarningKind::SkippedFile,
+            out_path.to_string_lossy(),
+            format!("Cannot create symlink {:?} -> {:?}: {}", out_path, target, e),
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Whether `target`, followed from the directory `dir` (real, below `root`),
+/// stays below `root`.
+fn symlink_target_inside(root: &Path, dir: &Path, target: &str) -> bool {
+    let mut current = dir.to_path_buf();
+    // `current` is only a lexical guess once a component does not exist
+    let mut resolved = true;
+    for component in std::path::Path::new(target).components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if !resolved || !current.pop() || !current.starts_with(root) {
+                    return false;
                 }
             }
-            
-            // Apply strip_components if specified
-            if let Some(n) = strip_components {
-                let path_buf = std::path::Path::new(&normalized_path).to_path_buf();
-                let stripped = crate::extract::strip_path_components(&path_buf, n);
-                normalized_path = stripped.to_string_lossy().into_owned();
+            std::path::Component::Normal(name) => {
+                current.push(name);
+                if !resolved {
+                    continue;
+                }
+                match current.symlink_metadata() {
+                    Ok(meta) if meta.file_type().is_symlink() => match current.canonicalize() {
+                        Ok(real) => current = real,
+                        Err(_) => return false,
+                    },
+                    Ok(_) => {}
+                    Err(_) => resolved = false,
+                }
             }
+            _ => return false,
+        }
+        if !current.starts_with(root) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Writes the wanted entries of `files` to `out_root`, reading their bytes
+/// back-to-back from `decoder` (a decoded shard or the inlined entries).
+#[allow(clippy::too_many_arguments)]
+fn extract_entries<R: Read>(
+    decoder: &mut R,
+    out_root: &Path,
+    files: &[FileEntry],
+    wanted: &HashSet<String>,
+    strip_components: Option<u32>,
+    thread_metrics: Option<Arc<ThreadMetrics>>,
+    nested_written: Option<&Mutex<Vec<PathBuf>>>,
+    options: &ExtractOptions,
+) -> Result<(), Box<dyn Error>> {
+    use std::io::BufWriter;
+
+    // Entries are stored back-to-back in the shard stream, so nothing after the
+    // last wanted entry has to be decoded at all.
+    let decode_end = if wanted.is_empty() {
+        files.len()
+    } else {
+        files
+            .iter()
+            .rposition(|f| wanted.contains(&f.path))
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    };
+
+    let mut in_buf = [0u8; 1 << 16];
+    let now = crate::fsx::now_secs();
+    for entry in &files[..decode_end] {
+        let mut remaining = entry.size;
+        if wanted.is_empty() || wanted.contains(&entry.path) {
+            let normalized_path = output_rel_path(&entry.path, strip_components);
 
             // ------------------------------------------------------------------
             // Security hardening: prevent path traversal ("../") and symlink abuse
             // ------------------------------------------------------------------
-            // Reject any remaining parent directory components
-            if std::path::Path::new(&normalized_path)
-                .components()
-                .any(|c| matches!(c, std::path::Component::ParentDir))
-            {
-                eprintln!("[katana] ⚠️  Skipping suspicious entry with '..': {}", normalized_path);
+            let Some(out_path) = checked_output_path(out_root, &normalized_path) else {
                 // Skip file bytes but continue extraction
                 while remaining > 0 {
                     let to_read = std::cmp::min(in_buf.len() as u64, remaining) as usize;
@@ -1182,33 +6253,45 @@ fn extract_katana_shard_with_progress(
                     remaining -= rd as u64;
                 }
                 continue;
+            };
+            
+            
+            if std::env::var("BLITZ_DEBUG_PATHS").is_ok() && !crate::private_logs::enabled() {
+                eprintln!("[dbg] extract -> {:?}", out_path);
             }
 
-            let out_path = out_root.join(&normalized_path);
+            // Symbolic links are created after all files (see `PendingSymlinks`)
+            if entry.symlink.is_some() {
+                if let Some(ref metrics) = thread_metrics {
+                    metrics.record_file_processed(0);
+                }
+                continue;
+            }
 
-            // Ensure the final canonicalized path is inside output root
-            if let (Ok(root_real), Ok(target_real)) = (out_root.canonicalize(), out_path.parent().unwrap_or(out_root).canonicalize()) {
-                if !target_real.starts_with(&root_real) {
-                    eprintln!("[katana] ⚠️  Detected path escaping output dir: {:?}", out_path);
-                    while remaining > 0 {
-                        let to_read = std::cmp::min(in_buf.len() as u64, remaining) as usize;
-                        let rd = decoder.read(&mut in_buf[..to_read])?;
-                        if rd == 0 { return Err("Unexpected EOF while skipping".into()); }
-                        remaining -= rd as u64;
+            // Файл уже существует: --skip-existing / --keep-newer / --rename-existing
+            if !crate::fsx::claim_output(&out_path, entry.mtime, options.conflict_policy)? {
+                while remaining > 0 {
+                    let to_read = std::cmp::min(in_buf.len() as u64, remaining) as usize;
+                    let rd = decoder.read(&mut in_buf[..to_read])?;
+                    if rd == 0 {
+                        return Err("Unexpected EOF while skipping".into());
                     }
-                    continue;
+                    remaining -= rd as u64;
                 }
-            }
-            
-            
-            if std::env::var("BLITZ_DEBUG_PATHS").is_ok() {
-                eprintln!("[dbg] extract -> {:?}", out_path);
+                if let Some(ref metrics) = thread_metrics {
+                    metrics.record_file_processed(entry.size);
+                }
+                continue;
             }
             
             // Проверяем, не является ли путь директорией
-            if out_path.exists() && (out_path.is_dir() || out_path.symlink_metadata()?.file_type().is_symlink()) {
+            if out_path.symlink_metadata().is_ok_and(|meta| meta.is_dir() || meta.file_type().is_symlink()) {
                 // Если это директория, пропускаем этот файл и не пытаемся его создать
-                eprintln!("[katana] Warning: skipping file that conflicts with existing directory: {:?}", out_path);
+                warn(
+                    WarningKind::SkippedFile,
+                    &entry.path,
+                    format!("Skipping file that conflicts with existing directory: {:?}", out_path),
+                );
                 // Пропускаем данные файла
                 while remaining > 0 {
                     let to_read = std::cmp::min(in_buf.len() as u64, remaining) as usize;
@@ -1227,7 +6310,8 @@ fn extract_katana_shard_with_progress(
             }
             
             let target_path = out_path.clone();
-            let mut out_f = BufWriter::new(File::create(&out_path)?);
+            // Разреженный файл: дыры не пишутся, длина выставляется в конце
+            let mut out_f = BufWriter::new(crate::fsx::SparseWriter::new(File::create(&out_path)?, &entry.sparse));
             while remaining > 0 {
                 let to_read = std::cmp::min(in_buf.len() as u64, remaining) as usize;
                 let rd = decoder.read(&mut in_buf[..to_read])?;
@@ -1238,11 +6322,27 @@ fn extract_katana_shard_with_progress(
                 remaining -= rd as u64;
             }
             out_f.flush()?;
-            if let Some(perm) = entry.permissions {
-                // Strip SUID/SGID bits for safety
-                let safe_perm = perm & 0o777; // удаляем 0o4000/0o2000
-                crate::fsx::set_unix_permissions(&out_path, safe_perm)?;
+            let out_f = out_f.into_inner().map_err(|e| e.into_error())?.finish(entry.size)?;
+            if let Some(mtime) = entry.mtime {
+                // Absurd timestamps confuse build systems comparing mtimes – clamp them
+                let restored = match check_mtime(mtime, now) {
+                    Some((clamped, reason)) => {
+                        warn(
+                            WarningKind::Metadata,
+                            &entry.path,
+                            format!("Clamped mtime of {} ({} is {})", normalized_path, mtime, reason),
+                        );
+                        clamped
+                    }
+                    None => mtime,
+                };
+                crate::fsx::set_mtime(&out_f, restored)?;
             }
+            restore_entry_btime(&out_f, entry);
+            drop(out_f);
+            restore_entry_permissions(&out_path, entry, options);
+            restore_entry_xattrs(&out_path, entry);
+            record_nested(nested_written, entry, &out_path);
             
             // Record file extraction (zero-overhead when progress disabled)
             if let Some(ref metrics) = thread_metrics {
@@ -1265,5 +6365,12 @@ fn extract_katana_shard_with_progress(
             }
         }
     }
+
+    // Tail of the shard was never decoded – account for it so progress still reaches 100%
+    if let Some(ref metrics) = thread_metrics {
+        for entry in &files[decode_end..] {
+            metrics.record_file_processed(entry.size);
+        }
+    }
     Ok(())
 }
@@ -20,11 +20,9 @@ use walkdir::WalkDir;
 use aes_gcm_stream::Aes256GcmStreamEncryptor;
 use rand::rngs::OsRng;
 use rand::RngCore;
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
-use crc32fast::Hasher as Crc32Hasher;
-
-type HmacSha256 = Hmac<Sha256>;
+use crate::fsx::RetryingAppender;
+use crate::memory_pool::PagePool;
+use crate::console::{eprintln, println};
 
 // Local replicas of structs to avoid cross-module visibility hassles
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -33,6 +31,28 @@ struct FileEntry {
     size: u64,
     offset: u64, // uncompressed offset within shard
     permissions: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inline: Option<Vec<u8>>, // содержимое крошечного файла прямо в индексе
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mtime: Option<i64>, // секунды с Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    btime: Option<i64>, // время создания (birth time), если ФС его хранит
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<(u32, u32)>, // (uid, gid) на Unix
+    #[serde(default, skip_serializing_if = "crate::katana::is_false")]
+    base_ref: bool, // данные в базовом архиве (инкрементальный режим)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    symlink: Option<String>, // цель символической ссылки (--symlinks keep)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<String>, // побайтная копия этой записи (--dedup)
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    xattrs: crate::fsx::Xattrs, // расширенные атрибуты и ACL (--xattrs)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<u32>, // id чанков в хранилище чанков (--dedup=chunks)
+    #[serde(default, skip_serializing_if = "crate::katana::is_false")]
+    nested_archive: bool, // архив BlitzArch, сохранённый без сжатия (--store-nested)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sparse: Vec<(u64, u64)>, // области данных разреженного файла (offset, len); дыры читаются нулями
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,12 +62,21 @@ struct ShardInfo {
     uncompressed_size: u64,
     file_count: usize,
     crc32: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    xxh3: Option<[u8; 16]>, // XXH3-128 вместо CRC32 (--checksum xxh3)
     #[serde(skip_serializing_if = "Option::is_none")]
     nonce: Option<[u8; 12]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<u64>, // id подключа шарда (HKDF от мастер-ключа)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    frames: Vec<(u64, u64)>, // (сжато, несжато) по фреймам (--seekable-frames)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dictionary: Option<String>, // BLAKE3 внешнего словаря zstd (--dictionary)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<crate::katana::ShardStats>, // уровень, время сжатия, оценка стоимости распаковки
 }
 
 
-const KATANA_MAGIC: &[u8; 8] = b"KATIDX01";
 // --- Footer integrity --------------------------------------------------
 // 16-байтная подпись + 8-байт длина данных + 32-байтный BLAKE3
 const FOOTER_MAGIC: &[u8; 16] = b"KATANA_HASH_FOOT"; // 16 bytes
@@ -57,14 +86,14 @@ const FLUSH_SIZE: usize = 4 * 1024 * 1024; // 4 MiB
 const MAX_INFLIGHT: usize = 3; // количество буферов в канале
 
 // --- Streaming encrypt sink --------------------------------------------
-struct EncryptSink<'a> {
-    inner: &'a mut File,
+struct EncryptSink<W: Write> {
+    inner: W,
     enc: Aes256GcmStreamEncryptor,
     bytes: u64,
     nonce: [u8; 12],
 }
-impl<'a> EncryptSink<'a> {
-    fn new(inner: &'a mut File, key: &[u8; 32], nonce: [u8; 12]) -> Self {
+impl<W: Write> EncryptSink<W> {
+    fn new(inner: W, key: &[u8; 32], nonce: [u8; 12]) -> Self {
         let enc = Aes256GcmStreamEncryptor::new(*key, &nonce);
         Self { inner, enc, bytes: 0, nonce }
     }
@@ -79,7 +108,7 @@ impl<'a> EncryptSink<'a> {
         Ok((self.nonce, self.bytes))
     }
 }
-impl<'a> Write for EncryptSink<'a> {
+impl<W: Write> Write for EncryptSink<W> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         let ct = self.enc.update(buf);
         self.inner.write_all(&ct)?;
@@ -91,6 +120,464 @@ impl<'a> Write for EncryptSink<'a> {
     }
 }
 
+// --- IO concurrency caps ------------------------------------------------
+/// Запись, ограниченная общим числом одновременных write-вызовов (--max-writes)
+struct CappedWriter<W: Write> {
+    inner: W,
+    permits: Arc<PagePool>,
+}
+impl<W: Write> Write for CappedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.permits.acquire(1);
+        let res = self.inner.write(buf);
+        self.permits.release(1);
+        res
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Files below this size are not probed for holes.
+const SPARSE_MIN_SIZE: u64 = 1 << 20;
+
+/// Data regions of a sparse input file for its index entry; empty for files
+/// without holes (or when the source cannot tell).
+fn sparse_map(vfs: &dyn crate::vfs::Vfs, path: &Path, len: u64) -> Vec<(u64, u64)> {
+    if len < SPARSE_MIN_SIZE {
+        return Vec::new();
+    }
+    vfs.data_extents(path).ok().flatten().unwrap_or_default()
+}
+
+/// Чтение входного файла под общим лимитом одновременных чтений (--max-reads)
+fn capped_read(f: &mut dyn Read, buf: &mut [u8], permits: &PagePool) -> std::io::Result<usize> {
+    permits.acquire(1);
+    let res = f.read(buf);
+    permits.release(1);
+    res
+}
+
+/// Счётчик записанных байт (границы zstd-фреймов в сжатом потоке) и времени,
+/// проведённого в ожидании приёмника (для `--adapt`)
+struct CountingWriter<W: Write> {
+    inner: W,
+    written: u64,
+    blocked: std::time::Duration,
+}
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let started = Instant::now();
+        let n = self.inner.write(buf)?;
+        self.blocked += started.elapsed();
+        self.written += n as u64;
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Range of `create --adapt`: like `zstd --adapt`, every shard encoder raises
+/// its level while the archive output is kept busy and lowers it while the
+/// output is idle, so a slow output gets smaller data and a fast one is not held
+/// up by compression. Shards are written to the output as soon as they and the
+/// ones before them are done, so the output's pace is what the encoders see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveLevel {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Default for AdaptiveLevel {
+    fn default() -> Self {
+        AdaptiveLevel { min: 1, max: 19 }
+    }
+}
+
+impl std::str::FromStr for AdaptiveLevel {
+    type Err = String;
+
+    /// Parses `min=N,max=N` (either may be left out; empty ⇒ 1..=19).
+    fn from_str(raw: &str) -> Result<Self, String> {
+        let mut range = AdaptiveLevel::default();
+        for part in raw.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| format!("expected min=N or max=N, got '{}'", part))?;
+            let value: i32 = value.trim().parse().map_err(|_| format!("invalid level '{}'", value))?;
+            if !(1..=22).contains(&value) {
+                return Err(format!("level {} is outside 1-22", value));
+            }
+            match key.trim() {
+                "min" => range.min = value,
+                "max" => range.max = value,
+                other => return Err(format!("unknown key '{}' (expected min or max)", other)),
+            }
+        }
+        if range.min > range.max {
+            return Err(format!("min={} is above max={}", range.min, range.max));
+        }
+        Ok(range)
+    }
+}
+
+/// Несжатых байт между решениями `--adapt`.
+const ADAPT_WINDOW: u64 = 8 * 1024 * 1024;
+
+/// Состояние `--adapt` одного кодера.
+struct LevelAdapt {
+    range: AdaptiveLevel,
+    window_in: u64,
+    window_start: Instant,
+    /// Время записи в выход архива (общее для всех шардов) и его значение в начале окна
+    output: Arc<OutputBusy>,
+    blocked_at_start: std::time::Duration,
+    /// Последний выбранный уровень – для событий прогресса
+    current: Arc<std::sync::atomic::AtomicI32>,
+}
+
+/// Сколько времени координатор провёл в записи в выход архива (файл или
+/// `--output` в облако): по нему `--adapt` судит, успевает ли приёмник.
+#[derive(Debug, Default)]
+struct OutputBusy {
+    nanos: std::sync::atomic::AtomicU64,
+}
+
+impl OutputBusy {
+    fn record(&self, spent: std::time::Duration) {
+        self.nanos.fetch_add(spent.as_nanos() as u64, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    fn total(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(self.nanos.load(std::sync::atomic::Ordering::Relaxed))
+    }
+}
+
+/// Окно автоподбора уровня одного кодера (уровень не задан явно).
+struct TunedLevel {
+    tuning: Arc<crate::autotune::LevelTuning>,
+    window_in: u64,
+    written_at_start: u64,
+    busy: std::time::Duration,
+    window_start: Instant,
+}
+
+/// zstd-кодер шарда. С `frame_size` (--seekable-frames) поток режется на
+/// независимые фреймы по `frame_size` несжатых байт, а их размеры собираются в
+/// таблицу фреймов индекса; склейка фреймов остаётся обычным zstd-потоком.
+/// С `--adapt` и автоподбором уровня новый уровень начинает новый фрейм,
+/// с `--shard-strategy per-file` – каждый файл.
+struct ShardEncoder<'p, W: Write> {
+    out: Option<CountingWriter<W>>,
+    encoder: Option<zstd::Encoder<'static, CountingWriter<W>>>,
+    level: i32,
+    params: &'p [zstd::stream::raw::CParameter],
+    threads: u32,
+    frame_size: Option<u64>,
+    in_frame: u64,
+    frame_start: u64,
+    frames: Vec<(u64, u64)>,
+    adapt: Option<LevelAdapt>,
+    tuned: Option<TunedLevel>,
+    rsync: Option<crate::cdc::FrameCutter>,
+    /// Фрейм на каждый файл (`--shard-strategy per-file`)
+    per_file: bool,
+    dictionary: Option<Arc<crate::dictionary::ZstdDictionary>>,
+    /// Буфер текущего raw-блока; `Some` ⇒ шард пишется без сжатия (`--store-nested`)
+    stored: Option<Vec<u8>>,
+}
+
+/// Заголовок zstd-фрейма без размера и чексуммы, окно 128 KiB (Window_Descriptor
+/// 0x38): ровно столько, сколько занимает один raw-блок.
+const STORED_FRAME_HEADER: [u8; 6] = [0x28, 0xB5, 0x2F, 0xFD, 0x00, 0x38];
+const RAW_BLOCK_SIZE: usize = 128 * 1024;
+
+/// Raw-блок zstd: 3-байтовый заголовок (last, тип 0, размер) и данные как есть.
+fn write_raw_block<W: Write>(out: &mut W, data: &[u8], last: bool) -> std::io::Result<()> {
+    let header = ((data.len() as u32) << 3) | last as u32;
+    out.write_all(&header.to_le_bytes()[..3])?;
+    out.write_all(data)
+}
+
+impl<'p, W: Write> ShardEncoder<'p, W> {
+    fn new(
+        out: W,
+        level: i32,
+        params: &'p [zstd::stream::raw::CParameter],
+        threads: u32,
+        frame_size: Option<u64>,
+    ) -> Self {
+        ShardEncoder {
+            out: Some(CountingWriter { inner: out, written: 0, blocked: std::time::Duration::ZERO }),
+            encoder: None,
+            level,
+            params,
+            threads,
+            frame_size,
+            in_frame: 0,
+            frame_start: 0,
+            frames: Vec::new(),
+            adapt: None,
+            tuned: None,
+            rsync: None,
+            per_file: false,
+            dictionary: None,
+            stored: None,
+        }
+    }
+
+    /// Включает `--store-nested`: данные идут в raw-блоки обычного zstd-фрейма,
+    /// так что читатели распаковывают шард как любой другой.
+    fn stored(mut self, enabled: bool) -> Self {
+        self.stored = enabled.then(|| Vec::with_capacity(RAW_BLOCK_SIZE));
+        self
+    }
+
+    fn write_stored(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let (Some(out), Some(block)) = (self.out.as_mut(), self.stored.as_mut()) else {
+            unreachable!("stored shard writes straight to the output");
+        };
+        if out.written == 0 {
+            out.write_all(&STORED_FRAME_HEADER)?;
+        }
+        // Полный блок уходит только когда есть следующий: последний помечается в finish
+        if block.len() == RAW_BLOCK_SIZE {
+            write_raw_block(out, block, false)?;
+            block.clear();
+        }
+        let n = buf.len().min(RAW_BLOCK_SIZE - block.len());
+        block.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Включает `--dictionary`: каждый фрейм сжимается с внешним словарём.
+    fn dictionary(mut self, dictionary: Option<&Arc<crate::dictionary::ZstdDictionary>>) -> Self {
+        self.dictionary = dictionary.cloned();
+        self
+    }
+
+    /// Включает `--rsync-friendly`: фреймы режутся по содержимому.
+    fn rsync_friendly(mut self, enabled: bool) -> Self {
+        self.rsync = enabled.then(Default::default);
+        self
+    }
+
+    /// Включает `--shard-strategy per-file`: файл начинается с нового фрейма,
+    /// так что чтение одного файла не распаковывает соседей по шарду.
+    fn frame_per_file(mut self, enabled: bool) -> Self {
+        self.per_file = enabled;
+        self
+    }
+
+    /// Конец файла: при `frame_per_file` закрывает текущий фрейм.
+    fn end_file(&mut self) -> std::io::Result<()> {
+        if self.per_file {
+            self.end_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Включает `--adapt`: старт с текущего уровня шардов `current`, темп
+    /// задаёт занятость выхода архива `output`.
+    fn adaptive(mut self, range: Option<AdaptiveLevel>, current: &Arc<std::sync::atomic::AtomicI32>, output: &Arc<OutputBusy>) -> Self {
+        if let Some(range) = range {
+            self.level = current.load(std::sync::atomic::Ordering::Relaxed).clamp(range.min, range.max);
+            self.adapt = Some(LevelAdapt {
+                range,
+                window_in: 0,
+                window_start: Instant::now(),
+                blocked_at_start: output.total(),
+                output: Arc::clone(output),
+                current: Arc::clone(current),
+            });
+        }
+        self
+    }
+
+    /// Включает автоподбор: уровень общий для всех шардов задания, его
+    /// пересматривает AutoTuner по отчётам кодеров.
+    fn tuned(mut self, tuning: Option<&Arc<crate::autotune::LevelTuning>>) -> Self {
+        if let Some(tuning) = tuning {
+            self.level = tuning.level();
+            self.threads = tuning.codec_threads();
+            self.tuned = Some(TunedLevel {
+                tuning: Arc::clone(tuning),
+                window_in: 0,
+                written_at_start: 0,
+                busy: std::time::Duration::ZERO,
+                window_start: Instant::now(),
+            });
+        }
+        self
+    }
+
+    fn written(&self) -> u64 {
+        match (&self.encoder, &self.out) {
+            (Some(encoder), _) => encoder.get_ref().written,
+            (None, Some(out)) => out.written,
+            (None, None) => 0,
+        }
+    }
+
+    fn blocked(&self) -> std::time::Duration {
+        match (&self.encoder, &self.out) {
+            (Some(encoder), _) => encoder.get_ref().blocked,
+            (None, Some(out)) => out.blocked,
+            (None, None) => std::time::Duration::ZERO,
+        }
+    }
+
+    /// Раз в [`ADAPT_WINDOW`]: выход архива был занят записью больше половины
+    /// окна – уровень выше, почти простаивал – ниже.
+    fn adapt_level(&mut self, consumed: u64) -> std::io::Result<()> {
+        let Some(adapt) = self.adapt.as_mut() else { return Ok(()) };
+        let blocked = adapt.output.total();
+        adapt.window_in += consumed;
+        if adapt.window_in < ADAPT_WINDOW {
+            return Ok(());
+        }
+        let elapsed = adapt.window_start.elapsed().as_secs_f64().max(1e-9);
+        let waiting = (blocked - adapt.blocked_at_start).as_secs_f64() / elapsed;
+        let next = if waiting > 0.5 {
+            self.level + 1
+        } else if waiting < 0.1 {
+            self.level - 1
+        } else {
+            self.level
+        }
+        .clamp(adapt.range.min, adapt.range.max);
+        adapt.window_in = 0;
+        adapt.window_start = Instant::now();
+        adapt.blocked_at_start = blocked;
+        if next != self.level {
+            adapt.current.store(next, std::sync::atomic::Ordering::Relaxed);
+            self.end_frame()?;
+            self.level = next;
+        }
+        Ok(())
+    }
+
+    /// Раз в [`ADAPT_WINDOW`]: отчёт об окне (сжатие без ожидания приёмника)
+    /// и переход на уровень и число потоков, которые выбрал AutoTuner.
+    fn retune_level(&mut self, consumed: u64, busy: std::time::Duration) -> std::io::Result<()> {
+        let written = self.written();
+        let level = self.level;
+        let Some(tuned) = self.tuned.as_mut() else { return Ok(()) };
+        tuned.window_in += consumed;
+        tuned.busy += busy;
+        if tuned.window_in < ADAPT_WINDOW {
+            return Ok(());
+        }
+        let next = tuned.tuning.record(
+            level,
+            tuned.window_in,
+            written - tuned.written_at_start,
+            tuned.busy,
+            tuned.window_start.elapsed(),
+        );
+        tuned.window_in = 0;
+        tuned.busy = std::time::Duration::ZERO;
+        tuned.window_start = Instant::now();
+        // Число потоков zstd вступает в силу со следующего фрейма
+        self.threads = tuned.tuning.codec_threads();
+        if next != level {
+            self.end_frame()?;
+            self.level = next;
+        }
+        let written = self.written();
+        if let Some(tuned) = self.tuned.as_mut() {
+            tuned.written_at_start = written;
+        }
+        Ok(())
+    }
+
+    fn encoder(&mut self) -> std::io::Result<&mut zstd::Encoder<'static, CountingWriter<W>>> {
+        if self.encoder.is_none() {
+            let out = self.out.take().expect("writer between frames");
+            self.frame_start = out.written;
+            let mut encoder = match &self.dictionary {
+                Some(dict) => zstd::Encoder::with_dictionary(out, self.level, dict.bytes())?,
+                None => zstd::Encoder::new(out, self.level)?,
+            };
+            encoder.include_checksum(true)?;
+            for p in self.params {
+                encoder.set_parameter(*p)?;
+            }
+            if self.threads > 1 {
+                encoder.multithread(self.threads)?;
+            }
+            self.encoder = Some(encoder);
+        }
+        Ok(self.encoder.as_mut().expect("encoder just created"))
+    }
+
+    fn end_frame(&mut self) -> std::io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            let out = encoder.finish()?;
+            self.frames.push((out.written - self.frame_start, self.in_frame));
+            self.in_frame = 0;
+            self.out = Some(out);
+            if let Some(cutter) = self.rsync.as_mut() {
+                cutter.restart();
+            }
+        }
+        Ok(())
+    }
+
+    /// Завершает поток; возвращает writer и таблицу фреймов (пустую, если поток
+    /// не резался на фреймы).
+    fn finish(mut self) -> std::io::Result<(W, Vec<(u64, u64)>)> {
+        if let Some(block) = self.stored.take() {
+            let mut out = self.out.take().expect("stored shard writer");
+            if out.written == 0 {
+                out.write_all(&STORED_FRAME_HEADER)?;
+            }
+            write_raw_block(&mut out, &block, true)?;
+            return Ok((out.inner, Vec::new()));
+        }
+        if self.encoder.is_none() && self.frames.is_empty() {
+            self.encoder()?; // пустой шард – всё равно валидный zstd-поток
+        }
+        self.end_frame()?;
+        let frames = if self.frame_size.is_some() || self.rsync.is_some() || self.per_file { self.frames } else { Vec::new() };
+        Ok((self.out.take().expect("writer after last frame").inner, frames))
+    }
+}
+
+impl<W: Write> Write for ShardEncoder<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.stored.is_some() {
+            return self.write_stored(buf);
+        }
+        let (started, blocked_before) = (Instant::now(), self.blocked());
+        let room = self.frame_size.map_or(buf.len() as u64, |size| size - self.in_frame);
+        let mut take = room.min(buf.len() as u64) as usize;
+        let cut = self.rsync.as_mut().and_then(|cutter| cutter.find_cut(&buf[..take]));
+        let n = if self.rsync.is_some() {
+            // Резчик уже учёл эти байты – кодер должен принять их целиком
+            take = cut.unwrap_or(take);
+            self.encoder()?.write_all(&buf[..take])?;
+            take
+        } else {
+            self.encoder()?.write(&buf[..take])?
+        };
+        self.in_frame += n as u64;
+        if cut.is_some() || Some(self.in_frame) == self.frame_size {
+            self.end_frame()?;
+        }
+        self.adapt_level(n as u64)?;
+        // Ожидание приёмника – не работа кодера
+        let busy = started.elapsed().saturating_sub(self.blocked().saturating_sub(blocked_before));
+        self.retune_level(n as u64, busy)?;
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.encoder.as_mut() {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Сообщения от воркеров координатору
 enum ShardMsg {
     Done {
@@ -100,29 +587,853 @@ enum ShardMsg {
         uncompressed: u64,
         files: Vec<FileEntry>,
         nonce: Option<[u8; 12]>,
+        frames: Vec<(u64, u64)>,
+        level: i32,
+        stored: bool,
     },
+    /// The shard could not be written; the archive is not finished.
+    Failed { shard_id: usize, error: std::io::Error },
+}
+
+/// Состав шардов по стратегии `--shard-strategy` (порядок обхода внутри шарда сохраняется).
+fn group_files(
+    vfs: &dyn crate::vfs::Vfs,
+    files: &[PathBuf],
+    workers: usize,
+    strategy: ShardStrategy,
+) -> std::io::Result<Vec<Vec<PathBuf>>> {
+    match strategy {
+        // Балансировка по байтам, а не по числу файлов; per-file режет те же
+        // шарды на фреймы по файлам
+        ShardStrategy::Solid | ShardStrategy::PerFile => {
+            let sizes = files.iter().map(|p| vfs.metadata(p).map(|m| m.len)).collect::<std::io::Result<Vec<u64>>>()?;
+            Ok(crate::katana::split_by_size(files, &sizes, workers))
+        }
+        ShardStrategy::SizeCapped(cap) => {
+            let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+            let mut group_size = 0u64;
+            for path in files {
+                let len = vfs.metadata(path)?.len;
+                match groups.last_mut() {
+                    Some(group) if group_size + len <= cap => {
+                        group.push(path.clone());
+                        group_size += len;
+                    }
+                    // Файл больше лимита получает свой шард
+                    _ => {
+                        groups.push(vec![path.clone()]);
+                        group_size = len;
+                    }
+                }
+            }
+            Ok(groups)
+        }
+    }
+}
+
+/// Шарды с учётом `--ordering-manifest`: известные файлы – в свои прежние
+/// шарды, новые – в дополнительные шарды по `shard_strategy`. Второе значение –
+/// число закреплённых шардов (их порядок файлов не трогаем), третье – число
+/// шардов вложенных архивов в конце списка (`--store-nested`, пишутся без сжатия).
+fn plan_shards(
+    vfs: &dyn crate::vfs::Vfs,
+    files: &[PathBuf],
+    base_dir: &Path,
+    workers: usize,
+    options: &KatanaCreateOptions,
+) -> std::io::Result<(Vec<Vec<PathBuf>>, usize, usize)> {
+    let (mut shards, rest) = match options.ordering_manifest.as_deref() {
+        Some(manifest) => manifest.assign(files, |p| entry_name(base_dir, p)),
+        None => (Vec::new(), files.to_vec()),
+    };
+    let pinned = shards.len();
+    if options.ordering_manifest.is_some() {
+        println!("[katana] Ordering manifest: {} shards kept, {} new files", pinned, rest.len());
+    }
+    let (nested, rest): (Vec<PathBuf>, Vec<PathBuf>) = if options.store_nested_archives {
+        rest.into_iter().partition(|p| is_nested_archive(p))
+    } else {
+        (Vec::new(), rest)
+    };
+    if !rest.is_empty() {
+        shards.extend(group_files(vfs, &rest, workers, options.shard_strategy)?);
+    }
+    if !nested.is_empty() {
+        println!("[katana] {} nested BlitzArch archives stored without recompression", nested.len());
+    }
+    let stored = nested.len();
+    shards.extend(nested.into_iter().map(|p| vec![p]));
+    Ok((shards, pinned, stored))
+}
+
+/// Архив BlitzArch среди входов: расширение `.blz` или трейлер Katana.
+fn is_nested_archive(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("blz"))
+        || crate::katana::is_katana_archive(path).unwrap_or(false)
+}
+
+/// Имя записи в индексе: путь относительно общего родителя входов.
+fn entry_name(base_dir: &Path, path: &Path) -> String {
+    let rel_path = match path.strip_prefix(base_dir) {
+        Ok(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => path.to_path_buf(),
+    };
+    crate::katana::normalize_path(&rel_path.to_string_lossy())
+}
+
+/// Находит побайтно одинаковые файлы: `(дубликат, оригинал)` – индексы в `files`.
+/// Кандидаты – файлы одинакового ненулевого размера; сравниваются по BLAKE3.
+fn find_duplicates(vfs: &dyn crate::vfs::Vfs, files: &[PathBuf]) -> std::io::Result<Vec<(usize, usize, [u8; 32])>> {
+    let mut by_size: std::collections::HashMap<u64, Vec<usize>> = std::collections::HashMap::new();
+    for (i, path) in files.iter().enumerate() {
+        let len = vfs.metadata(path)?.len;
+        if len > 0 {
+            by_size.entry(len).or_default().push(i);
+        }
+    }
+    let mut candidates: Vec<(usize, u64)> = by_size
+        .into_iter()
+        .filter(|(_, group)| group.len() > 1)
+        .flat_map(|(len, group)| group.into_iter().map(move |i| (i, len)))
+        .collect();
+    candidates.sort_unstable();
+    let hashes = candidates
+        .par_iter()
+        .map(|&(i, len)| {
+            let mut hasher = blake3::Hasher::new();
+            std::io::copy(&mut vfs.open(&files[i])?, &mut hasher)?;
+            Ok((i, (len, *hasher.finalize().as_bytes())))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    // Оригинал – первый по порядку обхода файл с тем же размером и хэшем
+    let mut first: std::collections::HashMap<(u64, [u8; 32]), usize> = std::collections::HashMap::new();
+    let mut pairs = Vec::new();
+    for (i, key) in hashes {
+        match first.get(&key) {
+            Some(&orig) => pairs.push((i, orig, key.1)),
+            None => {
+                first.insert(key, i);
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// Сверка оригиналов --dedup с тем, что попало в архив: между поиском дубликатов
+/// и сжатием оригинал мог измениться, и ссылка на него верна, только если хэш
+/// прочитанных при архивации байт совпал с хэшем, по которому нашли дубликат.
+#[derive(Default)]
+struct DedupCheck {
+    /// Хэш каждого оригинала на момент поиска дубликатов
+    expected: std::collections::HashMap<PathBuf, [u8; 32]>,
+    /// Дубликат → его оригинал
+    originals: std::collections::HashMap<PathBuf, PathBuf>,
+    changed: std::sync::Mutex<std::collections::HashSet<PathBuf>>,
+}
+
+impl DedupCheck {
+    /// Хэшер для байт `path`, если это оригинал дубликатов
+    fn hasher(&self, path: &Path) -> Option<blake3::Hasher> {
+        self.expected.contains_key(path).then(blake3::Hasher::new)
+    }
+
+    fn finish(&self, path: &Path, hasher: Option<blake3::Hasher>) {
+        if let Some(hasher) = hasher {
+            if self.expected.get(path) != Some(hasher.finalize().as_bytes()) {
+                self.changed.lock().unwrap().insert(path.to_path_buf());
+            }
+        }
+    }
+
+    /// Оригинал дубликата `dup` попал в архив не с теми байтами, что сравнивались
+    fn is_stale(&self, dup: &Path) -> bool {
+        self.originals.get(dup).is_some_and(|orig| self.changed.lock().unwrap().contains(orig))
+    }
+}
+
+/// Хранилище чанков (--dedup=chunks), собранное во временном файле
+struct ChunkStoreOut {
+    tmp_path: TempPath,
+    /// `offset` заполняется при записи в архив
+    info: ShardInfo,
+    chunk_sizes: Vec<u32>,
+    entries: Vec<FileEntry>,
+}
+
+/// Режет файлы на content-defined чанки ([`crate::cdc`]); уникальные (по BLAKE3)
+/// чанки пишутся в один zstd-поток, шифруемый как шард с подключом `key_id`.
+#[allow(clippy::too_many_arguments)]
+fn build_chunk_store(
+    vfs: &dyn crate::vfs::Vfs,
+    files: &[PathBuf],
+    base_dir: &Path,
+    key: Option<&[u8; 32]>,
+    key_id: u64,
+    compression_level: i32,
+    zstd_params: &[zstd::stream::raw::CParameter],
+    zstd_threads: u32,
+    time_budget: Option<&crate::timebox::TimeBudget>,
+    dedup: &DedupCheck,
+) -> Result<ChunkStoreOut, Box<dyn Error>> {
+    let mut tmp = crate::temp_manager::temp_file("chunks")?;
+    let nonce = key.map(|_| {
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        nonce
+    });
+    let mut write_chunks = |out: &mut dyn Write| -> Result<(Vec<u32>, Vec<FileEntry>), Box<dyn Error>> {
+        let mut encoder = zstd::Encoder::new(out, compression_level)?;
+        encoder.include_checksum(true)?;
+        for p in zstd_params {
+            encoder.set_parameter(*p)?;
+        }
+        if zstd_threads > 1 {
+            encoder.multithread(zstd_threads)?;
+        }
+        let mut known: std::collections::HashMap<[u8; 32], u32> = std::collections::HashMap::new();
+        let mut chunk_sizes: Vec<u32> = Vec::new();
+        let mut entries = Vec::with_capacity(files.len());
+        for (i, path) in files.iter().enumerate() {
+            if let Some(budget) = time_budget.filter(|b| b.expired()) {
+                budget.defer(&files[i..]);
+                break;
+            }
+            let meta = vfs.metadata(path)?;
+            let mut chunker = crate::cdc::Chunker::new(vfs.open_sequential(path)?);
+            let mut chunks = Vec::new();
+            let mut size = 0u64;
+            let mut hasher = dedup.hasher(path);
+            while let Some(chunk) = chunker.next_chunk()? {
+                size += chunk.len() as u64;
+                if let Some(h) = hasher.as_mut() {
+                    h.update(chunk);
+                }
+                let hash = *blake3::hash(chunk).as_bytes();
+                let id = match known.get(&hash) {
+                    Some(&id) => id,
+                    None => {
+                        let id = u32::try_from(chunk_sizes.len()).map_err(|_| "Too many unique chunks")?;
+                        encoder.write_all(chunk)?;
+                        chunk_sizes.push(chunk.len() as u32);
+                        known.insert(hash, id);
+                        id
+                    }
+                };
+                chunks.push(id);
+            }
+            dedup.finish(path, hasher);
+            entries.push(FileEntry {
+                path: entry_name(base_dir, path),
+                size,
+                offset: 0,
+                permissions: meta.permissions,
+                // Файл, опустевший после листинга, хранится как пустой inline
+                inline: chunks.is_empty().then(Vec::new),
+                mtime: meta.mtime,
+                btime: meta.btime,
+                owner: meta.owner,
+                base_ref: false,
+                symlink: None,
+                duplicate_of: None,
+                xattrs: Default::default(),
+                chunks,
+                nested_archive: false,
+                sparse: Vec::new(),
+            });
+        }
+        encoder.finish()?;
+        Ok((chunk_sizes, entries))
+    };
+    let (chunk_sizes, entries) = match (key, nonce) {
+        (Some(key), Some(nonce)) => {
+            let mut sink = EncryptSink::new(tmp.as_file_mut(), &crate::crypto::derive_shard_key(key, key_id), nonce);
+            let written = write_chunks(&mut sink)?;
+            sink.finalize()?;
+            written
+        }
+        _ => write_chunks(tmp.as_file_mut())?,
+    };
+
+    let tmp_path = tmp.into_temp_path();
+    let info = ShardInfo {
+        offset: 0,
+        compressed_size: std::fs::metadata(&tmp_path)?.len(),
+        uncompressed_size: chunk_sizes.iter().map(|&s| s as u64).sum(),
+        file_count: chunk_sizes.len(),
+        // контрольная сумма считается при дописывании в архив
+        crc32: 0,
+        xxh3: None,
+        nonce,
+        key_id: nonce.map(|_| key_id),
+        frames: Vec::new(),
+        dictionary: None,
+        stats: None,
+    };
+    Ok(ChunkStoreOut { tmp_path, info, chunk_sizes, entries })
+}
+
+/// Файлы входов так, как их заархивирует create (без самого архива,
+/// `exclude_outputs`, отсеянных `--include/--exclude` и фильтром размеров) и
+/// сколько файлов отсеял фильтр размеров.
+fn collect_files(
+    vfs: &dyn crate::vfs::Vfs,
+    inputs: &[PathBuf],
+    output_path: &Path,
+    options: &KatanaCreateOptions,
+) -> Result<(Vec<PathBuf>, crate::fsx::SizeSkipped), Box<dyn Error>> {
+    let mut files = Vec::new();
+    for path in inputs {
+        let listed = vfs.list_files_pruned(path, &|dir| options.filter.prunes(path, dir))?;
+        files.extend(listed.into_iter().filter(|f| options.filter.allows_under(path, f)));
+    }
+    exclude_output_path(&mut files, inputs, output_path)?;
+    for extra in &options.exclude_outputs {
+        exclude_output_path(&mut files, inputs, extra)?;
+    }
+    let mut skipped = crate::fsx::SizeSkipped::default();
+    if !options.size_filter.is_empty() {
+        let mut kept = Vec::with_capacity(files.len());
+        for path in files {
+            // Ссылка, сохраняемая как ссылка, данных не несёт; за пройденными
+            // ссылками (`--symlinks follow`, ссылки-входы) судим по размеру цели
+            if vfs.read_link(&path)?.is_some() {
+                kept.push(path);
+                continue;
+            }
+            let len = vfs.metadata(&path)?.len;
+            if !options.size_filter.allows(len) {
+                skipped.files += 1;
+                skipped.bytes += len;
+                continue;
+            }
+            kept.push(path);
+        }
+        files = kept;
+    }
+    Ok((files, skipped))
+}
+
+/// Отпечаток набора файлов: hex BLAKE3 по именам записей, размерам и mtime
+/// (в порядке имён, так что порядок обхода не влияет).
+pub(crate) fn fingerprint_files(vfs: &dyn crate::vfs::Vfs, base_dir: &Path, files: &[PathBuf]) -> std::io::Result<String> {
+    let mut entries = Vec::with_capacity(files.len());
+    for path in files {
+        let meta = vfs.metadata(path)?;
+        entries.push((entry_name(base_dir, path), meta.len, meta.mtime));
+    }
+    entries.sort_unstable();
+    let mut hasher = blake3::Hasher::new();
+    for (name, size, mtime) in entries {
+        hasher.update(format!("{}\0{}\0{:?}\n", name, size, mtime).as_bytes());
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
 
+/// Fingerprint of the source set `create` would archive from `inputs` with
+/// `options`: a hash of the entry names, sizes and mtimes. Archives record it
+/// in the index ([`crate::katana::archive_source_fingerprint`]).
+pub fn source_fingerprint(inputs: &[PathBuf], output_path: &Path, options: &KatanaCreateOptions) -> Result<String, Box<dyn Error>> {
+    let os_fs = crate::vfs::OsFs { symlinks: options.symlinks, io: options.io };
+    let vfs: &dyn crate::vfs::Vfs = options.vfs.as_deref().unwrap_or(&os_fs);
+    let (files, _) = collect_files(vfs, inputs, output_path, options)?;
+    Ok(fingerprint_files(vfs, &crate::katana::common_parent(inputs), &files)?)
 }
 
-/// Разбить вектор на приблизительно равные под-массивы
-fn split_even<T: Clone>(list: &[T], parts: usize) -> Vec<Vec<T>> {
-    let mut chunks = Vec::with_capacity(parts);
-    let chunk_sz = (list.len() + parts - 1) / parts;
-    for c in list.chunks(chunk_sz) {
-        chunks.push(c.to_vec());
+/// `create --skip-if-unchanged`: true if `previous` was created from the same
+/// source set `inputs` hold now (same entries, sizes and mtimes). A missing
+/// `previous`, or one without a fingerprint, counts as changed. `password`
+/// opens the index of a `previous` created with `--hide-names`.
+pub fn source_unchanged(
+    inputs: &[PathBuf],
+    previous: &Path,
+    options: &KatanaCreateOptions,
+    password: Option<&str>,
+) -> Result<bool, Box<dyn Error>> {
+    if !previous.exists() {
+        return Ok(false);
+    }
+    let Some(recorded) = crate::katana::archive_source_fingerprint(previous, password)? else {
+        return Ok(false);
+    };
+    // `previous` left itself out of its source set
+    Ok(source_fingerprint(inputs, previous, options)? == recorded)
+}
+
+/// Сколько байт начала файла сжимаем для оценки и общий лимит выборки.
+const ESTIMATE_SAMPLE_PER_FILE: usize = 64 * 1024;
+const ESTIMATE_SAMPLE_BUDGET: u64 = 64 * 1024 * 1024;
+/// (dense, размер, Some((выборка, сжатая выборка)))
+type SampledFile = (bool, u64, Option<(u64, u64)>);
+
+/// Shard of a [`CreateEstimate`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShardEstimate {
+    pub files: usize,
+    pub bytes: u64,
+    pub estimated_size: u64,
+}
+
+/// What `create --dry-run` expects to write: the planned file set, shard layout
+/// and a compressed size extrapolated from compressing samples of the inputs.
+///
+/// Savings from `--dedup` and `--incremental` are not included, so for those
+/// runs the estimate is an upper bound.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreateEstimate {
+    pub files: usize,
+    pub bytes: u64,
+    /// Files in already-compressed formats (by extension or magic bytes).
+    pub dense_files: usize,
+    pub dense_bytes: u64,
+    pub symlinks: usize,
+    /// Files `--inline-small` would store in the index.
+    pub inline_files: usize,
+    /// Files left out by `--min-file-size/--max-file-size/--skip-empty`.
+    pub skipped_by_size: crate::fsx::SizeSkipped,
+    /// Input bytes actually compressed to derive the estimate.
+    pub sampled_bytes: u64,
+    pub estimated_size: u64,
+    pub shards: Vec<ShardEstimate>,
+}
+
+impl CreateEstimate {
+    pub fn print_summary(&self) {
+        let mib = |b: u64| b as f64 / (1024.0 * 1024.0);
+        println!(
+            "[katana] Dry run: {} files, {:.2} MiB ({} already compressed, {:.2} MiB)",
+            self.files,
+            mib(self.bytes),
+            self.dense_files,
+            mib(self.dense_bytes)
+        );
+        if self.symlinks > 0 || self.inline_files > 0 {
+            println!("[katana] {} symbolic links, {} small files inlined into the index", self.symlinks, self.inline_files);
+        }
+        print_skipped_by_size(&self.skipped_by_size);
+        let ratio = if self.bytes > 0 { self.estimated_size as f64 / self.bytes as f64 * 100.0 } else { 100.0 };
+        println!(
+            "[katana] Estimated archive size: {:.2} MiB ({:.1}%), from {:.2} MiB sampled",
+            mib(self.estimated_size),
+            ratio,
+            mib(self.sampled_bytes)
+        );
+        let largest = self.shards.iter().map(|s| s.bytes).max().unwrap_or(0);
+        println!("[katana] {} shards, largest {:.2} MiB", self.shards.len(), mib(largest));
+        for (i, shard) in self.shards.iter().enumerate().take(MAX_PRINTED_SHARDS) {
+            println!(
+                "  shard {:>3}: {:>6} files, {:>10.2} MiB → ~{:.2} MiB",
+                i,
+                shard.files,
+                mib(shard.bytes),
+                mib(shard.estimated_size)
+            );
+        }
+        if self.shards.len() > MAX_PRINTED_SHARDS {
+            println!("  … and {} more", self.shards.len() - MAX_PRINTED_SHARDS);
+        }
     }
-    chunks
+}
+
+const MAX_PRINTED_SHARDS: usize = 16;
+
+fn print_skipped_by_size(skipped: &crate::fsx::SizeSkipped) {
+    if skipped.files > 0 {
+        println!(
+            "[katana] Skipped by size: {} files, {:.2} MiB",
+            skipped.files,
+            skipped.bytes as f64 / (1024.0 * 1024.0)
+        );
+    }
+}
+
+/// `create --dry-run`: plans the archive `create_katana_archive_with_options`
+/// would write from `inputs` with the same arguments, without writing anything.
+///
+/// Sampled files contribute their first 64 KiB compressed at the archive's
+/// level; files beyond the 64 MiB sampling budget are estimated from the ratio
+/// of sampled files of their kind (already compressed or not).
+pub fn estimate_create(
+    inputs: &[PathBuf],
+    output_path: &Path,
+    threads: usize,
+    compression_level: Option<i32>,
+    encrypted: bool,
+    options: &KatanaCreateOptions,
+) -> Result<CreateEstimate, Box<dyn Error>> {
+    let os_fs = crate::vfs::OsFs { symlinks: options.symlinks, io: options.io };
+    let vfs: &dyn crate::vfs::Vfs = options.vfs.as_deref().unwrap_or(&os_fs);
+    let (files, skipped_by_size) = collect_files(vfs, inputs, output_path, options)?;
+    if files.is_empty() {
+        return Err("No input files".into());
+    }
+    // Без явного уровня – сбалансированный уровень AutoTune
+    let level = compression_level.unwrap_or(3);
+
+    let mut estimate = CreateEstimate { skipped_by_size, ..Default::default() };
+    let mut regular = Vec::with_capacity(files.len());
+    for path in files {
+        if vfs.read_link(&path)?.is_some() {
+            estimate.symlinks += 1;
+            continue;
+        }
+        let len = vfs.metadata(&path)?.len;
+        estimate.files += 1;
+        estimate.bytes += len;
+        // Как в create: крошечные файлы уходят в индекс как есть
+        if options.inline_small_files && !encrypted && len < crate::katana::INLINE_MAX_SIZE {
+            estimate.inline_files += 1;
+            estimate.estimated_size += len;
+            continue;
+        }
+        regular.push((path, len));
+    }
+
+    // Равномерная выборка, если головы всех файлов не влезают в бюджет
+    let wanted: u64 = regular.iter().map(|(_, len)| (*len).min(ESTIMATE_SAMPLE_PER_FILE as u64)).sum();
+    let stride = wanted.div_ceil(ESTIMATE_SAMPLE_BUDGET).max(1) as usize;
+    let sampled = regular
+        .par_iter()
+        .enumerate()
+        .map(|(i, (path, len))| -> std::io::Result<SampledFile> {
+            let ext_dense = path.extension().and_then(|e| e.to_str()).is_some_and(|ext| options.extension_rules.is_dense_ext(ext));
+            if i % stride != 0 && ext_dense {
+                return Ok((true, *len, None));
+            }
+            let mut head = Vec::with_capacity(ESTIMATE_SAMPLE_PER_FILE.min(*len as usize));
+            vfs.open(path)?.take(ESTIMATE_SAMPLE_PER_FILE as u64).read_to_end(&mut head)?;
+            let dense = ext_dense || crate::compress::is_dense_header(&head);
+            if i % stride != 0 || head.is_empty() {
+                return Ok((dense, *len, None));
+            }
+            let packed = zstd::bulk::compress(&head, level)?;
+            Ok((dense, *len, Some((head.len() as u64, packed.len().min(head.len()) as u64))))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    // Доля сжатия по видам данных – для файлов вне выборки
+    let mut totals = [(0u64, 0u64); 2];
+    for (dense, _, sample) in &sampled {
+        if let Some((raw, packed)) = sample {
+            totals[*dense as usize].0 += raw;
+            totals[*dense as usize].1 += packed;
+        }
+    }
+    let ratio_of = |dense: bool| match totals[dense as usize] {
+        (0, _) => 1.0,
+        (raw, packed) => packed as f64 / raw as f64,
+    };
+    let mut size_of = std::collections::HashMap::with_capacity(regular.len());
+    for ((path, _), (dense, len, sample)) in regular.iter().zip(&sampled) {
+        if *dense {
+            estimate.dense_files += 1;
+            estimate.dense_bytes += len;
+        }
+        let ratio = match sample {
+            Some((raw, packed)) => {
+                estimate.sampled_bytes += raw;
+                *packed as f64 / *raw as f64
+            }
+            None => ratio_of(*dense),
+        };
+        size_of.insert(path.clone(), (*len, (*len as f64 * ratio).ceil() as u64));
+    }
+
+    let workers = if threads == 0 { num_cpus::get() } else { threads }.max(1);
+    let paths: Vec<PathBuf> = regular.into_iter().map(|(p, _)| p).collect();
+    let base_dir = crate::katana::common_parent(inputs);
+    let groups = if paths.is_empty() { Vec::new() } else { plan_shards(vfs, &paths, &base_dir, workers, options)?.0 };
+    for group in groups {
+        let mut shard = ShardEstimate { files: group.len(), ..Default::default() };
+        for path in &group {
+            let (len, packed) = size_of[path];
+            shard.bytes += len;
+            shard.estimated_size += packed;
+        }
+        estimate.estimated_size += shard.estimated_size;
+        estimate.shards.push(shard);
+    }
+    Ok(estimate)
+}
+
+/// Guards against archiving the output into itself (`create -o dir/out.blz dir/`).
+///
+/// A stale or partially written output found while walking an input directory is
+/// dropped from the plan with a warning; passing the output explicitly as an input
+/// file is an error.
+pub(crate) fn exclude_output_path(files: &mut Vec<PathBuf>, inputs: &[PathBuf], output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let output_abs = crate::fsx::absolute_path(output_path);
+    if inputs.iter().any(|p| p.is_file() && crate::fsx::absolute_path(p) == output_abs) {
+        return Err(format!("Output archive {} is also listed as an input", output_path.display()).into());
+    }
+    // The output's own advisory lock file exists only while it is written – drop it silently
+    let lock_abs = crate::fsx::lock_path(&output_abs);
+    files.retain(|p| p.file_name() != lock_abs.file_name() || crate::fsx::absolute_path(p) != lock_abs);
+    let before = files.len();
+    // Cheap file-name check first so large trees don't pay for canonicalize() per file
+    files.retain(|p| p.file_name() != output_abs.file_name() || crate::fsx::absolute_path(p) != output_abs);
+    if files.len() != before {
+        crate::warnings::warn(
+            crate::warnings::WarningKind::SkippedFile,
+            output_path.to_string_lossy(),
+            format!("Output archive {} lies inside an input directory – excluded from the archive", output_path.display()),
+        );
+    }
+    Ok(())
 }
 
 /// Основная функция создания архива Katana в «гибрид-стрим» режиме
 use std::time::Instant;
 use crate::autotune::{AutoTuner, CompressionStats};
 
+/// Advanced creation settings that go beyond the positional arguments of
+/// [`create_katana_archive`]. `Default` reproduces its behaviour exactly.
+#[derive(Debug, Clone, Default)]
+pub struct KatanaCreateOptions {
+    /// Expert zstd parameters applied to every shard encoder after the level
+    /// (e.g. `ChainLog`, `TargetLength`). Invalid values are rejected before
+    /// any shard is written.
+    pub zstd_params: Vec<zstd::stream::raw::CParameter>,
+    /// Store files smaller than [`crate::katana::INLINE_MAX_SIZE`] in the index
+    /// instead of a shard, so metadata-heavy trees (node_modules, maildirs) need
+    /// far fewer shard touches. Ignored for encrypted archives, whose index is
+    /// authenticated but not encrypted.
+    pub inline_small_files: bool,
+    /// Order of files inside each shard; `None` keeps the directory walk order.
+    pub ordering: Option<Arc<dyn crate::ordering::FileOrder>>,
+    /// Reuse this salt instead of generating one, so the shards can join an
+    /// existing encrypted archive (see [`crate::katana::append_to_archive`]).
+    pub salt: Option<[u8; 16]>,
+    /// Encrypt with this archive key instead of deriving one from the password
+    /// and `salt` (appending to an archive with key slots).
+    pub archive_key: Option<[u8; 32]>,
+    /// Encrypt the index block too (`--hide-names`), so entry names and sizes
+    /// cannot be listed without the password. Requires a password.
+    pub hide_names: bool,
+    /// Key-value metadata stored in the index (`--meta`, `--comment`), see
+    /// [`crate::katana::read_metadata`].
+    pub metadata: std::collections::BTreeMap<String, String>,
+    /// Compress every shard with this pre-trained zstd dictionary
+    /// (`create --dictionary`). It is stored outside the archive and only
+    /// referenced by id, so readers need the same file.
+    pub dictionary: Option<Arc<crate::dictionary::ZstdDictionary>>,
+    /// First shard subkey id; appended shards continue after the existing ones.
+    pub key_id_base: u64,
+    /// Further outputs that must never be archived, like `output_path` itself
+    /// (the target of an append while the shards are staged elsewhere).
+    pub exclude_outputs: Vec<PathBuf>,
+    /// `--include/--exclude` patterns applied while listing the inputs.
+    pub filter: crate::fsx::PathFilter,
+    /// Size limits applied while listing the inputs; the files left out are
+    /// reported in the summary and the dry-run plan.
+    pub size_filter: crate::fsx::SizeFilter,
+    /// Store BlitzArch archives among the inputs (`.blz` or a Katana trailer)
+    /// in shards of their own without recompression and mark them for
+    /// `extract --recursive-extract`.
+    pub store_nested_archives: bool,
+    /// Break a stale output lock of a crashed run and overwrite a partial
+    /// output it left behind (`create --force`).
+    pub force: bool,
+    /// Codec of the JSON index: a higher zstd level pays off for huge indexes,
+    /// `Store` skips compression for tiny ones.
+    pub index_compression: crate::katana::IndexCompression,
+    /// Index layout: version 2 (binary, default) or version 1 JSON for readers
+    /// older than the version 2 index (`create --index-format v1`).
+    pub index_format: crate::katana::IndexFormat,
+    /// Incremental mode: files whose size, mtime and permissions match this
+    /// archive's index are recorded as references instead of being stored again.
+    /// Extraction pulls them from the base (and its bases) transparently.
+    pub base: Option<PathBuf>,
+    /// Source of the input files; `None` reads the local filesystem
+    /// ([`crate::vfs::OsFs`]).
+    pub vfs: Option<Arc<dyn crate::vfs::Vfs>>,
+    /// Stop adding files to shards once this budget is exhausted; the archive is
+    /// finalized with what was written so far and the remaining inputs are
+    /// recorded in the budget (see [`crate::timebox`]).
+    pub time_budget: Option<Arc<crate::timebox::TimeBudget>>,
+    /// Handling of symbolic links by the default filesystem source (a custom
+    /// [`Self::vfs`] decides on its own via [`crate::vfs::Vfs::read_link`]).
+    pub symlinks: crate::vfs::SymlinkMode,
+    /// Store byte-identical files (same size and BLAKE3) once; the other copies
+    /// become references that extraction restores as copies or hard links.
+    pub dedup: bool,
+    /// Split files of at least [`crate::cdc::MIN_CHUNK`] bytes into
+    /// content-defined chunks and store every distinct chunk once (by BLAKE3)
+    /// in the chunk store, a single zstd stream behind the shards. Pays off
+    /// for VM images and build trees with many partially identical files.
+    pub chunk_dedup: bool,
+    /// Record extended attributes and POSIX ACLs ([`crate::vfs::Vfs::xattrs`]);
+    /// extraction restores them with `--xattrs`.
+    pub xattrs: bool,
+    /// Caps on concurrent file reads, shard compressions and shard writes;
+    /// unset caps are autotuned (see [`crate::autotune::IoLimits::resolve`]).
+    pub io_limits: crate::autotune::IoLimits,
+    /// How the default filesystem source reads large inputs and how shard temp
+    /// files are written (`create --io-mode`, `--readahead`).
+    pub io: crate::fsx::IoConfig,
+    /// Changes to the built-in dense extension list (the config file's
+    /// `[extensions] dense`). Only the dry-run estimate reads them: Katana
+    /// compresses every shard, so `--dense-ext` is rejected for it.
+    pub extension_rules: crate::compress::ExtensionRules,
+    /// Checksum of the stored shard bytes; XXH3 verifies multi-GB shards much
+    /// faster than CRC32 but needs a reader that knows
+    /// [`crate::katana::FEATURE_XXH3`].
+    pub shard_checksum: crate::katana::ShardChecksum,
+    /// How files are grouped into shards; `Solid` balances them by size across
+    /// the workers.
+    pub shard_strategy: ShardStrategy,
+    /// Keep the shard split and file order of an earlier run
+    /// (`create --ordering-manifest`); files it does not list get new shards
+    /// after the known ones, and [`Self::ordering`] only applies to those.
+    pub ordering_manifest: Option<Arc<crate::ordering::OrderingManifest>>,
+    /// Write the file-to-shard assignment of this run here once the archive
+    /// is complete (`create --export-ordering`).
+    pub export_ordering: Option<PathBuf>,
+    /// Write every shard as independent zstd frames of this many uncompressed
+    /// bytes and record a seek table, so a single file (or a range of it, see
+    /// [`crate::katana::read_range`]) is decoded from the nearest frame instead
+    /// of the start of the shard, and extraction splits the shard across cores.
+    /// Smaller frames cost a little ratio; `None` ⇒ [`crate::katana::DEFAULT_FRAME_SIZE`]
+    /// (content-defined frames only with [`Self::rsync_friendly`]).
+    pub seekable_frames: Option<u64>,
+    /// Cut every shard into zstd frames at content-defined boundaries
+    /// (see [`crate::cdc::FrameCutter`]), so a small change in the inputs only
+    /// alters the frames around it and rsync/rclone transfer little of an
+    /// updated archive. Frames are recorded in the seek table like
+    /// [`Self::seekable_frames`]. Encrypted shards still change completely.
+    pub rsync_friendly: bool,
+    /// Adapt the compression level of every shard to how fast the archive
+    /// output (file or [`Self::sink`]) drains, within this range
+    /// (`create --adapt`); the fixed level is the
+    /// starting point. A level change starts a new zstd frame.
+    pub adapt: Option<AdaptiveLevel>,
+    /// Retune the AutoTune level and codec threads from the ratio and
+    /// throughput the shards achieve (`create --retune`); shards started after
+    /// the data proved incompressible at level 1 are stored. Only applies
+    /// without an explicit level and without [`Self::adapt`], and never with
+    /// [`Self::rsync_friendly`]: levels and frame cuts then depend on timing,
+    /// so the same inputs no longer give the same archive.
+    pub retune: bool,
+    /// Stops the job when cancelled: workers quit at the next file or read
+    /// buffer, the partial output is removed and the call fails with
+    /// [`crate::ArchiverError::Cancelled`].
+    pub cancel: Option<crate::cancel::CancellationToken>,
+    /// Write the archive to this sink instead of `output_path`, which then only
+    /// names it in messages: no lock file, no partial-output handling, nothing
+    /// read back. Cannot be combined with [`Self::time_budget`] (no local
+    /// archive to resume).
+    pub sink: Option<Arc<dyn ArchiveSink>>,
+}
+
+/// Destination of the archive bytes other than a local file
+/// (`create --output s3://…` in builds with the `remote` feature).
+///
+/// Bytes arrive strictly in archive order, every shard as soon as it is
+/// compressed; the BLAKE3 footer is computed on the way instead of reading
+/// the output back.
+pub trait ArchiveSink: Send + Sync + std::fmt::Debug {
+    /// Appends the next bytes of the archive.
+    fn append(&self, buf: &[u8]) -> std::io::Result<()>;
+    /// Called once after the footer; until then the output must not become
+    /// visible as a complete archive.
+    fn finish(&self) -> std::io::Result<()>;
+}
+
+/// A [`KatanaCreateOptions::sink`] being written: bytes sent so far and their
+/// running BLAKE3 (the footer hash).
+struct SinkOutput {
+    sink: Arc<dyn ArchiveSink>,
+    len: u64,
+    hasher: blake3::Hasher,
+}
+
+/// Where the writer appends: the output file, or the sink.
+enum ArchiveOutput<'a> {
+    File(RetryingAppender),
+    Sink(&'a mut SinkOutput),
+}
+
+impl<'a> ArchiveOutput<'a> {
+    fn open(output_path: &Path, sink: &'a mut Option<SinkOutput>) -> std::io::Result<Self> {
+        match sink {
+            Some(sink) => Ok(ArchiveOutput::Sink(sink)),
+            None => RetryingAppender::open(output_path).map(ArchiveOutput::File),
+        }
+    }
+
+    fn committed_len(&self) -> u64 {
+        match self {
+            ArchiveOutput::File(file) => file.committed_len(),
+            ArchiveOutput::Sink(sink) => sink.len,
+        }
+    }
+
+    fn append(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self {
+            ArchiveOutput::File(file) => file.append(buf),
+            ArchiveOutput::Sink(sink) => {
+                sink.sink.append(buf)?;
+                sink.hasher.update(buf);
+                sink.len += buf.len() as u64;
+                Ok(())
+            }
+        }
+    }
+
+    fn retries(&self) -> u64 {
+        match self {
+            ArchiveOutput::File(file) => file.retries(),
+            ArchiveOutput::Sink(_) => 0,
+        }
+    }
+}
+
+/// Composition of the shards (`create --shard-strategy`).
+///
+/// Every shard is one zstd stream, so reading a single file decodes its shard
+/// from the start up to that file: smaller shards trade compression ratio for
+/// faster random access.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShardStrategy {
+    /// One shard per worker, files balanced by total bytes (best ratio).
+    #[default]
+    Solid,
+    /// Shards as for [`Solid`](Self::Solid), but every file starts a new zstd
+    /// frame: reading one file decodes only its own frames, at a small cost in
+    /// ratio (frames do not share matches).
+    PerFile,
+    /// Consecutive files grouped into shards of at most this many bytes
+    /// (uncompressed); a larger file gets a shard of its own.
+    SizeCapped(u64),
+}
+
+impl std::str::FromStr for ShardStrategy {
+    type Err = String;
+
+    /// Parses `solid`, `per-file` or `size-capped:MIB`.
+    fn from_str(raw: &str) -> Result<Self, String> {
+        match raw.trim() {
+            "solid" => Ok(ShardStrategy::Solid),
+            "per-file" => Ok(ShardStrategy::PerFile),
+            other => {
+                let mib = other
+                    .strip_prefix("size-capped:")
+                    .ok_or_else(|| format!("unknown shard strategy '{raw}' (expected solid, per-file or size-capped:MIB)"))?;
+                let mib = mib.trim_end_matches("MiB").trim();
+                match mib.parse::<u64>() {
+                    Ok(n) if n > 0 => Ok(ShardStrategy::SizeCapped(n * 1024 * 1024)),
+                    _ => Err(format!("invalid shard size '{mib}' (expected a positive number of MiB)")),
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn create_katana_archive<F>(
     inputs: &[PathBuf],
     output_path: &Path,
     threads: usize,
-    mut codec_threads: u32,
+    codec_threads: u32,
     mem_budget_mb: Option<u64>,
     password: Option<String>,
     compression_level: Option<i32>,
@@ -131,23 +1442,64 @@ pub fn create_katana_archive<F>(
 where
     F: Fn(crate::progress::ProgressState) + Send + Sync + 'static,
 {
-    // NOTE: actual implementation continues below
+    create_katana_archive_with_options(
+        inputs,
+        output_path,
+        threads,
+        codec_threads,
+        mem_budget_mb,
+        password,
+        compression_level,
+        &KatanaCreateOptions::default(),
+        progress_callback,
+    )
+}
 
+/// Same as [`create_katana_archive`] but accepts [`KatanaCreateOptions`].
+///
+/// Without `compression_level` AutoTune picks the level; with
+/// [`KatanaCreateOptions::retune`] it is retuned while the shards are
+/// compressed, see [`crate::autotune::AutoTuner::retune_level`].
+#[allow(clippy::too_many_arguments)]
+pub fn create_katana_archive_with_options<F>(
+    inputs: &[PathBuf],
+    output_path: &Path,
+    threads: usize,
+    mut codec_threads: u32,
+    mem_budget_mb: Option<u64>,
+    password: Option<String>,
+    compression_level: Option<i32>,
+    options: &KatanaCreateOptions,
+    progress_callback: Option<F>,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(crate::progress::ProgressState) + Send + Sync + 'static,
+{
+    if options.sink.is_some() && options.time_budget.is_some() {
+        return Err("Time budgets (--max-duration, --pausable) need a local output archive".into());
+    }
+    // Другой процесс BlitzArch, пишущий тот же архив, – сразу ошибка
+    let _lock = match (&options.sink, options.force) {
+        (Some(_), _) => None,
+        (None, true) => Some(crate::fsx::OutputLock::acquire_breaking_stale(output_path)?),
+        (None, false) => Some(crate::fsx::OutputLock::acquire(output_path)?),
+    };
+    if options.sink.is_none() {
+        discard_partial_output(output_path, options.force)?;
+    }
+    let mut sink_out = options.sink.clone().map(|sink| SinkOutput { sink, len: 0, hasher: blake3::Hasher::new() });
 
+    // Потоки и память – доля общих лимитов процесса, а не все ядра на каждое задание
+    let wanted_threads = if threads == 0 { num_cpus::get() } else { threads };
+    let wanted_memory_mb = (crate::autotune::memory_budget_from_mb(mem_budget_mb) / (1024 * 1024)) as u64;
+    let Some(lease) = crate::governor::ResourceGovernor::global().register(wanted_threads, wanted_memory_mb, options.cancel.as_ref()) else {
+        return Err(crate::ArchiverError::Cancelled.into());
+    };
+    let threads = lease.threads();
 
     // ---------------- Adaptive AutoTuner initialization -----------------
-    // Initialize AutoTuner with memory budget
-    let memory_budget = mem_budget_mb
-        .map(|mb| mb as usize * 1024 * 1024)  // Convert MiB to bytes
-        .unwrap_or_else(|| {
-            // Default: 70% of available system memory
-            use sysinfo::System;
-            let mut sys = System::new();
-            sys.refresh_memory();
-            (sys.total_memory() as f64 * 0.7) as usize
-        });
-    
-    let mut autotune = AutoTuner::new(memory_budget);
+    // Initialize AutoTuner with the leased memory budget
+    let mut autotune = AutoTuner::with_budget_mb(Some(lease.memory_mb()));
     
     // Get initial configuration
     // Получаем конфигурацию от AutoTune
@@ -163,9 +1515,35 @@ where
         codec_threads = current_config.codec_threads as u32;
     }
     
-    // Use passed compression level or fall back to AutoTune's recommendation
+    // Use passed compression level or fall back to AutoTune's recommendation,
+    // which --retune then adjusts from the shards' ratio and throughput. Not with
+    // --adapt, and not with --rsync-friendly: frame cuts must not depend on timing
+    let auto_level = options.retune && compression_level.is_none() && options.adapt.is_none() && !options.rsync_friendly;
     let compression_level = compression_level.unwrap_or(current_config.compression_level);
     
+    // Validate expert zstd parameters up front – workers can only panic on failure
+    let zstd_params: &[zstd::stream::raw::CParameter] = &options.zstd_params;
+    if options.seekable_frames == Some(0) {
+        return Err("seekable frames need a size of at least one byte".into());
+    }
+    // --rsync-friendly режет фреймы по содержимому: фиксированный размер сдвигал бы границы
+    let seekable_frames =
+        options.seekable_frames.or((!options.rsync_friendly).then_some(crate::katana::DEFAULT_FRAME_SIZE));
+    {
+        let mut probe = zstd::Encoder::new(Vec::new(), compression_level)?;
+        for p in zstd_params {
+            probe
+                .set_parameter(*p)
+                .map_err(|e| format!("Invalid zstd parameter {:?}: {}", p, e))?;
+        }
+    }
+
+    // --adapt: уровень, с которого стартуют шарды (и последний выбранный – для прогресса)
+    let adapt_level = Arc::new(std::sync::atomic::AtomicI32::new(
+        options.adapt.map_or(compression_level, |r| compression_level.clamp(r.min, r.max)),
+    ));
+    let output_busy = Arc::new(OutputBusy::default());
+
     // Clone config before rayon::scope to avoid borrowing issues
     let config_clone = current_config.clone();
     
@@ -179,49 +1557,281 @@ where
     if password.is_some() {
         /* fallback удалён – теперь поддерживаем потоковое шифрование напрямую */
     }
+    if options.hide_names && password.is_none() {
+        return Err("--hide-names requires a password".into());
+    }
     // --- High-level stats ---
     // Ключ/соль
 let (key_opt, salt_opt) = if let Some(ref pwd) = password {
-    let salt = crate::crypto::generate_salt();
-    let key = crate::crypto::derive_key_argon2(pwd, &salt);
+    let salt = options.salt.map_or_else(crate::crypto::generate_salt, |s| s.to_vec());
+    let key = options.archive_key.unwrap_or_else(|| crate::crypto::derive_key_argon2(pwd, &salt));
     (Some(Arc::new(key)), Some(salt))
 } else {
     (None, None)
 };
 let start_ts = Instant::now();
     // 1. Собрать список файлов
-    let mut files = Vec::new();
-    for path in inputs {
-        if path.is_file() {
-            files.push(path.clone());
-        } else if path.is_dir() {
-            for entry in WalkDir::new(path) {
-                let entry = entry?;
-                if entry.file_type().is_file() {
-                    files.push(entry.path().to_path_buf());
+    let os_fs = crate::vfs::OsFs { symlinks: options.symlinks, io: options.io };
+    let vfs: &dyn crate::vfs::Vfs = options.vfs.as_deref().unwrap_or(&os_fs);
+    let (mut files, skipped_by_size) = collect_files(vfs, inputs, output_path, options)?;
+
+    if files.is_empty() {
+        return Err("No input files".into());
+    }
+
+    // Determine common ancestor directory for all inputs
+    let base_dir: Arc<PathBuf> = Arc::new(crate::katana::common_parent(inputs));
+    let source_fingerprint = fingerprint_files(vfs, &base_dir, &files)?;
+
+    // Расширенные атрибуты (--xattrs) собираем заранее, по имени записи
+    let mut xattrs_by_name: std::collections::HashMap<String, crate::fsx::Xattrs> = std::collections::HashMap::new();
+    if options.xattrs {
+        for path in &files {
+            let attrs = vfs.xattrs(path)?;
+            if !attrs.is_empty() {
+                xattrs_by_name.insert(entry_name(&base_dir, path), attrs);
+            }
+        }
+    }
+
+    // Символические ссылки (--symlinks keep) хранятся без данных, прямо в индексе
+    let mut symlinks: Vec<FileEntry> = Vec::new();
+    let mut regular = Vec::with_capacity(files.len());
+    for path in files {
+        let Some(target) = vfs.read_link(&path)? else {
+            regular.push(path);
+            continue;
+        };
+        let meta = vfs.metadata(&path)?;
+        let rel_path = match path.strip_prefix(base_dir.as_path()) {
+            Ok(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => path.to_path_buf(),
+        };
+        symlinks.push(FileEntry {
+            path: crate::katana::normalize_path(&rel_path.to_string_lossy()),
+            size: 0,
+            offset: 0,
+            permissions: meta.permissions,
+            inline: None,
+            mtime: meta.mtime,
+            btime: meta.btime,
+            owner: meta.owner,
+            base_ref: false,
+            symlink: Some(target.to_string_lossy().into_owned()),
+            duplicate_of: None,
+            xattrs: Default::default(),
+            chunks: Vec::new(),
+            nested_archive: false,
+            sparse: Vec::new(),
+        });
+    }
+    files = regular;
+    if !symlinks.is_empty() {
+        println!("[katana] Storing {} symbolic links", symlinks.len());
+    }
+
+    // Инкрементальный режим: неизменённые файлы – ссылки на базовый архив
+    let mut base_refs: Vec<FileEntry> = Vec::new();
+    let base_link = match &options.base {
+        Some(base) => {
+            let snapshot = crate::katana::BaseSnapshot::load(base, output_path, password.as_deref())?;
+            let mut changed = Vec::with_capacity(files.len());
+            for path in files {
+                let meta = vfs.metadata(&path)?;
+                let rel_path = match path.strip_prefix(base_dir.as_path()) {
+                    Ok(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+                    _ => path.to_path_buf(),
+                };
+                let rel_path = crate::katana::normalize_path(&rel_path.to_string_lossy());
+                if snapshot.unchanged(&rel_path, meta.len, meta.mtime, meta.permissions) {
+                    base_refs.push(FileEntry {
+                        path: rel_path,
+                        size: meta.len,
+                        offset: 0,
+                        permissions: meta.permissions,
+                        inline: None,
+                        mtime: meta.mtime,
+                        btime: meta.btime,
+                        owner: meta.owner,
+                        base_ref: true,
+                        symlink: None,
+                        duplicate_of: None,
+                        xattrs: Default::default(),
+                        chunks: Vec::new(),
+                        nested_archive: false,
+                        sparse: Vec::new(),
+                    });
+                } else {
+                    changed.push(path);
                 }
             }
+            files = changed;
+            println!(
+                "[katana] Incremental: {} unchanged files referenced from {}, {} new or changed",
+                base_refs.len(),
+                base.display(),
+                files.len()
+            );
+            Some(snapshot.link)
         }
+        None => None,
+    };
+
+    // Дубликаты (--dedup): данные хранит первый из одинаковых файлов
+    let mut duplicates: Vec<(PathBuf, FileEntry)> = Vec::new();
+    let mut dedup_check = DedupCheck::default();
+    if options.dedup {
+        let pairs = find_duplicates(vfs, &files)?;
+        let rel_name = |path: &Path| entry_name(&base_dir, path);
+        let mut is_dup = vec![false; files.len()];
+        for (dup, orig, hash) in pairs {
+            let meta = vfs.metadata(&files[dup])?;
+            is_dup[dup] = true;
+            dedup_check.expected.insert(files[orig].clone(), hash);
+            dedup_check.originals.insert(files[dup].clone(), files[orig].clone());
+            duplicates.push((
+                files[dup].clone(),
+                FileEntry {
+                    path: rel_name(&files[dup]),
+                    size: meta.len,
+                    offset: 0,
+                    permissions: meta.permissions,
+                    inline: None,
+                    mtime: meta.mtime,
+                    btime: meta.btime,
+                    owner: meta.owner,
+                    base_ref: false,
+                    symlink: None,
+                    duplicate_of: Some(rel_name(&files[orig])),
+                    xattrs: Default::default(),
+                    chunks: Vec::new(),
+                    nested_archive: false,
+                    sparse: Vec::new(),
+                },
+            ));
+        }
+        files = files.into_iter().zip(is_dup).filter(|(_, d)| !d).map(|(p, _)| p).collect();
+        println!(
+            "[katana] Dedup: {} duplicate files ({:.2} MiB) stored as references",
+            duplicates.len(),
+            duplicates.iter().map(|(_, e)| e.size).sum::<u64>() as f64 / (1024.0 * 1024.0)
+        );
     }
 
-    if files.is_empty() {
-        return Err("No input files".into());
+    // Крошечные файлы – прямо в индекс (не для зашифрованных архивов)
+    let mut inline_files: Vec<FileEntry> = Vec::new();
+    if options.inline_small_files && password.is_some() {
+        eprintln!("[katana] Small-file inlining is disabled for encrypted archives");
+    } else if options.inline_small_files {
+        let mut sharded = Vec::with_capacity(files.len());
+        for path in files {
+            let meta = vfs.metadata(&path)?;
+            if meta.len >= crate::katana::INLINE_MAX_SIZE {
+                sharded.push(path);
+                continue;
+            }
+            let mut data = Vec::with_capacity(meta.len as usize);
+            vfs.open(&path)?.read_to_end(&mut data)?;
+            let mut hasher = dedup_check.hasher(&path);
+            if let Some(h) = hasher.as_mut() {
+                h.update(&data);
+            }
+            dedup_check.finish(&path, hasher);
+            let rel_path = match path.strip_prefix(base_dir.as_path()) {
+                Ok(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+                _ => path.to_path_buf(),
+            };
+            inline_files.push(FileEntry {
+                path: crate::katana::normalize_path(&rel_path.to_string_lossy()),
+                size: data.len() as u64,
+                offset: 0,
+                permissions: meta.permissions,
+                inline: Some(data),
+                mtime: meta.mtime,
+                btime: meta.btime,
+                owner: meta.owner,
+                base_ref: false,
+                symlink: None,
+                duplicate_of: None,
+                xattrs: Default::default(),
+                chunks: Vec::new(),
+                nested_archive: false,
+                sparse: Vec::new(),
+            });
+        }
+        files = sharded;
+        println!("[katana] Inlined {} small files into the index", inline_files.len());
+    }
+
+    let workers = if threads == 0 { num_cpus::get() } else { threads }.max(1);
+    let io = options.io_limits.resolve(
+        workers,
+        autotune.current_bottleneck(),
+        inputs.iter().any(|p| crate::fsx::is_network_fs(p)),
+    );
+    println!("[AutoTune] IO caps: reads={}, compressions={}, writes={}", io.reads, io.compressions, io.writes);
+    // Дедупликация по чанкам (--dedup=chunks): файлы от MIN_CHUNK – в хранилище чанков
+    let mut chunked = Vec::new();
+    if options.chunk_dedup {
+        let mut sharded = Vec::with_capacity(files.len());
+        for path in files {
+            if vfs.metadata(&path)?.len >= crate::cdc::MIN_CHUNK as u64 {
+                chunked.push(path);
+            } else {
+                sharded.push(path);
+            }
+        }
+        files = sharded;
     }
 
-     let num_shards = if threads == 0 { num_cpus::get() } else { threads }.max(1);
+    // 2. Разбить файлы на шарды
+    let (file_chunks, pinned_shards, stored_shards) = if files.is_empty() {
+        (Vec::new(), 0, 0) // всё уместилось в индекс
+    } else {
+        plan_shards(vfs, &files, &base_dir, workers, options)?
+    };
+    let num_shards = file_chunks.len();
     println!(
         "[katana] Compressing {} files with {} shards → {}",
         files.len(), num_shards, output_path.display()
     );
 
-    // 2. Разбить файлы на шарды
-    // Determine base directory for relative paths
-    // Determine common ancestor directory for all inputs
-    let base_dir: Arc<PathBuf> = Arc::new(crate::katana::common_parent(inputs));
+    let mut chunk_store: Option<ChunkStoreOut> = None;
+    if !chunked.is_empty() {
+        let store = build_chunk_store(
+            vfs,
+            &chunked,
+            &base_dir,
+            key_opt.as_deref(),
+            // Подключи шардов: key_id_base + shard_id < key_id_base + num_shards
+            options.key_id_base + num_shards as u64,
+            compression_level,
+            zstd_params,
+            codec_threads.max(workers as u32),
+            options.time_budget.as_deref(),
+            &dedup_check,
+        )?;
+        check_create_cancelled(options, output_path, false)?;
+        let total: u64 = store.entries.iter().map(|e| e.size).sum();
+        println!(
+            "[katana] Dedup: {} files ({:.2} MiB) chunked, {} unique chunks ({:.2} MiB) → {:.2} MiB stored",
+            store.entries.len(),
+            total as f64 / (1024.0 * 1024.0),
+            store.chunk_sizes.len(),
+            store.info.uncompressed_size as f64 / (1024.0 * 1024.0),
+            store.info.compressed_size as f64 / (1024.0 * 1024.0)
+        );
+        chunk_store = Some(store);
+    }
 
-    let file_chunks: Vec<Vec<PathBuf>> = split_even(&files, num_shards);
+    // Дальше тюнер ведёт уровень сжатия по отчётам шардов
+    let level_tuning = auto_level.then(|| Arc::new(crate::autotune::LevelTuning::new(autotune, compression_level).with_codec_threads(codec_threads)));
 
-    // 3. Выходной файл откроем позже, после завершения всех воркеров
+    let read_permits = PagePool::new(io.reads as u64);
+    let compress_permits = PagePool::new(io.compressions as u64);
+    let write_permits = PagePool::new(io.writes as u64);
+
+    // 3. Выходной файл откроем позже, с первым готовым шардом
 
     // 4. Каналы для обмена
     let (tx, rx): (Sender<ShardMsg>, Receiver<ShardMsg>) = bounded(MAX_INFLIGHT);
@@ -231,6 +1841,8 @@ let start_ts = Instant::now();
     let mut index_files: Vec<FileEntry> = Vec::new();
     // Temporary storage to keep deterministic order
     let mut shard_infos: Vec<Option<ShardInfo>> = vec![None; num_shards];
+    // Transient write failures (SMB/NFS) retried while writing the output
+    let mut write_retries: u64 = 0;
     let mut files_by_shard: Vec<Option<Vec<FileEntry>>> = vec![None; num_shards];
     
     // Progress tracking state
@@ -239,24 +1851,55 @@ let start_ts = Instant::now();
     let mut processed_files = 0;
     let mut processed_bytes = 0u64;
     let total_bytes: u64 = files.iter()
-        .map(|f| std::fs::metadata(f).map(|m| m.len()).unwrap_or(0))
+        .map(|f| vfs.metadata(f).map(|m| m.len).unwrap_or(0))
         .sum();
     
 
+    // Отмена: воркеры проверяют токен между файлами и буферами
+    let cancelled = || options.cancel.as_ref().is_some_and(|c| c.is_cancelled());
+    let mut output_started = false;
+    let mut shard_failure: Option<String> = None;
+
     // 6. Параллельное сжатие – каждый воркер пишет в temp-файл
-    rayon::scope(|s| {
+    let dedup_check = &dedup_check;
+    lease.install(|| rayon::scope(|s| {
         // workers
         for (shard_id, chunk) in file_chunks.into_iter().enumerate() {
             let key_clone = key_opt.clone();
             let tx = tx.clone();
             let base_dir: Arc<PathBuf> = Arc::clone(&base_dir);
+            let ordering = options.ordering.clone();
+            let key_id = options.key_id_base + shard_id as u64;
+            let time_budget = options.time_budget.clone();
+            let read_permits = Arc::clone(&read_permits);
+            let compress_permits = Arc::clone(&compress_permits);
+            let write_permits = Arc::clone(&write_permits);
+            let adapt_level = Arc::clone(&adapt_level);
+            let output_busy = Arc::clone(&output_busy);
+            let level_tuning = level_tuning.clone();
             s.spawn(move |_| {
+                // Не больше --max-compressions шардов сжимаются одновременно
+                compress_permits.acquire(1);
+                let _compress_permit = scopeguard::guard((), |_| compress_permits.release(1));
+                // Pin to a NUMA node (no-op unless --numa auto); restored on drop
+                let _affinity = crate::numa::pin_worker(shard_id);
+                // Порядок файлов внутри шарда (похожие данные рядом ⇒ лучше матчи zstd)
+                let mut chunk = chunk;
+                if let Some(ref ordering) = ordering.filter(|_| shard_id >= pinned_shards) {
+                    ordering.order(&mut chunk, vfs);
+                }
+                // Вложенный архив (--store-nested) пишется как есть, как и всё после
+                // того, как --retune признал данные несжимаемыми
+                let nested = shard_id >= num_shards - stored_shards;
+                let stored = nested || level_tuning.as_ref().is_some_and(|t| t.store());
                 // Временный файл для сжатого выхода этого шарда
-                let mut tmp = NamedTempFile::new().expect("tmp");
+                let mut tmp = crate::temp_manager::temp_file("shard").expect("tmp");
                 let tmp_path = tmp.path().to_path_buf();
 
-                let mut outfile = tmp.as_file_mut();
+                let mut outfile = CappedWriter { inner: crate::fsx::IoWriter::new(tmp.as_file_mut(), options.io.mode), permits: write_permits };
                 let mut nonce_opt: Option<[u8; 12]> = None;
+                let frames: Vec<(u64, u64)>;
+                let level: i32; // итоговый уровень (--adapt мог его сдвинуть)
                 let mut uncompressed: u64 = 0;
                 let mut local_files: Vec<FileEntry> = Vec::new();
 
@@ -265,76 +1908,135 @@ let start_ts = Instant::now();
                     let mut nonce = [0u8; 12];
                     OsRng.fill_bytes(&mut nonce);
                     nonce_opt = Some(nonce);
-                    let mut sink = EncryptSink::new(&mut outfile, &*key_arc, nonce);
+                    let shard_key = crate::crypto::derive_shard_key(key_arc, key_id);
+                    let mut sink = EncryptSink::new(&mut outfile, &shard_key, nonce);
                     let zstd_threads: u32 = codec_threads; // 0 ⇒ однопоточный zstd
                     {
-                        let mut encoder = zstd::Encoder::new(&mut sink, compression_level).expect("enc");
-                        encoder.include_checksum(true).expect("chk");
-                        if zstd_threads > 1 {
-                            encoder.multithread(zstd_threads).expect("mt");
-                        }
+                        let mut encoder =
+                            ShardEncoder::new(&mut sink, compression_level, zstd_params, zstd_threads, seekable_frames)
+                                .adaptive(options.adapt, &adapt_level, &output_busy)
+                            .tuned(level_tuning.as_ref().filter(|_| !stored))
+                            .rsync_friendly(options.rsync_friendly)
+                            .frame_per_file(options.shard_strategy == ShardStrategy::PerFile)
+                            .dictionary(options.dictionary.as_ref())
+                            .stored(stored);
                         let mut in_buf = vec![0u8; config_clone.input_buffer_size]; // Adaptive buffer
-                        for path in &chunk {
-                            let mut f = File::open(path).expect("open");
-                            let meta = f.metadata().expect("meta");
+                        for (i, path) in chunk.iter().enumerate() {
+                            // Time budget exhausted: leave the rest for a resumed run
+                            if let Some(budget) = time_budget.as_ref().filter(|b| b.expired()) {
+                                budget.defer(&chunk[i..]);
+                                break;
+                            }
+                            if cancelled() {
+                                break;
+                            }
+                            let mut f = vfs.open_sequential(path).expect("open");
+                            let meta = vfs.metadata(path).expect("meta");
+                            let sparse = sparse_map(vfs, path, meta.len);
                             let rel_path = match path.strip_prefix(base_dir.as_path()) {
                                 Ok(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
                                 _ => path.to_path_buf(),
                             };
                             let normalized_path = crate::katana::normalize_path(&rel_path.to_string_lossy());
+                            let mut hasher = dedup_check.hasher(path);
                             local_files.push(FileEntry {
                                 path: normalized_path,
-                                size: meta.len(),
+                                size: meta.len,
                                 offset: uncompressed,
-                                permissions: {
-                                    #[cfg(unix)] { crate::fsx::maybe_unix_mode(&meta) }
-                                    #[cfg(not(unix))] { None }
-                                },
+                                permissions: meta.permissions,
+                                inline: None,
+                                mtime: meta.mtime,
+                                btime: meta.btime,
+                                owner: meta.owner,
+                                base_ref: false,
+                                symlink: None,
+                                duplicate_of: None,
+                                xattrs: Default::default(),
+                                chunks: Vec::new(),
+                                nested_archive: nested,
+                                sparse,
                             });
                             loop {
-                                let rd = f.read(&mut in_buf).expect("read");
-                                if rd == 0 { break; }
+                                let rd = capped_read(&mut f, &mut in_buf, &read_permits).expect("read");
+                                if rd == 0 || cancelled() { break; }
                                 uncompressed += rd as u64;
+                                if let Some(h) = hasher.as_mut() {
+                                    h.update(&in_buf[..rd]);
+                                }
                                 encoder.write_all(&in_buf[..rd]).expect("enc write");
                             }
+                            dedup_check.finish(path, hasher);
+                            encoder.end_file().expect("enc write");
                         }
-                        encoder.finish().expect("finish");
+                        level = encoder.level;
+                        frames = encoder.finish().expect("finish").1;
                     }
                     // finalize encryption tag
                     let (_n, _bytes) = sink.finalize().expect("finalize");
                 } else {
                     let zstd_threads: u32 = codec_threads; // 0 ⇒ однопоточный zstd
-                    let mut encoder = zstd::Encoder::new(&mut outfile, compression_level).expect("enc");
-                    encoder.include_checksum(true).expect("chk");
-                    if zstd_threads > 1 {
-                            encoder.multithread(zstd_threads).expect("mt");
-                        }
+                    let mut encoder =
+                        ShardEncoder::new(&mut outfile, compression_level, zstd_params, zstd_threads, seekable_frames)
+                            .adaptive(options.adapt, &adapt_level, &output_busy)
+                            .tuned(level_tuning.as_ref().filter(|_| !stored))
+                            .rsync_friendly(options.rsync_friendly)
+                            .frame_per_file(options.shard_strategy == ShardStrategy::PerFile)
+                            .dictionary(options.dictionary.as_ref())
+                            .stored(stored);
                     let mut in_buf = vec![0u8; config_clone.input_buffer_size]; // Adaptive buffer
-                    for path in &chunk {
-                        let mut f = File::open(path).expect("open");
-                        let meta = f.metadata().expect("meta");
+                    for (i, path) in chunk.iter().enumerate() {
+                        // Time budget exhausted: leave the rest for a resumed run
+                        if let Some(budget) = time_budget.as_ref().filter(|b| b.expired()) {
+                            budget.defer(&chunk[i..]);
+                            break;
+                        }
+                        if cancelled() {
+                            break;
+                        }
+                        let mut f = vfs.open_sequential(path).expect("open");
+                        let meta = vfs.metadata(path).expect("meta");
+                        let sparse = sparse_map(vfs, path, meta.len);
                         let rel_path = match path.strip_prefix(base_dir.as_path()) {
                             Ok(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
                             _ => path.to_path_buf(),
                         };
                         let normalized_path = crate::katana::normalize_path(&rel_path.to_string_lossy());
+                        let mut hasher = dedup_check.hasher(path);
                         local_files.push(FileEntry {
                             path: normalized_path,
-                            size: meta.len(),
+                            size: meta.len,
                             offset: uncompressed,
-                            permissions: {
-                                #[cfg(unix)] { crate::fsx::maybe_unix_mode(&meta) }
-                                #[cfg(not(unix))] { None }
-                            },
+                            permissions: meta.permissions,
+                            inline: None,
+                            mtime: meta.mtime,
+                            btime: meta.btime,
+                            owner: meta.owner,
+                            base_ref: false,
+                            symlink: None,
+                            duplicate_of: None,
+                            xattrs: Default::default(),
+                            chunks: Vec::new(),
+                            nested_archive: nested,
+                            sparse,
                         });
                         loop {
-                            let rd = f.read(&mut in_buf).expect("read");
-                            if rd == 0 { break; }
+                            let rd = capped_read(&mut f, &mut in_buf, &read_permits).expect("read");
+                            if rd == 0 || cancelled() { break; }
                             uncompressed += rd as u64;
+                            if let Some(h) = hasher.as_mut() {
+                                h.update(&in_buf[..rd]);
+                            }
                             encoder.write_all(&in_buf[..rd]).expect("enc write");
                         }
+                        dedup_check.finish(path, hasher);
+                        encoder.end_file().expect("enc write");
                     }
-                    encoder.finish().expect("finish");
+                    level = encoder.level;
+                    frames = encoder.finish().expect("finish").1;
+                }
+                if let Err(error) = outfile.inner.finish() {
+                    tx.send(ShardMsg::Failed { shard_id, error }).expect("send");
+                    return;
                 }
                 let temp_path: TempPath = tmp.into_temp_path();
                 let compressed = std::fs::metadata(&temp_path).expect("meta").len();
@@ -346,14 +2048,26 @@ let start_ts = Instant::now();
                     uncompressed,
                     files: local_files,
                     nonce: nonce_opt,
+                    frames,
+                    level,
+                    stored,
                 }).expect("send");
             });
         }
         drop(tx);
 
-        // coordinator – собирает данные от воркеров
-        let mut pending: Vec<Option<(TempPath, u64, u64, Vec<FileEntry>, Option<[u8; 12]>)>> = (0..num_shards).map(|_| None).collect();
+        // coordinator – собирает данные от воркеров и пишет шарды по порядку, как только
+        // готов очередной: выход работает параллельно со сжатием, и --adapt видит его темп
+        let mut pending: Vec<Option<(TempPath, u64, u64, Vec<FileEntry>, Option<[u8; 12]>, Vec<(u64, u64)>, crate::katana::ShardStats)>> = (0..num_shards).map(|_| None).collect();
+        let mut out_file: Option<ArchiveOutput> = None;
+        let mut sink_slot = Some(&mut sink_out); // выход открывается с первым готовым шардом
+        let mut next_sid = 0;
         while let Ok(msg) = rx.recv() {
+            // Шард после сбоя уже не запишется; остальные воркеры дорабатывают, их temp-файлы удалятся
+            if let ShardMsg::Failed { shard_id, error } = msg {
+                shard_failure.get_or_insert_with(|| format!("Failed to write shard {shard_id}: {error}"));
+                continue;
+            }
              let ShardMsg::Done {
                  shard_id,
                  tmp_path,
@@ -361,7 +2075,10 @@ let start_ts = Instant::now();
                  uncompressed,
                  files,
                  nonce,
-             } = msg;
+                 frames,
+                 level,
+                 stored,
+             } = msg else { unreachable!("failures handled above") };
             {
                 // Update progress tracking (capture file count before moving)
                 let file_count = files.len();
@@ -369,7 +2086,12 @@ let start_ts = Instant::now();
                 processed_files += file_count;
                 processed_bytes += uncompressed;
                 
-                pending[shard_id] = Some((tmp_path, compressed, uncompressed, files, nonce));
+                let stats = crate::katana::ShardStats {
+                    level,
+                    decode_cost: crate::katana::ShardStats::estimate_decode_cost(compressed, uncompressed, stored, nonce.is_some()),
+                    stored,
+                };
+                pending[shard_id] = Some((tmp_path, compressed, uncompressed, files, nonce, frames, stats));
                 
                 // Call progress callback if provided
                 if let Some(ref callback) = progress_callback {
@@ -391,67 +2113,96 @@ let start_ts = Instant::now();
                         completed_shards: completed_shards as u32,
                         total_shards: num_shards as u32,
                         elapsed_time: elapsed,
+                        compression_level: match &level_tuning {
+                            Some(tuning) => Some(tuning.level()),
+                            None => options.adapt.map(|_| adapt_level.load(std::sync::atomic::Ordering::Relaxed)),
+                        },
                     };
                     
                     callback(progress_state);
                 }
             }
-        }
-        // Все shard'ы готовы – копируем в порядке shard_id
-        for sid in 0..num_shards {
-            if let Some((path, comp_size, uncomp_size, files, nonce)) = pending[sid].take() {
-                // Открываем выходной файл в режиме append
-                let mut out_file = OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .read(true)
-                    .open(output_path)
-                    .expect("open output for append");
-                let offset = out_file.seek(SeekFrom::End(0)).expect("seek end");
-                let mut tf = File::open(&path).expect("open temp shard");
-                {
-                    // large buffered copy (8 MiB)
-                    let mut buf = vec![0u8; 8 * 1024 * 1024];
-                    loop {
-                        let n = tf.read(&mut buf).expect("read shard temp");
-                        if n == 0 {
+            // Готовые шарды подряд – сразу в выход, пока остальные ещё сжимаются
+            while next_sid < num_shards && pending[next_sid].is_some() && shard_failure.is_none() && !cancelled() {
+                let sid = next_sid;
+                next_sid += 1;
+                let (path, comp_size, uncomp_size, files, nonce, frames, stats) = pending[sid].take().expect("ready shard");
+                if out_file.is_none() {
+                    output_started = true;
+                    let sink = sink_slot.take().expect("output opened once");
+                    match ArchiveOutput::open(output_path, sink) {
+                        Ok(opened) => out_file = Some(opened),
+                        Err(error) => {
+                            shard_failure = Some(format!("Failed to open output: {error}"));
                             break;
                         }
-                        out_file.write_all(&buf[..n]).expect("write shard");
                     }
                 }
-
-                // Посчитаем CRC32 сжатого шарда
-                let mut crc32 = crc32fast::Hasher::new();
-                {
-                    let mut tf_verify = File::open(&path).expect("open shard for crc");
-                    let mut buf_crc = vec![0u8; 8 * 1024 * 1024];
+                let out_file = out_file.as_mut().expect("output just opened");
+                let offset = out_file.committed_len();
+                // Контрольная сумма сжатого шарда – по ходу копирования
+                let mut digest = crate::katana::ShardDigest::new(options.shard_checksum);
+                // large buffered copy (8 MiB); ошибка ввода-вывода останавливает запись, воркеры дорабатывают
+                let copied = File::open(&path).and_then(|mut tf| {
+                    let mut buf = vec![0u8; 8 * 1024 * 1024];
                     loop {
-                        let n = tf_verify.read(&mut buf_crc).expect("read for crc");
-                        if n == 0 { break; }
-                        crc32.update(&buf_crc[..n]);
+                        let n = tf.read(&mut buf)?;
+                        if n == 0 {
+                            return Ok(());
+                        }
+                        digest.update(&buf[..n]);
+                        let started = Instant::now();
+                        out_file.append(&buf[..n])?;
+                        output_busy.record(started.elapsed());
                     }
+                });
+                if let Err(error) = copied {
+                    shard_failure = Some(format!("Failed to write shard {sid}: {error}"));
+                    break;
                 }
+                let (crc32, xxh3) = digest.finish();
                 shard_infos[sid] = Some(ShardInfo {
                     offset: offset as u64,
                     compressed_size: comp_size,
                     uncompressed_size: uncomp_size,
                     file_count: files.len(),
-                    crc32: crc32.finalize(),
+                    crc32,
+                    xxh3,
                     nonce: nonce,
+                    // каждый зашифрованный шард – свой подключ с id = key_id_base + shard_id
+                    key_id: nonce.map(|_| options.key_id_base + sid as u64),
+                    frames,
+                    dictionary: options.dictionary.as_ref().map(|d| d.id().to_string()),
+                    stats: Some(stats),
                 });
 
                 files_by_shard[sid] = Some(files);
             }
         }
+        // Отменено – недописанные шарды выбрасываем вместе с temp-файлами
+        if cancelled() {
+            return;
+        }
+        if let Some(out_file) = &out_file {
+            write_retries += out_file.retries();
+        }
 
-    });
+    }));
+
+    check_create_cancelled(options, output_path, output_started)?;
+    if let Some(error) = shard_failure {
+        return Err(error.into());
+    }
 
     // Consolidate shards in order
+    let mut ordering_export = Vec::new();
     for sid in 0..num_shards {
         if let Some(info) = shard_infos[sid].take() {
             index_shards.push(info);
             if let Some(files) = files_by_shard[sid].take() {
+                if options.export_ordering.is_some() {
+                    ordering_export.push(files.iter().map(|f| f.path.clone()).collect());
+                }
                 index_files.extend(files);
             }
         }
@@ -468,9 +2219,131 @@ let start_ts = Instant::now();
         salt: Option<[u8;16]>,
         shards: Vec<ShardInfo>,
         files: Vec<FileEntry>,
+        #[serde(default, skip_serializing_if = "crate::katana::is_zero")]
+        features: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        base: Option<crate::katana::BaseLink>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        chunk_store: Option<ShardInfo>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        chunk_sizes: Vec<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source_fingerprint: Option<String>,
+        #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+        extensions: std::collections::BTreeMap<String, crate::katana::IndexExtension>,
+    }
+
+    // Хранилище чанков – сразу за шардами
+    let mut chunk_sizes = Vec::new();
+    let mut chunked_files = Vec::new();
+    let chunk_store_info = match chunk_store {
+        Some(store) => {
+            output_started = true;
+            let mut out_file = ArchiveOutput::open(output_path, &mut sink_out)?;
+            let offset = out_file.committed_len();
+            let mut stored = File::open(&store.tmp_path)?;
+            let mut digest = crate::katana::ShardDigest::new(options.shard_checksum);
+            let mut buf = vec![0u8; 8 * 1024 * 1024];
+            loop {
+                let n = stored.read(&mut buf)?;
+                if n == 0 { break; }
+                digest.update(&buf[..n]);
+                out_file.append(&buf[..n])?;
+            }
+            write_retries += out_file.retries();
+            chunk_sizes = store.chunk_sizes;
+            chunked_files = store.entries;
+            let (crc32, xxh3) = digest.finish();
+            Some(ShardInfo { offset, crc32, xxh3, ..store.info })
+        }
+        None => None,
+    };
+    // Файлы, опустевшие после листинга, – к inline-записям
+    let (emptied, chunked_files): (Vec<FileEntry>, Vec<FileEntry>) =
+        chunked_files.into_iter().partition(|e| e.chunks.is_empty());
+    inline_files.extend(emptied);
+
+    // Clock skew on the source machine shows up as mtimes in the future
+    let now = crate::fsx::now_secs();
+    let skewed = index_files
+        .iter()
+        .chain(inline_files.iter())
+        .chain(chunked_files.iter())
+        .filter(|e| e.mtime.is_some_and(|m| crate::katana::check_mtime(m, now).is_some()))
+        .count();
+    if skewed > 0 {
+        eprintln!(
+            "[katana] ⚠️  {} file(s) have absurd modification times (clock skew?); they will be clamped on extract",
+            skewed
+        );
+    }
+
+    // Inlined files follow the entries of the last shard
+    let mut features = if inline_files.is_empty() { 0 } else { crate::katana::FEATURE_INLINE_SMALL };
+    if key_opt.is_some() {
+        features |= crate::katana::FEATURE_SHARD_SUBKEYS;
+        if options.hide_names {
+            features |= crate::katana::FEATURE_HIDDEN_NAMES;
+        }
+    }
+    index_files.extend(inline_files);
+    if chunk_store_info.is_some() {
+        features |= crate::katana::FEATURE_CHUNKS;
+    }
+    if index_shards.iter().chain(chunk_store_info.iter()).any(|s| s.xxh3.is_some()) {
+        features |= crate::katana::FEATURE_XXH3;
+    }
+    if index_shards.iter().any(|s| s.dictionary.is_some()) {
+        features |= crate::katana::FEATURE_DICTIONARY;
+    }
+    index_files.extend(chunked_files);
+    if !symlinks.is_empty() {
+        features |= crate::katana::FEATURE_SYMLINKS;
+    }
+    index_files.extend(symlinks);
+    // Оригинал, изменившийся после поиска дубликатов, хранит уже другие байты –
+    // ссылки на него были бы неверны
+    let (kept, stale): (Vec<_>, Vec<_>) = duplicates.into_iter().partition(|(path, _)| !dedup_check.is_stale(path));
+    duplicates = kept;
+    for (path, entry) in stale {
+        crate::warnings::warn(
+            crate::warnings::WarningKind::SkippedFile,
+            entry.path.clone(),
+            format!(
+                "{} changed while being archived; its duplicate {} was left out – archive again to include it",
+                entry.duplicate_of.as_deref().unwrap_or_default(),
+                path.display()
+            ),
+        );
+    }
+    // Оригинал, отложенный из-за --max-duration, тянет за собой свои дубликаты
+    if let Some(budget) = options.time_budget.as_ref() {
+        let stored: std::collections::HashSet<&str> = index_files.iter().map(|e| e.path.as_str()).collect();
+        let (kept, deferred): (Vec<_>, Vec<_>) = duplicates
+            .into_iter()
+            .partition(|(_, e)| e.duplicate_of.as_deref().is_some_and(|o| stored.contains(o)));
+        budget.defer(&deferred.into_iter().map(|(p, _)| p).collect::<Vec<_>>());
+        duplicates = kept;
+    }
+    if !duplicates.is_empty() {
+        features |= crate::katana::FEATURE_DUPLICATES;
+    }
+    index_files.extend(duplicates.into_iter().map(|(_, e)| e));
+    // Base references come last
+    if !base_refs.is_empty() {
+        features |= crate::katana::FEATURE_BASE_REFS;
+    }
+    index_files.extend(base_refs);
+    if !xattrs_by_name.is_empty() {
+        for entry in index_files.iter_mut().filter(|e| !e.base_ref && e.symlink.is_none()) {
+            if let Some(attrs) = xattrs_by_name.remove(&entry.path) {
+                entry.xattrs = attrs;
+                features |= crate::katana::FEATURE_XATTRS;
+            }
+        }
     }
 
-    let mut index = KatanaIndex {
+    let index = KatanaIndex {
         crc32: 0,
         hmac: None,
         salt: salt_opt.clone().map(|v| {
@@ -479,66 +2352,74 @@ let start_ts = Instant::now();
         }),
         shards: index_shards,
         files: index_files,
+        features,
+        base: base_link,
+        chunk_store: chunk_store_info,
+        chunk_sizes,
+        // Архив, урезанный по --max-duration, не отражает весь набор
+        source_fingerprint: options
+            .time_budget
+            .as_ref()
+            .is_none_or(|b| b.deferred().is_empty())
+            .then_some(source_fingerprint),
+        extensions: crate::katana::metadata_extension(&options.metadata)
+            .map(|ext| (crate::katana::METADATA_EXTENSION.to_string(), ext))
+            .into_iter()
+            .collect(),
     };
 
-    let index_json = serde_json::to_vec(&index)?;
-    // CRC32
-    let mut crc = Crc32Hasher::new();
-    crc.update(&index_json);
-    index.crc32 = crc.finalize();
-    // HMAC (если шифрование)
-    if let (Some(ref key_arc), Some(_)) = (key_opt, salt_opt.as_ref()) {
-        let mut mac = HmacSha256::new_from_slice(&key_arc[..]).expect("hmac new");
-        mac.update(&index_json);
-        let res = mac.finalize().into_bytes();
-        index.hmac = Some(res.into());
-    }
-    let index_json = serde_json::to_vec(&index)?;
-    let mut enc = zstd::Encoder::new(Vec::new(), 3)?;
-    enc.include_checksum(true).expect("chk");
-    enc.write_all(&index_json)?;
-    let index_comp = enc.finish()?;
+    let key = key_opt.as_ref().filter(|_| salt_opt.is_some()).map(|k| &**k);
+    let index_json = crate::katana::encode_signed_index(&index, options.index_format, features, key)?;
+    let mut index_comp = crate::katana::encode_index(&index_json, options.index_compression)?;
+    if let (true, Some(key), Some(salt)) = (options.hide_names, key_opt.as_ref(), index.salt) {
+        index_comp = crate::katana::seal_index(&index_comp, salt, key)?;
+    }
 
     let index_comp_size = index_comp.len() as u64;
+    check_create_cancelled(options, output_path, output_started)?;
 
         // Открываем файл для записи индекса и футера
-        let mut out_file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .append(true)
-            .open(output_path)?;
+        let mut out_file = ArchiveOutput::open(output_path, &mut sink_out)?;
     let index_json_size = index_json.len() as u64;
 
-    out_file.write_all(&index_comp)?;
-    out_file.write_all(&index_comp_size.to_le_bytes())?;
-    out_file.write_all(&index_json_size.to_le_bytes())?;
-    out_file.write_all(KATANA_MAGIC)?;
+    out_file.append(&index_comp)?;
+    out_file.append(&index_comp_size.to_le_bytes())?;
+    out_file.append(&index_json_size.to_le_bytes())?;
+    out_file.append(options.index_format.footer_magic())?;
 
     // --- Write footer (BLAKE3 over all previous bytes) -----------------
     use std::io::Seek;
-    let data_len = out_file.seek(SeekFrom::End(0))?; // длина данных без футера
-    out_file.flush()?; // гарантируем запись на диск, данные в page-cache
+    let data_len = out_file.committed_len(); // длина данных без футера
+    let hash = match &mut out_file {
+        ArchiveOutput::File(file) => {
+            file.file_mut().flush()?; // гарантируем запись на диск, данные в page-cache
 
-    // Рассчитываем хэш, читая из того же файла (page-cache ➜ почти бесплатно)
-    out_file.seek(SeekFrom::Start(0))?;
-    let mut hasher = blake3::Hasher::new();
-    let mut buf = [0u8; 8192];
-    loop {
-        let n = out_file.read(&mut buf)?;
-        if n == 0 { break; }
-        hasher.update(&buf[..n]);
-    }
-    let hash = hasher.finalize();
+            // Рассчитываем хэш, читая из того же файла (page-cache ➜ почти бесплатно)
+            file.file_mut().seek(SeekFrom::Start(0))?;
+            let mut hasher = blake3::Hasher::new();
+            let mut buf = [0u8; 8192];
+            loop {
+                let n = file.file_mut().read(&mut buf)?;
+                if n == 0 { break; }
+                hasher.update(&buf[..n]);
+            }
+            hasher.finalize()
+        }
+        ArchiveOutput::Sink(sink) => sink.hasher.finalize(),
+    };
 
-    // Вернуться в конец и дописать футер
-    out_file.seek(SeekFrom::Start(data_len))?;
-    out_file.write_all(FOOTER_MAGIC)?;
-    out_file.write_all(& (data_len as u64).to_le_bytes())?;
-    out_file.write_all(hash.as_bytes())?;
+    // Дописать футер (append ⇒ всегда в конец)
+    out_file.append(FOOTER_MAGIC)?;
+    out_file.append(& (data_len as u64).to_le_bytes())?;
+    out_file.append(hash.as_bytes())?;
+    write_retries += out_file.retries();
+    if let Some(sink) = &options.sink {
+        sink.finish()?;
+    }
 
     // --- Final stats & pretty log ---
     let total_comp_size: u64 = index_comp_size
-        + index.shards.iter().map(|s| s.compressed_size).sum::<u64>()
+        + index.shards.iter().chain(index.chunk_store.iter()).map(|s| s.compressed_size).sum::<u64>()
         + FOOTER_SIZE as u64;
     let total_uncomp_size: u64 = index.files.iter().map(|f| f.size).sum();
     let ratio = if total_comp_size > 0 {
@@ -562,6 +2443,14 @@ let start_ts = Instant::now();
         duration.as_secs_f64(),
         throughput,
     );
+    print_skipped_by_size(&skipped_by_size);
+    if write_retries > 0 {
+        println!("[katana] Recovered from {} transient write failure(s) on the output", write_retries);
+    }
+    if let Some(path) = &options.export_ordering {
+        crate::ordering::OrderingManifest::new(ordering_export).save(path)?;
+        println!("[katana] Ordering manifest written to {}", path.display());
+    }
     println!(
         "[CREATE] [████████████] 100.0% | {}/{} files | {:.1} MB/s | {:.2}s",
         index.files.len(),
@@ -573,6 +2462,56 @@ let start_ts = Instant::now();
     Ok(())
 }
 
+/// Выход, который не является целым архивом, – скорее всего шарды прерванного
+/// запуска. Шарды дописываются в конец выхода, так что без `--force` эти байты
+/// остались бы мёртвым префиксом – такой выход не трогаем и падаем; с `--force`
+/// выход обнуляется.
+fn discard_partial_output(output_path: &Path, force: bool) -> Result<(), Box<dyn Error>> {
+    let meta = match std::fs::metadata(output_path) {
+        Ok(meta) if meta.is_file() && meta.len() > 0 => meta,
+        _ => return Ok(()),
+    };
+    if crate::katana::is_katana_archive(output_path).unwrap_or(false) {
+        return Ok(());
+    }
+    if force {
+        println!("[katana] Discarding partial output {} ({} bytes)", output_path.display(), meta.len());
+        let f = std::fs::OpenOptions::new().write(true).open(output_path)?;
+        f.set_len(0)?;
+        f.sync_all()?;
+        return Ok(());
+    }
+    let age = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .map(|d| format!(", modified {} ago", crate::fsx::format_age(d.as_secs())))
+        .unwrap_or_default();
+    Err(format!(
+        "{} already holds {} bytes that are not a complete archive{} (an interrupted run?). Salvage the old \
+         data with `blitzarch repair` first, or rerun with --force to discard it",
+        output_path.display(),
+        meta.len(),
+        age
+    )
+    .into())
+}
+
+/// Fails with [`crate::ArchiverError::Cancelled`] once the job's token was set,
+/// removing the output if anything has been written to it already.
+fn check_create_cancelled(options: &KatanaCreateOptions, output_path: &Path, output_started: bool) -> Result<(), Box<dyn Error>> {
+    match options.cancel.as_ref().map(|c| c.check()) {
+        Some(Err(cancelled)) => {
+            if output_started {
+                let _ = std::fs::remove_file(output_path);
+            }
+            println!("[katana] Create cancelled – {} was not written", output_path.display());
+            Err(cancelled.into())
+        }
+        _ => Ok(()),
+    }
+}
+
 /// Creates a Katana archive with optional progress tracking.
 ///
 /// This thin wrapper delegates to `create_katana_archive` and, если указан
@@ -610,6 +2549,69 @@ where
 
     Ok(())
 }
+
+/// `create --per-input`: one archive per input, `<output_dir>/<input name>.blz`,
+/// published all-or-nothing through [`crate::fsx::OutputTransaction`]. If any
+/// archive fails (or its integrity check with `verify`), none of them appear
+/// and archives already in `output_dir` stay as they were.
+#[allow(clippy::too_many_arguments)]
+pub fn create_per_input_archives(
+    inputs: &[PathBuf],
+    output_dir: &Path,
+    threads: usize,
+    codec_threads: u32,
+    mem_budget_mb: Option<u64>,
+    password: Option<String>,
+    compression_level: Option<i32>,
+    options: &KatanaCreateOptions,
+    verify: bool,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut targets: Vec<PathBuf> = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let name = crate::fsx::absolute_path(input)
+            .file_name()
+            .map(|n| n.to_os_string())
+            .ok_or_else(|| format!("Cannot name an archive after input {}", input.display()))?;
+        let mut file_name = name;
+        file_name.push(".blz");
+        let target = output_dir.join(file_name);
+        if targets.contains(&target) {
+            return Err(format!("Inputs would share the archive name {} – rename one of them", target.display()).into());
+        }
+        targets.push(target);
+    }
+
+    let mut txn = crate::fsx::OutputTransaction::new(output_dir)?;
+    let mut staged: Vec<PathBuf> = Vec::with_capacity(inputs.len());
+    for (input, target) in inputs.iter().zip(&targets) {
+        let path = txn.stage(target)?;
+        // Ни старые архивы, ни только что собранные не попадают в следующие
+        let mut input_options = options.clone();
+        input_options.exclude_outputs.extend(targets.iter().chain(&staged).cloned());
+        println!(
+            "[katana] Archiving {} -> {}",
+            crate::private_logs::path(&input.to_string_lossy()),
+            crate::private_logs::path(&target.to_string_lossy())
+        );
+        create_katana_archive_with_options(
+            std::slice::from_ref(input),
+            &path,
+            threads,
+            codec_threads,
+            mem_budget_mb,
+            password.clone(),
+            compression_level,
+            &input_options,
+            None::<fn(crate::progress::ProgressState)>,
+        )
+        .map_err(|e| format!("{}: {} (no archives were written)", input.display(), e))?;
+        if verify {
+            perform_paranoid_check(&path).map_err(|e| format!("{}: {} (no archives were written)", target.display(), e))?;
+        }
+        staged.push(path);
+    }
+    Ok(txn.commit()?)
+}
 // -----------------------------------------------------------------------------
 // Полная проверка целостности: читаем футер, пересчитываем BLAKE3
 pub fn perform_paranoid_check(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
@@ -652,3 +2654,70 @@ pub fn perform_paranoid_check(path: &std::path::Path) -> Result<(), Box<dyn std:
     println!("[paranoid] Integrity verified, BLAKE3 = {}", calc_hash.to_hex());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AdaptiveLevel, ShardEncoder, ADAPT_WINDOW};
+    use std::io::Write;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::Arc;
+
+    /// Приёмник, который тормозит каждую запись
+    struct SlowSink(Vec<u8>);
+    impl Write for SlowSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            std::thread::sleep(std::time::Duration::from_millis(4));
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut x = 0x2545_F491_4F6C_DD1Du64;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    fn encode<W: Write>(out: W, data: &[u8], start: i32, range: AdaptiveLevel) -> (W, i32) {
+        let current = Arc::new(AtomicI32::new(start));
+        let mut encoder = ShardEncoder::new(out, start, &[], 0, None).adaptive(Some(range), &current);
+        for chunk in data.chunks(256 * 1024) {
+            encoder.write_all(chunk).unwrap();
+        }
+        (encoder.finish().unwrap().0, current.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn adaptive_level_follows_sink_backpressure() {
+        let data = noise(4 * ADAPT_WINDOW as usize);
+        let range = AdaptiveLevel { min: 1, max: 6 };
+        let (slow, level) = encode(SlowSink(Vec::new()), &data, 2, range);
+        assert!(level > 2, "a blocking sink raises the level (got {level})");
+        // Compressible data: the encoder works, the sink barely sees any bytes
+        let text: Vec<u8> = (0..).flat_map(|i: u64| format!("{} {}\n", i, i.wrapping_mul(0x9E37_79B9) % 100_003).into_bytes()).take(data.len()).collect();
+        let (fast, level) = encode(Vec::new(), &text, 4, range);
+        assert!(level < 4, "an idle sink lowers the level (got {level})");
+        // Level changes start new frames – still one decodable stream
+        assert_eq!(zstd::decode_all(&slow.0[..]).unwrap(), data);
+        assert_eq!(zstd::decode_all(&fast[..]).unwrap(), text);
+    }
+
+    #[test]
+    fn adaptive_level_ranges_parse() {
+        assert_eq!("".parse(), Ok(AdaptiveLevel::default()));
+        assert_eq!("min=3,max=15".parse(), Ok(AdaptiveLevel { min: 3, max: 15 }));
+        assert_eq!("max=5".parse(), Ok(AdaptiveLevel { min: 1, max: 5 }));
+        assert!("min=9,max=4".parse::<AdaptiveLevel>().is_err());
+        assert!("min=0".parse::<AdaptiveLevel>().is_err());
+        assert!("level=3".parse::<AdaptiveLevel>().is_err());
+    }
+}
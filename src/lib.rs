@@ -14,13 +14,18 @@
 //! - [`katana`]: Implements the high-performance, parallel-friendly "Katana" archive format.
 //! - [`autotune`]: Provides adaptive resource management and bottleneck detection for optimal performance.
 //! - [`workers`]: Contains the parallel processing logic for multi-threaded operations.
+//! - [`api`]: High-level builder API ([`Archive`]) for using BlitzArch as a library.
 //! 
 //! ## Examples
 //! 
 //! ```no_run
-//! // The high-level API is not yet implemented.
-//! // Please use the command-line interface.
-//! let api_is_ready = false;
+//! use blitzarch::Archive;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! Archive::create(["my_dir"]).level(7).threads(8).write_to("my_dir.blz")?;
+//! Archive::open("my_dir.blz")?.extract_to("restored")?;
+//! # Ok(())
+//! # }
 //! ```
 
 #![allow(unused_variables, unused_mut, unused_imports, dead_code)]
@@ -37,7 +42,11 @@ pub mod daemon;
 pub mod extract;
 pub mod index;
 pub mod error;
-pub use error::ArchiverError;
+pub use error::{ArchiverError, ErrorReport};
+
+// High-level builder API
+pub mod api;
+pub use api::{Archive, ArchiveBuilder, OpenArchive};
 
 pub mod workers;
 pub mod cli_runner;
@@ -46,8 +55,90 @@ pub mod progress;
 pub mod katana;
 pub mod katana_stream;
 
+// Read-only support for other archive formats (ZIP)
+pub mod formats;
+
 // Cross-platform filesystem wrapper
 pub mod fsx;
 
+// Worker CPU affinity / NUMA placement
+pub mod numa;
+
+// Per-job private temp workspaces
+pub mod temp_manager;
+
+// Blocking capacity pools shared by pipeline workers (IO caps)
+pub mod memory_pool;
+
+// Interactive archive browser (`blitzarch tui`)
+pub mod tui;
+
+// Roundtrip self-test (`blitzarch selftest`)
+pub mod selftest;
+
+// Throughput benchmark and regression gate (`blitzarch bench`)
+pub mod bench;
+
+// File ordering strategies inside shards
+pub mod ordering;
+
+// User configuration file (`~/.config/blitzarch/config.toml`)
+pub mod config;
+
+// Completed job summaries for the GUI "recent operations" view
+pub mod history;
+
+// Index checks (`blitzarch audit`)
+pub mod audit;
+
+// Detached archive checksums (`blitzarch attest`)
+pub mod attest;
+
+// Archive manifests in external formats (`blitzarch manifest`)
+pub mod manifest;
+
+// Pluggable file sources for the create pipeline
+pub mod vfs;
+
+// Time-boxed create / verify with resumable checkpoints (`--max-duration`)
+pub mod timebox;
+
+// Structured job warnings (GUI post-job report)
+pub mod warnings;
+
+// Engine console output, redirectable while a front end owns the terminal
+pub mod console;
+
+// Entry names kept out of logs and reports (`--private-logs`)
+pub mod private_logs;
+
+// Cooperative cancellation of create / extract jobs
+pub mod cancel;
+
+// Thread and memory limits shared by concurrent create / extract jobs
+pub mod governor;
+
+// Content-defined chunking (`--dedup=chunks`)
+pub mod cdc;
+
+// Decompressed shard cache for repeated single-file reads (GUI browsing)
+pub mod shard_cache;
+
 // Global dictionary cache (POC)
 pub mod dict_cache;
+
+// Pre-trained zstd dictionaries shared by archive series (`--dictionary`)
+pub mod dictionary;
+
+// Classic (MFUS) to Katana conversion (`blitzarch upgrade`)
+pub mod upgrade;
+
+// Read-only FUSE mount of archives (`blitzarch mount`)
+pub mod mount;
+
+// Continuous archiving of a watched directory (`blitzarch watch`)
+pub mod watch;
+
+// Archives in S3 / object storage (`--output s3://…`, `extract s3://…`)
+#[cfg(feature = "remote")]
+pub mod remote;
@@ -17,6 +17,7 @@ use std::process::{Command, Stdio};
 use term_size;
 use std::time::Instant;
 use std::sync::atomic::{AtomicBool, Ordering};
+use blitzarch::console::{eprint, eprintln, println};
 
 fn main() -> std::process::ExitCode {
     // Parse command line arguments to determine launch mode
@@ -99,9 +100,10 @@ fn launch_gui_mode() -> std::process::ExitCode {
 
 /// Launch CLI mode (command-line interface)
 fn launch_cli_mode() -> std::process::ExitCode {
+    let error_format = cli::error_format_from_args(env::args());
     if let Err(e) = run_cli_app() {
         if e.downcast_ref::<clap::Error>().is_none() {
-            eprintln!("Error: {}", e);
+            cli::print_error(e.as_ref(), error_format);
         }
         return std::process::ExitCode::FAILURE;
     }
@@ -110,26 +112,166 @@ fn launch_cli_mode() -> std::process::ExitCode {
 
 fn run_cli_app() -> Result<(), Box<dyn std::error::Error>> {
     let command = cli::run()?;
+    let json = cli::json::json_command(&command);
+    // Nothing but the JSON object may reach stdout
+    let console = json.map(|_| blitzarch::console::capture(std::io::stderr()));
+    let result = run_parsed(&command);
+    drop(console);
+    if let (Err(e), Some(name)) = (&result, json) {
+        // `--json` callers only read stdout: the failure goes there as well
+        if !cli::json::printed() {
+            cli::json::print(&cli::json::error_report(name, e.as_ref()))?;
+        }
+    }
+    result
+}
 
-    match &command {
-        Commands::Create { sharded: _, inputs, output, level: _, workers: _, threads, codec_threads, memory_budget, password, progress, skip_check, .. } => {
-            // Katana stream (default):
+fn run_parsed(command: &Commands) -> Result<(), Box<dyn std::error::Error>> {
+    if let Commands::Create { json: true, output, password, .. } = command {
+        // Вывод движка уходит в stderr, в stdout – только JSON-итог
+        let started = std::time::SystemTime::now();
+        let (result, warnings, elapsed) = cli::json::run_quiet(|| run_command(command));
+        result?;
+        let pass = cli::get_password_from_opt_or_env(password.clone())?;
+        let report = cli::json::create_report(&cli::sanitize_output_path(output), pass.as_deref(), started, elapsed, &warnings)?;
+        return cli::json::print(&report);
+    }
+    run_command(command)
+}
+
+fn run_command(command: &Commands) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Commands::Create { sharded: _, inputs, output, level: _, workers: worker_mode, threads, codec_threads, memory_budget, password, progress, skip_check, numa, zstd_param, inline_small, order, index_compression, index_format, base, max_duration, resume, pausable, symlinks, dedup, xattrs, checksum, shard_strategy, seekable_frames, skip_if_unchanged, dry_run, adapt, retune, export_ordering, rsync_friendly, hide_names, store_nested, force, per_input, .. } => {
                 let do_paranoid = !*skip_check; // secure by default
+                let format = cli::resolve_create_format(command)?;
+                if format == cli::ArchiveFormat::Classic {
+                    // Legacy bundle writer reads its options straight from the command
+                    workers::run_parallel_compression_with_progress(
+                        std::sync::Arc::new(command.clone()),
+                        *worker_mode,
+                        progress.then(|| create_cli_progress_callback("create")),
+                    )?;
+                    if do_paranoid {
+                        perform_paranoid_check(output)?;
+                    }
+                    return Ok(());
+                }
+            // Katana stream (default):
+                blitzarch::numa::set_policy((*numa).into());
                 let auto_threads = if *threads == 0 { num_cpus::get() } else { *threads };
 
-                // parse memory budget and export to env so katana_stream can read it
                 let mem_budget_mb = cli::parse_memory_budget_mb(memory_budget)
                     .map_err(|e| format!("Invalid --memory-budget: {e}"))?;
-                if let Some(mb) = mem_budget_mb {
-                    std::env::set_var("BLITZ_MEM_BUDGET_MB", mb.to_string());
-                }
                 // Sanitize output path (Windows-invalid chars / reserved names)
                 let output_path = cli::sanitize_output_path(output);
+                let time_budget = max_duration
+                    .map(blitzarch::timebox::TimeBudget::new)
+                    .or_else(|| pausable.then(blitzarch::timebox::TimeBudget::unlimited));
+                if let (true, Some(budget)) = (*pausable, time_budget.as_ref()) {
+                    blitzarch::timebox::pause_on_interrupt(budget);
+                }
+                if *resume {
+                    let pass = cli::get_password_from_opt_or_env(password.clone())?;
+                    let (_, left) = blitzarch::timebox::resume_create(&output_path, auto_threads, pass, None, time_budget.clone())?;
+                    report_time_budget(&output_path, left, time_budget.as_deref());
+                    return Ok(());
+                }
+                if let Some(previous) = skip_if_unchanged {
+                    let listing = blitzarch::katana_stream::KatanaCreateOptions {
+                        symlinks: (*symlinks).into(),
+                        exclude_outputs: vec![output_path.clone()],
+                        filter: cli::path_filter(command)?,
+                        size_filter: cli::size_filter(command),
+                        ..Default::default()
+                    };
+                    let pass = cli::get_password_from_opt_or_env(password.clone())?;
+                    if blitzarch::katana_stream::source_unchanged(inputs, previous, &listing, pass.as_deref())? {
+                        println!("[katana] Source unchanged since {} – no new archive written", previous.display());
+                        return Ok(());
+                    }
+                }
+                if *dry_run {
+                    let listing = blitzarch::katana_stream::KatanaCreateOptions {
+                        symlinks: (*symlinks).into(),
+                        inline_small_files: *inline_small,
+                        shard_strategy: shard_strategy.unwrap_or_default(),
+                        ordering_manifest: cli::ordering_manifest(command)?,
+                        filter: cli::path_filter(command)?,
+                        size_filter: cli::size_filter(command),
+                        store_nested_archives: *store_nested,
+                        extension_rules: cli::extension_rules(command)?,
+                        ..Default::default()
+                    };
+                    blitzarch::katana_stream::estimate_create(inputs, &output_path, auto_threads, None, password.is_some(), &listing)?.print_summary();
+                    return Ok(());
+                }
+                let remote_sink = cli::remote_create_sink(command)?;
+                let create_options = blitzarch::katana_stream::KatanaCreateOptions {
+                    zstd_params: zstd_param.clone(),
+                    inline_small_files: *inline_small,
+                    ordering: order.strategy(),
+                    index_compression: index_compression.unwrap_or_default(),
+                    index_format: index_format.map(Into::into).unwrap_or_default(),
+                    base: base.clone(),
+                    time_budget: time_budget.clone(),
+                    symlinks: (*symlinks).into(),
+                    dedup: *dedup == Some(cli::DedupMode::Files),
+                    chunk_dedup: *dedup == Some(cli::DedupMode::Chunks),
+                    xattrs: *xattrs,
+                    io_limits: cli::io_limits(command),
+                    io: cli::io_config(command),
+                    shard_checksum: checksum.unwrap_or_default(),
+                    shard_strategy: shard_strategy.unwrap_or_default(),
+                    ordering_manifest: cli::ordering_manifest(command)?,
+                    seekable_frames: seekable_frames.map(|mib| mib * 1024 * 1024),
+                    rsync_friendly: *rsync_friendly,
+                    hide_names: *hide_names,
+                    store_nested_archives: *store_nested,
+                    force: *force,
+                    metadata: cli::archive_metadata(command)?,
+                    dictionary: cli::create_dictionary(command)?,
+                    filter: cli::path_filter(command)?,
+                    size_filter: cli::size_filter(command),
+                    adapt: *adapt,
+                    retune: *retune,
+                    export_ordering: export_ordering.clone(),
+                    sink: remote_sink.clone(),
+                    ..Default::default()
+                };
+                if *per_input {
+                    let written = blitzarch::katana_stream::create_per_input_archives(inputs, &output_path, auto_threads, *codec_threads, mem_budget_mb, password.clone(), None, &create_options, do_paranoid)?;
+                    println!("[katana] Wrote {} archives to {}", written.len(), output_path.display());
+                    return Ok(());
+                }
+                if remote_sink.is_some() {
+                    // Части проверяет S3 по их SHA-256 – перечитывать нечего
+                    blitzarch::katana_stream::create_katana_archive_with_options(
+                        inputs,
+                        output,
+                        auto_threads,
+                        *codec_threads,
+                        mem_budget_mb,
+                        password.clone(),
+                        None,
+                        &create_options,
+                        progress.then(|| create_cli_progress_callback("create")),
+                    )?;
+                    return Ok(());
+                }
 
+                if *progress {
+                    let input = blitzarch::fsx::tree_stats(inputs, (*symlinks).into(), std::slice::from_ref(&output_path));
+                    println!(
+                        "[katana] Input: {} files in {} directories, {:.2} MiB",
+                        input.files,
+                        input.dirs,
+                        input.bytes as f64 / (1024.0 * 1024.0)
+                    );
+                }
                 if *progress {
                     // Create progress callback for real-time CLI display
                     let progress_callback = create_cli_progress_callback("create");
-                    blitzarch::katana_stream::create_katana_archive_with_progress(
+                    blitzarch::katana_stream::create_katana_archive_with_options(
                         inputs,
                         &output_path,
                         auto_threads,
@@ -137,17 +279,18 @@ fn run_cli_app() -> Result<(), Box<dyn std::error::Error>> {
                         mem_budget_mb,
                         password.clone(),
                         None, // compression_level - use AutoTune default
-                        !do_paranoid, // skip_check - invert paranoid flag
+                        &create_options,
                         Some(progress_callback),
                     )?;
 
                     // Paranoid BLAKE3 verification
                     if do_paranoid {
+                        blitzarch::katana_stream::perform_paranoid_check(&output_path)?;
                         perform_paranoid_check(output)?;
                     }
                 } else {
                     // Use existing katana_stream for backward compatibility
-                    blitzarch::katana_stream::create_katana_archive(
+                    blitzarch::katana_stream::create_katana_archive_with_options(
                         inputs,
                         &output_path,
                         auto_threads,
@@ -155,12 +298,15 @@ fn run_cli_app() -> Result<(), Box<dyn std::error::Error>> {
                         mem_budget_mb,
                         password.clone(),
                         None, // compression_level - use AutoTune default
+                        &create_options,
                         None::<fn(blitzarch::progress::ProgressState)>, // no progress callback for CLI
                     )?;
                     if do_paranoid {
                         perform_paranoid_check(output)?;
                     }
                 }
+                let left = blitzarch::timebox::finish_create(&output_path, inputs, time_budget.as_deref())?;
+                report_time_budget(&output_path, left, time_budget.as_deref());
 
         }
         Commands::Extract {
@@ -169,32 +315,392 @@ fn run_cli_app() -> Result<(), Box<dyn std::error::Error>> {
             output,
             password,
             strip_components,
+            relative_to,
             progress,
+            no_preserve_permissions,
+            case_collisions,
+            skip_existing,
+            rename_existing,
+            keep_newer,
+            hard_links,
+            xattrs,
+            privileged_xattrs,
+            only_executable,
+            uid,
+            json,
+            dictionary,
+            recursive_extract,
+            atomic,
             ..
         } => {
                 let out_dir = output.as_ref().ok_or("--output is required for Katana extract")?;
+                // --atomic: распаковка в соседний временный каталог, публикация в самом конце
+                let final_dir = out_dir;
+                let staging = if *atomic { Some(blitzarch::fsx::StagedDir::new(final_dir)?) } else { None };
+                let staged_path = staging.as_ref().map(|s| s.path());
+                let out_dir = staged_path.as_ref().unwrap_or(final_dir);
+                let extract_options = blitzarch::extract::ExtractOptions {
+                    preserve_permissions: !*no_preserve_permissions,
+                    case_collisions: (*case_collisions).into(),
+                    hard_links: *hard_links,
+                    filter: blitzarch::katana::ExtractFilter { only_executable: *only_executable, uid: *uid },
+                    conflict_policy: cli::conflict_policy(*skip_existing, *rename_existing, *keep_newer),
+                    dictionaries: blitzarch::dictionary::Dictionaries::load(dictionary)?,
+                };
+                blitzarch::fsx::set_restore_xattrs(*xattrs);
+                blitzarch::fsx::set_restore_privileged_xattrs(*privileged_xattrs);
                 let pass = cli::get_password_from_opt_or_env(password.clone())?;
-                
-                if *progress {
+                let (files, strip_components) = match relative_to {
+                    Some(prefix) => blitzarch::katana::relative_to_prefix(prefix, files, *strip_components)?,
+                    None => (files.clone(), *strip_components),
+                };
+                // s3://… – локальная копия только с нужными шардами
+                let fetched = cli::fetch_remote_archive(archive, &files, pass.as_deref(), final_dir)?;
+                let archive_name = archive;
+                let archive = fetched.as_deref().unwrap_or(archive);
+                // Не-Katana форматы (classic, ZIP, плагины) – через backend; вложенные архивы ищутся по индексу Katana
+                let backend = blitzarch::formats::backend_for(archive)?;
+                if let Some(backend) = &backend {
+                    blitzarch::formats::check_extract_options(backend.as_ref(), &extract_options)?;
+                }
+                if backend.is_some() && *recursive_extract {
+                    return Err("--recursive-extract works with Katana archives only".into());
+                }
+
+                if *json {
+                    let last = Arc::new(Mutex::new(None));
+                    let sink = Arc::clone(&last);
+                    let (result, warnings, elapsed) = cli::json::run_quiet(|| {
+                        let callback = move |state: ProgressState| *sink.lock().unwrap() = Some(state);
+                        if let Some(backend) = &backend {
+                            return backend.extract(archive, out_dir, &files, pass.as_deref(), strip_components, Some(&callback), &extract_options);
+                        }
+                        if *recursive_extract {
+                            blitzarch::katana::extract_katana_archive_recursive(
+                                archive, out_dir, &files, pass.clone(), strip_components, Some(callback), &extract_options
+                            )?;
+                        } else {
+                            blitzarch::katana::extract_katana_archive_with_options(
+                                archive, out_dir, &files, pass.clone(), strip_components, Some(callback), None, &extract_options
+                            )?;
+                        }
+                        if let Some(staging) = &staging {
+                            staging.publish(extract_options.conflict_policy)?;
+                        }
+                        Ok::<(), Box<dyn std::error::Error>>(())
+                    });
+                    result?;
+                    let last = last.lock().unwrap();
+                    cli::json::print(&cli::json::extract_report(archive_name, final_dir, last.as_ref(), elapsed, &warnings))?;
+                } else if *progress {
                     // Create progress callback for real-time CLI display
                     let progress_callback = create_cli_progress_callback("extract");
-                    blitzarch::katana::extract_katana_archive_with_progress(
-                        archive, out_dir, files, pass, *strip_components, Some(progress_callback)
+                    if let Some(backend) = &backend {
+                        backend.extract(archive, out_dir, &files, pass.as_deref(), strip_components, Some(&progress_callback), &extract_options)?;
+                    } else if *recursive_extract {
+                        blitzarch::katana::extract_katana_archive_recursive(
+                            archive, out_dir, &files, pass.clone(), strip_components, Some(progress_callback), &extract_options
+                        )?;
+                    } else {
+                        blitzarch::katana::extract_katana_archive_with_options(
+                            archive, out_dir, &files, pass.clone(), strip_components, Some(progress_callback), None, &extract_options
+                        )?;
+                    }
+                } else if let Some(backend) = &backend {
+                    backend.extract(archive, out_dir, &files, pass.as_deref(), strip_components, None, &extract_options)?;
+                } else if *recursive_extract {
+                    blitzarch::katana::extract_katana_archive_recursive(
+                        archive, out_dir, &files, pass.clone(), strip_components, None::<fn(ProgressState)>, &extract_options
                     )?;
                 } else {
-                    blitzarch::katana::extract_katana_archive_internal(archive, out_dir, files, pass, *strip_components)?;
+                    blitzarch::katana::extract_katana_archive_with_options(
+                        archive, out_dir, &files, pass.clone(), strip_components, None::<fn(ProgressState)>, None, &extract_options
+                    )?;
+                }
+                if let (Some(staging), false) = (&staging, *json) {
+                    staging.publish(extract_options.conflict_policy)?;
                 }
 
         }
-        Commands::List { archive } => {
-            let file = File::open(archive)?;
-            extract::list_files(file).map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
+        Commands::List { archive, json, password } => {
+            let password = cli::get_password_from_opt_or_env(password.clone())?;
+            let fetched = cli::fetch_remote_index(archive, password.as_deref())?;
+            let archive_name = archive;
+            let archive = fetched.as_deref().unwrap_or(archive);
+            if *json {
+                cli::json::print(&serde_json::json!({
+                    "command": "list",
+                    "archive": archive_name,
+                    "metadata": extract::read_metadata(archive, password.as_deref())?,
+                    "entries": extract::list_entries(archive, password.as_deref())?,
+                }))?;
+            } else if fetched.is_some() {
+                // Разреженная копия: читаем только индекс, без полного копирования
+                blitzarch::katana::list_katana_files(archive, password.clone())?;
+            } else {
+                let file = File::open(archive)?;
+                extract::list_files(file, password.as_deref()).map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
+            }
+        }
+        Commands::Tui { archive, output, password } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            blitzarch::tui::run_tui(archive, pass, output)?;
+        }
+        Commands::Mount { archive, mountpoint, password, cache_size } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            blitzarch::mount::mount(archive, mountpoint, pass, *cache_size)?;
+        }
+        Commands::Daemon { socket, max_jobs, memory_budget } => {
+            let budget_mb = cli::parse_memory_budget_mb(&Some(memory_budget.clone()))
+                .map_err(|e| format!("Invalid --memory-budget: {e}"))?
+                .ok_or("--memory-budget must be above 0")?;
+            let socket = socket.clone().unwrap_or_else(blitzarch::daemon::default_socket_path);
+            blitzarch::daemon::serve(&socket, *max_jobs, budget_mb)?;
+        }
+        Commands::Watch { dir, output, debounce, generations, password, level, threads, auto_compact } => {
+            let options = blitzarch::watch::WatchOptions {
+                debounce: *debounce,
+                generations: *generations,
+                password: cli::get_password_from_opt_or_env(password.clone())?,
+                level: Some(*level),
+                threads: *threads,
+                auto_compact: cli::auto_compact_threshold(*auto_compact)?,
+            };
+            // Ctrl-C дописывает текущую партию, а не рвёт индекс посреди дозаписи
+            let cancel = blitzarch::cancel::CancellationToken::new();
+            blitzarch::timebox::cancel_on_interrupt(&cancel);
+            blitzarch::watch::watch(dir, output, options, &cancel)?;
+        }
+        Commands::Selftest { dir, threads, password } => {
+            let report = blitzarch::selftest::run_selftest(dir, *threads, password.clone())?;
+            report.print_summary();
+            if !report.passed() {
+                return Err("Self-test failed: roundtrip mismatch".into());
+            }
+        }
+        Commands::Bench { inputs, level, threads, runs, json, compare, fail_threshold } => {
+            let options = blitzarch::bench::BenchOptions { level: *level, threads: *threads, runs: *runs };
+            let report = blitzarch::bench::run_bench(inputs, &options)?;
+            report.print_summary();
+            if let Some(path) = json {
+                report.save(path)?;
+            }
+            if let Some(baseline) = compare {
+                let comparison = blitzarch::bench::BenchComparison::new(&blitzarch::bench::BenchReport::load(baseline)?, &report, *fail_threshold);
+                comparison.print_summary();
+                comparison.check()?;
+            }
+        }
+        Commands::Append { archive, inputs, password, level, threads, auto_compact } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            blitzarch::katana::append_to_archive(archive, inputs, *threads, pass.clone(), Some(*level))?;
+            if let Some(threshold) = cli::auto_compact_threshold(*auto_compact)? {
+                blitzarch::katana::compact_if_wasteful(archive, pass, threshold)?;
+            }
+        }
+        Commands::Stats { archive, json, password } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            let shards = blitzarch::katana::shard_details(archive, pass.as_deref())?;
+            if *json {
+                cli::json::print(&serde_json::json!({
+                    "command": "stats",
+                    "archive": archive,
+                    "shards": shards,
+                }))?;
+            } else {
+                blitzarch::katana::print_shard_details(&shards);
+            }
+        }
+        Commands::Audit { archive, password } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            let findings = blitzarch::audit::audit_archive(archive, pass.as_deref())?;
+            for f in &findings {
+                println!("[audit] {}: {} – {}", f.rule, f.path, f.message);
+            }
+            if !findings.is_empty() {
+                return Err(format!("Audit found {} issue(s)", findings.len()).into());
+            }
+            println!("[audit] No issues found");
+        }
+        Commands::Manifest { archive, format, no_hashes, output, password } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            let mut out: Box<dyn std::io::Write> = match output {
+                Some(path) => Box::new(std::io::BufWriter::new(File::create(path)?)),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            match format {
+                cli::ManifestFormat::Mtree => blitzarch::manifest::write_mtree(archive, pass.as_deref(), !*no_hashes, &mut out)?,
+            }
+            out.flush()?;
+        }
+        Commands::TrainDictionary { dir, output, max_size } => {
+            let dictionary = blitzarch::dictionary::train_dictionary(dir, *max_size)?;
+            std::fs::write(output, dictionary.bytes())?;
+            println!("[katana] Dictionary {} written to {}", dictionary.id(), output.display());
+        }
+        Commands::Verify { archive, deep, password, max_duration, resume, json, dictionary } => {
+            let dictionaries = blitzarch::dictionary::Dictionaries::load(dictionary)?;
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            let budget = max_duration.map(blitzarch::timebox::TimeBudget::new);
+            if *json {
+                let (r, _, elapsed) = cli::json::run_quiet(|| blitzarch::timebox::verify_timeboxed(archive, pass.as_deref(), *deep, budget.as_deref(), *resume, &dictionaries));
+                let r = r?;
+                cli::json::print(&cli::json::verify_report(archive, &r, elapsed)?)?;
+                if !r.corrupt.is_empty() {
+                    return Err(format!("Verification failed: {} problem(s) found in {}", r.corrupt.len(), archive.display()).into());
+                }
+                return Ok(());
+            }
+            let started = std::time::Instant::now();
+            let r = match blitzarch::timebox::verify_timeboxed(archive, pass.as_deref(), *deep, budget.as_deref(), *resume, &dictionaries) {
+                Ok(r) => r,
+                Err(e) => {
+                    println!("{}", blitzarch::katana::VerifyReport::failed(e.to_string()).status_line(archive, started.elapsed()));
+                    return Err(e);
+                }
+            };
+            if !r.corrupt.is_empty() {
+                print_corrupt_entries(&r);
+                println!("{}", r.status_line(archive, started.elapsed()));
+                return Err(format!("Verification failed: {} problem(s) found in {}", r.corrupt.len(), archive.display()).into());
+            }
+            if let Some(next) = r.resume_at {
+                println!(
+                    "[verify] ⏱ Time budget exhausted: {}/{} shards checked; rerun with --resume to continue",
+                    next, r.shards
+                );
+                println!("{}", r.status_line(archive, started.elapsed()));
+                return Ok(());
+            }
+            println!(
+                "[verify] ✅ {} | Shards: {} | Files: {} | HMAC: {} | BLAKE3: {} | Deep: {}",
+                archive.display(),
+                r.shards,
+                r.files,
+                if r.index_hmac { "ok" } else { "skipped" },
+                if r.blake3 { "ok" } else if *resume { "skipped" } else { "absent" },
+                if r.deep { "ok" } else { "skipped" },
+            );
+            println!("{}", r.status_line(archive, started.elapsed()));
+        }
+        Commands::Attest { archive, out, verify, key_file } => {
+            let sig_path = out.clone().unwrap_or_else(|| blitzarch::attest::default_attestation_path(archive));
+            let key = key_file.as_deref().map(blitzarch::attest::key_from_file).transpose()?;
+            if *verify {
+                let a = blitzarch::attest::verify_attestation(archive, &sig_path, key.as_ref())?;
+                println!("[attest] ✅ {} matches {} ({} {})", archive.display(), sig_path.display(), a.algorithm, a.hash);
+            } else {
+                let a = blitzarch::attest::write_attestation(archive, &sig_path, key.as_ref())?;
+                println!("[attest] Wrote {} ({} {})", sig_path.display(), a.algorithm, a.hash);
+            }
+        }
+        Commands::Compact { archive, password } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            blitzarch::katana::compact_katana_archive(archive, pass)?;
+        }
+        Commands::Repair { archive, output, extract: extract_dir, password } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            let output = output.clone().unwrap_or_else(|| archive.with_extension("repaired.blz"));
+            let report = blitzarch::katana::repair_katana_archive(archive, &output, pass.as_deref())?;
+            for path in &report.files_lost {
+                println!("[repair] lost: {}", path);
+            }
+            if extract_dir.is_some() {
+                extract::katana_extract(&output, &[], extract_dir, None, pass.as_deref(), None)?;
+            }
+        }
+        Commands::Upgrade { archive, in_place: _, output, password, threads, force } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            let target = output.as_deref().unwrap_or(archive);
+            let threads = if *threads == 0 { num_cpus::get() } else { *threads };
+            match blitzarch::upgrade::upgrade_classic_archive(archive, target, pass.as_deref(), threads, *force)? {
+                Some(report) => println!(
+                    "[katana] Upgraded {} -> {} ({} files, {:.2} -> {:.2} MiB{}, verified)",
+                    archive.display(),
+                    report.output.display(),
+                    report.files,
+                    report.old_size as f64 / (1024.0 * 1024.0),
+                    report.new_size as f64 / (1024.0 * 1024.0),
+                    if report.encrypted { ", encrypted" } else { "" }
+                ),
+                None => println!("[katana] {} already is a Katana archive, nothing to do", archive.display()),
+            }
+        }
+        Commands::Encrypt { archive, password } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?.ok_or("--password (or BLITZARCH_PASSWORD) is required")?;
+            blitzarch::katana::encrypt_katana_archive(archive, &pass)?;
+        }
+        Commands::Decrypt { archive, password } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?.ok_or("--password (or BLITZARCH_PASSWORD) is required")?;
+            blitzarch::katana::decrypt_katana_archive(archive, &pass)?;
+        }
+        Commands::Touch { archive, patterns, chmod, mtime, password, no_footer } => {
+            let pass = cli::get_password_from_opt_or_env(password.clone())?;
+            let changes = blitzarch::katana::TouchChanges { mode: *chmod, mtime: *mtime, drop_footer: *no_footer };
+            let updated = blitzarch::katana::touch_entries(archive, patterns, changes, pass)?;
+            if updated == 0 {
+                println!("[katana] No entries matched, archive left unchanged");
+            }
+        }
+        Commands::Key { action } => {
+            let required = |password: &Option<String>| -> Result<String, Box<dyn std::error::Error>> {
+                Ok(cli::get_password_from_opt_or_env(password.clone())?.ok_or("--password (or BLITZARCH_PASSWORD) is required")?)
+            };
+            match action {
+                cli::KeyCommand::Add { archive, password, new_password } => {
+                    blitzarch::katana::add_key_slot(archive, &required(password)?, new_password)?;
+                }
+                cli::KeyCommand::Remove { archive, password } => {
+                    blitzarch::katana::remove_key_slot(archive, &required(password)?)?;
+                }
+                cli::KeyCommand::Rotate { archive, password, new_password } => {
+                    blitzarch::katana::rotate_key_slot(archive, &required(password)?, new_password)?;
+                }
+            }
         }
     }
 
     Ok(())
 }
 
+/// How many damaged entries `verify` names before summarising the rest.
+const MAX_LISTED_CORRUPT: usize = 10;
+
+/// Lists the first damaged entries of a failed `verify`.
+fn print_corrupt_entries(report: &blitzarch::katana::VerifyReport) {
+    let mut listed = 0;
+    let mut total = 0;
+    for damage in &report.corrupt {
+        let place = damage.shard.map_or_else(String::new, |id| format!("shard {}: ", id));
+        if damage.entries.is_empty() {
+            println!("[verify] ❌ {}{}", place, damage.error);
+        }
+        for entry in &damage.entries {
+            total += 1;
+            if listed < MAX_LISTED_CORRUPT {
+                listed += 1;
+                println!("[verify] ❌ {} ({}{})", entry, place, damage.error);
+            }
+        }
+    }
+    if total > listed {
+        println!("[verify] … and {} more corrupt entries", total - listed);
+    }
+}
+
+/// Tells the user whether a `--max-duration` create left files for `--resume`.
+fn report_time_budget(archive: &std::path::Path, left: usize, budget: Option<&blitzarch::timebox::TimeBudget>) {
+    if left > 0 {
+        let reason = if budget.is_some_and(|b| b.paused()) { "⏸ Paused" } else { "⏱ Time budget exhausted" };
+        println!(
+            "[katana] {}: {} files not archived yet; rerun with --resume to add them (checkpoint: {})",
+            reason,
+            left,
+            blitzarch::timebox::checkpoint_path(archive).display()
+        );
+    }
+}
+
 // -----------------------------------------------------------------------------
 /// Reads the file twice and compares BLAKE3-256 digests; returns Err on mismatch.
 fn perform_paranoid_check(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
@@ -261,13 +767,14 @@ fn create_cli_progress_callback(operation: &str) -> impl Fn(ProgressState) + Sen
             let empty = bw - filled;
             let progress_bar = format!("[{}{}]", "█".repeat(filled), "░".repeat(empty));
             let line = format!(
-                "[{}] {} {:.1}% | {}/{} files | {:.1} MB/s | ETA: {}",
+                "[{}] {} {:.1}% | {}/{} files | {:.1} MB/s{} | ETA: {}",
                 operation.to_uppercase(),
                 progress_bar,
                 state.progress_percent,
                 state.processed_files,
                 state.total_files,
                 state.speed_mbps,
+                state.compression_level.map_or_else(String::new, |level| format!(" | level {}", level)),
                 "{ETA}" // placeholder, will replace below
             );
             (line, progress_bar.len())
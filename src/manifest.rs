@@ -0,0 +1,111 @@
+//! Manifests of archive contents in external formats (`blitzarch manifest`).
+//!
+//! The mtree output follows the "full path" style written by `bsdtar --format
+//! mtree`: one line per entry with its type, mode, size, modification time and
+//! SHA-256, so an extracted tree can be checked with the OS tools
+//! (`mtree -p dir -f spec` on BSD/macOS, `bsdtar -xf spec` to compare) or fed
+//! into package and build systems. Directories the archive does not store
+//! itself are listed for every parent of an entry.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+
+use crate::katana::EntryInfo;
+
+/// Writes the mtree manifest of a Katana or classic archive to `out`.
+///
+/// With `hashes` every entry is decompressed to compute its `sha256digest`
+/// (Katana archives only); without, only the index is read.
+pub fn write_mtree(archive_path: &Path, password: Option<&str>, hashes: bool, out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    let entries = crate::extract::list_entries(archive_path, password)?;
+    let digests = if hashes {
+        if !crate::katana::is_katana_archive(archive_path)? {
+            return Err("SHA-256 digests need a Katana archive; use --no-hashes for classic archives".into());
+        }
+        crate::katana::entry_sha256(archive_path, password)?
+    } else {
+        vec![None; entries.len()]
+    };
+    render_mtree(&entries, &digests, out)
+}
+
+/// mtree lines for `entries`; `digests` is aligned with them.
+pub fn render_mtree(entries: &[EntryInfo], digests: &[Option<[u8; 32]>], out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    // Каталоги: явные (classic) и все родители записей
+    let mut dirs: BTreeMap<String, Option<&EntryInfo>> = BTreeMap::new();
+    let mut files = BTreeMap::new();
+    for (entry, digest) in entries.iter().zip(digests.iter().chain(std::iter::repeat(&None))) {
+        let path = entry.path.trim_matches('/');
+        let mut parent = Path::new(path).parent();
+        while let Some(dir) = parent.filter(|d| !d.as_os_str().is_empty()) {
+            dirs.entry(dir.to_string_lossy().replace('\\', "/")).or_insert(None);
+            parent = dir.parent();
+        }
+        if entry.is_dir {
+            dirs.insert(path.to_string(), Some(entry));
+        } else {
+            // Later duplicates win, as on extract
+            files.insert(path.to_string(), (entry, *digest));
+        }
+    }
+
+    writeln!(out, "#mtree")?;
+    writeln!(out, ". type=dir")?;
+    let mut lines: BTreeMap<&str, String> = BTreeMap::new();
+    for (path, entry) in &dirs {
+        let mut line = format!("./{} type=dir", escape(path));
+        if let Some(entry) = entry {
+            push_attrs(&mut line, entry);
+        }
+        lines.insert(path, line);
+    }
+    for (path, (entry, digest)) in &files {
+        let mut line = format!("./{}", escape(path));
+        match &entry.symlink {
+            Some(target) => {
+                line.push_str(" type=link");
+                push_attrs(&mut line, entry);
+                line.push_str(&format!(" link={}", escape(target)));
+            }
+            None => {
+                line.push_str(" type=file");
+                push_attrs(&mut line, entry);
+                line.push_str(&format!(" size={}", entry.size));
+                if let Some(digest) = digest {
+                    line.push_str(" sha256digest=");
+                    line.extend(digest.iter().map(|b| format!("{:02x}", b)));
+                }
+            }
+        }
+        lines.insert(path, line);
+    }
+    for line in lines.values() {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+fn push_attrs(line: &mut String, entry: &EntryInfo) {
+    if let Some(mode) = entry.permissions {
+        line.push_str(&format!(" mode={:04o}", mode & 0o7777));
+    }
+    if let Some(mtime) = entry.mtime {
+        line.push_str(&format!(" time={}.0", mtime));
+    }
+}
+
+/// vis(3)-style escaping used by mtree: whitespace, `#`, `=`, `\` and
+/// everything outside printable ASCII become `\ooo`.
+pub fn escape(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for &b in path.as_bytes() {
+        if b.is_ascii_graphic() && !matches!(b, b'#' | b'=' | b'\\') {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("\\{:03o}", b));
+        }
+    }
+    escaped
+}
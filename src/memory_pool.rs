@@ -47,3 +47,98 @@ impl PagePool {
         self.cv.notify_all();
     }
 }
+
+/// Fixed-size byte blocks shared by the shard workers of one job: at most
+/// `capacity` blocks of `block_size` bytes exist, allocated on first use and
+/// reused once a [`PooledBuf`] is dropped. [`BufferPool::get`] blocks while
+/// every block is out.
+#[derive(Debug)]
+pub struct BufferPool {
+    state: Mutex<BufferPoolState>,
+    cv: Condvar,
+    block_size: usize,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct BufferPoolState {
+    free: Vec<Vec<u8>>,
+    allocated: usize,
+}
+
+impl BufferPool {
+    /// `capacity` is at least 1.
+    pub fn new(block_size: usize, capacity: usize) -> Arc<Self> {
+        Arc::new(BufferPool {
+            state: Mutex::new(BufferPoolState { free: Vec::new(), allocated: 0 }),
+            cv: Condvar::new(),
+            block_size,
+            capacity: capacity.max(1),
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Blocks until a block is free; it comes back empty with room for
+    /// `block_size` bytes.
+    pub fn get(self: &Arc<Self>) -> PooledBuf {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(buf) = state.free.pop() {
+                return PooledBuf { buf, pool: Arc::clone(self) };
+            }
+            if state.allocated < self.capacity {
+                state.allocated += 1;
+                return PooledBuf { buf: Vec::with_capacity(self.block_size), pool: Arc::clone(self) };
+            }
+            state = self.cv.wait(state).unwrap();
+        }
+    }
+
+    /// Blocks [`get`](Self::get) could hand out right now without waiting.
+    pub fn idle(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.free.len() + (self.capacity - state.allocated)
+    }
+}
+
+/// A block borrowed from a [`BufferPool`]; returned to it when dropped.
+#[derive(Debug)]
+pub struct PooledBuf {
+    buf: Vec<u8>,
+    pool: Arc<BufferPool>,
+}
+
+impl PooledBuf {
+    /// Bytes that still fit before the block is full.
+    pub fn room(&self) -> usize {
+        self.pool.block_size.saturating_sub(self.buf.len())
+    }
+}
+
+impl std::ops::Deref for PooledBuf {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl std::ops::DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        let mut state = self.pool.state.lock().unwrap();
+        state.free.push(buf);
+        drop(state);
+        self.pool.cv.notify_one();
+    }
+}
@@ -0,0 +1,442 @@
+//! Read-only FUSE mount of an archive (`blitzarch mount`, `fuse` feature).
+//!
+//! The directory tree is built from the index when the archive is mounted;
+//! nothing is decompressed until a file is read. Reads go through
+//! [`crate::katana::LazyArchive::read_range`] (one frame of work per read on
+//! `--seekable-frames` archives) and the decoded data is kept in a
+//! [`BlockCache`] of fixed-size blocks, so paging through a file or reopening
+//! it in a viewer does not decode the same bytes again.
+//!
+//! [`MountTree`] and [`BlockCache`] know nothing about FUSE and are available
+//! in every build; [`mount`] needs the `fuse` feature on Linux or macOS.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::katana::EntryInfo;
+#[cfg(all(feature = "fuse", unix))]
+use crate::console::{eprintln, println};
+
+/// Inode of the archive root.
+pub const ROOT_INODE: u64 = 1;
+
+/// Size of the blocks cached by [`BlockCache`].
+pub const BLOCK_SIZE: u64 = 128 * 1024;
+
+/// Blocks decoded ahead of a sequential read that missed the cache.
+const READAHEAD_BLOCKS: u64 = 8;
+
+/// Default `--cache-size`.
+pub const DEFAULT_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    Dir,
+    File,
+    Symlink(String),
+}
+
+/// A file, symlink or directory of the mounted tree.
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// Path inside the archive, `""` for the root.
+    pub path: String,
+    pub kind: NodeKind,
+    pub size: u64,
+    pub permissions: Option<u32>,
+    pub mtime: Option<i64>,
+    pub parent: u64,
+    /// Child names and inodes (directories only), sorted by name.
+    pub children: BTreeMap<String, u64>,
+}
+
+/// Inode table of an archive. Directories missing from the index (Katana
+/// stores none) are derived from the file paths; inode `n` is `nodes[n - 1]`.
+#[derive(Debug, Clone)]
+pub struct MountTree {
+    nodes: Vec<Node>,
+}
+
+impl MountTree {
+    pub fn new(entries: &[EntryInfo]) -> Self {
+        let root = Node {
+            path: String::new(),
+            kind: NodeKind::Dir,
+            size: 0,
+            permissions: None,
+            mtime: None,
+            parent: ROOT_INODE,
+            children: BTreeMap::new(),
+        };
+        let mut tree = MountTree { nodes: vec![root] };
+        for entry in entries {
+            let path = entry.path.trim_matches('/');
+            if path.is_empty() {
+                continue;
+            }
+            let (parent_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+            let parent = tree.dir(parent_path);
+            let kind = match (&entry.symlink, entry.is_dir) {
+                (_, true) => NodeKind::Dir,
+                (Some(target), false) => NodeKind::Symlink(target.clone()),
+                (None, false) => NodeKind::File,
+            };
+            if let Some(&existing) = tree.nodes[(parent - 1) as usize].children.get(name) {
+                // Recorded directory after its files: keep the node, take its metadata
+                let node = &mut tree.nodes[(existing - 1) as usize];
+                if kind == NodeKind::Dir && node.kind == NodeKind::Dir {
+                    node.permissions = entry.permissions;
+                    node.mtime = entry.mtime;
+                }
+                continue;
+            }
+            let ino = tree.nodes.len() as u64 + 1;
+            tree.nodes.push(Node {
+                path: path.to_string(),
+                size: if kind == NodeKind::File { entry.size } else { 0 },
+                kind,
+                permissions: entry.permissions,
+                mtime: entry.mtime,
+                parent,
+                children: BTreeMap::new(),
+            });
+            tree.nodes[(parent - 1) as usize].children.insert(name.to_string(), ino);
+        }
+        tree
+    }
+
+    /// Inode of the directory at `path`, created (with its parents) if missing.
+    fn dir(&mut self, path: &str) -> u64 {
+        let mut ino = ROOT_INODE;
+        if path.is_empty() {
+            return ino;
+        }
+        let mut prefix_len = 0;
+        for name in path.split('/') {
+            prefix_len += name.len() + usize::from(prefix_len > 0);
+            ino = match self.nodes[(ino - 1) as usize].children.get(name) {
+                Some(&child) => child,
+                None => {
+                    let child = self.nodes.len() as u64 + 1;
+                    self.nodes.push(Node {
+                        path: path[..prefix_len].to_string(),
+                        kind: NodeKind::Dir,
+                        size: 0,
+                        permissions: None,
+                        mtime: None,
+                        parent: ino,
+                        children: BTreeMap::new(),
+                    });
+                    self.nodes[(ino - 1) as usize].children.insert(name.to_string(), child);
+                    child
+                }
+            };
+        }
+        ino
+    }
+
+    pub fn node(&self, ino: u64) -> Option<&Node> {
+        ino.checked_sub(1).and_then(|i| self.nodes.get(i as usize))
+    }
+
+    /// Inode of `name` in the directory `parent`.
+    pub fn lookup(&self, parent: u64, name: &str) -> Option<u64> {
+        self.node(parent)?.children.get(name).copied()
+    }
+
+    /// Inode of the entry at `path` (`""` for the root).
+    pub fn resolve(&self, path: &str) -> Option<u64> {
+        path.split('/').filter(|n| !n.is_empty()).try_fold(ROOT_INODE, |ino, name| self.lookup(ino, name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// Counters of a [`BlockCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub blocks: usize,
+    pub bytes: u64,
+}
+
+struct CachedBlock {
+    data: Arc<Vec<u8>>,
+    last_used: u64,
+}
+
+/// Decoded file blocks keyed by inode and block number, bounded by a byte
+/// budget; the least recently used blocks are evicted first.
+pub struct BlockCache {
+    budget: u64,
+    used: u64,
+    tick: u64,
+    blocks: HashMap<(u64, u64), CachedBlock>,
+    hits: u64,
+    misses: u64,
+}
+
+impl BlockCache {
+    pub fn new(budget_bytes: u64) -> Self {
+        BlockCache { budget: budget_bytes, used: 0, tick: 0, blocks: HashMap::new(), hits: 0, misses: 0 }
+    }
+
+    pub fn get(&mut self, ino: u64, block: u64) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        match self.blocks.get_mut(&(ino, block)) {
+            Some(cached) => {
+                cached.last_used = self.tick;
+                self.hits += 1;
+                Some(Arc::clone(&cached.data))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, ino: u64, block: u64, data: Arc<Vec<u8>>) {
+        let len = data.len() as u64;
+        if len > self.budget {
+            return;
+        }
+        if let Some(previous) = self.blocks.remove(&(ino, block)) {
+            self.used -= previous.data.len() as u64;
+        }
+        while self.used + len > self.budget {
+            let Some(oldest) = self.blocks.iter().min_by_key(|(_, b)| b.last_used).map(|(k, _)| *k) else { break };
+            if let Some(evicted) = self.blocks.remove(&oldest) {
+                self.used -= evicted.data.len() as u64;
+            }
+        }
+        self.tick += 1;
+        self.blocks.insert((ino, block), CachedBlock { data, last_used: self.tick });
+        self.used += len;
+    }
+
+    /// Reads `len` bytes at `offset` of the file `ino` (`size` bytes long),
+    /// fewer at its end. Missing blocks are decoded with `fetch(offset, len)`
+    /// together with up to [`READAHEAD_BLOCKS`] following ones.
+    pub fn read(
+        &mut self,
+        ino: u64,
+        size: u64,
+        offset: u64,
+        len: u64,
+        mut fetch: impl FnMut(u64, u64) -> Result<Vec<u8>, Box<dyn Error>>,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let end = size.min(offset.saturating_add(len));
+        let mut out = Vec::with_capacity(end.saturating_sub(offset) as usize);
+        let mut pos = offset;
+        while pos < end {
+            let block = pos / BLOCK_SIZE;
+            let data = match self.get(ino, block) {
+                Some(data) => data,
+                None => {
+                    let last = ((end - 1) / BLOCK_SIZE).max(block + READAHEAD_BLOCKS - 1).min((size - 1) / BLOCK_SIZE);
+                    let start = block * BLOCK_SIZE;
+                    let bytes = fetch(start, ((last + 1) * BLOCK_SIZE).min(size) - start)?;
+                    let mut first = None;
+                    for (i, chunk) in bytes.chunks(BLOCK_SIZE as usize).enumerate() {
+                        let data = Arc::new(chunk.to_vec());
+                        first.get_or_insert_with(|| Arc::clone(&data));
+                        self.insert(ino, block + i as u64, data);
+                    }
+                    first.ok_or("Unexpected end of entry data")?
+                }
+            };
+            let from = (pos - block * BLOCK_SIZE) as usize;
+            let to = (data.len() as u64).min(end - block * BLOCK_SIZE) as usize;
+            if from >= to {
+                return Err("Unexpected end of entry data".into());
+            }
+            out.extend_from_slice(&data[from..to]);
+            pos += (to - from) as u64;
+        }
+        Ok(out)
+    }
+
+    pub fn stats(&self) -> BlockCacheStats {
+        BlockCacheStats { hits: self.hits, misses: self.misses, blocks: self.blocks.len(), bytes: self.used }
+    }
+}
+
+/// Mounts the Katana archive at `archive_path` read-only on `mountpoint` and
+/// serves it until the filesystem is unmounted (`umount` / `fusermount -u`).
+#[cfg(all(feature = "fuse", unix))]
+pub fn mount(archive_path: &Path, mountpoint: &Path, password: Option<String>, cache_bytes: u64) -> Result<(), Box<dyn Error>> {
+    let archive = crate::katana::open_lazy_with_password(archive_path, password.as_deref())
+        .map_err(|e| format!("cannot mount {}: {e} (only Katana archives can be mounted)", archive_path.display()))?;
+    let tree = MountTree::new(&archive.entries());
+    if archive.is_encrypted() {
+        // Неверный пароль – ошибка сейчас, а не EIO на первом чтении
+        archive.check_password(password.as_deref())?;
+    }
+    let name = archive_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let options = [
+        fuser::MountOption::RO,
+        fuser::MountOption::FSName(format!("blitzarch:{name}")),
+        fuser::MountOption::Subtype("blitzarch".into()),
+        fuser::MountOption::DefaultPermissions,
+    ];
+    println!("[mount] {} mounted on {} ({} entries), unmount to stop", archive_path.display(), mountpoint.display(), tree.len() - 1);
+    let fs = fuse::ArchiveFs::new(archive, password, tree, BlockCache::new(cache_bytes));
+    fuser::mount2(fs, mountpoint, &options)?;
+    Ok(())
+}
+
+#[cfg(not(all(feature = "fuse", unix)))]
+pub fn mount(_archive_path: &Path, _mountpoint: &Path, _password: Option<String>, _cache_bytes: u64) -> Result<(), Box<dyn Error>> {
+    Err("this build has no FUSE support (rebuild with `--features fuse` on Linux or macOS)".into())
+}
+
+#[cfg(all(feature = "fuse", unix))]
+mod fuse {
+    use std::ffi::OsStr;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use fuser::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyOpen, Request};
+
+    use super::{BlockCache, MountTree, Node, NodeKind};
+    use crate::katana::LazyArchive;
+
+    /// The archive never changes while mounted.
+    const TTL: Duration = Duration::from_secs(3600);
+
+    pub(super) struct ArchiveFs {
+        archive: LazyArchive,
+        password: Option<String>,
+        tree: MountTree,
+        cache: BlockCache,
+        uid: u32,
+        gid: u32,
+    }
+
+    impl ArchiveFs {
+        pub(super) fn new(archive: LazyArchive, password: Option<String>, tree: MountTree, cache: BlockCache) -> Self {
+            // SAFETY: getuid/getgid cannot fail
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            ArchiveFs { archive, password, tree, cache, uid, gid }
+        }
+
+        fn attr(&self, ino: u64, node: &Node) -> FileAttr {
+            let mtime = node.mtime.and_then(|s| u64::try_from(s).ok()).map(|s| UNIX_EPOCH + Duration::from_secs(s)).unwrap_or(UNIX_EPOCH);
+            let (kind, default_perm, size) = match &node.kind {
+                NodeKind::Dir => (FileType::Directory, 0o755, 0),
+                NodeKind::File => (FileType::RegularFile, 0o644, node.size),
+                NodeKind::Symlink(target) => (FileType::Symlink, 0o777, target.len() as u64),
+            };
+            FileAttr {
+                ino,
+                size,
+                blocks: size.div_ceil(512),
+                atime: mtime,
+                mtime,
+                ctime: mtime,
+                crtime: mtime,
+                kind,
+                perm: (node.permissions.unwrap_or(default_perm) & 0o7777) as u16,
+                nlink: if kind == FileType::Directory { 2 } else { 1 },
+                uid: self.uid,
+                gid: self.gid,
+                rdev: 0,
+                blksize: super::BLOCK_SIZE as u32,
+                flags: 0,
+            }
+        }
+    }
+
+    impl Filesystem for ArchiveFs {
+        fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+            match name.to_str().and_then(|name| self.tree.lookup(parent, name)) {
+                Some(ino) => reply.entry(&TTL, &self.attr(ino, &self.tree.nodes[(ino - 1) as usize]), 0),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+            match self.tree.node(ino) {
+                Some(node) => reply.attr(&TTL, &self.attr(ino, node)),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+            match self.tree.node(ino).map(|n| &n.kind) {
+                Some(NodeKind::Symlink(target)) => reply.data(target.as_bytes()),
+                Some(_) => reply.error(libc::EINVAL),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+            match self.tree.node(ino).map(|n| &n.kind) {
+                Some(NodeKind::File) if flags & libc::O_ACCMODE == libc::O_RDONLY => reply.opened(0, fuser::consts::FOPEN_KEEP_CACHE),
+                Some(NodeKind::File) => reply.error(libc::EROFS),
+                Some(NodeKind::Dir) => reply.error(libc::EISDIR),
+                Some(NodeKind::Symlink(_)) => reply.error(libc::ELOOP),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn read(
+            &mut self,
+            _req: &Request<'_>,
+            ino: u64,
+            _fh: u64,
+            offset: i64,
+            size: u32,
+            _flags: i32,
+            _lock_owner: Option<u64>,
+            reply: ReplyData,
+        ) {
+            let Some(node) = self.tree.node(ino).filter(|n| n.kind == NodeKind::File) else {
+                return reply.error(libc::ENOENT);
+            };
+            let (path, file_size) = (node.path.clone(), node.size);
+            let (archive, password) = (&self.archive, self.password.as_deref());
+            let result = self.cache.read(ino, file_size, offset.max(0) as u64, size as u64, |start, len| {
+                archive.read_range(&path, start, len, password)
+            });
+            match result {
+                Ok(data) => reply.data(&data),
+                Err(e) => {
+                    eprintln!("[mount] Reading {} failed: {}", crate::private_logs::path(&path), e);
+                    reply.error(libc::EIO)
+                }
+            }
+        }
+
+        fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+            let Some(node) = self.tree.node(ino).filter(|n| n.kind == NodeKind::Dir) else {
+                return reply.error(libc::ENOTDIR);
+            };
+            let dots = [(ino, FileType::Directory, "."), (node.parent, FileType::Directory, "..")];
+            let children = node.children.iter().map(|(name, &child)| {
+                let kind = match self.tree.nodes[(child - 1) as usize].kind {
+                    NodeKind::Dir => FileType::Directory,
+                    NodeKind::File => FileType::RegularFile,
+                    NodeKind::Symlink(_) => FileType::Symlink,
+                };
+                (child, kind, name.as_str())
+            });
+            for (i, (child, kind, name)) in dots.into_iter().chain(children).enumerate().skip(offset.max(0) as usize) {
+                // Смещение следующей записи; буфер ответа полон – ядро спросит ещё
+                if reply.add(child, i as i64 + 1, kind, name) {
+                    break;
+                }
+            }
+            reply.ok();
+        }
+    }
+}
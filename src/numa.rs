@@ -0,0 +1,154 @@
+//! CPU affinity and NUMA awareness for shard workers.
+//!
+//! On multi-socket machines a shard worker that hops between sockets pays for
+//! remote memory accesses on every buffer it touches. With [`NumaPolicy::Auto`]
+//! each worker pins itself to one NUMA node (round-robin by shard id) for the
+//! lifetime of the shard. Codec threads spawned by zstd inherit the affinity of
+//! the worker, and buffers allocated after pinning are first-touched on the local
+//! node, so no explicit NUMA allocator is needed.
+//!
+//! Pinning is a no-op on single-node machines and on non-Linux platforms.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+/// Process-wide affinity policy for shard workers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NumaPolicy {
+    /// Leave thread placement to the OS scheduler (default).
+    Off,
+    /// Pin each shard worker to a NUMA node when more than one node is present.
+    Auto,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the affinity policy used by subsequent archive operations.
+pub fn set_policy(policy: NumaPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Returns the currently configured affinity policy.
+pub fn policy() -> NumaPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => NumaPolicy::Auto,
+        _ => NumaPolicy::Off,
+    }
+}
+
+/// CPU lists of all online NUMA nodes (empty when the topology is unknown).
+pub fn topology() -> &'static [Vec<usize>] {
+    static NODES: OnceLock<Vec<Vec<usize>>> = OnceLock::new();
+    NODES.get_or_init(detect_nodes)
+}
+
+#[cfg(target_os = "linux")]
+fn detect_nodes() -> Vec<Vec<usize>> {
+    let mut nodes: Vec<(usize, Vec<usize>)> = Vec::new();
+    let Ok(dir) = std::fs::read_dir("/sys/devices/system/node") else { return Vec::new() };
+    for entry in dir.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = name.strip_prefix("node").and_then(|n| n.parse::<usize>().ok()) else { continue };
+        if let Ok(list) = std::fs::read_to_string(entry.path().join("cpulist")) {
+            let cpus = parse_cpu_list(&list);
+            if !cpus.is_empty() {
+                nodes.push((id, cpus));
+            }
+        }
+    }
+    nodes.sort_by_key(|(id, _)| *id);
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn detect_nodes() -> Vec<Vec<usize>> {
+    Vec::new()
+}
+
+/// Parses the kernel cpulist format, e.g. `0-3,8-11,16`.
+pub(crate) fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((lo, hi)) => {
+                if let (Ok(lo), Ok(hi)) = (lo.trim().parse::<usize>(), hi.trim().parse::<usize>()) {
+                    cpus.extend(lo..=hi);
+                }
+            }
+            None => {
+                if let Ok(cpu) = part.trim().parse::<usize>() {
+                    cpus.push(cpu);
+                }
+            }
+        }
+    }
+    cpus
+}
+
+/// Restores the previous affinity mask of the current thread when dropped.
+///
+/// Rayon threads are reused across jobs, so a worker must not leave its pool
+/// thread pinned after the shard is done.
+pub struct AffinityGuard {
+    #[cfg(target_os = "linux")]
+    previous: libc::cpu_set_t,
+}
+
+impl Drop for AffinityGuard {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &self.previous);
+        }
+    }
+}
+
+/// Pins the calling thread to the NUMA node assigned to `worker_id`.
+///
+/// Returns `None` (and leaves the thread untouched) if the policy is `Off`,
+/// the machine has a single node, or the platform does not support affinity.
+pub fn pin_worker(worker_id: usize) -> Option<AffinityGuard> {
+    if policy() == NumaPolicy::Off {
+        return None;
+    }
+    let nodes = topology();
+    if nodes.len() < 2 {
+        return None;
+    }
+    pin_to_cpus(&nodes[worker_id % nodes.len()])
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_cpus(cpus: &[usize]) -> Option<AffinityGuard> {
+    unsafe {
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        let mut previous: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, size, &mut previous) != 0 {
+            return None;
+        }
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, size, &set) != 0 {
+            return None;
+        }
+        Some(AffinityGuard { previous })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_cpus(_cpus: &[usize]) -> Option<AffinityGuard> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_cpu_list;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert!(parse_cpu_list("").is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! File ordering strategies for shard workers.
+//!
+//! zstd only finds matches inside its window, so the order in which files are fed
+//! into a shard matters: similar content should sit next to each other. The
+//! [`TypeAwareOrder`] strategy sorts each shard by (extension, name cluster, size),
+//! which keeps e.g. all `.json` files together and places log rotations
+//! (`app.log`, `app.log.1`, `app-2024-01-02.log`) back-to-back.
+//!
+//! Strategies only reorder files *inside* a shard; the split into shards is not
+//! affected. New strategies implement [`FileOrder`] and are passed through
+//! [`crate::katana_stream::KatanaCreateOptions::ordering`].
+//!
+//! An [`OrderingManifest`] pins both the split and the order, for reproducible
+//! archives across incremental runs.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::vfs::Vfs;
+
+/// A pluggable ordering of the files of one shard.
+pub trait FileOrder: Send + Sync + std::fmt::Debug {
+    /// Short name used in logs and benchmarks.
+    fn name(&self) -> &'static str;
+
+    /// Reorders `files` in place; their metadata comes from `vfs`, the source
+    /// they are archived from.
+    fn order(&self, files: &mut [PathBuf], vfs: &dyn Vfs);
+}
+
+/// Keeps the directory walk order (the historical behaviour).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WalkOrder;
+
+impl FileOrder for WalkOrder {
+    fn name(&self) -> &'static str {
+        "walk"
+    }
+
+    fn order(&self, _files: &mut [PathBuf], _vfs: &dyn Vfs) {}
+}
+
+/// Sorts by lower-cased extension, then by name cluster, then by size.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TypeAwareOrder;
+
+impl FileOrder for TypeAwareOrder {
+    fn name(&self) -> &'static str {
+        "type"
+    }
+
+    fn order(&self, files: &mut [PathBuf], vfs: &dyn Vfs) {
+        // Sizes are looked up once per file, not per comparison
+        let mut keyed: Vec<((String, String, u64), PathBuf)> = files
+            .iter()
+            .map(|p| {
+                let size = vfs.metadata(p).map(|m| m.len).unwrap_or(0);
+                let (ext, cluster) = type_key(p);
+                ((ext, cluster, size), p.clone())
+            })
+            .collect();
+        keyed.sort_by(|(ka, pa), (kb, pb)| ka.cmp(kb).then_with(|| pa.cmp(pb)));
+        for (slot, (_, path)) in files.iter_mut().zip(keyed) {
+            *slot = path;
+        }
+    }
+}
+
+/// Returns `(extension, cluster)` for a path, ignoring rotation suffixes.
+///
+/// `app.log.3` → `("log", "app")`, `app-2024-01-02.log` → `("log", "app")`,
+/// `README` → `("", "readme")`.
+pub(crate) fn type_key(path: &Path) -> (String, String) {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    // Drop purely numeric trailing components (`.1`, `.20240102`)
+    let mut parts: Vec<&str> = name.split('.').collect();
+    while parts.len() > 1 && parts.last().is_some_and(|p| p.chars().all(|c| c.is_ascii_digit())) {
+        parts.pop();
+    }
+    let (stem, ext) = if parts.len() > 1 {
+        let ext = parts.pop().unwrap_or_default();
+        (parts.join("."), ext.to_string())
+    } else {
+        (parts.join("."), String::new())
+    };
+    // Strip counters and dates from the end of the stem (`app-2024-01-02`, `part_007`)
+    let cluster = stem
+        .trim_end_matches(|c: char| c.is_ascii_digit() || c == '-' || c == '_' || c == '.')
+        .to_string();
+    let cluster = if cluster.is_empty() { stem } else { cluster };
+    (ext, cluster)
+}
+
+/// Version written to (and required in) [`OrderingManifest::version`].
+pub const ORDERING_MANIFEST_VERSION: u32 = 1;
+
+/// The exact file-to-shard assignment of a Katana archive
+/// (`create --export-ordering`).
+///
+/// Passing it back with `--ordering-manifest` makes the next run keep every
+/// known file in the same shard at the same position, so shards whose files
+/// did not change compress to the same bytes and rsync-style deltas between
+/// the archives stay small. Files the manifest does not list go to new shards
+/// after the known ones; vanished files are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderingManifest {
+    pub version: u32,
+    /// Entry names (as stored in the index) of every shard, in archive order.
+    pub shards: Vec<Vec<String>>,
+}
+
+impl OrderingManifest {
+    pub fn new(shards: Vec<Vec<String>>) -> Self {
+        OrderingManifest { version: ORDERING_MANIFEST_VERSION, shards }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let manifest: OrderingManifest = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| format!("Invalid ordering manifest {}: {}", path.display(), e))?;
+        if manifest.version != ORDERING_MANIFEST_VERSION {
+            return Err(format!(
+                "Unsupported ordering manifest version {} in {} (expected {})",
+                manifest.version,
+                path.display(),
+                ORDERING_MANIFEST_VERSION
+            )
+            .into());
+        }
+        Ok(manifest)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Splits `files` into the manifest's shards (in manifest order) using
+    /// `name_of` to get their entry names. Returns those shards, with the ones
+    /// left empty dropped, and the files the manifest does not list.
+    pub fn assign(&self, files: &[PathBuf], name_of: impl Fn(&Path) -> String) -> (Vec<Vec<PathBuf>>, Vec<PathBuf>) {
+        let mut by_name: HashMap<String, &PathBuf> = files.iter().map(|p| (name_of(p), p)).collect();
+        let shards: Vec<Vec<PathBuf>> = self
+            .shards
+            .iter()
+            .map(|names| names.iter().filter_map(|n| by_name.remove(n)).cloned().collect::<Vec<_>>())
+            .filter(|shard| !shard.is_empty())
+            .collect();
+        // Новые файлы – в порядке обхода
+        let rest = files.iter().filter(|p| by_name.contains_key(&name_of(p))).cloned().collect();
+        (shards, rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{type_key, FileOrder, OrderingManifest, TypeAwareOrder};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_type_key_clusters_rotations() {
+        let key = |s: &str| type_key(&PathBuf::from(s));
+        assert_eq!(key("logs/app.log"), ("log".into(), "app".into()));
+        assert_eq!(key("logs/app.log.3"), ("log".into(), "app".into()));
+        assert_eq!(key("logs/app-2024-01-02.log"), ("log".into(), "app".into()));
+        assert_eq!(key("README"), ("".into(), "readme".into()));
+        assert_eq!(key("2024"), ("".into(), "2024".into()));
+    }
+
+    #[test]
+    fn test_manifest_keeps_known_shards() {
+        let manifest = OrderingManifest::new(vec![
+            vec!["b.txt".into(), "a.txt".into()],
+            vec!["gone.txt".into()],
+            vec!["c.txt".into()],
+        ]);
+        let files: Vec<PathBuf> = ["a.txt", "new.txt", "c.txt", "b.txt"].iter().map(PathBuf::from).collect();
+        let (shards, rest) = manifest.assign(&files, |p| p.to_string_lossy().into_owned());
+        let names = |v: &[PathBuf]| v.iter().map(|p| p.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        assert_eq!(shards.iter().map(|s| names(s)).collect::<Vec<_>>(), [vec!["b.txt", "a.txt"], vec!["c.txt"]]);
+        assert_eq!(names(&rest), ["new.txt"]);
+    }
+
+    #[test]
+    fn test_type_aware_order_groups_extensions() {
+        let mut files: Vec<PathBuf> = ["b.json", "app.log.1", "a.json", "app.log", "x.bin"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        TypeAwareOrder.order(&mut files, &crate::vfs::OsFs::default());
+        let names: Vec<_> = files.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+        assert_eq!(names, ["x.bin", "a.json", "b.json", "app.log", "app.log.1"]);
+    }
+}
@@ -0,0 +1,62 @@
+//! `--private-logs`: entry names never reach logs in plain text.
+//!
+//! Meant for encrypted archives, whose index hides the names from anyone
+//! without the password; stderr, warning reports (CLI JSON, GUI events) and
+//! error messages would otherwise leak them. A redacted name becomes a short
+//! keyed BLAKE3 tag, so the same file shows the same tag across the messages
+//! of a run. The key is random and never leaves the process: tags of known
+//! names cannot be precomputed, and tags of different runs do not match.
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static FROM_ENV: OnceLock<bool> = OnceLock::new();
+
+/// Turns redaction on or off for the whole process.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Also on when `BLITZARCH_PRIVATE_LOGS` is set to anything but `0`, for the
+/// GUI and other hosts without command-line flags (child CLI processes inherit it).
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+        || *FROM_ENV.get_or_init(|| std::env::var_os("BLITZARCH_PRIVATE_LOGS").is_some_and(|v| !v.is_empty() && v != "0"))
+}
+
+/// Key of this run's tags.
+static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+
+/// Tag standing in for `path`: `<name:` + 12 hex digits of its keyed BLAKE3 + `>`.
+fn tag(path: &str) -> String {
+    let key = KEY.get_or_init(rand::random);
+    let hash = blake3::keyed_hash(key, path.as_bytes()).to_hex();
+    format!("<name:{}>", &hash[..12])
+}
+
+/// `path` as it may appear in a log line.
+pub fn path(path: &str) -> Cow<'_, str> {
+    if enabled() { Cow::Owned(tag(path)) } else { Cow::Borrowed(path) }
+}
+
+/// Redacts a finished message: every occurrence of `known` (the entry the
+/// message is about) and every `"quoted"` string – paths are formatted with
+/// `{:?}` throughout the engine.
+pub fn message<'a>(message: &'a str, known: &str) -> Cow<'a, str> {
+    if !enabled() {
+        return Cow::Borrowed(message);
+    }
+    let message = if known.is_empty() { message.to_string() } else { message.replace(known, &tag(known)) };
+    let mut out = String::with_capacity(message.len());
+    let mut rest = message.as_str();
+    while let Some(start) = rest.find('"') {
+        let Some(len) = rest[start + 1..].find('"') else { break };
+        out.push_str(&rest[..start]);
+        out.push_str(&tag(&rest[start + 1..start + 1 + len]));
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
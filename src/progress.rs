@@ -50,6 +50,8 @@ pub struct ProgressState {
     pub elapsed_time: Duration,
     pub speed_mbps: f32,
     pub progress_percent: f32,
+    /// Current compression level of a `--adapt` or auto-level run; `None` while the level is fixed.
+    pub compression_level: Option<i32>,
 }
 
 impl ProgressState {
@@ -246,6 +248,7 @@ impl ProgressTracker {
             elapsed_time,
             speed_mbps,
             progress_percent,
+            compression_level: None,
         }
     }
     
@@ -262,6 +265,7 @@ impl ProgressTracker {
                 elapsed_time: Duration::from_secs(0),
                 speed_mbps: 0.0,
                 progress_percent: 0.0,
+                compression_level: None,
             };
         }
         
@@ -0,0 +1,100 @@
+//! Archives served over plain HTTP(S): `Range` requests against any web server
+//! or CDN that honours them (nginx, Apache, S3 website endpoints, GitHub
+//! releases behind their redirect).
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use super::{PinnedVersion, RangeSource};
+use crate::console::eprintln;
+
+/// Attempts after the first one for transport errors, 5xx and 429 replies.
+const RETRIES: u32 = 4;
+
+/// An archive at an `http://` or `https://` URL.
+pub struct HttpObject {
+    url: String,
+    agent: ureq::Agent,
+    version: PinnedVersion,
+}
+
+impl HttpObject {
+    pub fn new(url: &str) -> Result<Self, Box<dyn Error>> {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!("{url} is not an http:// or https:// URL").into());
+        }
+        Ok(HttpObject {
+            url: url.to_string(),
+            agent: ureq::AgentBuilder::new().timeout_connect(Duration::from_secs(30)).build(),
+            version: PinnedVersion::default(),
+        })
+    }
+
+    /// Ranged GET of `bytes=start-end` of the pinned version, retried like the
+    /// S3 client does.
+    fn get_range(&self, start: u64, end: u64) -> Result<ureq::Response, Box<dyn Error>> {
+        let range = format!("bytes={start}-{end}");
+        let mut attempt = 0;
+        loop {
+            let mut request = self.agent.get(&self.url).set("range", &range);
+            if let Some(etag) = self.version.if_match() {
+                request = request.set("if-match", etag);
+            }
+            let retry = match request.call() {
+                Ok(response) if response.status() == 206 => {
+                    self.version.check(self, response.header("etag"))?;
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    return Err(format!("{} does not support range requests (HTTP {} instead of 206)", self.url, response.status()).into())
+                }
+                Err(ureq::Error::Status(412, _)) => return Err(super::changed_while_reading(self)),
+                Err(ureq::Error::Status(code, response)) if (code >= 500 || code == 429) && attempt < RETRIES => {
+                    format!("HTTP {code} {}", response.status_text())
+                }
+                Err(ureq::Error::Status(code, response)) => {
+                    return Err(format!("GET {} failed with HTTP {code} {}", self.url, response.status_text()).into())
+                }
+                Err(ureq::Error::Transport(e)) if attempt < RETRIES => e.to_string(),
+                Err(e) => return Err(format!("GET {} failed: {e}", self.url).into()),
+            };
+            let delay = Duration::from_millis(250) * 2u32.pow(attempt);
+            eprintln!("[remote] GET {}: {retry}, retry {}/{} in {:?}", self.url, attempt + 1, RETRIES, delay);
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+impl fmt::Display for HttpObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.url)
+    }
+}
+
+impl RangeSource for HttpObject {
+    /// Asks for the first byte: the `Content-Range` of the 206 reply carries the
+    /// total size, and a server that ignores ranges is caught before anything
+    /// is downloaded. Its `ETag` pins the version read from then on.
+    fn object_len(&self) -> Result<u64, Box<dyn Error>> {
+        let response = self.get_range(0, 0)?;
+        let total = response.header("content-range").and_then(|v| v.rsplit_once('/')).and_then(|(_, total)| total.parse().ok());
+        total.ok_or_else(|| format!("{} did not report its size (Content-Range)", self.url).into())
+    }
+
+    fn read_into(&self, start: u64, len: u64, file: &mut File) -> Result<(), Box<dyn Error>> {
+        if len == 0 {
+            return Ok(());
+        }
+        let response = self.get_range(start, start + len - 1)?;
+        file.seek(SeekFrom::Start(start))?;
+        let copied = io::copy(&mut response.into_reader().take(len), file)?;
+        if copied != len {
+            return Err(format!("{} returned {copied} of {len} bytes at offset {start}", self.url).into());
+        }
+        Ok(())
+    }
+}
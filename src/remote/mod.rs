@@ -0,0 +1,204 @@
+//! Archives in object storage and on web servers (`remote` feature).
+//!
+//! `create --output s3://bucket/key` streams the archive into an S3 multipart
+//! upload while the shards are compressed, so nothing is staged on local disk.
+//! `extract` and `list` accept `s3://bucket/key` and `http(s)://…` archives:
+//! the footer and index are fetched with ranged reads and then only the shards
+//! holding the selected entries ([`RemoteArchive`]).
+//!
+//! Credentials and region come from the usual AWS variables
+//! (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_SESSION_TOKEN`,
+//! `AWS_REGION`); `AWS_ENDPOINT_URL` points at an S3-compatible store (MinIO,
+//! Ceph, R2), which is then addressed path-style. HTTP archives are fetched
+//! anonymously from servers that support `Range` requests.
+
+pub mod http;
+pub mod s3;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::console::println;
+
+/// First request of an open: the footer and, for all but huge indexes, the
+/// whole index behind it.
+const TAIL_FETCH: u64 = 1024 * 1024;
+
+/// A remote object read by byte ranges.
+pub trait RangeSource: Send + Sync + fmt::Display {
+    fn object_len(&self) -> Result<u64, Box<dyn Error>>;
+
+    /// Reads `len` bytes starting at `start` into the same offset of `file`.
+    fn read_into(&self, start: u64, len: u64, file: &mut File) -> Result<(), Box<dyn Error>>;
+}
+
+/// Version (`ETag`) of a remote object, pinned by its first reply.
+///
+/// Later range reads send it as `If-Match` and compare the `ETag` of each
+/// reply, so an object replaced between the index fetch and the shard fetches
+/// fails the read instead of mixing bytes of two versions. Weak ETags cannot
+/// be used with `If-Match`; they are only compared.
+#[derive(Debug, Default)]
+pub(crate) struct PinnedVersion(std::sync::OnceLock<Option<String>>);
+
+impl PinnedVersion {
+    /// Pins `etag` on the first call; later a different one is an error.
+    pub(crate) fn check(&self, object: &dyn fmt::Display, etag: Option<&str>) -> Result<(), Box<dyn Error>> {
+        match (self.0.get_or_init(|| etag.map(str::to_string)), etag) {
+            (Some(pinned), Some(etag)) if pinned != etag => Err(changed_while_reading(object)),
+            _ => Ok(()),
+        }
+    }
+
+    /// `If-Match` value for the next read, once a strong ETag is pinned.
+    pub(crate) fn if_match(&self) -> Option<&str> {
+        self.0.get().and_then(|etag| etag.as_deref()).filter(|etag| !etag.starts_with("W/"))
+    }
+}
+
+pub(crate) fn changed_while_reading(object: &dyn fmt::Display) -> Box<dyn Error> {
+    format!("{object} was replaced while it was being read; open it again").into()
+}
+
+/// The [`RangeSource`] behind an `s3://`, `http://` or `https://` URL.
+pub fn source_for(url: &str) -> Result<Box<dyn RangeSource>, Box<dyn Error>> {
+    if url.starts_with("s3://") {
+        Ok(Box::new(s3::S3Object::new(url)?))
+    } else {
+        Ok(Box::new(http::HttpObject::new(url)?))
+    }
+}
+
+/// A Katana archive at a URL, opened with its index only.
+///
+/// The archive is mirrored into a sparse local file of the same length:
+/// [`RemoteArchive::open`] fetches the footer and the index, and each later
+/// read fetches the shards it needs that are not there yet. Listing a hosted
+/// archive costs its index, pulling one file out of it one shard more. The
+/// local copy is removed when the archive is dropped.
+pub struct RemoteArchive {
+    source: Box<dyn RangeSource>,
+    copy: tempfile::NamedTempFile,
+    archive: crate::katana::LazyArchive,
+    /// Start offsets of the shards already in the local copy.
+    fetched: Mutex<HashSet<u64>>,
+}
+
+impl RemoteArchive {
+    /// Opens the archive at `url`, keeping the local copy in `scratch_dir`
+    /// (the system temp dir if `None`).
+    pub fn open(url: &str, scratch_dir: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        Self::open_with_password(url, scratch_dir, None)
+    }
+
+    /// [`RemoteArchive::open`] for archives whose index is encrypted
+    /// (`--hide-names`): `password` opens the index.
+    pub fn open_with_password(url: &str, scratch_dir: Option<&Path>, password: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let source = source_for(url)?;
+        let len = source.object_len()?;
+        let mut copy = match scratch_dir {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
+                crate::temp_manager::temp_file_in(dir, "remote")?
+            }
+            None => crate::temp_manager::temp_file("remote")?,
+        };
+        copy.as_file().set_len(len)?;
+
+        let tail = len.min(TAIL_FETCH);
+        source.read_into(len - tail, tail, copy.as_file_mut())?;
+        let (_, index_offset, _) = crate::katana::read_katana_footer(copy.as_file_mut())?;
+        if index_offset < len - tail {
+            source.read_into(index_offset, len - tail - index_offset, copy.as_file_mut())?;
+        }
+        let archive = crate::katana::open_lazy_with_password(copy.path(), password)?;
+        Ok(RemoteArchive { source, copy, archive, fetched: Mutex::new(HashSet::new()) })
+    }
+
+    /// The index, already local: entries, encryption, metadata.
+    pub fn archive(&self) -> &crate::katana::LazyArchive {
+        &self.archive
+    }
+
+    pub fn entries(&self) -> Vec<crate::katana::EntryInfo> {
+        self.archive.entries()
+    }
+
+    /// Archive metadata (`--meta`, `--comment`); see [`crate::katana::read_metadata`].
+    pub fn metadata(&self, password: Option<&str>) -> Result<std::collections::BTreeMap<String, String>, Box<dyn Error>> {
+        crate::katana::read_metadata(self.copy.path(), password)
+    }
+
+    /// Downloads the shards `selected` (every entry if empty) needs that are
+    /// not local yet, adjacent ones in a single request. Returns the bytes
+    /// fetched.
+    pub fn fetch(&self, selected: &[PathBuf], password: Option<&str>) -> Result<u64, Box<dyn Error>> {
+        let mut fetched = self.fetched.lock().unwrap_or_else(|e| e.into_inner());
+        let needed = crate::katana::shard_ranges_for(self.copy.path(), selected, password)?;
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for &(start, size) in &needed {
+            if fetched.contains(&start) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(last) if last.0 + last.1 == start => last.1 += size,
+                _ => ranges.push((start, size)),
+            }
+        }
+        if ranges.is_empty() {
+            return Ok(0);
+        }
+        let bytes: u64 = ranges.iter().map(|r| r.1).sum();
+        let len = self.copy.as_file().metadata()?.len();
+        println!(
+            "[remote] Fetching {:.2} of {:.2} MiB from {} in {} request(s)",
+            bytes as f64 / (1024.0 * 1024.0),
+            len as f64 / (1024.0 * 1024.0),
+            self.source,
+            ranges.len()
+        );
+        let mut file = self.copy.reopen()?;
+        for (start, size) in ranges {
+            self.source.read_into(start, size, &mut file)?;
+        }
+        fetched.extend(needed.iter().map(|r| r.0));
+        Ok(bytes)
+    }
+
+    /// Extracts `selected` (every entry if empty) into `output_dir`, fetching
+    /// only the shards it reads.
+    pub fn extract(&self, selected: &[PathBuf], password: Option<&str>, output_dir: &Path, strip_components: Option<u32>) -> Result<(), Box<dyn Error>> {
+        self.fetch(selected, password)?;
+        crate::extract::extract_files(self.copy.path(), selected, password, Some(output_dir), strip_components)
+    }
+
+    /// Streams one entry; see [`crate::katana::LazyArchive::open_entry`].
+    pub fn open_entry(&self, entry_path: &str, password: Option<&str>) -> Result<crate::katana::EntryReader, Box<dyn Error>> {
+        self.fetch(&[PathBuf::from(entry_path)], password)?;
+        self.archive.open_entry(entry_path, password)
+    }
+
+    /// The local copy, for readers that take a path. Only the index and the
+    /// shards fetched so far hold data.
+    pub fn local_path(&self) -> &Path {
+        self.copy.path()
+    }
+
+    /// Keeps the local copy beyond the archive; it is removed when the
+    /// returned path is dropped.
+    pub fn into_temp_path(self) -> tempfile::TempPath {
+        self.copy.into_temp_path()
+    }
+}
+
+/// Fetches what extracting `selected` (every entry if empty) from the archive
+/// at `url` reads into a local copy in `scratch_dir`; see [`RemoteArchive`].
+/// The copy is removed when the returned path is dropped.
+pub fn fetch_for_extract(url: &str, selected: &[PathBuf], password: Option<&str>, scratch_dir: &Path) -> Result<tempfile::TempPath, Box<dyn Error>> {
+    let archive = RemoteArchive::open_with_password(url, Some(scratch_dir), password)?;
+    archive.fetch(selected, password)?;
+    Ok(archive.into_temp_path())
+}
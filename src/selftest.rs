@@ -0,0 +1,182 @@
+//! Roundtrip self-test: archive a directory, extract it again and compare.
+//!
+//! `blitzarch selftest <dir>` lets users validate BlitzArch on their own data and
+//! filesystems before trusting it with backups. Everything happens in private
+//! temp directories; the source tree is only read.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use walkdir::WalkDir;
+use crate::console::println;
+
+/// Maximum number of mismatches printed by [`SelftestReport::print_summary`].
+const MAX_PRINTED_DIFFS: usize = 20;
+
+/// What went wrong for a single path during the roundtrip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// File exists in the source but was not extracted.
+    Missing(String),
+    /// Extracted file has no counterpart in the source.
+    Unexpected(String),
+    /// Extracted bytes differ from the source.
+    Content { path: String, source_size: u64, extracted_size: u64 },
+    /// Unix permission bits differ.
+    Mode { path: String, source: u32, extracted: u32 },
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Missing(p) => write!(f, "- {}  (missing after extract)", p),
+            Mismatch::Unexpected(p) => write!(f, "+ {}  (not in source)", p),
+            Mismatch::Content { path, source_size, extracted_size } => {
+                write!(f, "~ {}  (content differs, {} → {} bytes)", path, source_size, extracted_size)
+            }
+            Mismatch::Mode { path, source, extracted } => {
+                write!(f, "~ {}  (mode {:o} → {:o})", path, source, extracted)
+            }
+        }
+    }
+}
+
+/// Outcome of a roundtrip self-test.
+#[derive(Debug, Clone, Default)]
+pub struct SelftestReport {
+    pub files_checked: usize,
+    pub bytes_checked: u64,
+    pub archive_size: u64,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl SelftestReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    /// Prints a concise diff (first few mismatches) and a one-line verdict.
+    pub fn print_summary(&self) {
+        for m in self.mismatches.iter().take(MAX_PRINTED_DIFFS) {
+            println!("{}", m);
+        }
+        if self.mismatches.len() > MAX_PRINTED_DIFFS {
+            println!("… and {} more", self.mismatches.len() - MAX_PRINTED_DIFFS);
+        }
+        if self.passed() {
+            println!(
+                "[selftest] ✅ PASS | Files: {} | {:.2} MiB → {:.2} MiB archive",
+                self.files_checked,
+                self.bytes_checked as f64 / (1024.0 * 1024.0),
+                self.archive_size as f64 / (1024.0 * 1024.0),
+            );
+        } else {
+            println!(
+                "[selftest] ❌ FAIL | {} mismatches in {} files",
+                self.mismatches.len(),
+                self.files_checked
+            );
+        }
+    }
+}
+
+struct FileDigest {
+    size: u64,
+    mode: Option<u32>,
+    hash: blake3::Hash,
+}
+
+/// Archives `dir` into a temp file, extracts it into another temp dir and
+/// compares both trees by BLAKE3 hash (and Unix mode) in parallel.
+pub fn run_selftest(dir: &Path, threads: usize, password: Option<String>) -> Result<SelftestReport, Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()).into());
+    }
+    let work = tempfile::Builder::new().prefix(&format!("{}selftest-", crate::temp_manager::TEMP_PREFIX)).tempdir()?;
+    let archive_path = work.path().join("selftest.blz");
+    let out_dir = work.path().join("extracted");
+    std::fs::create_dir_all(&out_dir)?;
+
+    crate::katana_stream::create_katana_archive(
+        &[dir.to_path_buf()],
+        &archive_path,
+        threads,
+        0,
+        None,
+        password.clone(),
+        None,
+        None::<fn(crate::progress::ProgressState)>,
+    )?;
+    crate::katana_stream::perform_paranoid_check(&archive_path)?;
+    crate::katana::extract_katana_archive_internal(&archive_path, &out_dir, &[], password, None)?;
+
+    let source = digest_tree(dir)?;
+    let extracted = digest_tree(&out_dir)?;
+
+    let mut report = SelftestReport {
+        files_checked: source.len(),
+        bytes_checked: source.values().map(|d| d.size).sum(),
+        archive_size: std::fs::metadata(&archive_path)?.len(),
+        mismatches: Vec::new(),
+    };
+    for (path, src) in &source {
+        match extracted.get(path) {
+            None => report.mismatches.push(Mismatch::Missing(path.clone())),
+            Some(ext) if ext.hash != src.hash => report.mismatches.push(Mismatch::Content {
+                path: path.clone(),
+                source_size: src.size,
+                extracted_size: ext.size,
+            }),
+            Some(ext) => {
+                if let (Some(s), Some(e)) = (src.mode, ext.mode) {
+                    // SUID/SGID bits are stripped on extraction by design
+                    if s & 0o777 != e & 0o777 {
+                        report.mismatches.push(Mismatch::Mode { path: path.clone(), source: s & 0o777, extracted: e & 0o777 });
+                    }
+                }
+            }
+        }
+    }
+    for path in extracted.keys().filter(|p| !source.contains_key(*p)) {
+        report.mismatches.push(Mismatch::Unexpected(path.clone()));
+    }
+    Ok(report)
+}
+
+/// Hashes every regular file below `root` in parallel, keyed by normalized relative path.
+fn digest_tree(root: &Path) -> Result<BTreeMap<String, FileDigest>, Box<dyn Error>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in WalkDir::new(root) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push(entry.into_path());
+        }
+    }
+    let digests: Vec<Result<(String, FileDigest), std::io::Error>> = files
+        .par_iter()
+        .map(|p| {
+            let mut f = File::open(p)?;
+            let meta = f.metadata()?;
+            let mut hasher = blake3::Hasher::new();
+            let mut buf = vec![0u8; 1 << 16];
+            loop {
+                let n = f.read(&mut buf)?;
+                if n == 0 { break; }
+                hasher.update(&buf[..n]);
+            }
+            let rel = p.strip_prefix(root).unwrap_or(p);
+            let key = crate::katana::normalize_path(&rel.to_string_lossy());
+            Ok((key, FileDigest { size: meta.len(), mode: crate::fsx::maybe_unix_mode(&meta), hash: hasher.finalize() }))
+        })
+        .collect();
+    let mut map = BTreeMap::new();
+    for d in digests {
+        let (k, v) = d?;
+        map.insert(k, v);
+    }
+    Ok(map)
+}
@@ -0,0 +1,151 @@
+//! Decompressed shard cache for repeated partial extracts.
+//!
+//! Browsing an archive in the GUI previews files one by one, and every single
+//! file read decodes its shard from the start up to that file. With the cache
+//! enabled the first read decodes the whole shard once and keeps it in memory;
+//! further reads from that shard ([`crate::katana::open_entry`],
+//! [`crate::katana::extract_katana_to_memory`], ...) are served from RAM.
+//!
+//! Entries are keyed by archive path, size and mtime (a rewritten archive never
+//! hits stale data), shard offset and – for encrypted shards – a hash of the key,
+//! so a caller without the right password cannot read what another one cached.
+//! The cache is bounded by a byte budget and evicts least recently used shards;
+//! shards larger than the whole budget are never cached.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ShardKey {
+    archive: PathBuf,
+    archive_len: u64,
+    modified: Option<SystemTime>,
+    offset: u64,
+    key_tag: Option<[u8; 32]>,
+}
+
+struct CachedShard {
+    data: Arc<Vec<u8>>,
+    last_used: u64,
+}
+
+struct ShardCache {
+    budget: u64,
+    used: u64,
+    tick: u64,
+    entries: HashMap<ShardKey, CachedShard>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Counters of the shard cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShardCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Shards currently cached and their decompressed size.
+    pub shards: usize,
+    pub bytes: u64,
+}
+
+static CACHE: Mutex<Option<ShardCache>> = Mutex::new(None);
+
+fn cache() -> MutexGuard<'static, Option<ShardCache>> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Keeps decompressed shards in memory, up to `budget_bytes` in total.
+/// Replaces (and empties) a previously enabled cache.
+pub fn enable_shard_cache(budget_bytes: u64) {
+    *cache() = Some(ShardCache { budget: budget_bytes, used: 0, tick: 0, entries: HashMap::new(), hits: 0, misses: 0 });
+}
+
+/// Turns the cache off and frees everything it holds.
+pub fn disable_shard_cache() {
+    *cache() = None;
+}
+
+/// Frees all cached shards (e.g. when the GUI closes an archive); the cache
+/// stays enabled.
+pub fn clear_shard_cache() {
+    if let Some(cache) = cache().as_mut() {
+        cache.entries.clear();
+        cache.used = 0;
+    }
+}
+
+pub fn shard_cache_stats() -> ShardCacheStats {
+    match cache().as_ref() {
+        Some(c) => ShardCacheStats { hits: c.hits, misses: c.misses, shards: c.entries.len(), bytes: c.used },
+        None => ShardCacheStats::default(),
+    }
+}
+
+/// Read-only view of a cached shard (for `std::io::Cursor`).
+#[derive(Clone)]
+pub(crate) struct SharedBytes(pub(crate) Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// The decompressed shard at `offset` of `archive`: from the cache, or
+/// `decode`d and cached. `None` if the cache is off or the shard (`size`
+/// bytes) does not fit in it.
+pub(crate) fn get_or_decode(
+    archive: &Path,
+    offset: u64,
+    size: u64,
+    key_bytes: Option<&[u8; 32]>,
+    decode: impl FnOnce() -> Result<Vec<u8>, Box<dyn Error>>,
+) -> Result<Option<Arc<Vec<u8>>>, Box<dyn Error>> {
+    if cache().as_ref().is_none_or(|c| size > c.budget) {
+        return Ok(None);
+    }
+    let meta = std::fs::metadata(archive)?;
+    let key = ShardKey {
+        archive: archive.to_path_buf(),
+        archive_len: meta.len(),
+        modified: meta.modified().ok(),
+        offset,
+        key_tag: key_bytes.map(|k| *blake3::hash(k).as_bytes()),
+    };
+    {
+        let mut guard = cache();
+        let Some(cache) = guard.as_mut() else { return Ok(None) };
+        cache.tick += 1;
+        let tick = cache.tick;
+        if let Some(entry) = cache.entries.get_mut(&key) {
+            entry.last_used = tick;
+            cache.hits += 1;
+            return Ok(Some(Arc::clone(&entry.data)));
+        }
+        cache.misses += 1;
+    }
+    // Декодируем без блокировки – другие архивы не ждут
+    let data = Arc::new(decode()?);
+    let mut guard = cache();
+    let Some(cache) = guard.as_mut() else { return Ok(Some(data)) };
+    let len = data.len() as u64;
+    if len > cache.budget {
+        return Ok(Some(data));
+    }
+    while cache.used + len > cache.budget {
+        let Some(oldest) = cache.entries.iter().min_by_key(|(_, e)| e.last_used).map(|(k, _)| k.clone()) else { break };
+        if let Some(evicted) = cache.entries.remove(&oldest) {
+            cache.used -= evicted.data.len() as u64;
+        }
+    }
+    cache.tick += 1;
+    let last_used = cache.tick;
+    if let Some(previous) = cache.entries.insert(key, CachedShard { data: Arc::clone(&data), last_used }) {
+        cache.used -= previous.data.len() as u64;
+    }
+    cache.used += len;
+    Ok(Some(data))
+}
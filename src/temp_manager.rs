@@ -0,0 +1,246 @@
+//! Per-job temporary workspaces.
+//!
+//! Every archive operation that needs scratch files (e.g. decrypted shards during
+//! extraction) gets its own private directory under the system temp dir. File
+//! names inside a workspace come from a per-workspace counter, so concurrent jobs
+//! on the same archive can never collide, and the whole directory is removed when
+//! the workspace is dropped – even if a worker panicked halfway through.
+//!
+//! The workspace also keeps track of how many bytes its files currently occupy
+//! (and the peak) and refuses new reservations above a budget. By default the
+//! budget is the free space of the temp disk minus some headroom, probed on
+//! the first reservation (most workspaces, e.g. reads of unencrypted shards,
+//! never make one); `BLITZARCH_TEMP_LIMIT` (bytes) lowers it further.
+//!
+//! Standalone scratch files use the same naming: [`temp_file`] in the system
+//! temp dir, [`temp_file_in`] (hidden) next to a file about to be replaced. All
+//! of them start with [`TEMP_PREFIX`], so leftovers of a killed run are easy to
+//! find.
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Prefix of every scratch file and directory BlitzArch creates.
+pub const TEMP_PREFIX: &str = "blitzarch-";
+
+/// Scratch file `blitzarch-<label>-XXXXXX` in the system temp dir, removed on drop.
+pub fn temp_file(label: &str) -> io::Result<tempfile::NamedTempFile> {
+    tempfile::Builder::new().prefix(&format!("{}{}-", TEMP_PREFIX, label)).tempfile()
+}
+
+/// Hidden scratch file `.blitzarch-<label>-XXXXXX` in `dir`, for outputs that
+/// are renamed over their target (same filesystem).
+pub fn temp_file_in(dir: &Path, label: &str) -> io::Result<tempfile::NamedTempFile> {
+    tempfile::Builder::new().prefix(&format!(".{}{}-", TEMP_PREFIX, label)).tempfile_in(dir)
+}
+
+/// Environment variable capping every workspace created by [`TempWorkspace::new`], in bytes.
+pub const TEMP_LIMIT_ENV: &str = "BLITZARCH_TEMP_LIMIT";
+
+/// Free space left untouched on the temp disk so other processes keep working.
+const TEMP_HEADROOM: u64 = 256 * 1024 * 1024;
+
+/// Budget for a workspace in `dir`: free space minus headroom, capped by `limit`.
+fn default_budget(dir: &Path, limit: Option<u64>) -> Option<u64> {
+    let available = available_space(dir).map(|free| free.saturating_sub(TEMP_HEADROOM));
+    match (available, limit) {
+        (Some(a), Some(l)) => Some(a.min(l)),
+        (a, l) => a.or(l),
+    }
+}
+
+/// Free bytes on the disk holding `dir` (the mount point with the longest matching prefix).
+fn available_space(dir: &Path) -> Option<u64> {
+    let dir = dir.canonicalize().ok()?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|d| dir.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(|d| d.available_space())
+}
+
+/// A private scratch directory owned by a single job.
+#[derive(Debug)]
+pub struct TempWorkspace {
+    dir: tempfile::TempDir,
+    seq: AtomicU64,
+    bytes_in_use: AtomicU64,
+    peak_bytes: AtomicU64,
+    /// Set up front by [`Self::with_budget`], else on the first reservation.
+    budget: std::sync::OnceLock<Option<u64>>,
+    /// [`TEMP_LIMIT_ENV`] of a workspace made by [`Self::new`].
+    limit: Option<u64>,
+}
+
+impl TempWorkspace {
+    /// Creates a new workspace bounded by the free space of the temp disk and
+    /// [`TEMP_LIMIT_ENV`]. `label` only ends up in the directory name
+    /// (`blitzarch-<label>-XXXXXX`) to ease debugging.
+    pub fn new(label: &str) -> io::Result<Arc<Self>> {
+        let limit = std::env::var(TEMP_LIMIT_ENV).ok().and_then(|v| v.trim().parse().ok());
+        Self::create(label, std::sync::OnceLock::new(), limit)
+    }
+
+    /// Creates a new workspace that refuses to account more than `budget` bytes.
+    pub fn with_budget(label: &str, budget: Option<u64>) -> io::Result<Arc<Self>> {
+        Self::create(label, std::sync::OnceLock::from(budget), None)
+    }
+
+    fn create(label: &str, budget: std::sync::OnceLock<Option<u64>>, limit: Option<u64>) -> io::Result<Arc<Self>> {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("{}{}-", TEMP_PREFIX, label))
+            .tempdir()?;
+        Ok(Arc::new(Self {
+            dir,
+            seq: AtomicU64::new(0),
+            bytes_in_use: AtomicU64::new(0),
+            peak_bytes: AtomicU64::new(0),
+            budget,
+            limit,
+        }))
+    }
+
+    /// Bytes the workspace may hold; the first call on a [`Self::new`]
+    /// workspace probes the free space of the temp disk.
+    pub fn budget(&self) -> Option<u64> {
+        *self.budget.get_or_init(|| default_budget(self.path(), self.limit))
+    }
+
+    /// Directory backing this workspace.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Creates a new, uniquely named file inside the workspace.
+    ///
+    /// The file is deleted (and its reservation released) when the returned
+    /// [`WorkspaceFile`] is dropped.
+    pub fn create_file(self: &Arc<Self>, stem: &str) -> io::Result<WorkspaceFile> {
+        let n = self.seq.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.path().join(format!("{}-{}.tmp", stem, n));
+        let file = File::options().read(true).write(true).create_new(true).open(&path)?;
+        Ok(WorkspaceFile { workspace: Arc::clone(self), path, file: Some(file), reserved: 0 })
+    }
+
+    /// Bytes currently reserved by live files.
+    pub fn bytes_in_use(&self) -> u64 {
+        self.bytes_in_use.load(Ordering::Relaxed)
+    }
+
+    /// Highest value [`bytes_in_use`](Self::bytes_in_use) has reached.
+    pub fn peak_bytes(&self) -> u64 {
+        self.peak_bytes.load(Ordering::Relaxed)
+    }
+
+    fn reserve(&self, bytes: u64) -> io::Result<()> {
+        let now = self.bytes_in_use.fetch_add(bytes, Ordering::AcqRel) + bytes;
+        if let Some(budget) = self.budget() {
+            if now > budget {
+                self.bytes_in_use.fetch_sub(bytes, Ordering::AcqRel);
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("temp workspace budget exceeded ({} > {} bytes)", now, budget),
+                ));
+            }
+        }
+        self.peak_bytes.fetch_max(now, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn release(&self, bytes: u64) {
+        self.bytes_in_use.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+/// A scratch file owned by a [`TempWorkspace`].
+#[derive(Debug)]
+pub struct WorkspaceFile {
+    workspace: Arc<TempWorkspace>,
+    path: PathBuf,
+    file: Option<File>,
+    reserved: u64,
+}
+
+impl WorkspaceFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write handle created together with the file.
+    pub fn file_mut(&mut self) -> &mut File {
+        self.file.as_mut().expect("workspace file handle")
+    }
+
+    /// Accounts `bytes` more against the workspace (call before writing them).
+    pub fn reserve(&mut self, bytes: u64) -> io::Result<()> {
+        self.workspace.reserve(bytes)?;
+        self.reserved += bytes;
+        Ok(())
+    }
+
+    /// Opens an independent read handle on the file.
+    pub fn reopen(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+}
+
+impl Drop for WorkspaceFile {
+    fn drop(&mut self) {
+        // Close our handle first so removal also works on Windows
+        self.file.take();
+        let _ = std::fs::remove_file(&self.path);
+        self.workspace.release(self.reserved);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_budget, TempWorkspace, TEMP_HEADROOM};
+    use std::io::Write;
+
+    #[test]
+    fn test_default_budget_is_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        // Disk free space (when known) is capped by the explicit limit
+        assert!(default_budget(dir.path(), Some(1)).unwrap() <= 1);
+        if let Some(budget) = default_budget(dir.path(), None) {
+            assert!(budget <= u64::MAX - TEMP_HEADROOM);
+        }
+    }
+
+    #[test]
+    fn test_free_space_is_probed_on_first_reservation() {
+        let ws = TempWorkspace::new("lazy").unwrap();
+        let mut f = ws.create_file("shard").unwrap();
+        assert!(ws.budget.get().is_none());
+        // Tiny or nearly full temp disks may refuse even one byte
+        let _ = f.reserve(1);
+        assert!(ws.budget.get().is_some());
+    }
+
+    #[test]
+    fn test_workspace_naming_and_accounting() {
+        let ws = TempWorkspace::with_budget("test", Some(100)).unwrap();
+        let mut a = ws.create_file("shard").unwrap();
+        let b = ws.create_file("shard").unwrap();
+        assert_ne!(a.path(), b.path());
+
+        a.reserve(60).unwrap();
+        a.file_mut().write_all(&[0u8; 60]).unwrap();
+        assert_eq!(ws.bytes_in_use(), 60);
+        let mut c = ws.create_file("shard").unwrap();
+        assert!(c.reserve(50).is_err());
+
+        let a_path = a.path().to_path_buf();
+        drop(a);
+        assert!(!a_path.exists());
+        assert_eq!(ws.bytes_in_use(), 0);
+        assert_eq!(ws.peak_bytes(), 60);
+        c.reserve(50).unwrap();
+    }
+}
@@ -0,0 +1,383 @@
+//! Time-boxed create / verify (`--max-duration`).
+//!
+//! A [`TimeBudget`] is checked between files (create) or shards (verify). When
+//! it runs out the operation finishes what it is doing, leaves a valid archive
+//! behind and records where it stopped in a checkpoint file next to the archive
+//! (`<archive>.checkpoint`). Running the same command with `--resume` continues
+//! from there: create appends the remaining files, verify continues with the
+//! next unchecked shard. The checkpoint is removed once the work is complete.
+//!
+//! A checkpoint remembers the archive size it was written for; if the archive
+//! was modified in between, resuming fails instead of producing a mixed result.
+//!
+//! A budget can also be [paused](TimeBudget::pause) at any moment (`create
+//! --pausable` does so on Ctrl-C): the workers drain exactly as if the time had
+//! run out, so a laptop about to be suspended is left with a consistent archive
+//! plus checkpoint instead of a torn file.
+
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::katana::{AppendReport, VerifyReport};
+use crate::vfs::{OsFs, Vfs, VfsMetadata};
+use crate::console::eprintln;
+
+/// Format version written into new checkpoints.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Deadline shared by all workers of one job, plus the inputs they had to leave out.
+#[derive(Debug)]
+pub struct TimeBudget {
+    deadline: Option<Instant>,
+    paused: AtomicBool,
+    deferred: Mutex<Vec<PathBuf>>,
+}
+
+impl TimeBudget {
+    pub fn new(duration: Duration) -> Arc<Self> {
+        // Срок дальше, чем умеет Instant, – всё равно что без срока
+        Self::with_deadline(Instant::now().checked_add(duration))
+    }
+
+    /// A budget that only runs out when [paused](Self::pause).
+    pub fn unlimited() -> Arc<Self> {
+        Self::with_deadline(None)
+    }
+
+    fn with_deadline(deadline: Option<Instant>) -> Arc<Self> {
+        Arc::new(TimeBudget { deadline, paused: AtomicBool::new(false), deferred: Mutex::new(Vec::new()) })
+    }
+
+    pub fn expired(&self) -> bool {
+        self.paused() || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// Ends the budget now: the job stops at the next file and leaves a checkpoint.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Records inputs that were not processed because the budget ran out.
+    pub fn defer(&self, paths: &[PathBuf]) {
+        self.deferred.lock().unwrap().extend_from_slice(paths);
+    }
+
+    /// Inputs left over so far, sorted.
+    pub fn deferred(&self) -> Vec<PathBuf> {
+        let mut paths = self.deferred.lock().unwrap().clone();
+        paths.sort();
+        paths
+    }
+}
+
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    // A second Ctrl-C while draining aborts for real
+    if INTERRUPTS.fetch_add(1, Ordering::SeqCst) > 0 {
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Routes SIGINT (and SIGTERM on Unix) to [`on_interrupt`]: the first signal
+/// is only counted, a second one exits immediately.
+fn catch_interrupts() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        #[cfg(unix)]
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Pauses `budget` on the first SIGINT (and SIGTERM on Unix) instead of
+/// terminating the process; a second signal exits immediately. The handler
+/// stays installed for the rest of the process.
+pub fn pause_on_interrupt(budget: &Arc<TimeBudget>) {
+    catch_interrupts();
+    let budget = Arc::downgrade(budget);
+    std::thread::spawn(move || {
+        // Ends with the job (last strong reference gone) or after pausing it
+        while let Some(budget) = budget.upgrade() {
+            if INTERRUPTS.load(Ordering::SeqCst) > 0 {
+                eprintln!("[katana] ⏸ Pausing: finishing the current files (Ctrl-C again to abort)…");
+                budget.pause();
+                return;
+            }
+            drop(budget);
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    });
+}
+
+/// Cancels `token` on the first SIGINT (and SIGTERM on Unix) instead of
+/// terminating the process, so a long-running command (`watch`) can finish
+/// the batch it is writing; a second signal exits immediately.
+pub fn cancel_on_interrupt(token: &crate::cancel::CancellationToken) {
+    catch_interrupts();
+    let token = token.clone();
+    std::thread::spawn(move || {
+        while !token.is_cancelled() {
+            if INTERRUPTS.load(Ordering::SeqCst) > 0 {
+                eprintln!("[katana] Stopping: finishing the current batch (Ctrl-C again to abort)…");
+                token.cancel();
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    });
+}
+
+/// Parses durations like `90`, `45s`, `30m`, `2h` or `1h30m` (bare numbers are seconds).
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".into());
+    }
+    let too_long = || format!("invalid duration '{}': too long", s);
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse::<u64>().map(Duration::from_secs).map_err(|_| too_long());
+    }
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86_400,
+            _ => return Err(format!("invalid duration '{}': unknown unit '{}' (use s, m, h, d)", s, c)),
+        };
+        if digits.is_empty() {
+            return Err(format!("invalid duration '{}'", s));
+        }
+        let n: u64 = digits.parse().map_err(|_| too_long())?;
+        total = n.checked_mul(unit).and_then(|secs| total.checked_add(secs)).ok_or_else(too_long)?;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err(format!("invalid duration '{}': missing unit after {}", s, digits));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckpointKind {
+    Create,
+    Verify,
+}
+
+impl CheckpointKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CheckpointKind::Create => "create",
+            CheckpointKind::Verify => "verify",
+        }
+    }
+}
+
+/// Where an interrupted job stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub kind: CheckpointKind,
+    /// Archive size when the checkpoint was written.
+    pub archive_len: u64,
+    /// Original `create` inputs; entry names are derived relative to them.
+    #[serde(default)]
+    pub inputs: Vec<PathBuf>,
+    /// Files still to be added (`create`).
+    #[serde(default)]
+    pub remaining: Vec<PathBuf>,
+    /// First unchecked shard (`verify`).
+    #[serde(default)]
+    pub next_shard: usize,
+}
+
+/// `<archive>.checkpoint`
+pub fn checkpoint_path(archive_path: &Path) -> PathBuf {
+    let mut name = archive_path.as_os_str().to_owned();
+    name.push(".checkpoint");
+    PathBuf::from(name)
+}
+
+impl Checkpoint {
+    /// Checkpoint of `archive_path`, if there is one.
+    pub fn load(archive_path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        let data = match std::fs::read(checkpoint_path(archive_path)) {
+            Ok(d) => d,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let cp: Checkpoint = serde_json::from_slice(&data)?;
+        if cp.version > CHECKPOINT_VERSION {
+            return Err(format!("Unsupported checkpoint version {}", cp.version).into());
+        }
+        Ok(Some(cp))
+    }
+
+    /// Loads the checkpoint of `kind` and makes sure the archive is unchanged since.
+    fn load_for_resume(archive_path: &Path, kind: CheckpointKind) -> Result<Self, Box<dyn Error>> {
+        let cp = Self::load(archive_path)?
+            .filter(|cp| cp.kind == kind)
+            .ok_or_else(|| format!("No {} checkpoint to resume for {}", kind.name(), archive_path.display()))?;
+        if std::fs::metadata(archive_path)?.len() != cp.archive_len {
+            return Err(format!("{} changed since the checkpoint was written; start over", archive_path.display()).into());
+        }
+        Ok(cp)
+    }
+
+    pub fn save(&self, archive_path: &Path) -> Result<(), Box<dyn Error>> {
+        let path = checkpoint_path(archive_path);
+        let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let mut tmp = crate::temp_manager::temp_file_in(dir, "checkpoint")?;
+        serde_json::to_writer_pretty(&mut tmp, self)?;
+        tmp.persist(&path).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    pub fn clear(archive_path: &Path) -> io::Result<()> {
+        match std::fs::remove_file(checkpoint_path(archive_path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Call after a (possibly time-boxed) create: writes a checkpoint listing the
+/// files the budget left out, or removes a stale one. Returns the number of
+/// files still to be added.
+pub fn finish_create(archive_path: &Path, inputs: &[PathBuf], budget: Option<&TimeBudget>) -> Result<usize, Box<dyn Error>> {
+    let remaining = budget.map(TimeBudget::deferred).unwrap_or_default();
+    if remaining.is_empty() {
+        Checkpoint::clear(archive_path)?;
+        return Ok(0);
+    }
+    // Absolute paths: the resumed run may start from another working directory
+    let inputs = inputs.iter().map(std::path::absolute).collect::<io::Result<Vec<_>>>()?;
+    let remaining = remaining.iter().map(std::path::absolute).collect::<io::Result<Vec<_>>>()?;
+    let count = remaining.len();
+    Checkpoint {
+        version: CHECKPOINT_VERSION,
+        kind: CheckpointKind::Create,
+        archive_len: std::fs::metadata(archive_path)?.len(),
+        inputs,
+        remaining,
+        next_shard: 0,
+    }
+    .save(archive_path)?;
+    Ok(count)
+}
+
+/// Restricts [`OsFs`] to the files a checkpoint still lists, so the resumed
+/// append sees the original inputs (and derives the same entry names).
+#[derive(Debug)]
+struct RemainingFs {
+    files: std::collections::HashSet<PathBuf>,
+}
+
+impl Vfs for RemainingFs {
+    fn list_files(&self, root: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(OsFs::default().list_files(root)?.into_iter().filter(|p| self.files.contains(p)).collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<VfsMetadata> {
+        OsFs::default().metadata(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn io::Read + Send>> {
+        OsFs::default().open(path)
+    }
+}
+
+/// Continues an interrupted create by appending the files its checkpoint still
+/// lists. Returns the append report and the number of files left over (0 once
+/// the archive is complete; the checkpoint is then removed).
+pub fn resume_create(
+    archive_path: &Path,
+    threads: usize,
+    password: Option<String>,
+    compression_level: Option<i32>,
+    budget: Option<Arc<TimeBudget>>,
+) -> Result<(AppendReport, usize), Box<dyn Error>> {
+    let cp = Checkpoint::load_for_resume(archive_path, CheckpointKind::Create)?;
+    let vfs = Arc::new(RemainingFs { files: cp.remaining.iter().cloned().collect() });
+    let report = crate::katana::append_with_source(
+        archive_path,
+        &cp.inputs,
+        threads,
+        password,
+        compression_level,
+        Some(vfs),
+        budget.clone(),
+    )?;
+    let left = finish_create(archive_path, &cp.inputs, budget.as_deref())?;
+    Ok((report, left))
+}
+
+/// Verifies an archive within `budget`, continuing from the checkpoint if
+/// `resume` is set. Stopping early writes a checkpoint
+/// ([`VerifyReport::resume_at`] is then set); a complete run removes it.
+///
+/// Damaged shards do not stop the run: they are collected in
+/// [`VerifyReport::corrupt`], so one pass reports every affected entry.
+pub fn verify_timeboxed(
+    archive_path: &Path,
+    password: Option<&str>,
+    deep: bool,
+    budget: Option<&TimeBudget>,
+    resume: bool,
+    dictionaries: &crate::dictionary::Dictionaries,
+) -> Result<VerifyReport, Box<dyn Error>> {
+    let start = if resume { Checkpoint::load_for_resume(archive_path, CheckpointKind::Verify)?.next_shard } else { 0 };
+    let report = crate::katana::verify_archive_impl(archive_path, password, deep, start, budget, true, dictionaries)?;
+    match report.resume_at {
+        Some(next_shard) => Checkpoint {
+            version: CHECKPOINT_VERSION,
+            kind: CheckpointKind::Verify,
+            archive_len: std::fs::metadata(archive_path)?.len(),
+            inputs: Vec::new(),
+            remaining: Vec::new(),
+            next_shard,
+        }
+        .save(archive_path)?,
+        None => Checkpoint::clear(archive_path)?,
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_duration;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("45s").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86_400));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("99999999999999999999").is_err());
+        assert!(parse_duration("999999999999999999d").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+    }
+}
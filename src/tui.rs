@@ -0,0 +1,474 @@
+//! Interactive terminal browser (`blitzarch tui`).
+//!
+//! Shows the archive as a directory tree read straight from the index, with a
+//! tree-wide search, marks on files and whole directories and extraction of the
+//! marked entries with a live progress gauge – interactive restores on servers
+//! without the GUI.
+//!
+//! [`Browser`] holds the navigation state and knows nothing about the terminal,
+//! [`run_tui`] draws it with ratatui and maps keys onto it (`tui` feature).
+//! While the screen belongs to ratatui, engine output is captured through
+//! [`crate::console`] and shown in the footer instead of being printed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "tui")]
+use std::sync::{mpsc, Arc, Mutex};
+#[cfg(feature = "tui")]
+use std::time::Duration;
+
+#[cfg(feature = "tui")]
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use crate::katana::EntryInfo;
+#[cfg(feature = "tui")]
+use crate::progress::ProgressState;
+
+/// One line of the browser listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Row {
+    Dir { name: String, path: String, files: usize, bytes: u64 },
+    File { name: String, path: String, size: u64 },
+}
+
+impl Row {
+    pub fn path(&self) -> &str {
+        match self {
+            Row::Dir { path, .. } | Row::File { path, .. } => path,
+        }
+    }
+}
+
+/// Marks of a directory's files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkState {
+    None,
+    Some,
+    All,
+}
+
+/// Navigation, search and mark state over the files of an archive.
+#[derive(Debug, Clone)]
+pub struct Browser {
+    /// File paths and sizes, sorted by path.
+    files: Vec<(String, u64)>,
+    cwd: String,
+    search: String,
+    marked: BTreeSet<String>,
+    cursor: usize,
+}
+
+impl Browser {
+    /// Directory entries of classic archives are left out; directories are
+    /// derived from the file paths.
+    pub fn new(entries: &[EntryInfo]) -> Self {
+        let mut files: Vec<(String, u64)> = entries
+            .iter()
+            .filter(|e| !e.is_dir)
+            .map(|e| (e.path.trim_matches('/').to_string(), e.size))
+            .collect();
+        files.sort();
+        files.dedup_by(|a, b| a.0 == b.0);
+        Browser { files, cwd: String::new(), search: String::new(), marked: BTreeSet::new(), cursor: 0 }
+    }
+
+    /// Current directory, `""` at the archive root.
+    pub fn cwd(&self) -> &str {
+        &self.cwd
+    }
+
+    pub fn search(&self) -> &str {
+        &self.search
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Children of the current directory (directories first), or with a search
+    /// every file of the archive whose path contains it (case-insensitive).
+    pub fn rows(&self) -> Vec<Row> {
+        if !self.search.is_empty() {
+            let needle = self.search.to_lowercase();
+            return self
+                .files
+                .iter()
+                .filter(|(path, _)| path.to_lowercase().contains(&needle))
+                .map(|(path, size)| Row::File { name: path.clone(), path: path.clone(), size: *size })
+                .collect();
+        }
+        let mut dirs: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+        let mut files = Vec::new();
+        for (path, size) in self.files_under(&self.cwd) {
+            let rest = if self.cwd.is_empty() { path.as_str() } else { &path[self.cwd.len() + 1..] };
+            match rest.split_once('/') {
+                Some((dir, _)) => {
+                    let stats = dirs.entry(dir).or_default();
+                    stats.0 += 1;
+                    stats.1 += size;
+                }
+                None => files.push(Row::File { name: rest.to_string(), path: path.clone(), size: *size }),
+            }
+        }
+        dirs.into_iter()
+            .map(|(name, (files, bytes))| Row::Dir { name: name.to_string(), path: self.join(name), files, bytes })
+            .chain(files)
+            .collect()
+    }
+
+    pub fn move_cursor(&mut self, delta: isize) {
+        let len = self.rows().len();
+        self.cursor = self.cursor.saturating_add_signed(delta).min(len.saturating_sub(1));
+    }
+
+    /// Opens the directory under the cursor; a file row does nothing.
+    pub fn enter(&mut self) {
+        if let Some(Row::Dir { path, .. }) = self.rows().get(self.cursor) {
+            self.cwd = path.clone();
+            self.search.clear();
+            self.cursor = 0;
+        }
+    }
+
+    /// Goes to the parent directory, keeping the cursor on the one we came from.
+    pub fn leave(&mut self) {
+        if self.cwd.is_empty() {
+            return;
+        }
+        let left = std::mem::take(&mut self.cwd);
+        self.cwd = left.rsplit_once('/').map(|(parent, _)| parent.to_string()).unwrap_or_default();
+        self.cursor = self.rows().iter().position(|r| r.path() == left).unwrap_or(0);
+    }
+
+    pub fn set_search(&mut self, search: &str) {
+        self.search = search.to_string();
+        self.cursor = 0;
+    }
+
+    /// Marks the row under the cursor – all files below it for a directory – or
+    /// unmarks it if it is fully marked already.
+    pub fn toggle_mark(&mut self) {
+        let Some(row) = self.rows().into_iter().nth(self.cursor) else { return };
+        let paths: Vec<String> = match &row {
+            Row::Dir { path, .. } => self.files_under(path).map(|(p, _)| p.clone()).collect(),
+            Row::File { path, .. } => vec![path.clone()],
+        };
+        if self.mark_state(&row) == MarkState::All {
+            for path in &paths {
+                self.marked.remove(path);
+            }
+        } else {
+            self.marked.extend(paths);
+        }
+    }
+
+    /// Marks every row shown, or unmarks them if they are all marked.
+    pub fn toggle_all(&mut self) {
+        let rows = self.rows();
+        let unmark = !rows.is_empty() && rows.iter().all(|r| self.mark_state(r) == MarkState::All);
+        let paths: Vec<String> = rows
+            .iter()
+            .flat_map(|row| match row {
+                Row::Dir { path, .. } => self.files_under(path).map(|(p, _)| p.clone()).collect(),
+                Row::File { path, .. } => vec![path.clone()],
+            })
+            .collect();
+        for path in paths {
+            if unmark {
+                self.marked.remove(&path);
+            } else {
+                self.marked.insert(path);
+            }
+        }
+    }
+
+    pub fn mark_state(&self, row: &Row) -> MarkState {
+        match row {
+            Row::File { path, .. } if self.marked.contains(path) => MarkState::All,
+            Row::File { .. } => MarkState::None,
+            Row::Dir { path, .. } => {
+                let (total, marked) = self
+                    .files_under(path)
+                    .fold((0, 0), |(t, m), (p, _)| (t + 1, m + self.marked.contains(p) as usize));
+                match marked {
+                    0 => MarkState::None,
+                    m if m == total => MarkState::All,
+                    _ => MarkState::Some,
+                }
+            }
+        }
+    }
+
+    /// Marked files sorted by path, ready for the extractors.
+    pub fn marked_paths(&self) -> Vec<PathBuf> {
+        self.marked.iter().map(PathBuf::from).collect()
+    }
+
+    /// Number of marked files and their total size.
+    pub fn marked_totals(&self) -> (usize, u64) {
+        self.files.iter().filter(|(p, _)| self.marked.contains(p)).fold((0, 0), |(n, b), (_, s)| (n + 1, b + s))
+    }
+
+    /// What `x` extracts: the marked files, or the row under the cursor.
+    pub fn selection(&self) -> Vec<PathBuf> {
+        if !self.marked.is_empty() {
+            return self.marked_paths();
+        }
+        match self.rows().into_iter().nth(self.cursor) {
+            Some(Row::Dir { path, .. }) => self.files_under(&path).map(|(p, _)| PathBuf::from(p)).collect(),
+            Some(Row::File { path, .. }) => vec![PathBuf::from(path)],
+            None => Vec::new(),
+        }
+    }
+
+    fn files_under<'a>(&'a self, dir: &'a str) -> impl Iterator<Item = &'a (String, u64)> + 'a {
+        self.files.iter().filter(move |(path, _)| {
+            dir.is_empty() || (path.len() > dir.len() && path.starts_with(dir) && path.as_bytes()[dir.len()] == b'/')
+        })
+    }
+
+    fn join(&self, name: &str) -> String {
+        if self.cwd.is_empty() { name.to_string() } else { format!("{}/{}", self.cwd, name) }
+    }
+}
+
+/// Running or finished extraction shown at the bottom of the screen.
+#[cfg(feature = "tui")]
+enum Job {
+    Idle(String),
+    Running { progress: Arc<Mutex<Option<ProgressState>>>, done: mpsc::Receiver<Result<String, String>> },
+}
+
+/// Last line the engine wrote while the TUI owns the terminal.
+#[cfg(feature = "tui")]
+#[derive(Clone, Default)]
+struct ConsoleLine {
+    pending: Arc<Mutex<String>>,
+    last: Arc<Mutex<String>>,
+}
+
+#[cfg(feature = "tui")]
+impl ConsoleLine {
+    fn take(&self) -> String {
+        std::mem::take(&mut *self.last.lock().unwrap())
+    }
+}
+
+#[cfg(feature = "tui")]
+impl std::io::Write for ConsoleLine {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut pending = self.pending.lock().unwrap();
+        for c in String::from_utf8_lossy(buf).chars() {
+            match c {
+                '\n' => {
+                    let line = std::mem::take(&mut *pending);
+                    if !line.trim().is_empty() {
+                        *self.last.lock().unwrap() = line.trim().to_string();
+                    }
+                }
+                // Progress lines redraw themselves with `\r`
+                '\r' => pending.clear(),
+                c if c.is_control() => {}
+                c => pending.push(c),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens the terminal UI for `archive_path`; extracted files go to `output_dir`.
+#[cfg(feature = "tui")]
+pub fn run_tui(archive_path: &Path, password: Option<String>, output_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let entries = crate::extract::list_entries(archive_path, password.as_deref())?;
+    let mut browser = Browser::new(&entries);
+    let title = archive_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let console = ConsoleLine::default();
+    let _console = crate::console::capture(console.clone());
+    let mut terminal = ratatui::init();
+    let mut job = Job::Idle(format!("{} files · Space mark · a all · / search · x extract · q quit", browser.files.len()));
+    let mut searching = false;
+    let mut last_line = String::new();
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        loop {
+            if let Job::Running { done, .. } = &job {
+                if let Ok(outcome) = done.try_recv() {
+                    // The summary replaces whatever the extractor printed last
+                    console.take();
+                    job = Job::Idle(match outcome {
+                        Ok(summary) => summary,
+                        Err(e) => format!("Extraction failed: {}", e),
+                    });
+                }
+            }
+            let line = console.take();
+            if !line.is_empty() {
+                match &mut job {
+                    Job::Idle(message) => *message = line,
+                    Job::Running { .. } => last_line = line,
+                }
+            }
+            terminal.draw(|frame| draw(frame, &browser, &title, &job, &last_line, searching))?;
+            if !event::poll(Duration::from_millis(100))? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if searching {
+                let mut search = browser.search().to_string();
+                match key.code {
+                    KeyCode::Enter => searching = false,
+                    KeyCode::Esc => {
+                        searching = false;
+                        search.clear();
+                    }
+                    KeyCode::Backspace => {
+                        search.pop();
+                    }
+                    KeyCode::Char(c) => search.push(c),
+                    _ => {}
+                }
+                browser.set_search(&search);
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Esc if !browser.search().is_empty() => browser.set_search(""),
+                KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => browser.move_cursor(-1),
+                KeyCode::Down | KeyCode::Char('j') => browser.move_cursor(1),
+                KeyCode::PageUp => browser.move_cursor(-20),
+                KeyCode::PageDown => browser.move_cursor(20),
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => browser.enter(),
+                KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => browser.leave(),
+                KeyCode::Char(' ') => {
+                    browser.toggle_mark();
+                    browser.move_cursor(1);
+                }
+                KeyCode::Char('a') => browser.toggle_all(),
+                KeyCode::Char('/') => searching = true,
+                KeyCode::Char('x') if matches!(job, Job::Idle(_)) => {
+                    let selection = browser.selection();
+                    if !selection.is_empty() {
+                        last_line.clear();
+                        job = start_extract(archive_path, password.clone(), output_dir, selection);
+                    }
+                }
+                _ => {}
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(not(feature = "tui"))]
+pub fn run_tui(_archive_path: &Path, _password: Option<String>, _output_dir: &Path) -> Result<(), Box<dyn Error>> {
+    Err("this build has no terminal UI (rebuild with `--features tui`)".into())
+}
+
+#[cfg(feature = "tui")]
+fn start_extract(archive_path: &Path, password: Option<String>, output_dir: &Path, selection: Vec<PathBuf>) -> Job {
+    let progress = Arc::new(Mutex::new(None));
+    let (tx, done) = mpsc::channel();
+    let (archive, out, shared) = (archive_path.to_path_buf(), output_dir.to_path_buf(), Arc::clone(&progress));
+    std::thread::spawn(move || {
+        let count = selection.len();
+        let (outcome, warnings) = crate::warnings::capture(|| -> Result<(), Box<dyn Error>> {
+            if crate::katana::is_katana_archive(&archive)? {
+                let callback = move |state: ProgressState| *shared.lock().unwrap() = Some(state);
+                crate::katana::extract_katana_archive_with_progress(&archive, &out, &selection, password, None, Some(callback))
+            } else {
+                crate::extract::extract_files(&archive, &selection, password.as_deref(), Some(&out), None)
+            }
+        });
+        let summary = |()| match warnings.last() {
+            None => format!("Extracted {} file(s) to {}", count, out.display()),
+            Some(last) => format!("Extracted {} file(s) to {} · {} warning(s), last: {}", count, out.display(), warnings.len(), last.message),
+        };
+        let _ = tx.send(outcome.map(summary).map_err(|e| e.to_string()));
+    });
+    Job::Running { progress, done }
+}
+
+#[cfg(feature = "tui")]
+fn draw(frame: &mut Frame, browser: &Browser, title: &str, job: &Job, last_line: &str, searching: bool) {
+    let [header, body, footer] =
+        Layout::vertical([Constraint::Length(1), Constraint::Min(3), Constraint::Length(3)]).areas(frame.area());
+
+    let (marked, marked_bytes) = browser.marked_totals();
+    let location = if browser.search().is_empty() { format!("/{}", browser.cwd()) } else { format!("search: {}", browser.search()) };
+    frame.render_widget(
+        Paragraph::new(format!(" {}  {}  [{} marked, {}]", title, location, marked, human_bytes(marked_bytes))).bold(),
+        header,
+    );
+
+    let rows = browser.rows();
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|row| {
+            let mark = match browser.mark_state(row) {
+                MarkState::All => "[x]",
+                MarkState::Some => "[~]",
+                MarkState::None => "[ ]",
+            };
+            ListItem::new(match row {
+                Row::Dir { name, files, bytes, .. } => {
+                    Line::from(format!("{} {}/  ({} files, {})", mark, name, files, human_bytes(*bytes))).bold()
+                }
+                Row::File { name, size, .. } => Line::from(format!("{} {}  {}", mark, name, human_bytes(*size))),
+            })
+        })
+        .collect();
+    let mut state = ListState::default().with_selected((!rows.is_empty()).then_some(browser.cursor()));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::default().borders(Borders::ALL))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+        body,
+        &mut state,
+    );
+
+    let block = Block::default().borders(Borders::ALL);
+    match job {
+        _ if searching => frame.render_widget(Paragraph::new(format!("/{}▏", browser.search())).block(block), footer),
+        Job::Idle(message) => frame.render_widget(Paragraph::new(message.as_str()).block(block), footer),
+        Job::Running { progress, .. } => {
+            let (ratio, label) = match progress.lock().unwrap().as_ref() {
+                Some(state) => (
+                    (state.progress_percent as f64 / 100.0).clamp(0.0, 1.0),
+                    format!("{}/{} files · {:.1} MB/s", state.processed_files, state.total_files, state.speed_mbps),
+                ),
+                None => (0.0, "starting…".to_string()),
+            };
+            let title = if last_line.is_empty() { "Extracting".to_string() } else { format!("Extracting · {}", last_line) };
+            frame.render_widget(Gauge::default().block(block.title(title)).ratio(ratio).label(label), footer);
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
@@ -0,0 +1,147 @@
+//! Conversion of classic (MFUS) archives to the Katana format (`blitzarch upgrade`).
+//!
+//! The classic archive is unpacked into a staging directory next to the
+//! target, packed again as a Katana archive with the same metadata and – for
+//! encrypted archives – the same password, and the result is compared entry by
+//! entry (path, size, BLAKE3 of the content) with what the old archive held.
+//! Only then is it renamed over the target, so a failed or interrupted upgrade
+//! leaves the original untouched.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::formats::ArchiveKind;
+
+/// Outcome of [`upgrade_classic_archive`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradeReport {
+    /// Where the Katana archive was written.
+    pub output: PathBuf,
+    /// Files carried over (directories not counted).
+    pub files: usize,
+    /// Their total uncompressed size.
+    pub bytes: u64,
+    pub encrypted: bool,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+/// Converts the classic archive at `archive_path` to a Katana archive at
+/// `output` (which may be `archive_path` itself for an in-place upgrade).
+///
+/// Archive metadata (`--meta`, `--comment`) and entry permissions are kept; an
+/// encrypted archive needs its `password` and is encrypted again with it.
+/// Returns `Ok(None)` without touching anything if the archive already is a
+/// Katana archive. An existing `output` other than the archive itself is only
+/// replaced with `force`.
+pub fn upgrade_classic_archive(
+    archive_path: &Path,
+    output: &Path,
+    password: Option<&str>,
+    threads: usize,
+    force: bool,
+) -> Result<Option<UpgradeReport>, Box<dyn Error>> {
+    match crate::formats::detect(archive_path)? {
+        ArchiveKind::Classic => {}
+        ArchiveKind::Katana => return Ok(None),
+        _ => return Err(format!("{} is not a classic BlitzArch archive", archive_path.display()).into()),
+    }
+    let _lock = crate::fsx::OutputLock::acquire(output)?;
+    if !force && output.symlink_metadata().is_ok() && !same_file(archive_path, output) {
+        return Err(format!("{} already exists (use --force to overwrite it)", output.display()).into());
+    }
+
+    let mut reader = crate::extract::ArchiveReader::new(File::open(archive_path)?)?;
+    let index = reader.read_footer_and_index()?;
+    let encrypted = index.header.salt.is_some();
+    match (encrypted, password) {
+        (true, None) => return Err(format!("{} is encrypted: --password (or BLITZARCH_PASSWORD) is required", archive_path.display()).into()),
+        (false, Some(_)) => return Err(format!("{} is not encrypted; upgrade it without a password and run `blitzarch encrypt` afterwards", archive_path.display()).into()),
+        _ => {}
+    }
+
+    // Рядом с целью: переименование атомарно, места на диске хватит там же
+    let dir = match output.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let staging = tempfile::Builder::new().prefix(".blitzarch-upgrade-").tempdir_in(dir)?;
+    let tree = staging.path().join("tree");
+    crate::extract::extract_files(archive_path, &[], password, Some(&tree), None)?;
+
+    let staged = tempfile::Builder::new().prefix(".blitzarch-upgrade-").suffix(".blz").tempfile_in(dir)?;
+    let options = crate::katana_stream::KatanaCreateOptions { metadata: index.metadata.clone(), ..Default::default() };
+    crate::katana_stream::create_katana_archive_with_options(
+        std::slice::from_ref(&tree),
+        staged.path(),
+        threads,
+        0,
+        None,
+        password.map(str::to_string),
+        None,
+        &options,
+        None::<fn(crate::progress::ProgressState)>,
+    )?;
+
+    let (files, bytes) = verify_upgrade(&index, &tree, staged.path(), password)
+        .map_err(|e| format!("upgraded archive failed verification, {} left unchanged: {e}", output.display()))?;
+    let old_size = std::fs::metadata(archive_path)?.len();
+    let new_size = staged.as_file().metadata()?.len();
+    crate::katana::replace_archive(staged, File::open(archive_path)?, output)?;
+    Ok(Some(UpgradeReport {
+        output: output.to_path_buf(),
+        files,
+        bytes,
+        encrypted,
+        old_size,
+        new_size,
+    }))
+}
+
+/// Whether `a` and `b` name the same file (an in-place upgrade).
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Checks that the Katana archive at `upgraded` holds exactly the files of the
+/// classic `index`, with the content unpacked from it into `tree`.
+fn verify_upgrade(
+    index: &crate::archive::ArchiveIndex,
+    tree: &Path,
+    upgraded: &Path,
+    password: Option<&str>,
+) -> Result<(usize, u64), Box<dyn Error>> {
+    crate::katana_stream::perform_paranoid_check(upgraded)?;
+    let archive = crate::katana::open_lazy_with_password(upgraded, password)?;
+    let mut new_files: BTreeMap<String, u64> =
+        archive.entries().into_iter().filter(|e| !e.is_dir).map(|e| (e.path, e.size)).collect();
+
+    let mut bytes = 0;
+    let old_files: Vec<_> = index.entries.iter().filter(|e| !e.is_dir).collect();
+    for entry in &old_files {
+        let name = entry.path.to_string_lossy().replace('\\', "/");
+        match new_files.remove(&name) {
+            Some(size) if size == entry.uncompressed_size => {}
+            Some(size) => return Err(format!("{name}: {size} bytes instead of {}", entry.uncompressed_size).into()),
+            None => return Err(format!("{name} is missing").into()),
+        }
+        let mut expected = blake3::Hasher::new();
+        io::copy(&mut File::open(tree.join(&entry.path))?, &mut expected)?;
+        let mut actual = blake3::Hasher::new();
+        io::copy(&mut archive.open_entry(&name, password)?, &mut actual)?;
+        if expected.finalize() != actual.finalize() {
+            return Err(format!("{name}: content differs").into());
+        }
+        bytes += entry.uncompressed_size;
+    }
+    if let Some(extra) = new_files.keys().next() {
+        return Err(format!("unexpected entry {extra}").into());
+    }
+    Ok((old_files.len(), bytes))
+}
@@ -0,0 +1,206 @@
+//! Filesystem abstraction for the create pipeline.
+//!
+//! The Katana writer enumerates and reads its inputs through [`Vfs`], so an
+//! embedder can archive trees that do not live on a local disk – S3 prefixes,
+//! documents stored in a database, generated content – by implementing the
+//! trait and passing it via
+//! [`crate::katana_stream::KatanaCreateOptions::vfs`]. Without one the writer
+//! uses [`OsFs`], i.e. the regular filesystem.
+//!
+//! Paths are opaque keys for a custom implementation; they only need to be
+//! stable, and entry names in the archive are derived from them exactly as for
+//! real files (relative to the common parent of the inputs).
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Metadata the archive records for a file.
+///
+/// New fields may be added; implementors outside this crate build it with
+/// [`VfsMetadata::new`] and the `with_*` setters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VfsMetadata {
+    /// Size in bytes; exactly this many bytes must be readable from [`Vfs::open`].
+    pub len: u64,
+    /// Unix permission bits, if the source has them.
+    pub permissions: Option<u32>,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: Option<i64>,
+    /// Creation (birth) time in seconds since the Unix epoch, if known.
+    pub btime: Option<i64>,
+    /// Owner (uid, gid), if the source has one.
+    pub owner: Option<(u32, u32)>,
+}
+
+impl VfsMetadata {
+    /// Metadata of a `len`-byte file with nothing else known.
+    pub fn new(len: u64) -> Self {
+        VfsMetadata { len, ..Default::default() }
+    }
+
+    pub fn with_permissions(self, mode: u32) -> Self {
+        VfsMetadata { permissions: Some(mode), ..self }
+    }
+
+    pub fn with_mtime(self, secs: i64) -> Self {
+        VfsMetadata { mtime: Some(secs), ..self }
+    }
+
+    pub fn with_btime(self, secs: i64) -> Self {
+        VfsMetadata { btime: Some(secs), ..self }
+    }
+
+    pub fn with_owner(self, uid: u32, gid: u32) -> Self {
+        VfsMetadata { owner: Some((uid, gid)), ..self }
+    }
+
+    pub fn from_fs(meta: &std::fs::Metadata) -> Self {
+        VfsMetadata {
+            len: meta.len(),
+            permissions: crate::fsx::maybe_unix_mode(meta),
+            mtime: crate::fsx::mtime_secs(meta),
+            btime: crate::fsx::btime_secs(meta),
+            owner: crate::fsx::owner_ids(meta),
+        }
+    }
+}
+
+/// Source of files for archive creation.
+pub trait Vfs: Send + Sync + std::fmt::Debug {
+    /// All regular files of the input `root`: `root` itself if it is a file,
+    /// every file below it if it is a directory, nothing if it does not exist.
+    fn list_files(&self, root: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// [`Vfs::list_files`] without descending into the directories for which
+    /// `skip_dir` is true. The default lists everything; callers filter the
+    /// files they get either way.
+    fn list_files_pruned(&self, root: &Path, skip_dir: &dyn Fn(&Path) -> bool) -> io::Result<Vec<PathBuf>> {
+        let _ = skip_dir;
+        self.list_files(root)
+    }
+
+    /// Metadata of a file returned by [`Vfs::list_files`].
+    fn metadata(&self, path: &Path) -> io::Result<VfsMetadata>;
+
+    /// Opens a file returned by [`Vfs::list_files`] for reading.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Opens a file to be read whole, front to back, as the shard workers do;
+    /// local files honour `--io-mode`. The default is [`Vfs::open`].
+    fn open_sequential(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        self.open(path)
+    }
+
+    /// Target of `path` if it is to be archived as a symbolic link rather than
+    /// a file; such paths are never opened. The default stores no links.
+    fn read_link(&self, _path: &Path) -> io::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
+    /// Extended attributes (and ACLs) of a file, archived with `--xattrs`.
+    /// The default has none.
+    fn xattrs(&self, _path: &Path) -> io::Result<crate::fsx::Xattrs> {
+        Ok(Default::default())
+    }
+
+    /// Data regions `(offset, len)` of a sparse file, recorded so extraction
+    /// can leave the holes unwritten. The default reports no holes.
+    fn data_extents(&self, _path: &Path) -> io::Result<Option<Vec<(u64, u64)>>> {
+        Ok(None)
+    }
+}
+
+/// What the local filesystem walker does with symbolic links (`--symlinks`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkMode {
+    /// Leave links out of the archive (links given directly as inputs are followed).
+    #[default]
+    Skip,
+    /// Archive what links point to; links to directories are descended into.
+    Follow,
+    /// Store links as links (their target path); extraction recreates them.
+    Keep,
+}
+
+/// The local filesystem (default).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OsFs {
+    pub symlinks: SymlinkMode,
+    /// How [`Vfs::open_sequential`] reads large files.
+    pub io: crate::fsx::IoConfig,
+}
+
+impl Vfs for OsFs {
+    fn list_files(&self, root: &Path) -> io::Result<Vec<PathBuf>> {
+        self.list_files_pruned(root, &|_| false)
+    }
+
+    fn list_files_pruned(&self, root: &Path, skip_dir: &dyn Fn(&Path) -> bool) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let dangling_or_file_link = self.symlinks == SymlinkMode::Keep
+            && root.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink())
+            && !root.is_dir();
+        if root.is_file() || dangling_or_file_link {
+            files.push(root.to_path_buf());
+        } else if root.is_dir() {
+            let walker = walkdir::WalkDir::new(root)
+                .follow_links(self.symlinks == SymlinkMode::Follow)
+                .into_iter()
+                .filter_entry(|e| e.depth() == 0 || !e.file_type().is_dir() || !skip_dir(e.path()));
+            for entry in walker {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) if e.loop_ancestor().is_some() => {
+                        let path = e.path().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+                        let message = format!("Skipping symlink loop: {:?} points back to {:?}", path, e.loop_ancestor().unwrap_or(Path::new("")));
+                        crate::warnings::warn(crate::warnings::WarningKind::SkippedFile, path, message);
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                let kept_link = self.symlinks == SymlinkMode::Keep && entry.path_is_symlink() && entry.depth() > 0;
+                if entry.file_type().is_file() || kept_link {
+                    files.push(entry.path().to_path_buf());
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<VfsMetadata> {
+        if self.symlinks == SymlinkMode::Keep {
+            let meta = std::fs::symlink_metadata(path)?;
+            if meta.file_type().is_symlink() {
+                return Ok(VfsMetadata { len: 0, ..VfsMetadata::from_fs(&meta) });
+            }
+        }
+        std::fs::metadata(path).map(|m| VfsMetadata::from_fs(&m))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn open_sequential(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        crate::fsx::open_sequential(path, self.io)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+        if self.symlinks != SymlinkMode::Keep || !std::fs::symlink_metadata(path)?.file_type().is_symlink() {
+            return Ok(None);
+        }
+        std::fs::read_link(path).map(Some)
+    }
+
+    fn xattrs(&self, path: &Path) -> io::Result<crate::fsx::Xattrs> {
+        crate::fsx::read_xattrs(path)
+    }
+
+    fn data_extents(&self, path: &Path) -> io::Result<Option<Vec<(u64, u64)>>> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        crate::fsx::data_extents(&file, len)
+    }
+}
@@ -0,0 +1,130 @@
+//! Non-fatal problems reported while a job runs.
+//!
+//! The engine keeps printing warnings to stderr as before; [`warn`] also hands
+//! them to every active [`capture`] so callers without a console (the GUI) can
+//! show a report after the job finishes.
+//!
+//! Captures are scoped to the thread that opened them and to the worker pools
+//! its job leases from the [`crate::governor`]; two jobs running at the same
+//! time (e.g. in the daemon) only see their own warnings. Warnings from
+//! threads outside any capture are only printed.
+
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use crate::console::eprintln;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// An entry or input file was left out (unsafe path, conflicts with a directory, ...).
+    SkippedFile,
+    /// Permissions could not be restored.
+    PermissionFailure,
+    /// An entry was written under another name or over another entry (case collisions).
+    PathRewrite,
+    /// Other metadata was adjusted or dropped (clamped mtimes, xattrs, hard links).
+    Metadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// Entry or file the warning is about.
+    pub path: String,
+    pub message: String,
+}
+
+/// Warnings collected for one [`capture`]. Each job owns its sink; threads
+/// reach it through their scope rather than through a shared registry.
+pub(crate) struct Sink {
+    /// Capture this one was opened in; it sees our warnings as well.
+    parent: Option<Arc<Sink>>,
+    warnings: Mutex<Vec<Warning>>,
+}
+
+thread_local! {
+    /// Innermost capture the current thread reports to.
+    static SCOPE: RefCell<Option<Arc<Sink>>> = const { RefCell::new(None) };
+}
+
+/// Capture the current thread reports to; hand it to [`enter_scope`] on
+/// worker threads that run part of the same job.
+pub(crate) fn current_scope() -> Option<Arc<Sink>> {
+    SCOPE.with(|s| s.borrow().clone())
+}
+
+/// Makes the current thread report to `scope` (see [`current_scope`]).
+pub(crate) fn enter_scope(scope: Option<Arc<Sink>>) {
+    SCOPE.with(|s| *s.borrow_mut() = scope);
+}
+
+/// Reports a warning: printed to stderr and recorded by active captures.
+/// With `--private-logs` the path and the names in the message are redacted
+/// before either sees them.
+pub fn warn(kind: WarningKind, path: impl Into<String>, message: impl Into<String>) {
+    let (path, message) = (path.into(), message.into());
+    let warning = Warning {
+        kind,
+        message: crate::private_logs::message(&message, &path).into_owned(),
+        path: crate::private_logs::path(&path).into_owned(),
+    };
+    eprintln!("[katana] ⚠️  {}", warning.message);
+    let mut scope = current_scope();
+    while let Some(sink) = scope {
+        sink.warnings.lock().unwrap_or_else(|e| e.into_inner()).push(warning.clone());
+        scope = sink.parent.clone();
+    }
+}
+
+/// Runs `f` and returns its result together with the warnings it reported,
+/// on this thread or on the worker pools of its job.
+pub fn capture<R>(f: impl FnOnce() -> R) -> (R, Vec<Warning>) {
+    let parent = current_scope();
+    let sink = Arc::new(Sink { parent: parent.clone(), warnings: Mutex::new(Vec::new()) });
+    enter_scope(Some(sink.clone()));
+    // Leave the scope even if `f` panics
+    let guard = scopeguard::guard(parent, enter_scope);
+    let result = f();
+    drop(guard);
+    let warnings = std::mem::take(&mut *sink.warnings.lock().unwrap_or_else(|e| e.into_inner()));
+    (result, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capture, warn, WarningKind};
+
+    #[test]
+    fn test_capture_nested() {
+        let ((_, inner), outer) = capture(|| {
+            warn(WarningKind::Metadata, "a", "first");
+            capture(|| warn(WarningKind::SkippedFile, "b", "second"))
+        });
+        // Other tests in this process may warn concurrently
+        let ours = |ws: &[super::Warning]| {
+            ws.iter().filter(|w| w.path == "a" || w.path == "b").map(|w| (w.path.clone(), w.kind)).collect::<Vec<_>>()
+        };
+        assert_eq!(ours(&inner), [("b".to_string(), WarningKind::SkippedFile)]);
+        assert_eq!(ours(&outer), [("a".to_string(), WarningKind::Metadata), ("b".to_string(), WarningKind::SkippedFile)]);
+        assert_eq!(serde_json::to_value(WarningKind::PathRewrite).unwrap(), "path_rewrite");
+    }
+
+    #[test]
+    fn test_capture_is_per_thread() {
+        let other = std::thread::spawn(|| capture(|| {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            warn(WarningKind::Metadata, "other-job", "other");
+        }).1);
+        let (_, ours) = capture(|| {
+            warn(WarningKind::Metadata, "this-job", "ours");
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        });
+        let theirs = other.join().unwrap();
+        assert!(ours.iter().all(|w| w.path != "other-job"), "{ours:?}");
+        assert!(theirs.iter().all(|w| w.path != "this-job"), "{theirs:?}");
+        assert_eq!(ours.iter().filter(|w| w.path == "this-job").count(), 1);
+        assert_eq!(theirs.iter().filter(|w| w.path == "other-job").count(), 1);
+    }
+}
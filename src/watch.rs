@@ -0,0 +1,383 @@
+//! Continuous archiving of a directory (`blitzarch watch`).
+//!
+//! Changes reported by the OS file watcher are collected until the directory
+//! has been quiet for the debounce interval, then archived as one batch:
+//!
+//! * a batch that only adds files is appended to the current archive
+//!   ([`crate::katana::append_to_archive`]);
+//! * a batch that modifies or deletes files – which an append cannot express –
+//!   rolls a new generation: an incremental archive of the whole directory on
+//!   top of the previous one (`backup.blz`, `backup.g0001.blz`, …), where
+//!   unchanged files are references into the older generations.
+//!
+//! With `--generations` every batch rolls a new generation and archives are
+//! never modified once written. The newest generation always restores the
+//! current state of the directory; it needs the older ones next to it.
+//!
+//! Every append re-hashes the whole archive for its BLAKE3 footer, so appends
+//! to a large archive are spaced out ([`rehash_pause`]): changes keep
+//! collecting and go in as one bigger batch.
+//!
+//! [`WatchedArchive`] works in every build; [`watch`] needs the `watch`
+//! feature (the OS file watcher).
+
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+use crate::progress::ProgressState;
+use crate::console::{eprintln, println};
+
+/// How often the watch loop checks for cancellation while idle.
+#[cfg(feature = "watch")]
+const IDLE_POLL: Duration = Duration::from_millis(200);
+
+/// Read rate assumed for the full-archive rehash of an append.
+const REHASH_BYTES_PER_SEC: u64 = 256 << 20;
+/// Appends are spaced so that rehashing takes at most 1/N of the time.
+const REHASH_DUTY_DIVISOR: u32 = 4;
+
+/// Minimum time between two appends to an archive of `archive_len` bytes:
+/// a few times what re-hashing it takes, so a busy directory next to a huge
+/// archive does not keep the disk busy re-reading it.
+pub fn rehash_pause(archive_len: u64) -> Duration {
+    Duration::from_secs_f64(archive_len as f64 / REHASH_BYTES_PER_SEC as f64) * REHASH_DUTY_DIVISOR
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Quiet period after the last change before a batch is archived.
+    pub debounce: Duration,
+    /// Roll a new generation for every batch instead of appending.
+    pub generations: bool,
+    pub password: Option<String>,
+    pub level: Option<i32>,
+    /// Worker threads; `0` = number of CPU cores.
+    pub threads: usize,
+    /// Compact the archive after an append once dead bytes exceed this many
+    /// percent of it (see [`crate::katana::compact_if_wasteful`]).
+    pub auto_compact: Option<f64>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions { debounce: Duration::from_secs(2), generations: false, password: None, level: None, threads: 0, auto_compact: None }
+    }
+}
+
+/// What a batch did to the archive series.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOutcome {
+    /// Nothing the archive does not already hold.
+    Unchanged,
+    /// New files were appended to the current generation.
+    Appended { archive: PathBuf, files: usize },
+    /// A new generation was written.
+    Generation { archive: PathBuf, generation: u32, added: usize, modified: usize, removed: usize },
+}
+
+/// Files of a batch, as entry names relative to the watched directory.
+#[derive(Debug, Default)]
+struct Batch {
+    added: Vec<PathBuf>,
+    modified: usize,
+    removed: usize,
+}
+
+/// Input source exposing only the given files, so appended entries are named
+/// relative to the watched directory like the ones already archived.
+#[derive(Debug)]
+struct SelectedFiles {
+    files: Vec<PathBuf>,
+    fs: crate::vfs::OsFs,
+}
+
+impl crate::vfs::Vfs for SelectedFiles {
+    fn list_files(&self, root: &Path) -> std::io::Result<Vec<PathBuf>> {
+        Ok(self.files.iter().filter(|f| f.starts_with(root) && f.is_file()).cloned().collect())
+    }
+
+    fn metadata(&self, path: &Path) -> std::io::Result<crate::vfs::VfsMetadata> {
+        self.fs.metadata(path)
+    }
+
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn std::io::Read + Send>> {
+        self.fs.open(path)
+    }
+
+    fn open_sequential(&self, path: &Path) -> std::io::Result<Box<dyn std::io::Read + Send>> {
+        self.fs.open_sequential(path)
+    }
+
+    fn data_extents(&self, path: &Path) -> std::io::Result<Option<Vec<(u64, u64)>>> {
+        self.fs.data_extents(path)
+    }
+}
+
+/// The archive series of a watched directory.
+#[derive(Debug)]
+pub struct WatchedArchive {
+    dir: PathBuf,
+    output: PathBuf,
+    latest: PathBuf,
+    generation: u32,
+    /// Size and mtime of every entry the latest generation restores.
+    known: HashMap<String, (u64, Option<i64>)>,
+    options: WatchOptions,
+}
+
+/// Path of generation `n` of the series started at `output` (`n` = 0 is
+/// `output` itself): `backup.blz` → `backup.g0003.blz`.
+pub fn generation_path(output: &Path, n: u32) -> PathBuf {
+    if n == 0 {
+        return output.to_path_buf();
+    }
+    let stem = output.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = output.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    output.with_file_name(format!("{stem}.g{n:04}{ext}"))
+}
+
+impl WatchedArchive {
+    /// Continues the series at `output` (its newest generation), or starts it
+    /// with a full archive of `dir`.
+    pub fn open(dir: &Path, output: &Path, options: WatchOptions) -> Result<Self, Box<dyn Error>> {
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", dir.display()).into());
+        }
+        let dir = dir.canonicalize()?;
+        let parent = output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        std::fs::create_dir_all(parent)?;
+        let output = parent.canonicalize()?.join(output.file_name().ok_or("--output needs a file name")?);
+        if output.starts_with(&dir) {
+            return Err(format!("the archive {} must be outside the watched directory", output.display()).into());
+        }
+        let mut generation = 0;
+        while generation_path(&output, generation + 1).exists() {
+            generation += 1;
+        }
+        let mut watched = WatchedArchive { latest: generation_path(&output, generation), dir, output, generation, known: HashMap::new(), options };
+        if watched.latest.exists() {
+            println!("[watch] Continuing {} (generation {})", watched.latest.display(), generation);
+        } else {
+            watched.write_generation(None)?;
+            println!("[watch] Archived {} into {}", watched.dir.display(), watched.latest.display());
+        }
+        watched.reload()?;
+        Ok(watched)
+    }
+
+    /// The generation restoring the current state of the directory.
+    pub fn latest(&self) -> &Path {
+        &self.latest
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Minimum time to leave between batches (see [`rehash_pause`]); zero with
+    /// `--generations`, which never rewrites an existing archive.
+    pub fn min_batch_interval(&self) -> Duration {
+        if self.options.generations {
+            return Duration::ZERO;
+        }
+        std::fs::metadata(&self.latest).map(|m| rehash_pause(m.len())).unwrap_or_default()
+    }
+
+    fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        self.known = crate::katana::list_entries(&self.latest, self.options.password.as_deref())?
+            .into_iter()
+            .filter(|e| !e.is_dir)
+            .map(|e| (e.path, (e.size, e.mtime)))
+            .collect();
+        Ok(())
+    }
+
+    /// Writes the whole directory to `self.latest`, incrementally on top of `base`.
+    fn write_generation(&self, base: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+        let options = crate::katana_stream::KatanaCreateOptions { base, ..Default::default() };
+        crate::katana_stream::create_katana_archive_with_options(
+            &[self.dir.clone()],
+            &self.latest,
+            self.options.threads,
+            0,
+            None,
+            self.options.password.clone(),
+            self.options.level,
+            &options,
+            None::<fn(ProgressState)>,
+        )?;
+        crate::katana_stream::perform_paranoid_check(&self.latest)?;
+        Ok(())
+    }
+
+    fn entry_name(&self, path: &Path) -> Option<String> {
+        let rel = path.strip_prefix(&self.dir).ok().filter(|r| !r.as_os_str().is_empty())?;
+        Some(crate::katana::normalize_path(&rel.to_string_lossy()))
+    }
+
+    /// Sorts the changed paths into added, modified and removed files;
+    /// directories stand for everything below them.
+    fn classify(&self, changed: &BTreeSet<PathBuf>) -> Batch {
+        let mut batch = Batch::default();
+        let mut seen = BTreeSet::new();
+        for path in changed {
+            let Some(name) = self.entry_name(path) else { continue };
+            let files: Vec<PathBuf> = if path.is_dir() {
+                walkdir::WalkDir::new(path).into_iter().filter_map(Result::ok).filter(|e| e.file_type().is_file()).map(|e| e.into_path()).collect()
+            } else if path.is_file() {
+                vec![path.clone()]
+            } else {
+                let prefix = format!("{name}/");
+                let gone = self.known.keys().filter(|k| **k == name || k.starts_with(&prefix));
+                batch.removed += gone.filter(|k| seen.insert((*k).clone())).count();
+                continue;
+            };
+            for file in files {
+                let Some(name) = self.entry_name(&file) else { continue };
+                let Ok(meta) = std::fs::metadata(&file) else { continue };
+                if !seen.insert(name.clone()) {
+                    continue;
+                }
+                match self.known.get(&name) {
+                    None => batch.added.push(file),
+                    Some(&(size, mtime)) if (size, mtime) != (meta.len(), crate::fsx::mtime_secs(&meta)) => batch.modified += 1,
+                    Some(_) => {}
+                }
+            }
+        }
+        batch
+    }
+
+    /// Archives one batch of changed paths (files or directories below the
+    /// watched directory, existing or not).
+    pub fn apply(&mut self, changed: &BTreeSet<PathBuf>) -> Result<BatchOutcome, Box<dyn Error>> {
+        let batch = self.classify(changed);
+        if batch.added.is_empty() && batch.modified == 0 && batch.removed == 0 {
+            return Ok(BatchOutcome::Unchanged);
+        }
+        if batch.modified == 0 && batch.removed == 0 && !self.options.generations {
+            let files = batch.added.len();
+            let vfs = Arc::new(SelectedFiles { files: batch.added, fs: crate::vfs::OsFs::default() });
+            crate::katana::append_with_source(
+                &self.latest,
+                &[self.dir.clone()],
+                self.options.threads,
+                self.options.password.clone(),
+                self.options.level,
+                Some(vfs),
+                None,
+            )?;
+            if let Some(threshold) = self.options.auto_compact {
+                crate::katana::compact_if_wasteful(&self.latest, self.options.password.clone(), threshold)?;
+            }
+            self.reload()?;
+            return Ok(BatchOutcome::Appended { archive: self.latest.clone(), files });
+        }
+        let base = self.latest.clone();
+        self.latest = generation_path(&self.output, self.generation + 1);
+        if let Err(e) = self.write_generation(Some(base.clone())) {
+            let _ = std::fs::remove_file(&self.latest);
+            self.latest = base;
+            return Err(e);
+        }
+        self.generation += 1;
+        self.reload()?;
+        Ok(BatchOutcome::Generation {
+            archive: self.latest.clone(),
+            generation: self.generation,
+            added: batch.added.len(),
+            modified: batch.modified,
+            removed: batch.removed,
+        })
+    }
+}
+
+/// Watches `dir` and keeps the archive series at `output` up to date until
+/// `cancel` fires. A batch that fails is reported and retried with the next
+/// change. On cancellation the batch being written is completed and changes
+/// still waiting for the debounce are archived before returning.
+#[cfg(feature = "watch")]
+pub fn watch(dir: &Path, output: &Path, options: WatchOptions, cancel: &CancellationToken) -> Result<(), Box<dyn Error>> {
+    use notify::Watcher;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    let debounce = options.debounce;
+    let mut archive = WatchedArchive::open(dir, output, options)?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&archive.dir, notify::RecursiveMode::Recursive)?;
+    println!("[watch] Watching {} (debounce {:?}), Ctrl-C to stop", archive.dir.display(), debounce);
+
+    let mut pending: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut last_change = Instant::now();
+    let mut last_batch = Instant::now();
+    let mut pause = archive.min_batch_interval();
+    if pause > debounce {
+        println!("[watch] Large archive: appending at most every {:?}", pause);
+    }
+    loop {
+        if cancel.is_cancelled() {
+            // Изменения, ждавшие паузы, – последней партией
+            if !pending.is_empty() {
+                report_batch(archive.apply(&pending));
+            }
+            return Ok(());
+        }
+        let wait = if pending.is_empty() { IDLE_POLL } else { debounce.saturating_sub(last_change.elapsed()).min(IDLE_POLL) };
+        match rx.recv_timeout(wait) {
+            Ok(Ok(event)) => {
+                if !matches!(event.kind, notify::EventKind::Access(_)) {
+                    pending.extend(event.paths);
+                    last_change = Instant::now();
+                }
+                continue;
+            }
+            Ok(Err(e)) => {
+                // Переполнение очереди событий и т.п. – пересканируем всё дерево
+                eprintln!("[watch] Watcher error: {e}; rescanning {}", archive.dir.display());
+                pending.insert(archive.dir.clone());
+                last_change = Instant::now();
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err("file watcher stopped".into()),
+        }
+        if pending.is_empty() || last_change.elapsed() < debounce || last_batch.elapsed() < pause {
+            continue;
+        }
+        let batch = std::mem::take(&mut pending);
+        if !report_batch(archive.apply(&batch)) {
+            pending = batch;
+        }
+        last_batch = Instant::now();
+        pause = archive.min_batch_interval();
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+pub fn watch(_dir: &Path, _output: &Path, _options: WatchOptions, _cancel: &CancellationToken) -> Result<(), Box<dyn Error>> {
+    Err("this build has no file watcher (rebuild with `--features watch`)".into())
+}
+
+/// Prints what a batch did; `false` if it failed and has to be retried.
+#[cfg(feature = "watch")]
+fn report_batch(outcome: Result<BatchOutcome, Box<dyn Error>>) -> bool {
+    match outcome {
+        Ok(BatchOutcome::Unchanged) => {}
+        Ok(BatchOutcome::Appended { archive, files }) => println!("[watch] Appended {files} new file(s) to {}", archive.display()),
+        Ok(BatchOutcome::Generation { archive, generation, added, modified, removed }) => println!(
+            "[watch] Generation {generation}: {} ({added} added, {modified} modified, {removed} removed)",
+            archive.display()
+        ),
+        Err(e) => {
+            eprintln!("[watch] Archiving the last changes failed: {e}");
+            return false;
+        }
+    }
+    true
+}
@@ -5,26 +5,45 @@ pub use sharded::run_parallel_compression_sharded;
 
 use crate::cli::{Commands, WorkerMode};
 
-use crate::compress::{collect_file_metadata, group_files_into_bundles};
+use crate::compress::{collect_file_metadata_filtered, group_files_into_bundles};
 use crate::ArchiverError;
 
 use crossbeam_channel::bounded;
 use std::fs::File;
 use std::sync::Arc;
 use std::thread;
+use crate::console::{eprintln, println};
 
 /// Parallel compression with heuristic (existing)
 pub fn run_parallel_compression(args: Arc<Commands>, mode: WorkerMode) -> Result<(), ArchiverError> {
-    if let Commands::Create { inputs, output, level, password, threads, text_bundle, use_lzma2, lz_level, adaptive, adaptive_threshold, .. } = &*args {
+    run_parallel_compression_with_progress::<fn(ProgressState)>(args, mode, None)
+}
+
+/// [`run_parallel_compression`] with progress: every worker reports the
+/// bundles it finishes through its own slot of the tracker.
+pub fn run_parallel_compression_with_progress<F>(
+    args: Arc<Commands>,
+    mode: WorkerMode,
+    progress_callback: Option<F>,
+) -> Result<(), ArchiverError>
+where
+    F: Fn(ProgressState) + Send + Sync + 'static,
+{
+    if let Commands::Create { inputs, output, level, password, threads, memory_budget, text_bundle, adaptive, adaptive_threshold, .. } = &*args {
         let num_workers = match mode {
             WorkerMode::Auto => num_cpus::get(),
             WorkerMode::W2 => 2,
             WorkerMode::W4 => 4,
         };
+        let mem_budget_mb = crate::cli::parse_memory_budget_mb(memory_budget)
+            .map_err(|e| ArchiverError::Other(format!("Invalid --memory-budget: {e}").into()))?;
 
-        println!("Spawning {} worker threads.", num_workers);
+        let _lock = crate::fsx::OutputLock::acquire(output).map_err(|e| ArchiverError::Io { source: e, path: output.clone() })?;
 
-        let mut metadata_list = collect_file_metadata(inputs)?;
+        let filter = crate::cli::path_filter(&args).map_err(|e| ArchiverError::Other(e.to_string().into()))?;
+        let archive_metadata = crate::cli::archive_metadata(&args).map_err(|e| ArchiverError::Other(e.to_string().into()))?;
+        let extension_rules = crate::cli::extension_rules(&args).map_err(|e| ArchiverError::Other(e.to_string().into()))?;
+        let mut metadata_list = collect_file_metadata_filtered(inputs, &filter, &extension_rules)?;
 
         // --- Adaptive dataset-level decision ---
         let dense_ratio = {
@@ -32,18 +51,24 @@ pub fn run_parallel_compression(args: Arc<Commands>, mode: WorkerMode) -> Result
             let dense = metadata_list.iter().filter(|m| m.dense_hint.unwrap_or(false)).count();
             if total == 0 { 0.0 } else { dense as f32 / total as f32 }
         };
-        let mut global_algo = if *use_lzma2 {
-            CompressionAlgo::Lzma2 { preset: lz_level.unwrap_or(6) }
-        } else {
-            CompressionAlgo::Zstd
-        };
+        let mut global_algo = crate::cli::bundle_algo(&args);
         if *adaptive && dense_ratio > 0.8 {
             println!("[adaptive] Dense dataset detected ({} % dense) → Store mode", (dense_ratio*100.0) as u32);
             global_algo = CompressionAlgo::Store;
         }
+        // Fewer parallel bundles (then codec threads) when the budget is tight
+        let (num_workers, codec_threads) = crate::compress::fit_memory_budget(num_workers, *threads as u32, global_algo, *level, mem_budget_mb);
+        println!("Spawning {} worker threads.", num_workers);
 
         let (directories, files): (Vec<_>, Vec<_>) = metadata_list.into_iter().partition(|m| m.is_dir);
-        let bundles = group_files_into_bundles(&files, *text_bundle);
+        let bundles = group_files_into_bundles(&files, *text_bundle, &extension_rules);
+
+        let mut progress_tracker = ProgressTracker::new(num_workers, std::time::Duration::from_millis(50));
+        if let Some(callback) = progress_callback {
+            progress_tracker.enable_with_callback(callback);
+            progress_tracker.set_totals(files.len() as u64, files.iter().map(|m| m.size).sum(), bundles.len());
+        }
+        let progress_tracker = Arc::new(progress_tracker);
 
         let (bundle_sender, bundle_receiver) = bounded::<Box<[FileMetadata]>>(num_workers);
         
@@ -60,7 +85,7 @@ enum WorkerBundle {
         tmp_file: NamedTempFile,
         comp_size: u64,
         algo: CompressionAlgo,
-        mapping: Vec<(PathBuf, u64, u64, u64)>,
+        mapping: Vec<(PathBuf, u64, u64, u64, Option<i64>)>,
     },
     Store {
         tmp_file: NamedTempFile,
@@ -73,11 +98,13 @@ let (compressed_sender, compressed_receiver) = bounded::<WorkerBundle>(num_worke
 
         let scope_result = thread::scope(|s| {
             // --- Bundling/Compression Worker Threads ---
-            for _ in 0..num_workers {
+            for worker_id in 0..num_workers {
                 let bundle_receiver = bundle_receiver.clone();
+                let progress_tracker = Arc::clone(&progress_tracker);
+                let metrics = progress_tracker.get_thread_metrics(worker_id).expect("one metrics slot per worker");
                 let compressed_sender = compressed_sender.clone();
                 let level = *level;
-                let threads = *threads as u32;
+                let threads = codec_threads;
                 let _enable_pp = false;
                 let _adaptive_flag = *adaptive;
                 let threshold = *adaptive_threshold;
@@ -126,13 +153,17 @@ let (compressed_sender, compressed_receiver) = bounded::<WorkerBundle>(num_worke
                                  let mut offset_cur = 0u64;
                                  for (idx, meta) in sb_files.iter().enumerate() {
                                      let st_sz = stored_sizes[idx];
-                                     mapping.push((meta.path.clone(), offset_cur, st_sz, meta.size));
+                                     mapping.push((meta.path.clone(), offset_cur, st_sz, meta.size, meta.created_time));
                                      offset_cur += st_sz;
                                  }
                                  if compressed_sender.send(WorkerBundle::Compressed { tmp_file: temp_file, comp_size, algo: used_algo, mapping }).is_err() {
                                     break;
                                 }
                             }
+                            for file_meta in bundle.iter() {
+                                metrics.record_file_processed(file_meta.size);
+                            }
+                            progress_tracker.record_shard_completed();
                         }
                 });
             }
@@ -150,6 +181,7 @@ let (compressed_sender, compressed_receiver) = bounded::<WorkerBundle>(num_worke
             // --- Writer Thread (main thread) ---
             let output_file = File::create(output)?;
             let mut archive_writer = ArchiveWriter::new(output_file, password.clone(), global_algo)?;
+            archive_writer.set_metadata(archive_metadata);
 
             archive_writer.write_header()?;
 
@@ -162,16 +194,11 @@ let (compressed_sender, compressed_receiver) = bounded::<WorkerBundle>(num_worke
             for bundle_msg in compressed_receiver {
                 match bundle_msg {
                     WorkerBundle::Compressed { mut tmp_file, comp_size, algo: bundle_algo, mapping: original_paths } => {
-                        let algo_str = match bundle_algo {
-                            CompressionAlgo::Zstd => "zstd",
-                            CompressionAlgo::Lzma2 { .. } => "lzma2",
-                            CompressionAlgo::Store => "store",
-                        };
-                        archive_writer.set_current_algo(algo_str);
+                        archive_writer.set_current_algo(bundle_algo.tag());
                         archive_writer.write_bundle_stream(&mut tmp_file, comp_size)?;
 
-                        for (path, offset, stored_sz, uncomp_sz) in original_paths {
-                            archive_writer.add_file_entry(path, false, bundle_id_counter, offset, stored_sz, uncomp_sz, None);
+                        for (path, offset, stored_sz, uncomp_sz, created_time) in original_paths {
+                            archive_writer.add_file_entry(path, false, bundle_id_counter, offset, stored_sz, uncomp_sz, None).created_time = created_time;
                         }
                         bundle_id_counter += 1;
                     },
@@ -182,7 +209,7 @@ let (compressed_sender, compressed_receiver) = bounded::<WorkerBundle>(num_worke
                         for file in &files {
                             // The size of the file content + 8 bytes for the size prefix
                             let stored_size = file.size + 8;
-                            archive_writer.add_file_entry(file.path.clone(), false, bundle_id_counter, offset_in_bundle, file.size, file.size, Some(file.permissions));
+                            archive_writer.add_file_entry(file.path.clone(), false, bundle_id_counter, offset_in_bundle, file.size, file.size, Some(file.permissions)).created_time = file.created_time;
                             offset_in_bundle += stored_size;
                         }
                         bundle_id_counter += 1;
@@ -194,7 +221,10 @@ let (compressed_sender, compressed_receiver) = bounded::<WorkerBundle>(num_worke
         });
 
         match scope_result {
-            Ok(res) => Ok(res),
+            Ok(res) => {
+                progress_tracker.force_completion();
+                Ok(res)
+            }
             Err(_) => Err(ArchiverError::Other("A worker thread panicked".into())),
         }
 
@@ -208,7 +238,7 @@ let (compressed_sender, compressed_receiver) = bounded::<WorkerBundle>(num_worke
 // Legacy compatibility wrappers for CLI runner (temporary)
 // -----------------------------------------------------------------------------
 use std::path::{Path, PathBuf};
-use crate::progress::ProgressState;
+use crate::progress::{ProgressState, ProgressTracker};
 
 #[allow(clippy::too_many_arguments)]
 pub fn create_archive_parallel(
@@ -23,10 +23,11 @@ use tempfile::NamedTempFile;
 use crate::archive::ArchiveWriter;
 use crate::cli::{Commands, WorkerMode};
 use crate::common::FileMetadata;
-use crate::compress::{create_store_temp_bundle, collect_file_metadata, compress_bundle_streaming};
+use crate::compress::{create_store_temp_bundle, collect_file_metadata_filtered, compress_bundle_streaming};
 use crate::compress::CompressionAlgo;
 
 use crate::ArchiverError;
+use crate::console::{eprintln, println};
 
 /// Groups files into bundles by accumulating `target_size` bytes of *uncompressed*
 /// data. A single file larger than the target size becomes its own bundle.
@@ -68,9 +69,8 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
         password,
 
         threads,
+        memory_budget,
         bundle_size,
-        use_lzma2,
-        lz_level,
         adaptive,
         adaptive_threshold,
         workers: _,     // handled by `mode`
@@ -84,10 +84,17 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
         WorkerMode::W2 => 2,
         WorkerMode::W4 => 4,
     };
+    let algo = crate::cli::bundle_algo(&args);
+    let mem_budget_mb = crate::cli::parse_memory_budget_mb(memory_budget)
+        .map_err(|e| ArchiverError::Other(format!("Invalid --memory-budget: {e}").into()))?;
+    let (num_workers, codec_threads) = crate::compress::fit_memory_budget(num_workers, *threads as u32, algo, *level, mem_budget_mb);
     println!("[sharded] Spawning {num_workers} worker threads (bundle_size {bundle_size} MiB)");
 
     // 1. Collect file metadata
-    let mut metadata_list = collect_file_metadata(inputs)?;
+    let filter = crate::cli::path_filter(&args).map_err(|e| ArchiverError::Other(e.to_string().into()))?;
+    let archive_metadata = crate::cli::archive_metadata(&args).map_err(|e| ArchiverError::Other(e.to_string().into()))?;
+    let extension_rules = crate::cli::extension_rules(&args).map_err(|e| ArchiverError::Other(e.to_string().into()))?;
+    let mut metadata_list = collect_file_metadata_filtered(inputs, &filter, &extension_rules)?;
 
     // Split directories vs regular files so we can add dirs to index immediately
     let (directories, files): (Vec<_>, Vec<_>) = metadata_list.into_iter().partition(|m| m.is_dir);
@@ -105,11 +112,11 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
             tmp_file: NamedTempFile,
             comp_size: u64,
             algo: CompressionAlgo,
-            mapping: Vec<(PathBuf, u64, u64, u64)>,
+            mapping: Vec<(PathBuf, u64, u64, u64, Option<i64>)>,
         },
         Store {
             tmp_file: NamedTempFile,
-            mapping: Vec<(PathBuf, u64, u64, u64)>,
+            mapping: Vec<(PathBuf, u64, u64, u64, Option<i64>)>,
         },
     }
     let (result_tx, result_rx) = bounded::<WorkerBundle>(num_workers);
@@ -121,15 +128,11 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
             let brx = bundle_rx.clone();
             let rtx = result_tx.clone();
             let lvl = *level;
-            let th = *threads as u32;
+            let th = codec_threads;
             let enable_pp = false;
             let adaptive_flag = *adaptive;
             let threshold = *adaptive_threshold;
-            let algo = if *use_lzma2 {
-                CompressionAlgo::Lzma2 { preset: lz_level.unwrap_or(6) }
-            } else {
-                CompressionAlgo::Zstd
-            };
+            let extension_rules = &extension_rules;
 
             s.spawn(move || {
                 for bundle in brx {
@@ -137,7 +140,7 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
                     let (dense_files, normal_files): (Vec<_>, Vec<_>) = bundle
                         .iter()
                         .cloned()
-                        .partition(|m| crate::compress::is_dense_ext(m.path.extension().and_then(|s| s.to_str()).unwrap_or("")));
+                        .partition(|m| extension_rules.is_dense_ext(m.path.extension().and_then(|s| s.to_str()).unwrap_or("")));
 
                     let mut sub_bundles: Vec<(Vec<FileMetadata>, CompressionAlgo)> = Vec::new();
                     if !normal_files.is_empty() {
@@ -162,7 +165,7 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
                             let mut offset_cur = 0u64;
                             for (idx, meta) in sb_files.iter().enumerate() {
                                 let st_sz = stored_sizes[idx];
-                                mapping.push((meta.path.clone(), offset_cur, st_sz, meta.size));
+                                mapping.push((meta.path.clone(), offset_cur, st_sz, meta.size, meta.created_time));
                                 offset_cur += st_sz;
                             }
 
@@ -181,7 +184,7 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
                         let mut offset = 0u64;
                         for (idx, meta) in sb_files.iter().enumerate() {
                             let st_sz = stored_sizes[idx];
-                            mapping.push((meta.path.clone(), offset, st_sz, meta.size));
+                            mapping.push((meta.path.clone(), offset, st_sz, meta.size, meta.created_time));
                             offset += st_sz;
                         }
 
@@ -204,13 +207,9 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
         });
 
         // --- Writer thread (in the main scope) ------------------------------
-        let algo_main = if *use_lzma2 {
-            CompressionAlgo::Lzma2 { preset: lz_level.unwrap_or(6) }
-        } else {
-            CompressionAlgo::Zstd
-        };
         let output_file = File::create(output)?;
-        let mut writer = ArchiveWriter::new(output_file, password.clone(), algo_main)?;
+        let mut writer = ArchiveWriter::new(output_file, password.clone(), algo)?;
+        writer.set_metadata(archive_metadata);
         writer.write_header()?;
 
         // Write directory entries first so their bundle_id/offets are 0/0.
@@ -222,26 +221,21 @@ pub fn run_parallel_compression_sharded(args: Arc<Commands>, mode: WorkerMode) -
         for bundle_msg in result_rx {
             match bundle_msg {
                 WorkerBundle::Compressed { mut tmp_file, comp_size, algo: bundle_algo, mapping } => {
-                    let algo_str = match bundle_algo {
-                        CompressionAlgo::Zstd => "zstd",
-                        CompressionAlgo::Lzma2 { .. } => "lzma2",
-                        CompressionAlgo::Store => "store",
-                    };
-                    writer.set_current_algo(algo_str);
+                    writer.set_current_algo(bundle_algo.tag());
                     writer.write_bundle_stream(&mut tmp_file, comp_size)?;
 
-                    for (path, offset, stored_sz, uncomp_sz) in mapping {
-                        writer.add_file_entry(path, false, bundle_id, offset, stored_sz, uncomp_sz, None);
+                    for (path, offset, stored_sz, uncomp_sz, created_time) in mapping {
+                        writer.add_file_entry(path, false, bundle_id, offset, stored_sz, uncomp_sz, None).created_time = created_time;
                     }
                     bundle_id += 1;
                 },
                 WorkerBundle::Store { mut tmp_file, mapping } => {
                     writer.set_current_algo("store");
-                    let total_size = mapping.iter().map(|(_, _, stored_sz, _)| *stored_sz).sum();
+                    let total_size = mapping.iter().map(|(_, _, stored_sz, _, _)| *stored_sz).sum();
                     writer.write_bundle_stream(&mut tmp_file, total_size)?;
 
-                    for (path, offset, stored_sz, uncomp_sz) in mapping {
-                        writer.add_file_entry(path, false, bundle_id, offset, stored_sz, uncomp_sz, None);
+                    for (path, offset, stored_sz, uncomp_sz, created_time) in mapping {
+                        writer.add_file_entry(path, false, bundle_id, offset, stored_sz, uncomp_sz, None).created_time = created_time;
                     }
                     bundle_id += 1;
                 }
@@ -0,0 +1,95 @@
+use blitzarch::Archive;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn write_file(p: &Path, data: &[u8]) {
+    if let Some(parent) = p.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    fs::write(p, data).unwrap();
+}
+
+#[test]
+fn api_builder_roundtrip() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("a.txt"), &b"hello api ".repeat(500));
+    write_file(&src.path().join("dir/b.bin"), &[3u8; 70_000]);
+
+    for password in [None, Some("s3cret")] {
+        let out = tempdir().unwrap();
+        let arch = out.path().join("api.blz");
+        let progressed = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&progressed);
+
+        let mut builder = Archive::create([src.path()])
+            .level(5)
+            .threads(2)
+            .on_progress(move |_| flag.store(true, Ordering::Relaxed));
+        if let Some(pw) = password {
+            builder = builder.password(pw);
+        }
+        builder.write_to(&arch).unwrap();
+        assert!(progressed.load(Ordering::Relaxed));
+
+        let mut archive = Archive::open(&arch).unwrap();
+        if let Some(pw) = password {
+            archive = archive.password(pw);
+        }
+        let mut names: Vec<_> = archive.entries().unwrap().into_iter().map(|e| (e.path, e.size)).collect();
+        names.sort();
+        assert_eq!(names, vec![("a.txt".to_string(), 5000), ("dir/b.bin".to_string(), 70_000)]);
+
+        assert_eq!(archive.read("dir/b.bin").unwrap(), vec![3u8; 70_000]);
+
+        let restore = out.path().join("restore");
+        archive.extract_to(&restore).unwrap();
+        assert_eq!(fs::read(restore.join("a.txt")).unwrap(), b"hello api ".repeat(500));
+
+        let partial = out.path().join("partial");
+        archive.extract_files_to(&["a.txt"], &partial).unwrap();
+        assert!(partial.join("a.txt").exists());
+        assert!(!partial.join("dir/b.bin").exists());
+    }
+}
+
+#[test]
+fn api_open_rejects_non_archive() {
+    let dir = tempdir().unwrap();
+    let junk = dir.path().join("junk.bin");
+    fs::write(&junk, vec![0u8; 4096]).unwrap();
+    assert!(Archive::open(&junk).is_err());
+    assert!(Archive::create(Vec::<&str>::new()).write_to(dir.path().join("x.blz")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn extract_options_belong_to_the_job() {
+    use blitzarch::extract::ExtractOptions;
+    use std::os::unix::fs::PermissionsExt;
+
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("tool.sh"), b"#!/bin/sh\n");
+    fs::set_permissions(src.path().join("tool.sh"), fs::Permissions::from_mode(0o700)).unwrap();
+    let out = tempdir().unwrap();
+    let arch = out.path().join("modes.blz");
+    Archive::create([src.path()]).write_to(&arch).unwrap();
+
+    // Two extractions at once: only the first restores the archived mode
+    let jobs: Vec<_> = [true, false]
+        .into_iter()
+        .map(|preserve| {
+            let archive = Archive::open(&arch).unwrap().extract_options(ExtractOptions { preserve_permissions: preserve, ..Default::default() });
+            let dir = out.path().join(format!("preserve-{preserve}"));
+            std::thread::spawn(move || {
+                archive.extract_to(&dir).unwrap();
+                fs::metadata(dir.join("tool.sh")).unwrap().permissions().mode() & 0o777
+            })
+        })
+        .collect();
+    let modes: Vec<u32> = jobs.into_iter().map(|job| job.join().unwrap()).collect();
+    assert_eq!(modes[0], 0o700);
+    assert_eq!(modes[1] & 0o111, 0, "created with the default mode");
+}
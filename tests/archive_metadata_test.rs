@@ -0,0 +1,77 @@
+use blitzarch::api::Archive;
+use blitzarch::cli::{archive_metadata, Args, WorkerMode};
+use blitzarch::{extract, katana};
+use clap::Parser;
+use std::collections::BTreeMap;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn katana_metadata_survives_index_rewrites() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("meta.blz");
+    Archive::create([src.path()])
+        .metadata("creator", "nightly-backup")
+        .metadata("job", "4711")
+        .write_to(&arch)
+        .unwrap();
+
+    let expected: BTreeMap<String, String> =
+        [("creator", "nightly-backup"), ("job", "4711")].into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+    assert_eq!(katana::read_metadata(&arch, None).unwrap(), expected);
+    assert_eq!(extract::read_metadata(&arch, None).unwrap(), expected);
+
+    // Перезапись индекса сохраняет метаданные
+    katana::touch_katana_archive(&arch, &[], 0o600, None).unwrap();
+    assert_eq!(katana::read_metadata(&arch, None).unwrap(), expected);
+
+    let plain = dir.path().join("plain.blz");
+    Archive::create([src.path()]).write_to(&plain).unwrap();
+    assert!(katana::read_metadata(&plain, None).unwrap().is_empty());
+}
+
+#[test]
+fn hidden_metadata_needs_the_password() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("hidden.blz");
+    Archive::create([src.path()]).password("pw").hide_names(true).metadata("host", "db-01").write_to(&arch).unwrap();
+    assert!(katana::read_metadata(&arch, None).is_err());
+    assert_eq!(katana::read_metadata(&arch, Some("pw")).unwrap()["host"], "db-01");
+}
+
+#[test]
+fn classic_index_carries_metadata() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("classic.blz");
+    let args = Args::try_parse_from([
+        "blitzarch".as_ref(), "create".as_ref(), "--format".as_ref(), "classic".as_ref(),
+        "--comment".as_ref(), "weekly".as_ref(), "-o".as_ref(), arch.as_os_str(), src.path().as_os_str(),
+    ])
+    .unwrap();
+    blitzarch::workers::run_parallel_compression_with_progress(
+        std::sync::Arc::new(args.command),
+        WorkerMode::W2,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    assert_eq!(extract::read_metadata(&arch, None).unwrap()["comment"], "weekly");
+}
+
+#[test]
+fn meta_and_comment_flags_parse() {
+    let command = |args: &[&str]| Args::try_parse_from(args).unwrap().command;
+    let cmd = command(&["blitzarch", "create", "in", "-o", "out.blz", "--meta", "job=42", "--meta", "host=a=b", "--comment", "hi"]);
+    let metadata = archive_metadata(&cmd).unwrap();
+    assert_eq!(metadata["job"], "42");
+    assert_eq!(metadata["host"], "a=b");
+    assert_eq!(metadata["comment"], "hi");
+
+    let bad = command(&["blitzarch", "create", "in", "-o", "out.blz", "--meta", "novalue"]);
+    assert!(archive_metadata(&bad).unwrap_err().to_string().contains("KEY=VALUE"));
+}
@@ -0,0 +1,128 @@
+use assert_cmd::prelude::*;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Archive with one file in a shard and one inlined in the index.
+fn create(dir: &Path) -> PathBuf {
+    let src = dir.join("src");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::write(src.join("big.bin"), (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>()).unwrap();
+    fs::write(src.join("docs/small.txt"), b"small").unwrap();
+    let archive = dir.join("data.blz");
+    let options = KatanaCreateOptions { inline_small_files: true, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src], &archive, 1, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    archive
+}
+
+fn extract(archive: &Path, out: &Path, atomic: bool) -> std::process::Output {
+    let mut cmd = Command::cargo_bin("blitzarch").unwrap();
+    cmd.arg("extract").arg(archive).arg("-o").arg(out);
+    if atomic {
+        cmd.arg("--atomic");
+    }
+    cmd.output().unwrap()
+}
+
+/// Names in `dir`, leftovers of a staged extraction included.
+fn listing(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn atomic_extract_publishes_the_complete_tree() {
+    let dir = tempdir().unwrap();
+    let archive = create(dir.path());
+    let out = dir.path().join("out");
+    assert!(extract(&archive, &out, true).status.success());
+    assert_eq!(fs::read(out.join("docs/small.txt")).unwrap(), b"small");
+    assert_eq!(fs::read(out.join("big.bin")).unwrap(), fs::read(dir.path().join("src/big.bin")).unwrap());
+    assert_eq!(listing(dir.path()), ["data.blz", "out", "src"]);
+
+    // Into an existing directory the files are merged; unrelated ones stay
+    let existing = dir.path().join("existing");
+    fs::create_dir_all(existing.join("docs")).unwrap();
+    fs::write(existing.join("docs/notes.txt"), b"mine").unwrap();
+    fs::write(existing.join("big.bin"), b"old").unwrap();
+    assert!(extract(&archive, &existing, true).status.success());
+    assert_eq!(listing(&existing.join("docs")), ["notes.txt", "small.txt"]);
+    assert_eq!(fs::read(existing.join("big.bin")).unwrap().len(), 200_000);
+    assert_eq!(listing(dir.path()), ["data.blz", "existing", "out", "src"]);
+}
+
+#[test]
+fn failed_atomic_extract_leaves_nothing_behind() {
+    let dir = tempdir().unwrap();
+    let archive = create(dir.path());
+    // A flipped byte in the shard: the inlined file is still written, the shard fails
+    let mut bytes = fs::read(&archive).unwrap();
+    bytes[10] ^= 0x55;
+    fs::write(&archive, &bytes).unwrap();
+
+    let partial = dir.path().join("partial");
+    assert!(!extract(&archive, &partial, false).status.success());
+    assert!(partial.join("docs/small.txt").exists());
+
+    let out = dir.path().join("out");
+    assert!(!extract(&archive, &out, true).status.success());
+    assert!(!out.exists());
+
+    let existing = dir.path().join("existing");
+    fs::create_dir_all(&existing).unwrap();
+    fs::write(existing.join("keep.txt"), b"mine").unwrap();
+    assert!(!extract(&archive, &existing, true).status.success());
+    assert_eq!(listing(&existing), ["keep.txt"]);
+    assert_eq!(listing(dir.path()), ["data.blz", "existing", "partial", "src"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn interrupted_merge_is_rolled_back_by_the_next_run() {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempdir().unwrap();
+    create(dir.path());
+    fs::set_permissions(dir.path().join("src/docs"), fs::Permissions::from_mode(0o750)).unwrap();
+    let archive_750 = dir.path().join("docs750.blz");
+    katana_stream::create_katana_archive_with_options(
+        &[dir.path().join("src")], &archive_750, 1, 0, None, None, None, &KatanaCreateOptions::default(),
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+
+    // A merge that died after replacing keep.txt and publishing stray.txt
+    let existing = dir.path().join("existing");
+    fs::create_dir_all(&existing).unwrap();
+    fs::write(existing.join("keep.txt"), b"theirs").unwrap();
+    fs::write(existing.join("stray.txt"), b"half-published").unwrap();
+    let stage = dir.path().join(".existing.blitzarch-partial-crashed");
+    fs::create_dir_all(stage.join("replaced")).unwrap();
+    fs::write(stage.join("replaced/0"), b"mine").unwrap();
+    let mut journal = Vec::new();
+    for (dest, aside) in [(existing.join("keep.txt"), Some(stage.join("replaced/0"))), (existing.join("stray.txt"), None)] {
+        journal.extend_from_slice(b"f\0");
+        journal.extend_from_slice(dest.as_os_str().as_bytes());
+        journal.push(0);
+        journal.extend_from_slice(aside.as_ref().map(|a| a.as_os_str().as_bytes()).unwrap_or_default());
+        journal.push(0);
+    }
+    fs::write(stage.join("merge.journal"), journal).unwrap();
+
+    let run = extract(&archive_750, &existing, true);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert!(String::from_utf8_lossy(&run.stdout).contains("Rolled back an interrupted extraction"));
+    assert_eq!(fs::read(existing.join("keep.txt")).unwrap(), b"mine");
+    assert!(!existing.join("stray.txt").exists());
+    assert_eq!(fs::read(existing.join("docs/small.txt")).unwrap(), b"small");
+    // The directory created by the merge has the archived mode
+    assert_eq!(fs::metadata(existing.join("docs")).unwrap().permissions().mode() & 0o777, 0o750);
+    assert_eq!(listing(dir.path()), ["data.blz", "docs750.blz", "existing", "src"]);
+}
@@ -0,0 +1,115 @@
+use blitzarch::autotune::{AutoTuner, CompressionStats, LevelTuning, LEVEL_SAMPLE_BYTES, MAX_AUTO_LEVEL};
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn stats(ratio: f64, mbps: f64, io_wait: f64) -> CompressionStats {
+    CompressionStats {
+        avg_file_size: 0,
+        mbps_per_thread: mbps,
+        compression_ratio: ratio,
+        files_processed: 0,
+        bytes_processed: LEVEL_SAMPLE_BYTES,
+        io_wait,
+    }
+}
+
+#[test]
+fn level_follows_ratio_and_throughput() {
+    let mut tuner = AutoTuner::with_budget_mb(Some(256));
+    assert_eq!(tuner.retune_level(&stats(1.01, 200.0, 0.0), 6), 1);
+    assert_eq!(tuner.retune_level(&stats(3.0, 200.0, 0.8), 3), 4);
+    assert_eq!(tuner.retune_level(&stats(3.0, 200.0, 0.8), MAX_AUTO_LEVEL), MAX_AUTO_LEVEL);
+    assert_eq!(tuner.retune_level(&stats(3.0, 10.0, 0.0), 5), 4);
+    assert_eq!(tuner.retune_level(&stats(3.0, 200.0, 0.3), 5), 5);
+    // Too little data to judge
+    let small = CompressionStats { bytes_processed: 1024, ..stats(1.0, 200.0, 0.0) };
+    assert_eq!(tuner.retune_level(&small, 5), 5);
+}
+
+#[test]
+fn tuning_ignores_windows_of_an_old_level() {
+    let tuning = LevelTuning::new(AutoTuner::with_budget_mb(Some(256)), 3);
+    let window = 16 << 20;
+    let busy = Duration::from_millis(100);
+    assert_eq!(tuning.record(3, window, window, busy, busy), 3);
+    assert_eq!(tuning.record(3, window, window, busy, busy), 1);
+    assert_eq!(tuning.level(), 1);
+    // A shard still finishing a window at level 3 is told the new level
+    assert_eq!(tuning.record(3, window, window / 4, busy, busy), 1);
+}
+
+#[test]
+fn incompressible_data_at_level_one_switches_to_store() {
+    let tuning = LevelTuning::new(AutoTuner::with_budget_mb(Some(256)), 1).with_codec_threads(4);
+    let window = LEVEL_SAMPLE_BYTES;
+    let busy = Duration::from_millis(100);
+    assert!(!tuning.store());
+    // Workers mostly waiting for I/O need fewer zstd threads
+    assert_eq!(tuning.record(1, window, window, busy, busy * 10), 1);
+    assert!(tuning.store());
+    assert_eq!(tuning.codec_threads(), 3);
+}
+
+#[test]
+fn incompressible_input_drops_to_level_one() {
+    let src = tempdir().unwrap();
+    let mut x = 0x9E37_79B9_7F4A_7C15u64;
+    let noise: Vec<u8> = (0..48 << 20)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+    fs::write(src.path().join("noise.bin"), &noise).unwrap();
+
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("auto.blz");
+    // No level given: AutoTune picks the start and --retune adjusts it
+    let options = KatanaCreateOptions { retune: true, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 1, 1, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    let shards = katana::shard_details(&arch, None).unwrap();
+    assert_eq!(shards[0].stats.expect("stats").level, 1);
+
+    let out = dir.path().join("out");
+    fs::create_dir_all(&out).unwrap();
+    katana::extract_katana_archive_internal(&arch, &out, &[], None, None).unwrap();
+    assert!(fs::read(out.join("noise.bin")).unwrap() == noise);
+}
+
+#[test]
+fn retuning_is_opt_in_and_off_for_rsync_friendly() {
+    let src = tempdir().unwrap();
+    let mut x = 0x2545_F491_4F6C_DD1Du64;
+    let noise: Vec<u8> = (0..48 << 20)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+    fs::write(src.path().join("noise.bin"), &noise).unwrap();
+    let dir = tempdir().unwrap();
+    let level_of = |name: &str, options: &KatanaCreateOptions| {
+        let arch = dir.path().join(name);
+        katana_stream::create_katana_archive_with_options(
+            &[src.path().to_path_buf()], &arch, 1, 1, None, None, None, options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+        katana::shard_details(&arch, None).unwrap()[0].stats.expect("stats").level
+    };
+    let default = level_of("default.blz", &KatanaCreateOptions::default());
+    let rsync = level_of("rsync.blz", &KatanaCreateOptions { retune: true, rsync_friendly: true, ..Default::default() });
+    assert_eq!(rsync, default);
+    assert_eq!(level_of("again.blz", &KatanaCreateOptions::default()), default);
+}
@@ -0,0 +1,52 @@
+use blitzarch::bench::{run_bench, BenchComparison, BenchOptions, BenchReport};
+use blitzarch::cli::parse_percent;
+use tempfile::tempdir;
+
+#[test]
+fn bench_report_roundtrips_and_gates_regressions() {
+    let src = tempdir().unwrap();
+    for i in 0..8 {
+        std::fs::write(src.path().join(format!("f{}.txt", i)), format!("line {}\n", i).repeat(2000)).unwrap();
+    }
+    let options = BenchOptions { runs: 1, ..Default::default() };
+    let report = run_bench(&[src.path().to_path_buf()], &options).unwrap();
+    assert_eq!(report.files, 8);
+    assert!(report.create_mb_s > 0.0 && report.extract_mb_s > 0.0);
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("baseline.json");
+    report.save(&path).unwrap();
+    let baseline = BenchReport::load(&path).unwrap();
+    assert_eq!(baseline, report);
+
+    let same = BenchComparison::new(&baseline, &report, 5.0);
+    assert!(same.regressions().is_empty());
+    assert!(same.mismatches.is_empty() && same.versions.is_none());
+    same.check().unwrap();
+
+    // Базовая линия создавала вдвое быстрее – регрессия только у create
+    let faster = BenchReport { create_mb_s: report.create_mb_s * 2.0, ..baseline.clone() };
+    let slower = BenchComparison::new(&faster, &report, 5.0);
+    let names: Vec<&str> = slower.regressions().iter().map(|m| m.name).collect();
+    assert_eq!(names, ["create"]);
+    assert!((slower.metrics[0].change_pct + 50.0).abs() < 1e-6);
+    assert!(BenchComparison::new(&faster, &report, 60.0).regressions().is_empty());
+    assert!(slower.check().unwrap_err().to_string().contains("regression"));
+
+    // Другие уровень и потоки – сравнивать нечего, даже без регрессии
+    let other = BenchReport { level: 9, threads: report.threads + 1, ..baseline.clone() };
+    let err = BenchComparison::new(&other, &report, 5.0).check().unwrap_err().to_string();
+    assert!(err.contains("level 9 → 3") && err.contains("threads"), "{err}");
+    let older = BenchReport { blitzarch: "0.0.1".into(), ..baseline.clone() };
+    let upgrade = BenchComparison::new(&older, &report, 5.0);
+    assert_eq!(upgrade.versions.as_ref().map(|(from, _)| from.as_str()), Some("0.0.1"));
+    upgrade.check().unwrap();
+}
+
+#[test]
+fn percent_thresholds_parse() {
+    assert_eq!(parse_percent("5%").unwrap(), 5.0);
+    assert_eq!(parse_percent(" 2.5 ").unwrap(), 2.5);
+    assert!(parse_percent("-1%").is_err());
+    assert!(parse_percent("fast").is_err());
+}
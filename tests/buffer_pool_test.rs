@@ -0,0 +1,59 @@
+use blitzarch::katana;
+use blitzarch::memory_pool::BufferPool;
+use std::fs;
+use std::sync::mpsc;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[test]
+fn pool_reuses_blocks_and_waits_when_all_are_out() {
+    let pool = BufferPool::new(16, 2);
+    let mut first = pool.get();
+    first.extend_from_slice(b"0123456789");
+    assert_eq!(first.room(), 6);
+    let _second = pool.get();
+    assert_eq!(pool.idle(), 0);
+
+    let (tx, rx) = mpsc::channel();
+    let waiting = std::sync::Arc::clone(&pool);
+    let handle = std::thread::spawn(move || {
+        let block = waiting.get();
+        tx.send((block.len(), block.room())).unwrap();
+    });
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+    drop(first);
+    // The returned block comes back empty
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), (0, 16));
+    handle.join().unwrap();
+    assert_eq!(pool.idle(), 1);
+}
+
+/// Several shards larger than the pool, so blocks are parked and spilled.
+#[test]
+fn shards_larger_than_the_pool_roundtrip() {
+    let src = tempdir().unwrap();
+    let mut expected = Vec::new();
+    for i in 0..6u32 {
+        let data: Vec<u8> = (0..(11u32 << 20) + i * 777)
+            .map(|j| (j.wrapping_mul(2_654_435_761).wrapping_add(i) >> 11) as u8)
+            .collect();
+        let name = format!("file{i}.bin");
+        fs::write(src.path().join(&name), &data).unwrap();
+        expected.push((name, data));
+    }
+
+    for password in [None, Some("pool secret".to_string())] {
+        let dir = tempdir().unwrap();
+        let arch = dir.path().join("pool.blz");
+        katana::create_katana_archive_with_progress::<fn(blitzarch::progress::ProgressState)>(
+            &[src.path().to_path_buf()], &arch, 3, 0, None, password.clone(), None,
+        )
+        .unwrap();
+        let out = dir.path().join("out");
+        fs::create_dir_all(&out).unwrap();
+        katana::extract_katana_archive_internal(&arch, &out, &[], password, None).unwrap();
+        for (name, data) in &expected {
+            assert!(fs::read(out.join(name)).unwrap() == *data, "{name}");
+        }
+    }
+}
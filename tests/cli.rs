@@ -74,3 +74,301 @@ fn test_cli_create_list_extract_cycle() -> Result<(), Box<dyn std::error::Error>
 
     Ok(())
 }
+
+#[test]
+fn test_cli_json_error_format() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let bogus = dir.path().join("not_an_archive.blz");
+    fs::write(&bogus, b"definitely not a katana archive, just some bytes padding")?;
+
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("extract")
+        .arg(&bogus)
+        .arg("--output")
+        .arg(dir.path().join("out"))
+        .arg("--error-format")
+        .arg("json");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(r#""code":"not_an_archive""#))
+        .stderr(predicate::str::contains(r#""category":"format""#));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_selftest_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+    let source_dir = tempdir()?;
+    fs::create_dir(source_dir.path().join("nested"))?;
+    fs::write(source_dir.path().join("a.txt"), b"roundtrip me")?;
+    fs::write(source_dir.path().join("nested/b.bin"), [7u8; 4096])?;
+
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("selftest").arg(source_dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("[selftest] ✅ PASS | Files: 2"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_create_format_capabilities() -> Result<(), Box<dyn std::error::Error>> {
+    let source_dir = tempdir()?;
+    fs::write(source_dir.path().join("a.txt"), b"classic format payload")?;
+    let out_dir = tempdir()?;
+
+    // Classic writer accepts LZMA2 and can be listed afterwards
+    let classic = out_dir.path().join("classic.blz");
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("create").arg("--format").arg("classic").arg("--use-lzma2")
+        .arg("--output").arg(&classic).arg(source_dir.path());
+    cmd.assert().success();
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("list").arg(&classic);
+    cmd.assert().success().stdout(predicate::str::contains("a.txt"));
+
+    // The classic pipeline reports progress too
+    let with_progress = out_dir.path().join("classic-progress.blz");
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("create").arg("--format").arg("classic").arg("--progress")
+        .arg("--output").arg(&with_progress).arg(source_dir.path());
+    cmd.assert().success().stderr(predicate::str::contains("[CREATE]"));
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("list").arg(&with_progress);
+    cmd.assert().success().stdout(predicate::str::contains("a.txt"));
+
+    // Options the selected format cannot honour are rejected before any work is done
+    let rejected = out_dir.path().join("rejected.blz");
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("create").arg("--use-lzma2").arg("--output").arg(&rejected).arg(source_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--format katana does not support --use-lzma2 (supported by: classic)"));
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("create").arg("--format").arg("classic").arg("--zstd-param").arg("windowLog=20")
+        .arg("--output").arg(&rejected).arg(source_dir.path());
+    cmd.assert().failure().stderr(predicate::str::contains("does not support --zstd-param"));
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("create").arg("--text-ext").arg("proto").arg("--output").arg(&rejected).arg(source_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--format katana does not support --text-ext (supported by: classic)"));
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("create").arg("--dense-ext").arg("parquet").arg("--output").arg(&rejected).arg(source_dir.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--format katana does not support --dense-ext (supported by: classic)"));
+    assert!(!rejected.exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runner_picks_the_writer_that_honours_io_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let source_dir = tempdir()?;
+    fs::write(source_dir.path().join("a.txt"), b"io mode payload")?;
+    let out_dir = tempdir()?;
+
+    let plain = out_dir.path().join("plain.blz");
+    let mut cmd = Command::cargo_bin("blitzarch-cli")?;
+    cmd.arg("create").arg("--output").arg(&plain).arg(source_dir.path());
+    cmd.assert().success().stderr(predicate::str::contains("streaming writer").not());
+
+    // Параллельный писатель не знает --io-mode: задание уходит в потоковый
+    let readahead = out_dir.path().join("readahead.blz");
+    let mut cmd = Command::cargo_bin("blitzarch-cli")?;
+    cmd.arg("create").arg("--io-mode").arg("readahead").arg("--readahead").arg("4")
+        .arg("--output").arg(&readahead).arg(source_dir.path());
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("--io-mode, --readahead need the streaming writer"));
+    let mut cmd = Command::cargo_bin("blitzarch")?;
+    cmd.arg("list").arg(&readahead);
+    cmd.assert().success().stdout(predicate::str::contains("a.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_extract_no_preserve_permissions() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    let source_dir = tempdir()?;
+    let script = source_dir.path().join("run.sh");
+    fs::write(&script, "#!/bin/sh\necho hi\n")?;
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o751))?;
+    let archive_dir = tempdir()?;
+    let archive_path = archive_dir.path().join("perm.blz");
+    Command::cargo_bin("blitzarch")?
+        .args(["create", "--output"])
+        .arg(&archive_path)
+        .arg(source_dir.path())
+        .assert()
+        .success();
+
+    let mode_after = |extra: &[&str]| -> Result<u32, Box<dyn std::error::Error>> {
+        let out = tempdir()?;
+        Command::cargo_bin("blitzarch")?
+            .arg("extract")
+            .arg(&archive_path)
+            .arg("-o")
+            .arg(out.path())
+            .args(extra)
+            .assert()
+            .success();
+        Ok(fs::metadata(out.path().join("run.sh"))?.permissions().mode() & 0o777)
+    };
+    // The child inherits our umask
+    assert_eq!(mode_after(&[])?, 0o751 & !blitzarch::fsx::process_umask());
+    assert_eq!(mode_after(&["--no-preserve-permissions"])?, 0o666 & !blitzarch::fsx::process_umask());
+    Ok(())
+}
+
+#[test]
+fn test_list_entries_both_formats() -> Result<(), Box<dyn std::error::Error>> {
+    let source_dir = tempdir()?;
+    fs::create_dir(source_dir.path().join("nested"))?;
+    fs::write(source_dir.path().join("a.txt"), b"listing payload ".repeat(64))?;
+    fs::write(source_dir.path().join("nested/b.bin"), [3u8; 5000])?;
+    let out_dir = tempdir()?;
+
+    for format in ["katana", "classic"] {
+        let archive = out_dir.path().join(format!("{format}.blz"));
+        Command::cargo_bin("blitzarch")?
+            .args(["create", "--format", format, "--output"])
+            .arg(&archive)
+            .arg(source_dir.path())
+            .assert()
+            .success();
+
+        let entries = blitzarch::extract::list_entries(&archive, None)?;
+        let b = entries.iter().find(|e| e.path.ends_with("nested/b.bin")).expect(format);
+        assert_eq!(b.size, 5000);
+        assert!(!b.is_dir);
+        assert!(b.shard_id.is_some());
+        assert!(entries.iter().any(|e| e.path.ends_with("a.txt") && e.size == 16 * 64));
+        if format == "classic" {
+            assert!(b.compressed_size.is_some());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_cli_attest_and_verify() -> Result<(), Box<dyn std::error::Error>> {
+    let source_dir = tempdir()?;
+    fs::write(source_dir.path().join("a.txt"), b"attest me")?;
+    let out_dir = tempdir()?;
+    let archive = out_dir.path().join("att.blz");
+    Command::cargo_bin("blitzarch")?
+        .args(["create", "--output"])
+        .arg(&archive)
+        .arg(source_dir.path())
+        .assert()
+        .success();
+
+    Command::cargo_bin("blitzarch")?.arg("attest").arg(&archive).assert().success();
+    assert!(out_dir.path().join("att.blz.sig").exists());
+    Command::cargo_bin("blitzarch")?
+        .args(["attest", "--verify"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("matches"));
+
+    // Keyed attestation needs the key to verify
+    let key = out_dir.path().join("key");
+    fs::write(&key, b"secret key material")?;
+    let keyed = out_dir.path().join("att.keyed.sig");
+    Command::cargo_bin("blitzarch")?
+        .args(["attest", "--key-file"]).arg(&key).arg("--out").arg(&keyed).arg(&archive)
+        .assert()
+        .success();
+    Command::cargo_bin("blitzarch")?
+        .args(["attest", "--verify", "--out"]).arg(&keyed).arg(&archive)
+        .assert()
+        .failure();
+
+    // Any modification of the archive is detected
+    let mut bytes = fs::read(&archive)?;
+    bytes[0] ^= 0xFF;
+    fs::write(&archive, bytes)?;
+    Command::cargo_bin("blitzarch")?
+        .args(["attest", "--verify"])
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Attestation mismatch"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_verify_deep() -> Result<(), Box<dyn std::error::Error>> {
+    let source_dir = tempdir()?;
+    fs::write(source_dir.path().join("a.txt"), b"verify me ".repeat(5000))?;
+    fs::write(source_dir.path().join("b.bin"), [9u8; 70_000])?;
+    let out_dir = tempdir()?;
+    let archive = out_dir.path().join("v.blz");
+    Command::cargo_bin("blitzarch")?
+        .args(["create", "--output"])
+        .arg(&archive)
+        .arg(source_dir.path())
+        .assert()
+        .success();
+    Command::cargo_bin("blitzarch")?
+        .args(["verify", "--deep"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[verify] ✅").and(predicate::str::contains("Deep: ok")));
+    Ok(())
+}
+
+#[test]
+fn test_cli_verify_status_line() -> Result<(), Box<dyn std::error::Error>> {
+    let source_dir = tempdir()?;
+    fs::write(source_dir.path().join("a.txt"), b"first shard ".repeat(5000))?;
+    fs::write(source_dir.path().join("b.txt"), b"second shard ".repeat(5000))?;
+    let out_dir = tempdir()?;
+    let archive = out_dir.path().join("status.blz");
+    Command::cargo_bin("blitzarch")?
+        .args(["create", "--shard-strategy", "per-file", "--threads", "2", "--output"])
+        .arg(&archive)
+        .arg(source_dir.path())
+        .assert()
+        .success();
+    Command::cargo_bin("blitzarch")?
+        .args(["verify", "--deep"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"(?m)^VERIFY OK shards=2/2 files=2 bytes=\d+ corrupt=0 duration=[\d.]+s archive=")?);
+
+    // Damage the first shard: every bad entry is listed, the run still covers all shards
+    let mut bytes = fs::read(&archive)?;
+    bytes[30] ^= 0xFF;
+    fs::write(&archive, bytes)?;
+    Command::cargo_bin("blitzarch")?
+        .arg("verify")
+        .arg(&archive)
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("BLAKE3 footer mismatch")
+                .and(predicate::str::is_match(r"\[verify\] ❌ [ab]\.txt \(shard 0: ")?)
+                .and(predicate::str::is_match(r"(?m)^VERIFY FAILED shards=2/2 files=2 bytes=\d+ corrupt=2 ")?),
+        )
+        .stderr(predicate::str::contains("Verification failed: 2 problem(s) found"));
+
+    // An archive whose index cannot be read still gets a status line
+    let truncated = out_dir.path().join("truncated.blz");
+    fs::write(&truncated, &fs::read(&archive)?[..40])?;
+    Command::cargo_bin("blitzarch")?
+        .arg("verify")
+        .arg(&truncated)
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_match(r"(?m)^VERIFY FAILED shards=0/0 files=0 bytes=0 corrupt=1 duration=[\d.]+s archive=")?);
+    Ok(())
+}
@@ -80,6 +80,8 @@ fn roundtrip_zstd() {
         adaptive: false,
         adaptive_threshold: 0.8,
         algo: compress::CompressionAlgo::Zstd,
+        mem_budget_mb: None,
+        extension_rules: Default::default(),
     };
     roundtrip(opts, None);
 }
@@ -93,6 +95,8 @@ fn roundtrip_store() {
         adaptive: false,
         adaptive_threshold: 0.8,
         algo: compress::CompressionAlgo::Store,
+        mem_budget_mb: None,
+        extension_rules: Default::default(),
     };
     roundtrip(opts, None);
 }
@@ -106,10 +110,110 @@ fn roundtrip_lzma2() {
         adaptive: false,
         adaptive_threshold: 0.8,
         algo: compress::CompressionAlgo::Lzma2 { preset: 7 },
+        mem_budget_mb: None,
+        extension_rules: Default::default(),
     };
     roundtrip(opts, None);
 }
 
+#[test]
+fn roundtrip_brotli() {
+    for password in [None, Some("brotli-pw")] {
+        let opts = compress::CompressOptions {
+            level: 3,
+            threads: 2,
+            text_bundle: TextBundleMode::Small,
+            adaptive: false,
+            adaptive_threshold: 0.8,
+            algo: compress::CompressionAlgo::Brotli { quality: 9 },
+            mem_budget_mb: None,
+            extension_rules: Default::default(),
+        };
+        roundtrip(opts, password);
+    }
+}
+
+#[test]
+fn brotli_selective_extract_of_non_adjacent_files() {
+    let src_dir = tempdir().unwrap();
+    create_test_data(src_dir.path(), 5, 2048).unwrap();
+    let archive_dir = tempdir().unwrap();
+    let archive_path = archive_dir.path().join("test.blz");
+    let opts = compress::CompressOptions {
+        level: 3,
+        threads: 1,
+        text_bundle: TextBundleMode::Small,
+        adaptive: false,
+        adaptive_threshold: 0.8,
+        algo: compress::CompressionAlgo::Brotli { quality: 9 },
+        mem_budget_mb: None,
+        extension_rules: Default::default(),
+    };
+    compress::run(&[src_dir.path().to_path_buf()], &archive_path, opts, None).expect("compression failed");
+
+    // One rayon thread takes the sequential bundle path, more the per-file one
+    for threads in [1, 4] {
+        let out_dir = tempdir().unwrap();
+        let wanted = [PathBuf::from("file_1.bin"), PathBuf::from("file_3.bin")];
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| blitzarch::extract::extract_files(&archive_path, &wanted, None, Some(out_dir.path()), None))
+            .expect("extraction failed");
+        for name in &wanted {
+            assert_eq!(fs::read(out_dir.path().join(name)).unwrap(), fs::read(src_dir.path().join(name)).unwrap());
+        }
+        assert!(!out_dir.path().join("file_2.bin").exists());
+    }
+}
+
+#[test]
+fn brotli_bundle_reader_skips_unwanted_streams() {
+    use std::io::Read;
+    let stream = |data: &[u8]| {
+        let mut out = Vec::new();
+        let mut enc = brotli::CompressorWriter::new(&mut out, 4096, 9, 22);
+        enc.write_all(data).unwrap();
+        drop(enc);
+        out
+    };
+    let (a, b, c) = (stream(b"first file"), stream(b"second file"), stream(b"third file"));
+    let bundle = [&a[..], &b[..], &c[..]].concat();
+
+    let streams = vec![(0, a.len() as u64), ((a.len() + b.len()) as u64, c.len() as u64)];
+    let mut decoded = String::new();
+    compress::BrotliBundleReader::new(&bundle[..], streams).read_to_string(&mut decoded).unwrap();
+    assert_eq!(decoded, "first filethird file");
+}
+
+#[test]
+fn roundtrip_lzma2_within_memory_budget() {
+    let opts = compress::CompressOptions {
+        level: 7,
+        threads: 8,
+        text_bundle: TextBundleMode::Small,
+        adaptive: false,
+        adaptive_threshold: 0.8,
+        algo: compress::CompressionAlgo::Lzma2 { preset: 7 },
+        mem_budget_mb: Some(64),
+        extension_rules: Default::default(),
+    };
+    roundtrip(opts, None);
+}
+
+#[test]
+fn memory_budget_caps_workers_before_codec_threads() {
+    use compress::{fit_memory_budget, CompressionAlgo};
+    let lzma = CompressionAlgo::Lzma2 { preset: 6 }; // ~120 MiB per thread
+    assert_eq!(fit_memory_budget(8, 2, lzma, 6, None), (8, 2));
+    assert_eq!(fit_memory_budget(8, 2, lzma, 6, Some(4096)), (8, 2));
+    assert_eq!(fit_memory_budget(8, 2, lzma, 6, Some(1024)), (4, 2));
+    assert_eq!(fit_memory_budget(8, 4, lzma, 6, Some(300)), (1, 2));
+    assert_eq!(fit_memory_budget(8, 4, lzma, 6, Some(1)), (1, 1));
+    assert_eq!(fit_memory_budget(4, 0, CompressionAlgo::Zstd, 3, Some(24)), (2, 0));
+}
+
 #[test]
 fn roundtrip_zstd_encrypted() {
     let opts = compress::CompressOptions {
@@ -119,6 +223,8 @@ fn roundtrip_zstd_encrypted() {
         adaptive: false,
         adaptive_threshold: 0.8,
         algo: compress::CompressionAlgo::Zstd,
+        mem_budget_mb: None,
+        extension_rules: Default::default(),
     };
     let pwd = "secret_pass";
     roundtrip(opts, Some(pwd));
@@ -139,6 +245,8 @@ fn random_access_extract() {
         adaptive: false,
         adaptive_threshold: 0.8,
         algo: compress::CompressionAlgo::Zstd,
+        mem_budget_mb: None,
+        extension_rules: Default::default(),
     };
     compress::run(&[src_dir.path().to_path_buf()], &arch_path, opts, None).unwrap();
 
@@ -175,6 +283,8 @@ fn zstd_wrong_password_fails() {
         adaptive: false,
         adaptive_threshold: 0.8,
         algo: compress::CompressionAlgo::Zstd,
+        mem_budget_mb: None,
+        extension_rules: Default::default(),
     };
 
     let src_dir = tempdir().unwrap();
@@ -193,3 +303,95 @@ fn zstd_wrong_password_fails() {
     let res = blitzarch::extract::extract_files(&arch_path, &[], Some("wrong"), Some(out.path()), None);
     assert!(res.is_err(), "Extraction with wrong password should fail");
 }
+
+#[test]
+fn katana_expert_zstd_params_roundtrip() {
+    use blitzarch::katana_stream::{create_katana_archive_with_options, KatanaCreateOptions};
+    use zstd::stream::raw::CParameter;
+
+    let src = tempfile::tempdir().unwrap();
+    std::fs::write(src.path().join("text.txt"), "lorem ipsum dolor sit amet ".repeat(4096)).unwrap();
+    let arch_dir = tempfile::tempdir().unwrap();
+    let arch = arch_dir.path().join("params.blz");
+
+    let options = KatanaCreateOptions {
+        zstd_params: vec![CParameter::ChainLog(20), CParameter::TargetLength(64)],
+        ..Default::default()
+    };
+    create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 1, 0, None, None, Some(9), &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    ).unwrap();
+
+    let out = tempfile::tempdir().unwrap();
+    blitzarch::katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+    assert_eq!(
+        std::fs::read(src.path().join("text.txt")).unwrap(),
+        std::fs::read(out.path().join("text.txt")).unwrap()
+    );
+
+    // Out-of-range values are rejected before anything is written
+    let bad = KatanaCreateOptions { zstd_params: vec![CParameter::WindowLog(99)], ..Default::default() };
+    let bad_arch = arch_dir.path().join("bad.blz");
+    assert!(create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &bad_arch, 1, 0, None, None, None, &bad,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    ).is_err());
+}
+
+#[test]
+fn classic_pipelines_report_progress() {
+    use blitzarch::cli::{Args, WorkerMode};
+    use blitzarch::progress::ProgressState;
+    use clap::Parser;
+    use std::sync::{Arc, Mutex};
+
+    let src_dir = tempdir().unwrap();
+    create_test_data(src_dir.path(), 6, 4096).unwrap();
+    let arch_dir = tempdir().unwrap();
+
+    let recorder = || {
+        let states: Arc<Mutex<Vec<ProgressState>>> = Arc::default();
+        let sink = Arc::clone(&states);
+        (states, move |s: ProgressState| sink.lock().unwrap().push(s))
+    };
+    let check = |states: &Mutex<Vec<ProgressState>>| {
+        let states = states.lock().unwrap();
+        let last = states.last().expect("progress reported");
+        assert_eq!((last.processed_files, last.total_files), (6, 6));
+        assert_eq!(last.processed_bytes, 6 * 4096);
+        assert_eq!(last.completed_shards, last.total_shards);
+        assert_eq!(last.progress_percent, 100.0);
+    };
+
+    let (states, callback) = recorder();
+    let sequential = arch_dir.path().join("seq.blz");
+    let opts = compress::CompressOptions {
+        level: 3,
+        threads: 1,
+        text_bundle: TextBundleMode::Small,
+        adaptive: false,
+        adaptive_threshold: 0.8,
+        algo: compress::CompressionAlgo::Zstd,
+        mem_budget_mb: None,
+        extension_rules: Default::default(),
+    };
+    compress::run_with_progress(&[src_dir.path().to_path_buf()], &sequential, opts, None, Some(callback)).unwrap();
+    check(&states);
+
+    let (states, callback) = recorder();
+    let parallel = arch_dir.path().join("par.blz");
+    let args = Args::try_parse_from([
+        "blitzarch".as_ref(), "create".as_ref(), "--format".as_ref(), "classic".as_ref(),
+        "-o".as_ref(), parallel.as_os_str(), src_dir.path().as_os_str(),
+    ])
+    .unwrap();
+    blitzarch::workers::run_parallel_compression_with_progress(Arc::new(args.command), WorkerMode::W2, Some(callback)).unwrap();
+    check(&states);
+
+    for arch in [&sequential, &parallel] {
+        let out = tempdir().unwrap();
+        blitzarch::extract::extract_files(arch, &[], None, Some(out.path()), None).unwrap();
+        assert_eq!(fs::read_dir(out.path()).unwrap().count(), 6);
+    }
+}
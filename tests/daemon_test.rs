@@ -0,0 +1,157 @@
+#![cfg(unix)]
+
+use assert_cmd::prelude::*;
+use blitzarch::cancel::CancellationToken;
+use blitzarch::daemon::{self, Client, RpcError, Scheduler};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+/// Daemon process, killed when the test ends.
+struct Daemon(Child);
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn start_daemon(socket: &Path) -> Daemon {
+    let child = Command::cargo_bin("blitzarch")
+        .unwrap()
+        .args(["daemon", "--max-jobs", "1", "--memory-budget", "2048", "--socket"])
+        .arg(socket)
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while Client::connect(socket).is_err() {
+        assert!(Instant::now() < deadline, "daemon did not come up");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    Daemon(child)
+}
+
+#[test]
+fn runs_jobs_over_the_socket() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::write(src.join("docs/a.txt"), "alpha\n".repeat(1000)).unwrap();
+    fs::write(src.join("b.txt"), "beta").unwrap();
+    let socket = dir.path().join("daemon.sock");
+    let _daemon = start_daemon(&socket);
+    let mut client = Client::connect(&socket).unwrap();
+
+    let archive = dir.path().join("out.blz");
+    let mut events = Vec::new();
+    let created = client
+        .call("create", json!({ "inputs": [&src], "output": &archive, "threads": 1 }), |method, params| {
+            events.push((method.to_string(), params.clone()))
+        })
+        .unwrap();
+    assert_eq!(created["command"], "create");
+    assert_eq!(created["files"], 2);
+    let job = created["job"].as_u64().unwrap();
+    assert_eq!(events[0], ("job".to_string(), json!({ "job": job, "state": "queued" })));
+    assert_eq!(events[1], ("job".to_string(), json!({ "job": job, "state": "running" })));
+    assert!(events[2..].iter().all(|(method, params)| method == "progress" && params["job"] == job));
+
+    let listed = client.call("list", json!({ "archive": &archive }), |_, _| {}).unwrap();
+    let mut names: Vec<&str> = listed["entries"].as_array().unwrap().iter().map(|e| e["path"].as_str().unwrap()).collect();
+    names.sort();
+    assert_eq!(names, ["b.txt", "docs/a.txt"]);
+
+    let verified = client.call("verify", json!({ "archive": &archive, "deep": true }), |_, _| {}).unwrap();
+    assert_eq!(verified["ok"], true);
+
+    let out = dir.path().join("restored");
+    client.call("extract", json!({ "archive": &archive, "output_dir": &out, "files": ["docs"] }), |_, _| {}).unwrap();
+    assert_eq!(fs::read_to_string(out.join("docs/a.txt")).unwrap(), "alpha\n".repeat(1000));
+    assert!(!out.join("b.txt").exists());
+
+    let jobs = client.call("jobs", json!(null), |_, _| {}).unwrap();
+    assert_eq!(jobs.as_array().unwrap().len(), 4);
+    assert!(jobs.as_array().unwrap().iter().all(|j| j["state"] == "done"));
+    let status = client.call("status", json!(null), |_, _| {}).unwrap();
+    assert_eq!((status["running"].as_u64(), status["memory_budget_mb"].as_u64()), (Some(0), Some(2048)));
+}
+
+#[test]
+fn reports_errors_as_json_rpc_errors() {
+    let dir = tempdir().unwrap();
+    let socket = dir.path().join("daemon.sock");
+    let _daemon = start_daemon(&socket);
+    let mut client = Client::connect(&socket).unwrap();
+
+    let rpc_error = |e: Box<dyn std::error::Error>| e.downcast::<RpcError>().unwrap();
+    let err = rpc_error(client.call("frobnicate", json!({}), |_, _| {}).unwrap_err());
+    assert_eq!(err.code, daemon::METHOD_NOT_FOUND);
+    let err = rpc_error(client.call("create", json!({ "output": "x.blz" }), |_, _| {}).unwrap_err());
+    assert_eq!(err.code, daemon::INVALID_PARAMS);
+
+    let missing = dir.path().join("missing.blz");
+    let err = rpc_error(client.call("verify", json!({ "archive": &missing }), |_, _| {}).unwrap_err());
+    assert_eq!(err.code, daemon::JOB_FAILED);
+    assert!(err.data.unwrap()["job"].is_u64());
+
+    let err = rpc_error(client.call("cancel", json!({ "job": 999 }), |_, _| {}).unwrap_err());
+    assert_eq!(err.code, daemon::INVALID_PARAMS);
+
+    // Второй демон на том же сокете не стартует
+    let second = Command::cargo_bin("blitzarch").unwrap().args(["daemon", "--socket"]).arg(&socket).output().unwrap();
+    assert!(!second.status.success());
+    assert!(String::from_utf8_lossy(&second.stderr).contains("already listening"));
+}
+
+#[test]
+fn socket_is_private_to_the_user() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempdir().unwrap();
+    let socket = dir.path().join("run/daemon.sock");
+    let _daemon = start_daemon(&socket);
+    assert_eq!(fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+    assert_eq!(fs::metadata(dir.path().join("run")).unwrap().permissions().mode() & 0o777, 0o700);
+
+    // Каталог, куда могут писать другие, не годится
+    let shared = dir.path().join("shared");
+    fs::create_dir(&shared).unwrap();
+    fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+    let out = Command::cargo_bin("blitzarch").unwrap().args(["daemon", "--socket"]).arg(shared.join("d.sock")).output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("writable by other users"));
+    assert!(!shared.join("d.sock").exists());
+}
+
+#[test]
+fn scheduler_respects_slots_and_memory() {
+    let scheduler = Scheduler::new(2, 1000);
+    let token = CancellationToken::new();
+    let big = scheduler.admit(800, &token).unwrap();
+    assert_eq!(scheduler.running(), (1, 800));
+
+    // 300 MiB more do not fit until the big job is done
+    let waiter = {
+        let scheduler = scheduler.clone();
+        std::thread::spawn(move || scheduler.admit(300, &CancellationToken::new()).map(|p| p.memory_mb()))
+    };
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(scheduler.running(), (1, 800));
+    let small = scheduler.admit(150, &token).unwrap();
+    assert_eq!(scheduler.running(), (2, 950));
+    drop(big);
+    drop(small);
+    assert_eq!(waiter.join().unwrap(), Some(300));
+    assert_eq!(scheduler.running(), (0, 0));
+
+    // A job above the whole budget runs alone; a cancelled one gives up waiting
+    let huge = scheduler.admit(5000, &token).unwrap();
+    let cancelled = CancellationToken::new();
+    cancelled.cancel();
+    assert!(scheduler.admit(10, &cancelled).is_none());
+    drop(huge);
+}
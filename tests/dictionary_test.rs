@@ -0,0 +1,98 @@
+use assert_cmd::prelude::*;
+use blitzarch::api::Archive;
+use blitzarch::dictionary::{self, Dictionaries, ZstdDictionary};
+use blitzarch::katana;
+use blitzarch::katana_stream::ShardStrategy;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Small JSON records sharing most of their structure, like a nightly export.
+fn write_records(dir: &Path, night: u32) {
+    fs::create_dir_all(dir).unwrap();
+    for i in 0..60 {
+        let record = format!(
+            "{{\"night\":{night},\"customer_id\":{i},\"status\":\"active\",\"plan\":\"enterprise-annual\",\
+             \"region\":\"eu-central-1\",\"tags\":[\"billing\",\"priority-support\",\"sso\"],\"balance_cents\":{}}}\n",
+            i * 137 + night
+        );
+        fs::write(dir.join(format!("customer-{i:03}.json")), record).unwrap();
+    }
+}
+
+#[test]
+fn dictionary_series_roundtrip_needs_the_same_dictionary() {
+    let dir = tempdir().unwrap();
+    write_records(&dir.path().join("night0"), 0);
+    write_records(&dir.path().join("night1"), 1);
+    let dict = dictionary::train_dictionary(&dir.path().join("night0"), 16 * 1024).unwrap();
+    assert_eq!(dict.id().len(), 64);
+
+    let plain = dir.path().join("plain.blz");
+    let with_dict = dir.path().join("dict.blz");
+    // Отдельные маленькие фреймы – как раз тот случай, где словарь выигрывает
+    let per_file = || Archive::create([dir.path().join("night1")]).shard_strategy(ShardStrategy::PerFile);
+    per_file().write_to(&plain).unwrap();
+    per_file().dictionary(dict.clone()).write_to(&with_dict).unwrap();
+    assert!(fs::metadata(&with_dict).unwrap().len() < fs::metadata(&plain).unwrap().len());
+
+    // Без словаря чтение отказывает с понятной ошибкой
+    let err = katana::open_entry(&with_dict, "customer-007.json", None).err().unwrap().to_string();
+    assert!(err.contains(dict.id()) && err.contains("--dictionary"), "{err}");
+
+    // Словарь с другим содержимым – другой id, тоже не подходит
+    let mut dicts = Dictionaries::new();
+    dicts.insert(ZstdDictionary::from_bytes(b"not the dictionary".to_vec()).unwrap());
+    assert!(katana::open_entry_with(&with_dict, "customer-007.json", None, &dicts).is_err());
+
+    let series = dir.path().join("series.zdict");
+    fs::write(&series, dict.bytes()).unwrap();
+    dicts.insert(ZstdDictionary::load(&series).unwrap());
+    let mut text = String::new();
+    katana::open_entry_with(&with_dict, "customer-007.json", None, &dicts).unwrap().read_to_string(&mut text).unwrap();
+    assert!(text.starts_with("{\"night\":1,\"customer_id\":7,"));
+    assert!(katana::verify_archive_from(&with_dict, None, true, 0, None, &dicts).unwrap().corrupt.is_empty());
+
+    // Словарь передаётся с заданием, а не живёт в процессе
+    assert!(katana::open_entry(&with_dict, "customer-007.json", None).is_err());
+    let opened = Archive::open(&with_dict).unwrap().dictionary(dict.clone());
+    assert_eq!(opened.read("customer-007.json").unwrap(), text.as_bytes());
+}
+
+#[test]
+fn cli_trains_and_uses_an_external_dictionary() {
+    let dir = tempdir().unwrap();
+    write_records(&dir.path().join("sample"), 0);
+    write_records(&dir.path().join("src"), 1);
+    let zdict = dir.path().join("series.zdict");
+    let arch = dir.path().join("night1.blz");
+    let run = |args: &[&std::ffi::OsStr]| Command::cargo_bin("blitzarch").unwrap().args(args).output().unwrap();
+
+    let trained = run(&["train-dictionary".as_ref(), dir.path().join("sample").as_os_str(), "--output".as_ref(), zdict.as_os_str(), "--max-size".as_ref(), "16384".as_ref()]);
+    assert!(trained.status.success(), "{}", String::from_utf8_lossy(&trained.stderr));
+    assert!(fs::metadata(&zdict).unwrap().len() > 0);
+
+    let created = run(&["create".as_ref(), "--dictionary".as_ref(), zdict.as_os_str(), "--output".as_ref(), arch.as_os_str(), dir.path().join("src").as_os_str()]);
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+
+    let out = dir.path().join("out");
+    let missing = run(&["extract".as_ref(), arch.as_os_str(), "--output".as_ref(), out.as_os_str()]);
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("--dictionary"));
+
+    let extracted = run(&["extract".as_ref(), arch.as_os_str(), "--output".as_ref(), out.as_os_str(), "--dictionary".as_ref(), zdict.as_os_str()]);
+    assert!(extracted.status.success(), "{}", String::from_utf8_lossy(&extracted.stderr));
+    assert_eq!(
+        fs::read(out.join("customer-042.json")).unwrap(),
+        fs::read(dir.path().join("src/customer-042.json")).unwrap()
+    );
+
+    let verified = run(&["verify".as_ref(), "--deep".as_ref(), arch.as_os_str(), "--dictionary".as_ref(), zdict.as_os_str()]);
+    assert!(verified.status.success(), "{}", String::from_utf8_lossy(&verified.stderr));
+
+    // Только Katana умеет внешние словари
+    let classic = run(&["create".as_ref(), "--format".as_ref(), "classic".as_ref(), "--dictionary".as_ref(), zdict.as_os_str(), "--output".as_ref(), dir.path().join("c.blz").as_os_str(), dir.path().join("src").as_os_str()]);
+    assert!(!classic.status.success());
+}
@@ -0,0 +1,45 @@
+use blitzarch::katana_stream::{self, KatanaCreateOptions, ShardStrategy};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn dry_run_estimates_without_writing() {
+    let src = tempdir().unwrap();
+    let text: Vec<u8> = (0..400_000u32).flat_map(|i| format!("{} request {} took {} ms\n", i, i.wrapping_mul(7919) % 10007, i % 113).into_bytes()).collect();
+    fs::write(src.path().join("app.log"), &text).unwrap();
+    // Pseudo-random bytes behind a ZIP signature: dense by magic and by content
+    let mut noise = b"PK\x03\x04".to_vec();
+    let mut x = 0x9E37_79B9_7F4A_7C15u64;
+    noise.extend((0..300_000).map(|_| {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        (x >> 24) as u8
+    }));
+    fs::write(src.path().join("bundle.dat"), &noise).unwrap();
+    fs::write(src.path().join("tiny.txt"), b"hi").unwrap();
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("plan.blz");
+    let options = KatanaCreateOptions { shard_strategy: ShardStrategy::SizeCapped(1), inline_small_files: true, ..Default::default() };
+    let plan = katana_stream::estimate_create(&[src.path().to_path_buf()], &arch, 2, Some(3), false, &options).unwrap();
+    assert!(!arch.exists(), "dry run writes nothing");
+    assert_eq!((plan.files, plan.inline_files, plan.dense_files), (3, 1, 1));
+    assert_eq!(plan.bytes, (text.len() + noise.len() + 2) as u64);
+    assert_eq!(plan.dense_bytes, noise.len() as u64);
+    assert_eq!(plan.shards.len(), 2);
+    assert!(plan.sampled_bytes > 0);
+
+    katana_stream::create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 2, 0, None, None, Some(3), &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    // Head samples cannot see everything, but the magnitude has to be right
+    let actual = fs::metadata(&arch).unwrap().len();
+    assert!(plan.estimated_size > actual / 3 && plan.estimated_size < actual * 3, "{} vs {}", plan.estimated_size, actual);
+
+    // Encrypted archives never inline
+    let plan = katana_stream::estimate_create(&[src.path().to_path_buf()], &arch_dir.path().join("enc.blz"), 2, Some(3), true, &options).unwrap();
+    assert_eq!((plan.inline_files, plan.shards.len()), (0, 3));
+}
@@ -0,0 +1,65 @@
+use blitzarch::cli::TextBundleMode;
+use blitzarch::common::FileMetadata;
+use blitzarch::compress::{self, ExtensionRules};
+use blitzarch::config::UserConfig;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+fn file(name: &str, size: u64) -> FileMetadata {
+    FileMetadata {
+        absolute_path: PathBuf::from(name),
+        path: PathBuf::from(name),
+        size,
+        permissions: 0o644,
+        modified_time: 0,
+        created_time: None,
+        is_dir: false,
+        dense_hint: None,
+    }
+}
+
+#[test]
+fn config_file_lists_parse() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "[extensions]\ndense = [\"parquet\", \"-pdf\"]\ntext = [\".Proto\"]\n").unwrap();
+    let config = UserConfig::load(&path).unwrap();
+    assert_eq!(config.extensions.dense, ["parquet", "-pdf"]);
+    assert_eq!(config.extensions.text, [".Proto"]);
+
+    fs::write(&path, "[extensions]\ndens = [\"parquet\"]\n").unwrap();
+    assert!(UserConfig::load(&path).is_err());
+
+    // Later entries (CLI) win over earlier ones (config file)
+    let rules = ExtensionRules::default().dense(["parquet"]).dense(["-parquet"]);
+    assert_eq!(rules, ExtensionRules::default().dense(["-PARQUET"]));
+}
+
+#[test]
+fn user_text_extensions_get_text_bundles() {
+    // 40 MiB per file: two fit a 128 MiB text bundle, but not a 16 MiB one
+    let files: Vec<FileMetadata> = (0..2).map(|i| file(&format!("schema{i}.proto"), 40 << 20)).collect();
+    assert_eq!(compress::group_files_into_bundles(&files, TextBundleMode::Window, &ExtensionRules::default()).len(), 2);
+
+    let rules = ExtensionRules::default().text(["proto"]);
+    assert_eq!(compress::group_files_into_bundles(&files, TextBundleMode::Window, &rules).len(), 1);
+}
+
+#[test]
+fn user_dense_extensions_change_the_katana_estimate() {
+    use blitzarch::katana_stream::{self, KatanaCreateOptions};
+
+    let src = tempdir().unwrap();
+    for i in 0..4 {
+        fs::write(src.path().join(format!("part{i}.parquet")), "column,value\n".repeat(400)).unwrap();
+    }
+    let out = src.path().join("plan.blz");
+    let estimate = |extension_rules: ExtensionRules| {
+        let options = KatanaCreateOptions { extension_rules, ..Default::default() };
+        katana_stream::estimate_create(&[src.path().to_path_buf()], &out, 2, Some(3), false, &options).unwrap()
+    };
+    assert_eq!(estimate(ExtensionRules::default()).dense_files, 0);
+    let dense = estimate(ExtensionRules::default().dense(["parquet"]));
+    assert_eq!((dense.dense_files, dense.dense_bytes), (4, 4 * 13 * 400));
+}
@@ -0,0 +1,100 @@
+use assert_cmd::prelude::*;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::tempdir;
+
+const T0: u64 = 1_600_000_000;
+
+fn write_at(p: &Path, data: &[u8], secs: u64) {
+    fs::create_dir_all(p.parent().unwrap()).unwrap();
+    fs::write(p, data).unwrap();
+    File::options().write(true).open(p).unwrap().set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+}
+
+/// Archive with `a.txt` (newer than the files on disk), `b.txt` (older) and
+/// `docs/c.txt` (not on disk yet), plus an output directory holding the old
+/// `a.txt` and `b.txt`.
+fn fixture() -> (tempfile::TempDir, std::path::PathBuf, std::path::PathBuf) {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    write_at(&src.join("a.txt"), b"archived a", T0 + 100);
+    write_at(&src.join("b.txt"), b"archived b", T0);
+    write_at(&src.join("docs/c.txt"), b"archived c", T0);
+    let archive = dir.path().join("conflicts.blz");
+    katana_stream::create_katana_archive_with_options(
+        &[src], &archive, 1, 0, None, None, None, &KatanaCreateOptions::default(),
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    let out = dir.path().join("out");
+    write_at(&out.join("a.txt"), b"existing a", T0 + 50);
+    write_at(&out.join("b.txt"), b"existing b", T0 + 50);
+    (dir, archive, out)
+}
+
+fn extract(archive: &Path, out: &Path, flag: Option<&str>) {
+    let mut cmd = Command::cargo_bin("blitzarch").unwrap();
+    cmd.arg("extract").arg(archive).arg("-o").arg(out);
+    if let Some(flag) = flag {
+        cmd.arg(flag);
+    }
+    cmd.assert().success();
+}
+
+fn read(out: &Path, name: &str) -> String {
+    fs::read_to_string(out.join(name)).unwrap()
+}
+
+#[test]
+fn overwrites_by_default() {
+    for flag in [None, Some("--overwrite")] {
+        let (_dir, archive, out) = fixture();
+        extract(&archive, &out, flag);
+        assert_eq!((read(&out, "a.txt"), read(&out, "b.txt")), ("archived a".into(), "archived b".into()));
+    }
+}
+
+#[test]
+fn skip_existing_keeps_files_on_disk() {
+    let (_dir, archive, out) = fixture();
+    extract(&archive, &out, Some("--skip-existing"));
+    assert_eq!((read(&out, "a.txt"), read(&out, "b.txt")), ("existing a".into(), "existing b".into()));
+    assert_eq!(read(&out, "docs/c.txt"), "archived c");
+}
+
+#[test]
+fn rename_existing_moves_files_aside() {
+    let (_dir, archive, out) = fixture();
+    write_at(&out.join("a copy.txt"), b"older copy", T0);
+    extract(&archive, &out, Some("--rename-existing"));
+    assert_eq!(read(&out, "a.txt"), "archived a");
+    assert_eq!(read(&out, "a copy.txt"), "older copy");
+    assert_eq!(read(&out, "a copy 2.txt"), "existing a");
+    assert_eq!(read(&out, "b copy.txt"), "existing b");
+    assert!(!out.join("docs/c copy.txt").exists());
+}
+
+#[test]
+fn keep_newer_replaces_only_older_files() {
+    let (_dir, archive, out) = fixture();
+    extract(&archive, &out, Some("--keep-newer"));
+    assert_eq!((read(&out, "a.txt"), read(&out, "b.txt")), ("archived a".into(), "existing b".into()));
+    assert_eq!(read(&out, "docs/c.txt"), "archived c");
+}
+
+#[test]
+fn policies_are_mutually_exclusive() {
+    let (_dir, archive, out) = fixture();
+    Command::cargo_bin("blitzarch")
+        .unwrap()
+        .arg("extract")
+        .arg(&archive)
+        .arg("-o")
+        .arg(&out)
+        .args(["--skip-existing", "--keep-newer"])
+        .assert()
+        .failure();
+}
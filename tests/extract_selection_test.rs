@@ -0,0 +1,83 @@
+use blitzarch::api::Archive;
+use blitzarch::katana::{self, EntrySelector};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+fn extracted(arch: &Path, selected: &[&str]) -> Vec<String> {
+    let out = tempdir().unwrap();
+    let selected: Vec<PathBuf> = selected.iter().map(PathBuf::from).collect();
+    katana::extract_katana_archive_internal(arch, out.path(), &selected, None, None).unwrap();
+    let mut names: Vec<String> = walkdir::WalkDir::new(out.path())
+        .into_iter()
+        .map(|e| e.unwrap())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().strip_prefix(out.path()).unwrap().to_string_lossy().replace('\\', "/"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn selector_matches_paths_directories_and_globs() {
+    let sel = |s: &[&str]| EntrySelector::new(&s.iter().map(PathBuf::from).collect::<Vec<_>>()).unwrap();
+    let dir = sel(&["photos/2023/"]);
+    assert!(dir.matches("photos/2023/a.jpg") && dir.matches("photos/2023/sub/b.jpg"));
+    assert!(!dir.matches("photos/2023x.txt"), "a prefix only matches whole components");
+    assert!(sel(&["photos/2023/a.jpg"]).matches("photos/2023/a.jpg"));
+    let glob = sel(&["photos/*"]);
+    assert!(glob.matches("photos/2023/sub/b.jpg"), "a matching parent directory selects its contents");
+    assert!(!glob.matches("docs/readme.md"));
+    assert!(sel(&["**/*.md"]).matches("docs/readme.md"));
+    assert!(!sel(&["*.md"]).matches("docs/readme.md"), "* stays within a directory");
+    assert!(EntrySelector::new(&[PathBuf::from("[")]).is_err());
+}
+
+#[test]
+fn extract_expands_directories_and_globs() {
+    let src = tempdir().unwrap();
+    for name in ["photos/2023/a.jpg", "photos/2023/sub/b.jpg", "photos/2024/c.jpg", "photos/2023x.txt", "docs/readme.md"] {
+        let path = src.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, name).unwrap();
+    }
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("photos.blz");
+    Archive::create([src.path()]).threads(2).write_to(&arch).unwrap();
+
+    let year = ["photos/2023/a.jpg", "photos/2023/sub/b.jpg"];
+    assert_eq!(extracted(&arch, &["photos/2023"]), year);
+    assert_eq!(extracted(&arch, &["photos/2023/**"]), year);
+    assert_eq!(extracted(&arch, &["**/*.md", "photos/2024"]), ["docs/readme.md", "photos/2024/c.jpg"]);
+    assert!(extracted(&arch, &["missing"]).is_empty(), "an unmatched selection extracts nothing");
+
+    let tree = katana::extract_katana_to_memory(&arch, &[PathBuf::from("photos/2023")], None, 1 << 20).unwrap();
+    assert_eq!(tree.len(), 2);
+}
+
+#[test]
+fn relative_to_strips_the_prefix_of_the_selection() {
+    let src = tempdir().unwrap();
+    for name in ["photos/2023/a.jpg", "photos/2023/sub/b.jpg", "photos/2024/c.jpg", "docs/readme.md"] {
+        let path = src.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, name).unwrap();
+    }
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("photos.blz");
+    Archive::create([src.path().join("photos"), src.path().join("docs")]).write_to(&arch).unwrap();
+
+    let (files, strip) = katana::relative_to_prefix("photos/", &[PathBuf::from("photos/2023")], None).unwrap();
+    assert_eq!(strip, Some(1));
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &files, None, strip).unwrap();
+    assert_eq!(fs::read(out.path().join("2023/sub/b.jpg")).unwrap(), b"photos/2023/sub/b.jpg");
+    assert!(!out.path().join("photos").exists() && !out.path().join("2024").exists());
+
+    // Без выбора – всё под префиксом; --strip-components добавляется сверху
+    let (files, strip) = katana::relative_to_prefix("./photos", &[], Some(1)).unwrap();
+    assert_eq!((files, strip), (vec![PathBuf::from("photos")], Some(2)));
+    assert!(katana::relative_to_prefix("photos", &[PathBuf::from("docs")], None).is_err());
+    assert!(katana::relative_to_prefix("photos", &[PathBuf::from("photos2023")], None).is_err());
+    assert!(katana::relative_to_prefix("../x", &[], None).is_err());
+}
@@ -0,0 +1,125 @@
+use assert_cmd::prelude::*;
+use blitzarch::api::Archive;
+use blitzarch::extract::ExtractOptions;
+use blitzarch::formats::{self, ArchiveBackend, ArchiveKind};
+use blitzarch::katana::EntryInfo;
+use blitzarch::progress::ProgressState;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Toy format: `TOYARC\n` followed by `name=content` lines.
+struct ToyBackend;
+
+impl ArchiveBackend for ToyBackend {
+    fn name(&self) -> &'static str {
+        "toy"
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<bool> {
+        Ok(fs::read(path)?.starts_with(b"TOYARC\n"))
+    }
+
+    fn list_entries(&self, path: &Path, _password: Option<&str>) -> Result<Vec<EntryInfo>, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        Ok(text
+            .lines()
+            .skip(1)
+            .filter_map(|l| l.split_once('='))
+            .map(|(name, content)| EntryInfo {
+                path: name.to_string(),
+                size: content.len() as u64,
+                permissions: None,
+                mtime: None,
+                is_dir: false,
+                shard_id: None,
+                compressed_size: None,
+                symlink: None,
+                duplicate_of: None,
+            })
+            .collect())
+    }
+
+    fn extract(
+        &self,
+        path: &Path,
+        output_dir: &Path,
+        _selected: &[PathBuf],
+        _password: Option<&str>,
+        _strip_components: Option<u32>,
+        _progress: Option<&dyn Fn(ProgressState)>,
+        _options: &ExtractOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(output_dir)?;
+        for line in fs::read_to_string(path)?.lines().skip(1) {
+            if let Some((name, content)) = line.split_once('=') {
+                fs::write(output_dir.join(name), content)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn detects_formats_by_signature() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("a.txt"), "alpha").unwrap();
+
+    let katana = dir.path().join("k.blz");
+    Archive::create([&src]).write_to(&katana).unwrap();
+    assert_eq!(formats::detect(&katana).unwrap(), ArchiveKind::Katana);
+
+    let seven = dir.path().join("photos.blz"); // расширение не важно
+    fs::write(&seven, b"7z\xBC\xAF\x27\x1C\x00\x04rest of the header").unwrap();
+    assert_eq!(formats::detect(&seven).unwrap(), ArchiveKind::SevenZip);
+    let rar = dir.path().join("r.rar");
+    fs::write(&rar, b"Rar!\x1A\x07\x01\x00more").unwrap();
+    assert_eq!(formats::detect(&rar).unwrap(), ArchiveKind::Rar);
+    let text = dir.path().join("notes.zip");
+    fs::write(&text, "just some notes").unwrap();
+    assert_eq!(formats::detect(&text).unwrap(), ArchiveKind::Unknown);
+    fs::write(dir.path().join("empty"), b"").unwrap();
+    assert_eq!(formats::detect(&dir.path().join("empty")).unwrap(), ArchiveKind::Unknown);
+
+    let err = blitzarch::extract::list_entries(&seven, None).unwrap_err().to_string();
+    assert!(err.contains("is a 7z archive"), "{err}");
+    let err = blitzarch::extract::extract_files(&text, &[], None, Some(&dir.path().join("out")), None).unwrap_err().to_string();
+    assert!(err.contains("not an archive BlitzArch can read"), "{err}");
+    assert!(!err.contains("Katana"), "{err}");
+}
+
+#[test]
+fn registered_backend_reads_its_format() {
+    let dir = tempdir().unwrap();
+    let toy = dir.path().join("bundle.toy");
+    fs::write(&toy, "TOYARC\nhello.txt=hi there\nbye.txt=see you\n").unwrap();
+    formats::register_backend(ToyBackend);
+
+    assert_eq!(formats::detect(&toy).unwrap(), ArchiveKind::Plugin("toy"));
+    let names: Vec<String> = blitzarch::extract::list_entries(&toy, None).unwrap().into_iter().map(|e| e.path).collect();
+    assert_eq!(names, ["hello.txt", "bye.txt"]);
+    let out = dir.path().join("out");
+    blitzarch::extract::extract_files(&toy, &[], None, Some(&out), None).unwrap();
+    assert_eq!(fs::read_to_string(out.join("bye.txt")).unwrap(), "see you");
+}
+
+#[test]
+fn cli_reports_unsupported_formats() {
+    let dir = tempdir().unwrap();
+    let rar = dir.path().join("holiday.rar");
+    fs::write(&rar, b"Rar!\x1A\x07\x00old rar").unwrap();
+
+    let extract = Command::cargo_bin("blitzarch").unwrap().args(["extract", "--output"]).arg(dir.path().join("out")).arg(&rar).output().unwrap();
+    assert!(!extract.status.success());
+    assert!(String::from_utf8_lossy(&extract.stderr).contains("is a RAR archive"));
+
+    let list = Command::cargo_bin("blitzarch").unwrap().args(["--error-format", "json", "list", "--json"]).arg(&rar).output().unwrap();
+    assert!(!list.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&list.stderr).unwrap();
+    assert_eq!(report["code"], "unsupported_format");
+}
@@ -0,0 +1,58 @@
+use blitzarch::cancel::CancellationToken;
+use blitzarch::governor::{Limits, ResourceGovernor, MIN_LEASE_MB};
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[test]
+fn concurrent_leases_share_the_limits() {
+    let governor = ResourceGovernor::new(Limits { threads: 8, memory_mb: 1000 });
+    let first = governor.register(6, 600, None).unwrap();
+    assert_eq!((first.threads(), first.memory_mb()), (6, 600));
+
+    // The second job gets what is left, not what it asked for
+    let second = governor.register(8, 800, None).unwrap();
+    assert_eq!((second.threads(), second.memory_mb()), (2, 400));
+    assert_eq!(governor.in_use(), (2, Limits { threads: 8, memory_mb: 1000 }));
+
+    drop(first);
+    assert_eq!(governor.in_use(), (1, Limits { threads: 2, memory_mb: 400 }));
+    let third = governor.register(8, 100, None).unwrap();
+    assert_eq!((third.threads(), third.memory_mb()), (6, 100));
+}
+
+#[test]
+fn a_lone_job_always_starts() {
+    let governor = ResourceGovernor::new(Limits { threads: 2, memory_mb: 32 });
+    let lease = governor.register(16, 4096, None).unwrap();
+    assert_eq!((lease.threads(), lease.memory_mb()), (2, MIN_LEASE_MB));
+}
+
+#[test]
+fn jobs_wait_for_free_threads_and_can_be_cancelled() {
+    let governor = ResourceGovernor::new(Limits { threads: 4, memory_mb: 1000 });
+    let running = governor.register(4, 100, None).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let waiting = std::sync::Arc::clone(&governor);
+    let handle = std::thread::spawn(move || {
+        let lease = waiting.register(2, 100, None).unwrap();
+        tx.send(lease.threads()).unwrap();
+    });
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+    drop(running);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 2);
+    handle.join().unwrap();
+
+    let _running = governor.register(4, 100, None).unwrap();
+    let token = CancellationToken::new();
+    token.cancel();
+    assert!(governor.register(1, 100, Some(&token)).is_none());
+}
+
+#[test]
+fn install_runs_in_a_pool_of_the_leased_size() {
+    let governor = ResourceGovernor::new(Limits { threads: 3, memory_mb: 1000 });
+    let lease = governor.register(8, 100, None).unwrap();
+    // Leased workers plus the coordinator thread
+    assert_eq!(lease.install(rayon::current_num_threads), 4);
+}
@@ -0,0 +1,102 @@
+use blitzarch::api::Archive;
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn hidden_index_needs_the_password() {
+    let src = tempdir().unwrap();
+    fs::create_dir_all(src.path().join("payroll")).unwrap();
+    fs::write(src.path().join("payroll/very_secret_name.txt"), b"salaries").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("hidden.blz");
+    Archive::create([src.path()]).password("alpha").hide_names(true).write_to(&arch).unwrap();
+
+    // Ни имён, ни открытого JSON-индекса в файле
+    let raw = fs::read(&arch).unwrap();
+    assert!(!raw.windows(b"very_secret_name".len()).any(|w| w == b"very_secret_name"));
+    let err = katana::list_entries(&arch, None).unwrap_err().to_string();
+    assert!(err.contains("hidden"), "{err}");
+    assert!(katana::list_entries(&arch, Some("wrong")).is_err());
+
+    let entries = katana::list_entries(&arch, Some("alpha")).unwrap();
+    assert!(entries.iter().any(|e| e.path.ends_with("payroll/very_secret_name.txt")));
+    let out = tempdir().unwrap();
+    Archive::open(&arch).unwrap().password("alpha").extract_to(out.path()).unwrap();
+    let restored = walk_one(out.path(), "very_secret_name.txt");
+    assert_eq!(fs::read(restored).unwrap(), b"salaries");
+
+    // Index rewrites seal the index again
+    katana::add_key_slot(&arch, "alpha", "beta").unwrap();
+    let raw = fs::read(&arch).unwrap();
+    assert!(!raw.windows(b"very_secret_name".len()).any(|w| w == b"very_secret_name"));
+    assert!(katana::list_entries(&arch, None).is_err());
+    assert_eq!(katana::list_entries(&arch, Some("beta")).unwrap().len(), entries.len());
+}
+
+#[test]
+fn index_readers_open_hidden_indexes_with_the_password() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    fs::write(src.path().join("b.txt"), b"beta").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("hidden.blz");
+    Archive::create([src.path()]).password("alpha").hide_names(true).write_to(&arch).unwrap();
+
+    assert!(katana::open_lazy(&arch).is_err());
+    let lazy = katana::open_lazy_with_password(&arch, Some("alpha")).unwrap();
+    assert_eq!(lazy.entries().iter().filter(|e| !e.is_dir).count(), 2);
+    assert!(katana::index_stats(&arch).is_err());
+    let stats = katana::index_stats_with_password(&arch, Some("alpha")).unwrap();
+    assert!(stats.encrypted);
+    assert_eq!(stats.total_size, 9);
+    assert_eq!(katana::key_slot_count(&arch, Some("alpha")).unwrap(), 0);
+
+    // `create --skip-if-unchanged` reads the fingerprint out of the sealed index
+    let inputs = vec![src.path().to_path_buf()];
+    let options = KatanaCreateOptions::default();
+    assert!(katana::archive_source_fingerprint(&arch, Some("alpha")).unwrap().is_some());
+    assert!(katana_stream::source_unchanged(&inputs, &arch, &options, Some("alpha")).unwrap());
+    fs::write(src.path().join("c.txt"), b"gamma").unwrap();
+    assert!(!katana_stream::source_unchanged(&inputs, &arch, &options, Some("alpha")).unwrap());
+}
+
+#[test]
+fn hide_names_requires_a_password() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"a").unwrap();
+    let dir = tempdir().unwrap();
+    let err = Archive::create([src.path()]).hide_names(true).write_to(dir.path().join("plain.blz")).unwrap_err();
+    assert!(err.to_string().contains("--hide-names requires a password"));
+}
+
+fn walk_one(root: &std::path::Path, name: &str) -> std::path::PathBuf {
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_map(Result::ok)
+        .find(|e| e.file_name() == name)
+        .map(|e| e.into_path())
+        .unwrap()
+}
+
+#[test]
+fn plain_list_uses_the_password() {
+    use assert_cmd::prelude::*;
+
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("very_secret_name.txt"), b"salaries").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("hidden.blz");
+    Archive::create([src.path()]).password("alpha").hide_names(true).write_to(&arch).unwrap();
+
+    let listing = std::process::Command::cargo_bin("blitzarch")
+        .unwrap()
+        .env_remove("BLITZARCH_PASSWORD")
+        .args(["list", "--password", "alpha"])
+        .arg(&arch)
+        .output()
+        .unwrap();
+    assert!(listing.status.success(), "{}", String::from_utf8_lossy(&listing.stderr));
+    assert!(String::from_utf8_lossy(&listing.stdout).contains("very_secret_name.txt"));
+}
@@ -0,0 +1,59 @@
+use blitzarch::autotune::{BottleneckType, IoConcurrency, IoLimits};
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, perform_paranoid_check, KatanaCreateOptions};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn io_limits_resolve() {
+    let auto = IoLimits::default();
+    assert_eq!(
+        auto.resolve(8, &BottleneckType::Balanced, false),
+        IoConcurrency { reads: 8, compressions: 8, writes: 8 }
+    );
+    assert_eq!(
+        auto.resolve(8, &BottleneckType::Balanced, true),
+        IoConcurrency { reads: 4, compressions: 8, writes: 8 }
+    );
+    assert_eq!(
+        auto.resolve(8, &BottleneckType::IOBound, false),
+        IoConcurrency { reads: 4, compressions: 8, writes: 4 }
+    );
+    assert_eq!(auto.resolve(8, &BottleneckType::MemoryBound, false).compressions, 4);
+    assert_eq!(auto.resolve(1, &BottleneckType::IOBound, true), IoConcurrency { reads: 1, compressions: 1, writes: 1 });
+
+    // Explicit caps win over autotuning, clamped to 1..=workers
+    let fixed = IoLimits { reads: Some(2), compressions: Some(0), writes: Some(64) };
+    assert_eq!(
+        fixed.resolve(8, &BottleneckType::IOBound, true),
+        IoConcurrency { reads: 2, compressions: 1, writes: 8 }
+    );
+}
+
+#[test]
+fn capped_pipeline_roundtrip() {
+    let src = tempdir().unwrap();
+    for i in 0..24 {
+        fs::write(src.path().join(format!("f{:02}.txt", i)), format!("file {} ", i).repeat(3000)).unwrap();
+    }
+    for password in [None, Some("caps")] {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("capped.blz");
+        let options = KatanaCreateOptions {
+            io_limits: IoLimits { reads: Some(1), compressions: Some(2), writes: Some(1) },
+            ..Default::default()
+        };
+        katana_stream::create_katana_archive_with_options(
+            &[src.path().to_path_buf()], &arch, 4, 0, None, password.map(String::from), None, &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+        perform_paranoid_check(&arch).unwrap();
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], password.map(String::from), None).unwrap();
+        for i in 0..24 {
+            let name = format!("f{:02}.txt", i);
+            assert_eq!(fs::read(out.path().join(&name)).unwrap(), fs::read(src.path().join(&name)).unwrap());
+        }
+    }
+}
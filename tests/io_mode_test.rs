@@ -0,0 +1,51 @@
+use blitzarch::fsx::{self, IoConfig, IoMode, IoWriter};
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use tempfile::tempdir;
+
+/// Not a multiple of the direct I/O block size.
+fn content(len: usize) -> Vec<u8> {
+    (0..len as u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect()
+}
+
+#[test]
+fn every_io_mode_roundtrips_large_files() {
+    let src = tempdir().unwrap();
+    let big = content((9 << 20) + 1234);
+    fs::write(src.path().join("big.bin"), &big).unwrap();
+    fs::write(src.path().join("small.txt"), b"small").unwrap();
+
+    for mode in [IoMode::Buffered, IoMode::Readahead, IoMode::Direct] {
+        let io = IoConfig { mode, readahead: 1 << 20 };
+
+        let mut read_back = Vec::new();
+        fsx::open_sequential(&src.path().join("big.bin"), io).unwrap().read_to_end(&mut read_back).unwrap();
+        assert!(read_back == big, "{mode:?}: open_sequential");
+
+        let dir = tempdir().unwrap();
+        let written = dir.path().join("written.bin");
+        let mut file = File::create(&written).unwrap();
+        let mut writer = IoWriter::new(&mut file, mode);
+        for piece in big.chunks(1_000_003) {
+            writer.write_all(piece).unwrap();
+            writer.flush().unwrap();
+        }
+        writer.finish().unwrap();
+        drop(file);
+        assert!(fs::read(&written).unwrap() == big, "{mode:?}: IoWriter");
+
+        let arch = dir.path().join("io.blz");
+        katana_stream::create_katana_archive_with_options(
+            &[src.path().to_path_buf()], &arch, 2, 0, None, None, None, &KatanaCreateOptions { io, ..Default::default() },
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+        let out = dir.path().join("out");
+        fs::create_dir_all(&out).unwrap();
+        katana::extract_katana_archive_internal(&arch, &out, &[], None, None).unwrap();
+        assert!(fs::read(out.join("big.bin")).unwrap() == big, "{mode:?}: archive");
+        assert_eq!(fs::read(out.join("small.txt")).unwrap(), b"small");
+    }
+}
@@ -0,0 +1,64 @@
+use assert_cmd::prelude::*;
+use serde_json::Value;
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+fn run_json(args: &[&std::ffi::OsStr]) -> Value {
+    let output = Command::cargo_bin("blitzarch").unwrap().args(args).output().unwrap();
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    // stdout carries nothing but the JSON object
+    serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("{}: {}", e, String::from_utf8_lossy(&output.stdout)))
+}
+
+#[test]
+fn create_extract_verify_list_emit_json() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), "alpha ".repeat(1000)).unwrap();
+    fs::write(src.path().join("b.txt"), b"beta").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("j.blz");
+
+    let created = run_json(&["create".as_ref(), "--json".as_ref(), "--output".as_ref(), arch.as_os_str(), src.path().as_os_str()]);
+    assert_eq!(created["command"], "create");
+    assert_eq!(created["written"], true);
+    assert_eq!(created["files"], 2);
+    assert_eq!(created["input_bytes"], 6004);
+    assert!(created["archive_bytes"].as_u64().unwrap() > 0);
+    assert!(created["warnings"].as_array().unwrap().is_empty());
+
+    let out = dir.path().join("out");
+    let extracted = run_json(&["extract".as_ref(), "--json".as_ref(), arch.as_os_str(), "--output".as_ref(), out.as_os_str()]);
+    assert_eq!(extracted["command"], "extract");
+    assert_eq!(extracted["files"], 2);
+    assert_eq!(extracted["bytes"], 6004);
+
+    let verified = run_json(&["verify".as_ref(), "--json".as_ref(), arch.as_os_str()]);
+    assert_eq!(verified["command"], "verify");
+    assert_eq!(verified["ok"], true);
+    assert_eq!(verified["files"], 2);
+
+    let listed = run_json(&["list".as_ref(), "--json".as_ref(), arch.as_os_str()]);
+    assert_eq!(listed["command"], "list");
+    assert_eq!(listed["entries"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn failed_json_command_emits_an_error_object() {
+    let dir = tempdir().unwrap();
+    let missing = dir.path().join("missing.blz");
+    let out = dir.path().join("out");
+    let output = Command::cargo_bin("blitzarch")
+        .unwrap()
+        .args(["extract".as_ref(), "--json".as_ref(), missing.as_os_str(), "--output".as_ref(), out.as_os_str()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let failed: Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("{}: {}", e, String::from_utf8_lossy(&output.stdout)));
+    assert_eq!(failed["command"], "extract");
+    assert_eq!(failed["ok"], false);
+    assert!(!failed["error"]["code"].as_str().unwrap().is_empty());
+    assert!(!failed["error"]["message"].as_str().unwrap().is_empty());
+}
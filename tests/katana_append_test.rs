@@ -0,0 +1,96 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, perform_paranoid_check};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(p: &Path, data: &[u8]) {
+    if let Some(parent) = p.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    File::create(p).unwrap().write_all(data).unwrap();
+}
+
+fn create_stream(src: &Path, arch: &Path, password: Option<String>) {
+    katana_stream::create_katana_archive(
+        &[src.to_path_buf()], arch, 2, 0, None, password, None,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+}
+
+#[test]
+fn append_adds_entries_plain_and_encrypted() {
+    for password in [None, Some("append-pw")] {
+        let src = tempdir().unwrap();
+        write_file(&src.path().join("a.txt"), &b"first ".repeat(2000));
+        write_file(&src.path().join("dir/b.bin"), &[5u8; 30_000]);
+        let more = tempdir().unwrap();
+        write_file(&more.path().join("new/c.txt"), &b"second ".repeat(3000));
+        write_file(&more.path().join("new/d.bin"), &[9u8; 12_345]);
+
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("grow.blz");
+        create_stream(src.path(), &arch, password.map(String::from));
+
+        let report = katana::append_to_archive(
+            &arch,
+            &[more.path().to_path_buf()],
+            2,
+            password.map(String::from),
+            Some(3),
+        )
+        .unwrap();
+        assert_eq!(report.files_added, 2);
+        perform_paranoid_check(&arch).unwrap();
+
+        let mut names: Vec<_> = katana::list_entries(&arch, password).unwrap().into_iter().map(|e| e.path).collect();
+        names.sort();
+        assert_eq!(names, ["a.txt", "dir/b.bin", "new/c.txt", "new/d.bin"]);
+
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], password.map(String::from), None).unwrap();
+        assert_eq!(fs::read(out.path().join("a.txt")).unwrap(), b"first ".repeat(2000));
+        assert_eq!(fs::read(out.path().join("new/c.txt")).unwrap(), b"second ".repeat(3000));
+        assert_eq!(fs::read(out.path().join("new/d.bin")).unwrap(), vec![9u8; 12_345]);
+
+        if password.is_some() {
+            let extra = tempdir().unwrap();
+            write_file(&extra.path().join("e.txt"), b"extra");
+            assert!(katana::append_to_archive(&arch, &[extra.path().to_path_buf()], 1, None, None).is_err());
+        }
+    }
+}
+
+#[test]
+fn append_rejects_duplicates_without_touching_archive() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("a.txt"), b"original");
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("dup.blz");
+    create_stream(src.path(), &arch, None);
+    let before = fs::read(&arch).unwrap();
+
+    let other = tempdir().unwrap();
+    write_file(&other.path().join("a.txt"), b"replacement");
+    let err = katana::append_to_archive(&arch, &[other.path().to_path_buf()], 1, None, None).unwrap_err();
+    assert!(err.to_string().contains("already exists"), "{}", err);
+    assert_eq!(fs::read(&arch).unwrap(), before);
+    // No staging leftovers next to the archive
+    assert_eq!(fs::read_dir(arch_dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn append_skips_archive_inside_input_dir() {
+    let work = tempdir().unwrap();
+    write_file(&work.path().join("data/one.txt"), b"one");
+    let arch = work.path().join("self.blz");
+    create_stream(&work.path().join("data"), &arch, None);
+    write_file(&work.path().join("sub/two.txt"), b"two");
+
+    // `work` contains the archive itself
+    katana::append_to_archive(&arch, &[work.path().to_path_buf()], 1, None, None).unwrap();
+    let names: Vec<_> = katana::list_entries(&arch, None).unwrap().into_iter().map(|e| e.path).collect();
+    assert_eq!(names, ["one.txt", "data/one.txt", "sub/two.txt"]);
+}
@@ -0,0 +1,70 @@
+use blitzarch::cancel::{self, CancellationToken};
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions, ShardStrategy};
+use blitzarch::progress::ProgressState;
+use blitzarch::ErrorReport;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn create(src: &Path, arch: &Path, options: &KatanaCreateOptions, progress: Option<impl Fn(ProgressState) + Send + Sync + 'static>) -> Result<(), Box<dyn std::error::Error>> {
+    katana_stream::create_katana_archive_with_options(&[src.to_path_buf()], arch, 2, 0, None, None, None, options, progress)
+}
+
+#[test]
+fn cancelled_jobs_leave_no_partial_output() {
+    let src = tempdir().unwrap();
+    for i in 0..24 {
+        fs::write(src.path().join(format!("f{i:02}.bin")), vec![i as u8; 64 * 1024]).unwrap();
+    }
+    fs::create_dir_all(src.path().join("sub/deeper")).unwrap();
+    fs::write(src.path().join("sub/deeper/last.txt"), b"last").unwrap();
+    let arch_dir = tempdir().unwrap();
+
+    // Cancelled before the job starts
+    let token = CancellationToken::new();
+    token.cancel();
+    let options = KatanaCreateOptions { cancel: Some(token.clone()), ..Default::default() };
+    let arch = arch_dir.path().join("early.blz");
+    let err = create(src.path(), &arch, &options, None::<fn(ProgressState)>).unwrap_err();
+    assert!(cancel::is_cancelled(err.as_ref()), "{err}");
+    assert_eq!(ErrorReport::from_error(err.as_ref()).code, "cancelled");
+    assert!(!arch.exists());
+
+    // Cancelled from the progress callback after the first finished shard
+    let token = CancellationToken::new();
+    let options = KatanaCreateOptions {
+        cancel: Some(token.clone()),
+        shard_strategy: ShardStrategy::SizeCapped(1),
+        ..Default::default()
+    };
+    let arch = arch_dir.path().join("mid.blz");
+    let err = create(src.path(), &arch, &options, Some(move |_: ProgressState| token.cancel())).unwrap_err();
+    assert!(cancel::is_cancelled(err.as_ref()), "{err}");
+    assert!(!arch.exists());
+
+    // An untouched token changes nothing
+    let options = KatanaCreateOptions { cancel: Some(CancellationToken::new()), ..Default::default() };
+    let arch = arch_dir.path().join("full.blz");
+    create(src.path(), &arch, &options, None::<fn(ProgressState)>).unwrap();
+
+    // Extraction removes what it created and keeps what was there before
+    let out = tempdir().unwrap();
+    fs::write(out.path().join("f00.bin"), b"mine").unwrap();
+    let token = CancellationToken::new();
+    token.cancel();
+    let err = katana::extract_katana_archive_cancellable(
+        &arch, out.path(), &[], None, None, None::<fn(ProgressState)>, &token,
+    )
+    .unwrap_err();
+    assert!(cancel::is_cancelled(err.as_ref()), "{err}");
+    let left: Vec<_> = fs::read_dir(out.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(left, ["f00.bin"]);
+    assert_eq!(fs::read(out.path().join("f00.bin")).unwrap(), b"mine");
+
+    katana::extract_katana_archive_cancellable(
+        &arch, out.path(), &[], None, None, None::<fn(ProgressState)>, &CancellationToken::new(),
+    )
+    .unwrap();
+    assert_eq!(fs::read(out.path().join("sub/deeper/last.txt")).unwrap(), b"last");
+}
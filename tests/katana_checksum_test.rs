@@ -0,0 +1,89 @@
+use blitzarch::katana::{self, IndexCompression, ShardChecksum};
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use rand::{RngCore, SeedableRng};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_random(p: &Path, len: usize, seed: u64) {
+    fs::create_dir_all(p.parent().unwrap()).unwrap();
+    let mut data = vec![0u8; len];
+    rand::rngs::StdRng::seed_from_u64(seed).fill_bytes(&mut data);
+    fs::write(p, data).unwrap();
+}
+
+fn extract_and_compare(arch: &Path, src: &Path, names: &[&str], password: Option<&str>) {
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(arch, out.path(), &[], password.map(String::from), None).unwrap();
+    for p in names {
+        assert_eq!(fs::read(out.path().join(p)).unwrap(), fs::read(src.join(p)).unwrap(), "{}", p);
+    }
+}
+
+#[test]
+fn xxh3_checksums_roundtrip_and_detect_corruption() {
+    let src = tempdir().unwrap();
+    write_random(&src.path().join("a.bin"), 300_000, 1);
+    write_random(&src.path().join("dir/b.bin"), 200_000, 2);
+    fs::write(src.path().join("dir/c.txt"), b"text ".repeat(10_000)).unwrap();
+    fs::write(src.path().join("dir/small.txt"), b"below the chunking threshold").unwrap();
+    let names = ["a.bin", "dir/b.bin", "dir/c.txt", "dir/small.txt"];
+
+    for password in [None, Some("xxh3-pw")] {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("xxh3.blz");
+        let options = KatanaCreateOptions {
+            shard_checksum: ShardChecksum::Xxh3,
+            chunk_dedup: true,
+            index_compression: IndexCompression::Store,
+            ..Default::default()
+        };
+        katana_stream::create_katana_archive_with_options(
+            &[src.path().to_path_buf()], &arch, 2, 0, None, password.map(String::from), None, &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+        let bytes = fs::read(&arch).unwrap();
+        let index = String::from_utf8_lossy(&bytes);
+        assert!(index.contains("\"xxh3\""), "shards record XXH3");
+        assert!(katana::verify_archive(&arch, password, true).unwrap().deep);
+        extract_and_compare(&arch, src.path(), &names, password);
+
+        // Appended shards keep using XXH3; conversions carry it over
+        let more = tempdir().unwrap();
+        write_random(&more.path().join("d.bin"), 100_000, 3);
+        katana::append_to_archive(&arch, &[more.path().join("d.bin")], 1, password.map(String::from), None).unwrap();
+        assert!(katana::verify_archive(&arch, password, true).unwrap().deep);
+        katana::compact_katana_archive(&arch, password.map(String::from)).unwrap();
+        if let Some(pw) = password {
+            katana::decrypt_katana_archive(&arch, pw).unwrap();
+            assert!(katana::verify_archive(&arch, None, true).unwrap().deep);
+            katana::encrypt_katana_archive(&arch, pw).unwrap();
+        }
+        assert!(katana::verify_archive(&arch, password, true).unwrap().deep);
+        extract_and_compare(&arch, src.path(), &names, password);
+
+        // A flipped byte in the first shard is caught by its XXH3
+        let mut bytes = fs::read(&arch).unwrap();
+        bytes[10] ^= 0x55;
+        fs::write(&arch, &bytes).unwrap();
+        let out = tempdir().unwrap();
+        let err = katana::extract_katana_archive_internal(&arch, out.path(), &[], password.map(String::from), None)
+            .unwrap_err();
+        assert!(err.to_string().contains("XXH3 mismatch"), "{}", err);
+    }
+
+    // CRC32 stays the default and leaves the feature bit alone
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("crc.blz");
+    let options = KatanaCreateOptions { index_compression: IndexCompression::Store, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 2, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    let bytes = fs::read(&arch).unwrap();
+    assert!(!String::from_utf8_lossy(&bytes).contains("\"xxh3\""));
+    assert_eq!("xxh3".parse::<ShardChecksum>(), Ok(ShardChecksum::Xxh3));
+    assert!("md5".parse::<ShardChecksum>().is_err());
+}
@@ -0,0 +1,97 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, perform_paranoid_check};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(p: &Path, data: &[u8]) {
+    if let Some(parent) = p.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    File::create(p).unwrap().write_all(data).unwrap();
+}
+
+fn create_stream(src: &Path, arch: &Path, password: Option<String>) {
+    katana_stream::create_katana_archive(
+        &[src.to_path_buf()], arch, 2, 0, None, password, None,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+}
+
+#[test]
+fn compact_drops_dead_prefix() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("a.txt"), &b"alpha ".repeat(1000));
+    write_file(&src.path().join("dir/b.bin"), &[7u8; 20_000]);
+
+    // Leftover bytes of an interrupted write in front of the shards
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("dirty.blz");
+    fs::write(&arch, vec![0xAAu8; 50_000]).unwrap();
+    create_stream(src.path(), &arch, None);
+    let size_before = fs::metadata(&arch).unwrap().len();
+
+    let report = katana::compact_katana_archive(&arch, None).unwrap();
+    assert_eq!(report.old_size, size_before);
+    // Rebuilt index may differ by a few bytes
+    assert!(report.reclaimed_bytes().abs_diff(50_000) < 64, "{}", report.reclaimed_bytes());
+    assert_eq!(fs::metadata(&arch).unwrap().len(), report.new_size);
+    perform_paranoid_check(&arch).unwrap();
+
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+    assert_eq!(fs::read(out.path().join("dir/b.bin")).unwrap(), vec![7u8; 20_000]);
+
+    // Already minimal – nothing to reclaim, no leftovers in the directory
+    assert!(katana::compact_katana_archive(&arch, None).unwrap().reclaimed_bytes() < 64);
+    assert_eq!(fs::read_dir(arch_dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn compact_encrypted_requires_password() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("secret.txt"), b"top secret");
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("enc.blz");
+    let password = "hunter2".to_string();
+    create_stream(src.path(), &arch, Some(password.clone()));
+
+    assert!(katana::compact_katana_archive(&arch, None).is_err());
+    katana::compact_katana_archive(&arch, Some(password.clone())).unwrap();
+
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &[], Some(password), None).unwrap();
+    assert_eq!(fs::read(out.path().join("secret.txt")).unwrap(), b"top secret");
+}
+
+#[test]
+fn compact_if_wasteful_honours_threshold() {
+    let src = tempdir().unwrap();
+    // Несжимаемые 60 КБ: мёртвый префикс – чуть меньше половины архива
+    let mut x = 0x9E37_79B9_7F4A_7C15u64;
+    let noise: Vec<u8> = (0..60_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+    write_file(&src.path().join("noise.bin"), &noise);
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("dirty.blz");
+    fs::write(&arch, vec![0xAAu8; 50_000]).unwrap();
+    create_stream(src.path(), &arch, None);
+    let size = fs::metadata(&arch).unwrap().len();
+
+    assert!(katana::compact_if_wasteful(&arch, None, 60.0).unwrap().is_none());
+    assert_eq!(fs::metadata(&arch).unwrap().len(), size);
+    let report = katana::compact_if_wasteful(&arch, None, 30.0).unwrap().expect("compacted");
+    assert!(report.reclaimed_bytes() >= 49_000);
+    assert!(katana::compact_if_wasteful(&arch, None, 0.0).unwrap().is_none());
+    perform_paranoid_check(&arch).unwrap();
+}
@@ -0,0 +1,225 @@
+use blitzarch::extract::ExtractOptions;
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, perform_paranoid_check, KatanaCreateOptions};
+use blitzarch::progress::ProgressState;
+use blitzarch::vfs::{Vfs, VfsMetadata};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn write_file(p: &Path, data: &[u8]) {
+    fs::create_dir_all(p.parent().unwrap()).unwrap();
+    fs::write(p, data).unwrap();
+}
+
+fn create(src: &Path, arch: &Path, password: Option<&str>, inline: bool) {
+    let options = KatanaCreateOptions { dedup: true, inline_small_files: inline, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src.to_path_buf()], arch, 2, 0, None, password.map(String::from), None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+}
+
+fn fixture() -> tempfile::TempDir {
+    let src = tempdir().unwrap();
+    let photo: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    write_file(&src.path().join("a/photo.jpg"), &photo);
+    write_file(&src.path().join("b/photo copy.jpg"), &photo);
+    write_file(&src.path().join("c/photo (2).jpg"), &photo);
+    let mut other = photo.clone();
+    other[100] ^= 1;
+    write_file(&src.path().join("other.jpg"), &other);
+    write_file(&src.path().join("node_modules/x/LICENSE"), b"MIT License");
+    write_file(&src.path().join("node_modules/y/LICENSE"), b"MIT License");
+    write_file(&src.path().join("empty1"), b"");
+    write_file(&src.path().join("empty2"), b"");
+    src
+}
+
+#[test]
+fn dedup_stores_one_copy_and_restores_all() {
+    let src = fixture();
+    for (password, inline) in [(None, false), (None, true), (Some("dd-pw"), false)] {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("dedup.blz");
+        create(src.path(), &arch, password, inline);
+        perform_paranoid_check(&arch).unwrap();
+
+        let entries = katana::list_entries(&arch, password).unwrap();
+        assert_eq!(entries.len(), 8);
+        let dups: Vec<_> = entries.iter().filter(|e| e.duplicate_of.is_some()).collect();
+        assert_eq!(dups.len(), 3, "{:?}", dups);
+        assert!(entries.iter().all(|e| e.path != "other.jpg" || e.duplicate_of.is_none()));
+        let stored: u64 = katana::index_stats(&arch).unwrap().compressed_size;
+        assert!(stored < 200_000, "{}", stored);
+
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], password.map(String::from), None).unwrap();
+        for p in ["a/photo.jpg", "b/photo copy.jpg", "c/photo (2).jpg", "other.jpg", "node_modules/y/LICENSE", "empty2"] {
+            assert_eq!(fs::read(out.path().join(p)).unwrap(), fs::read(src.path().join(p)).unwrap(), "{}", p);
+        }
+
+        // A duplicate selected without its original is read from the archive
+        let dup = dups.iter().find(|e| e.path.ends_with(".jpg")).unwrap();
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[PathBuf::from(&dup.path)], password.map(String::from), None)
+            .unwrap();
+        assert_eq!(fs::read(out.path().join(&dup.path)).unwrap(), fs::read(src.path().join(&dup.path)).unwrap());
+        assert!(!out.path().join(dup.duplicate_of.as_ref().unwrap()).exists());
+
+        let mut data = Vec::new();
+        katana::open_entry(&arch, &dup.path, password).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, fs::read(src.path().join(&dup.path)).unwrap());
+        let tree = katana::extract_katana_to_memory(&arch, &[PathBuf::from(&dup.path)], password.map(String::from), 1 << 30).unwrap();
+        assert_eq!(tree[&dup.path], data);
+
+        // Converting keeps the references (inlined originals move into a shard)
+        if password.is_none() && inline {
+            katana::encrypt_katana_archive(&arch, "later").unwrap();
+            let out = tempdir().unwrap();
+            katana::extract_katana_archive_internal(&arch, out.path(), &[], Some("later".into()), None).unwrap();
+            assert_eq!(fs::read(out.path().join("node_modules/y/LICENSE")).unwrap(), b"MIT License");
+            assert_eq!(fs::read(out.path().join("c/photo (2).jpg")).unwrap(), fs::read(src.path().join("a/photo.jpg")).unwrap());
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn dedup_hard_links_on_request() {
+    use std::os::unix::fs::MetadataExt;
+    let src = fixture();
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("links.blz");
+    create(src.path(), &arch, None, false);
+
+    let out = tempdir().unwrap();
+    let options = ExtractOptions { hard_links: true, ..Default::default() };
+    katana::extract_katana_archive_with_options(&arch, out.path(), &[], None, None, None::<fn(ProgressState)>, None, &options)
+        .unwrap();
+    let inode = |p: &str| fs::metadata(out.path().join(p)).unwrap().ino();
+    assert_eq!(inode("a/photo.jpg"), inode("b/photo copy.jpg"));
+    assert_eq!(inode("a/photo.jpg"), inode("c/photo (2).jpg"));
+    assert_ne!(inode("a/photo.jpg"), inode("other.jpg"));
+}
+
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut x = seed;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+}
+
+#[test]
+fn chunk_dedup_shares_partial_content() {
+    let src = tempdir().unwrap();
+    let image = noise(2 * 1024 * 1024, 0x5EED);
+    let mut snapshot = image.clone();
+    snapshot.splice(1_000_000..1_000_000, *b"a few new bytes");
+    write_file(&src.path().join("vm/base.img"), &image);
+    write_file(&src.path().join("vm/snapshot.img"), &snapshot);
+    write_file(&src.path().join("build/lib.o"), &noise(40_000, 7));
+    write_file(&src.path().join("notes.txt"), b"small files stay in shards");
+    let names = ["vm/base.img", "vm/snapshot.img", "build/lib.o", "notes.txt"];
+
+    for password in [None, Some("cdc-pw")] {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("chunks.blz");
+        let options = KatanaCreateOptions { chunk_dedup: true, ..Default::default() };
+        katana_stream::create_katana_archive_with_options(
+            &[src.path().to_path_buf()], &arch, 2, 0, None, password.map(String::from), None, &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+        perform_paranoid_check(&arch).unwrap();
+        // Random data does not compress: only chunks shared by the two images save space
+        let stored = fs::metadata(&arch).unwrap().len();
+        assert!(stored < image.len() as u64 + 600_000, "{}", stored);
+        assert!(katana::verify_archive(&arch, password, true).unwrap().deep);
+
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], password.map(String::from), None).unwrap();
+        for p in names {
+            assert_eq!(fs::read(out.path().join(p)).unwrap(), fs::read(src.path().join(p)).unwrap(), "{}", p);
+        }
+
+        let mut data = Vec::new();
+        katana::open_entry(&arch, "vm/snapshot.img", password).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, snapshot);
+        let wanted = [PathBuf::from("build/lib.o")];
+        let tree = katana::extract_katana_to_memory(&arch, &wanted, password.map(String::from), 1 << 30).unwrap();
+        assert_eq!(tree["build/lib.o"], fs::read(src.path().join("build/lib.o")).unwrap());
+
+        // Rewrites carry the chunk store along
+        let extra = tempdir().unwrap();
+        write_file(&extra.path().join("later.txt"), b"appended");
+        katana::append_to_archive(&arch, &[extra.path().join("later.txt")], 2, password.map(String::from), None).unwrap();
+        katana::compact_katana_archive(&arch, password.map(String::from)).unwrap();
+        let password = match password {
+            Some(pw) => {
+                katana::decrypt_katana_archive(&arch, pw).unwrap();
+                None
+            }
+            None => {
+                katana::encrypt_katana_archive(&arch, "later").unwrap();
+                Some("later")
+            }
+        };
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], password.map(String::from), None).unwrap();
+        for p in names {
+            assert_eq!(fs::read(out.path().join(p)).unwrap(), fs::read(src.path().join(p)).unwrap(), "{}", p);
+        }
+        assert_eq!(fs::read(out.path().join("later.txt")).unwrap(), b"appended");
+    }
+}
+
+/// Serves `a.bin` with its original bytes on the first read only, as if it were
+/// rewritten between the duplicate scan and compression.
+struct RewrittenOriginal {
+    reads: std::sync::atomic::AtomicUsize,
+}
+
+impl Vfs for RewrittenOriginal {
+    fn list_files(&self, _root: &Path) -> std::io::Result<Vec<PathBuf>> {
+        Ok(vec![PathBuf::from("/src/a.bin"), PathBuf::from("/src/b.bin")])
+    }
+
+    fn metadata(&self, _path: &Path) -> std::io::Result<VfsMetadata> {
+        Ok(VfsMetadata::new(4096).with_permissions(0o644).with_mtime(1_700_000_000))
+    }
+
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+        let first = path.ends_with("b.bin") || self.reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0;
+        Ok(Box::new(std::io::Cursor::new(vec![if first { b'x' } else { b'y' }; 4096])))
+    }
+}
+
+#[test]
+fn dedup_drops_references_to_an_original_that_changed() {
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("changed.blz");
+    let vfs = Arc::new(RewrittenOriginal { reads: Default::default() });
+    let options = KatanaCreateOptions { dedup: true, vfs: Some(vfs), ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[PathBuf::from("/src")], &arch, 2, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+
+    // b.bin matched the bytes a.bin had during the scan, not the ones archived
+    let entries = katana::list_entries(&arch, None).unwrap();
+    assert!(entries.iter().all(|e| e.duplicate_of.is_none()), "{:?}", entries);
+    assert!(entries.iter().all(|e| e.path != "b.bin"));
+    let mut data = Vec::new();
+    katana::open_entry(&arch, "a.bin", None).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, vec![b'y'; 4096]);
+}
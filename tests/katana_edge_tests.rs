@@ -1,7 +1,7 @@
 //! Extensive edge-case tests for Katana archives.
 //! Heavy tests are marked with `#[ignore]` so CI can skip them by default.
 
-use blitzarch::katana;
+use blitzarch::{katana, katana_stream};
 use rand::{distributions::{Alphanumeric, Distribution}, rngs::ThreadRng, thread_rng, Rng, RngCore};
 use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
@@ -190,3 +190,79 @@ fn katana_million_files_random_access() {
         assert_eq!(fs::read(original).unwrap(), fs::read(extracted).unwrap());
     }
 }
+
+#[test]
+fn katana_output_inside_input_dir_is_excluded() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("data.txt"), b"payload").unwrap();
+    // Stale output from an earlier run sits inside the input directory
+    let arch_path = src.path().join("out.blz");
+    fs::write(&arch_path, b"partial output of a crashed run").unwrap();
+
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 1, None).unwrap();
+
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch_path, out.path(), &[], None, None).unwrap();
+    assert_eq!(fs::read(out.path().join("data.txt")).unwrap(), b"payload");
+    assert!(!out.path().join("out.blz").exists());
+
+    // Passing the output itself as an input file is rejected
+    assert!(katana::create_katana_archive(&[arch_path.clone()], &arch_path, 1, None).is_err());
+}
+
+#[test]
+fn retrying_appender_classifies_and_appends() {
+    use blitzarch::fsx::{is_transient_io_error, RetryingAppender};
+    use std::io::{Error, ErrorKind};
+
+    assert!(is_transient_io_error(&Error::from(ErrorKind::TimedOut)));
+    assert!(!is_transient_io_error(&Error::from(ErrorKind::PermissionDenied)));
+    #[cfg(unix)]
+    {
+        assert!(is_transient_io_error(&Error::from_raw_os_error(libc::ESTALE)));
+        assert!(!is_transient_io_error(&Error::from_raw_os_error(libc::ENOSPC)));
+    }
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("out.bin");
+    fs::write(&path, b"head").unwrap();
+    let mut out = RetryingAppender::open(&path).unwrap();
+    assert_eq!(out.committed_len(), 4);
+    out.append(b"-tail").unwrap();
+    assert_eq!(out.committed_len(), 9);
+    assert_eq!(out.retries(), 0);
+    assert_eq!(fs::read(&path).unwrap(), b"head-tail");
+}
+
+#[test]
+fn katana_verify_detects_corruption() {
+    let mut rng = thread_rng();
+    let src = tempdir().unwrap();
+    create_files(src.path(), 6, 40_000, &mut rng);
+    let arch_dir = tempdir().unwrap();
+
+    for password in [None, Some("verify-pw")] {
+        let arch = arch_dir.path().join("verify.blz");
+        katana_stream::create_katana_archive(
+            &[src.path().to_path_buf()], &arch, 2, 0, None, password.map(String::from), None,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+
+        let report = katana::verify_archive(&arch, password, true).unwrap();
+        assert_eq!((report.files, report.blake3, report.deep), (6, true, true));
+        assert_eq!(report.index_hmac, password.is_some());
+        if password.is_some() {
+            assert!(katana::verify_archive(&arch, None, true).is_err());
+            assert!(!katana::verify_archive(&arch, None, false).unwrap().index_hmac);
+        }
+
+        // Flip one byte inside the first shard
+        let mut bytes = fs::read(&arch).unwrap();
+        bytes[10] ^= 0x55;
+        fs::write(&arch, &bytes).unwrap();
+        assert!(katana::verify_archive(&arch, password, false).is_err());
+        assert!(arch.exists(), "verify must never delete the archive");
+        fs::remove_file(&arch).unwrap();
+    }
+}
@@ -97,3 +97,40 @@ fn katana_wrong_password() {
 
     assert!(result.is_err(), "Extraction should fail with wrong password");
 }
+
+#[test]
+fn katana_encrypt_decrypt_in_place() {
+    let src_dir = tempdir().unwrap();
+    create_random_files(src_dir.path(), 4, 20_000);
+    // Small files are inlined into the index of the plain archive
+    fs::write(src_dir.path().join("tiny.txt"), b"tiny").unwrap();
+    fs::write(src_dir.path().join("small.txt"), b"small file").unwrap();
+
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("convert.blz");
+    let options = blitzarch::katana_stream::KatanaCreateOptions { inline_small_files: true, ..Default::default() };
+    blitzarch::katana_stream::create_katana_archive_with_options(
+        &[src_dir.path().to_path_buf()], &arch_path, 2, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    let password = "convert-pw";
+
+    let report = katana::encrypt_katana_archive(&arch_path, password).unwrap();
+    assert_eq!(report.inlined_packed, 2);
+    assert!(katana::encrypt_katana_archive(&arch_path, password).is_err());
+    blitzarch::katana_stream::perform_paranoid_check(&arch_path).unwrap();
+    katana::verify_archive(&arch_path, Some(password), true).unwrap();
+    assert!(katana::extract_katana_archive_internal(&arch_path, tempdir().unwrap().path(), &[], None, None).is_err());
+    let out_dir = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch_path, out_dir.path(), &[], Some(password.to_string()), None).unwrap();
+    compare_dirs(src_dir.path(), out_dir.path());
+
+    assert!(katana::decrypt_katana_archive(&arch_path, "wrong").is_err());
+    katana::decrypt_katana_archive(&arch_path, password).unwrap();
+    assert!(katana::decrypt_katana_archive(&arch_path, password).is_err());
+    katana::verify_archive(&arch_path, None, true).unwrap();
+    let out_dir = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch_path, out_dir.path(), &[], None, None).unwrap();
+    compare_dirs(src_dir.path(), out_dir.path());
+}
@@ -0,0 +1,57 @@
+#![cfg(unix)]
+
+use blitzarch::extract::ExtractOptions;
+use blitzarch::katana::{self, ExtractFilter};
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use blitzarch::progress::ProgressState;
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn extracted(arch: &Path, selected: &[&str], filter: ExtractFilter) -> Vec<String> {
+    let out = tempdir().unwrap();
+    let selected: Vec<_> = selected.iter().map(Into::into).collect();
+    let options = ExtractOptions { filter, ..Default::default() };
+    katana::extract_katana_archive_with_options(arch, out.path(), &selected, None, None, None::<fn(ProgressState)>, None, &options)
+        .unwrap();
+    let mut names: Vec<String> = walkdir::WalkDir::new(out.path())
+        .into_iter()
+        .map(|e| e.unwrap())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().strip_prefix(out.path()).unwrap().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn extract_filters_by_mode_and_owner() {
+    let src = tempdir().unwrap();
+    fs::create_dir_all(src.path().join("bin")).unwrap();
+    for (name, mode, len) in [("run.sh", 0o755, 50), ("notes.txt", 0o644, 20_000), ("bin/tool", 0o700, 40_000)] {
+        let p = src.path().join(name);
+        fs::write(&p, vec![b'x'; len]).unwrap();
+        fs::set_permissions(&p, fs::Permissions::from_mode(mode)).unwrap();
+    }
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("filter.blz");
+    let options = KatanaCreateOptions { inline_small_files: true, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 2, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+
+    let all = ["bin/tool", "notes.txt", "run.sh"];
+    assert_eq!(extracted(&arch, &[], ExtractFilter::default()), all);
+    let exec = ExtractFilter { only_executable: true, ..Default::default() };
+    assert_eq!(extracted(&arch, &[], exec), ["bin/tool", "run.sh"]);
+    // The filter narrows an explicit selection
+    assert_eq!(extracted(&arch, &["run.sh", "notes.txt"], exec), ["run.sh"]);
+
+    let uid = fs::metadata(src.path().join("run.sh")).unwrap().uid();
+    assert_eq!(extracted(&arch, &[], ExtractFilter { uid: Some(uid), ..Default::default() }), all);
+    let other = ExtractFilter { uid: Some(uid.wrapping_add(1)), ..Default::default() };
+    assert!(extracted(&arch, &[], other).is_empty());
+}
@@ -0,0 +1,59 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+
+fn create(inputs: &[PathBuf], arch: &Path) {
+    katana_stream::create_katana_archive_with_options(
+        inputs, arch, 2, 0, None, None, None, &KatanaCreateOptions::default(),
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+}
+
+#[test]
+fn source_fingerprint_detects_changes() {
+    let src = tempdir().unwrap();
+    fs::create_dir_all(src.path().join("dir")).unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    fs::write(src.path().join("dir/b.txt"), b"beta").unwrap();
+    let inputs = vec![src.path().to_path_buf()];
+    let options = KatanaCreateOptions::default();
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("prev.blz");
+    create(&inputs, &arch);
+    let recorded = katana::archive_source_fingerprint(&arch, None).unwrap().expect("fingerprint recorded");
+    assert_eq!(recorded, katana_stream::source_fingerprint(&inputs, &arch, &options).unwrap());
+    assert!(katana_stream::source_unchanged(&inputs, &arch, &options, None).unwrap());
+
+    // Touching a file changes its mtime, and with it the fingerprint
+    let f = fs::File::options().write(true).open(src.path().join("a.txt")).unwrap();
+    f.set_modified(SystemTime::now() + Duration::from_secs(120)).unwrap();
+    drop(f);
+    assert!(!katana_stream::source_unchanged(&inputs, &arch, &options, None).unwrap());
+    create(&inputs, &arch);
+    assert!(katana_stream::source_unchanged(&inputs, &arch, &options, None).unwrap());
+
+    // New files and size changes count as well
+    fs::write(src.path().join("dir/c.txt"), b"gamma").unwrap();
+    assert!(!katana_stream::source_unchanged(&inputs, &arch, &options, None).unwrap());
+    create(&inputs, &arch);
+    fs::write(src.path().join("dir/b.txt"), b"beta, longer").unwrap();
+    assert!(!katana_stream::source_unchanged(&inputs, &arch, &options, None).unwrap());
+
+    // An archive placed inside the source tree does not fingerprint itself
+    let inner = src.path().join("self.blz");
+    create(&inputs, &inner);
+    assert!(katana_stream::source_unchanged(&inputs, &inner, &options, None).unwrap());
+
+    // Appending drops the fingerprint; a missing archive is always "changed"
+    let more = tempdir().unwrap();
+    fs::write(more.path().join("d.txt"), b"delta").unwrap();
+    katana::append_to_archive(&arch, &[more.path().join("d.txt")], 1, None, None).unwrap();
+    assert_eq!(katana::archive_source_fingerprint(&arch, None).unwrap(), None);
+    assert!(!katana_stream::source_unchanged(&inputs, &arch, &options, None).unwrap());
+    assert!(!katana_stream::source_unchanged(&inputs, &arch_dir.path().join("missing.blz"), &options, None).unwrap());
+}
@@ -0,0 +1,92 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, perform_paranoid_check, KatanaCreateOptions};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::tempdir;
+
+fn write_at(p: &Path, data: &[u8], secs: u64) {
+    fs::create_dir_all(p.parent().unwrap()).unwrap();
+    fs::write(p, data).unwrap();
+    File::options().write(true).open(p).unwrap().set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+}
+
+fn create(src: &Path, arch: &Path, base: Option<PathBuf>, password: Option<&str>) {
+    let options = KatanaCreateOptions { base, inline_small_files: true, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src.to_path_buf()], arch, 2, 0, None, password.map(String::from), None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    perform_paranoid_check(arch).unwrap();
+}
+
+#[test]
+fn incremental_chain_restores_all_files() {
+    for password in [None, Some("inc-pw")] {
+        let src = tempdir().unwrap();
+        let t0 = 1_600_000_000;
+        write_at(&src.path().join("big.bin"), &[1u8; 200_000], t0);
+        write_at(&src.path().join("docs/readme.txt"), &b"v1 ".repeat(3000), t0);
+        write_at(&src.path().join("tiny.txt"), b"tiny", t0);
+
+        let arch_dir = tempdir().unwrap();
+        let full = arch_dir.path().join("full.blz");
+        create(src.path(), &full, None, password);
+
+        // Second run: one file changed, one added
+        write_at(&src.path().join("docs/readme.txt"), &b"v2 ".repeat(3000), t0 + 10);
+        write_at(&src.path().join("new.log"), &b"log ".repeat(500), t0 + 10);
+        let inc1 = arch_dir.path().join("inc1.blz");
+        create(src.path(), &inc1, Some(full.clone()), password);
+
+        let entries = katana::list_entries(&inc1, password).unwrap();
+        assert_eq!(entries.len(), 4);
+        let big = entries.iter().find(|e| e.path == "big.bin").unwrap();
+        assert_eq!((big.size, big.shard_id), (200_000, None));
+
+        // Third run on top of the increment: references resolve through the chain
+        write_at(&src.path().join("new.log"), &b"log2 ".repeat(500), t0 + 20);
+        let inc2 = arch_dir.path().join("inc2.blz");
+        create(src.path(), &inc2, Some(inc1.clone()), password);
+
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&inc2, out.path(), &[], password.map(String::from), None).unwrap();
+        for name in ["big.bin", "docs/readme.txt", "tiny.txt", "new.log"] {
+            assert_eq!(fs::read(out.path().join(name)).unwrap(), fs::read(src.path().join(name)).unwrap(), "{}", name);
+        }
+        let mut reader = katana::open_entry(&inc2, "big.bin", password).unwrap();
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut data).unwrap();
+        assert_eq!(data, vec![1u8; 200_000]);
+        let tree = katana::extract_katana_to_memory(&inc2, &[], password.map(String::from), 1 << 30).unwrap();
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree["docs/readme.txt"], b"v2 ".repeat(3000));
+    }
+}
+
+#[test]
+fn incremental_detects_replaced_base() {
+    let src = tempdir().unwrap();
+    let past = SystemTime::now() - Duration::from_secs(3600);
+    let secs = past.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    write_at(&src.path().join("a.txt"), &b"aaaa".repeat(1000), secs);
+    write_at(&src.path().join("b.txt"), &b"bbbb".repeat(1000), secs);
+
+    let arch_dir = tempdir().unwrap();
+    let full = arch_dir.path().join("full.blz");
+    create(src.path(), &full, None, None);
+    write_at(&src.path().join("b.txt"), &b"BBBB".repeat(1000), secs + 5);
+    let inc = arch_dir.path().join("inc.blz");
+    create(src.path(), &inc, Some(full.clone()), None);
+
+    // Rewriting the base changes its BLAKE3 footer
+    katana::touch_katana_archive(&full, &[], 0o600, None).unwrap();
+    let out = tempdir().unwrap();
+    let err = katana::extract_katana_archive_internal(&inc, out.path(), &[], None, None).unwrap_err();
+    assert!(err.to_string().contains("changed since"), "{}", err);
+
+    fs::remove_file(&full).unwrap();
+    let err = katana::extract_katana_archive_internal(&inc, out.path(), &[], None, None).unwrap_err();
+    assert!(err.to_string().contains("not found"), "{}", err);
+}
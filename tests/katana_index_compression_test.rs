@@ -0,0 +1,58 @@
+use blitzarch::katana::{self, IndexCompression, IndexFormat};
+use blitzarch::katana_stream::{self, perform_paranoid_check, KatanaCreateOptions};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// Compressed index bytes as stored in front of the 24-byte Katana footer.
+fn stored_index(arch: &Path) -> Vec<u8> {
+    let data = fs::read(arch).unwrap();
+    let data_len = u64::from_le_bytes(data[data.len() - 40..data.len() - 32].try_into().unwrap()) as usize;
+    let footer = &data[data_len - 24..data_len];
+    let comp_size = u64::from_le_bytes(footer[..8].try_into().unwrap()) as usize;
+    data[data_len - 24 - comp_size..data_len - 24].to_vec()
+}
+
+#[test]
+fn index_codecs_roundtrip() {
+    let src = tempdir().unwrap();
+    for i in 0..50 {
+        fs::write(src.path().join(format!("file_{i:03}.txt")), format!("payload {i} ").repeat(100)).unwrap();
+    }
+
+    let cases = [
+        (IndexCompression::Store, IndexFormat::V1, b'{'),
+        (IndexCompression::Store, IndexFormat::V2, b'K'),
+        (IndexCompression::Zstd(19), IndexFormat::V2, 0x28),
+    ];
+    for (codec, format, first_byte) in cases {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("idx.blz");
+        let options = KatanaCreateOptions { index_compression: codec, index_format: format, ..Default::default() };
+        katana_stream::create_katana_archive_with_options(
+            &[src.path().to_path_buf()], &arch, 2, 0, None, None, None, &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+        perform_paranoid_check(&arch).unwrap();
+        assert_eq!(stored_index(&arch)[0], first_byte);
+        assert_eq!(katana::open_lazy(&arch).unwrap().len(), 50);
+
+        // In-place index rewrites keep the codec
+        katana::touch_katana_archive(&arch, &["file_00*".to_string()], 0o600, None).unwrap();
+        assert_eq!(stored_index(&arch)[0], first_byte);
+
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+        assert_eq!(fs::read_to_string(out.path().join("file_049.txt")).unwrap(), "payload 49 ".repeat(100));
+    }
+}
+
+#[test]
+fn index_compression_parse() {
+    assert_eq!("store".parse::<IndexCompression>().unwrap(), IndexCompression::Store);
+    assert_eq!("zstd".parse::<IndexCompression>().unwrap(), IndexCompression::Zstd(3));
+    assert_eq!("zstd:19".parse::<IndexCompression>().unwrap(), IndexCompression::Zstd(19));
+    assert!("zstd:40".parse::<IndexCompression>().is_err());
+    assert!("brotli".parse::<IndexCompression>().is_err());
+}
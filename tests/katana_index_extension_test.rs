@@ -0,0 +1,37 @@
+use blitzarch::katana::{self, IndexExtension};
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn index_extensions_survive_index_rewrites() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    fs::write(src.path().join("b.bin"), vec![3u8; 50_000]).unwrap();
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("ext.blz");
+    katana_stream::create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 2, 0, None, None, None, &KatanaCreateOptions::default(),
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    assert!(katana::index_extensions(&arch).unwrap().is_empty());
+
+    let ext = IndexExtension { version: 3, data: serde_json::json!({ "owner": "backup-job", "runs": [1, 2] }) };
+    katana::set_index_extension(&arch, "example.job", Some(ext.clone()), None).unwrap();
+
+    let more = tempdir().unwrap();
+    fs::write(more.path().join("c.txt"), b"gamma").unwrap();
+    katana::append_to_archive(&arch, &[more.path().join("c.txt")], 1, None, None).unwrap();
+    katana::touch_katana_archive(&arch, &[], 0o600, None).unwrap();
+    katana::compact_katana_archive(&arch, None).unwrap();
+    katana::encrypt_katana_archive(&arch, "ext-pw").unwrap();
+    assert_eq!(katana::index_extensions(&arch).unwrap()["example.job"], ext);
+    assert!(katana::verify_archive(&arch, Some("ext-pw"), true).unwrap().index_hmac);
+
+    katana::set_index_extension(&arch, "example.job", None, Some("ext-pw".into())).unwrap();
+    assert!(katana::index_extensions(&arch).unwrap().is_empty());
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &[], Some("ext-pw".into()), None).unwrap();
+    assert_eq!(fs::read(out.path().join("c.txt")).unwrap(), b"gamma");
+}
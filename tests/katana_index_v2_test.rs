@@ -0,0 +1,96 @@
+use blitzarch::katana::{self, IndexCompression, IndexFormat, INDEX_FORMAT_VERSION};
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// Data length (without the BLAKE3 footer) and the range of the stored index.
+fn index_range(data: &[u8]) -> (usize, Range<usize>) {
+    let data_len = u64::from_le_bytes(data[data.len() - 40..data.len() - 32].try_into().unwrap()) as usize;
+    let comp_size = u64::from_le_bytes(data[data_len - 24..data_len - 16].try_into().unwrap()) as usize;
+    (data_len, data_len - 24 - comp_size..data_len - 24)
+}
+
+fn footer_magic(arch: &Path) -> Vec<u8> {
+    let data = fs::read(arch).unwrap();
+    let (data_len, _) = index_range(&data);
+    data[data_len - 8..data_len].to_vec()
+}
+
+fn create(src: &Path, arch: &Path, format: IndexFormat, compression: IndexCompression, password: Option<&str>) {
+    let options = KatanaCreateOptions { index_format: format, index_compression: compression, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src.to_path_buf()], arch, 2, 0, None, password.map(str::to_string), None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+}
+
+fn source() -> tempfile::TempDir {
+    let src = tempdir().unwrap();
+    for i in 0..20 {
+        fs::write(src.path().join(format!("doc_{i:02}.txt")), format!("line {i}\n").repeat(300)).unwrap();
+    }
+    src
+}
+
+#[test]
+fn both_index_versions_roundtrip_and_keep_their_version() {
+    let src = source();
+    for (format, magic) in [(IndexFormat::V1, b"KATIDX01"), (IndexFormat::V2, b"KATIDX02")] {
+        for password in [None, Some("v2-secret")] {
+            let dir = tempdir().unwrap();
+            let arch = dir.path().join("idx.blz");
+            create(src.path(), &arch, format, IndexCompression::default(), password);
+            assert_eq!(footer_magic(&arch), magic);
+            assert!(katana::is_katana_archive(&arch).unwrap());
+            let report = katana::verify_archive(&arch, password, true).unwrap();
+            assert_eq!(report.index_hmac, password.is_some());
+
+            // Rewrites keep the version the archive was written with
+            katana::touch_katana_archive(&arch, &["doc_0*".to_string()], 0o600, password.map(str::to_string)).unwrap();
+            assert_eq!(footer_magic(&arch), magic);
+
+            let out = dir.path().join("out");
+            fs::create_dir_all(&out).unwrap();
+            katana::extract_katana_archive_internal(&arch, &out, &[], password.map(str::to_string), None).unwrap();
+            assert_eq!(fs::read_to_string(out.join("doc_19.txt")).unwrap(), "line 19\n".repeat(300));
+        }
+    }
+}
+
+#[test]
+fn newer_versions_and_unknown_features_ask_for_an_upgrade() {
+    let src = source();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("idx.blz");
+    create(src.path(), &arch, IndexFormat::V2, IndexCompression::Store, None);
+    let original = fs::read(&arch).unwrap();
+    let (_, index) = index_range(&original);
+    assert!(original[index.clone()].starts_with(b"KIX2"));
+    assert_eq!(u16::from_le_bytes(original[index.start + 4..index.start + 6].try_into().unwrap()), INDEX_FORMAT_VERSION);
+
+    let mut newer = original.clone();
+    newer[index.start + 4..index.start + 6].copy_from_slice(&(INDEX_FORMAT_VERSION + 1).to_le_bytes());
+    fs::write(&arch, &newer).unwrap();
+    let err = katana::open_lazy(&arch).unwrap_err().to_string();
+    assert!(err.contains("upgrade"), "{err}");
+
+    // An unknown feature bit, correctly signed, is refused before the payload is read
+    let mut future = original.clone();
+    let header = index.start;
+    future[header + 6..header + 10].copy_from_slice(&(1u32 << 30).to_le_bytes());
+    future[header + 10..header + 14].fill(0);
+    let crc = crc32fast::hash(&future[index.clone()]);
+    future[header + 10..header + 14].copy_from_slice(&crc.to_le_bytes());
+    fs::write(&arch, &future).unwrap();
+    let err = katana::open_lazy(&arch).unwrap_err().to_string();
+    assert!(err.contains("upgrade"), "{err}");
+
+    // A flipped payload byte fails the CRC
+    let mut damaged = original;
+    damaged[index.end - 1] ^= 0x55;
+    fs::write(&arch, &damaged).unwrap();
+    assert!(katana::open_lazy(&arch).is_err());
+}
@@ -70,3 +70,79 @@ fn katana_detection_false_for_regular_archive() {
     blitzarch::compress::run(&[src.path().to_path_buf()], &arch_path, opts, None).unwrap();
     assert_eq!(katana::is_katana_archive(&arch_path).unwrap(), false);
 }
+
+#[test]
+fn katana_index_stats_summary() {
+    let src = tempfile::tempdir().unwrap();
+    let root = src.path();
+    std::fs::create_dir_all(root.join("photos/2023")).unwrap();
+    std::fs::write(root.join("photos/2023/a.jpg"), vec![1u8; 100]).unwrap();
+    std::fs::write(root.join("photos/b.jpg"), vec![2u8; 50]).unwrap();
+    std::fs::write(root.join("readme.txt"), vec![3u8; 10]).unwrap();
+
+    let arch_dir = tempfile::tempdir().unwrap();
+    let arch = arch_dir.path().join("stats.blz");
+    blitzarch::katana::create_katana_archive(&[root.to_path_buf()], &arch, 2, Some("pw".into())).unwrap();
+
+    // No password needed for the summary
+    let stats = blitzarch::katana::index_stats(&arch).unwrap();
+    assert_eq!(stats.total_entries, 3);
+    assert_eq!(stats.total_size, 160);
+    assert!(stats.encrypted);
+    assert_eq!(stats.top_level.len(), 2);
+    let photos = &stats.top_level[0];
+    assert_eq!(photos.name, "photos");
+    assert!(photos.is_dir);
+    assert_eq!(photos.children, 2);
+    assert_eq!(photos.files, 2);
+    assert_eq!(photos.size, 150);
+    assert!(!stats.top_level[1].is_dir);
+}
+
+#[test]
+fn katana_inline_small_files_roundtrip() {
+    use blitzarch::katana_stream::{create_katana_archive_with_options, KatanaCreateOptions};
+    use std::path::PathBuf;
+
+    let src = tempdir().unwrap();
+    create_test_files(&src.path().join("big"), 3, 64 * 1024);
+    create_test_files(&src.path().join("node_modules/pkg"), 20, 300);
+    fs::write(src.path().join("empty.txt"), b"").unwrap();
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("inline.blz");
+    let options = KatanaCreateOptions { inline_small_files: true, ..Default::default() };
+    create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 2, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    ).unwrap();
+    blitzarch::katana_stream::perform_paranoid_check(&arch).unwrap();
+
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+    dirs_equal(&src.path().join("big"), &out.path().join("big"));
+    dirs_equal(&src.path().join("node_modules/pkg"), &out.path().join("node_modules/pkg"));
+    assert!(out.path().join("empty.txt").is_file());
+
+    // Selective extraction of an inlined entry touches no shard
+    let one = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, one.path(), &[PathBuf::from("node_modules/pkg/f3.dat")], None, None).unwrap();
+    assert_eq!(
+        fs::read(one.path().join("node_modules/pkg/f3.dat")).unwrap(),
+        fs::read(src.path().join("node_modules/pkg/f3.dat")).unwrap()
+    );
+    assert!(!one.path().join("big").exists());
+    let mem = katana::extract_katana_to_memory(&arch, &[PathBuf::from("node_modules/pkg/f7.dat")], None, 1 << 20).unwrap();
+    assert_eq!(mem["node_modules/pkg/f7.dat"], fs::read(src.path().join("node_modules/pkg/f7.dat")).unwrap());
+
+    // A tree of only tiny files produces an archive without shards
+    let arch_small = arch_dir.path().join("only_small.blz");
+    create_katana_archive_with_options(
+        &[src.path().join("node_modules")], &arch_small, 2, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    ).unwrap();
+    assert_eq!(katana::index_stats(&arch_small).unwrap().shard_count, 0);
+    let out_small = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch_small, out_small.path(), &[], None, None).unwrap();
+    dirs_equal(&src.path().join("node_modules/pkg"), &out_small.path().join("pkg"));
+}
@@ -0,0 +1,122 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::tempdir;
+
+fn write_with_mtime(p: &Path, data: &[u8], mtime: SystemTime) {
+    fs::create_dir_all(p.parent().unwrap()).unwrap();
+    fs::write(p, data).unwrap();
+    File::options().write(true).open(p).unwrap().set_modified(mtime).unwrap();
+}
+
+fn mtime_secs(p: &Path) -> u64 {
+    fs::metadata(p).unwrap().modified().unwrap().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[test]
+fn mtimes_restored_and_future_clamped() {
+    let past = UNIX_EPOCH + Duration::from_secs(1_000_000_000); // 2001-09-09
+    let future = SystemTime::now() + Duration::from_secs(10 * 365 * 24 * 3600);
+
+    for inline in [false, true] {
+        let src = tempdir().unwrap();
+        write_with_mtime(&src.path().join("old.txt"), &b"old ".repeat(2000), past);
+        write_with_mtime(&src.path().join("small.txt"), b"tiny", past);
+        write_with_mtime(&src.path().join("skewed/future.txt"), &b"future ".repeat(2000), future);
+
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("mtime.blz");
+        let options = KatanaCreateOptions { inline_small_files: inline, ..Default::default() };
+        katana_stream::create_katana_archive_with_options(
+            &[src.path().to_path_buf()], &arch, 2, 0, None, None, None, &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+
+        let findings = blitzarch::audit::audit_archive(&arch, None).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].rule, "timestamp-skew");
+        assert_eq!(findings[0].path, "skewed/future.txt");
+
+        let out = tempdir().unwrap();
+        let before_extract = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+        assert_eq!(mtime_secs(&out.path().join("old.txt")), 1_000_000_000);
+        assert_eq!(mtime_secs(&out.path().join("small.txt")), 1_000_000_000);
+        let clamped = mtime_secs(&out.path().join("skewed/future.txt"));
+        assert!(clamped >= before_extract && clamped <= before_extract + 60, "{}", clamped);
+    }
+}
+
+#[test]
+fn birth_times_restored_where_supported() {
+    use blitzarch::fsx;
+
+    let src = tempdir().unwrap();
+    let file = src.path().join("created.txt");
+    fs::write(&file, b"born long ago").unwrap();
+    if fsx::CAN_SET_BTIME {
+        fsx::set_btime(&File::options().write(true).open(&file).unwrap(), 1_000_000_000).unwrap();
+    } else {
+        let err = fsx::set_btime(&File::open(&file).unwrap(), 1_000_000_000).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    }
+    let archived = fsx::btime_secs(&fs::metadata(&file).unwrap());
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("btime.blz");
+    katana_stream::create_katana_archive(
+        &[src.path().to_path_buf()], &arch, 1, 0, None, None, None,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+
+    let out = tempdir().unwrap();
+    let (result, warnings) = blitzarch::warnings::capture(|| {
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None)
+    });
+    result.unwrap();
+    assert!(warnings.iter().all(|w| w.path != "created.txt"), "{:?}", warnings);
+    let restored = fsx::btime_secs(&fs::metadata(out.path().join("created.txt")).unwrap());
+    if fsx::CAN_SET_BTIME {
+        assert_eq!(archived, Some(1_000_000_000));
+        assert_eq!(restored, archived);
+    }
+}
+
+#[test]
+fn classic_archives_restore_birth_times_where_supported() {
+    use assert_cmd::prelude::*;
+    use blitzarch::fsx;
+
+    let src = tempdir().unwrap();
+    let file = src.path().join("created.txt");
+    fs::write(&file, b"born long ago").unwrap();
+    if fsx::CAN_SET_BTIME {
+        fsx::set_btime(&File::options().write(true).open(&file).unwrap(), 1_000_000_000).unwrap();
+    }
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("btime.blz");
+    let created = std::process::Command::cargo_bin("blitzarch")
+        .unwrap()
+        .args(["create", "--format", "classic", "--output"])
+        .arg(&arch)
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+
+    let out = tempdir().unwrap();
+    let (result, warnings) = blitzarch::warnings::capture(|| {
+        blitzarch::extract::extract_files(&arch, &[], None, Some(out.path()), None)
+    });
+    result.unwrap();
+    assert!(warnings.iter().all(|w| !w.path.ends_with("created.txt")), "{:?}", warnings);
+    if fsx::CAN_SET_BTIME {
+        let restored = fsx::btime_secs(&fs::metadata(out.path().join("created.txt")).unwrap());
+        assert_eq!(restored, Some(1_000_000_000));
+    }
+}
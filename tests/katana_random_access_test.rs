@@ -45,3 +45,142 @@ fn katana_random_access_extract() {
     assert!(!out.path().join("a.txt").exists());
     assert!(!out.path().join("dir/b.bin").exists());
 }
+
+#[test]
+fn katana_extract_head_of_single_shard() {
+    let src = tempdir().unwrap();
+    // Single shard: the wanted file is followed by larger entries that must not be written
+    write_random_file(&src.path().join("a_first.txt"), 2048);
+    write_random_file(&src.path().join("b_second.bin"), 256 * 1024);
+    write_random_file(&src.path().join("c_third.bin"), 512 * 1024);
+
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("single.blz");
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 1, None).unwrap();
+
+    let want_rel = PathBuf::from("a_first.txt");
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch_path, out.path(), &[want_rel.clone()], None, None).unwrap();
+
+    assert_eq!(
+        fs::read(src.path().join(&want_rel)).unwrap(),
+        fs::read(out.path().join(&want_rel)).unwrap()
+    );
+    assert!(!out.path().join("b_second.bin").exists());
+    assert!(!out.path().join("c_third.bin").exists());
+}
+
+#[test]
+fn katana_extract_to_memory() {
+    let src = tempdir().unwrap();
+    write_random_file(&src.path().join("a.txt"), 1500);
+    write_random_file(&src.path().join("dir/b.bin"), 3000);
+    write_random_file(&src.path().join("dir/c.log"), 1024);
+
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("mem.blz");
+    let password = "memory".to_string();
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 2, Some(password.clone())).unwrap();
+
+    let all = katana::extract_katana_to_memory(&arch_path, &[], Some(password.clone()), 1 << 20).unwrap();
+    assert_eq!(all.len(), 3);
+    for (path, data) in &all {
+        assert_eq!(data, &fs::read(src.path().join(path)).unwrap(), "{}", path);
+    }
+
+    let one = katana::extract_katana_to_memory(&arch_path, &[PathBuf::from("dir/b.bin")], Some(password.clone()), 1 << 20).unwrap();
+    assert_eq!(one.keys().collect::<Vec<_>>(), vec!["dir/b.bin"]);
+
+    // Budget is enforced before anything is decoded
+    assert!(katana::extract_katana_to_memory(&arch_path, &[], Some(password), 4096).is_err());
+    assert!(katana::extract_katana_to_memory(&arch_path, &[], None, 1 << 20).is_err());
+}
+
+#[test]
+fn katana_open_entry_streams_single_file() {
+    use std::io::Read;
+
+    let src = tempdir().unwrap();
+    write_random_file(&src.path().join("a.txt"), 1500);
+    write_random_file(&src.path().join("dir/big.bin"), 3 * 1024 * 1024 + 17);
+    write_random_file(&src.path().join("dir/z.log"), 1024);
+
+    let arch_dir = tempdir().unwrap();
+    for password in [None, Some("stream")] {
+        let arch_path = arch_dir.path().join(format!("entry_{}.blz", password.is_some()));
+        katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 1, password.map(String::from)).unwrap();
+
+        for name in ["dir/big.bin", "dir/z.log", "a.txt"] {
+            let mut reader = katana::open_entry(&arch_path, name, password).unwrap();
+            let expected = fs::read(src.path().join(name)).unwrap();
+            assert_eq!(reader.size(), expected.len() as u64);
+            // Small reads to exercise the lazy path
+            let mut got = Vec::new();
+            let mut buf = [0u8; 4093];
+            loop {
+                let n = reader.read(&mut buf).unwrap();
+                if n == 0 { break; }
+                got.extend_from_slice(&buf[..n]);
+            }
+            assert_eq!(got, expected, "{}", name);
+        }
+        assert!(katana::open_entry(&arch_path, "missing.txt", password).is_err());
+    }
+}
+
+#[test]
+fn katana_open_lazy_reads_index_only() {
+    let src = tempdir().unwrap();
+    write_random_file(&src.path().join("a.txt"), 1500);
+    write_random_file(&src.path().join("dir/b.bin"), 40_000);
+
+    for password in [None, Some("lazy-pw")] {
+        let arch_dir = tempdir().unwrap();
+        let arch_path = arch_dir.path().join("lazy.blz");
+        blitzarch::katana_stream::create_katana_archive(
+            &[src.path().to_path_buf()], &arch_path, 2, 0, None, password.map(String::from), None,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+
+        let lazy = katana::open_lazy(&arch_path).unwrap();
+        assert_eq!(lazy.index_reads(), 1);
+        assert_eq!(lazy.is_encrypted(), password.is_some());
+        let mut names: Vec<_> = lazy.entries().into_iter().map(|e| e.path).collect();
+        names.sort();
+        assert_eq!(names, ["a.txt", "dir/b.bin"]);
+        lazy.verify_shards().unwrap();
+
+        if password.is_some() {
+            assert!(lazy.open_entry("dir/b.bin", None).is_err());
+            assert!(lazy.open_entry("dir/b.bin", Some("wrong")).is_err());
+        }
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut lazy.open_entry("dir/b.bin", password).unwrap(), &mut data).unwrap();
+        assert_eq!(data, fs::read(src.path().join("dir/b.bin")).unwrap());
+    }
+}
+
+#[test]
+fn katana_index_view_contains() {
+    let src = tempdir().unwrap();
+    write_random_file(&src.path().join("a.txt"), 100);
+    write_random_file(&src.path().join("dir/sub/b.bin"), 5000);
+
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("view.blz");
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 0, None).unwrap();
+
+    let view = katana::KatanaIndexView::open(&arch_path, None).unwrap();
+    assert_eq!(view.len(), 2);
+    assert_eq!(view.contains("dir/sub/b.bin").unwrap().size, 5000);
+    for alias in ["./dir/sub/b.bin", "/dir//sub/b.bin", "dir\\sub\\b.bin", "dir/./sub/b.bin/"] {
+        assert_eq!(view.contains(alias).unwrap().path, "dir/sub/b.bin", "{}", alias);
+    }
+    assert!(view.contains("dir/sub").is_none());
+    assert!(view.contains("b.bin").is_none());
+    assert!(view.contains("A.txt").is_none());
+
+    let lazy = katana::open_lazy(&arch_path).unwrap();
+    assert_eq!(lazy.index_view().get("a.txt"), view.get("a.txt"));
+}
@@ -0,0 +1,169 @@
+use blitzarch::api::Archive;
+use blitzarch::extract::katana_extract;
+use blitzarch::katana::{self, RecoveredIndex};
+use blitzarch::katana_stream::ShardStrategy;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+/// Archive of four files, one shard each (a 1-byte cap); returns the archive and the sources.
+fn per_file_archive(dir: &Path, password: Option<&str>) -> (PathBuf, Vec<(String, Vec<u8>)>) {
+    let src = dir.join("src");
+    fs::create_dir_all(&src).unwrap();
+    let files: Vec<(String, Vec<u8>)> = (0..4)
+        .map(|i| (format!("file{i}.txt"), format!("content of file {i} ").repeat(2000 + i * 500).into_bytes()))
+        .collect();
+    for (name, data) in &files {
+        fs::write(src.join(name), data).unwrap();
+    }
+    let arch = dir.join("a.blz");
+    let mut builder = Archive::create([&src]).shard_strategy(ShardStrategy::SizeCapped(1));
+    if let Some(pw) = password {
+        builder = builder.password(pw);
+    }
+    builder.write_to(&arch).unwrap();
+    (arch, files)
+}
+
+fn extract_all(arch: &Path, out: &Path, password: Option<&str>) {
+    katana_extract(arch, &[], &Some(out.to_path_buf()), None, password, None).unwrap();
+}
+
+#[test]
+fn torn_footer_keeps_everything() {
+    let dir = tempdir().unwrap();
+    let (arch, files) = per_file_archive(dir.path(), None);
+    let mut bytes = fs::read(&arch).unwrap();
+    let len = bytes.len();
+    bytes[len - 50..].fill(0); // BLAKE3 footer only
+    fs::write(&arch, &bytes).unwrap();
+    assert!(katana::list_entries(&arch, None).is_err());
+
+    let fixed = dir.path().join("fixed.blz");
+    let report = katana::repair_katana_archive(&arch, &fixed, None).unwrap();
+    assert_eq!(report.index, RecoveredIndex::ScannedTrailer);
+    assert_eq!((report.shards_kept, report.shards_lost, report.files_kept), (4, 0, 4));
+    // Исходный архив не трогаем
+    assert_eq!(fs::read(&arch).unwrap(), bytes);
+
+    let out = dir.path().join("out");
+    extract_all(&fixed, &out, None);
+    for (name, data) in &files {
+        assert_eq!(&fs::read(out.join(name)).unwrap(), data);
+    }
+}
+
+#[test]
+fn missing_trailer_finds_the_index_block() {
+    let dir = tempdir().unwrap();
+    let (arch, _) = per_file_archive(dir.path(), None);
+    let len = fs::metadata(&arch).unwrap().len();
+    // BLAKE3 footer (56 bytes) and most of the 24-byte trailer gone
+    fs::OpenOptions::new().write(true).open(&arch).unwrap().set_len(len - 56 - 20).unwrap();
+
+    let fixed = dir.path().join("fixed.blz");
+    let report = katana::repair_katana_archive(&arch, &fixed, None).unwrap();
+    assert_eq!(report.index, RecoveredIndex::ScannedBlock);
+    assert_eq!(report.files_kept, 4);
+    assert!(katana::verify_archive(&fixed, None, true).unwrap().corrupt.is_empty());
+}
+
+#[test]
+fn corrupt_shard_loses_only_its_files() {
+    let dir = tempdir().unwrap();
+    let (arch, files) = per_file_archive(dir.path(), Some("pw"));
+    let mut bytes = fs::read(&arch).unwrap();
+    bytes[40] ^= 0xFF; // первый шард
+    fs::write(&arch, &bytes).unwrap();
+
+    let fixed = dir.path().join("fixed.blz");
+    assert!(katana::repair_katana_archive(&arch, &fixed, None).is_err());
+    let report = katana::repair_katana_archive(&arch, &fixed, Some("pw")).unwrap();
+    assert_eq!(report.index, RecoveredIndex::Trailer);
+    assert_eq!((report.shards_kept, report.shards_lost), (3, 1));
+    assert_eq!(report.files_lost.len(), 1);
+
+    let out = dir.path().join("out");
+    extract_all(&fixed, &out, Some("pw"));
+    for (name, data) in &files {
+        if report.files_lost.contains(name) {
+            assert!(!out.join(name).exists());
+        } else {
+            assert_eq!(&fs::read(out.join(name)).unwrap(), data);
+        }
+    }
+}
+
+#[test]
+fn duplicates_of_lost_files_are_lost_too() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    fs::create_dir_all(&src).unwrap();
+    let data = b"the same bytes twice ".repeat(1000);
+    fs::write(src.join("a.txt"), &data).unwrap();
+    fs::write(src.join("copy.txt"), &data).unwrap();
+    let arch = dir.path().join("dup.blz");
+    Archive::create([&src]).dedup(true).write_to(&arch).unwrap();
+    let mut bytes = fs::read(&arch).unwrap();
+    bytes[20] ^= 0xFF;
+    fs::write(&arch, &bytes).unwrap();
+
+    let report = katana::repair_katana_archive(&arch, &dir.path().join("fixed.blz"), None).unwrap();
+    let mut lost = report.files_lost.clone();
+    lost.sort();
+    assert_eq!(lost, ["a.txt", "copy.txt"]);
+    assert_eq!(report.files_kept, 0);
+}
+
+#[test]
+fn truncated_archive_is_rebuilt_from_frames() {
+    let dir = tempdir().unwrap();
+    let (arch, files) = per_file_archive(dir.path(), None);
+    let len = fs::metadata(&arch).unwrap().len();
+    // Cut off in the middle of the shards: index and footer are gone
+    let cut = len / 2;
+    fs::OpenOptions::new().write(true).open(&arch).unwrap().set_len(cut).unwrap();
+
+    let fixed = dir.path().join("fixed.blz");
+    let report = katana::repair_katana_archive(&arch, &fixed, None).unwrap();
+    assert_eq!(report.index, RecoveredIndex::Rebuilt);
+    assert!(report.shards_kept >= 1, "{report:?}");
+
+    let out = dir.path().join("out");
+    extract_all(&fixed, &out, None);
+    let recovered: Vec<Vec<u8>> = fs::read_dir(out.join("recovered"))
+        .unwrap()
+        .map(|e| fs::read(e.unwrap().path()).unwrap())
+        .collect();
+    assert_eq!(recovered.len(), report.shards_kept);
+    // Каждый шард – ровно один исходный файл
+    for blob in &recovered {
+        assert!(files.iter().any(|(_, data)| data == blob));
+    }
+
+    // Пустой файл не восстановить
+    let empty = dir.path().join("empty.blz");
+    fs::write(&empty, [0u8; 100]).unwrap();
+    assert!(katana::repair_katana_archive(&empty, &dir.path().join("x.blz"), None).is_err());
+}
+
+#[test]
+fn hidden_index_needs_the_password() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("secret.txt"), "classified ".repeat(1000)).unwrap();
+    let arch = dir.path().join("hidden.blz");
+    Archive::create([&src]).password("s3cret").hide_names(true).write_to(&arch).unwrap();
+    let mut bytes = fs::read(&arch).unwrap();
+    let len = bytes.len();
+    bytes[len - 50..].fill(0);
+    fs::write(&arch, &bytes).unwrap();
+
+    let fixed = dir.path().join("fixed.blz");
+    let err = katana::repair_katana_archive(&arch, &fixed, None).unwrap_err();
+    assert!(err.to_string().contains("password required"), "{err}");
+    assert!(!fixed.exists());
+    let report = katana::repair_katana_archive(&arch, &fixed, Some("s3cret")).unwrap();
+    assert_eq!(report.files_kept, 1);
+}
@@ -0,0 +1,161 @@
+use blitzarch::katana::{self, IndexCompression};
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn create(src: &Path, arch: &Path, frames: Option<u64>, password: Option<&str>) {
+    let options = KatanaCreateOptions {
+        seekable_frames: frames,
+        index_compression: IndexCompression::Store,
+        ..Default::default()
+    };
+    katana_stream::create_katana_archive_with_options(
+        &[src.to_path_buf()], arch, 1, 0, None, password.map(String::from), None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+}
+
+#[test]
+fn seekable_frames_serve_ranges_from_the_nearest_frame() {
+    let src = tempdir().unwrap();
+    // Compressible but not trivially repetitive
+    let big: Vec<u8> = (0..6_000_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8 % 16 + b'a').collect();
+    fs::write(src.path().join("a_first.txt"), b"leading entry ".repeat(500)).unwrap();
+    fs::write(src.path().join("b_big.bin"), &big).unwrap();
+    fs::write(src.path().join("c_last.txt"), b"trailing entry").unwrap();
+
+    for password in [None, Some("frames-pw")] {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("seek.blz");
+        create(src.path(), &arch, Some(256 * 1024), password);
+        let raw = fs::read(&arch).unwrap();
+        assert!(String::from_utf8_lossy(&raw).contains("\"frames\""), "seek table recorded");
+        assert!(katana::verify_archive(&arch, password, true).unwrap().deep);
+
+        for (offset, len) in [(0, 10), (262_140, 10), (1_000_000, 70_000), (5_999_990, 100), (7_000_000, 5)] {
+            let got = katana::read_range(&arch, "b_big.bin", offset, len, password).unwrap();
+            let from = (offset as usize).min(big.len());
+            let to = (from + len as usize).min(big.len());
+            assert_eq!(got, &big[from..to], "range {offset}+{len}");
+        }
+        assert_eq!(katana::read_range(&arch, "c_last.txt", 9, 100, password).unwrap(), b"entry");
+        let archive = katana::open_lazy(&arch).unwrap();
+        assert_eq!(archive.read_range("a_first.txt", 14, 7, password).unwrap(), b"leading");
+
+        // Old-style readers decode the frames as one stream
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], password.map(String::from), None).unwrap();
+        assert_eq!(fs::read(out.path().join("b_big.bin")).unwrap(), big);
+    }
+
+    // A damaged first frame does not stop reads further into the shard
+    let arch_dir = tempdir().unwrap();
+    for (frames, readable) in [(Some(256 * 1024), true), (None, false)] {
+        let arch = arch_dir.path().join(format!("{readable}.blz"));
+        create(src.path(), &arch, frames, None);
+        let mut bytes = fs::read(&arch).unwrap();
+        bytes[20..4000].fill(0);
+        fs::write(&arch, &bytes).unwrap();
+        let tail = katana::read_range(&arch, "b_big.bin", 5_500_000, 1000, None);
+        assert_eq!(tail.is_ok(), readable, "{:?}", tail.as_ref().err());
+        if let Ok(tail) = tail {
+            assert_eq!(tail, &big[5_500_000..5_501_000]);
+        }
+    }
+}
+
+/// One shard, many entries: extraction splits it at the seek table into runs
+/// decoded in parallel, each starting mid-frame.
+#[test]
+fn single_shard_with_frames_extracts_in_segments() {
+    let src = tempdir().unwrap();
+    let mut expected = Vec::new();
+    for i in 0..40u32 {
+        let data: Vec<u8> = (0..100_000 + i * 3_001).map(|j| (j.wrapping_mul(2_654_435_761).wrapping_add(i) >> 24) as u8 % 16 + b'a').collect();
+        let name = format!("f{i:02}.txt");
+        fs::write(src.path().join(&name), &data).unwrap();
+        expected.push((name, data));
+    }
+
+    // Encrypted shards are decrypted once and split the same way
+    for password in [None, Some("segments-pw")] {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("one_shard.blz");
+        create(src.path(), &arch, Some(64 * 1024), password);
+        assert_eq!(katana::shard_details(&arch, password).unwrap().len(), 1);
+        let pass = password.map(String::from);
+
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], pass.clone(), None).unwrap();
+        for (name, data) in &expected {
+            assert!(fs::read(out.path().join(name)).unwrap() == *data, "{name}");
+        }
+
+        // A selection still reads only its own entries
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &["f17.txt".into(), "f33.txt".into()], pass, None).unwrap();
+        assert!(fs::read(out.path().join("f17.txt")).unwrap() == expected[17].1);
+        assert!(fs::read(out.path().join("f33.txt")).unwrap() == expected[33].1);
+        assert!(!out.path().join("f00.txt").exists());
+    }
+}
+
+/// Without `--seekable-frames` both writers still cut shards into frames of
+/// `DEFAULT_FRAME_SIZE`, so default archives are split across cores as well.
+#[test]
+fn default_archives_have_a_seek_table() {
+    let src = tempdir().unwrap();
+    let mut expected = Vec::new();
+    for i in 0..20u32 {
+        let data: Vec<u8> = (0..1_000_000u32).map(|j| (j.wrapping_mul(2_654_435_761).wrapping_add(i) >> 24) as u8 % 16 + b'a').collect();
+        let name = format!("f{i:02}.txt");
+        fs::write(src.path().join(&name), &data).unwrap();
+        expected.push((name, data));
+    }
+
+    let arch_dir = tempdir().unwrap();
+    let streamed = arch_dir.path().join("streamed.blz");
+    create(src.path(), &streamed, None, None);
+    let legacy = arch_dir.path().join("legacy.blz");
+    katana::create_katana_archive_with_progress(
+        &[src.path().to_path_buf()], &legacy, 1, 0, None, Some("default-pw".into()),
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+
+    for (arch, password) in [(&streamed, None), (&legacy, Some("default-pw"))] {
+        let shards = katana::shard_details(arch, password).unwrap();
+        assert_eq!(shards.len(), 1);
+        let frames = 20_000_000u64.div_ceil(katana::DEFAULT_FRAME_SIZE) as usize;
+        assert_eq!(shards[0].frames, frames, "{}", arch.display());
+        assert!(katana::verify_archive(arch, password, true).unwrap().deep);
+
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(arch, out.path(), &[], password.map(String::from), None).unwrap();
+        for (name, data) in &expected {
+            assert!(fs::read(out.path().join(name)).unwrap() == *data, "{name}");
+        }
+        assert_eq!(katana::read_range(arch, "f19.txt", 999_990, 10, password).unwrap(), &expected[19].1[999_990..]);
+    }
+}
+
+#[test]
+fn zero_frame_size_is_rejected() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"payload").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("zero.blz");
+    let options = KatanaCreateOptions { seekable_frames: Some(0), ..Default::default() };
+    let err = katana_stream::create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 1, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("at least one byte"), "{err}");
+    assert!(!arch.exists());
+
+    let cli = assert_cmd::Command::cargo_bin("blitzarch").unwrap().args(["create", "--seekable-frames", "0", "--output"]).arg(&arch).arg(src.path()).output().unwrap();
+    assert!(!cli.status.success());
+}
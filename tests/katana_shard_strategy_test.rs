@@ -0,0 +1,55 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions, ShardStrategy};
+use std::fs;
+use std::io::Read;
+use tempfile::tempdir;
+
+#[test]
+fn shard_strategies_control_shard_composition() {
+    let src = tempdir().unwrap();
+    let sizes = [300_000usize, 300_000, 300_000, 900_000, 2_000_000, 10];
+    let mut inputs = Vec::new();
+    for (i, size) in sizes.iter().enumerate() {
+        let data: Vec<u8> = (0..*size).map(|b| (b * 31 + i * 7) as u8).collect();
+        inputs.push(src.path().join(format!("f{i}.bin")));
+        fs::write(inputs.last().unwrap(), data).unwrap();
+    }
+
+    // Inputs are taken in order: f0..f2 fit 1 MiB, f3 starts a new shard,
+    // f4 exceeds the cap on its own and f5 cannot join it; per-file keeps the
+    // solid shards and gives every file a frame of its own
+    let cases = [
+        (ShardStrategy::Solid, 2),
+        (ShardStrategy::PerFile, 2),
+        (ShardStrategy::SizeCapped(1024 * 1024), 4),
+    ];
+    for (strategy, shards) in cases {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("strategy.blz");
+        let options = KatanaCreateOptions { shard_strategy: strategy, ..Default::default() };
+        katana_stream::create_katana_archive_with_options(
+            &inputs, &arch, 2, 0, None, None, None, &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+        assert_eq!(katana::index_stats(&arch).unwrap().shard_count, shards, "{:?}", strategy);
+        let frames: usize = katana::shard_details(&arch, None).unwrap().iter().map(|s| s.frames).sum();
+        assert_eq!(frames, if strategy == ShardStrategy::PerFile { sizes.len() } else { shards }, "{:?}", strategy);
+
+        let mut data = Vec::new();
+        katana::open_entry(&arch, "f4.bin", None).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, fs::read(src.path().join("f4.bin")).unwrap());
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+        for i in 0..sizes.len() {
+            let name = format!("f{i}.bin");
+            assert_eq!(fs::read(out.path().join(&name)).unwrap(), fs::read(src.path().join(&name)).unwrap());
+        }
+    }
+
+    assert_eq!("per-file".parse(), Ok(ShardStrategy::PerFile));
+    assert_eq!("size-capped:64".parse(), Ok(ShardStrategy::SizeCapped(64 * 1024 * 1024)));
+    assert_eq!("size-capped:8MiB".parse(), Ok(ShardStrategy::SizeCapped(8 * 1024 * 1024)));
+    assert!("size-capped:0".parse::<ShardStrategy>().is_err());
+    assert!("chunky".parse::<ShardStrategy>().is_err());
+}
@@ -0,0 +1,94 @@
+#![cfg(unix)]
+
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, perform_paranoid_check, KatanaCreateOptions};
+use blitzarch::vfs::SymlinkMode;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+fn create(src: &Path, arch: &Path, symlinks: SymlinkMode) {
+    let options = KatanaCreateOptions { symlinks, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src.to_path_buf()], arch, 2, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+}
+
+fn fixture() -> tempfile::TempDir {
+    let src = tempdir().unwrap();
+    fs::create_dir_all(src.path().join("dir")).unwrap();
+    fs::write(src.path().join("dir/data.txt"), b"payload ".repeat(500)).unwrap();
+    symlink("dir/data.txt", src.path().join("link.txt")).unwrap();
+    symlink("dir", src.path().join("dirlink")).unwrap();
+    symlink("../../../../etc/passwd", src.path().join("dir/escape")).unwrap();
+    symlink("/etc/hostname", src.path().join("absolute")).unwrap();
+    src
+}
+
+#[test]
+fn keep_restores_links_and_rejects_escapes() {
+    let src = fixture();
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("links.blz");
+    create(src.path(), &arch, SymlinkMode::Keep);
+    perform_paranoid_check(&arch).unwrap();
+
+    let entries = katana::list_entries(&arch, None).unwrap();
+    let link = entries.iter().find(|e| e.path == "link.txt").unwrap();
+    assert_eq!(link.symlink.as_deref(), Some("dir/data.txt"));
+    assert_eq!(entries.iter().filter(|e| e.symlink.is_some()).count(), 4);
+
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+    assert_eq!(fs::read_link(out.path().join("link.txt")).unwrap(), PathBuf::from("dir/data.txt"));
+    assert_eq!(fs::read_link(out.path().join("dirlink")).unwrap(), PathBuf::from("dir"));
+    assert_eq!(fs::read(out.path().join("link.txt")).unwrap(), b"payload ".repeat(500));
+    // Links leaving the output directory are not recreated
+    assert!(fs::symlink_metadata(out.path().join("dir/escape")).is_err());
+    assert!(fs::symlink_metadata(out.path().join("absolute")).is_err());
+}
+
+#[test]
+fn follow_and_skip_modes() {
+    let src = fixture();
+    fs::remove_file(src.path().join("dir/escape")).unwrap();
+    fs::remove_file(src.path().join("absolute")).unwrap();
+    let arch_dir = tempdir().unwrap();
+
+    let followed = arch_dir.path().join("follow.blz");
+    create(src.path(), &followed, SymlinkMode::Follow);
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&followed, out.path(), &[], None, None).unwrap();
+    assert!(!fs::symlink_metadata(out.path().join("link.txt")).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read(out.path().join("link.txt")).unwrap(), b"payload ".repeat(500));
+    assert_eq!(fs::read(out.path().join("dirlink/data.txt")).unwrap(), b"payload ".repeat(500));
+
+    let skipped = arch_dir.path().join("skip.blz");
+    create(src.path(), &skipped, SymlinkMode::Skip);
+    let paths: Vec<_> = katana::list_entries(&skipped, None).unwrap().into_iter().map(|e| e.path).collect();
+    assert_eq!(paths, ["dir/data.txt"]);
+}
+
+#[test]
+fn chained_links_do_not_escape() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("keep.txt"), b"kept").unwrap();
+    symlink(".", src.path().join("a")).unwrap();
+    symlink("a/..", src.path().join("b")).unwrap();
+    symlink("a/a/keep.txt", src.path().join("c")).unwrap();
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("chain.blz");
+    create(src.path(), &arch, SymlinkMode::Keep);
+
+    let parent = tempdir().unwrap();
+    let out = parent.path().join("out");
+    fs::create_dir_all(&out).unwrap();
+    katana::extract_katana_archive_internal(&arch, &out, &[], None, None).unwrap();
+    assert_eq!(fs::read_link(out.join("a")).unwrap(), PathBuf::from("."));
+    assert_eq!(fs::read(out.join("c")).unwrap(), b"kept");
+    // `b` would resolve to the parent of the output directory
+    assert!(fs::symlink_metadata(out.join("b")).is_err());
+}
@@ -0,0 +1,142 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::perform_paranoid_check;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+fn write_file(p: &Path, data: &[u8]) {
+    if let Some(parent) = p.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    File::create(p).unwrap().write_all(data).unwrap();
+}
+
+#[test]
+fn touch_rewrites_index_only() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("docs/a.txt"), b"alpha");
+    write_file(&src.path().join("docs/sub/b.txt"), b"beta");
+    write_file(&src.path().join("bin/run.sh"), b"#!/bin/sh\n");
+
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("touch.blz");
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 2, None).unwrap();
+    let size_before = fs::metadata(&arch_path).unwrap().len();
+
+    let updated = katana::touch_katana_archive(&arch_path, &["docs/**".to_string()], 0o600, None).unwrap();
+    assert_eq!(updated, 2);
+
+    // Index size may differ slightly, shard data must not be rewritten
+    let size_after = fs::metadata(&arch_path).unwrap().len();
+    assert!(size_after.abs_diff(size_before) < 64);
+    perform_paranoid_check(&arch_path).unwrap();
+
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch_path, out.path(), &[], None, None).unwrap();
+    assert_eq!(fs::read(out.path().join("docs/sub/b.txt")).unwrap(), b"beta");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |p: PathBuf| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(out.path().join("docs/a.txt")), 0o600);
+        assert_eq!(mode(out.path().join("docs/sub/b.txt")), 0o600);
+        assert_ne!(mode(out.path().join("bin/run.sh")), 0o600);
+    }
+}
+
+#[test]
+fn touch_encrypted_requires_password() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("secret.txt"), b"top secret");
+
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("touch.enc.blz");
+    let password = "hunter2".to_string();
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 1, Some(password.clone())).unwrap();
+
+    assert!(katana::touch_katana_archive(&arch_path, &[], 0o640, None).is_err());
+    let updated = katana::touch_katana_archive(&arch_path, &[], 0o640, Some(password.clone())).unwrap();
+    assert_eq!(updated, 1);
+
+    // HMAC must have been re-signed with the same key
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch_path, out.path(), &[], Some(password), None).unwrap();
+    assert_eq!(fs::read(out.path().join("secret.txt")).unwrap(), b"top secret");
+}
+
+#[test]
+fn touch_sets_modification_times() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("docs/a.txt"), b"alpha");
+    write_file(&src.path().join("bin/run.sh"), b"#!/bin/sh\n");
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("mtime.blz");
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 2, None).unwrap();
+
+    let changes = katana::TouchChanges { mtime: Some(1_600_000_000), ..Default::default() };
+    assert_eq!(katana::touch_entries(&arch_path, &["docs/**".to_string()], changes, None).unwrap(), 1);
+    perform_paranoid_check(&arch_path).unwrap();
+    for entry in katana::list_entries(&arch_path, None).unwrap().iter().filter(|e| !e.is_dir) {
+        assert_eq!(entry.mtime == Some(1_600_000_000), entry.path.ends_with("a.txt"), "{}", entry.path);
+    }
+    assert!(katana::touch_entries(&arch_path, &[], katana::TouchChanges::default(), None).is_err());
+}
+
+#[test]
+fn touch_without_footer_skips_the_rehash() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("a.txt"), b"alpha");
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("nofoot.blz");
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 2, None).unwrap();
+
+    let changes = katana::TouchChanges { mode: Some(0o600), drop_footer: true, ..Default::default() };
+    assert_eq!(katana::touch_entries(&arch_path, &[], changes, None).unwrap(), 1);
+    // Без футера нечего сверять целиком, но архив читается
+    assert!(perform_paranoid_check(&arch_path).is_err());
+    let entry = katana::list_entries(&arch_path, None).unwrap().into_iter().find(|e| e.path == "a.txt").unwrap();
+    assert_eq!(entry.permissions, Some(0o600));
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch_path, out.path(), &[], None, None).unwrap();
+    assert_eq!(fs::read(out.path().join("a.txt")).unwrap(), b"alpha");
+
+    // Следующая обычная правка возвращает футер
+    katana::touch_katana_archive(&arch_path, &[], 0o644, None).unwrap();
+    perform_paranoid_check(&arch_path).unwrap();
+}
+
+#[test]
+fn interrupted_in_place_update_is_rolled_back_on_open() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("a.txt"), b"alpha");
+    let arch_dir = tempdir().unwrap();
+    let arch_path = arch_dir.path().join("crash.blz");
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch_path, 2, None).unwrap();
+    let original = fs::read(&arch_path).unwrap();
+    let (_, index_offset, _) = katana::read_katana_footer(&mut File::open(&arch_path).unwrap()).unwrap();
+
+    // What an update leaves behind when it dies after truncating the old index
+    let mut record = b"KATUNDO1".to_vec();
+    record.extend_from_slice(&index_offset.to_le_bytes());
+    record.extend_from_slice(&(original.len() as u64).to_le_bytes());
+    record.extend_from_slice(&original[index_offset as usize..]);
+    let digest = *blake3::hash(&record).as_bytes();
+    record.extend_from_slice(&digest);
+    let undo = arch_dir.path().join("crash.blz.undo");
+    fs::write(&undo, &record).unwrap();
+    let mut torn = original[..index_offset as usize].to_vec();
+    torn.extend_from_slice(b"half of a new ind");
+    fs::write(&arch_path, &torn).unwrap();
+
+    assert_eq!(katana::list_entries(&arch_path, None).unwrap().iter().filter(|e| !e.is_dir).count(), 1);
+    assert_eq!(fs::read(&arch_path).unwrap(), original);
+    assert!(!undo.exists());
+
+    // A torn record means the archive was never touched: it is only dropped
+    fs::write(&undo, &record[..record.len() - 1]).unwrap();
+    assert!(katana::is_katana_archive(&arch_path).unwrap());
+    assert!(!undo.exists());
+    assert_eq!(fs::read(&arch_path).unwrap(), original);
+}
@@ -0,0 +1,75 @@
+#![cfg(unix)]
+
+use blitzarch::fsx::{self, Xattrs};
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, perform_paranoid_check, KatanaCreateOptions};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn create(src: &Path, arch: &Path, xattrs: bool, inline: bool) {
+    let options = KatanaCreateOptions { xattrs, inline_small_files: inline, ..Default::default() };
+    katana_stream::create_katana_archive_with_options(
+        &[src.to_path_buf()], arch, 2, 0, None, None, None, &options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+}
+
+#[test]
+fn xattrs_recorded_and_restored_on_request() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("tagged.bin"), vec![1u8; 20_000]).unwrap();
+    fs::write(src.path().join("small.txt"), b"tiny").unwrap();
+    fs::write(src.path().join("plain.txt"), b"no attributes").unwrap();
+    let attrs: Xattrs = [("user.origin".to_string(), b"camera-1".to_vec()), ("user.empty".to_string(), Vec::new())].into();
+    for name in ["tagged.bin", "small.txt"] {
+        if !fsx::write_xattrs(&src.path().join(name), &attrs).is_empty() {
+            eprintln!("user xattrs not supported in {:?}, skipping", src.path());
+            return;
+        }
+    }
+
+    for inline in [false, true] {
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("xattr.blz");
+        create(src.path(), &arch, true, inline);
+        perform_paranoid_check(&arch).unwrap();
+
+        // Without --xattrs nothing is applied
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+        assert!(fsx::read_xattrs(&out.path().join("tagged.bin")).unwrap().is_empty());
+
+        fsx::set_restore_xattrs(true);
+        let out = tempdir().unwrap();
+        let result = katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None);
+        fsx::set_restore_xattrs(false);
+        result.unwrap();
+        assert_eq!(fsx::read_xattrs(&out.path().join("tagged.bin")).unwrap(), attrs);
+        assert_eq!(fsx::read_xattrs(&out.path().join("small.txt")).unwrap(), attrs);
+        assert!(fsx::read_xattrs(&out.path().join("plain.txt")).unwrap().is_empty());
+    }
+
+    // Not recorded unless asked for
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("plain.blz");
+    create(src.path(), &arch, false, false);
+    fsx::set_restore_xattrs(true);
+    let out = tempdir().unwrap();
+    let result = katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None);
+    fsx::set_restore_xattrs(false);
+    result.unwrap();
+    assert!(fsx::read_xattrs(&out.path().join("tagged.bin")).unwrap().is_empty());
+}
+
+#[test]
+fn privileged_namespaces_need_their_own_switch() {
+    for name in ["security.capability", "security.selinux", "trusted.overlay.opaque"] {
+        assert!(fsx::is_privileged_xattr(name), "{name}");
+    }
+    for name in ["user.origin", "system.posix_acl_access", "com.apple.quarantine"] {
+        assert!(!fsx::is_privileged_xattr(name), "{name}");
+    }
+    assert!(!fsx::restore_privileged_xattrs());
+}
@@ -0,0 +1,50 @@
+//! Session key cache (global state – kept in its own test binary).
+
+use blitzarch::api::Archive;
+use blitzarch::crypto;
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[test]
+fn session_key_cache_lifecycle() {
+    let salt_a = [1u8; 16];
+    let salt_b = [2u8; 16];
+    let uncached = crypto::derive_key_argon2("secret", &salt_a);
+    assert_eq!(crypto::cached_key_count(), 0, "disabled by default");
+
+    crypto::enable_key_cache(2, Duration::from_secs(60));
+    assert_eq!(crypto::derive_key_argon2("secret", &salt_a), uncached);
+    assert_eq!(crypto::derive_key_argon2("secret", &salt_a), uncached, "served from the cache");
+    assert_ne!(crypto::derive_key_argon2("wrong", &salt_a), uncached, "the password is part of the lookup");
+    assert_eq!(crypto::cached_key_count(), 2);
+    crypto::derive_key_argon2("secret", &salt_b);
+    assert_eq!(crypto::cached_key_count(), 2, "bounded");
+
+    crypto::invalidate_cached_key(&salt_b);
+    assert_eq!(crypto::cached_key_count(), 1);
+    crypto::lock_key_cache();
+    assert_eq!(crypto::cached_key_count(), 0);
+
+    // Простаивающие ключи стираются без обращений к кэшу
+    crypto::enable_key_cache(4, Duration::from_millis(100));
+    crypto::derive_key_argon2("secret", &salt_a);
+    assert_eq!(crypto::cached_key_count(), 1);
+    std::thread::sleep(Duration::from_millis(600));
+    assert_eq!(crypto::cached_key_count(), 0);
+
+    // Prefetch fills the cache from the archive's salt
+    crypto::enable_key_cache(4, Duration::from_secs(60));
+    let src = tempdir().unwrap();
+    std::fs::write(src.path().join("a.txt"), "hello").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("enc.blz");
+    Archive::create([src.path()]).password("pw").write_to(&arch).unwrap();
+    crypto::lock_key_cache();
+    blitzarch::katana::prefetch_archive_key(&arch, "pw").unwrap().unwrap().join().unwrap();
+    assert_eq!(crypto::cached_key_count(), 1);
+    let entries = Archive::open(&arch).unwrap().password("pw").entries().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(crypto::cached_key_count(), 1, "listing reused the prefetched key");
+    crypto::disable_key_cache();
+    assert_eq!(crypto::cached_key_count(), 0);
+}
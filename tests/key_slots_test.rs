@@ -0,0 +1,105 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::perform_paranoid_check;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_file(p: &Path, data: &[u8]) {
+    if let Some(parent) = p.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    File::create(p).unwrap().write_all(data).unwrap();
+}
+
+fn extract_with(arch: &Path, password: &str) -> Result<Vec<u8>, String> {
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(arch, out.path(), &[], Some(password.to_string()), None)
+        .map_err(|e| e.to_string())?;
+    Ok(fs::read(out.path().join("secret.txt")).unwrap())
+}
+
+/// Start of the first shard; key changes must not re-encrypt it.
+fn shard_bytes(arch: &Path) -> Vec<u8> {
+    let data = fs::read(arch).unwrap();
+    data[..64 * 1024].to_vec()
+}
+
+#[test]
+fn key_slots_add_rotate_remove() {
+    let src = tempdir().unwrap();
+    // Несжимаемые данные: шард заметно больше сравниваемого начала
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let payload: Vec<u8> = (0..256 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    write_file(&src.path().join("secret.txt"), &payload);
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("slots.blz");
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch, 1, Some("alpha".into())).unwrap();
+    let shards_before = shard_bytes(&arch);
+    assert_eq!(katana::key_slot_count(&arch, None).unwrap(), 0);
+
+    assert!(katana::add_key_slot(&arch, "wrong", "beta").is_err());
+    assert_eq!(katana::add_key_slot(&arch, "alpha", "beta").unwrap(), 2);
+    let expected = extract_with(&arch, "alpha").unwrap();
+    assert_eq!(extract_with(&arch, "beta").unwrap(), expected);
+    assert!(extract_with(&arch, "gamma").unwrap_err().contains("no key slot"));
+    assert_eq!(shard_bytes(&arch), shards_before, "shards are not re-encrypted");
+    perform_paranoid_check(&arch).unwrap();
+
+    // Ротация меняет только свой слот
+    assert_eq!(katana::rotate_key_slot(&arch, "alpha", "gamma").unwrap(), 2);
+    assert!(extract_with(&arch, "alpha").is_err());
+    assert_eq!(extract_with(&arch, "gamma").unwrap(), expected);
+    assert_eq!(extract_with(&arch, "beta").unwrap(), expected);
+
+    assert_eq!(katana::remove_key_slot(&arch, "beta").unwrap(), 1);
+    assert!(extract_with(&arch, "beta").is_err());
+    assert!(katana::remove_key_slot(&arch, "gamma").unwrap_err().to_string().contains("only password"));
+    assert_eq!(extract_with(&arch, "gamma").unwrap(), expected);
+    assert_eq!(shard_bytes(&arch), shards_before);
+
+    // The index is rewritten in a copy renamed over the archive: a reader that
+    // opened the archive before keeps the complete old one
+    let opened = fs::File::open(&arch).unwrap();
+    let old_len = opened.metadata().unwrap().len();
+    katana::add_key_slot(&arch, "gamma", "delta").unwrap();
+    assert_eq!(opened.metadata().unwrap().len(), old_len);
+    for entry in fs::read_dir(dir.path()).unwrap() {
+        let name = entry.unwrap().file_name();
+        assert!(!name.to_string_lossy().starts_with(".blitzarch-"), "scratch file {name:?} left behind");
+    }
+}
+
+#[test]
+fn key_slots_keep_append_and_list_working() {
+    let src = tempdir().unwrap();
+    write_file(&src.path().join("secret.txt"), b"first");
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("append.blz");
+    katana::create_katana_archive(&[src.path().to_path_buf()], &arch, 1, Some("alpha".into())).unwrap();
+    katana::add_key_slot(&arch, "alpha", "beta").unwrap();
+
+    let more = tempdir().unwrap();
+    write_file(&more.path().join("later.txt"), b"second");
+    katana::append_to_archive(&arch, &[more.path().join("later.txt")], 1, Some("beta".into()), None).unwrap();
+
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &[], Some("alpha".into()), None).unwrap();
+    assert_eq!(fs::read(out.path().join("secret.txt")).unwrap(), b"first");
+    assert_eq!(fs::read(out.path().join("later.txt")).unwrap(), b"second");
+    let listed = katana::list_entries(&arch, Some("beta")).unwrap();
+    assert_eq!(listed.len(), 2);
+
+    katana::decrypt_katana_archive(&arch, "beta").unwrap();
+    assert_eq!(katana::key_slot_count(&arch, None).unwrap(), 0);
+    let plain = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, plain.path(), &[], None, None).unwrap();
+    assert_eq!(fs::read(plain.path().join("later.txt")).unwrap(), b"second");
+}
@@ -0,0 +1,73 @@
+use blitzarch::api::Archive;
+use blitzarch::manifest::{escape, write_mtree};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn mtree(arch: &Path, password: Option<&str>, hashes: bool) -> String {
+    let mut out = Vec::new();
+    write_mtree(arch, password, hashes, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn mtree_lists_dirs_modes_sizes_and_digests() {
+    let src = tempdir().unwrap();
+    fs::create_dir_all(src.path().join("docs/deep")).unwrap();
+    fs::write(src.path().join("docs/deep/report one.txt"), b"quarterly numbers").unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    fs::write(src.path().join("copy.txt"), b"alpha").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("m.blz");
+    Archive::create([src.path()]).password("pw").dedup(true).write_to(&arch).unwrap();
+
+    let spec = mtree(&arch, Some("pw"), true);
+    let lines: Vec<&str> = spec.lines().collect();
+    assert_eq!(lines[..2], ["#mtree", ". type=dir"]);
+    let line = |name: &str| *lines.iter().find(|l| l.split(' ').next().unwrap().ends_with(name)).unwrap_or_else(|| panic!("{name} in {spec}"));
+    let report = line("report\\040one.txt");
+    assert!(report.contains(" type=file") && report.contains(" size=17"), "{report}");
+    assert!(report.contains(&format!("sha256digest={}", sha256_hex(b"quarterly numbers"))));
+    assert!(line("/docs/deep").contains("type=dir"));
+    // Копия получает хэш оригинала
+    let alpha = format!("sha256digest={}", sha256_hex(b"alpha"));
+    assert!(line("/a.txt").contains(&alpha) && line("/copy.txt").contains(&alpha));
+    #[cfg(unix)]
+    assert!(line("/a.txt").contains(" mode=0"));
+
+    // Без хэшей достаточно индекса
+    let plain = mtree(&arch, Some("pw"), false);
+    assert!(!plain.contains("sha256digest"));
+    let mut out = Vec::new();
+    assert!(write_mtree(&arch, None, true, &mut out).is_err());
+}
+
+#[test]
+fn mtree_escapes_special_characters() {
+    assert_eq!(escape("a b#c=d\\e"), "a\\040b\\043c\\075d\\134e");
+    assert_eq!(escape("ü"), "\\303\\274");
+    assert_eq!(escape("plain/path.txt"), "plain/path.txt");
+}
+
+#[test]
+fn digests_refuse_a_damaged_shard() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("data.txt"), "payload ".repeat(512)).unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("d.blz");
+    Archive::create([src.path()]).write_to(&arch).unwrap();
+    let mut bytes = fs::read(&arch).unwrap();
+    bytes[40] ^= 0xFF; // первый шард
+    fs::write(&arch, &bytes).unwrap();
+
+    assert!(blitzarch::katana::entry_sha256(&arch, None).is_err());
+    let mut out = Vec::new();
+    assert!(write_mtree(&arch, None, true, &mut out).is_err());
+    // Без хэшей шарды не читаются
+    assert!(mtree(&arch, None, false).contains("data.txt"));
+}
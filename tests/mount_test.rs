@@ -0,0 +1,98 @@
+use blitzarch::katana::{self, EntryInfo};
+use blitzarch::mount::{BlockCache, MountTree, NodeKind, BLOCK_SIZE, ROOT_INODE};
+use std::cell::RefCell;
+use std::fs;
+use tempfile::tempdir;
+
+fn entry(path: &str, size: u64) -> EntryInfo {
+    EntryInfo {
+        path: path.into(),
+        size,
+        permissions: Some(0o640),
+        mtime: Some(1_700_000_000),
+        is_dir: false,
+        shard_id: Some(0),
+        compressed_size: None,
+        symlink: None,
+        duplicate_of: None,
+    }
+}
+
+#[test]
+fn tree_derives_directories_from_paths() {
+    let mut link = entry("docs/latest", 0);
+    link.symlink = Some("guide.md".into());
+    let mut dir = entry("docs", 0);
+    dir.is_dir = true;
+    dir.permissions = Some(0o700);
+    let tree = MountTree::new(&[entry("README.md", 10), entry("docs/guide.md", 7), entry("src/util/mod.rs", 50), link, dir]);
+
+    let root = tree.node(ROOT_INODE).unwrap();
+    assert_eq!(root.children.keys().collect::<Vec<_>>(), ["README.md", "docs", "src"]);
+    let docs = tree.lookup(ROOT_INODE, "docs").unwrap();
+    assert_eq!(tree.node(docs).unwrap().kind, NodeKind::Dir);
+    assert_eq!(tree.node(docs).unwrap().permissions, Some(0o700), "recorded directory metadata is kept");
+    assert_eq!(tree.node(tree.lookup(docs, "latest").unwrap()).unwrap().kind, NodeKind::Symlink("guide.md".into()));
+
+    let util = tree.resolve("src/util").unwrap();
+    assert_eq!(tree.node(util).unwrap().path, "src/util");
+    assert_eq!(tree.node(util).unwrap().parent, tree.resolve("src").unwrap());
+    let module = tree.node(tree.resolve("src/util/mod.rs").unwrap()).unwrap();
+    assert_eq!((module.kind.clone(), module.size), (NodeKind::File, 50));
+    assert!(tree.resolve("src/missing").is_none());
+    assert_eq!(tree.len(), 8);
+}
+
+#[test]
+fn block_cache_reads_ahead_and_evicts_lru_blocks() {
+    let file: Vec<u8> = (0..BLOCK_SIZE * 20).map(|i| (i % 251) as u8).collect();
+    let size = file.len() as u64;
+    let fetches = RefCell::new(Vec::new());
+    let fetch = |start: u64, len: u64| {
+        fetches.borrow_mut().push((start, len));
+        Ok(file[start as usize..(start + len) as usize].to_vec())
+    };
+    let mut cache = BlockCache::new(BLOCK_SIZE * 8);
+
+    assert_eq!(cache.read(2, size, 100, 50, fetch).unwrap(), file[100..150]);
+    assert_eq!(fetches.borrow().as_slice(), [(0, BLOCK_SIZE * 8)]);
+    // Straddles blocks 6 and 7, both read ahead
+    let at = BLOCK_SIZE * 7 - 10;
+    assert_eq!(cache.read(2, size, at, 20, fetch).unwrap(), file[at as usize..at as usize + 20]);
+    assert_eq!(fetches.borrow().len(), 1);
+
+    // Tail of the file: clipped at its end, evicting the oldest blocks
+    let tail = cache.read(2, size, size - 100, 1000, fetch).unwrap();
+    assert_eq!(tail, file[file.len() - 100..]);
+    assert_eq!(fetches.borrow()[1], (BLOCK_SIZE * 19, BLOCK_SIZE));
+    assert_eq!((cache.stats().blocks, cache.stats().bytes), (8, BLOCK_SIZE * 8));
+    assert!(cache.read(2, size, size, 10, fetch).unwrap().is_empty());
+    assert_eq!(fetches.borrow().len(), 2);
+
+    // Block 0 was the least recently used one
+    assert_eq!(cache.read(2, size, BLOCK_SIZE, 10, fetch).unwrap(), file[BLOCK_SIZE as usize..BLOCK_SIZE as usize + 10]);
+    assert_eq!(fetches.borrow().len(), 2);
+    assert_eq!(cache.read(2, size, 0, 10, fetch).unwrap(), file[..10]);
+    assert_eq!(fetches.borrow()[2], (0, BLOCK_SIZE * 8));
+}
+
+#[test]
+fn block_cache_serves_archive_entries() {
+    let src = tempdir().unwrap();
+    let content: Vec<u8> = (0..600_000u32).flat_map(|i| i.to_le_bytes()).collect();
+    fs::write(src.path().join("data.bin"), &content).unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("mount.blz");
+    blitzarch::Archive::create([src.path()]).threads(1).write_to(&arch).unwrap();
+
+    let archive = katana::open_lazy(&arch).unwrap();
+    let tree = MountTree::new(&archive.entries());
+    let ino = tree.resolve("data.bin").unwrap();
+    let mut cache = BlockCache::new(64 << 20);
+    let read = |cache: &mut BlockCache, offset: u64, len: u64| {
+        cache.read(ino, content.len() as u64, offset, len, |start, len| archive.read_range("data.bin", start, len, None)).unwrap()
+    };
+    assert_eq!(read(&mut cache, 1_000_000, 4096), content[1_000_000..1_004_096]);
+    assert_eq!(read(&mut cache, 1_002_000, 4096), content[1_002_000..1_006_096]);
+    assert_eq!(cache.stats().misses, 1);
+}
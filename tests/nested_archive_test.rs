@@ -0,0 +1,119 @@
+use assert_cmd::prelude::*;
+use blitzarch::api::Archive;
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+fn write_tree(dir: &Path, tag: &str) {
+    fs::create_dir_all(dir).unwrap();
+    fs::write(dir.join(format!("{tag}.log")), format!("{tag} line\n").repeat(20_000)).unwrap();
+    fs::write(dir.join("readme.txt"), format!("about {tag}")).unwrap();
+}
+
+/// `outer/` with a `.blz`, an archive without the extension and a plain file;
+/// `logs.blz` itself holds another archive, stored as nested.
+fn outer_tree(root: &Path) -> std::path::PathBuf {
+    let outer = root.join("outer");
+    fs::create_dir_all(&outer).unwrap();
+    write_tree(&root.join("deep"), "deep");
+    write_tree(&root.join("logs"), "logs");
+    Archive::create([root.join("deep")]).write_to(root.join("logs/deep.blz")).unwrap();
+    Archive::create([root.join("logs")]).store_nested(true).write_to(outer.join("logs.blz")).unwrap();
+    write_tree(&root.join("photos"), "photos");
+    Archive::create([root.join("photos")]).write_to(outer.join("photos.backup")).unwrap();
+    fs::write(outer.join("notes.txt"), "plain file").unwrap();
+    outer
+}
+
+#[test]
+fn nested_archives_are_stored_verbatim_and_unpacked_recursively() {
+    let dir = tempdir().unwrap();
+    let outer = outer_tree(dir.path());
+    // `.blz` по имени, но не архив: несколько raw-блоков, распаковке не подлежит
+    let mut x = 0x2545_F491_4F6C_DD1Du64;
+    let fake: Vec<u8> = (0..300_000).map(|_| {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        x as u8
+    }).collect();
+    fs::write(outer.join("fake.blz"), &fake).unwrap();
+    let arch = dir.path().join("all.blz");
+    Archive::create([&outer]).store_nested(true).write_to(&arch).unwrap();
+
+    // Без пересжатия: байты вложенного архива лежат в внешнем как есть
+    let inner = fs::read(outer.join("logs.blz")).unwrap();
+    let bytes = fs::read(&arch).unwrap();
+    assert!(bytes.windows(inner.len()).any(|w| w == inner));
+    assert!(katana::verify_archive(&arch, None, true).unwrap().corrupt.is_empty());
+
+    let out = dir.path().join("out");
+    katana::extract_katana_archive_internal(&arch, &out, &[], None, None).unwrap();
+    assert_eq!(fs::read(out.join("logs.blz")).unwrap(), inner);
+    fs::remove_dir_all(&out).unwrap();
+    let unpacked = katana::extract_katana_archive_recursive(&arch, &out, &[], None, None, None::<fn(blitzarch::progress::ProgressState)>, &Default::default()).unwrap();
+    assert_eq!(unpacked, 3);
+    assert!(!out.join("logs.blz").exists() && !out.join("photos.backup").exists());
+    assert_eq!(fs::read_to_string(out.join("logs/readme.txt")).unwrap(), "about logs");
+    assert_eq!(fs::read_to_string(out.join("logs/deep/readme.txt")).unwrap(), "about deep");
+    assert_eq!(fs::read_to_string(out.join("photos/readme.txt")).unwrap(), "about photos");
+    assert_eq!(fs::read_to_string(out.join("notes.txt")).unwrap(), "plain file");
+    assert_eq!(fs::read(out.join("fake.blz")).unwrap(), fake);
+}
+
+#[test]
+fn nested_archives_count_as_dense() {
+    let dir = tempdir().unwrap();
+    let outer = outer_tree(dir.path());
+    let options = KatanaCreateOptions { store_nested_archives: true, ..Default::default() };
+    let plan = katana_stream::estimate_create(&[outer], &dir.path().join("plan.blz"), 2, Some(3), false, &options).unwrap();
+    assert_eq!(plan.dense_files, 1); // logs.blz по расширению
+    assert!(plan.shards.len() >= 3, "{:?}", plan.shards);
+}
+
+#[test]
+fn cli_store_nested_and_recursive_extract() {
+    let dir = tempdir().unwrap();
+    let outer = outer_tree(dir.path());
+    let arch = dir.path().join("all.blz");
+    let out = dir.path().join("out");
+
+    let created = Command::cargo_bin("blitzarch").unwrap().args(["create", "--store-nested", "--output"]).arg(&arch).arg(&outer).output().unwrap();
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+    assert!(String::from_utf8_lossy(&created.stdout).contains("2 nested BlitzArch archives stored"));
+
+    let extracted = Command::cargo_bin("blitzarch").unwrap().args(["extract", "--recursive-extract", "--output"]).arg(&out).arg(&arch).output().unwrap();
+    assert!(extracted.status.success(), "{}", String::from_utf8_lossy(&extracted.stderr));
+    assert_eq!(fs::read_to_string(out.join("logs/deep/deep.log")).unwrap(), "deep line\n".repeat(20_000));
+    assert!(!out.join("logs/deep.blz").exists());
+
+    let classic = Command::cargo_bin("blitzarch").unwrap().args(["create", "--format", "classic", "--store-nested", "--output"]).arg(dir.path().join("c.blz")).arg(&outer).output().unwrap();
+    assert!(!classic.status.success());
+}
+
+#[test]
+fn only_archives_written_by_the_run_are_unpacked() {
+    let dir = tempdir().unwrap();
+    let outer = outer_tree(dir.path());
+    let arch = dir.path().join("all.blz");
+    Archive::create([&outer]).store_nested(true).write_to(&arch).unwrap();
+    // Стоит рядом, но в архиве без пометки: имя `.blz` не в счёт
+    let plain = dir.path().join("plain.blz");
+    Archive::create([&outer]).write_to(&plain).unwrap();
+
+    let out = dir.path().join("out");
+    let unpacked = katana::extract_katana_archive_recursive(&plain, &out, &[], None, None, None::<fn(blitzarch::progress::ProgressState)>, &Default::default()).unwrap();
+    assert_eq!(unpacked, 0);
+    assert!(out.join("logs.blz").is_file() && !out.join("logs").exists());
+    fs::remove_file(out.join("photos.backup")).unwrap();
+
+    // logs.blz already exists and is skipped: the user's file is neither unpacked nor removed
+    let skip = blitzarch::extract::ExtractOptions { conflict_policy: blitzarch::fsx::ConflictPolicy::Skip, ..Default::default() };
+    let result = katana::extract_katana_archive_recursive(&arch, &out, &[], None, None, None::<fn(blitzarch::progress::ProgressState)>, &skip);
+    assert_eq!(result.unwrap(), 1); // photos.backup only
+    assert!(out.join("logs.blz").is_file() && !out.join("logs").exists());
+    assert_eq!(fs::read_to_string(out.join("photos/readme.txt")).unwrap(), "about photos");
+}
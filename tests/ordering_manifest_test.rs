@@ -0,0 +1,51 @@
+use blitzarch::api::Archive;
+use blitzarch::katana_stream::ShardStrategy;
+use blitzarch::ordering::OrderingManifest;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn ordering_manifest_keeps_shard_assignment() {
+    let src = tempdir().unwrap();
+    for i in 0..8 {
+        fs::write(src.path().join(format!("f{i}.txt")), format!("file {i} ").repeat(400)).unwrap();
+    }
+    let dir = tempdir().unwrap();
+    let first = dir.path().join("first.json");
+    Archive::create([src.path()])
+        .threads(2)
+        .shard_strategy(ShardStrategy::SizeCapped(8 * 1024))
+        .export_ordering(&first)
+        .write_to(dir.path().join("1.blz"))
+        .unwrap();
+    let m1 = OrderingManifest::load(&first).unwrap();
+    assert!(m1.shards.len() > 1);
+    assert_eq!(m1.shards.iter().map(Vec::len).sum::<usize>(), 8);
+
+    // Один файл удалён, один добавлен: остальные шарды не сдвигаются
+    let removed = m1.shards[0][0].clone();
+    fs::remove_file(src.path().join(&removed)).unwrap();
+    fs::write(src.path().join("new.txt"), "new file").unwrap();
+    let second = dir.path().join("second.json");
+    let arch = dir.path().join("2.blz");
+    Archive::create([src.path()])
+        .threads(2)
+        .shard_strategy(ShardStrategy::SizeCapped(8 * 1024))
+        .ordering_manifest(m1.clone())
+        .export_ordering(&second)
+        .write_to(&arch)
+        .unwrap();
+    let m2 = OrderingManifest::load(&second).unwrap();
+    let mut expected: Vec<Vec<String>> =
+        m1.shards.iter().map(|s| s.iter().filter(|n| **n != removed).cloned().collect()).collect();
+    expected.retain(|s: &Vec<String>| !s.is_empty());
+    expected.push(vec!["new.txt".into()]);
+    assert_eq!(m2.shards, expected);
+
+    let names: Vec<String> = Archive::open(&arch).unwrap().entries().unwrap().into_iter().map(|e| e.path).collect();
+    assert_eq!(names.len(), 8);
+    assert!(names.contains(&"new.txt".to_string()) && !names.contains(&removed));
+
+    fs::write(&second, r#"{"version": 99, "shards": []}"#).unwrap();
+    assert!(OrderingManifest::load(&second).unwrap_err().to_string().contains("version 99"));
+}
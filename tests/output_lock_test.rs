@@ -0,0 +1,158 @@
+use blitzarch::fsx::{self, OutputLock};
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn create(src: &Path, arch: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    create_with(src, arch, &KatanaCreateOptions::default())
+}
+
+fn create_with(src: &Path, arch: &Path, options: &KatanaCreateOptions) -> Result<(), Box<dyn std::error::Error>> {
+    katana_stream::create_katana_archive_with_options(
+        &[src.to_path_buf()], arch, 2, 0, None, None, None, options,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+}
+
+/// PID of a process that has already exited.
+fn dead_pid() -> u32 {
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    let pid = child.id();
+    child.wait().unwrap();
+    pid
+}
+
+#[test]
+fn concurrent_writers_are_refused() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("locked.blz");
+
+    // Another process holding the lock: a separate handle with its own flock
+    let mut other = fs::File::create(fsx::lock_path(&arch)).unwrap();
+    writeln!(other, "4242").unwrap();
+    other.lock().unwrap();
+
+    let err = OutputLock::acquire(&arch).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+    assert!(err.to_string().contains("(pid 4242)"), "{err}");
+    let err = create(src.path(), &arch).unwrap_err().to_string();
+    assert!(err.contains("Another BlitzArch process"), "{err}");
+    assert!(!arch.exists());
+
+    drop(other);
+    fs::remove_file(fsx::lock_path(&arch)).unwrap();
+    create(src.path(), &arch).unwrap();
+    assert!(arch.exists());
+    assert!(!fsx::lock_path(&arch).exists(), "lock file removed after create");
+
+    // Nested locks in one thread are fine and release together
+    let outer = OutputLock::acquire(&arch).unwrap();
+    let inner = OutputLock::acquire(&arch).unwrap();
+    drop(inner);
+    assert!(fsx::lock_path(&arch).exists());
+
+    // Another job of this process (another thread) does not nest
+    let target = arch.clone();
+    let err = std::thread::spawn(move || OutputLock::acquire(&target).unwrap_err()).join().unwrap();
+    assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+    drop(outer);
+    assert!(!fsx::lock_path(&arch).exists());
+
+    // Released out of order, the lock stays until its last guard is gone
+    let outer = OutputLock::acquire(&arch).unwrap();
+    let inner = OutputLock::acquire(&arch).unwrap();
+    drop(outer);
+    assert!(fsx::lock_path(&arch).exists());
+    let target = arch.clone();
+    assert!(std::thread::spawn(move || OutputLock::acquire(&target).is_err()).join().unwrap());
+    drop(inner);
+    assert!(!fsx::lock_path(&arch).exists());
+}
+
+#[test]
+fn archive_inside_source_skips_its_lock_file() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    let arch = src.path().join("self.blz");
+    create(src.path(), &arch).unwrap();
+    let names: Vec<String> = blitzarch::katana::list_entries(&arch, None).unwrap().into_iter().map(|e| e.path).collect();
+    assert!(names.iter().all(|n| !n.ends_with(".lock")), "{names:?}");
+}
+
+#[test]
+fn stale_locks_are_explained_and_broken_with_force() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("stale.blz");
+    let force = KatanaCreateOptions { force: true, ..Default::default() };
+
+    // Holder still has the lock, but the recorded owner is gone (leftover child of a crashed run)
+    let pid = dead_pid();
+    let mut other = fs::File::create(fsx::lock_path(&arch)).unwrap();
+    writeln!(other, "{} {}", pid, chrono::Utc::now().timestamp() - 3 * 3600).unwrap();
+    other.lock().unwrap();
+    let owner = fsx::LockOwner::read(&fsx::lock_path(&arch)).unwrap();
+    assert_eq!(owner.pid, pid);
+    assert!(!owner.is_running());
+
+    let err = create(src.path(), &arch).unwrap_err().to_string();
+    assert!(err.contains(&format!("(pid {pid})")) && err.contains("3h ago"), "{err}");
+    assert!(err.contains("looks stale") && err.contains("--force"), "{err}");
+    create_with(src.path(), &arch, &force).unwrap();
+    assert!(arch.exists());
+    drop(other);
+
+    // A live owner is never broken
+    let mut live = fs::File::create(fsx::lock_path(&arch)).unwrap();
+    writeln!(live, "{}", std::process::id()).unwrap();
+    live.lock().unwrap();
+    let err = OutputLock::acquire_breaking_stale(&arch).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+    assert!(!err.to_string().contains("looks stale"), "{err}");
+    drop(live);
+
+    // A holder that has just taken the flock records its PID a moment later:
+    // the dead PID it replaces is no reason to break the lock
+    let mut fresh = fs::File::create(fsx::lock_path(&arch)).unwrap();
+    writeln!(fresh, "{}", dead_pid()).unwrap();
+    fresh.lock().unwrap();
+    let recorder = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        fresh.set_len(0).unwrap();
+        std::io::Seek::rewind(&mut fresh).unwrap();
+        writeln!(fresh, "{}", std::process::id()).unwrap();
+        fresh
+    });
+    let err = OutputLock::acquire_breaking_stale(&arch).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ResourceBusy);
+    drop(recorder.join().unwrap());
+
+    // Unlocked lock file of a crashed run: taken over silently
+    fs::write(fsx::lock_path(&arch), format!("{}\n", dead_pid())).unwrap();
+    create(src.path(), &arch).unwrap();
+    assert!(!fsx::lock_path(&arch).exists());
+}
+
+#[test]
+fn partial_output_is_refused_and_discarded_with_force() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("out.blz");
+
+    // Прерванный запуск оставил шарды без индекса: без --force – ошибка, выход не тронут
+    fs::write(&arch, vec![0x5A; 5000]).unwrap();
+    let err = create(src.path(), &arch).unwrap_err().to_string();
+    assert!(err.contains("5000 bytes that are not a complete archive"), "{err}");
+    assert!(err.contains("--force"), "{err}");
+    assert_eq!(fs::read(&arch).unwrap(), vec![0x5A; 5000]);
+
+    create_with(src.path(), &arch, &KatanaCreateOptions { force: true, ..Default::default() }).unwrap();
+    assert!(!fs::read(&arch).unwrap().starts_with(&[0x5A; 16]), "dead prefix dropped");
+    assert_eq!(blitzarch::katana::list_entries(&arch, None).unwrap().len(), 1);
+}
@@ -0,0 +1,155 @@
+use blitzarch::api::Archive;
+use blitzarch::fsx::PathFilter;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn tree() -> tempfile::TempDir {
+    let src = tempdir().unwrap();
+    for (name, data) in [
+        ("src/main.rs", "fn main() {}"),
+        ("src/scratch.tmp", "junk"),
+        ("target/debug/app.o", "object"),
+        ("docs/target/readme.md", "nested target"),
+        ("notes.md", "notes"),
+    ] {
+        let path = src.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+    src
+}
+
+fn extracted_names(out: &Path) -> Vec<String> {
+    let mut names: Vec<String> = walkdir::WalkDir::new(out)
+        .into_iter()
+        .map(|e| e.unwrap())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().strip_prefix(out).unwrap().to_string_lossy().replace('\\', "/"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn filter_patterns_match_relative_paths() {
+    let filter = PathFilter::new(&[], &["target/**".into(), "*.tmp".into(), "/docs".into()]).unwrap();
+    assert!(!filter.allows(Path::new("target/debug/app.o")));
+    assert!(!filter.allows(Path::new("sub/target/x")), "unanchored patterns match at any depth");
+    assert!(!filter.allows(Path::new("src/scratch.tmp")));
+    assert!(!filter.allows(Path::new("docs/readme.md")), "a matching directory covers its contents");
+    assert!(filter.allows(Path::new("sub/docs/readme.md")), "a leading / anchors the pattern");
+    assert!(filter.allows(Path::new("src/main.rs")));
+
+    let only_rs = PathFilter::new(&["*.rs".into()], &["src/skip.rs".into()]).unwrap();
+    assert!(only_rs.allows(Path::new("src/main.rs")));
+    assert!(!only_rs.allows(Path::new("notes.md")));
+    assert!(!only_rs.allows(Path::new("src/skip.rs")), "excludes win");
+    assert!(PathFilter::new(&[], &["[".into()]).is_err());
+}
+
+#[test]
+fn katana_create_honours_include_and_exclude() {
+    let src = tree();
+    let arch_dir = tempdir().unwrap();
+    let cases: [(&[&str], &[&str], &[&str]); 3] = [
+        (&[], &["target", "*.tmp"], &["notes.md", "src/main.rs"]),
+        (&[], &["/target/"], &["docs/target/readme.md", "notes.md", "src/main.rs", "src/scratch.tmp"]),
+        (&["src", "*.md"], &["*.tmp"], &["docs/target/readme.md", "notes.md", "src/main.rs"]),
+    ];
+    for (i, (include, exclude, expected)) in cases.into_iter().enumerate() {
+        let arch = arch_dir.path().join(format!("{i}.blz"));
+        let mut builder = Archive::create([src.path()]).threads(2);
+        for p in include {
+            builder = builder.include(*p);
+        }
+        for p in exclude {
+            builder = builder.exclude(*p);
+        }
+        builder.write_to(&arch).unwrap();
+        let out = tempdir().unwrap();
+        blitzarch::katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+        assert_eq!(extracted_names(out.path()), expected, "case {i}");
+    }
+}
+
+#[test]
+fn classic_create_honours_exclude() {
+    use blitzarch::cli::{Args, WorkerMode};
+    use clap::Parser;
+
+    let src = tree();
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("classic.blz");
+    let args = Args::try_parse_from([
+        "blitzarch".as_ref(), "create".as_ref(), "--format".as_ref(), "classic".as_ref(),
+        "--exclude".as_ref(), "target".as_ref(), "--exclude".as_ref(), "*.tmp".as_ref(),
+        "-o".as_ref(), arch.as_os_str(), src.path().as_os_str(),
+    ])
+    .unwrap();
+    blitzarch::workers::run_parallel_compression_with_progress(
+        std::sync::Arc::new(args.command),
+        WorkerMode::W2,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    let out = tempdir().unwrap();
+    blitzarch::extract::extract_files(&arch, &[], None, Some(out.path()), None).unwrap();
+    assert_eq!(extracted_names(out.path()), ["notes.md", "src/main.rs"]);
+}
+
+#[test]
+fn respect_gitignore_follows_nested_ignore_files() {
+    let src = tree();
+    fs::write(src.path().join(".gitignore"), "target/\n*.tmp\n").unwrap();
+    // Глубже лежащий файл перекрывает корневой, `!` возвращает файл
+    fs::write(src.path().join("src/.blitzignore"), "!scratch.tmp\n").unwrap();
+    fs::write(src.path().join("docs/.gitignore"), "*.md\n").unwrap();
+    let arch_dir = tempdir().unwrap();
+
+    let arch = arch_dir.path().join("ignored.blz");
+    Archive::create([src.path()]).threads(2).respect_gitignore(true).write_to(&arch).unwrap();
+    let out = tempdir().unwrap();
+    blitzarch::katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+    assert_eq!(
+        extracted_names(out.path()),
+        [".gitignore", "docs/.gitignore", "notes.md", "src/.blitzignore", "src/main.rs", "src/scratch.tmp"]
+    );
+
+    // Без флага ignore-файлы – обычные файлы
+    let arch = arch_dir.path().join("all.blz");
+    Archive::create([src.path()]).threads(2).write_to(&arch).unwrap();
+    let out = tempdir().unwrap();
+    blitzarch::katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+    assert_eq!(extracted_names(out.path()).len(), 8);
+
+    let filter = PathFilter::default().respect_ignore_files(true);
+    assert!(filter.ignored(src.path(), &src.path().join("target"), true));
+    assert!(filter.ignored(src.path(), &src.path().join("docs/target/readme.md"), false), "like git, target/ matches at any depth");
+    assert!(!filter.ignored(src.path(), &src.path().join("notes.md"), false));
+    assert!(!filter.ignored(src.path(), &src.path().join("src/scratch.tmp"), false));
+}
+
+#[test]
+fn ignored_and_excluded_directories_are_not_walked() {
+    use blitzarch::vfs::{OsFs, Vfs};
+    let src = tree();
+    fs::write(src.path().join(".gitignore"), "target/\n").unwrap();
+    fs::create_dir_all(src.path().join("target/debug/deps")).unwrap();
+    fs::write(src.path().join("target/debug/deps/lib.rlib"), "rlib").unwrap();
+    let filter = PathFilter::new(&[], &["docs".to_string()]).unwrap().respect_ignore_files(true);
+
+    let visited = std::sync::Mutex::new(Vec::new());
+    let files = OsFs::default()
+        .list_files_pruned(src.path(), &|dir| {
+            visited.lock().unwrap().push(dir.to_path_buf());
+            filter.prunes(src.path(), dir)
+        })
+        .unwrap();
+    let visited = visited.into_inner().unwrap();
+    assert!(visited.contains(&src.path().join("target")) && visited.contains(&src.path().join("docs")));
+    // Ни одного каталога внутри отброшенных
+    assert!(!visited.iter().any(|d| d.starts_with(src.path().join("target/debug")) || d.starts_with(src.path().join("docs/target"))));
+    assert!(!files.iter().any(|f| f.starts_with(src.path().join("target")) || f.starts_with(src.path().join("docs"))));
+    assert!(files.contains(&src.path().join("src/main.rs")));
+}
@@ -0,0 +1,109 @@
+use assert_cmd::prelude::*;
+use blitzarch::fsx::OutputTransaction;
+use blitzarch::katana_stream::{create_per_input_archives, KatanaCreateOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::tempdir;
+
+fn project(root: &Path, name: &str) -> PathBuf {
+    let dir = root.join(name);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("src/main.rs"), format!("fn main() {{ println!(\"{name}\"); }}\n").repeat(50)).unwrap();
+    fs::write(dir.join("README"), name).unwrap();
+    dir
+}
+
+/// Leftover `.blitzarch-txn-*` staging directories in `dir`.
+fn staging_dirs(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.file_name().unwrap().to_string_lossy().starts_with(".blitzarch-txn-"))
+        .collect()
+}
+
+#[test]
+fn writes_one_archive_per_input() {
+    let dir = tempdir().unwrap();
+    let inputs = vec![project(dir.path(), "alpha"), project(dir.path(), "beta")];
+    let out = dir.path().join("archives");
+
+    let written = create_per_input_archives(&inputs, &out, 2, 1, None, None, None, &KatanaCreateOptions::default(), true).unwrap();
+    assert_eq!(written, [out.join("alpha.blz"), out.join("beta.blz")]);
+    let restored = dir.path().join("restored");
+    blitzarch::extract::extract_files(&out.join("beta.blz"), &[], None, Some(&restored), None).unwrap();
+    assert_eq!(fs::read_to_string(restored.join("README")).unwrap(), "beta");
+    assert!(staging_dirs(&out).is_empty());
+}
+
+#[test]
+fn failed_input_publishes_nothing() {
+    let dir = tempdir().unwrap();
+    let out = dir.path().join("archives");
+    fs::create_dir_all(&out).unwrap();
+    fs::write(out.join("alpha.blz"), "last night's archive").unwrap();
+    let inputs = vec![project(dir.path(), "alpha"), dir.path().join("missing"), project(dir.path(), "gamma")];
+
+    let err = create_per_input_archives(&inputs, &out, 2, 1, None, None, None, &KatanaCreateOptions::default(), false).unwrap_err();
+    assert!(err.to_string().contains("no archives were written"), "{err}");
+    assert_eq!(fs::read_to_string(out.join("alpha.blz")).unwrap(), "last night's archive");
+    assert!(!out.join("gamma.blz").exists());
+    assert!(staging_dirs(&out).is_empty());
+
+    // Одинаковые имена архивов – ошибка до начала работы
+    let clash = vec![project(&dir.path().join("a"), "same"), project(&dir.path().join("b"), "same")];
+    let err = create_per_input_archives(&clash, &out, 2, 1, None, None, None, &KatanaCreateOptions::default(), false).unwrap_err();
+    assert!(err.to_string().contains("same.blz"), "{err}");
+}
+
+#[test]
+fn transaction_rolls_back_when_a_rename_fails() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("one.blz"), "old one").unwrap();
+    let mut txn = OutputTransaction::new(dir.path()).unwrap();
+    fs::write(txn.stage(&dir.path().join("one.blz")).unwrap(), "new one").unwrap();
+    // Второй выход так и не был записан – переименовать нечего
+    txn.stage(&dir.path().join("two.blz")).unwrap();
+
+    assert!(txn.commit().is_err());
+    assert_eq!(fs::read_to_string(dir.path().join("one.blz")).unwrap(), "old one");
+    assert!(!dir.path().join("two.blz").exists());
+    assert!(staging_dirs(dir.path()).is_empty());
+    assert!(!dir.path().join("one.blz.lock").exists());
+}
+
+#[test]
+fn cli_per_input() {
+    let dir = tempdir().unwrap();
+    let alpha = project(dir.path(), "alpha");
+    let beta = project(dir.path(), "beta");
+    let out = dir.path().join("archives");
+
+    let created = Command::cargo_bin("blitzarch").unwrap().args(["create", "--per-input", "--output"]).arg(&out).arg(&alpha).arg(&beta).output().unwrap();
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+    assert!(out.join("alpha.blz").exists() && out.join("beta.blz").exists());
+
+    let classic = Command::cargo_bin("blitzarch").unwrap().args(["create", "--format", "classic", "--per-input", "--output"]).arg(dir.path().join("c")).arg(&alpha).output().unwrap();
+    assert!(!classic.status.success());
+    assert!(String::from_utf8_lossy(&classic.stderr).contains("--per-input"));
+}
+
+#[test]
+fn interrupted_commit_is_rolled_back_by_the_next_transaction() {
+    let dir = tempdir().unwrap();
+    // A commit that died after moving one.blz aside and publishing its replacement
+    let stale = dir.path().join(".blitzarch-txn-crashed");
+    fs::create_dir_all(&stale).unwrap();
+    fs::write(stale.join("backup-0"), "old one").unwrap();
+    fs::write(dir.path().join("one.blz"), "new one").unwrap();
+    let target = dir.path().join("one.blz").into_os_string().into_string().unwrap();
+    let backup = stale.join("backup-0").into_os_string().into_string().unwrap();
+    fs::write(stale.join("commit.journal"), format!("f\0{target}\0{backup}\0")).unwrap();
+
+    let txn = OutputTransaction::new(dir.path()).unwrap();
+    assert_eq!(fs::read_to_string(dir.path().join("one.blz")).unwrap(), "old one");
+    assert!(!stale.exists());
+    drop(txn);
+    assert!(staging_dirs(dir.path()).is_empty());
+}
@@ -0,0 +1,63 @@
+use assert_cmd::prelude::*;
+use blitzarch::api::Archive;
+use blitzarch::private_logs;
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+#[test]
+fn redacts_paths_and_quoted_names() {
+    private_logs::set_enabled(true);
+    let tag = private_logs::path("hr/salaries.xlsx").into_owned();
+    assert!(tag.starts_with("<name:") && tag.len() == "<name:>".len() + 12, "{tag}");
+    assert_eq!(private_logs::path("hr/salaries.xlsx"), tag);
+    // Keyed: a dictionary of plain hashes does not reveal the name
+    let unkeyed = blake3::hash(b"hr/salaries.xlsx").to_hex();
+    assert_ne!(tag, format!("<name:{}>", &unkeyed[..12]));
+
+    let message = private_logs::message("Cannot restore permissions of \"out/hr/salaries.xlsx\": denied", "hr/salaries.xlsx");
+    assert!(!message.contains("salaries"), "{message}");
+    assert!(message.ends_with(": denied"));
+    let message = private_logs::message("Case collision: hr/salaries.xlsx overwrites an entry", "hr/salaries.xlsx");
+    assert_eq!(message, format!("Case collision: {tag} overwrites an entry"));
+}
+
+#[test]
+fn cli_private_logs_hide_names_in_warnings() {
+    let src = tempdir().unwrap();
+    fs::create_dir_all(src.path().join("plans")).unwrap();
+    fs::write(src.path().join("plans/merger-with-acme.txt"), "confidential").unwrap();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("p.blz");
+    Archive::create([src.path()]).password("pw").hide_names(true).write_to(&arch).unwrap();
+
+    // Каталог на месте файла – предупреждение с именем записи
+    let out = dir.path().join("out");
+    fs::create_dir_all(out.join("plans/merger-with-acme.txt")).unwrap();
+    let run = |private: bool| {
+        let mut cmd = Command::cargo_bin("blitzarch").unwrap();
+        cmd.env("BLITZARCH_PASSWORD", "pw").env_remove("BLITZARCH_PRIVATE_LOGS").args(["extract", "--json", "--output"]).arg(&out).arg(&arch);
+        if private {
+            cmd.arg("--private-logs");
+        }
+        cmd.output().unwrap()
+    };
+
+    let plain = run(false);
+    assert!(plain.status.success(), "{}", String::from_utf8_lossy(&plain.stderr));
+    assert!(String::from_utf8_lossy(&plain.stdout).contains("merger-with-acme"));
+
+    let private = run(true);
+    assert!(private.status.success(), "{}", String::from_utf8_lossy(&private.stderr));
+    let stdout = String::from_utf8_lossy(&private.stdout);
+    let stderr = String::from_utf8_lossy(&private.stderr);
+    assert!(!stdout.contains("merger") && !stderr.contains("merger"), "{stdout}\n{stderr}");
+    let report: serde_json::Value = serde_json::from_slice(&private.stdout).unwrap();
+    let warning = &report["warnings"][0];
+    assert_eq!(warning["path"], private_logs_tag("plans/merger-with-acme.txt"));
+}
+
+fn private_logs_tag(path: &str) -> String {
+    private_logs::set_enabled(true);
+    private_logs::path(path).into_owned()
+}
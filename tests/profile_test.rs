@@ -0,0 +1,108 @@
+use assert_cmd::Command;
+use blitzarch::cli::{self, Args};
+use clap::CommandFactory;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn profile_keys_become_create_options() {
+    let profile: toml::Table = toml::from_str(
+        "level = 7\nthreads = 0\nencrypt = true\nadaptive = false\nxattrs = false\n\
+         exclude = [\"target/**\", \"*.tmp\"]\nio-mode = \"direct\"\n",
+    )
+    .unwrap();
+    let cmd = Args::command();
+    let create = cmd.find_subcommand("create").unwrap();
+    let mut args = cli::profile_args(create, "backup", &profile).unwrap();
+    args.sort();
+    assert_eq!(
+        args,
+        ["--encrypt", "--exclude=*.tmp", "--exclude=target/**", "--io-mode=direct", "--level=7", "--no-adaptive", "--threads=0"]
+    );
+
+    for bad in ["no_such_flag = 1", "encrypt = \"yes\"", "profile = \"other\"", "level = [[1]]"] {
+        let profile: toml::Table = toml::from_str(bad).unwrap();
+        assert!(cli::profile_args(create, "backup", &profile).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn command_line_overrides_profile() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("keep.txt"), b"keep").unwrap();
+    fs::write(src.join("junk.tmp"), b"junk").unwrap();
+    let config = dir.path().join("config.toml");
+    fs::write(&config, "[profile.backup]\nlevel = 7\nencrypt = true\nexclude = [\"*.tmp\"]\nformat = \"classic\"\n").unwrap();
+    let archive = dir.path().join("backup.blz");
+
+    let create = |extra: &[&str]| {
+        let mut cmd = Command::cargo_bin("blitzarch").unwrap();
+        cmd.env("BLITZARCH_CONFIG", &config)
+            .env_remove("BLITZARCH_PASSWORD")
+            .args(["create", "--profile", "backup", "--skip-check"])
+            .args(extra)
+            .arg("-o")
+            .arg(&archive)
+            .arg(&src);
+        cmd
+    };
+    // encrypt = true with no password
+    create(&[]).assert().failure().stderr(predicates::str::contains("--encrypt needs a password"));
+    // --format on the command line wins over the profile's classic
+    create(&["--password", "pw", "--format", "katana", "--level", "1"]).assert().success();
+    assert!(blitzarch::katana::is_katana_archive(&archive).unwrap());
+
+    let listing = Command::cargo_bin("blitzarch")
+        .unwrap()
+        .args(["list", "--password", "pw"])
+        .arg(&archive)
+        .output()
+        .unwrap();
+    let listing = String::from_utf8_lossy(&listing.stdout);
+    assert!(listing.contains("keep.txt") && !listing.contains("junk.tmp"), "{listing}");
+
+    // --no-encrypt turns the profile's flag off
+    fs::remove_file(&archive).unwrap();
+    create(&["--no-encrypt", "--format", "katana"]).assert().success();
+    assert!(!blitzarch::katana::open_lazy(&archive).unwrap().is_encrypted());
+
+    Command::cargo_bin("blitzarch")
+        .unwrap()
+        .env("BLITZARCH_CONFIG", &config)
+        .args(["create", "--profile", "missing", "-o"])
+        .arg(&archive)
+        .arg(&src)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn command_line_wins_over_conflicting_and_global_profile_keys() {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("page.html"), b"<html>hello</html>".repeat(50)).unwrap();
+    let config = dir.path().join("config.toml");
+    fs::write(&config, "[profile.web]\nformat = \"classic\"\nuse_lzma2 = true\nprivate_logs = true\n").unwrap();
+    let archive = dir.path().join("web.blz");
+
+    // use_lzma2 conflicts with --codec: the command line's codec is used
+    Command::cargo_bin("blitzarch")
+        .unwrap()
+        .env("BLITZARCH_CONFIG", &config)
+        .args(["create", "--profile", "web", "--skip-check", "--codec", "brotli", "-o"])
+        .arg(&archive)
+        .arg(&src)
+        .assert()
+        .success();
+    assert!(archive.exists());
+
+    // Global flags can be profile keys too
+    let profile: toml::Table = toml::from_str("private_logs = true").unwrap();
+    let mut cmd = Args::command();
+    cmd.build();
+    let create = cmd.find_subcommand("create").unwrap();
+    assert_eq!(cli::profile_args(create, "web", &profile).unwrap(), ["--private-logs"]);
+}
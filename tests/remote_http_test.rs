@@ -0,0 +1,154 @@
+#![cfg(feature = "remote")]
+
+use assert_cmd::prelude::*;
+use rand::{RngCore, SeedableRng};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+/// Static file server for `/archive.blz`; `ranges: false` answers every GET
+/// with the whole file, like a server without range support.
+fn serve_file(body: Vec<u8>, ranges: bool) -> (String, Arc<AtomicU64>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/archive.blz", listener.local_addr().unwrap());
+    let served = Arc::new(AtomicU64::new(0));
+    let counter = Arc::clone(&served);
+    let body = Arc::new(body);
+    std::thread::spawn(move || {
+        for conn in listener.incoming() {
+            let (body, counter) = (Arc::clone(&body), Arc::clone(&counter));
+            std::thread::spawn(move || serve(conn.unwrap(), &body, ranges, &counter));
+        }
+    });
+    (url, served)
+}
+
+fn serve(stream: TcpStream, body: &[u8], ranges: bool, served: &AtomicU64) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let path = line.split_whitespace().nth(1).unwrap().to_string();
+        let mut range = None;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header.split_once(':').unwrap();
+            if name.eq_ignore_ascii_case("range") {
+                let (start, end) = value.trim().strip_prefix("bytes=").unwrap().split_once('-').unwrap();
+                range = Some((start.parse::<usize>().unwrap(), end.parse::<usize>().unwrap()));
+            }
+        }
+        let (head, slice) = match (path.as_str(), range) {
+            ("/archive.blz", Some((start, end))) if ranges => (
+                format!("HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{end}/{}\r\n", body.len()),
+                &body[start..=end],
+            ),
+            ("/archive.blz", _) => ("HTTP/1.1 200 OK\r\n".to_string(), body),
+            _ => ("HTTP/1.1 404 Not Found\r\n".to_string(), &[][..]),
+        };
+        served.fetch_add(slice.len() as u64, Ordering::SeqCst);
+        // Клиент вправе закрыть соединение, не дочитав ответ 200
+        let reply = format!("{head}Content-Length: {}\r\n\r\n", slice.len());
+        if writer.write_all(reply.as_bytes()).and_then(|_| writer.write_all(slice)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Archive with a small text file and 8 MiB of noise, one shard per file.
+fn hosted_archive() -> (tempfile::TempDir, Vec<u8>, Vec<u8>) {
+    let dir = tempdir().unwrap();
+    let src = dir.path().join("src");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::write(src.join("docs/notes.txt"), "meeting notes\n".repeat(100)).unwrap();
+    let mut noise = vec![0u8; 8 * 1024 * 1024];
+    rand::rngs::StdRng::seed_from_u64(11).fill_bytes(&mut noise);
+    fs::write(src.join("noise.bin"), &noise).unwrap();
+    let archive = dir.path().join("archive.blz");
+    let created = Command::cargo_bin("blitzarch")
+        .unwrap()
+        .args(["create", "--shard-strategy", "size-capped:1", "--comment", "hosted", "--output"])
+        .arg(&archive)
+        .arg(&src)
+        .output()
+        .unwrap();
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+    let bytes = fs::read(&archive).unwrap();
+    (dir, bytes, noise)
+}
+
+#[test]
+fn browses_a_hosted_archive_with_range_requests() {
+    let (_dir, bytes, noise) = hosted_archive();
+    let (url, served) = serve_file(bytes.clone(), true);
+
+    let archive = blitzarch::katana::open_remote(&url).unwrap();
+    let mut names: Vec<String> = archive.entries().into_iter().filter(|e| !e.is_dir).map(|e| e.path).collect();
+    names.sort();
+    assert_eq!(names, ["docs/notes.txt", "noise.bin"]);
+    assert_eq!(archive.metadata(None).unwrap()["comment"], "hosted");
+    assert!(served.load(Ordering::SeqCst) < bytes.len() as u64 / 4);
+
+    let mut notes = String::new();
+    archive.open_entry("docs/notes.txt", None).unwrap().read_to_string(&mut notes).unwrap();
+    assert_eq!(notes, "meeting notes\n".repeat(100));
+    let after_one = served.load(Ordering::SeqCst);
+    assert!(after_one < bytes.len() as u64 / 4, "fetched {after_one} of {} bytes", bytes.len());
+    // Уже скачанный шард повторно не запрашивается
+    assert_eq!(archive.fetch(&["docs/notes.txt".into()], None).unwrap(), 0);
+    // Выбор, которому ничего не соответствует, ничего и не качает
+    assert_eq!(archive.fetch(&["nosuchfile".into()], None).unwrap(), 0);
+    assert_eq!(served.load(Ordering::SeqCst), after_one);
+
+    let out = tempdir().unwrap();
+    archive.extract(&[], None, out.path(), None).unwrap();
+    assert_eq!(fs::read(out.path().join("noise.bin")).unwrap(), noise);
+    assert!(served.load(Ordering::SeqCst) < after_one + bytes.len() as u64);
+}
+
+#[test]
+fn cli_lists_and_extracts_urls() {
+    let (_dir, bytes, _) = hosted_archive();
+    let (url, served) = serve_file(bytes.clone(), true);
+
+    let list = Command::cargo_bin("blitzarch").unwrap().args(["list", "--json", &url]).output().unwrap();
+    assert!(list.status.success(), "{}", String::from_utf8_lossy(&list.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&list.stdout).unwrap();
+    assert_eq!(report["archive"], url.as_str());
+    assert_eq!(report["metadata"]["comment"], "hosted");
+
+    let out = tempdir().unwrap();
+    let extract = Command::cargo_bin("blitzarch").unwrap().args(["extract", &url, "docs/notes.txt", "--output"]).arg(out.path()).output().unwrap();
+    assert!(extract.status.success(), "{}", String::from_utf8_lossy(&extract.stderr));
+    assert_eq!(fs::read_to_string(out.path().join("docs/notes.txt")).unwrap(), "meeting notes\n".repeat(100));
+    assert!(!out.path().join("noise.bin").exists());
+    assert!(served.load(Ordering::SeqCst) < bytes.len() as u64 / 2);
+
+    let create = Command::cargo_bin("blitzarch").unwrap().args(["create", "--output", &url]).arg(out.path()).output().unwrap();
+    assert!(!create.status.success());
+    assert!(String::from_utf8_lossy(&create.stderr).contains("s3://"));
+}
+
+#[test]
+fn server_without_range_support_is_refused() {
+    let (_dir, bytes, _) = hosted_archive();
+    let (url, _) = serve_file(bytes, false);
+    let err = blitzarch::katana::open_remote(&url).err().unwrap().to_string();
+    assert!(err.contains("does not support range requests"), "{err}");
+
+    let missing = url.replace("archive.blz", "missing.blz");
+    let err = blitzarch::katana::open_remote(&missing).err().unwrap().to_string();
+    assert!(err.contains("404"), "{err}");
+}
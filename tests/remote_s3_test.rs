@@ -0,0 +1,231 @@
+#![cfg(feature = "remote")]
+
+use assert_cmd::prelude::*;
+use rand::{RngCore, SeedableRng};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+/// In-memory S3: multipart uploads, HEAD and ranged GETs, path-style.
+#[derive(Default)]
+struct Store {
+    objects: HashMap<String, Vec<u8>>,
+    uploads: HashMap<String, BTreeMap<u32, Vec<u8>>>,
+    parts_received: usize,
+    /// Bytes served by GETs.
+    served: u64,
+    aborted: usize,
+    /// Answer CompleteMultipartUpload with 200 and an `<Error>` body.
+    fail_complete: bool,
+}
+
+struct Reply {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+    /// Content-Length of a HEAD reply (which has no body).
+    head_len: Option<usize>,
+}
+
+impl Reply {
+    fn new(status: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Reply { status, headers: Vec::new(), body: body.into(), head_len: None }
+    }
+}
+
+fn start_fake_s3() -> (String, Arc<Mutex<Store>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let store = Arc::new(Mutex::new(Store::default()));
+    let shared = Arc::clone(&store);
+    std::thread::spawn(move || {
+        for conn in listener.incoming() {
+            let store = Arc::clone(&shared);
+            std::thread::spawn(move || serve(conn.unwrap(), &store));
+        }
+    });
+    (url, store)
+}
+
+fn serve(stream: TcpStream, store: &Mutex<Store>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut words = line.split_whitespace();
+        let (method, target) = (words.next().unwrap().to_string(), words.next().unwrap().to_string());
+        let mut headers = HashMap::new();
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header.split_once(':').unwrap();
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+        let len = headers.get("content-length").map_or(0, |v| v.parse().unwrap());
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).unwrap();
+
+        let reply = handle(&method, &target, &headers, &body, &mut store.lock().unwrap());
+        let mut head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n", reply.status, reply.head_len.unwrap_or(reply.body.len()));
+        for (name, value) in &reply.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes()).unwrap();
+        writer.write_all(&reply.body).unwrap();
+    }
+}
+
+fn handle(method: &str, target: &str, headers: &HashMap<String, String>, body: &[u8], store: &mut Store) -> Reply {
+    let signed = headers.get("authorization").is_some_and(|a| a.starts_with("AWS4-HMAC-SHA256 Credential=test-key/"))
+        && headers.get("x-amz-content-sha256").is_some_and(|h| *h == format!("{:x}", Sha256::digest(body)));
+    if !signed {
+        return Reply::new("403 Forbidden", "<Error><Code>SignatureDoesNotMatch</Code></Error>");
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query: HashMap<&str, &str> = query.split('&').filter_map(|kv| kv.split_once('=')).collect();
+    let path = path.to_string();
+    match (method, query.get("uploadId")) {
+        ("POST", None) if query.contains_key("uploads") => {
+            let id = format!("upload-{}", store.uploads.len() + store.aborted + store.objects.len());
+            store.uploads.insert(id.clone(), BTreeMap::new());
+            Reply::new("200 OK", format!("<InitiateMultipartUploadResult><UploadId>{id}</UploadId></InitiateMultipartUploadResult>"))
+        }
+        ("PUT", Some(id)) => {
+            let number: u32 = query["partNumber"].parse().unwrap();
+            store.uploads.get_mut(*id).unwrap().insert(number, body.to_vec());
+            store.parts_received += 1;
+            let mut reply = Reply::new("200 OK", "");
+            reply.headers.push(("ETag", format!("\"etag-{number}\"")));
+            reply
+        }
+        ("POST", Some(id)) => {
+            if store.fail_complete {
+                return Reply::new("200 OK", "<Error><Code>InternalError</Code><Message>try again</Message></Error>");
+            }
+            let parts = store.uploads.remove(*id).unwrap();
+            let xml = String::from_utf8_lossy(body);
+            let mut object = Vec::new();
+            for piece in xml.split("<PartNumber>").skip(1) {
+                let number: u32 = piece.split('<').next().unwrap().parse().unwrap();
+                object.extend_from_slice(&parts[&number]);
+            }
+            store.objects.insert(path, object);
+            Reply::new("200 OK", "<CompleteMultipartUploadResult/>")
+        }
+        ("DELETE", Some(id)) => {
+            store.uploads.remove(*id);
+            store.aborted += 1;
+            Reply::new("204 No Content", "")
+        }
+        ("HEAD", None) => match store.objects.get(&path) {
+            Some(object) => Reply { head_len: Some(object.len()), ..Reply::new("200 OK", "") },
+            None => Reply { head_len: Some(0), ..Reply::new("404 Not Found", "") },
+        },
+        ("GET", None) => {
+            let Some(object) = store.objects.get(&path) else {
+                return Reply::new("404 Not Found", "<Error><Code>NoSuchKey</Code></Error>");
+            };
+            let range = headers["range"].strip_prefix("bytes=").unwrap();
+            let (start, end) = range.split_once('-').unwrap();
+            let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+            let slice = object[start..=end].to_vec();
+            store.served += slice.len() as u64;
+            Reply::new("206 Partial Content", slice)
+        }
+        _ => Reply::new("400 Bad Request", "<Error><Code>InvalidRequest</Code></Error>"),
+    }
+}
+
+fn blitzarch(endpoint: &str) -> Command {
+    let mut cmd = Command::cargo_bin("blitzarch").unwrap();
+    cmd.env("AWS_ENDPOINT_URL", endpoint)
+        .env("AWS_ACCESS_KEY_ID", "test-key")
+        .env("AWS_SECRET_ACCESS_KEY", "test-secret")
+        .env("AWS_REGION", "eu-central-1")
+        .env("RAYON_NUM_THREADS", "4");
+    cmd
+}
+
+#[test]
+fn streams_to_s3_and_fetches_only_needed_shards() {
+    let (endpoint, store) = start_fake_s3();
+    let src = tempdir().unwrap();
+    fs::create_dir_all(src.path().join("docs")).unwrap();
+    fs::write(src.path().join("docs/notes.txt"), "meeting notes\n".repeat(100)).unwrap();
+    // Несжимаемые 20 MiB – больше одной части загрузки
+    let mut noise = vec![0u8; 20 * 1024 * 1024];
+    rand::rngs::StdRng::seed_from_u64(7).fill_bytes(&mut noise);
+    fs::write(src.path().join("noise.bin"), &noise).unwrap();
+
+    let created = blitzarch(&endpoint)
+        .args(["create", "--shard-strategy", "size-capped:1", "--output", "s3://backups/nightly/src.blz"])
+        .arg(src.path())
+        .output()
+        .unwrap();
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+    let archive_len = {
+        let store = store.lock().unwrap();
+        assert!(store.parts_received >= 2, "{} parts", store.parts_received);
+        assert!(store.uploads.is_empty());
+        store.objects["/backups/nightly/src.blz"].len() as u64
+    };
+
+    let out = tempdir().unwrap();
+    let extracted = blitzarch(&endpoint)
+        .args(["extract", "s3://backups/nightly/src.blz", "docs/notes.txt", "--output"])
+        .arg(out.path())
+        .output()
+        .unwrap();
+    assert!(extracted.status.success(), "{}", String::from_utf8_lossy(&extracted.stderr));
+    assert_eq!(fs::read_to_string(out.path().join("docs/notes.txt")).unwrap(), "meeting notes\n".repeat(100));
+    assert!(!out.path().join("noise.bin").exists());
+    let served = store.lock().unwrap().served;
+    assert!(served < archive_len / 4, "fetched {served} of {archive_len} bytes");
+    // Временная копия убрана
+    let leftovers: Vec<_> = fs::read_dir(out.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(leftovers, ["docs"]);
+
+    let full = tempdir().unwrap();
+    let extracted = blitzarch(&endpoint).args(["extract", "s3://backups/nightly/src.blz", "--output"]).arg(full.path()).output().unwrap();
+    assert!(extracted.status.success(), "{}", String::from_utf8_lossy(&extracted.stderr));
+    assert_eq!(fs::read(full.path().join("noise.bin")).unwrap(), noise);
+}
+
+#[test]
+fn failed_upload_is_aborted() {
+    let (endpoint, store) = start_fake_s3();
+    store.lock().unwrap().fail_complete = true;
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), "alpha").unwrap();
+
+    let created = blitzarch(&endpoint).args(["create", "--output", "s3://backups/a.blz"]).arg(src.path()).output().unwrap();
+    assert!(!created.status.success());
+    assert!(String::from_utf8_lossy(&created.stderr).contains("InternalError"));
+    let store = store.lock().unwrap();
+    assert_eq!(store.aborted, 1);
+    assert!(store.objects.is_empty() && store.uploads.is_empty());
+}
+
+#[test]
+fn remote_output_rejects_local_only_options() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), "alpha").unwrap();
+    for flags in [&["--format", "classic"][..], &["--max-duration", "1h"], &["--per-input"]] {
+        let run = blitzarch("http://127.0.0.1:9").arg("create").args(flags).args(["--output", "s3://b/a.blz"]).arg(src.path()).output().unwrap();
+        assert!(!run.status.success(), "{flags:?}");
+        assert!(String::from_utf8_lossy(&run.stderr).contains(flags[0]), "{}", String::from_utf8_lossy(&run.stderr));
+    }
+}
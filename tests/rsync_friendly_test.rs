@@ -0,0 +1,72 @@
+use blitzarch::api::Archive;
+use blitzarch::cdc::Chunker;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// Compressible, non-repeating text: words picked by xorshift.
+fn text(len: usize) -> Vec<u8> {
+    const WORDS: [&str; 16] = [
+        "alpha ", "bravo ", "charlie ", "delta ", "echo ", "foxtrot ", "golf ", "hotel ",
+        "india ", "juliet ", "kilo ", "lima ", "mike ", "november ", "oscar ", "papa\n",
+    ];
+    let mut x = 0x9E37_79B9_7F4A_7C15u64;
+    let mut out = Vec::with_capacity(len + 16);
+    while out.len() < len {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        out.extend_from_slice(WORDS[(x % 16) as usize].as_bytes());
+    }
+    out
+}
+
+/// Share of `new` (by bytes) made of content-defined chunks already in `old` –
+/// roughly what a delta transfer can skip.
+fn shared_fraction(old: &Path, new: &Path) -> f64 {
+    let old = fs::read(old).unwrap();
+    let new = fs::read(new).unwrap();
+    let mut known = HashSet::new();
+    let mut chunker = Chunker::new(&old[..]);
+    while let Some(chunk) = chunker.next_chunk().unwrap() {
+        known.insert(blake3::hash(chunk));
+    }
+    let mut shared = 0;
+    let mut chunker = Chunker::new(&new[..]);
+    while let Some(chunk) = chunker.next_chunk().unwrap() {
+        if known.contains(&blake3::hash(chunk)) {
+            shared += chunk.len();
+        }
+    }
+    shared as f64 / new.len() as f64
+}
+
+#[test]
+fn rsync_friendly_frames_survive_small_edits() {
+    let src = tempdir().unwrap();
+    let file = src.path().join("log.txt");
+    let mut data = text(24 * 1024 * 1024);
+    fs::write(&file, &data).unwrap();
+    let dir = tempdir().unwrap();
+    let create = |name: &str, rsync: bool| {
+        let arch = dir.path().join(name);
+        Archive::create([src.path()]).threads(1).level(3).rsync_friendly(rsync).write_to(&arch).unwrap();
+        arch
+    };
+    let (plain_old, rsync_old) = (create("plain-old.blz", false), create("rsync-old.blz", true));
+
+    // Правка в первой четверти файла
+    data.splice(5_000_000..5_000_000, *b"an edit in the middle ");
+    fs::write(&file, &data).unwrap();
+    let (plain_new, rsync_new) = (create("plain-new.blz", false), create("rsync-new.blz", true));
+
+    let plain = shared_fraction(&plain_old, &plain_new);
+    let rsync = shared_fraction(&rsync_old, &rsync_new);
+    assert!(rsync > 0.8, "only {:.0}% of the rsync-friendly archive is unchanged", rsync * 100.0);
+    assert!(rsync > plain + 0.3, "rsync-friendly {:.2} vs plain {:.2}", rsync, plain);
+
+    let out = tempdir().unwrap();
+    blitzarch::katana::extract_katana_archive_internal(&rsync_new, out.path(), &[], None, None).unwrap();
+    assert_eq!(fs::read(out.path().join("log.txt")).unwrap(), data);
+}
@@ -0,0 +1,56 @@
+//! Decompressed shard cache (global state – kept in its own test binary).
+
+use blitzarch::api::Archive;
+use blitzarch::katana;
+use blitzarch::shard_cache::{self, ShardCacheStats};
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+fn read(arch: &std::path::Path, name: &str, password: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let mut out = String::new();
+    katana::open_entry(arch, name, password)?.read_to_string(&mut out)?;
+    Ok(out)
+}
+
+#[test]
+fn repeated_reads_hit_the_shard_cache() {
+    let src = tempdir().unwrap();
+    for i in 0..4 {
+        fs::write(src.path().join(format!("f{i}.txt")), format!("content of file {i}\n").repeat(500)).unwrap();
+    }
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("plain.blz");
+    Archive::create([src.path()]).threads(1).write_to(&arch).unwrap();
+
+    shard_cache::enable_shard_cache(16 << 20);
+    assert!(read(&arch, "f2.txt", None).unwrap().starts_with("content of file 2"));
+    assert_eq!(read(&arch, "f3.txt", None).unwrap(), "content of file 3\n".repeat(500));
+    let tree = katana::extract_katana_to_memory(&arch, &[PathBuf::from("f0.txt")], None, 1 << 20).unwrap();
+    assert_eq!(tree["f0.txt"], "content of file 0\n".repeat(500).into_bytes());
+    let stats = shard_cache::shard_cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.shards), (2, 1, 1));
+
+    // A rewritten archive is never served from stale entries
+    fs::write(src.path().join("f3.txt"), "changed").unwrap();
+    Archive::create([src.path()]).threads(1).write_to(&arch).unwrap();
+    assert_eq!(read(&arch, "f3.txt", None).unwrap(), "changed");
+    assert_eq!(shard_cache::shard_cache_stats().misses, 2);
+
+    // The key is part of the cache key
+    let enc = dir.path().join("enc.blz");
+    Archive::create([src.path()]).threads(1).password("right").write_to(&enc).unwrap();
+    assert_eq!(read(&enc, "f1.txt", Some("right")).unwrap(), "content of file 1\n".repeat(500));
+    assert!(read(&enc, "f1.txt", Some("wrong")).is_err());
+    assert!(read(&enc, "f1.txt", None).is_err());
+
+    shard_cache::clear_shard_cache();
+    assert_eq!(shard_cache::shard_cache_stats().shards, 0);
+
+    // Shards larger than the budget are decoded as before
+    shard_cache::enable_shard_cache(1024);
+    assert!(read(&arch, "f1.txt", None).unwrap().starts_with("content of file 1"));
+    assert_eq!(shard_cache::shard_cache_stats(), ShardCacheStats { misses: 0, ..Default::default() });
+    shard_cache::disable_shard_cache();
+}
@@ -0,0 +1,94 @@
+use assert_cmd::prelude::*;
+use blitzarch::api::Archive;
+use blitzarch::katana::{self, ShardStats};
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Compressible text in one shard, incompressible noise in another.
+fn tree() -> tempfile::TempDir {
+    let src = tempdir().unwrap();
+    fs::create_dir_all(src.path().join("text")).unwrap();
+    fs::create_dir_all(src.path().join("noise")).unwrap();
+    fs::write(src.path().join("text/a.log"), "the same line again\n".repeat(50_000)).unwrap();
+    let mut x = 0x9E37_79B9_7F4A_7C15u64;
+    let noise: Vec<u8> = (0..400_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect();
+    fs::write(src.path().join("noise/b.bin"), noise).unwrap();
+    src
+}
+
+#[test]
+fn decode_cost_estimate() {
+    assert_eq!(ShardStats::estimate_decode_cost(100, 1000, false, false), 1100);
+    assert_eq!(ShardStats::estimate_decode_cost(100, 1000, false, true), 1200);
+    assert_eq!(ShardStats::estimate_decode_cost(1000, 1000, true, false), 125);
+}
+
+#[test]
+fn create_records_shard_stats() {
+    let src = tree();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("s.blz");
+    Archive::create([src.path()]).level(5).threads(2).write_to(&arch).unwrap();
+
+    let shards = katana::shard_details(&arch, None).unwrap();
+    assert!(!shards.is_empty());
+    for s in &shards {
+        let stats = s.stats.expect("stats recorded at create time");
+        assert_eq!(stats.level, 5);
+        assert_eq!(s.decode_cost, stats.decode_cost);
+        assert_eq!(stats.decode_cost, ShardStats::estimate_decode_cost(s.compressed_size, s.uncompressed_size, false, false));
+        if s.uncompressed_size > 0 {
+            assert!(s.ratio > 0.0);
+        }
+    }
+    assert!(shards.iter().any(|s| s.ratio > 50.0), "{shards:?}");
+
+    // Порядок распаковки по стоимости не меняет результат
+    let out = dir.path().join("out");
+    katana::extract_katana_archive_internal(&arch, &out, &[], None, None).unwrap();
+    assert_eq!(fs::read(out.join("text/a.log")).unwrap(), fs::read(src.path().join("text/a.log")).unwrap());
+    assert_eq!(fs::read(out.join("noise/b.bin")).unwrap(), fs::read(src.path().join("noise/b.bin")).unwrap());
+}
+
+#[test]
+fn encryption_keeps_stats() {
+    let src = tree();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("e.blz");
+    Archive::create([src.path()]).password("pw").write_to(&arch).unwrap();
+    for s in katana::shard_details(&arch, Some("pw")).unwrap() {
+        assert!(s.encrypted);
+        let stats = s.stats.unwrap();
+        assert_eq!(stats.decode_cost, ShardStats::estimate_decode_cost(s.compressed_size, s.uncompressed_size, false, true));
+    }
+}
+
+#[test]
+fn cli_stats_prints_shards() {
+    let src = tree();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("s.blz");
+    Archive::create([src.path()]).write_to(&arch).unwrap();
+
+    let text = Command::cargo_bin("blitzarch").unwrap().arg("stats").arg(&arch).output().unwrap();
+    assert!(text.status.success(), "{}", String::from_utf8_lossy(&text.stderr));
+    let stdout = String::from_utf8_lossy(&text.stdout);
+    assert!(stdout.contains("decode cost"), "{stdout}");
+
+    let json = Command::cargo_bin("blitzarch").unwrap().args(["stats", "--json"]).arg(&arch).output().unwrap();
+    assert!(json.status.success(), "{}", String::from_utf8_lossy(&json.stderr));
+    let value: serde_json::Value = serde_json::from_slice(&json.stdout).unwrap();
+    let shards = value["shards"].as_array().unwrap();
+    assert_eq!(shards.len(), katana::shard_details(&arch, None).unwrap().len());
+    assert!(shards[0]["stats"]["level"].is_i64());
+    // Время сжатия в индекс не попадает – архив воспроизводим
+    assert!(shards[0]["stats"].get("encode_ms").is_none());
+}
@@ -0,0 +1,114 @@
+use assert_cmd::prelude::*;
+use blitzarch::api::Archive;
+use blitzarch::cli::parse_byte_size;
+use blitzarch::fsx::{SizeFilter, SizeSkipped};
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs;
+use std::process::Command;
+use tempfile::tempdir;
+
+/// Documents next to a "VM image" and an empty lock file.
+fn tree() -> tempfile::TempDir {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("report.txt"), "quarterly numbers ".repeat(100)).unwrap();
+    fs::write(src.path().join("note.txt"), "hi").unwrap();
+    fs::write(src.path().join("disk.qcow2"), vec![7u8; 300_000]).unwrap();
+    fs::write(src.path().join(".lock"), b"").unwrap();
+    src
+}
+
+fn names(arch: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<String> = katana::list_entries(arch, None).unwrap().into_iter().map(|e| e.path).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn parses_byte_sizes() {
+    assert_eq!(parse_byte_size("512").unwrap(), 512);
+    assert_eq!(parse_byte_size("64k").unwrap(), 64 * 1024);
+    assert_eq!(parse_byte_size("2MiB").unwrap(), 2 << 20);
+    assert_eq!(parse_byte_size("1.5G").unwrap(), 3 << 29);
+    assert_eq!(parse_byte_size("1TB").unwrap(), 1 << 40);
+    assert_eq!(parse_byte_size("10B").unwrap(), 10);
+    assert!(parse_byte_size("lots").is_err());
+    assert!(parse_byte_size("-1K").is_err());
+}
+
+#[test]
+fn size_filter_leaves_files_out_and_counts_them() {
+    let src = tree();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("docs.blz");
+    Archive::create([src.path()]).max_file_size(100 * 1024).skip_empty(true).write_to(&arch).unwrap();
+    assert_eq!(names(&arch), ["note.txt", "report.txt"]);
+
+    let min = dir.path().join("min.blz");
+    Archive::create([src.path()]).min_file_size(100).write_to(&min).unwrap();
+    assert_eq!(names(&min), ["disk.qcow2", "report.txt"]);
+
+    let options = KatanaCreateOptions {
+        size_filter: SizeFilter { min: None, max: Some(100 * 1024), skip_empty: true },
+        ..Default::default()
+    };
+    let plan = katana_stream::estimate_create(&[src.path().to_path_buf()], &dir.path().join("plan.blz"), 2, Some(3), false, &options).unwrap();
+    assert_eq!(plan.files, 2);
+    assert_eq!(plan.skipped_by_size, SizeSkipped { files: 2, bytes: 300_000 });
+}
+
+#[test]
+fn cli_reports_skipped_files() {
+    let src = tree();
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("docs.blz");
+    let run = |extra: &[&str]| {
+        Command::cargo_bin("blitzarch")
+            .unwrap()
+            .arg("create")
+            .args(extra)
+            .args(["--max-file-size", "100K", "--skip-empty", "--output"])
+            .arg(&arch)
+            .arg(src.path())
+            .output()
+            .unwrap()
+    };
+
+    let plan = run(&["--dry-run"]);
+    assert!(plan.status.success(), "{}", String::from_utf8_lossy(&plan.stderr));
+    assert!(String::from_utf8_lossy(&plan.stdout).contains("Skipped by size: 2 files"));
+    assert!(!arch.exists());
+
+    let created = run(&[]);
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+    assert!(String::from_utf8_lossy(&created.stdout).contains("Skipped by size: 2 files"));
+    assert_eq!(names(&arch), ["note.txt", "report.txt"]);
+
+    // Classic не умеет фильтр по размеру
+    let classic = run(&["--format", "classic"]);
+    assert!(!classic.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn followed_links_are_filtered_by_target_size() {
+    use blitzarch::vfs::SymlinkMode;
+    use std::os::unix::fs::symlink;
+
+    let src = tree();
+    let outside = tempdir().unwrap();
+    fs::write(outside.path().join("big.img"), vec![1u8; 300_000]).unwrap();
+    fs::write(outside.path().join("small.txt"), "tiny").unwrap();
+    symlink(outside.path().join("big.img"), src.path().join("big-link")).unwrap();
+    symlink(outside.path().join("small.txt"), src.path().join("small-link")).unwrap();
+    let dir = tempdir().unwrap();
+
+    let followed = dir.path().join("followed.blz");
+    Archive::create([src.path()]).symlinks(SymlinkMode::Follow).max_file_size(100 * 1024).write_to(&followed).unwrap();
+    assert_eq!(names(&followed), [".lock", "note.txt", "report.txt", "small-link"]);
+
+    // Ссылки, сохранённые как ссылки, фильтр не трогает
+    let kept = dir.path().join("kept.blz");
+    Archive::create([src.path()]).symlinks(SymlinkMode::Keep).max_file_size(100 * 1024).skip_empty(true).write_to(&kept).unwrap();
+    assert_eq!(names(&kept), ["big-link", "note.txt", "report.txt", "small-link"]);
+}
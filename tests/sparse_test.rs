@@ -0,0 +1,89 @@
+#![cfg(target_os = "linux")]
+
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, KatanaCreateOptions};
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use tempfile::tempdir;
+
+const SIZE: u64 = 64 << 20;
+
+/// 64 MiB file with data only in its first and a middle block.
+fn write_sparse(path: &Path) {
+    let mut f = File::create(path).unwrap();
+    f.set_len(SIZE).unwrap();
+    f.write_all(&[0xAB; 4096]).unwrap();
+    f.seek(SeekFrom::Start(32 << 20)).unwrap();
+    f.write_all(&[0xCD; 8192]).unwrap();
+}
+
+fn allocated(path: &Path) -> u64 {
+    fs::metadata(path).unwrap().blocks() * 512
+}
+
+#[test]
+fn sparse_files_roundtrip_with_holes() {
+    let src = tempdir().unwrap();
+    let image = src.path().join("disk.img");
+    write_sparse(&image);
+    let Some(extents) = blitzarch::fsx::data_extents(&File::open(&image).unwrap(), SIZE).unwrap() else {
+        eprintln!("filesystem does not report holes – skipping");
+        return;
+    };
+    assert!(extents.iter().map(|e| e.1).sum::<u64>() < SIZE / 4);
+    fs::write(src.path().join("dense.txt"), b"no holes here").unwrap();
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("sparse.blz");
+    katana_stream::create_katana_archive_with_options(
+        &[src.path().to_path_buf()], &arch, 2, 0, None, None, None, &KatanaCreateOptions::default(),
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+    assert!(fs::metadata(&arch).unwrap().len() < 1 << 20);
+
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+    let restored = out.path().join("disk.img");
+    assert_eq!(fs::metadata(&restored).unwrap().len(), SIZE);
+    assert_eq!(fs::read(&restored).unwrap(), fs::read(&image).unwrap());
+    assert!(allocated(&restored) < SIZE / 4, "holes were written out: {} bytes allocated", allocated(&restored));
+    assert_eq!(fs::read(out.path().join("dense.txt")).unwrap(), b"no holes here");
+}
+
+#[test]
+fn sparse_writer_skips_holes() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("out.bin");
+    let extents = [(10u64, 5u64), (100, 10)];
+    let mut content = vec![0u8; 200];
+    content[10..15].copy_from_slice(b"hello");
+    content[100..110].copy_from_slice(b"0123456789");
+    let mut writer = blitzarch::fsx::SparseWriter::new(File::create(&path).unwrap(), &extents);
+    // Odd-sized writes straddle the region boundaries
+    for piece in content.chunks(7) {
+        writer.write_all(piece).unwrap();
+    }
+    drop(writer.finish(200).unwrap());
+    assert_eq!(fs::read(&path).unwrap(), content);
+}
+
+#[test]
+fn sparse_writer_keeps_data_written_into_holes() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("out.bin");
+    // Карта снята до того, как в дыру дописали данные
+    let extents = [(10u64, 5u64)];
+    let mut content = vec![0u8; 200];
+    content[10..15].copy_from_slice(b"hello");
+    content[60..66].copy_from_slice(b"late!!");
+    content[199] = 1;
+    let mut writer = blitzarch::fsx::SparseWriter::new(File::create(&path).unwrap(), &extents);
+    for piece in content.chunks(7) {
+        writer.write_all(piece).unwrap();
+    }
+    drop(writer.finish(200).unwrap());
+    assert_eq!(fs::read(&path).unwrap(), content);
+}
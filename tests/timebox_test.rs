@@ -0,0 +1,129 @@
+use blitzarch::katana;
+use blitzarch::katana_stream::{self, perform_paranoid_check, KatanaCreateOptions};
+use blitzarch::timebox::{self, Checkpoint, CheckpointKind, TimeBudget};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn write_file(p: &Path, data: &[u8]) {
+    fs::create_dir_all(p.parent().unwrap()).unwrap();
+    fs::write(p, data).unwrap();
+}
+
+#[test]
+fn exhausted_create_resumes_and_completes() {
+    for password in [None, Some("tb-pw")] {
+        let src = tempdir().unwrap();
+        write_file(&src.path().join("a.txt"), &b"alpha ".repeat(4000));
+        write_file(&src.path().join("dir/b.bin"), &[7u8; 50_000]);
+        write_file(&src.path().join("dir/c.txt"), &b"gamma ".repeat(100));
+
+        let arch_dir = tempdir().unwrap();
+        let arch = arch_dir.path().join("boxed.blz");
+        let inputs = [src.path().to_path_buf()];
+
+        // Budget already spent: the archive is finalized without any file
+        let budget = TimeBudget::new(Duration::ZERO);
+        let options = KatanaCreateOptions { time_budget: Some(budget.clone()), ..Default::default() };
+        katana_stream::create_katana_archive_with_options(
+            &inputs, &arch, 2, 0, None, password.map(String::from), None, &options,
+            None::<fn(blitzarch::progress::ProgressState)>,
+        )
+        .unwrap();
+        perform_paranoid_check(&arch).unwrap();
+        assert!(katana::list_entries(&arch, password).unwrap().is_empty());
+        assert_eq!(timebox::finish_create(&arch, &inputs, Some(&budget)).unwrap(), 3);
+        let cp = Checkpoint::load(&arch).unwrap().unwrap();
+        assert_eq!(cp.kind, CheckpointKind::Create);
+        assert_eq!(cp.remaining.len(), 3);
+
+        // Another exhausted budget adds nothing and keeps the checkpoint
+        let (report, left) =
+            timebox::resume_create(&arch, 2, password.map(String::from), None, Some(TimeBudget::new(Duration::ZERO)))
+                .unwrap();
+        assert_eq!((report.files_added, left), (0, 3));
+
+        let (report, left) = timebox::resume_create(&arch, 2, password.map(String::from), None, None).unwrap();
+        assert_eq!((report.files_added, left), (3, 0));
+        assert!(Checkpoint::load(&arch).unwrap().is_none());
+        perform_paranoid_check(&arch).unwrap();
+
+        let out = tempdir().unwrap();
+        katana::extract_katana_archive_internal(&arch, out.path(), &[], password.map(String::from), None).unwrap();
+        assert_eq!(fs::read(out.path().join("a.txt")).unwrap(), b"alpha ".repeat(4000));
+        assert_eq!(fs::read(out.path().join("dir/b.bin")).unwrap(), vec![7u8; 50_000]);
+        assert_eq!(fs::read(out.path().join("dir/c.txt")).unwrap(), b"gamma ".repeat(100));
+        assert!(timebox::resume_create(&arch, 2, password.map(String::from), None, None).is_err());
+    }
+}
+
+#[test]
+fn exhausted_verify_resumes_from_checkpoint() {
+    let src = tempdir().unwrap();
+    for i in 0..8 {
+        write_file(&src.path().join(format!("f{}.bin", i)), &vec![i as u8; 20_000]);
+    }
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("verify.blz");
+    katana_stream::create_katana_archive(
+        &[src.path().to_path_buf()], &arch, 4, 0, None, None, None,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+
+    let spent = TimeBudget::new(Duration::ZERO);
+    let r = timebox::verify_timeboxed(&arch, None, true, Some(&spent), false, &Default::default()).unwrap();
+    assert_eq!(r.resume_at, Some(0));
+    assert_eq!(Checkpoint::load(&arch).unwrap().unwrap().kind, CheckpointKind::Verify);
+
+    let r = timebox::verify_timeboxed(&arch, None, true, None, true, &Default::default()).unwrap();
+    assert_eq!(r.resume_at, None);
+    assert!(r.deep);
+    assert!(Checkpoint::load(&arch).unwrap().is_none());
+    assert!(timebox::verify_timeboxed(&arch, None, false, None, true, &Default::default()).is_err());
+
+    // A checkpoint does not survive changes to the archive
+    timebox::verify_timeboxed(&arch, None, false, Some(&spent), false, &Default::default()).unwrap();
+    let mut bytes = fs::read(&arch).unwrap();
+    bytes.push(0);
+    fs::write(&arch, bytes).unwrap();
+    let err = timebox::verify_timeboxed(&arch, None, false, None, true, &Default::default()).unwrap_err();
+    assert!(err.to_string().contains("changed since"), "{}", err);
+}
+
+#[test]
+fn paused_create_leaves_a_resumable_archive() {
+    let src = tempdir().unwrap();
+    // More single-file shards than any pool has threads in flight
+    for i in 0..96 {
+        write_file(&src.path().join(format!("f{:02}.bin", i)), &vec![i as u8; 4_000]);
+    }
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("paused.blz");
+    let inputs = [src.path().to_path_buf()];
+
+    // Paused by the caller once the first shard is done
+    let budget = TimeBudget::unlimited();
+    assert!(!budget.expired());
+    let pauser = budget.clone();
+    blitzarch::Archive::create(inputs.iter().cloned())
+        .threads(1)
+        .shard_strategy(katana_stream::ShardStrategy::SizeCapped(1))
+        .time_budget(budget.clone())
+        .on_progress(move |_| pauser.pause())
+        .write_to(&arch)
+        .unwrap();
+    assert!(budget.paused() && budget.expired());
+    let stored = katana::list_entries(&arch, None).unwrap().len();
+    assert!((1..96).contains(&stored), "{stored} files stored before the pause");
+    let cp = Checkpoint::load(&arch).unwrap().unwrap();
+    assert_eq!(cp.remaining.len(), 96 - stored);
+
+    let (report, left) = timebox::resume_create(&arch, 2, None, None, Some(TimeBudget::unlimited())).unwrap();
+    assert_eq!((report.files_added, left), (96 - stored, 0));
+    assert!(Checkpoint::load(&arch).unwrap().is_none());
+    let out = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None).unwrap();
+    assert_eq!(fs::read(out.path().join("f95.bin")).unwrap(), vec![95u8; 4_000]);
+}
@@ -0,0 +1,41 @@
+#![cfg(unix)]
+
+use blitzarch::fsx::{tree_stats, TreeStats};
+use blitzarch::vfs::{OsFs, SymlinkMode, Vfs};
+use std::fs;
+use std::os::unix::fs::symlink;
+use tempfile::tempdir;
+
+#[test]
+fn tree_stats_match_the_create_listing() {
+    let src = tempdir().unwrap();
+    let root = src.path();
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("top.txt"), vec![1u8; 1000]).unwrap();
+    fs::write(root.join("a/one.bin"), vec![2u8; 20_000]).unwrap();
+    fs::write(root.join("a/b/two.bin"), vec![3u8; 300]).unwrap();
+    symlink(root.join("a/one.bin"), root.join("link_file")).unwrap();
+    symlink(root.join("a"), root.join("link_dir")).unwrap();
+    symlink(root, root.join("a/b/loop")).unwrap();
+    let inputs = vec![root.to_path_buf()];
+
+    let skip = tree_stats(&inputs, SymlinkMode::Skip, &[]);
+    assert_eq!(skip, TreeStats { files: 3, dirs: 3, symlinks: 3, bytes: 21_300, unreadable: 0 });
+    let keep = tree_stats(&inputs, SymlinkMode::Keep, &[]);
+    assert_eq!((keep.files, keep.bytes), (6, 21_300));
+    // Following: link_file and link_dir add their targets, the loop is cut
+    let follow = tree_stats(&inputs, SymlinkMode::Follow, &[]);
+    assert_eq!((follow.files, follow.bytes), (6, 21_300 + 20_000 + 20_300));
+
+    for (mode, stats) in [(SymlinkMode::Skip, skip), (SymlinkMode::Keep, keep)] {
+        let listed = OsFs { symlinks: mode, ..Default::default() }.list_files(root).unwrap();
+        assert_eq!(listed.len() as u64, stats.files, "{mode:?}");
+    }
+
+    // Excluded paths (the archive being written) and inputs given as files
+    let excluded = tree_stats(&inputs, SymlinkMode::Skip, &[root.join("a")]);
+    assert_eq!((excluded.files, excluded.bytes), (1, 1000));
+    let files = tree_stats(&[root.join("top.txt"), root.join("link_file")], SymlinkMode::Skip, &[]);
+    assert_eq!((files.files, files.bytes), (2, 21_000));
+    assert_eq!(tree_stats(&[root.join("missing")], SymlinkMode::Skip, &[]).unreadable, 1);
+}
@@ -0,0 +1,80 @@
+use blitzarch::katana::EntryInfo;
+use blitzarch::tui::{Browser, MarkState, Row};
+use std::path::PathBuf;
+
+fn entry(path: &str, size: u64) -> EntryInfo {
+    EntryInfo {
+        path: path.into(),
+        size,
+        permissions: None,
+        mtime: None,
+        is_dir: false,
+        shard_id: Some(0),
+        compressed_size: None,
+        symlink: None,
+        duplicate_of: None,
+    }
+}
+
+fn names(browser: &Browser) -> Vec<String> {
+    browser
+        .rows()
+        .iter()
+        .map(|r| match r {
+            Row::Dir { name, .. } => format!("{}/", name),
+            Row::File { name, .. } => name.clone(),
+        })
+        .collect()
+}
+
+fn sample() -> Browser {
+    Browser::new(&[
+        entry("README.md", 10),
+        entry("src/main.rs", 100),
+        entry("src/util/mod.rs", 50),
+        entry("docs/guide.md", 7),
+    ])
+}
+
+#[test]
+fn browser_navigates_the_tree() {
+    let mut b = sample();
+    assert_eq!(names(&b), ["docs/", "src/", "README.md"]);
+    assert!(matches!(&b.rows()[1], Row::Dir { files: 2, bytes: 150, .. }));
+
+    b.move_cursor(1);
+    b.enter();
+    assert_eq!(b.cwd(), "src");
+    assert_eq!(names(&b), ["util/", "main.rs"]);
+    b.move_cursor(10);
+    assert_eq!(b.cursor(), 1, "cursor stays on the last row");
+    b.leave();
+    assert_eq!(b.cwd(), "");
+    assert_eq!(b.rows()[b.cursor()].path(), "src");
+
+    // Поиск идёт по всему дереву
+    b.set_search("MOD");
+    assert_eq!(names(&b), ["src/util/mod.rs"]);
+}
+
+#[test]
+fn marks_cover_directories_and_selection() {
+    let mut b = sample();
+    b.move_cursor(1);
+    b.toggle_mark();
+    assert_eq!(b.marked_paths(), [PathBuf::from("src/main.rs"), PathBuf::from("src/util/mod.rs")]);
+    assert_eq!(b.marked_totals(), (2, 150));
+
+    b.enter();
+    b.toggle_mark(); // util/ – fully marked, unmark it
+    b.leave();
+    assert_eq!(b.mark_state(&b.rows()[1]), MarkState::Some);
+    assert_eq!(b.selection(), [PathBuf::from("src/main.rs")]);
+
+    b.toggle_all();
+    assert_eq!(b.marked_totals().0, 4);
+    b.toggle_all();
+    assert!(b.marked_paths().is_empty());
+    // Без отметок извлекается строка под курсором
+    assert_eq!(b.selection(), [PathBuf::from("src/main.rs"), PathBuf::from("src/util/mod.rs")]);
+}
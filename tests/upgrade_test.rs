@@ -0,0 +1,156 @@
+use assert_cmd::prelude::*;
+use blitzarch::archive::ArchiveWriter;
+use blitzarch::common::FileMetadata;
+use blitzarch::compress::{create_store_temp_bundle, CompressionAlgo};
+use blitzarch::formats::{self, ArchiveKind};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::tempdir;
+
+fn blitzarch() -> Command {
+    let mut cmd = Command::cargo_bin("blitzarch").unwrap();
+    cmd.env_remove("BLITZARCH_PASSWORD");
+    cmd
+}
+
+/// Classic archive of a small tree at `dir/old.blz`.
+fn classic_archive(dir: &Path) -> PathBuf {
+    let src = dir.join("src");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::write(src.join("docs/notes.txt"), "meeting notes\n".repeat(200)).unwrap();
+    fs::write(src.join("data.bin"), (0..50_000u32).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>()).unwrap();
+    let archive = dir.join("old.blz");
+    let created = blitzarch()
+        .args(["create", "--format", "classic", "--comment", "weekly", "--output"])
+        .arg(&archive)
+        .arg(&src)
+        .output()
+        .unwrap();
+    assert!(created.status.success(), "{}", String::from_utf8_lossy(&created.stderr));
+    assert_eq!(formats::detect(&archive).unwrap(), ArchiveKind::Classic);
+    archive
+}
+
+/// Encrypted classic archive of `dir/src` at `dir/old.blz`. The classic CLI
+/// cannot encrypt compressed bundles, so the files go into one store bundle.
+fn encrypted_classic_archive(dir: &Path, password: &str) -> PathBuf {
+    classic_archive(dir);
+    let files: Vec<FileMetadata> = ["docs/notes.txt", "data.bin"]
+        .iter()
+        .map(|name| {
+            let absolute_path = dir.join("src").join(name);
+            FileMetadata {
+                size: fs::metadata(&absolute_path).unwrap().len(),
+                absolute_path,
+                path: PathBuf::from(name),
+                permissions: 0o640,
+                modified_time: 0,
+                created_time: None,
+                is_dir: false,
+                dense_hint: None,
+            }
+        })
+        .collect();
+    let archive = dir.join("old.blz");
+    let mut writer = ArchiveWriter::new(fs::File::create(&archive).unwrap(), Some(password.into()), CompressionAlgo::Store).unwrap();
+    writer.set_metadata([("comment".to_string(), "weekly".to_string())].into());
+    writer.write_header().unwrap();
+    writer.add_file_entry(PathBuf::from("docs"), true, 0, 0, 0, 0, Some(0o750));
+    let (bundle, _) = create_store_temp_bundle(&files).unwrap();
+    writer.write_store_bundle(bundle, &files).unwrap();
+    let mut offset = 0;
+    for file in &files {
+        writer.add_file_entry(file.path.clone(), false, 0, offset, file.size, file.size, Some(file.permissions));
+        offset += file.size + 8;
+    }
+    writer.finalize().unwrap();
+    archive
+}
+
+#[test]
+fn upgrades_in_place() {
+    let dir = tempdir().unwrap();
+    let archive = classic_archive(dir.path());
+    let before = blitzarch::extract::list_entries(&archive, None).unwrap();
+
+    let upgraded = blitzarch().args(["upgrade", "--in-place"]).arg(&archive).output().unwrap();
+    assert!(upgraded.status.success(), "{}", String::from_utf8_lossy(&upgraded.stderr));
+    assert_eq!(formats::detect(&archive).unwrap(), ArchiveKind::Katana);
+    assert_eq!(blitzarch::katana::read_metadata(&archive, None).unwrap()["comment"], "weekly");
+    let after = blitzarch::katana::list_entries(&archive, None).unwrap();
+    let files = |entries: Vec<blitzarch::katana::EntryInfo>| -> Vec<(String, u64)> {
+        let mut v: Vec<_> = entries.into_iter().filter(|e| !e.is_dir).map(|e| (e.path, e.size)).collect();
+        v.sort();
+        v
+    };
+    assert_eq!(files(before), files(after));
+
+    let out = dir.path().join("out");
+    blitzarch::extract::extract_files(&archive, &[], None, Some(&out), None).unwrap();
+    assert_eq!(fs::read_to_string(out.join("docs/notes.txt")).unwrap(), "meeting notes\n".repeat(200));
+    // Ни временных файлов, ни каталогов рядом с архивом
+    let mut left: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+    left.sort();
+    assert_eq!(left, ["old.blz", "out", "src"]);
+
+    // Повторный запуск ничего не меняет
+    let again = blitzarch().args(["upgrade", "--in-place"]).arg(&archive).output().unwrap();
+    assert!(again.status.success());
+    assert!(String::from_utf8_lossy(&again.stdout).contains("already is a Katana archive"));
+}
+
+#[test]
+fn encrypted_archive_keeps_its_password() {
+    let dir = tempdir().unwrap();
+    let archive = encrypted_classic_archive(dir.path(), "s3cret");
+    assert_eq!(formats::detect(&archive).unwrap(), ArchiveKind::Classic);
+
+    let missing = blitzarch().args(["upgrade", "--in-place"]).arg(&archive).output().unwrap();
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("--password"));
+    let wrong = blitzarch().args(["upgrade", "--in-place", "--password", "nope"]).arg(&archive).output().unwrap();
+    assert!(!wrong.status.success());
+    assert_eq!(formats::detect(&archive).unwrap(), ArchiveKind::Classic);
+
+    let katana = dir.path().join("new.blz");
+    let upgraded = blitzarch().args(["upgrade", "--output"]).arg(&katana).arg(&archive).env("BLITZARCH_PASSWORD", "s3cret").output().unwrap();
+    assert!(upgraded.status.success(), "{}", String::from_utf8_lossy(&upgraded.stderr));
+    assert_eq!(formats::detect(&archive).unwrap(), ArchiveKind::Classic);
+    assert!(blitzarch::katana::open_lazy(&katana).unwrap().is_encrypted());
+    let out = dir.path().join("out");
+    assert!(blitzarch::extract::extract_files(&katana, &[], None, Some(&out), None).is_err());
+    blitzarch::extract::extract_files(&katana, &[], Some("s3cret"), Some(&out), None).unwrap();
+    assert_eq!(fs::read(out.join("data.bin")).unwrap(), fs::read(dir.path().join("src/data.bin")).unwrap());
+    assert_eq!(blitzarch::katana::read_metadata(&katana, Some("s3cret")).unwrap()["comment"], "weekly");
+}
+
+#[test]
+fn requires_a_destination() {
+    let dir = tempdir().unwrap();
+    let archive = classic_archive(dir.path());
+    let run = blitzarch().arg("upgrade").arg(&archive).output().unwrap();
+    assert!(!run.status.success());
+    assert!(String::from_utf8_lossy(&run.stderr).contains("--in-place"));
+
+    fs::write(dir.path().join("notes.txt"), "not an archive").unwrap();
+    let err = blitzarch::upgrade::upgrade_classic_archive(&dir.path().join("notes.txt"), &dir.path().join("n.blz"), None, 2, false).unwrap_err();
+    assert!(err.to_string().contains("not a classic"), "{err}");
+}
+
+#[test]
+fn output_is_not_overwritten_without_force() {
+    let dir = tempdir().unwrap();
+    let archive = classic_archive(dir.path());
+    let target = dir.path().join("new.blz");
+    fs::write(&target, "keep me").unwrap();
+
+    let refused = blitzarch().args(["upgrade", "--output"]).arg(&target).arg(&archive).output().unwrap();
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("--force"), "{}", String::from_utf8_lossy(&refused.stderr));
+    assert_eq!(fs::read_to_string(&target).unwrap(), "keep me");
+
+    let forced = blitzarch().args(["upgrade", "--force", "--output"]).arg(&target).arg(&archive).output().unwrap();
+    assert!(forced.status.success(), "{}", String::from_utf8_lossy(&forced.stderr));
+    assert_eq!(formats::detect(&target).unwrap(), ArchiveKind::Katana);
+}
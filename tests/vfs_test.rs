@@ -0,0 +1,58 @@
+use blitzarch::vfs::{Vfs, VfsMetadata};
+use blitzarch::Archive;
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+/// Documents kept in memory, e.g. loaded from a database.
+#[derive(Debug, Default)]
+struct DocStore(BTreeMap<PathBuf, Vec<u8>>);
+
+impl Vfs for DocStore {
+    fn list_files(&self, root: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(self.0.keys().filter(|p| p.starts_with(root)).cloned().collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<VfsMetadata> {
+        let doc = self.0.get(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(VfsMetadata::new(doc.len() as u64).with_permissions(0o640).with_mtime(1_700_000_000))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let doc = self.0.get(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(Box::new(io::Cursor::new(doc.clone())))
+    }
+}
+
+#[test]
+fn archive_virtual_tree() {
+    let mut store = DocStore::default();
+    store.0.insert("/db/docs/invoice-1.json".into(), b"{\"total\": 10}".repeat(400));
+    store.0.insert("/db/docs/2024/report.txt".into(), b"quarterly ".repeat(1000));
+    store.0.insert("/db/docs/tiny.txt".into(), b"x".to_vec());
+    store.0.insert("/db/other/skip.txt".into(), b"not selected".to_vec());
+
+    let dir = tempdir().unwrap();
+    let arch = dir.path().join("virtual.blz");
+    Archive::create(["/db/docs"])
+        .vfs(Arc::new(store))
+        .inline_small_files(true)
+        .threads(2)
+        .write_to(&arch)
+        .unwrap();
+
+    let archive = Archive::open(&arch).unwrap();
+    let mut names: Vec<_> = archive.entries().unwrap().into_iter().map(|e| (e.path, e.permissions, e.mtime)).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            ("2024/report.txt".to_string(), Some(0o640), Some(1_700_000_000)),
+            ("invoice-1.json".to_string(), Some(0o640), Some(1_700_000_000)),
+            ("tiny.txt".to_string(), Some(0o640), Some(1_700_000_000)),
+        ]
+    );
+    assert_eq!(archive.read("2024/report.txt").unwrap(), b"quarterly ".repeat(1000));
+}
@@ -0,0 +1,34 @@
+use blitzarch::katana;
+use blitzarch::katana_stream;
+use blitzarch::warnings::{self, WarningKind};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn extract_reports_skipped_entries() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("blocked.txt"), b"blocked").unwrap();
+    fs::write(src.path().join("fine.txt"), b"fine").unwrap();
+
+    let arch_dir = tempdir().unwrap();
+    let arch = arch_dir.path().join("warn.blz");
+    katana_stream::create_katana_archive(
+        &[src.path().to_path_buf()], &arch, 2, 0, None, None, None,
+        None::<fn(blitzarch::progress::ProgressState)>,
+    )
+    .unwrap();
+
+    // A directory in the way of an entry: the entry is skipped, the job succeeds
+    let out = tempdir().unwrap();
+    fs::create_dir(out.path().join("blocked.txt")).unwrap();
+    let (result, warnings) =
+        warnings::capture(|| katana::extract_katana_archive_internal(&arch, out.path(), &[], None, None));
+    result.unwrap();
+    assert_eq!(fs::read(out.path().join("fine.txt")).unwrap(), b"fine");
+
+    let ours: Vec<_> = warnings.iter().filter(|w| w.path == "blocked.txt").collect();
+    assert_eq!(ours.len(), 1, "{:?}", warnings);
+    assert_eq!(ours[0].kind, WarningKind::SkippedFile);
+    let json = serde_json::to_value(ours[0]).unwrap();
+    assert_eq!(json["kind"], "skipped_file");
+}
@@ -0,0 +1,129 @@
+use blitzarch::katana;
+use blitzarch::watch::{generation_path, rehash_pause, BatchOutcome, WatchOptions, WatchedArchive};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::tempdir;
+
+fn write_at(p: &Path, data: &[u8], secs: u64) {
+    fs::create_dir_all(p.parent().unwrap()).unwrap();
+    fs::write(p, data).unwrap();
+    File::options().write(true).open(p).unwrap().set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+}
+
+fn changed(paths: &[PathBuf]) -> BTreeSet<PathBuf> {
+    paths.iter().cloned().collect()
+}
+
+fn names(archive: &Path) -> Vec<String> {
+    let mut names: Vec<String> = katana::list_entries(archive, None).unwrap().into_iter().map(|e| e.path).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn appends_new_files_and_rolls_generations_on_changes() {
+    let src = tempdir().unwrap();
+    let t0 = 1_600_000_000;
+    write_at(&src.path().join("a.txt"), &b"alpha ".repeat(2000), t0);
+    write_at(&src.path().join("docs/b.txt"), b"beta", t0);
+    let out = tempdir().unwrap();
+    let output = out.path().join("backup.blz");
+    let options = WatchOptions { threads: 2, ..Default::default() };
+    let mut watched = WatchedArchive::open(src.path(), &output, options.clone()).unwrap();
+    let src_dir = src.path().canonicalize().unwrap();
+    assert_eq!(names(&output), ["a.txt", "docs/b.txt"]);
+
+    // Events for files already archived as they are change nothing
+    assert_eq!(watched.apply(&changed(&[src_dir.join("a.txt")])).unwrap(), BatchOutcome::Unchanged);
+
+    // New files (also inside a new directory) are appended in place
+    write_at(&src_dir.join("c.txt"), b"gamma", t0);
+    write_at(&src_dir.join("logs/d.log"), &b"delta ".repeat(500), t0);
+    let outcome = watched.apply(&changed(&[src_dir.join("c.txt"), src_dir.join("logs")])).unwrap();
+    assert_eq!(outcome, BatchOutcome::Appended { archive: output.canonicalize().unwrap(), files: 2 });
+    assert_eq!(names(&output), ["a.txt", "c.txt", "docs/b.txt", "logs/d.log"]);
+
+    // A modification and a deletion roll generation 1 on top of the archive
+    write_at(&src_dir.join("a.txt"), &b"alpha v2 ".repeat(2000), t0 + 10);
+    fs::remove_dir_all(src_dir.join("docs")).unwrap();
+    let outcome = watched.apply(&changed(&[src_dir.join("a.txt"), src_dir.join("docs")])).unwrap();
+    let gen1 = generation_path(&output.canonicalize().unwrap(), 1);
+    assert_eq!(outcome, BatchOutcome::Generation { archive: gen1.clone(), generation: 1, added: 0, modified: 1, removed: 1 });
+    assert!(gen1.ends_with("backup.g0001.blz"));
+    assert_eq!(names(&gen1), ["a.txt", "c.txt", "logs/d.log"]);
+
+    let restored = tempdir().unwrap();
+    katana::extract_katana_archive_internal(&gen1, restored.path(), &[], None, None).unwrap();
+    for name in ["a.txt", "c.txt", "logs/d.log"] {
+        assert_eq!(fs::read(restored.path().join(name)).unwrap(), fs::read(src_dir.join(name)).unwrap(), "{name}");
+    }
+    assert!(!restored.path().join("docs").exists());
+
+    // Reopening continues with the newest generation
+    drop(watched);
+    let watched = WatchedArchive::open(src.path(), &output, options).unwrap();
+    assert_eq!((watched.generation(), watched.latest()), (1, gen1.as_path()));
+}
+
+#[test]
+fn generations_mode_never_touches_written_archives() {
+    let src = tempdir().unwrap();
+    write_at(&src.path().join("a.txt"), b"alpha", 1_600_000_000);
+    let out = tempdir().unwrap();
+    let output = out.path().join("series.blz");
+    let options = WatchOptions { generations: true, threads: 2, ..Default::default() };
+    let mut watched = WatchedArchive::open(src.path(), &output, options).unwrap();
+    let before = fs::read(&output).unwrap();
+
+    let src_dir = src.path().canonicalize().unwrap();
+    write_at(&src_dir.join("b.txt"), b"beta", 1_600_000_000);
+    let outcome = watched.apply(&changed(&[src_dir.join("b.txt")])).unwrap();
+    assert!(matches!(outcome, BatchOutcome::Generation { generation: 1, added: 1, .. }));
+    assert_eq!(fs::read(&output).unwrap(), before);
+    assert_eq!(names(watched.latest()), ["a.txt", "b.txt"]);
+}
+
+#[test]
+fn rejects_an_archive_inside_the_watched_directory() {
+    let src = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"alpha").unwrap();
+    let err = WatchedArchive::open(src.path(), &src.path().join("self.blz"), WatchOptions::default()).unwrap_err();
+    assert!(err.to_string().contains("outside the watched directory"));
+    assert!(!src.path().join("self.blz").exists());
+}
+
+#[test]
+fn appends_are_paced_by_archive_size_across_batches() {
+    // Перехеширование терабайтного архива – не на каждую мелочь
+    assert!(rehash_pause(0).is_zero());
+    assert!(rehash_pause(1 << 40) > Duration::from_secs(3600));
+    assert!(rehash_pause(2 << 30) > rehash_pause(1 << 30));
+
+    let src = tempdir().unwrap();
+    let t0 = 1_600_000_000;
+    write_at(&src.path().join("a.txt"), b"alpha", t0);
+    let out = tempdir().unwrap();
+    let output = out.path().join("paced.blz");
+    let mut watched = WatchedArchive::open(src.path(), &output, WatchOptions { threads: 2, ..Default::default() }).unwrap();
+    let src_dir = src.path().canonicalize().unwrap();
+    let small = watched.min_batch_interval();
+    assert!(small < Duration::from_secs(1), "{small:?}");
+
+    let mut expected = vec!["a.txt".to_string()];
+    for batch in 0..3 {
+        let name = format!("batch{batch}.log");
+        write_at(&src_dir.join(&name), &format!("entry {batch} ").repeat(4000).into_bytes(), t0);
+        let outcome = watched.apply(&changed(&[src_dir.join(&name)])).unwrap();
+        assert_eq!(outcome, BatchOutcome::Appended { archive: output.canonicalize().unwrap(), files: 1 });
+        expected.push(name);
+        expected.sort();
+        assert_eq!(names(&output), expected);
+    }
+    assert!(watched.min_batch_interval() >= small);
+
+    let series = out.path().join("series.blz");
+    let generations = WatchedArchive::open(src.path(), &series, WatchOptions { generations: true, threads: 2, ..Default::default() }).unwrap();
+    assert!(generations.min_batch_interval().is_zero());
+}
@@ -0,0 +1,130 @@
+use assert_cmd::prelude::*;
+use blitzarch::formats::zip as blz_zip;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+
+/// `docs/` with a deflated and a stored file, a script with the x bit and an
+/// entry trying to climb out of the output directory.
+fn write_zip(path: &Path) {
+    let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.add_directory("docs/", FileOptions::default()).unwrap();
+    zip.start_file("docs/report.txt", deflated).unwrap();
+    zip.write_all("quarterly numbers\n".repeat(1000).as_bytes()).unwrap();
+    zip.start_file("docs/raw.bin", FileOptions::default().compression_method(CompressionMethod::Stored)).unwrap();
+    zip.write_all(&[1, 2, 3, 4]).unwrap();
+    zip.start_file("bin/run.sh", deflated.unix_permissions(0o755)).unwrap();
+    zip.write_all(b"#!/bin/sh\necho hi\n").unwrap();
+    zip.start_file("../evil.txt", deflated).unwrap();
+    zip.write_all(b"gotcha").unwrap();
+    zip.set_comment("sent by accounting");
+    zip.finish().unwrap();
+}
+
+#[test]
+fn detects_zip_by_magic() {
+    let dir = tempdir().unwrap();
+    let zip_path = dir.path().join("mail-attachment.dat");
+    write_zip(&zip_path);
+    assert!(blz_zip::is_zip_archive(&zip_path).unwrap());
+    fs::write(dir.path().join("short"), b"PK").unwrap();
+    assert!(!blz_zip::is_zip_archive(&dir.path().join("short")).unwrap());
+    fs::write(dir.path().join("fake.zip"), b"not a zip at all").unwrap();
+    assert!(!blz_zip::is_zip_archive(&dir.path().join("fake.zip")).unwrap());
+}
+
+#[test]
+fn lists_and_extracts_zip() {
+    let dir = tempdir().unwrap();
+    let zip_path = dir.path().join("in.zip");
+    write_zip(&zip_path);
+
+    let entries = blitzarch::extract::list_entries(&zip_path, None).unwrap();
+    let names: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(names, ["docs", "docs/report.txt", "docs/raw.bin", "bin/run.sh", "../evil.txt"]);
+    assert!(entries[0].is_dir);
+    assert_eq!(entries[1].size, 18_000);
+    assert!(entries[1].compressed_size.unwrap() < 18_000);
+    assert_eq!(blitzarch::extract::read_metadata(&zip_path, None).unwrap()["comment"], "sent by accounting");
+
+    let out = dir.path().join("out");
+    blitzarch::extract::extract_files(&zip_path, &[], None, Some(&out), None).unwrap();
+    assert_eq!(fs::read_to_string(out.join("docs/report.txt")).unwrap(), "quarterly numbers\n".repeat(1000));
+    assert_eq!(fs::read(out.join("docs/raw.bin")).unwrap(), [1, 2, 3, 4]);
+    assert!(!dir.path().join("evil.txt").exists());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(out.join("bin/run.sh")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o100, 0o100);
+    }
+
+    // Выборка и --strip-components как у архивов Katana
+    let some = dir.path().join("some");
+    blz_zip::extract_zip_archive(&zip_path, &some, &["docs/*.txt".into()], None, Some(1)).unwrap();
+    assert!(some.join("report.txt").exists());
+    assert!(!some.join("raw.bin").exists() && !some.join("run.sh").exists());
+}
+
+#[test]
+fn cli_extracts_and_lists_zip() {
+    let dir = tempdir().unwrap();
+    let zip_path = dir.path().join("in.zip");
+    write_zip(&zip_path);
+    let out = dir.path().join("out");
+
+    let extracted = Command::cargo_bin("blitzarch").unwrap().args(["extract", "--output"]).arg(&out).arg(&zip_path).output().unwrap();
+    assert!(extracted.status.success(), "{}", String::from_utf8_lossy(&extracted.stderr));
+    assert_eq!(fs::read(out.join("bin/run.sh")).unwrap(), b"#!/bin/sh\necho hi\n");
+
+    let json = Command::cargo_bin("blitzarch").unwrap().args(["extract", "--json", "--output"]).arg(dir.path().join("j")).arg(&zip_path).output().unwrap();
+    assert!(json.status.success(), "{}", String::from_utf8_lossy(&json.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&json.stdout).unwrap();
+    assert_eq!(report["files"], 4); // docs/, report.txt, raw.bin, run.sh; ../evil.txt пропущен
+    assert_eq!(report["warnings"].as_array().unwrap().len(), 1);
+
+    let listed = Command::cargo_bin("blitzarch").unwrap().arg("list").arg(&zip_path).output().unwrap();
+    assert!(listed.status.success(), "{}", String::from_utf8_lossy(&listed.stderr));
+    assert!(String::from_utf8_lossy(&listed.stdout).contains("docs/report.txt (18000 bytes)"));
+
+    let recursive = Command::cargo_bin("blitzarch").unwrap().args(["extract", "--recursive-extract", "--output"]).arg(&out).arg(&zip_path).output().unwrap();
+    assert!(!recursive.status.success());
+    // Selection by stored metadata needs Katana entries
+    let filtered = Command::cargo_bin("blitzarch").unwrap().args(["extract", "--only-executable", "--output"]).arg(&out).arg(&zip_path).output().unwrap();
+    assert!(!filtered.status.success());
+    assert!(String::from_utf8_lossy(&filtered.stderr).contains("ZIP archives do not support --only-executable"));
+}
+
+#[cfg(unix)]
+#[test]
+fn chained_symlinks_stay_inside_output() {
+    let dir = tempdir().unwrap();
+    let zip_path = dir.path().join("links.zip");
+    let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+    // `b` only escapes once `a` exists; `b/evil.txt` would be written through it
+    zip.add_symlink("b", "a/..", FileOptions::default()).unwrap();
+    zip.add_symlink("a", ".", FileOptions::default()).unwrap();
+    zip.add_symlink("c", "a/..", FileOptions::default()).unwrap();
+    zip.start_file("b/evil.txt", FileOptions::default()).unwrap();
+    zip.write_all(b"gotcha").unwrap();
+    zip.add_symlink("d", "a/a/keep.txt", FileOptions::default()).unwrap();
+    zip.start_file("keep.txt", FileOptions::default()).unwrap();
+    zip.write_all(b"kept").unwrap();
+    zip.finish().unwrap();
+
+    let out = dir.path().join("out");
+    blz_zip::extract_zip_archive(&zip_path, &out, &[], None, None).unwrap();
+    assert_eq!(fs::read_link(out.join("a")).unwrap(), Path::new("."));
+    assert_eq!(fs::read(out.join("d")).unwrap(), b"kept");
+    for escaping in ["b", "c"] {
+        let meta = fs::symlink_metadata(out.join(escaping));
+        assert!(meta.is_err() || !meta.unwrap().file_type().is_symlink(), "{escaping}");
+    }
+    assert!(!dir.path().join("evil.txt").exists());
+    assert_eq!(fs::read(out.join("b/evil.txt")).unwrap(), b"gotcha");
+}